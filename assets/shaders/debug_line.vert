@@ -0,0 +1,26 @@
+#version 450
+
+#extension GL_EXT_nonuniform_qualifier: require
+
+#include "uniforms/globals.glsl"
+#include "uniforms/bindless.glsl"
+
+layout (push_constant) uniform PushConstant {
+    uint vertex_buffer_index;
+} push_constant;
+
+struct LineVertex {
+    vec3 position;
+    vec4 color;
+};
+
+BINDLESS_SBO_RO(std430, LineVertex, u_line_vertices);
+
+layout (location = 0) out vec4 out_color;
+
+void main() {
+    LineVertex vertex = u_line_vertices[push_constant.vertex_buffer_index].items[gl_VertexIndex];
+
+    gl_Position = CAMERA_PROJECTION * CAMERA_VIEW * vec4(vertex.position, 1.0f);
+    out_color = vertex.color;
+}
@@ -0,0 +1,7 @@
+#version 450
+
+void main() {
+    // NOTE: A single triangle covers the whole target, the rest is clipped
+    vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(position * 2.0f - 1.0f, 0.0f, 1.0f);
+}
@@ -1,14 +1,84 @@
 #version 450
 
+#extension GL_EXT_nonuniform_qualifier: require
+
+#include "uniforms/globals.glsl"
+#include "uniforms/bindless.glsl"
+
 layout (location = 0) in vec3 in_color;
 layout (location = 1) in vec3 in_normal;
+#ifdef MATERIAL_TEXTURED
+layout (location = 2) in vec2 in_uv;
+layout (location = 3) flat in uint in_texture_index;
+#endif
+#ifdef MATERIAL_STANDARD
+// NOTE: Only affects the output for pipelines with blending
+layout (location = 4) in float in_alpha;
+#endif
+#ifdef DEBUG_VIEW_UVS
+layout (location = 5) in vec2 in_debug_uv;
+#endif
+#ifdef DEBUG_VIEW_OBJECT_INDEX
+layout (location = 6) flat in uint in_debug_object_index;
+#endif
+layout (location = 7) in vec3 in_world_position;
 
 layout (location = 0) out vec4 out_frag_color;
 
+// Distinct color for each index (golden ratio hue sequence).
+vec3 index_color(uint index) {
+    float hue = fract(float(index) * 0.618034);
+    vec3 rgb = clamp(abs(mod(hue * 6.0 + vec3(0.0, 4.0, 2.0), 6.0) - 3.0) - 1.0, 0.0, 1.0);
+    return mix(vec3(0.25), rgb, 0.85);
+}
+
+// Returns 0.0 if the point is in the shadow of the directional light.
+float shadow_factor(vec3 world_position, vec3 normal) {
+    // NOTE: Receivers are offset along the normal to avoid acne on slopes
+    vec3 offset_position = world_position + normal * SHADOW_NORMAL_OFFSET;
+    vec4 light_position = LIGHT_VIEW_PROJECTION * vec4(offset_position, 1.0);
+    vec3 coords = light_position.xyz / light_position.w;
+
+    // NOTE: The shadow map is rendered with the flipped viewport like the main pass
+    vec2 uv = vec2(coords.x, -coords.y) * 0.5 + 0.5;
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || coords.z > 1.0) {
+        return 1.0;
+    }
+    return texture(u_global_textures_shadow[SHADOW_MAP_INDEX], vec3(uv, coords.z));
+}
+
 void main() {
-    const vec3 light_direction = normalize(vec3(-0.5, -0.5, -0.5));
+    #if defined(DEBUG_VIEW_NORMALS)
+    out_frag_color = vec4(normalize(in_normal) * 0.5 + 0.5, 1.0);
+    return;
+    #elif defined(DEBUG_VIEW_UVS)
+    out_frag_color = vec4(fract(in_debug_uv), 0.0, 1.0);
+    return;
+    #elif defined(DEBUG_VIEW_OBJECT_INDEX)
+    out_frag_color = vec4(index_color(in_debug_object_index), 1.0);
+    return;
+    #endif
+
+    vec3 albedo = in_color;
+    #ifdef MATERIAL_TEXTURED
+    albedo *= texture(u_global_textures[nonuniformEXT(in_texture_index)], in_uv).rgb;
+    #endif
+
+    vec3 normal = normalize(in_normal);
+
+    // NOTE: Without the directional light a fixed one is used without shadows
+    vec3 light_direction = normalize(vec3(-0.5, -0.5, -0.5));
+    vec3 light = vec3(1.0);
+    if (SHADOW_MAP_INDEX != 0xFFFFFFFFu) {
+        light_direction = LIGHT_DIRECTION;
+        light = LIGHT_COLOR * shadow_factor(in_world_position, normal);
+    }
 
-    vec3 color = clamp(dot(-light_direction, normalize(in_normal)), 0.0, 1.0) * in_color;
+    vec3 color = clamp(dot(-light_direction, normal), 0.0, 1.0) * light * albedo;
 
+    #ifdef MATERIAL_STANDARD
+    out_frag_color = vec4(color, in_alpha);
+    #else
     out_frag_color = vec4(color, 1.0f);
+    #endif
 }
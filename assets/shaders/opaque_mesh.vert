@@ -2,6 +2,10 @@
 
 #extension GL_EXT_nonuniform_qualifier: require
 #extension GL_ARB_shader_draw_parameters: require
+#ifdef VERTEX_FETCH_DEVICE_ADDRESS
+#extension GL_EXT_buffer_reference: require
+#extension GL_EXT_buffer_reference_uvec2: require
+#endif
 
 #define VERTEX_POSITION 0
 #define VERTEX_NORMAL 1
@@ -16,12 +20,23 @@
 
 layout (push_constant) uniform PushConstant {
     uint mesh_buffer_index;
-    uint object_buffer_index;
+    uint object_transform_buffer_index;
+    uint object_data_buffer_index;
     uint material_buffer_index;
+    uint instance_buffer_index;
 } push_constant;
 
 struct MaterialData {
+    #ifdef MATERIAL_STANDARD
+    vec4 base_color;
+    float metallic;
+    float roughness;
+    #else
     vec3 color;
+    #ifdef MATERIAL_TEXTURED
+    uint texture_index;
+    #endif
+    #endif
 };
 
 BINDLESS_SBO_RO(std430, MaterialData, u_material_buffer);
@@ -32,14 +47,51 @@ MaterialData material_data_read(uint buffer_index, uint slot) {
 
 layout (location = 0) out vec3 out_color;
 layout (location = 1) out vec3 out_normal;
+#ifdef MATERIAL_TEXTURED
+layout (location = 2) out vec2 out_uv;
+layout (location = 3) flat out uint out_texture_index;
+#endif
+#ifdef MATERIAL_STANDARD
+layout (location = 4) out float out_alpha;
+#endif
+#ifdef DEBUG_VIEW_UVS
+layout (location = 5) out vec2 out_debug_uv;
+#endif
+#ifdef DEBUG_VIEW_OBJECT_INDEX
+layout (location = 6) flat out uint out_debug_object_index;
+#endif
+layout (location = 7) out vec3 out_world_position;
 
 void main() {
-    ObjectData object_data = object_data_read(push_constant.object_buffer_index);
+    uint object_slot = object_slot_read(push_constant.instance_buffer_index);
+    ObjectTransform object_transform = object_transform_read(push_constant.object_transform_buffer_index, object_slot);
+    ObjectData object_data = object_data_read(push_constant.object_data_buffer_index, object_slot);
     MaterialData material_data = material_data_read(push_constant.material_buffer_index, object_data.data.z);
 
     Vertex vertex = vertex_read(push_constant.mesh_buffer_index, object_data.offsets);
 
-    gl_Position = CAMERA_PROJECTION * CAMERA_VIEW * object_data.transform * vec4(vertex.position, 1.0f);
+    vec4 world_position = object_transform.transform * vec4(vertex.position, 1.0f);
+    gl_Position = CAMERA_PROJECTION * CAMERA_VIEW * world_position;
+    out_world_position = world_position.xyz;
+    #ifdef MATERIAL_STANDARD
+    out_color = material_data.base_color.rgb;
+    out_alpha = material_data.base_color.a;
+    if (object_data.offsets[VERTEX_COLOR] != 0xFFFFFFFFu) {
+        out_color *= vertex.color.rgb;
+        out_alpha *= vertex.color.a;
+    }
+    #else
     out_color = material_data.color;
-    out_normal = (object_data.transform_inverse_transpose * vec4(vertex.normal, 1.0)).xyz;
+    #endif
+    out_normal = (object_transform.transform_inverse_transpose * vec4(vertex.normal, 1.0)).xyz;
+    #ifdef MATERIAL_TEXTURED
+    out_uv = vertex.uv0;
+    out_texture_index = material_data.texture_index;
+    #endif
+    #ifdef DEBUG_VIEW_UVS
+    out_debug_uv = vertex.uv0;
+    #endif
+    #ifdef DEBUG_VIEW_OBJECT_INDEX
+    out_debug_object_index = object_slot;
+    #endif
 }
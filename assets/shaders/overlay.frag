@@ -0,0 +1,24 @@
+#version 450
+
+#extension GL_EXT_nonuniform_qualifier: require
+
+#include "uniforms/bindless.glsl"
+
+layout (push_constant) uniform PushConstant {
+    uint vertex_buffer_index;
+    uint index_buffer_index;
+    uint texture_index;
+} push_constant;
+
+layout (location = 0) in vec2 in_uv;
+layout (location = 1) in vec4 in_color;
+
+layout (location = 0) out vec4 out_frag_color;
+
+void main() {
+    vec4 color = in_color;
+    if (push_constant.texture_index != 0xffffffffu) {
+        color *= texture(u_global_textures[push_constant.texture_index], in_uv);
+    }
+    out_frag_color = color;
+}
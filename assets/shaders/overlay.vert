@@ -0,0 +1,38 @@
+#version 450
+
+#extension GL_EXT_nonuniform_qualifier: require
+
+#include "uniforms/globals.glsl"
+#include "uniforms/bindless.glsl"
+#include "math/color.glsl"
+
+layout (push_constant) uniform PushConstant {
+    uint vertex_buffer_index;
+    uint index_buffer_index;
+    uint texture_index;
+} push_constant;
+
+struct OverlayVertex {
+    vec2 position;
+    vec2 uv;
+    uint color;
+};
+
+BINDLESS_SBO_RO(std430, OverlayVertex, u_overlay_vertices);
+BINDLESS_SBO_RO(std430, uint, u_overlay_indices);
+
+layout (location = 0) out vec2 out_uv;
+layout (location = 1) out vec4 out_color;
+
+void main() {
+    uint index = u_overlay_indices[push_constant.index_buffer_index].items[gl_VertexIndex];
+    OverlayVertex vertex = u_overlay_vertices[push_constant.vertex_buffer_index].items[index];
+
+    // NOTE: Viewport is flipped, so the top of the target is at `y = 1`
+    vec2 position = vertex.position / vec2(OUTPUT_RESOLUTION);
+    gl_Position = vec4(position.x * 2.0f - 1.0f, 1.0f - position.y * 2.0f, 0.0f, 1.0f);
+
+    vec4 color = unpackUnorm4x8(vertex.color);
+    out_uv = vertex.uv;
+    out_color = vec4(srgb_to_linear(color.rgb), color.a);
+}
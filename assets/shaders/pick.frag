@@ -0,0 +1,9 @@
+#version 450
+
+layout (location = 0) flat in uint in_pick_id;
+
+layout (location = 0) out uint out_pick_id;
+
+void main() {
+    out_pick_id = in_pick_id;
+}
@@ -0,0 +1,36 @@
+#version 450
+
+#extension GL_EXT_nonuniform_qualifier: require
+#extension GL_ARB_shader_draw_parameters: require
+#ifdef VERTEX_FETCH_DEVICE_ADDRESS
+#extension GL_EXT_buffer_reference: require
+#extension GL_EXT_buffer_reference_uvec2: require
+#endif
+
+#define VERTEX_POSITION 0
+#define VERTEX_ATTR_COUNT 5
+
+#include "uniforms/globals.glsl"
+#include "uniforms/bindless.glsl"
+#include "uniforms/object.glsl"
+
+layout (push_constant) uniform PushConstant {
+    uint mesh_buffer_index;
+    uint object_transform_buffer_index;
+    uint object_data_buffer_index;
+    uint pick_id_base;
+    uint instance_buffer_index;
+} push_constant;
+
+layout (location = 0) flat out uint out_pick_id;
+
+void main() {
+    uint object_slot = object_slot_read(push_constant.instance_buffer_index);
+    ObjectTransform object_transform = object_transform_read(push_constant.object_transform_buffer_index, object_slot);
+    ObjectData object_data = object_data_read(push_constant.object_data_buffer_index, object_slot);
+
+    Vertex vertex = vertex_read(push_constant.mesh_buffer_index, object_data.offsets);
+
+    gl_Position = CAMERA_PROJECTION * CAMERA_VIEW * object_transform.transform * vec4(vertex.position, 1.0f);
+    out_pick_id = push_constant.pick_id_base | object_slot;
+}
@@ -0,0 +1,33 @@
+#version 450
+
+#extension GL_EXT_nonuniform_qualifier: require
+#extension GL_ARB_shader_draw_parameters: require
+#ifdef VERTEX_FETCH_DEVICE_ADDRESS
+#extension GL_EXT_buffer_reference: require
+#extension GL_EXT_buffer_reference_uvec2: require
+#endif
+
+#define VERTEX_POSITION 0
+#define VERTEX_ATTR_COUNT 5
+
+#include "uniforms/globals.glsl"
+#include "uniforms/bindless.glsl"
+#include "uniforms/object.glsl"
+
+layout (push_constant) uniform PushConstant {
+    uint mesh_buffer_index;
+    uint object_transform_buffer_index;
+    uint object_data_buffer_index;
+    uint material_buffer_index;
+    uint instance_buffer_index;
+} push_constant;
+
+void main() {
+    uint object_slot = object_slot_read(push_constant.instance_buffer_index);
+    ObjectTransform object_transform = object_transform_read(push_constant.object_transform_buffer_index, object_slot);
+    ObjectData object_data = object_data_read(push_constant.object_data_buffer_index, object_slot);
+
+    Vertex vertex = vertex_read(push_constant.mesh_buffer_index, object_data.offsets);
+
+    gl_Position = LIGHT_VIEW_PROJECTION * object_transform.transform * vec4(vertex.position, 1.0f);
+}
@@ -0,0 +1,17 @@
+#version 450
+
+#extension GL_EXT_nonuniform_qualifier: require
+
+#include "uniforms/bindless.glsl"
+
+layout (push_constant) uniform PushConstant {
+    uint texture_index;
+} push_constant;
+
+layout (location = 0) in vec3 in_direction;
+
+layout (location = 0) out vec4 out_frag_color;
+
+void main() {
+    out_frag_color = texture(u_global_textures_cube[push_constant.texture_index], in_direction);
+}
@@ -0,0 +1,17 @@
+#version 450
+
+#include "uniforms/globals.glsl"
+
+layout (location = 0) out vec3 out_direction;
+
+void main() {
+    // Fullscreen triangle
+    vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0f - 1.0f;
+
+    // NOTE: Points at the far plane of an infinite projection have zero `w`,
+    // so only the direction of the unprojected point is used.
+    vec3 view_direction = (CAMERA_PROJECTION_INVERSE * vec4(position, FAR_DEPTH, 1.0f)).xyz;
+    out_direction = mat3(CAMERA_VIEW_INVERSE) * view_direction;
+
+    gl_Position = vec4(position, FAR_DEPTH, 1.0f);
+}
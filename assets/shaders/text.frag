@@ -0,0 +1,22 @@
+#version 450
+
+#extension GL_EXT_nonuniform_qualifier: require
+
+#include "uniforms/bindless.glsl"
+
+layout (push_constant) uniform PushConstant {
+    uint glyph_buffer_index;
+    uint atlas_index;
+} push_constant;
+
+layout (location = 0) in vec2 in_texel;
+layout (location = 1) in vec4 in_color;
+
+layout (location = 0) out vec4 out_frag_color;
+
+void main() {
+    // NOTE: Glyphs are fetched without filtering to keep the bitmap font crisp
+    float coverage = texelFetch(u_global_textures[push_constant.atlas_index], ivec2(in_texel), 0).r;
+    float alpha = in_color.a * coverage;
+    out_frag_color = vec4(in_color.rgb * alpha, alpha);
+}
@@ -0,0 +1,60 @@
+#version 450
+
+#extension GL_EXT_nonuniform_qualifier: require
+
+#include "uniforms/globals.glsl"
+#include "uniforms/bindless.glsl"
+
+layout (push_constant) uniform PushConstant {
+    uint glyph_buffer_index;
+    uint atlas_index;
+} push_constant;
+
+struct TextGlyph {
+    vec4 anchor;
+    vec4 color;
+    vec2 offset;
+    vec2 size;
+    vec2 texel;
+    vec2 texel_size;
+};
+
+BINDLESS_SBO_RO(std430, TextGlyph, u_text_glyphs);
+
+const vec2 QUAD_CORNERS[6] = vec2[](
+    vec2(0.0f, 0.0f), vec2(0.0f, 1.0f), vec2(1.0f, 1.0f),
+    vec2(0.0f, 0.0f), vec2(1.0f, 1.0f), vec2(1.0f, 0.0f)
+);
+
+layout (location = 0) out vec2 out_texel;
+layout (location = 1) out vec4 out_color;
+
+void main() {
+    TextGlyph glyph = u_text_glyphs[push_constant.glyph_buffer_index].items[gl_VertexIndex / 6];
+    vec2 corner = QUAD_CORNERS[gl_VertexIndex % 6];
+
+    vec2 resolution = vec2(OUTPUT_RESOLUTION);
+    vec2 anchor = glyph.anchor.xy;
+    if (glyph.anchor.w != 0.0f) {
+        vec4 clip_position = CAMERA_PROJECTION * CAMERA_VIEW * vec4(glyph.anchor.xyz, 1.0f);
+        if (clip_position.w <= 0.0f) {
+            // NOTE: Text behind the camera is moved outside of the clip volume
+            gl_Position = vec4(0.0f, 0.0f, 2.0f, 1.0f);
+            out_texel = vec2(0.0f);
+            out_color = vec4(0.0f);
+            return;
+        }
+        vec2 ndc = clip_position.xy / clip_position.w;
+        anchor = vec2(ndc.x + 1.0f, 1.0f - ndc.y) * 0.5f * resolution;
+    }
+
+    // NOTE: Quads are snapped to pixels so that glyphs stay sharp
+    vec2 position = floor(anchor + glyph.offset + 0.5f) + corner * glyph.size;
+
+    // NOTE: Viewport is flipped, so the top of the target is at `y = 1`
+    position /= resolution;
+    gl_Position = vec4(position.x * 2.0f - 1.0f, 1.0f - position.y * 2.0f, 0.0f, 1.0f);
+
+    out_texel = glyph.texel + corner * glyph.texel_size;
+    out_color = glyph.color;
+}
@@ -0,0 +1,53 @@
+#version 450
+
+#extension GL_EXT_nonuniform_qualifier: require
+
+#include "uniforms/globals.glsl"
+#include "uniforms/bindless.glsl"
+#include "math/color.glsl"
+
+#define TONEMAP_ACES 0
+#define TONEMAP_REINHARD 1
+
+layout (push_constant) uniform PushConstant {
+    uint hdr_texture_index;
+    uint tonemap;
+    uint encode_srgb;
+} push_constant;
+
+layout (location = 0) out vec4 out_frag_color;
+
+// Narkowicz 2015, "ACES Filmic Tone Mapping Curve"
+vec3 tonemap_aces(vec3 color) {
+    const float a = 2.51f;
+    const float b = 0.03f;
+    const float c = 2.43f;
+    const float d = 0.59f;
+    const float e = 0.14f;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0f, 1.0f);
+}
+
+vec3 tonemap_reinhard(vec3 color) {
+    return color / (1.0f + color);
+}
+
+void main() {
+    // NOTE: The HDR target is scaled by the resolution scale,
+    // so it is upscaled by the linear filtering of its sampler
+    uint hdr_texture_index = push_constant.hdr_texture_index;
+    vec2 uv = gl_FragCoord.xy / vec2(OUTPUT_RESOLUTION);
+    vec4 hdr = texture(u_global_textures[hdr_texture_index], uv);
+    vec3 color = max(hdr.rgb, vec3(0.0f)) * EXPOSURE;
+
+    if (push_constant.tonemap == TONEMAP_REINHARD) {
+        color = tonemap_reinhard(color);
+    } else {
+        color = tonemap_aces(color);
+    }
+
+    // NOTE: sRGB outputs are encoded when written
+    if (push_constant.encode_srgb != 0u) {
+        color = linear_to_srgb(color);
+    }
+    out_frag_color = vec4(color, hdr.a);
+}
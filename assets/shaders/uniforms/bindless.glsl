@@ -19,6 +19,8 @@ BINDLESS_TEX(sampler2D, u_global_textures);
 BINDLESS_TEX(usampler2D, u_global_textures_uint);
 BINDLESS_TEX(sampler3D, u_global_textures_3d);
 BINDLESS_TEX(usampler3D, u_global_textures_3d_uint);
+BINDLESS_TEX(samplerCube, u_global_textures_cube);
+BINDLESS_TEX(sampler2DShadow, u_global_textures_shadow);
 
 #define BINDLESS_UBO(ty, name) \
 layout (set = BINDLESS_SET, binding = BINDLESS_UBO_BINDING) uniform ty##Buffer { \
@@ -30,6 +32,11 @@ layout (set = BINDLESS_SET, binding = BINDLESS_SBO_BINDING, layout_) readonly bu
 ty_ items[]; \
 } name_[BINDLESS_SBO_COUNT]
 
+#define BINDLESS_SBO_RW(layout_, ty_, name_) \
+layout (set = BINDLESS_SET, binding = BINDLESS_SBO_BINDING, layout_) buffer ty_##RwBuffer { \
+ty_ items[]; \
+} name_[BINDLESS_SBO_COUNT]
+
 struct DummyUniform { uint ignore; };
 BINDLESS_UBO(DummyUniform, u_dummy_ubo);
 BINDLESS_SBO_RO(std430, DummyUniform, u_dummy_sbo);
@@ -15,9 +15,20 @@ layout (set = GLOBALS_SET, binding = GLOBALS_BINDING, std140) uniform GlobalUnif
     mat4 camera_previous_view;
     mat4 camera_previous_projection;
     uvec2 render_resolution;
+    uvec2 output_resolution;
     float time;
     float delta_time;
     uint frame_index;
+    float interpolation_factor;
+    mat4 light_view_projection;
+    vec4 light_direction;
+    vec4 light_color;
+    uint shadow_map_index;
+    float shadow_normal_offset;
+    float exposure;
+    // NOTE: Zero unless mesh buffers are accessed by their device addresses
+    uvec2 vertex_buffer_address;
+    uvec2 index_buffer_address;
 }
 globals;
 
@@ -28,8 +39,18 @@ globals;
 #define CAMERA_PREVIOUS_VIEW globals.camera_previous_view
 #define CAMERA_PREVIOUS_PROJECTION globals.camera_previous_projection
 #define RENDER_RESOLUTION globals.render_resolution
+#define OUTPUT_RESOLUTION globals.output_resolution
 #define TIME globals.time
 #define DELTA_TIME globals.delta_time
 #define FRAME_INDEX globals.frame_index
+#define INTERPOLATION_FACTOR globals.interpolation_factor
+#define LIGHT_VIEW_PROJECTION globals.light_view_projection
+#define LIGHT_DIRECTION globals.light_direction.xyz
+#define LIGHT_COLOR globals.light_color.rgb
+#define SHADOW_MAP_INDEX globals.shadow_map_index
+#define SHADOW_NORMAL_OFFSET globals.shadow_normal_offset
+#define EXPOSURE globals.exposure
+#define VERTEX_BUFFER_ADDRESS globals.vertex_buffer_address
+#define INDEX_BUFFER_ADDRESS globals.index_buffer_address
 
 #endif  // UNIFORMS_GLOBALS_GLSL
@@ -4,24 +4,87 @@
 #include "../math/sphere.glsl"
 #include "./bindless.glsl"
 
-struct ObjectData {
+// NOTE: Transforms are stored separately from the rest of the object data
+// since they are updated much more often.
+struct ObjectTransform {
     mat4 transform;
     mat4 transform_inverse_transpose;
     Sphere bounding_sphere;
+};
+
+struct ObjectData {
+    // x: first index, y: index count, z: material slot,
+    // w: layers mask (0 for disabled objects)
     uvec4 data;
+    // 0 for u16 indices, 1 for u32 indices
+    uint index_type;
     #ifdef VERTEX_ATTR_COUNT
     uint offsets[VERTEX_ATTR_COUNT];
     #endif
 };
 
+BINDLESS_SBO_RO(std430, ObjectTransform, u_object_transforms);
 BINDLESS_SBO_RO(std430, ObjectData, u_object_data);
+BINDLESS_SBO_RO(std430, uint, u_instance_object_slots);
+
+// NOTE: Instance buffer index is `0xFFFFFFFF` for non-batched draws,
+// in which case the instance index is used as the object slot.
+uint object_slot_read(uint instance_buffer_index) {
+    uint slot = gl_InstanceIndex;
+    if (instance_buffer_index != 0xFFFFFFFFu) {
+        slot = u_instance_object_slots[instance_buffer_index].items[gl_InstanceIndex];
+    }
+    return slot;
+}
 
-ObjectData object_data_read(uint buffer_index) {
-    return u_object_data[buffer_index].items[gl_InstanceIndex];
+ObjectTransform object_transform_read(uint buffer_index, uint slot) {
+    return u_object_transforms[buffer_index].items[slot];
 }
 
+ObjectData object_data_read(uint buffer_index, uint slot) {
+    return u_object_data[buffer_index].items[slot];
+}
+
+#ifdef VERTEX_FETCH_DEVICE_ADDRESS
+// NOTE: Requires `GL_EXT_buffer_reference` and `GL_EXT_buffer_reference_uvec2`
+#include "./globals.glsl"
+
+layout (buffer_reference, std430, buffer_reference_align = 4) readonly buffer VertexBufferFloat {
+    float items[];
+};
+
+layout (buffer_reference, std430, buffer_reference_align = 4) readonly buffer VertexBufferPacked {
+    uint items[];
+};
+
+// NOTE: The vertex buffer is accessed by its device address from the frame globals,
+// so the bindless buffer index is ignored.
+float vertex_buffer_read_float(uint buffer_index, uint offset) {
+    return VertexBufferFloat(VERTEX_BUFFER_ADDRESS).items[offset];
+}
+
+uint vertex_buffer_read_packed(uint buffer_index, uint offset) {
+    return VertexBufferPacked(VERTEX_BUFFER_ADDRESS).items[offset];
+}
+#else
 BINDLESS_SBO_RO(std430, float, u_vertex_buffer_float);
 
+// NOTE: Wrapped to get a unique block name for the same buffer.
+struct PackedVertexData {
+    uint bits;
+};
+
+BINDLESS_SBO_RO(std430, PackedVertexData, u_vertex_buffer_packed);
+
+float vertex_buffer_read_float(uint buffer_index, uint offset) {
+    return u_vertex_buffer_float[buffer_index].items[offset];
+}
+
+uint vertex_buffer_read_packed(uint buffer_index, uint offset) {
+    return u_vertex_buffer_packed[buffer_index].items[offset].bits;
+}
+#endif // VERTEX_FETCH_DEVICE_ADDRESS
+
 #ifdef VERTEX_ATTR_COUNT
 struct Vertex {
     #ifdef VERTEX_POSITION
@@ -33,7 +96,8 @@ struct Vertex {
     #endif
 
     #ifdef VERTEX_TANGENT
-    vec3 tangent;
+    // w: bitangent sign
+    vec4 tangent;
     #endif
 
     #ifdef VERTEX_UV0
@@ -45,30 +109,81 @@ struct Vertex {
     #endif
 };
 
+// NOTE: Offsets with this bit point to a default value shared by all
+// vertices, which is bound when the mesh doesn't have the attribute.
+#define VERTEX_DEFAULT_BIT 0x80000000u
+
+// NOTE: Offsets with this bit point to attributes packed into a single `uint`
+// per vertex (10-10-10-2 SNORM vectors or half-float UVs).
+#define VERTEX_PACKED_BIT 0x40000000u
+
+uint vertex_data_offset(uint byte_offset, uint components) {
+    if ((byte_offset & VERTEX_DEFAULT_BIT) != 0u) {
+        return (byte_offset & ~VERTEX_DEFAULT_BIT) / 4;
+    }
+    return byte_offset / 4 + gl_VertexIndex * components;
+}
+
 vec4 vertex_data_read_vec4(uint buffer_index, uint byte_offset) {
-    uint offset = byte_offset / 4 + gl_VertexIndex * 4;
+    uint offset = vertex_data_offset(byte_offset, 4);
     return vec4(
-        u_vertex_buffer_float[buffer_index].items[offset],
-        u_vertex_buffer_float[buffer_index].items[offset + 1],
-        u_vertex_buffer_float[buffer_index].items[offset + 2],
-        u_vertex_buffer_float[buffer_index].items[offset + 3]
+        vertex_buffer_read_float(buffer_index, offset),
+        vertex_buffer_read_float(buffer_index, offset + 1),
+        vertex_buffer_read_float(buffer_index, offset + 2),
+        vertex_buffer_read_float(buffer_index, offset + 3)
     );
 }
 
 vec3 vertex_data_read_vec3(uint buffer_index, uint byte_offset) {
-    uint offset = byte_offset / 4 + gl_VertexIndex * 3;
+    uint offset = vertex_data_offset(byte_offset, 3);
     return vec3(
-        u_vertex_buffer_float[buffer_index].items[offset],
-        u_vertex_buffer_float[buffer_index].items[offset + 1],
-        u_vertex_buffer_float[buffer_index].items[offset + 2]
+        vertex_buffer_read_float(buffer_index, offset),
+        vertex_buffer_read_float(buffer_index, offset + 1),
+        vertex_buffer_read_float(buffer_index, offset + 2)
     );
 }
 
+uint vertex_data_read_packed(uint buffer_index, uint byte_offset) {
+    uint offset = (byte_offset & ~VERTEX_PACKED_BIT) / 4 + gl_VertexIndex;
+    return vertex_buffer_read_packed(buffer_index, offset);
+}
+
+// NOTE: Must match `unpack_snorm_10_10_10` in `vertex.rs`.
+vec3 unpack_snorm_10_10_10(uint packed) {
+    // Shift each component into the highest bits to extend its sign
+    ivec3 components = ivec3(uvec3(packed << 22, packed << 12, packed << 2)) >> 22;
+    return max(vec3(components) / 511.0, -1.0);
+}
+
+vec3 vertex_data_read_snorm_vec3(uint buffer_index, uint byte_offset) {
+    if ((byte_offset & VERTEX_PACKED_BIT) != 0u) {
+        return unpack_snorm_10_10_10(vertex_data_read_packed(buffer_index, byte_offset));
+    }
+    return vertex_data_read_vec3(buffer_index, byte_offset);
+}
+
+// NOTE: Must match `unpack_snorm_10_10_10_2` in `vertex.rs`.
+vec4 unpack_snorm_10_10_10_2(uint packed) {
+    float sign = max(float(int(packed) >> 30), -1.0);
+    return vec4(unpack_snorm_10_10_10(packed), sign);
+}
+
+vec4 vertex_data_read_snorm_vec4(uint buffer_index, uint byte_offset) {
+    if ((byte_offset & VERTEX_PACKED_BIT) != 0u) {
+        return unpack_snorm_10_10_10_2(vertex_data_read_packed(buffer_index, byte_offset));
+    }
+    return vertex_data_read_vec4(buffer_index, byte_offset);
+}
+
 vec2 vertex_data_read_vec2(uint buffer_index, uint byte_offset) {
-    uint offset = byte_offset / 4 + gl_VertexIndex * 2;
+    if ((byte_offset & VERTEX_PACKED_BIT) != 0u) {
+        return unpackHalf2x16(vertex_data_read_packed(buffer_index, byte_offset));
+    }
+
+    uint offset = vertex_data_offset(byte_offset, 2);
     return vec2(
-        u_vertex_buffer_float[buffer_index].items[offset],
-        u_vertex_buffer_float[buffer_index].items[offset + 1]
+        vertex_buffer_read_float(buffer_index, offset),
+        vertex_buffer_read_float(buffer_index, offset + 1)
     );
 }
 
@@ -79,10 +194,10 @@ Vertex vertex_read(uint buffer_index, uint[VERTEX_ATTR_COUNT] offsets) {
     result.position = vertex_data_read_vec3(buffer_index, offsets[VERTEX_POSITION]);
     #endif
     #ifdef VERTEX_NORMAL
-    result.normal = vertex_data_read_vec3(buffer_index, offsets[VERTEX_NORMAL]);
+    result.normal = vertex_data_read_snorm_vec3(buffer_index, offsets[VERTEX_NORMAL]);
     #endif
     #ifdef VERTEX_TANGENT
-    result.tangent = vertex_data_read_vec3(buffer_index, offsets[VERTEX_TANGENT]);
+    result.tangent = vertex_data_read_snorm_vec4(buffer_index, offsets[VERTEX_TANGENT]);
     #endif
     #ifdef VERTEX_UV0
     result.uv0 = vertex_data_read_vec2(buffer_index, offsets[VERTEX_UV0]);
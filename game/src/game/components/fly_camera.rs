@@ -0,0 +1,48 @@
+use bevy_ecs::component::Component;
+use ecs::components::Transform;
+use glam::{EulerRot, Quat};
+
+/// Moves the camera entity with the keyboard and rotates it with the mouse.
+///
+/// The entity [`Transform`] is updated during the fixed update, while
+/// the drawn camera is smoothed between the last two updates.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct FlyCamera {
+    /// Movement speed in units per second.
+    pub speed: f32,
+    /// Speed multiplier applied while sprinting.
+    pub sprint_multiplier: f32,
+    /// Rotation in radians per pixel of the mouse motion.
+    pub sensitivity: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Transform before the last fixed update.
+    pub prev_transform: Transform,
+    /// Transform interpolated for the current draw.
+    pub view: Transform,
+}
+
+impl FlyCamera {
+    /// Max absolute pitch, slightly less than a right angle
+    /// to keep the up direction well defined.
+    pub const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+    /// Creates a controller which starts from the entity `transform`.
+    pub fn new(transform: &Transform) -> Self {
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        Self {
+            speed: 3.0,
+            sprint_multiplier: 4.0,
+            sensitivity: 0.002,
+            yaw,
+            pitch: pitch.clamp(-Self::MAX_PITCH, Self::MAX_PITCH),
+            prev_transform: *transform,
+            view: *transform,
+        }
+    }
+
+    /// Returns the rotation without a roll.
+    pub fn rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0)
+    }
+}
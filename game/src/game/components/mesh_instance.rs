@@ -1,17 +0,0 @@
-use bevy_ecs::component::Component;
-
-use renderer::{DynamicObjectHandle, MaterialInstanceHandle, MeshHandle, StaticObjectHandle};
-
-#[derive(Debug, Clone, PartialEq, Component)]
-pub struct StaticMeshInstance {
-    pub mesh: MeshHandle,
-    pub material: MaterialInstanceHandle,
-    pub handle: StaticObjectHandle,
-}
-
-#[derive(Debug, Clone, PartialEq, Component)]
-pub struct DynamicMeshInstance {
-    pub mesh: MeshHandle,
-    pub material: MaterialInstanceHandle,
-    pub handle: DynamicObjectHandle,
-}
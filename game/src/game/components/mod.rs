@@ -1,5 +1,7 @@
 pub use self::camera::Camera;
-pub use self::mesh_instance::{DynamicMeshInstance, StaticMeshInstance};
+pub use self::fly_camera::FlyCamera;
+pub use self::orbit_camera::OrbitCamera;
 
 mod camera;
-mod mesh_instance;
+mod fly_camera;
+mod orbit_camera;
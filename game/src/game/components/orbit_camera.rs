@@ -0,0 +1,120 @@
+use bevy_ecs::component::Component;
+use ecs::components::Transform;
+use glam::{EulerRot, Quat, Vec2, Vec3};
+
+/// Orbits the camera entity around a focus point with the mouse.
+///
+/// Input changes the target pose, which the drawn pose follows with
+/// a damping applied at the draw rate.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct OrbitCamera {
+    /// Pose requested by the input.
+    pub target: OrbitPose,
+    /// Damped pose of the current draw.
+    pub current: OrbitPose,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub min_pitch: f32,
+    pub max_pitch: f32,
+    /// Rotation in radians per pixel of the mouse motion.
+    pub sensitivity: f32,
+    /// Relative distance change per line of the mouse wheel.
+    pub zoom_speed: f32,
+    /// Focus movement per pixel of the mouse motion, relative to the distance.
+    pub pan_speed: f32,
+    /// Time in seconds in which the remaining distance
+    /// to the target pose is reduced by ~63%.
+    pub smoothing: f32,
+}
+
+impl OrbitCamera {
+    /// Creates a controller which keeps the `transform` and orbits
+    /// around the point `distance` units in front of it.
+    pub fn new(transform: &Transform, distance: f32) -> Self {
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        let mut res = Self {
+            target: OrbitPose {
+                focus: transform.translation + transform.forward() * distance,
+                distance,
+                yaw,
+                pitch,
+            },
+            current: OrbitPose::default(),
+            min_distance: 0.5,
+            max_distance: 100.0,
+            min_pitch: -super::FlyCamera::MAX_PITCH,
+            max_pitch: super::FlyCamera::MAX_PITCH,
+            sensitivity: 0.004,
+            zoom_speed: 0.1,
+            pan_speed: 0.001,
+            smoothing: 0.05,
+        };
+        res.rotate(Vec2::ZERO);
+        res.zoom(0.0);
+        res.current = res.target;
+        res
+    }
+
+    /// Rotates the target pose by the mouse motion in pixels.
+    pub fn rotate(&mut self, mouse_delta: Vec2) {
+        self.target.yaw -= mouse_delta.x * self.sensitivity;
+        self.target.pitch = (self.target.pitch - mouse_delta.y * self.sensitivity)
+            .clamp(self.min_pitch, self.max_pitch);
+    }
+
+    /// Moves the target focus in the view plane by the mouse motion in pixels.
+    pub fn pan(&mut self, mouse_delta: Vec2) {
+        let rotation = self.target.rotation();
+        let offset = rotation * Vec3::new(-mouse_delta.x, mouse_delta.y, 0.0);
+        self.target.focus += offset * self.pan_speed * self.target.distance;
+    }
+
+    /// Changes the target distance by the mouse wheel lines,
+    /// positive lines move the camera closer.
+    pub fn zoom(&mut self, lines: f32) {
+        let scale = (1.0 - self.zoom_speed).powf(lines);
+        self.target.distance =
+            (self.target.distance * scale).clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Moves the current pose towards the target pose.
+    pub fn update(&mut self, dt: f32) {
+        let t = if self.smoothing > 0.0 {
+            1.0 - (-dt / self.smoothing).exp()
+        } else {
+            1.0
+        };
+        self.current = self.current.lerp(&self.target, t);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OrbitPose {
+    pub focus: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl OrbitPose {
+    /// Returns the rotation without a roll.
+    pub fn rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0)
+    }
+
+    /// Returns the transform of the camera looking at the focus.
+    pub fn transform(&self) -> Transform {
+        let rotation = self.rotation();
+        Transform::from_translation(self.focus + rotation * Vec3::Z * self.distance)
+            .with_rotation(rotation)
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            focus: self.focus.lerp(other.focus, t),
+            distance: self.distance + (other.distance - self.distance) * t,
+            yaw: self.yaw + (other.yaw - self.yaw) * t,
+            pitch: self.pitch + (other.pitch - self.pitch) * t,
+        }
+    }
+}
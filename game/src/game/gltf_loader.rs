@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use anyhow::{Context, Result};
+use bevy_ecs::prelude::*;
+use ecs::components::Transform;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use renderer::ecs::DynamicMeshInstance;
+use renderer::materials::StandardMaterialInstance;
+use renderer::{MaterialInstanceHandle, RendererState};
+
+use super::SceneObjectBundle;
+
+/// glTF scene which is loaded in the background.
+#[derive(Resource)]
+pub struct LoadingScene {
+    pub path: PathBuf,
+    /// Number of processed nodes, including the ones which failed to load.
+    pub processed_nodes: usize,
+    /// Total number of nodes, known once the file is parsed.
+    pub total_nodes: Option<usize>,
+    finished: bool,
+    events: Mutex<mpsc::Receiver<LoaderEvent>>,
+}
+
+impl LoadingScene {
+    /// Spawns a thread which parses the file and processes its nodes
+    /// on a pool of worker threads.
+    ///
+    /// NOTE: Loading is cancelled when the resource is dropped.
+    pub fn start(path: &Path, renderer: Arc<RendererState>) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::Builder::new()
+            .name("gltf_loader".to_owned())
+            .spawn({
+                let path = path.to_owned();
+                move || {
+                    if let Err(e) = load_scene(&path, &renderer, &sender) {
+                        sender.send(LoaderEvent::Failed(e)).ok();
+                    }
+                }
+            })
+            .context("failed to spawn glTF loader thread")?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            processed_nodes: 0,
+            total_nodes: None,
+            finished: false,
+            events: Mutex::new(receiver),
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+enum LoaderEvent {
+    Parsed { total_nodes: usize },
+    NodeProcessed { bundles: Vec<SceneObjectBundle> },
+    Failed(anyhow::Error),
+}
+
+pub fn spawn_loaded_nodes_system(mut commands: Commands, scene: Option<ResMut<LoadingScene>>) {
+    let Some(mut scene) = scene else {
+        return;
+    };
+    if scene.finished {
+        return;
+    }
+
+    let scene = &mut *scene;
+    let events = scene.events.get_mut().unwrap();
+
+    let processed_before = scene.processed_nodes;
+    loop {
+        match events.try_recv() {
+            Ok(LoaderEvent::Parsed { total_nodes }) => {
+                scene.total_nodes = Some(total_nodes);
+            }
+            Ok(LoaderEvent::NodeProcessed { bundles }) => {
+                scene.processed_nodes += 1;
+                commands.spawn_batch(bundles);
+            }
+            Ok(LoaderEvent::Failed(e)) => {
+                tracing::error!(path = %scene.path.display(), "failed to load glTF scene: {e:?}");
+            }
+            Err(mpsc::TryRecvError::Empty) => break,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                scene.finished = true;
+                tracing::info!(
+                    path = %scene.path.display(),
+                    processed_nodes = scene.processed_nodes,
+                    "finished loading glTF scene"
+                );
+                return;
+            }
+        }
+    }
+
+    if scene.processed_nodes != processed_before {
+        tracing::debug!(
+            processed_nodes = scene.processed_nodes,
+            total_nodes = scene.total_nodes,
+            "loading glTF scene"
+        );
+    }
+}
+
+fn load_scene(
+    path: &Path,
+    renderer: &Arc<RendererState>,
+    sender: &mpsc::Sender<LoaderEvent>,
+) -> Result<()> {
+    let (gltf, buffers, _images) = gltf::import(path)?;
+    let scene = gltf
+        .default_scene()
+        .context("default glTF scene not found")?;
+
+    // Flatten the hierarchy to distribute nodes between workers
+    let mut nodes = Vec::new();
+    let mut stack = scene
+        .nodes()
+        .map(|node| (node, Mat4::IDENTITY))
+        .collect::<Vec<_>>();
+    while let Some((node, transform)) = stack.pop() {
+        for child in node.children() {
+            let child_transform =
+                transform.mul_mat4(&Mat4::from_cols_array_2d(&child.transform().matrix()));
+            stack.push((child, child_transform));
+        }
+        nodes.push((node, transform));
+    }
+
+    if sender
+        .send(LoaderEvent::Parsed {
+            total_nodes: nodes.len(),
+        })
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    let materials = SceneMaterials::default();
+
+    let worker_count = std::thread::available_parallelism()
+        .map_or(1, |count| count.get())
+        .min(nodes.len());
+    let next_node = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        for _ in 0..worker_count {
+            scope.spawn(|| {
+                while let Some((node, transform)) =
+                    nodes.get(next_node.fetch_add(1, Ordering::Relaxed))
+                {
+                    let bundles =
+                        process_gltf_node(node, &buffers, transform, &materials, renderer)
+                            .unwrap_or_else(|e| {
+                                tracing::warn!(
+                                    node = node.index(),
+                                    name = ?node.name(),
+                                    "failed to load glTF node: {e:?}"
+                                );
+                                Vec::new()
+                            });
+
+                    // NOTE: Stop if loading was cancelled
+                    if sender.send(LoaderEvent::NodeProcessed { bundles }).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// Material instances shared between all primitives of the scene.
+#[derive(Default)]
+struct SceneMaterials {
+    /// NOTE: `None` is used for the default glTF material.
+    instances: Mutex<HashMap<Option<usize>, MaterialInstanceHandle>>,
+}
+
+impl SceneMaterials {
+    fn get_or_add(
+        &self,
+        material: &gltf::Material,
+        renderer: &Arc<RendererState>,
+    ) -> Result<MaterialInstanceHandle> {
+        let mut instances = self.instances.lock().unwrap();
+        if let Some(handle) = instances.get(&material.index()) {
+            return Ok(handle.clone());
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let handle = renderer.add_material_instance(StandardMaterialInstance {
+            base_color: Vec4::from_array(pbr.base_color_factor()),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            double_sided: material.double_sided(),
+            blending: material.alpha_mode() == gltf::material::AlphaMode::Blend,
+        })?;
+        instances.insert(material.index(), handle.clone());
+        Ok(handle)
+    }
+}
+
+fn process_gltf_node(
+    node: &gltf::Node,
+    buffers: &[gltf::buffer::Data],
+    global_transform: &Mat4,
+    materials: &SceneMaterials,
+    renderer: &Arc<RendererState>,
+) -> Result<Vec<SceneObjectBundle>> {
+    let Some(mesh) = node.mesh() else {
+        return Ok(Vec::new());
+    };
+
+    let mut bundles = Vec::new();
+    for primitive in mesh.primitives() {
+        let reader =
+            primitive.reader(|buffer| buffers.get(buffer.index()).map(std::ops::Deref::deref));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        let Some(indices) = reader.read_indices() else {
+            continue;
+        };
+
+        let vertex_count = positions.len();
+
+        #[inline]
+        fn optional_iter<I, T: Default>(iter: Option<I>, len: usize) -> Result<Option<I>>
+        where
+            I: Iterator<Item = T> + ExactSizeIterator,
+        {
+            if let Some(iter) = &iter {
+                anyhow::ensure!(iter.len() == len, "component array length mismatch");
+            }
+            Ok(iter)
+        }
+
+        let normals = optional_iter(reader.read_normals(), vertex_count)?;
+        let tangents = optional_iter(reader.read_tangents(), vertex_count)?;
+        let uv0 = optional_iter(
+            reader.read_tex_coords(0).map(|iter| iter.into_f32()),
+            vertex_count,
+        )?;
+        let colors = optional_iter(
+            reader.read_colors(0).map(|iter| iter.into_rgba_f32()),
+            vertex_count,
+        )?;
+
+        let mesh = {
+            let mut builder = renderer::Mesh::builder(
+                positions
+                    .map(|[x, y, z]| renderer::Position(Vec3::new(x, y, z)))
+                    .collect::<Vec<_>>(),
+            );
+
+            if let Some(normals) = normals {
+                builder = builder.with_normals(
+                    normals
+                        .map(|[x, y, z]| renderer::Normal(Vec3::new(x, y, z)))
+                        .collect::<Vec<_>>(),
+                );
+            } else {
+                builder = builder.with_computed_normals();
+            }
+
+            if let Some(tangents) = tangents {
+                builder = builder.with_tangents(
+                    tangents
+                        .map(|tangent| renderer::Tangent(Vec4::from_array(tangent)))
+                        .collect::<Vec<_>>(),
+                );
+            } else if uv0.is_some() {
+                // NOTE: glTF requires MikkTSpace tangents when they are not specified
+                builder = builder.with_computed_tangents();
+            }
+            if let Some(uv0) = uv0 {
+                builder = builder.with_uv0(
+                    uv0.map(|[x, y]| renderer::UV0(Vec2::new(x, y)))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            if let Some(colors) = colors {
+                builder = builder.with_colors(
+                    colors
+                        .map(|color| renderer::Color(Vec4::from_array(color)))
+                        .collect::<Vec<_>>(),
+                );
+            }
+
+            builder.with_indices(indices.into_u32().collect()).build()?
+        };
+
+        let mesh = renderer.add_mesh(&mesh)?;
+        let material = materials.get_or_add(&primitive.material(), renderer)?;
+
+        bundles.push(SceneObjectBundle {
+            transform: Transform::from_matrix(*global_transform),
+            mesh_instance: DynamicMeshInstance {
+                mesh,
+                materials: material.into(),
+            },
+        });
+    }
+
+    Ok(bundles)
+}
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy_ecs::system::Resource;
+use glam::Vec2;
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+/// Action triggered by the bound keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Sprint,
+    /// Moves the focus of the orbit camera instead of rotating it.
+    Pan,
+}
+
+/// Maps keys to actions and accumulates the input between fixed updates.
+#[derive(Resource)]
+pub struct InputMap {
+    bindings: HashMap<KeyCode, Action>,
+    mouse_bindings: HashMap<MouseButton, Action>,
+    pressed_keys: HashSet<KeyCode>,
+    pressed_buttons: HashSet<MouseButton>,
+    mouse_delta: Vec2,
+    scroll_delta: f32,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut input_map = Self {
+            bindings: HashMap::new(),
+            mouse_bindings: HashMap::new(),
+            pressed_keys: HashSet::new(),
+            pressed_buttons: HashSet::new(),
+            mouse_delta: Vec2::ZERO,
+            scroll_delta: 0.0,
+        };
+        input_map.bind(KeyCode::KeyW, Action::MoveForward);
+        input_map.bind(KeyCode::KeyS, Action::MoveBackward);
+        input_map.bind(KeyCode::KeyA, Action::MoveLeft);
+        input_map.bind(KeyCode::KeyD, Action::MoveRight);
+        input_map.bind(KeyCode::Space, Action::MoveUp);
+        input_map.bind(KeyCode::ControlLeft, Action::MoveDown);
+        input_map.bind(KeyCode::ShiftLeft, Action::Sprint);
+        input_map.bind_mouse_button(MouseButton::Middle, Action::Pan);
+        input_map
+    }
+}
+
+impl InputMap {
+    /// Binds the key to the action, replacing its previous binding.
+    pub fn bind(&mut self, key: KeyCode, action: Action) {
+        self.bindings.insert(key, action);
+    }
+
+    pub fn unbind(&mut self, key: KeyCode) {
+        self.bindings.remove(&key);
+        self.pressed_keys.remove(&key);
+    }
+
+    /// Binds the mouse button to the action, replacing its previous binding.
+    pub fn bind_mouse_button(&mut self, button: MouseButton, action: Action) {
+        self.mouse_bindings.insert(button, action);
+    }
+
+    /// Updates the state of the key.
+    ///
+    /// Returns `false` if the key is not bound to any action.
+    pub fn handle_key(&mut self, key: KeyCode, pressed: bool) -> bool {
+        if !self.bindings.contains_key(&key) {
+            return false;
+        }
+
+        if pressed {
+            self.pressed_keys.insert(key);
+        } else {
+            self.pressed_keys.remove(&key);
+        }
+        true
+    }
+
+    /// Updates the state of the mouse button.
+    ///
+    /// Returns `false` if the button is not bound to any action.
+    pub fn handle_mouse_button(&mut self, button: MouseButton, pressed: bool) -> bool {
+        if !self.mouse_bindings.contains_key(&button) {
+            return false;
+        }
+
+        if pressed {
+            self.pressed_buttons.insert(button);
+        } else {
+            self.pressed_buttons.remove(&button);
+        }
+        true
+    }
+
+    /// Accumulates the mouse wheel motion in lines.
+    pub fn handle_mouse_wheel(&mut self, lines: f32) {
+        self.scroll_delta += lines;
+    }
+
+    /// Accumulates the raw mouse motion in pixels.
+    pub fn handle_mouse_motion(&mut self, delta: Vec2) {
+        self.mouse_delta += delta;
+    }
+
+    /// Releases all keys and discards the mouse motion,
+    /// e.g. when the window loses focus.
+    pub fn reset(&mut self) {
+        self.pressed_keys.clear();
+        self.pressed_buttons.clear();
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll_delta = 0.0;
+    }
+
+    /// Returns `true` if any key or mouse button bound to the action is pressed.
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.pressed_keys
+            .iter()
+            .any(|key| self.bindings.get(key) == Some(&action))
+            || self
+                .pressed_buttons
+                .iter()
+                .any(|button| self.mouse_bindings.get(button) == Some(&action))
+    }
+
+    /// Returns `-1.0`, `0.0` or `1.0` depending on which of the actions are pressed.
+    pub fn axis(&self, negative: Action, positive: Action) -> f32 {
+        self.is_pressed(positive) as i32 as f32 - self.is_pressed(negative) as i32 as f32
+    }
+
+    /// Returns the mouse motion accumulated since the previous call.
+    pub fn take_mouse_delta(&mut self) -> Vec2 {
+        std::mem::take(&mut self.mouse_delta)
+    }
+
+    /// Returns the mouse wheel motion accumulated since the previous call.
+    pub fn take_scroll_delta(&mut self) -> f32 {
+        std::mem::take(&mut self.scroll_delta)
+    }
+}
@@ -2,20 +2,25 @@ use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use bevy_ecs::prelude::*;
 use bevy_ecs::schedule::ScheduleLabel;
 use ecs::components::Transform;
-use glam::{Mat4, Vec2, Vec3};
+use glam::{Vec2, Vec3};
 use rand::Rng;
+use renderer::ecs::{DynamicMeshInstance, FixedUpdateTime, RendererPlugin};
 use renderer::materials::DebugMaterialInstance;
-use renderer::RendererState;
-use winit::event::WindowEvent;
+use renderer::{Color, RendererEvent, RendererState};
+use winit::event::{DeviceEvent, MouseScrollDelta, WindowEvent};
 
-use self::components::{Camera, DynamicMeshInstance, StaticMeshInstance};
-use self::resources::{Graphics, MainCamera, Time};
+use self::components::{Camera, FlyCamera, OrbitCamera};
+use self::gltf_loader::LoadingScene;
+use self::input_map::{Action, InputMap};
+use self::resources::{CameraTransition, DrawTime, Graphics, MainCamera};
 
 mod components;
+mod gltf_loader;
+mod input_map;
 mod resources;
 
 pub struct Game {
@@ -23,45 +28,49 @@ pub struct Game {
     fixed_update_schedule: Schedule,
     draw_schedule: Schedule,
     minimized: bool,
+    cursor_grabbed: bool,
+    pause_when_unfocused: bool,
 }
 
 impl Game {
     pub fn new(renderer: Arc<RendererState>) -> Result<Self> {
-        let started_at = Instant::now();
-
         let mut world = World::default();
-        world.insert_resource(Time {
-            started_at,
-            now: started_at,
-            step: Duration::from_secs(1) / 10, // TEMP 10 FPS
+        world.insert_resource(MainCamera {
+            entity: None,
+            view: Transform::IDENTITY,
         });
-        world.insert_resource(MainCamera { entity: None });
-        world.insert_resource(Graphics::new(renderer)?);
+        world.insert_resource(Graphics::new(renderer.clone())?);
+        world.init_resource::<InputMap>();
+        world.init_resource::<DrawTime>();
 
         let mut fixed_update_schedule = FixedUpdateSchedule::base_schedule();
-        fixed_update_schedule.add_systems(rotate_objects_system.in_set(FixedUpdateSet::OnUpdate));
         fixed_update_schedule.add_systems(
-            (
-                (
-                    apply_static_objects_transform_system,
-                    apply_dynamic_objects_transform_system,
-                ),
-                sync_fixed_update_system,
-            )
-                .chain()
-                .in_set(FixedUpdateSet::AfterUpdate),
+            gltf_loader::spawn_loaded_nodes_system.in_set(FixedUpdateSet::BeforeUpdate),
+        );
+        fixed_update_schedule.add_systems(
+            (rotate_objects_system, fly_camera_system).in_set(FixedUpdateSet::OnUpdate),
+        );
+        RendererPlugin::new(renderer).build(
+            &mut world,
+            &mut fixed_update_schedule,
+            FixedUpdateSet::AfterUpdate,
         );
 
         let mut draw_schedule = DrawSchedule::base_schedule();
+        draw_schedule.add_systems(update_draw_time_system.in_set(DrawSet::BeforeDraw));
+        draw_schedule
+            .add_systems((smooth_fly_camera_system, orbit_camera_system).in_set(DrawSet::OnDraw));
         draw_schedule.add_systems(apply_camera_transform_system.in_set(DrawSet::AfterDraw));
 
+        let transform =
+            Transform::from_translation(Vec3::new(0.0, 0.5, 3.0)).looking_at(Vec3::ZERO, Vec3::Y);
         let entity = world
             .spawn((
                 Camera {
                     projection: Default::default(),
                 },
-                Transform::from_translation(Vec3::new(0.0, 0.5, 3.0))
-                    .looking_at(Vec3::ZERO, Vec3::Y),
+                FlyCamera::new(&transform),
+                transform,
             ))
             .id();
         world.resource_mut::<MainCamera>().entity = Some(entity);
@@ -71,9 +80,17 @@ impl Game {
             fixed_update_schedule,
             draw_schedule,
             minimized: false,
+            cursor_grabbed: false,
+            pause_when_unfocused: false,
         })
     }
 
+    /// Stops drawing while the window is not focused.
+    pub fn with_pause_when_unfocused(mut self, pause: bool) -> Self {
+        self.pause_when_unfocused = pause;
+        self
+    }
+
     pub fn handle_event(
         &mut self,
         event: winit::event::Event<()>,
@@ -84,11 +101,9 @@ impl Game {
         let mut redraw_requested = false;
         match event {
             winit::event::Event::AboutToWait => {
-                self.world
-                    .resource::<Graphics>()
-                    .renderer
-                    .window()
-                    .request_redraw();
+                if let Some(window) = self.world.resource::<Graphics>().renderer.window() {
+                    window.request_redraw();
+                }
             }
             winit::event::Event::WindowEvent { event, .. } => match event {
                 WindowEvent::RedrawRequested if !elwt.exiting() && !self.minimized => {
@@ -96,6 +111,7 @@ impl Game {
                 }
                 WindowEvent::Resized(size) => {
                     self.minimized = size.width == 0 || size.height == 0;
+                    self.world.resource::<Graphics>().renderer.notify_resized();
                 }
                 WindowEvent::CloseRequested => {
                     self.world
@@ -104,82 +120,136 @@ impl Game {
                         .set_running(false);
                     elwt.exit();
                 }
+                WindowEvent::Focused(focused) => {
+                    if !focused {
+                        self.world.resource_mut::<InputMap>().reset();
+                    }
+                    self.set_cursor_grabbed(focused);
+                    if self.pause_when_unfocused {
+                        let graphics = self.world.resource::<Graphics>();
+                        graphics.renderer.set_paused(!focused);
+                    }
+                }
                 WindowEvent::KeyboardInput { event, .. } => {
                     use winit::keyboard::{KeyCode, PhysicalKey};
 
-                    let code = match event.physical_key {
-                        PhysicalKey::Code(code) if event.state.is_pressed() => code,
-                        _ => return,
+                    let PhysicalKey::Code(code) = event.physical_key else {
+                        return;
                     };
+                    let pressed = event.state.is_pressed();
+                    let mapped = self
+                        .world
+                        .resource_mut::<InputMap>()
+                        .handle_key(code, pressed);
+                    if mapped || !pressed {
+                        return;
+                    }
 
                     match code {
-                        KeyCode::ArrowRight => {
-                            self.spawn_cube();
-                            tracing::info!("added test object");
+                        KeyCode::ArrowRight => match self.spawn_cube() {
+                            Ok(()) => tracing::info!("added test object"),
+                            Err(e) => tracing::error!("failed to add test object: {e:?}"),
+                        },
+                        KeyCode::F5 => {
+                            self.world.resource::<Graphics>().renderer.reload_shaders();
                         }
+                        KeyCode::KeyV => self.toggle_vsync(),
+                        KeyCode::KeyT => self.toggle_fixed_timestep(),
+                        KeyCode::KeyG => self.cycle_debug_view(),
+                        KeyCode::KeyC => self.toggle_orbit_camera(),
                         _ => {}
                     }
                 }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    self.world
+                        .resource_mut::<InputMap>()
+                        .handle_mouse_button(button, state.is_pressed());
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        MouseScrollDelta::PixelDelta(position) => {
+                            (position.y / PIXELS_PER_SCROLL_LINE) as f32
+                        }
+                    };
+                    self.world
+                        .resource_mut::<InputMap>()
+                        .handle_mouse_wheel(lines);
+                }
                 _ => {}
             },
+            winit::event::Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta: (x, y) },
+                ..
+            } if self.cursor_grabbed => {
+                self.world
+                    .resource_mut::<InputMap>()
+                    .handle_mouse_motion(Vec2::new(x as f32, y as f32));
+            }
             _ => {}
         }
 
         let now = Instant::now();
 
-        let (mut updated_at, step) = {
-            let time = self.world.resource::<Time>();
-            (time.now, time.step)
+        let step = self.world.resource::<Graphics>().renderer.fixed_timestep();
+        let mut updated_at = {
+            let mut time = self.world.resource_mut::<FixedUpdateTime>();
+            time.step = step;
+            time.updated_at
         };
+        let mut fixed_updated = false;
         loop {
             updated_at += step;
             if updated_at > now {
                 break;
             }
 
-            self.world.resource_mut::<Time>().now = updated_at;
+            self.world.resource_mut::<FixedUpdateTime>().updated_at = updated_at;
             self.fixed_update_schedule.run(&mut self.world);
+            self.world.clear_trackers();
+            fixed_updated = true;
         }
 
         if redraw_requested {
             self.draw_schedule.run(&mut self.world);
             self.world.resource::<Graphics>().renderer.notify_draw();
+        } else if self.minimized && fixed_updated {
+            // NOTE: The renderer skips frames while minimized, but still
+            // has to process the instructions sent during the fixed update.
+            self.world.resource::<Graphics>().renderer.notify_draw();
         }
-    }
-
-    // TEMP
-    pub fn load_gltf(&mut self, path: &Path) -> Result<()> {
-        let (gltf, buffers, _images) = gltf::import(path)?;
-        let scene = gltf
-            .default_scene()
-            .context("default glTF scene not found")?;
-
-        let renderer = self.world.resource::<Graphics>().renderer.clone();
 
-        let mut stack = Vec::new();
-        for node in scene.nodes() {
-            stack.push((node.children(), Mat4::IDENTITY, Some(node)));
-
-            while let Some((children, transform, node)) = stack.last_mut() {
-                if let Some(node) = node.take() {
-                    process_gltf_node(node, &buffers, transform, &mut self.world, &renderer)?;
-                }
-
-                if let Some(child) = children.next() {
-                    let child_transform =
-                        transform.mul_mat4(&Mat4::from_cols_array_2d(&child.transform().matrix()));
-                    stack.push((child.children(), child_transform, Some(child)));
-                } else {
-                    stack.pop();
+        for event in self.world.resource::<Graphics>().renderer.take_events() {
+            match event {
+                RendererEvent::Resized { width, height } => {
+                    tracing::debug!(width, height, "render resolution changed");
                 }
             }
         }
 
+        if let Some(e) = self.world.resource::<Graphics>().renderer.take_error() {
+            tracing::error!("renderer stopped: {:?}", anyhow::Error::from(e));
+            elwt.exit();
+        }
+    }
+
+    /// Starts loading the glTF scene in the background.
+    ///
+    /// Objects are spawned during the fixed update as soon as their nodes are
+    /// processed. The progress is available through the [`LoadingScene`] resource.
+    pub fn load_gltf(&mut self, path: &Path) -> Result<()> {
+        if let Some(scene) = self.world.get_resource::<LoadingScene>() {
+            anyhow::ensure!(scene.is_finished(), "another glTF scene is still loading");
+        }
+
+        let renderer = self.world.resource::<Graphics>().renderer.clone();
+        let scene = LoadingScene::start(path, renderer)?;
+        self.world.insert_resource(scene);
         Ok(())
     }
 
     // TEMP
-    pub fn spawn_cube(&mut self) {
+    pub fn spawn_cube(&mut self) -> Result<()> {
         let graphics = self.world.resource::<Graphics>();
 
         let mut rng = rand::thread_rng();
@@ -196,27 +266,130 @@ impl Game {
         let material = graphics
             .renderer
             .add_material_instance(DebugMaterialInstance {
-                color: Vec3::new(
-                    rng.gen_range(0.0..1.0),
-                    rng.gen_range(0.0..1.0),
-                    rng.gen_range(0.0..1.0),
-                ),
-            });
-
-        let handle = graphics.renderer.add_dynamic_object(
-            mesh.clone(),
-            material.clone(),
-            &transform.to_matrix(),
-        );
+                color: Color::from_srgb(rng.gen(), rng.gen(), rng.gen(), u8::MAX)
+                    .to_linear()
+                    .truncate(),
+                double_sided: false,
+            })?;
 
         self.world.spawn(SceneObjectBundle {
             transform,
             mesh_instance: DynamicMeshInstance {
                 mesh,
-                material,
-                handle,
+                materials: material.into(),
             },
         });
+        Ok(())
+    }
+
+    /// Locks and hides the cursor so that the mouse rotates the camera.
+    fn set_cursor_grabbed(&mut self, grabbed: bool) {
+        use winit::window::CursorGrabMode;
+
+        let Some(window) = self.world.resource::<Graphics>().renderer.window() else {
+            return;
+        };
+
+        let res = if grabbed {
+            // NOTE: Platforms support either of the modes
+            window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(e) = res {
+            tracing::warn!(grabbed, "failed to change cursor grab: {e}");
+            return;
+        }
+
+        window.set_cursor_visible(!grabbed);
+        self.cursor_grabbed = grabbed;
+    }
+
+    fn toggle_vsync(&self) {
+        use renderer::PresentMode;
+
+        let renderer = &self.world.resource::<Graphics>().renderer;
+
+        let mode = if renderer.present_mode() == PresentMode::Fifo {
+            // Prefer tearing over the additional latency
+            [PresentMode::Immediate, PresentMode::Mailbox]
+                .into_iter()
+                .find(|mode| renderer.supported_present_modes().contains(mode))
+                .unwrap_or(PresentMode::Fifo)
+        } else {
+            PresentMode::Fifo
+        };
+
+        tracing::info!(?mode, "changed present mode");
+        renderer.set_present_mode(mode);
+    }
+
+    fn cycle_debug_view(&self) {
+        use renderer::DebugView;
+
+        let renderer = &self.world.resource::<Graphics>().renderer;
+
+        let current = renderer.debug_view();
+        let index = DebugView::ALL.iter().position(|view| *view == current);
+        let index = index.unwrap_or_default();
+
+        // NOTE: Views which are not supported by the device are skipped
+        for offset in 1..=DebugView::ALL.len() {
+            let view = DebugView::ALL[(index + offset) % DebugView::ALL.len()];
+            if renderer.set_debug_view(view) {
+                tracing::info!(?view, "changed debug view");
+                return;
+            }
+        }
+    }
+
+    /// Switches the main camera between the fly and orbit controllers.
+    ///
+    /// The view is blended from the previously drawn one, so it doesn't snap
+    /// when the new controller constrains the pose.
+    fn toggle_orbit_camera(&mut self) {
+        const ORBIT_DISTANCE: f32 = 5.0;
+        const TRANSITION_DURATION: Duration = Duration::from_millis(200);
+
+        let main_camera = self.world.resource::<MainCamera>();
+        let (entity, from) = (main_camera.entity, main_camera.view);
+        let Some(mut entity) = entity.and_then(|entity| self.world.get_entity_mut(entity)) else {
+            return;
+        };
+
+        if let Some(fly_camera) = entity.take::<FlyCamera>() {
+            let orbit_camera = OrbitCamera::new(&fly_camera.view, ORBIT_DISTANCE);
+            entity.insert((orbit_camera.current.transform(), orbit_camera));
+            tracing::info!("switched to orbit camera");
+        } else if let Some(orbit_camera) = entity.take::<OrbitCamera>() {
+            let transform = orbit_camera.current.transform();
+            entity.insert((transform, FlyCamera::new(&transform)));
+            tracing::info!("switched to fly camera");
+        } else {
+            return;
+        }
+
+        self.world
+            .insert_resource(CameraTransition::new(from, TRANSITION_DURATION));
+    }
+
+    fn toggle_fixed_timestep(&self) {
+        const SLOW_TIMESTEP: Duration = Duration::from_millis(100);
+
+        let renderer = &self.world.resource::<Graphics>().renderer;
+
+        let timestep = if renderer.fixed_timestep() == SLOW_TIMESTEP {
+            Duration::from_secs(1) / 60
+        } else {
+            SLOW_TIMESTEP
+        };
+
+        tracing::info!(?timestep, "changed fixed timestep");
+        if let Err(e) = renderer.set_fixed_timestep(timestep) {
+            tracing::error!("failed to change fixed timestep: {e}");
+        }
     }
 }
 
@@ -263,101 +436,6 @@ pub enum DrawSet {
     AfterDraw,
 }
 
-fn process_gltf_node(
-    node: gltf::Node,
-    buffers: &[gltf::buffer::Data],
-    global_transform: &Mat4,
-    ecs_world: &mut World,
-    renderer: &Arc<RendererState>,
-) -> Result<()> {
-    let Some(mesh) = node.mesh() else {
-        return Ok(());
-    };
-
-    for primitive in mesh.primitives() {
-        let reader =
-            primitive.reader(|buffer| buffers.get(buffer.index()).map(std::ops::Deref::deref));
-        let Some(positions) = reader.read_positions() else {
-            continue;
-        };
-        let Some(indices) = reader.read_indices() else {
-            continue;
-        };
-
-        let vertex_count = positions.len();
-
-        #[inline]
-        fn optional_iter<I, T: Default>(iter: Option<I>, len: usize) -> Result<Option<I>>
-        where
-            I: Iterator<Item = T> + ExactSizeIterator,
-        {
-            if let Some(iter) = &iter {
-                anyhow::ensure!(iter.len() == len, "component array length mismatch");
-            }
-            Ok(iter)
-        }
-
-        let normals = optional_iter(reader.read_normals(), vertex_count)?;
-        let tangents = optional_iter(reader.read_tangents(), vertex_count)?;
-        let uv0 = optional_iter(
-            reader.read_tex_coords(0).map(|iter| iter.into_f32()),
-            vertex_count,
-        )?;
-
-        let mesh = {
-            let mut builder = renderer::Mesh::builder(
-                positions
-                    .map(|[x, y, z]| renderer::Position(Vec3::new(x, y, z)))
-                    .collect::<Vec<_>>(),
-            );
-
-            if let Some(normals) = normals {
-                builder = builder.with_normals(
-                    normals
-                        .map(|[x, y, z]| renderer::Normal(Vec3::new(x, y, z)))
-                        .collect::<Vec<_>>(),
-                );
-            } else {
-                builder = builder.with_computed_normals();
-            }
-
-            if let Some(tangents) = tangents {
-                builder = builder.with_tangents(
-                    tangents
-                        .map(|[x, y, z, _]| renderer::Tangent(Vec3::new(x, y, z)))
-                        .collect::<Vec<_>>(),
-                );
-            }
-            if let Some(uv0) = uv0 {
-                builder = builder.with_uv0(
-                    uv0.map(|[x, y]| renderer::UV0(Vec2::new(x, y)))
-                        .collect::<Vec<_>>(),
-                );
-            }
-
-            builder.with_indices(indices.into_u32().collect()).build()?
-        };
-
-        let mesh = renderer.add_mesh(&mesh)?;
-        let material = renderer.add_material_instance(renderer::materials::DebugMaterialInstance {
-            color: glam::vec3(1.0, 1.0, 1.0),
-        });
-
-        let handle = renderer.add_dynamic_object(mesh.clone(), material.clone(), global_transform);
-
-        ecs_world.spawn(SceneObjectBundle {
-            transform: Transform::from_matrix(*global_transform),
-            mesh_instance: DynamicMeshInstance {
-                mesh,
-                material,
-                handle,
-            },
-        });
-    }
-
-    Ok(())
-}
-
 #[derive(Bundle)]
 struct SceneObjectBundle {
     transform: Transform,
@@ -366,7 +444,7 @@ struct SceneObjectBundle {
 
 // TEMP
 fn rotate_objects_system(
-    time: Res<Time>,
+    time: Res<FixedUpdateTime>,
     mut query: Query<(&mut Transform, &DynamicMeshInstance)>,
 ) {
     for (mut transform, _) in &mut query {
@@ -374,48 +452,128 @@ fn rotate_objects_system(
     }
 }
 
-fn apply_static_objects_transform_system(
-    graphics: Res<Graphics>,
-    query: Query<(&Transform, &StaticMeshInstance), Changed<Transform>>,
+fn fly_camera_system(
+    time: Res<FixedUpdateTime>,
+    mut input: ResMut<InputMap>,
+    mut query: Query<(&mut Transform, &mut FlyCamera)>,
 ) {
-    for (transform, object) in &query {
-        graphics
-            .renderer
-            .update_static_object(&object.handle, transform.to_matrix());
+    if query.is_empty() {
+        return;
+    }
+
+    let dt = time.step.as_secs_f32();
+    let mouse_delta = input.take_mouse_delta();
+    // NOTE: Fly cameras don't zoom
+    input.take_scroll_delta();
+
+    for (mut transform, mut camera) in &mut query {
+        camera.prev_transform = *transform;
+
+        camera.yaw -= mouse_delta.x * camera.sensitivity;
+        camera.pitch = (camera.pitch - mouse_delta.y * camera.sensitivity)
+            .clamp(-FlyCamera::MAX_PITCH, FlyCamera::MAX_PITCH);
+        transform.rotation = camera.rotation();
+
+        let direction = transform.forward() * input.axis(Action::MoveBackward, Action::MoveForward)
+            + transform.right() * input.axis(Action::MoveLeft, Action::MoveRight)
+            + Vec3::Y * input.axis(Action::MoveDown, Action::MoveUp);
+
+        let mut speed = camera.speed;
+        if input.is_pressed(Action::Sprint) {
+            speed *= camera.sprint_multiplier;
+        }
+        transform.translation += direction.normalize_or_zero() * speed * dt;
     }
 }
 
-fn apply_dynamic_objects_transform_system(
-    graphics: Res<Graphics>,
-    query: Query<(&Transform, &DynamicMeshInstance), Changed<Transform>>,
+/// Interpolates fly cameras between the last two fixed updates,
+/// the same way the renderer interpolates dynamic objects.
+fn smooth_fly_camera_system(
+    time: Res<FixedUpdateTime>,
+    mut query: Query<(&Transform, &mut FlyCamera)>,
 ) {
-    for (transform, object) in &query {
-        graphics
-            .renderer
-            .update_dynamic_object(&object.handle, transform.to_matrix(), false);
+    let elapsed = Instant::now().saturating_duration_since(time.updated_at);
+    let t = (elapsed.as_secs_f32() / time.step.as_secs_f32()).clamp(0.0, 1.0);
+
+    for (transform, mut camera) in &mut query {
+        let prev = camera.prev_transform;
+        camera.view = Transform {
+            translation: prev.translation.lerp(transform.translation, t),
+            rotation: prev.rotation.slerp(transform.rotation, t),
+            scale: transform.scale,
+        };
     }
 }
 
-fn sync_fixed_update_system(time: Res<Time>, graphics: Res<Graphics>) {
-    graphics.renderer.finish_fixed_update(time.now, time.step);
+/// Moves orbit cameras with the input at the draw rate,
+/// so they stay smooth regardless of the fixed timestep.
+fn orbit_camera_system(
+    time: Res<DrawTime>,
+    mut input: ResMut<InputMap>,
+    mut query: Query<(&mut Transform, &mut OrbitCamera)>,
+) {
+    if query.is_empty() {
+        return;
+    }
+
+    let dt = time.delta.as_secs_f32();
+    let mouse_delta = input.take_mouse_delta();
+    let scroll_delta = input.take_scroll_delta();
+    let panning = input.is_pressed(Action::Pan);
+
+    for (mut transform, mut camera) in &mut query {
+        if panning {
+            camera.pan(mouse_delta);
+        } else {
+            camera.rotate(mouse_delta);
+        }
+        camera.zoom(scroll_delta);
+        camera.update(dt);
+        *transform = camera.current.transform();
+    }
+}
+
+fn update_draw_time_system(mut time: ResMut<DrawTime>) {
+    let now = Instant::now();
+    time.delta = match time.drawn_at {
+        Some(drawn_at) => now.saturating_duration_since(drawn_at),
+        None => Duration::ZERO,
+    };
+    time.drawn_at = Some(now);
 }
 
 fn apply_camera_transform_system(
+    mut commands: Commands,
     graphics: Res<Graphics>,
-    main_camera: Res<MainCamera>,
-    world: &World,
+    time: Res<DrawTime>,
+    mut main_camera: ResMut<MainCamera>,
+    transition: Option<ResMut<CameraTransition>>,
+    query: Query<(&Transform, &Camera, Option<&FlyCamera>)>,
 ) {
     let Some(entity) = main_camera.entity else {
         return;
     };
-
-    let (Some(transform), Some(camera)) =
-        (world.get::<Transform>(entity), world.get::<Camera>(entity))
-    else {
+    let Ok((transform, camera, fly_camera)) = query.get(entity) else {
         return;
     };
 
+    // NOTE: Fly cameras are drawn with the smoothed transform
+    let mut view = match fly_camera {
+        Some(fly_camera) => fly_camera.view,
+        None => *transform,
+    };
+    if let Some(mut transition) = transition {
+        match transition.advance(time.delta, &view) {
+            Some(blended) => view = blended,
+            None => commands.remove_resource::<CameraTransition>(),
+        }
+    }
+
+    main_camera.view = view;
     graphics
         .renderer
-        .update_camera(&transform.to_matrix().inverse(), &camera.projection);
+        .update_camera(&view.to_matrix().inverse(), &camera.projection);
 }
+
+/// Scroll distance of a mouse wheel line for touchpads.
+const PIXELS_PER_SCROLL_LINE: f64 = 20.0;
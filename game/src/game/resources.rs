@@ -4,18 +4,61 @@ use std::time::{Duration, Instant};
 use anyhow::Result;
 use bevy_ecs::entity::Entity;
 use bevy_ecs::system::Resource;
+use ecs::components::Transform;
 use renderer::{MeshHandle, RendererState};
 
-#[derive(Resource)]
-pub struct Time {
-    pub started_at: Instant,
-    pub now: Instant,
-    pub step: Duration,
-}
-
 #[derive(Resource)]
 pub struct MainCamera {
     pub entity: Option<Entity>,
+    /// Transform the camera was drawn with during the last draw.
+    pub view: Transform,
+}
+
+/// Time between the draws, unlike [`FixedUpdateTime`] it depends
+/// on the frame rate.
+///
+/// [`FixedUpdateTime`]: renderer::ecs::FixedUpdateTime
+#[derive(Default, Resource)]
+pub struct DrawTime {
+    pub drawn_at: Option<Instant>,
+    /// Time since the previous draw, zero for the first one.
+    pub delta: Duration,
+}
+
+/// Blends the drawn camera from its previous view,
+/// e.g. after switching the camera controller.
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct CameraTransition {
+    pub from: Transform,
+    pub duration: Duration,
+    pub elapsed: Duration,
+}
+
+impl CameraTransition {
+    pub fn new(from: Transform, duration: Duration) -> Self {
+        Self {
+            from,
+            duration,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Advances the transition and returns the blended transform,
+    /// or `None` if the transition is finished.
+    pub fn advance(&mut self, delta: Duration, to: &Transform) -> Option<Transform> {
+        self.elapsed += delta;
+        if self.elapsed >= self.duration {
+            return None;
+        }
+
+        let t = self.elapsed.as_secs_f32() / self.duration.as_secs_f32();
+        let t = t * t * (3.0 - 2.0 * t);
+        Some(Transform {
+            translation: self.from.translation.lerp(to.translation, t),
+            rotation: self.from.rotation.slerp(to.rotation, t),
+            scale: to.scale,
+        })
+    }
 }
 
 #[derive(Resource)]
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use anyhow::Result;
@@ -43,6 +44,22 @@ struct App {
     #[argh(switch)]
     vk_debug_shaders: bool,
 
+    /// name Vulkan objects and label passes for graphics debuggers
+    #[argh(switch)]
+    vk_debug_labels: bool,
+
+    /// path to the pipeline cache file
+    #[argh(option)]
+    vk_pipeline_cache: Option<PathBuf>,
+
+    /// load shaders from the specified directory (reload with F5)
+    #[argh(option)]
+    shaders_dir: Option<PathBuf>,
+
+    /// stop drawing while the window is not focused
+    #[argh(switch)]
+    pause_unfocused: bool,
+
     /// enable X11-specific popup mode
     #[cfg(x11_platform)]
     #[argh(switch)]
@@ -117,10 +134,18 @@ impl App {
         let mut renderer = Renderer::builder(window.clone())
             .app_version((0, 0, 1))
             .validation_layer(self.vk_validation_layer)
+            .debug_labels(self.vk_debug_labels)
             .shaders_debug_info_enabled(self.vk_debug_shaders)
-            .build()?;
+            .pipeline_cache_path(self.vk_pipeline_cache);
+        if let Some(shaders_dir) = self.shaders_dir {
+            renderer = renderer.shaders_override_dir(shaders_dir);
+        }
+        let mut renderer = renderer.build()?;
+        renderer.state().set_gpu_profiling_enabled(self.profiling);
 
-        let mut game = Box::new(Game::new(renderer.state().clone())?);
+        let mut game = Box::new(
+            Game::new(renderer.state().clone())?.with_pause_when_unfocused(self.pause_unfocused),
+        );
 
         if let Some(gltf_scene_path) = self.gltf_scene {
             game.load_gltf(gltf_scene_path.as_ref())?;
@@ -1,8 +1,125 @@
-use proc_macro2::TokenStream;
-use quote::{format_ident, quote};
-use syn::{Data, DeriveInput, Fields, Type};
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::spanned::Spanned;
+use syn::{Data, DataEnum, DeriveInput, Field, Fields, GenericParam, Generics, Type};
 
 pub fn impl_as_shader_layout(input: DeriveInput, layout_type: LayoutType) -> TokenStream {
+    let result = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                // NOTE: `PhantomData` fields are not a part of the shader layout
+                let fields: Vec<_> = fields
+                    .named
+                    .iter()
+                    .filter(|field| !is_phantom_data(&field.ty))
+                    .collect();
+                check_fields(&input.generics, &fields)
+                    .map(|_| impl_struct(&input, &fields, layout_type))
+            }
+            Fields::Unnamed(fields) => Err(syn::Error::new_spanned(
+                fields,
+                "Tuple structs are not supported",
+            )),
+            Fields::Unit => Err(syn::Error::new_spanned(
+                &input.ident,
+                "Unit structs are not supported",
+            )),
+        },
+        Data::Enum(data) => impl_enum(&input, data, layout_type),
+        Data::Union(data) => Err(syn::Error::new_spanned(
+            data.union_token,
+            "Unions are not supported",
+        )),
+    };
+
+    result.unwrap_or_else(syn::Error::into_compile_error)
+}
+
+fn is_phantom_data(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "PhantomData"),
+        _ => false,
+    }
+}
+
+/// Checks that all field types can have a fixed shader layout.
+fn check_fields(generics: &Generics, fields: &[&Field]) -> syn::Result<()> {
+    let mut result = Ok(());
+    for field in fields {
+        let field_result =
+            check_field_type(&field.ty).and_then(|_| check_field_generics(generics, &field.ty));
+        if let Err(e) = field_result {
+            match &mut result {
+                Ok(()) => result = Err(e),
+                Err(errors) => errors.combine(e),
+            }
+        }
+    }
+    result
+}
+
+/// Generic parameters can only be used in `PhantomData` fields, since
+/// the padding of the generated struct must be known without them.
+fn check_field_generics(generics: &Generics, ty: &Type) -> syn::Result<()> {
+    fn uses_param(tokens: TokenStream, params: &[&syn::Ident]) -> bool {
+        tokens.into_iter().any(|token| match token {
+            TokenTree::Ident(ident) => params.contains(&&ident),
+            TokenTree::Group(group) => uses_param(group.stream(), params),
+            _ => false,
+        })
+    }
+
+    let params = generics
+        .params
+        .iter()
+        .map(|param| match param {
+            GenericParam::Type(param) => &param.ident,
+            GenericParam::Lifetime(param) => &param.lifetime.ident,
+            GenericParam::Const(param) => &param.ident,
+        })
+        .collect::<Vec<_>>();
+
+    if uses_param(ty.to_token_stream(), &params) {
+        return Err(syn::Error::new_spanned(
+            ty,
+            "Generic parameters are only supported in `PhantomData` fields",
+        ));
+    }
+    Ok(())
+}
+
+fn check_field_type(ty: &Type) -> syn::Result<()> {
+    let unsupported = |message: &str| Err(syn::Error::new_spanned(ty, message));
+
+    match ty {
+        Type::Array(array) => check_field_type(&array.elem),
+        Type::Group(group) => check_field_type(&group.elem),
+        Type::Paren(paren) => check_field_type(&paren.elem),
+        Type::Reference(_) | Type::Ptr(_) => {
+            unsupported("References and pointers are not supported in shader layouts")
+        }
+        Type::Slice(_) | Type::TraitObject(_) | Type::ImplTrait(_) => {
+            unsupported("Unsized types are not supported in shader layouts")
+        }
+        Type::Tuple(_) => unsupported("Tuples are not supported in shader layouts"),
+        Type::Path(path) => match path.path.segments.last() {
+            Some(segment) if segment.ident == "str" => {
+                unsupported("Unsized types are not supported in shader layouts")
+            }
+            Some(segment) if ["Vec", "String", "Box"].iter().any(|name| segment.ident == name) => {
+                unsupported("Heap allocated types are not supported in shader layouts, use fixed-size arrays instead")
+            }
+            _ => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn impl_struct(input: &DeriveInput, fields: &[&Field], layout_type: LayoutType) -> TokenStream {
     let trait_name = format_ident!("{}", layout_type.name());
     let trait_path = quote! { ::gfx::#trait_name };
 
@@ -11,21 +128,13 @@ pub fn impl_as_shader_layout(input: DeriveInput, layout_type: LayoutType) -> Tok
     let as_trait_method = layout_type.as_trait_method();
     let write_as_trait_method = layout_type.write_as_trait_method();
 
-    let visibility = input.vis;
-    let input_name = input.ident;
+    let visibility = &input.vis;
+    let input_name = &input.ident;
+    // NOTE: The generated struct is not generic since its fields don't use the parameters
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     let generated_name = format_ident!("{}{}", layout_type.name(), input_name);
 
-    let fields: Vec<_> = match &input.data {
-        Data::Struct(data) => match &data.fields {
-            Fields::Named(fields) => fields.named.iter().collect(),
-            Fields::Unnamed(_) => panic!("Tuple structs are not supported"),
-            Fields::Unit => panic!("Unit structs are not supported"),
-        },
-        _ => panic!("Only structs are supported"),
-    };
-
     let layout_version_of_ty = |ty: &Type| {
         quote! { <#ty as #as_trait_path>::Output }
     };
@@ -123,11 +232,27 @@ pub fn impl_as_shader_layout(input: DeriveInput, layout_type: LayoutType) -> Tok
         #doc
         #[derive(Debug, Clone, Copy)]
         #[repr(C)]
-        #visibility struct #generated_name #ty_generics #where_clause {
+        #visibility struct #generated_name {
             #generated_struct_fields
         }
     };
 
+    // NOTE: Padding is computed from the shader alignment, so the generated
+    // struct is only valid if `repr(C)` doesn't insert any extra padding.
+    let alignment_checks: TokenStream = fields
+        .iter()
+        .map(|field| {
+            let layout_ty = layout_version_of_ty(&field.ty);
+            let align_mask = layout_align_mask_of_ty(&field.ty);
+            quote_spanned! {field.ty.span()=>
+                const _: () = assert!(
+                    ::core::mem::align_of::<#layout_ty>() <= #align_mask + 1,
+                    "field type has a stricter alignment than its shader layout",
+                );
+            }
+        })
+        .collect();
+
     let as_trait_fields: TokenStream = fields
         .iter()
         .map(|field| {
@@ -151,19 +276,21 @@ pub fn impl_as_shader_layout(input: DeriveInput, layout_type: LayoutType) -> Tok
     quote! {
         #struct_definition
         #pad_fn_impls
+        #alignment_checks
 
-        unsafe impl #impl_generics ::gfx::inner_proc_stuff::bytemuck::Zeroable for #generated_name #ty_generics #where_clause {}
-        unsafe impl #impl_generics ::gfx::inner_proc_stuff::bytemuck::Pod for #generated_name #ty_generics #where_clause {}
+        unsafe impl ::gfx::inner_proc_stuff::bytemuck::Zeroable for #generated_name {}
+        unsafe impl ::gfx::inner_proc_stuff::bytemuck::Pod for #generated_name {}
 
-        impl #impl_generics #generated_name #ty_generics #where_clause {
+        impl #generated_name {
             pub fn as_bytes(&self) -> &[u8] {
-                <#generated_name #ty_generics as #trait_path>::as_bytes(self)
+                <#generated_name as #trait_path>::as_bytes(self)
             }
         }
 
-        unsafe impl #impl_generics #trait_path for #generated_name #ty_generics #where_clause {
+        unsafe impl #trait_path for #generated_name {
             const ALIGN_MASK: usize = #struct_alignment;
 
+            // NOTE: The struct size is already padded to its alignment
             type ArrayPadding = [u8; 0];
         }
 
@@ -184,6 +311,62 @@ pub fn impl_as_shader_layout(input: DeriveInput, layout_type: LayoutType) -> Tok
     }
 }
 
+/// Fieldless enums are represented as `u32` discriminants.
+fn impl_enum(
+    input: &DeriveInput,
+    data: &DataEnum,
+    layout_type: LayoutType,
+) -> syn::Result<TokenStream> {
+    if data.variants.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "Enums without variants are not supported",
+        ));
+    }
+
+    if let Some(variant) = data
+        .variants
+        .iter()
+        .find(|variant| !matches!(variant.fields, Fields::Unit))
+    {
+        return Err(syn::Error::new_spanned(
+            &variant.fields,
+            "Only enums with unit variants are supported",
+        ));
+    }
+
+    let as_trait_name = format_ident!("As{}", layout_type.name());
+    let as_trait_path = quote! { ::gfx::#as_trait_name };
+    let as_trait_method = layout_type.as_trait_method();
+    let write_as_trait_method = layout_type.write_as_trait_method();
+
+    let input_name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let variants = data.variants.iter().map(|variant| &variant.ident);
+    let discriminants = quote! {
+        match self {
+            #( Self::#variants => Self::#variants as u32, )*
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics #as_trait_path for #input_name #ty_generics #where_clause {
+            type Output = u32;
+
+            #[inline]
+            fn #as_trait_method(&self) -> Self::Output {
+                #discriminants
+            }
+
+            #[inline]
+            fn #write_as_trait_method(&self, dst: &mut Self::Output) {
+                *dst = #discriminants;
+            }
+        }
+    })
+}
+
+#[derive(Clone, Copy)]
 pub enum LayoutType {
     Std140,
     Std430,
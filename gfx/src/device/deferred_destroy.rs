@@ -0,0 +1,51 @@
+use std::sync::Mutex;
+
+use vulkanalia::prelude::v1_0::*;
+
+use super::epochs::{EpochSnapshot, Epochs};
+
+/// A resource which was dropped by its last owner.
+pub(crate) enum DeferredDestroy {
+    Buffer(vk::Buffer, gpu_alloc::MemoryBlock<vk::DeviceMemory>),
+    BufferView(vk::BufferView),
+    Image(vk::Image, gpu_alloc::MemoryBlock<vk::DeviceMemory>),
+    ImageView(vk::ImageView),
+}
+
+/// Resources which are waiting for the GPU to stop using them.
+///
+/// Dropped resources could still be used by the work which is recorded
+/// or submitted without referencing them (e.g. through bindless descriptors),
+/// so they are destroyed only after the epochs of all queues are passed.
+#[derive(Default)]
+pub(crate) struct DeferredDestroyQueue {
+    // NOTE: Enqueueing never waits for the Vulkan calls since resources are
+    // destroyed outside of the lock.
+    pending: Mutex<Vec<(EpochSnapshot, DeferredDestroy)>>,
+}
+
+impl DeferredDestroyQueue {
+    pub fn push(&self, epochs: &Epochs, resource: DeferredDestroy) {
+        let snapshot = epochs.snapshot();
+        self.pending.lock().unwrap().push((snapshot, resource));
+    }
+
+    /// Moves resources which are no longer used by the GPU into `ready`.
+    ///
+    /// NOTE: Resources are destroyed in the order they were dropped,
+    /// so views are destroyed before their images.
+    pub fn drain_passed(&self, epochs: &Epochs, ready: &mut Vec<DeferredDestroy>) {
+        let mut pending = self.pending.lock().unwrap();
+        let passed = pending
+            .iter()
+            .take_while(|(snapshot, _)| epochs.is_passed(snapshot))
+            .count();
+        ready.extend(pending.drain(..passed).map(|(_, resource)| resource));
+    }
+
+    /// Takes all resources regardless of their epochs.
+    pub fn drain_all(&self) -> Vec<DeferredDestroy> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        pending.into_iter().map(|(_, resource)| resource).collect()
+    }
+}
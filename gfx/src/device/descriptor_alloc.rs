@@ -8,46 +8,187 @@ use vulkanalia::prelude::v1_0::*;
 use crate::resources::{DescriptorSetLayout, DescriptorSetLayoutFlags, DescriptorSetSize};
 use crate::types::OutOfDeviceMemory;
 
-#[derive(Default)]
-pub(crate) struct DescriptorAlloc {
-    buckets: FastHashMap<(DescriptorSetSize, bool), DescriptorBucket>,
-    sets_cache: Vec<AllocatedDescriptorSet>,
-    raw_sets_cache: Vec<vk::DescriptorSet>,
+/// Low-level descriptor pool operations used by the allocator.
+pub(crate) trait DescriptorPoolBackend {
+    type Pool: Copy + std::fmt::Debug;
+    type Set: Copy + std::fmt::Debug;
+    type Layout: Copy;
+
+    unsafe fn create_pool(
+        &self,
+        size: &DescriptorSetSize,
+        max_sets: u32,
+        update_after_bind: bool,
+    ) -> Result<Self::Pool, DescriptorAllocError>;
+
+    unsafe fn allocate_sets(
+        &self,
+        pool: Self::Pool,
+        layout: Self::Layout,
+        count: u32,
+    ) -> Result<Vec<Self::Set>, PoolAllocError>;
+
+    unsafe fn free_sets(&self, pool: Self::Pool, sets: &[Self::Set]);
+
+    unsafe fn reset_pool(&self, pool: Self::Pool);
+
+    unsafe fn destroy_pool(&self, pool: Self::Pool);
 }
 
-impl DescriptorAlloc {
-    pub fn new() -> Self {
-        Self::default()
+impl DescriptorPoolBackend for Device {
+    type Pool = vk::DescriptorPool;
+    type Set = vk::DescriptorSet;
+    type Layout = vk::DescriptorSetLayout;
+
+    unsafe fn create_pool(
+        &self,
+        size: &DescriptorSetSize,
+        max_sets: u32,
+        update_after_bind: bool,
+    ) -> Result<Self::Pool, DescriptorAllocError> {
+        create_descriptor_pool(
+            self,
+            size,
+            max_sets,
+            if update_after_bind {
+                vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET
+                    | vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
+            } else {
+                vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET
+            },
+        )
+    }
+
+    unsafe fn allocate_sets(
+        &self,
+        pool: Self::Pool,
+        layout: Self::Layout,
+        count: u32,
+    ) -> Result<Vec<Self::Set>, PoolAllocError> {
+        let set_layouts = SmallVec::<[_; 16]>::from_elem(layout, count as usize);
+
+        match self.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&set_layouts),
+        ) {
+            Ok(sets) => Ok(sets),
+            Err(vk::ErrorCode::OUT_OF_DEVICE_MEMORY) => Err(PoolAllocError::OutOfDeviceMemory),
+            Err(vk::ErrorCode::OUT_OF_HOST_MEMORY) => crate::out_of_host_memory(),
+            Err(vk::ErrorCode::FRAGMENTED_POOL) => Err(PoolAllocError::FragmentedPool),
+            Err(vk::ErrorCode::OUT_OF_POOL_MEMORY) => Err(PoolAllocError::OutOfPoolMemory),
+            Err(e) => crate::unexpected_vulkan_error(e),
+        }
     }
 
+    unsafe fn free_sets(&self, pool: Self::Pool, sets: &[Self::Set]) {
+        self.free_descriptor_sets(pool, sets).unwrap();
+    }
+
+    unsafe fn reset_pool(&self, pool: Self::Pool) {
+        self.reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())
+            .unwrap();
+    }
+
+    unsafe fn destroy_pool(&self, pool: Self::Pool) {
+        self.destroy_descriptor_pool(pool, None);
+    }
+}
+
+/// Descriptor set allocation error of a single pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PoolAllocError {
+    OutOfDeviceMemory,
+    OutOfPoolMemory,
+    FragmentedPool,
+}
+
+/// Descriptor allocator statistics.
+///
+/// NOTE: Intended for debugging, e.g. to find leaked descriptor sets.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorAllocStats {
+    /// Number of descriptor pools across all layout sizes.
+    pub pool_count: usize,
+    /// Number of currently allocated descriptor sets.
+    pub allocated_sets: u64,
+}
+
+/// Descriptor set allocator.
+///
+/// Sets are allocated from pools grouped by the descriptor counts of their
+/// layout. Each new pool of a group is twice as large as the previous one
+/// (up to a limit), and pools without allocated sets are reset for reuse.
+pub(crate) struct DescriptorAlloc<B: DescriptorPoolBackend = Device> {
+    buckets: FastHashMap<(DescriptorSetSize, bool), DescriptorBucket<B>>,
+    sets_cache: Vec<AllocatedDescriptorSet<B::Set>>,
+    raw_sets_cache: Vec<B::Set>,
+}
+
+impl DescriptorAlloc {
     pub unsafe fn allocate(
         &mut self,
         device: &Device,
         layout: &DescriptorSetLayout,
         count: u32,
     ) -> Result<Vec<AllocatedDescriptorSet>, DescriptorAllocError> {
-        if count == 0 {
-            return Ok(Default::default());
+        let update_after_bind = layout
+            .info()
+            .flags
+            .contains(DescriptorSetLayoutFlags::UPDATE_AFTER_BIND_POOL);
+
+        self.allocate_raw(
+            device,
+            layout.handle(),
+            layout.size(),
+            update_after_bind,
+            count,
+        )
+    }
+}
+
+impl<B: DescriptorPoolBackend> DescriptorAlloc<B> {
+    pub fn new() -> Self {
+        Self {
+            buckets: FastHashMap::default(),
+            sets_cache: Vec::new(),
+            raw_sets_cache: Vec::new(),
         }
+    }
 
-        let info = layout.info();
+    pub fn stats(&self) -> DescriptorAllocStats {
+        let mut stats = DescriptorAllocStats::default();
+        for bucket in self.buckets.values() {
+            stats.pool_count += bucket.pools.len();
+            stats.allocated_sets += bucket.total;
+        }
+        stats
+    }
 
-        let update_after_bind = info
-            .flags
-            .contains(DescriptorSetLayoutFlags::UPDATE_AFTER_BIND_POOL);
+    pub unsafe fn allocate_raw(
+        &mut self,
+        backend: &B,
+        layout: B::Layout,
+        size: &DescriptorSetSize,
+        update_after_bind: bool,
+        count: u32,
+    ) -> Result<Vec<AllocatedDescriptorSet<B::Set>>, DescriptorAllocError> {
+        if count == 0 {
+            return Ok(Default::default());
+        }
 
         let bucket = self
             .buckets
-            .entry((*layout.size(), update_after_bind))
-            .or_insert_with(|| DescriptorBucket::new(update_after_bind, layout.size()));
+            .entry((*size, update_after_bind))
+            .or_insert_with(|| DescriptorBucket::new(update_after_bind, size));
 
-        match bucket.allocate(device, layout, count, &mut self.sets_cache) {
+        match bucket.allocate(backend, layout, count, &mut self.sets_cache) {
             Ok(()) => Ok(std::mem::take(&mut self.sets_cache)),
             Err(e) => {
                 if let Some(mut last_pool_id) = self.sets_cache.first().map(|s| s.pool_id) {
                     for set in &self.sets_cache {
                         if set.pool_id != last_pool_id {
-                            bucket.free(device, &self.raw_sets_cache, last_pool_id);
+                            bucket.free(backend, &self.raw_sets_cache, last_pool_id);
 
                             self.raw_sets_cache.clear();
                             last_pool_id = set.pool_id;
@@ -56,7 +197,7 @@ impl DescriptorAlloc {
                     }
 
                     if !self.raw_sets_cache.is_empty() {
-                        bucket.free(device, &self.raw_sets_cache, last_pool_id);
+                        bucket.free(backend, &self.raw_sets_cache, last_pool_id);
                         self.raw_sets_cache.clear();
                     }
                 }
@@ -67,7 +208,7 @@ impl DescriptorAlloc {
         }
     }
 
-    pub unsafe fn free(&mut self, device: &Device, sets: &[AllocatedDescriptorSet]) {
+    pub unsafe fn free(&mut self, backend: &B, sets: &[AllocatedDescriptorSet<B::Set>]) {
         let (mut last_key, mut last_pool_id) = match sets.first() {
             Some(set) => ((set.size, set.update_after_bind), set.pool_id),
             None => return,
@@ -78,7 +219,7 @@ impl DescriptorAlloc {
                 self.buckets
                     .get_mut(&last_key)
                     .expect("invalid bucket key")
-                    .free(device, &self.raw_sets_cache, last_pool_id);
+                    .free(backend, &self.raw_sets_cache, last_pool_id);
 
                 self.raw_sets_cache.clear();
                 last_key = (set.size, set.update_after_bind);
@@ -92,21 +233,21 @@ impl DescriptorAlloc {
             self.buckets
                 .get_mut(&last_key)
                 .expect("invalid bucket key")
-                .free(device, &self.raw_sets_cache, last_pool_id);
+                .free(backend, &self.raw_sets_cache, last_pool_id);
 
             self.raw_sets_cache.clear();
         }
     }
 
-    pub unsafe fn cleanup(&mut self, device: &Device) {
+    pub unsafe fn cleanup(&mut self, backend: &B) {
         for bucket in self.buckets.values_mut() {
-            bucket.cleanup(device);
+            bucket.cleanup(backend);
         }
         self.buckets.retain(|_, bucket| !bucket.pools.is_empty());
     }
 }
 
-impl Drop for DescriptorAlloc {
+impl<B: DescriptorPoolBackend> Drop for DescriptorAlloc<B> {
     fn drop(&mut self) {
         if self.buckets.drain().any(|(_, bucket)| bucket.total > 0) {
             tracing::error!("allocator is dropped while some descriptor sets are still allocated");
@@ -114,28 +255,30 @@ impl Drop for DescriptorAlloc {
     }
 }
 
-pub(crate) struct AllocatedDescriptorSet {
-    handle: vk::DescriptorSet,
+pub(crate) struct AllocatedDescriptorSet<S = vk::DescriptorSet> {
+    handle: S,
     size: DescriptorSetSize,
     pool_id: u64,
     update_after_bind: bool,
 }
 
-impl AllocatedDescriptorSet {
-    pub fn handle(&self) -> vk::DescriptorSet {
+impl<S: Copy> AllocatedDescriptorSet<S> {
+    pub fn handle(&self) -> S {
         self.handle
     }
 }
 
-struct DescriptorBucket {
-    pools: VecDeque<DescriptorPool>,
+struct DescriptorBucket<B: DescriptorPoolBackend> {
+    pools: VecDeque<DescriptorPool<B::Pool>>,
     offset: u64,
     total: u64,
     update_after_bind: bool,
     size: DescriptorSetSize,
+    /// Max sets of the next pool, doubled with each created pool.
+    next_max_sets: u32,
 }
 
-impl DescriptorBucket {
+impl<B: DescriptorPoolBackend> DescriptorBucket<B> {
     fn new(update_after_bind: bool, size: &DescriptorSetSize) -> Self {
         Self {
             pools: VecDeque::new(),
@@ -143,22 +286,23 @@ impl DescriptorBucket {
             total: 0,
             update_after_bind,
             size: *size,
+            next_max_sets: MIN_SETS,
         }
     }
 
     unsafe fn allocate(
         &mut self,
-        device: &Device,
-        layout: &DescriptorSetLayout,
+        backend: &B,
+        layout: B::Layout,
         mut count: u32,
-        allocated_sets: &mut Vec<AllocatedDescriptorSet>,
+        allocated_sets: &mut Vec<AllocatedDescriptorSet<B::Set>>,
     ) -> Result<(), DescriptorAllocError> {
-        fn extend_allocated_sets(
+        fn extend_allocated_sets<S: Copy>(
             update_after_bind: bool,
             size: &DescriptorSetSize,
             pool_id: u64,
-            handles: &[vk::DescriptorSet],
-            allocated_sets: &mut Vec<AllocatedDescriptorSet>,
+            handles: &[S],
+            allocated_sets: &mut Vec<AllocatedDescriptorSet<S>>,
         ) {
             allocated_sets.extend(handles.iter().map(|&handle| AllocatedDescriptorSet {
                 handle,
@@ -172,8 +316,6 @@ impl DescriptorBucket {
             return Ok(());
         }
 
-        let mut set_layouts = SmallVec::<[_; 16]>::new();
-
         // Allocate from existing pools
         for (i, pool) in self.pools.iter_mut().enumerate() {
             if pool.remaining == 0 {
@@ -187,31 +329,24 @@ impl DescriptorBucket {
                 "allocating descriptor sets from an existing pool",
             );
 
-            set_layouts.resize_with(allocate as usize, || layout.handle());
-
-            let new_sets = match device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfo::builder()
-                    .descriptor_pool(pool.handle)
-                    .set_layouts(&set_layouts),
-            ) {
+            let new_sets = match backend.allocate_sets(pool.handle, layout, allocate) {
                 Ok(new_sets) => new_sets,
-                Err(vk::ErrorCode::OUT_OF_DEVICE_MEMORY) => {
+                Err(PoolAllocError::OutOfDeviceMemory) => {
                     return Err(DescriptorAllocError::OutOfDeviceMemory(OutOfDeviceMemory))
                 }
-                Err(vk::ErrorCode::OUT_OF_HOST_MEMORY) => crate::out_of_host_memory(),
-                Err(vk::ErrorCode::FRAGMENTED_POOL) => {
-                    tracing::error!(
+                Err(PoolAllocError::FragmentedPool) => {
+                    tracing::debug!(
                         descriptor_pool = ?pool.handle,
                         "failed to allocate descriptor sets due to pool fragmentation",
                     );
+                    // NOTE: The pool will be reset when all its sets are freed
                     pool.remaining = 0;
                     continue;
                 }
-                Err(vk::ErrorCode::OUT_OF_POOL_MEMORY) => {
+                Err(PoolAllocError::OutOfPoolMemory) => {
                     pool.remaining = 0;
                     continue;
                 }
-                Err(e) => crate::unexpected_vulkan_error(e),
             };
 
             extend_allocated_sets(
@@ -231,22 +366,16 @@ impl DescriptorBucket {
             }
         }
 
+        let mut retries = 0;
         while count > 0 {
             let (pool_size, max_sets) = self.next_pool_size(count);
             tracing::trace!(?pool_size, max_sets, "creating a new descriptor pool");
 
-            let handle = create_descriptor_pool(
-                device,
-                &pool_size,
-                max_sets,
-                if self.update_after_bind {
-                    vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET
-                        | vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
-                } else {
-                    vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET
-                },
-            )?
-            .with_defer(|pool| device.destroy_descriptor_pool(pool, None));
+            let handle = backend
+                .create_pool(&pool_size, max_sets, self.update_after_bind)?
+                .with_defer(|pool| backend.destroy_pool(pool));
+
+            self.next_max_sets = max_sets.saturating_mul(2).clamp(MIN_SETS, MAX_SETS);
 
             let allocate = max_sets.min(count);
             tracing::trace!(
@@ -255,19 +384,24 @@ impl DescriptorBucket {
                 "allocating descriptor sets from a new pool",
             );
 
-            set_layouts.resize_with(allocate as usize, || layout.handle());
-
-            let new_sets = match device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfo::builder()
-                    .descriptor_pool(*handle)
-                    .set_layouts(&set_layouts),
-            ) {
+            let new_sets = match backend.allocate_sets(*handle, layout, allocate) {
                 Ok(new_sets) => new_sets,
-                Err(vk::ErrorCode::OUT_OF_DEVICE_MEMORY) => {
+                Err(PoolAllocError::OutOfDeviceMemory) => {
                     return Err(DescriptorAllocError::OutOfDeviceMemory(OutOfDeviceMemory))
                 }
-                Err(vk::ErrorCode::OUT_OF_HOST_MEMORY) => crate::out_of_host_memory(),
-                Err(e) => crate::unexpected_vulkan_error(e),
+                // NOTE: Even a new pool can fail the allocation, e.g. when the
+                // implementation reserves more memory for some descriptor types.
+                // Retry with the next (larger) pool in that case.
+                Err(e) if retries < MAX_NEW_POOL_RETRIES => {
+                    tracing::warn!(
+                        descriptor_pool = ?*handle,
+                        error = ?e,
+                        "failed to allocate descriptor sets from a new pool, retrying",
+                    );
+                    retries += 1;
+                    continue;
+                }
+                Err(_) => return Err(DescriptorAllocError::OutOfPoolMemory),
             };
 
             extend_allocated_sets(
@@ -283,6 +417,7 @@ impl DescriptorBucket {
                 handle: handle.disarm(),
                 allocated: allocate,
                 remaining: max_sets - allocate,
+                max_sets,
             });
             self.total += allocate as u64;
         }
@@ -290,27 +425,24 @@ impl DescriptorBucket {
         Ok(())
     }
 
-    unsafe fn free(
-        &mut self,
-        device: &Device,
-        descriptor_sets: &[vk::DescriptorSet],
-        pool_id: u64,
-    ) {
+    unsafe fn free(&mut self, backend: &B, descriptor_sets: &[B::Set], pool_id: u64) {
         let pool = pool_id
             .checked_sub(self.offset)
             .and_then(|i| self.pools.get_mut(i as usize))
             .expect("invalid descriptor pool id");
 
         tracing::trace!(descriptor_pool = ?pool.handle, ?descriptor_sets, "deallocating descriptor sets");
-        device
-            .free_descriptor_sets(pool.handle, descriptor_sets)
-            .unwrap();
+        backend.free_sets(pool.handle, descriptor_sets);
 
         let deallocated = descriptor_sets.len() as u32;
         pool.allocated -= deallocated;
         pool.remaining += deallocated;
         self.total -= deallocated as u64;
 
+        if pool.allocated > 0 {
+            return;
+        }
+
         while self.pools.len() > 1 {
             let pool = match self.pools.front_mut() {
                 Some(pool) if pool.allocated == 0 => pool,
@@ -318,14 +450,24 @@ impl DescriptorBucket {
             };
 
             tracing::trace!(descriptor_pool = ?pool.handle, "destroying an empty descriptor pool");
-            device.destroy_descriptor_pool(pool.handle, None);
+            backend.destroy_pool(pool.handle);
 
             self.offset += 1;
             self.pools.pop_front();
         }
+
+        // NOTE: Reset the empty pool if it is kept to get rid of fragmentation
+        if let Some(pool) = pool_id
+            .checked_sub(self.offset)
+            .and_then(|i| self.pools.get_mut(i as usize))
+        {
+            tracing::trace!(descriptor_pool = ?pool.handle, "recycling an empty descriptor pool");
+            backend.reset_pool(pool.handle);
+            pool.remaining = pool.max_sets;
+        }
     }
 
-    unsafe fn cleanup(&mut self, device: &Device) {
+    unsafe fn cleanup(&mut self, backend: &B) {
         loop {
             let pool = match self.pools.front_mut() {
                 Some(pool) if pool.allocated == 0 => pool,
@@ -333,7 +475,7 @@ impl DescriptorBucket {
             };
 
             tracing::trace!(descriptor_pool = ?pool.handle, "destroying an empty descriptor pool");
-            device.destroy_descriptor_pool(pool.handle, None);
+            backend.destroy_pool(pool.handle);
 
             self.offset += 1;
             self.pools.pop_front();
@@ -341,12 +483,11 @@ impl DescriptorBucket {
     }
 
     fn next_pool_size(&self, required: u32) -> (DescriptorSetSize, u32) {
-        let mut max_sets = MIN_SETS
+        let mut max_sets = self
+            .next_max_sets
             .max(required)
-            .max(self.total.min(MAX_SETS as u64) as u32)
             .checked_next_power_of_two()
             .unwrap_or(i32::MAX as u32);
-
         // Prevent any part from decreasing to less than its current value
         max_sets = (u32::MAX / self.size.samplers.max(1)).min(max_sets);
         max_sets = (u32::MAX / self.size.combined_image_samplers.max(1)).min(max_sets);
@@ -382,7 +523,7 @@ impl DescriptorBucket {
     }
 }
 
-impl Drop for DescriptorBucket {
+impl<B: DescriptorPoolBackend> Drop for DescriptorBucket<B> {
     fn drop(&mut self) {
         if self.total > 0 {
             tracing::error!("descriptor sets leaked");
@@ -391,10 +532,11 @@ impl Drop for DescriptorBucket {
 }
 
 #[derive(Debug)]
-struct DescriptorPool {
-    handle: vk::DescriptorPool,
+struct DescriptorPool<P> {
+    handle: P,
     allocated: u32,
     remaining: u32,
+    max_sets: u32,
 }
 
 unsafe fn create_descriptor_pool(
@@ -496,7 +638,241 @@ pub enum DescriptorAllocError {
     OutOfDeviceMemory(#[from] OutOfDeviceMemory),
     #[error("a pool allocation has failed due to fragmentation of the pool's memory")]
     Fragmentation,
+    #[error("descriptor sets cannot be allocated even from a new pool")]
+    OutOfPoolMemory,
 }
 
 const MIN_SETS: u32 = 64;
-const MAX_SETS: u32 = 512;
+const MAX_SETS: u32 = 4096;
+const MAX_NEW_POOL_RETRIES: u32 = 2;
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use shared::FastHashSet;
+
+    use super::*;
+
+    /// Pool backend which simulates fragmentation of pools with freed sets.
+    #[derive(Default)]
+    struct TestBackend {
+        pools: RefCell<FastHashMap<u32, TestPool>>,
+        next_pool: Cell<u32>,
+        next_set: Cell<u64>,
+        allocations: Cell<u32>,
+        /// Number of allocations from new pools which will fail.
+        failing_new_pools: Cell<u32>,
+    }
+
+    struct TestPool {
+        max_sets: u32,
+        sets: FastHashSet<u64>,
+        fragmented: bool,
+    }
+
+    impl DescriptorPoolBackend for TestBackend {
+        type Pool = u32;
+        type Set = u64;
+        type Layout = ();
+
+        unsafe fn create_pool(
+            &self,
+            size: &DescriptorSetSize,
+            max_sets: u32,
+            _: bool,
+        ) -> Result<Self::Pool, DescriptorAllocError> {
+            assert_ne!(*size, DescriptorSetSize::ZERO);
+
+            let id = self.next_pool.get();
+            self.next_pool.set(id + 1);
+            self.pools.borrow_mut().insert(
+                id,
+                TestPool {
+                    max_sets,
+                    sets: Default::default(),
+                    fragmented: false,
+                },
+            );
+            Ok(id)
+        }
+
+        unsafe fn allocate_sets(
+            &self,
+            pool: Self::Pool,
+            _: Self::Layout,
+            count: u32,
+        ) -> Result<Vec<Self::Set>, PoolAllocError> {
+            let mut pools = self.pools.borrow_mut();
+            let pool = pools.get_mut(&pool).unwrap();
+
+            if pool.sets.is_empty() && self.failing_new_pools.get() > 0 {
+                self.failing_new_pools.set(self.failing_new_pools.get() - 1);
+                return Err(PoolAllocError::OutOfPoolMemory);
+            }
+            if pool.sets.len() + count as usize > pool.max_sets as usize {
+                return Err(PoolAllocError::OutOfPoolMemory);
+            }
+
+            let allocations = self.allocations.get() + 1;
+            self.allocations.set(allocations);
+            if pool.fragmented && allocations % 5 == 0 {
+                return Err(PoolAllocError::FragmentedPool);
+            }
+
+            Ok((0..count)
+                .map(|_| {
+                    let set = self.next_set.get();
+                    self.next_set.set(set + 1);
+                    pool.sets.insert(set);
+                    set
+                })
+                .collect())
+        }
+
+        unsafe fn free_sets(&self, pool: Self::Pool, sets: &[Self::Set]) {
+            let mut pools = self.pools.borrow_mut();
+            let pool = pools.get_mut(&pool).unwrap();
+            for set in sets {
+                assert!(pool.sets.remove(set), "set is freed from the wrong pool");
+            }
+            pool.fragmented = true;
+        }
+
+        unsafe fn reset_pool(&self, pool: Self::Pool) {
+            let mut pools = self.pools.borrow_mut();
+            let pool = pools.get_mut(&pool).unwrap();
+            assert!(pool.sets.is_empty(), "non-empty pool is reset");
+            pool.fragmented = false;
+        }
+
+        unsafe fn destroy_pool(&self, pool: Self::Pool) {
+            let pool = self.pools.borrow_mut().remove(&pool).unwrap();
+            assert!(pool.sets.is_empty(), "non-empty pool is destroyed");
+        }
+    }
+
+    fn test_layouts() -> [(DescriptorSetSize, bool); 4] {
+        [
+            (
+                DescriptorSetSize {
+                    uniform_buffers: 1,
+                    ..Default::default()
+                },
+                false,
+            ),
+            (
+                DescriptorSetSize {
+                    samplers: 1,
+                    sampled_images: 4,
+                    ..Default::default()
+                },
+                false,
+            ),
+            (
+                DescriptorSetSize {
+                    storage_buffers: 2,
+                    ..Default::default()
+                },
+                true,
+            ),
+            (DescriptorSetSize::ZERO, false),
+        ]
+    }
+
+    #[test]
+    fn many_sets_are_allocated_and_freed() {
+        let backend = TestBackend::default();
+        let mut alloc = DescriptorAlloc::<TestBackend>::new();
+        let layouts = test_layouts();
+
+        let mut rng = 0x1234_5678u32;
+        let mut next_random = move || {
+            rng ^= rng << 13;
+            rng ^= rng >> 17;
+            rng ^= rng << 5;
+            rng
+        };
+
+        let mut live = Vec::new();
+        for i in 0..3000 {
+            let (size, update_after_bind) = &layouts[i % layouts.len()];
+            let count = 1 + next_random() % 8;
+            let sets = unsafe { alloc.allocate_raw(&backend, (), size, *update_after_bind, count) }
+                .unwrap();
+            assert_eq!(sets.len(), count as usize);
+            live.extend(sets);
+
+            if next_random() % 2 == 0 {
+                let mut freed = Vec::new();
+                for _ in 0..next_random() % 16 {
+                    if live.is_empty() {
+                        break;
+                    }
+                    let index = next_random() as usize % live.len();
+                    freed.push(live.swap_remove(index));
+                }
+                unsafe { alloc.free(&backend, &freed) };
+            }
+
+            let stats = alloc.stats();
+            assert_eq!(stats.allocated_sets, live.len() as u64);
+            assert_eq!(stats.pool_count, backend.pools.borrow().len());
+        }
+
+        unsafe { alloc.free(&backend, &live) };
+        assert_eq!(alloc.stats().allocated_sets, 0);
+
+        unsafe { alloc.cleanup(&backend) };
+        assert_eq!(alloc.stats(), DescriptorAllocStats::default());
+        assert!(backend.pools.borrow().is_empty());
+    }
+
+    #[test]
+    fn pools_grow_geometrically() {
+        let backend = TestBackend::default();
+        let mut alloc = DescriptorAlloc::<TestBackend>::new();
+        let (size, _) = test_layouts()[0];
+
+        let mut sets = Vec::new();
+        for _ in 0..MIN_SETS * 7 {
+            sets.extend(unsafe { alloc.allocate_raw(&backend, (), &size, false, 1) }.unwrap());
+        }
+
+        let mut max_sets = backend
+            .pools
+            .borrow()
+            .iter()
+            .map(|(id, pool)| (*id, pool.max_sets))
+            .collect::<Vec<_>>();
+        max_sets.sort_unstable();
+        assert_eq!(
+            max_sets,
+            [(0, MIN_SETS), (1, MIN_SETS * 2), (2, MIN_SETS * 4)]
+        );
+
+        unsafe { alloc.free(&backend, &sets) };
+        unsafe { alloc.cleanup(&backend) };
+        assert!(backend.pools.borrow().is_empty());
+    }
+
+    #[test]
+    fn new_pool_failure_is_retried() {
+        let backend = TestBackend::default();
+        let mut alloc = DescriptorAlloc::<TestBackend>::new();
+        let (size, _) = test_layouts()[1];
+
+        backend.failing_new_pools.set(MAX_NEW_POOL_RETRIES);
+        let sets = unsafe { alloc.allocate_raw(&backend, (), &size, false, 4) }.unwrap();
+        assert_eq!(alloc.stats().pool_count, 1);
+        unsafe { alloc.free(&backend, &sets) };
+
+        backend.failing_new_pools.set(u32::MAX);
+        let (size, _) = test_layouts()[2];
+        let res = unsafe { alloc.allocate_raw(&backend, (), &size, false, 4) };
+        assert!(matches!(res, Err(DescriptorAllocError::OutOfPoolMemory)));
+
+        unsafe { alloc.cleanup(&backend) };
+        assert!(backend.pools.borrow().is_empty());
+    }
+}
@@ -1,38 +1,74 @@
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
 
 use shared::FastHashMap;
+use smallvec::SmallVec;
 
 use crate::encoder::CommandBuffer;
 use crate::queue::QueueId;
 
 pub(crate) struct Epochs {
-    queues: FastHashMap<QueueId, Mutex<QueueEpochs>>,
+    queues: FastHashMap<QueueId, QueueState>,
 }
 
+/// Next epoch of each queue at some point in time.
+pub(crate) type EpochSnapshot = SmallVec<[(QueueId, u64); 4]>;
+
 impl Epochs {
     pub fn new(queues: impl IntoIterator<Item = QueueId>) -> Self {
         Self {
             queues: queues
                 .into_iter()
-                .map(|id| (id, Mutex::new(QueueEpochs::default())))
+                .map(|id| (id, QueueState::default()))
                 .collect(),
         }
     }
 
     pub fn next_epoch(&self, queue: QueueId) -> u64 {
-        self.queues[&queue].lock().unwrap().next_epoch()
+        self.queues[&queue].next_epoch()
     }
 
     pub fn next_epoch_all_queues(&self) -> Vec<(QueueId, u64)> {
         self.queues
             .iter()
-            .map(|(&id, queue)| (id, queue.lock().unwrap().next_epoch()))
+            .map(|(&id, queue)| (id, queue.next_epoch()))
             .collect()
     }
 
+    /// Closes epochs of the `queue` which are older than `epoch`.
+    ///
+    /// NOTE: Must be called once the submission of `epoch` is completed.
     pub fn close_epoch(&self, queue: QueueId, epoch: u64) {
-        self.queues[&queue].lock().unwrap().close_epoch(epoch);
+        let queue = &self.queues[&queue];
+        queue.completed.fetch_max(epoch + 1, Ordering::AcqRel);
+        queue.epochs.lock().unwrap().close_epoch(epoch);
+    }
+
+    /// Returns the next epoch of each queue.
+    ///
+    /// NOTE: Doesn't lock the queues, so it can be used while epochs are closed,
+    /// e.g. from the drop of a resource referenced by a command buffer.
+    pub fn snapshot(&self) -> EpochSnapshot {
+        self.queues
+            .iter()
+            .map(|(&id, queue)| (id, queue.next.load(Ordering::Acquire)))
+            .collect()
+    }
+
+    /// Returns whether the GPU has finished all work which could have been
+    /// recorded before the `snapshot` was taken.
+    ///
+    /// For each queue, either the first submission after the snapshot or
+    /// all submissions made so far must be completed.
+    ///
+    /// NOTE: Only fenced submissions are tracked.
+    pub fn is_passed(&self, snapshot: &EpochSnapshot) -> bool {
+        snapshot.iter().all(|(id, epoch)| {
+            let queue = &self.queues[id];
+            let next = queue.next.load(Ordering::Acquire);
+            queue.completed.load(Ordering::Acquire) >= next.min(epoch + 1)
+        })
     }
 
     pub fn drain_free_command_buffers(
@@ -41,18 +77,36 @@ impl Epochs {
         primary: &mut Vec<CommandBuffer>,
         secondaty: &mut Vec<CommandBuffer>,
     ) {
-        let mut queue = self.queues[&queue].lock().unwrap();
+        let mut queue = self.queues[&queue].epochs.lock().unwrap();
         primary.append(&mut queue.free_primary_buffers);
         secondaty.append(&mut queue.free_secondary_buffers);
     }
 
     pub fn submit(&self, queue: QueueId, command_buffers: impl Iterator<Item = CommandBuffer>) {
-        let mut queue = self.queues[&queue].lock().unwrap();
+        let mut queue = self.queues[&queue].epochs.lock().unwrap();
         let epoch = queue.epochs.front_mut().unwrap();
         epoch.command_buffers.extend(command_buffers);
     }
 }
 
+#[derive(Default)]
+struct QueueState {
+    epochs: Mutex<QueueEpochs>,
+    /// A copy of [`QueueEpochs::next`] which can be read without locking.
+    next: AtomicU64,
+    /// All submissions of epochs lower than this one are completed.
+    completed: AtomicU64,
+}
+
+impl QueueState {
+    fn next_epoch(&self) -> u64 {
+        let mut epochs = self.epochs.lock().unwrap();
+        let epoch = epochs.next_epoch();
+        self.next.store(epochs.next, Ordering::Release);
+        epoch
+    }
+}
+
 #[derive(Default)]
 struct QueueEpochs {
     next: u64,
@@ -80,8 +134,14 @@ impl QueueEpochs {
             for mut epoch in self.epochs.drain(n..) {
                 for mut command_buffer in epoch.command_buffers.drain(..) {
                     command_buffer.clear_references();
-                    self.free_secondary_buffers
-                        .extend(command_buffer.drain_secondary_buffers());
+                    self.free_secondary_buffers.extend(
+                        command_buffer
+                            .drain_secondary_buffers()
+                            .map(|mut secondary| {
+                                secondary.clear_references();
+                                secondary
+                            }),
+                    );
                     self.free_primary_buffers.push(command_buffer);
                 }
                 self.epochs_cache.push_back(epoch);
@@ -119,3 +179,50 @@ impl Drop for QueueEpochs {
 struct Epoch {
     command_buffers: Vec<CommandBuffer>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_pass_after_next_submission() {
+        let graphics = QueueId {
+            family: 0,
+            index: 0,
+        };
+        let transfer = QueueId {
+            family: 1,
+            index: 0,
+        };
+        let epochs = Epochs::new([graphics, transfer]);
+
+        // Idle queues have nothing to wait for
+        assert!(epochs.is_passed(&epochs.snapshot()));
+
+        let first = epochs.next_epoch(graphics);
+        let snapshot = epochs.snapshot();
+        assert!(!epochs.is_passed(&snapshot));
+
+        // The work recorded before the snapshot could be submitted with the next epoch
+        let second = epochs.next_epoch(graphics);
+        epochs.close_epoch(graphics, first);
+        assert!(!epochs.is_passed(&snapshot));
+        epochs.close_epoch(graphics, second);
+        assert!(epochs.is_passed(&snapshot));
+
+        // Later submissions are not waited for
+        let snapshot = epochs.snapshot();
+        let third = epochs.next_epoch(graphics);
+        let fourth = epochs.next_epoch(graphics);
+        epochs.close_epoch(graphics, third);
+        assert!(epochs.is_passed(&snapshot));
+
+        // Other queues must be completed too
+        epochs.close_epoch(graphics, fourth);
+        let transfer_epoch = epochs.next_epoch(transfer);
+        let snapshot = epochs.snapshot();
+        assert!(!epochs.is_passed(&snapshot));
+        epochs.close_epoch(transfer, transfer_epoch);
+        assert!(epochs.is_passed(&snapshot));
+    }
+}
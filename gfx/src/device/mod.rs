@@ -1,37 +1,44 @@
+use std::ffi::CString;
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 
 use bumpalo::Bump;
 use gpu_alloc::GpuAllocator;
 use gpu_alloc_vulkanalia::AsMemoryDevice;
 use shared::util::WithDefer;
-use shared::FastDashMap;
+use shared::{FastDashMap, FastHashSet};
 use smallvec::SmallVec;
 use vulkanalia::prelude::v1_0::*;
-use vulkanalia::vk::{DeviceV1_1, DeviceV1_2};
+use vulkanalia::vk::{DeviceV1_1, DeviceV1_2, ExtDebugUtilsExtension, InstanceV1_1};
 
 pub(crate) use self::descriptor_alloc::AllocatedDescriptorSet;
-pub use self::descriptor_alloc::DescriptorAllocError;
+pub use self::descriptor_alloc::{DescriptorAllocError, DescriptorAllocStats};
+pub(crate) use self::epochs::EpochSnapshot;
 
+use self::deferred_destroy::{DeferredDestroy, DeferredDestroyQueue};
 use self::descriptor_alloc::DescriptorAlloc;
 use self::epochs::Epochs;
 use crate::graphics::Graphics;
-use crate::physical::{DeviceFeatures, DeviceProperties};
+use crate::physical::{DeviceFeature, DeviceFeatures, DeviceProperties};
 use crate::queue::QueueId;
 use crate::resources::{
     Blending, Buffer, BufferInfo, BufferUsage, BufferView, BufferViewInfo, ColorBlend,
     ComponentMask, ComputePipeline, ComputePipelineInfo, DescriptorBindingFlags, DescriptorSet,
     DescriptorSetInfo, DescriptorSetLayout, DescriptorSetLayoutFlags, DescriptorSetLayoutInfo,
-    DescriptorSetSize, DescriptorSlice, DescriptorType, Fence, FenceState, Framebuffer,
-    FramebufferInfo, GraphicsPipeline, GraphicsPipelineInfo, Image, ImageInfo, ImageView,
-    ImageViewInfo, ImageViewType, MemoryBlockMut, MemoryUsage, PipelineLayout, PipelineLayoutInfo,
-    RenderPass, RenderPassInfo, Sampler, SamplerInfo, Semaphore, ShaderModule, ShaderModuleInfo,
-    StencilTest, UpdateDescriptorSet,
+    DescriptorSetSize, DescriptorSlice, DescriptorType, Fence, FenceState, FenceWaitStatus, Format,
+    Framebuffer, FramebufferInfo, GraphicsPipeline, GraphicsPipelineInfo, Image, ImageInfo,
+    ImageUsageFlags, ImageView, ImageViewInfo, ImageViewType, MappedBuffer, MemoryBlockMut,
+    MemoryUsage, MemoryUsagePreference, PipelineCache, PipelineLayout, PipelineLayoutInfo,
+    QueryPool, QueryPoolInfo, RenderPass, RenderPassInfo, Sampler, SamplerInfo, Samples, Semaphore,
+    ShaderModule, ShaderModuleInfo, StencilTest, UpdateDescriptorSet,
 };
 use crate::surface::{CreateSurfaceError, Surface, Window};
 use crate::types::{DeviceAddress, DeviceLost, OutOfDeviceMemory, State};
-use crate::util::{FromGfx, ToVk};
+use crate::util::{check_descriptor_usage, FromGfx, ToVk};
 
+mod deferred_destroy;
 mod descriptor_alloc;
 mod epochs;
 
@@ -84,6 +91,7 @@ impl Device {
         physical: vk::PhysicalDevice,
         properties: Box<DeviceProperties>,
         features: Box<DeviceFeatures>,
+        enabled_features: FastHashSet<DeviceFeature>,
         queues: impl IntoIterator<Item = QueueId>,
     ) -> Self {
         let allocator = Mutex::new(GpuAllocator::new(
@@ -91,6 +99,13 @@ impl Device {
             map_memory_device_properties(&properties, &features),
         ));
         let descriptors = Mutex::new(DescriptorAlloc::new());
+        let heap_usage = (0..properties.memory.memory_heap_count)
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        let direct_upload = is_unified_memory(&properties.memory);
+        let bar_heap = (!direct_upload)
+            .then(|| find_bar_heap(&properties.memory))
+            .flatten();
 
         Self {
             inner: Arc::new(Inner {
@@ -98,10 +113,16 @@ impl Device {
                 physical,
                 properties,
                 features,
+                enabled_features,
                 allocator,
+                heap_usage,
+                direct_upload,
+                bar_heap,
                 descriptors,
                 samplers_cache: Default::default(),
+                pipeline_cache: Default::default(),
                 epochs: Epochs::new(queues),
+                deferred_destroys: Default::default(),
             }),
         }
     }
@@ -118,6 +139,41 @@ impl Device {
         &self.inner.logical
     }
 
+    /// Assigns a debug name to the Vulkan object, e.g. `buffer.handle()`.
+    ///
+    /// Does nothing if `VK_EXT_debug_utils` is not enabled.
+    pub fn set_object_name<T>(&self, handle: T, name: &str)
+    where
+        T: vk::Handle<Repr = u64>,
+    {
+        let graphics = self.graphics();
+        if !graphics.debug_utils_enabled() {
+            return;
+        }
+
+        let Ok(name) = CString::new(name) else {
+            tracing::warn!(name, "object name must not contain null");
+            return;
+        };
+
+        let info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(T::TYPE)
+            .object_handle(handle.as_raw())
+            .object_name(name.as_bytes_with_nul());
+
+        let res = unsafe {
+            graphics
+                .instance()
+                .set_debug_utils_object_name_ext(self.inner.logical.handle(), &info)
+        };
+        if let Err(e) = res {
+            match e {
+                vk::ErrorCode::OUT_OF_HOST_MEMORY => crate::out_of_host_memory(),
+                _ => crate::unexpected_vulkan_error(e),
+            }
+        }
+    }
+
     pub fn physical(&self) -> vk::PhysicalDevice {
         self.inner.physical
     }
@@ -130,16 +186,162 @@ impl Device {
         &self.inner.properties
     }
 
+    /// Returns the highest sample count not greater than `samples` which is
+    /// supported for both color and depth framebuffer attachments.
+    pub fn find_supported_framebuffer_samples(&self, samples: Samples) -> Samples {
+        let limits = self.limits();
+        let supported =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+        let res = Samples::ALL_DESC
+            .into_iter()
+            .find(|&item| item <= samples && supported.contains(item.to_vk()))
+            .unwrap_or(Samples::_1);
+
+        if res != samples {
+            tracing::warn!(
+                requested = ?samples,
+                fallback = ?res,
+                "unsupported framebuffer sample count"
+            );
+        }
+        res
+    }
+
     pub fn features(&self) -> &DeviceFeatures {
         &self.inner.features
     }
 
+    /// Returns `true` if the feature was requested when creating the device.
+    pub fn is_feature_enabled(&self, feature: DeviceFeature) -> bool {
+        self.inner.enabled_features.contains(&feature)
+    }
+
+    /// Returns `true` if all device local memory is also host visible,
+    /// e.g. on integrated GPUs.
+    ///
+    /// Buffers created with [`Device::create_mappable_buffer`] and
+    /// [`MemoryUsage::FAST_DEVICE_ACCESS`] can then be written directly
+    /// instead of copying the data from a staging buffer.
+    pub fn supports_direct_upload(&self) -> bool {
+        self.inner.direct_upload
+    }
+
+    /// Returns `true` if there is memory which is both device local and host visible,
+    /// either a resizable BAR heap or unified memory.
+    ///
+    /// Mappable buffers with [`MemoryUsage::FAST_DEVICE_ACCESS`] are then
+    /// allocated in device local memory while it has room.
+    pub fn supports_fast_mappable_buffers(&self) -> bool {
+        self.inner.direct_upload || self.inner.bar_heap.is_some()
+    }
+
+    /// Returns `true` if optimally tiled images of the `format`
+    /// can be created with the specified `usage`.
+    pub fn format_supported(&self, format: Format, usage: ImageUsageFlags) -> bool {
+        let properties = unsafe {
+            self.graphics()
+                .instance()
+                .get_physical_device_format_properties(self.inner.physical, format.to_vk())
+        };
+        let required = format_features(format, usage);
+        properties.optimal_tiling_features.contains(required)
+    }
+
+    /// Returns the usage and budget of each memory heap.
+    ///
+    /// Values are reported by the driver if [`DeviceFeature::MemoryBudget`] is
+    /// enabled. Otherwise the usage only includes memory allocated by this device
+    /// and the budget is equal to the heap size.
+    pub fn memory_usage(&self) -> Vec<HeapUsage> {
+        let memory = &self.inner.properties.memory;
+        let heaps = &memory.memory_heaps[..memory.memory_heap_count as usize];
+
+        let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let has_budget = self.is_feature_enabled(DeviceFeature::MemoryBudget);
+        if has_budget {
+            let mut properties =
+                vk::PhysicalDeviceMemoryProperties2::builder().push_next(&mut budget);
+            unsafe {
+                self.graphics()
+                    .instance()
+                    .get_physical_device_memory_properties2(self.inner.physical, &mut properties)
+            };
+        }
+
+        heaps
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| {
+                let (usage, budget) = if has_budget {
+                    (budget.heap_usage[index], budget.heap_budget[index])
+                } else {
+                    let usage = self.inner.heap_usage[index].load(Ordering::Relaxed);
+                    (usage, heap.size)
+                };
+
+                HeapUsage {
+                    size: heap.size,
+                    device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                    usage,
+                    budget,
+                }
+            })
+            .collect()
+    }
+
+    fn on_out_of_device_memory(&self, size: u64) -> OutOfDeviceMemory {
+        let heaps = self
+            .memory_usage()
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| format!("#{index} {heap}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        tracing::warn!(size, "out of device memory, heaps: {heaps}");
+        OutOfDeviceMemory
+    }
+
+    /// Returns the number of descriptor pools and allocated descriptor sets.
+    pub fn descriptor_alloc_stats(&self) -> DescriptorAllocStats {
+        self.inner.descriptors.lock().unwrap().stats()
+    }
+
     pub fn downgrade(&self) -> WeakDevice {
         WeakDevice(Arc::downgrade(&self.inner))
     }
 
+    /// Waits for all queues to become idle and destroys all dropped resources.
     pub fn wait_idle(&self) -> Result<(), DeviceLost> {
-        self.inner.wait_idle()
+        self.inner.wait_idle()?;
+        self.flush_destroys();
+        Ok(())
+    }
+
+    /// Destroys dropped resources which are no longer used by the GPU.
+    ///
+    /// Buffers, images and their views are not destroyed on drop, since
+    /// they could still be used by the submitted work which doesn't hold
+    /// a reference to them. Instead, they wait until the next submission
+    /// of each queue is completed, or until the queue becomes idle.
+    ///
+    /// NOTE: Should be called after waiting for fences, while no command
+    /// buffers which could use the dropped resources are recorded.
+    ///
+    /// Queues never call it on submit, so dropped resources accumulate
+    /// until this method or [`wait_idle`] is called, or the device is
+    /// dropped. Applications which submit work on their own should call
+    /// it once per frame after waiting for the frame fence.
+    ///
+    /// [`wait_idle`]: Self::wait_idle
+    pub fn flush_destroys(&self) {
+        let mut ready = Vec::new();
+        self.inner
+            .deferred_destroys
+            .drain_passed(&self.inner.epochs, &mut ready);
+        for resource in ready {
+            unsafe { self.inner.destroy_now(resource) };
+        }
     }
 
     pub fn map_memory(
@@ -160,6 +362,41 @@ impl Device {
         }
     }
 
+    /// Maps the whole buffer memory until the returned [`MappedBuffer`] is dropped.
+    pub fn map_persistent(&self, buffer: Buffer) -> Result<MappedBuffer, MapError> {
+        let size = buffer.info().size;
+        let (ptr, coherent) = {
+            let mut memory_block = buffer.as_mappable();
+            let coherent = memory_block
+                .props()
+                .contains(gpu_alloc::MemoryPropertyFlags::HOST_COHERENT);
+            let ptr = self.map_memory(&mut memory_block, 0, size)?.as_mut_ptr();
+            (ptr, coherent)
+        };
+        Ok(MappedBuffer::new(buffer, ptr, coherent))
+    }
+
+    /// Makes host writes to the byte ranges of the mapped buffers visible to the device.
+    ///
+    /// Ranges of buffers with coherent memory are skipped.
+    pub fn flush_mapped_ranges<'a, I>(&self, ranges: I) -> Result<(), OutOfDeviceMemory>
+    where
+        I: IntoIterator<Item = (&'a MappedBuffer, std::ops::Range<usize>)>,
+    {
+        let atom_mask = self.limits().non_coherent_atom_size - 1;
+        let ranges = ranges
+            .into_iter()
+            .filter(|(buffer, range)| !buffer.is_coherent() && !range.is_empty())
+            .map(|(buffer, range)| buffer.memory_range(atom_mask, range))
+            .collect::<SmallVec<[_; 4]>>();
+        if ranges.is_empty() {
+            return Ok(());
+        }
+
+        unsafe { self.logical().flush_mapped_memory_ranges(&ranges) }
+            .map_err(OutOfDeviceMemory::on_creation)
+    }
+
     pub fn upload_to_memory<T>(
         &self,
         memory_block: &mut MemoryBlockMut,
@@ -183,6 +420,30 @@ impl Device {
         Ok(())
     }
 
+    pub fn download_from_memory<T>(
+        &self,
+        memory_block: &mut MemoryBlockMut,
+        offset: usize,
+        data: &mut [T],
+    ) -> Result<(), MapError>
+    where
+        T: bytemuck::Pod,
+    {
+        let size = std::mem::size_of_val(data);
+        let slice = self.map_memory(memory_block, offset, size)?;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                slice.as_ptr() as *const u8,
+                data.as_mut_ptr() as *mut u8,
+                size,
+            )
+        }
+
+        self.unmap_memory(memory_block);
+        Ok(())
+    }
+
     pub fn create_semaphore(&self) -> Result<Semaphore, OutOfDeviceMemory> {
         let logical = &self.inner.logical;
 
@@ -260,7 +521,15 @@ impl Device {
         Ok(())
     }
 
-    pub fn wait_fences(&self, fences: &mut [&mut Fence], wait_all: bool) -> Result<(), DeviceLost> {
+    /// Waits for the fences to become signalled.
+    ///
+    /// Waits indefinitely if `timeout` is `None`.
+    pub fn wait_fences(
+        &self,
+        fences: &mut [&mut Fence],
+        wait_all: bool,
+        timeout: Option<Duration>,
+    ) -> Result<FenceWaitStatus, DeviceLost> {
         let handles = fences
             .iter()
             .filter_map(|fence| match fence.state() {
@@ -277,13 +546,16 @@ impl Device {
             .collect::<SmallVec<[_; 16]>>();
 
         if handles.is_empty() {
-            return Ok(());
+            return Ok(FenceWaitStatus::Signalled);
         }
 
-        unsafe {
+        let timeout = timeout.map_or(u64::MAX, |timeout| {
+            timeout.as_nanos().try_into().unwrap_or(u64::MAX)
+        });
+        let code = unsafe {
             self.inner
                 .logical
-                .wait_for_fences(&handles, wait_all, u64::MAX)
+                .wait_for_fences(&handles, wait_all, timeout)
         }
         .map_err(|e| match e {
             vk::ErrorCode::DEVICE_LOST => DeviceLost,
@@ -291,6 +563,10 @@ impl Device {
             _ => crate::unexpected_vulkan_error(e),
         })?;
 
+        if code == vk::SuccessCode::TIMEOUT {
+            return Ok(FenceWaitStatus::Timeout);
+        }
+
         let all_signalled = wait_all || handles.len() == 1;
 
         let mut epochs_to_close = SmallVec::<[_; 16]>::new();
@@ -320,7 +596,7 @@ impl Device {
             }
         }
 
-        Ok(())
+        Ok(FenceWaitStatus::Signalled)
     }
 
     pub fn create_surface(&self, window: Arc<dyn Window>) -> Result<Surface, CreateSurfaceError> {
@@ -342,6 +618,47 @@ impl Device {
         self.create_buffer_impl(info, Some(memory_usage))
     }
 
+    /// Creates a mappable buffer which falls back to other memory according
+    /// to the `preference` instead of exhausting the resizable BAR heap.
+    pub fn create_mappable_buffer_with_preference(
+        &self,
+        info: BufferInfo,
+        memory_usage: MemoryUsage,
+        preference: MemoryUsagePreference,
+    ) -> Result<Buffer, OutOfDeviceMemory> {
+        if preference == MemoryUsagePreference::Required
+            || !memory_usage.contains(MemoryUsage::FAST_DEVICE_ACCESS)
+        {
+            return self.create_buffer_impl(info, Some(memory_usage));
+        }
+        let Some(bar_heap) = self.inner.bar_heap else {
+            return self.create_buffer_impl(info, Some(memory_usage));
+        };
+
+        let heap = self.memory_usage()[bar_heap as usize];
+        let fallback = memory_usage - MemoryUsage::FAST_DEVICE_ACCESS;
+        if !bar_heap_has_room(&heap, info.size as u64) {
+            tracing::debug!(
+                size = info.size,
+                label = info.label,
+                "resizable BAR heap is close to its budget, using host memory: {heap}"
+            );
+            return self.create_buffer_impl(info, Some(fallback));
+        }
+
+        match self.create_buffer_impl(info, Some(memory_usage)) {
+            Err(OutOfDeviceMemory) => {
+                tracing::debug!(
+                    size = info.size,
+                    label = info.label,
+                    "retrying in host memory"
+                );
+                self.create_buffer_impl(info, Some(fallback))
+            }
+            res => res,
+        }
+    }
+
     fn create_buffer_impl(
         &self,
         info: BufferInfo,
@@ -422,11 +739,14 @@ impl Device {
                 }
             }
             .map_err(|e| match e {
-                gpu_alloc::AllocationError::OutOfDeviceMemory => OutOfDeviceMemory,
+                gpu_alloc::AllocationError::OutOfDeviceMemory => {
+                    self.on_out_of_device_memory(reqs.memory_requirements.size)
+                }
                 gpu_alloc::AllocationError::OutOfHostMemory => crate::out_of_host_memory(),
                 _ => panic!("unexpected allocation error: {e:?}"),
             })?
         };
+        self.inner.track_heap_usage(&block, true);
 
         unsafe { logical.bind_buffer_memory(*handle, *block.memory(), block.offset()) }
             .map_err(OutOfDeviceMemory::on_creation)?;
@@ -439,7 +759,11 @@ impl Device {
             None
         };
 
-        tracing::debug!(buffer = ?*handle, "created buffer");
+        if let Some(label) = info.label {
+            self.set_object_name(*handle, label);
+        }
+
+        tracing::debug!(buffer = ?*handle, label = info.label, "created buffer");
 
         Ok(Buffer::new(
             handle.disarm(),
@@ -455,13 +779,7 @@ impl Device {
         handle: vk::Buffer,
         block: gpu_alloc::MemoryBlock<vk::DeviceMemory>,
     ) {
-        self.inner
-            .allocator
-            .lock()
-            .unwrap()
-            .dealloc(self.logical().as_memory_device(), block);
-
-        self.logical().destroy_buffer(handle, None);
+        self.destroy_deferred(DeferredDestroy::Buffer(handle, block));
     }
 
     pub fn create_buffer_view(
@@ -496,7 +814,7 @@ impl Device {
     }
 
     pub(crate) unsafe fn destroy_buffer_view(&self, handle: vk::BufferView) {
-        self.logical().destroy_buffer_view(handle, None);
+        self.destroy_deferred(DeferredDestroy::BufferView(handle));
     }
 
     pub fn create_image(&self, info: ImageInfo) -> Result<Image, OutOfDeviceMemory> {
@@ -504,6 +822,7 @@ impl Device {
 
         let handle = {
             let info = vk::ImageCreateInfo::builder()
+                .flags(info.flags.to_vk())
                 .image_type(info.extent.to_vk())
                 .format(info.format.to_vk())
                 .extent(vk::Extent3D::from_gfx(info.extent))
@@ -558,15 +877,22 @@ impl Device {
             }
         }
         .map_err(|e| match e {
-            gpu_alloc::AllocationError::OutOfDeviceMemory => OutOfDeviceMemory,
+            gpu_alloc::AllocationError::OutOfDeviceMemory => {
+                self.on_out_of_device_memory(reqs.memory_requirements.size)
+            }
             gpu_alloc::AllocationError::OutOfHostMemory => crate::out_of_host_memory(),
             _ => panic!("unexpected allocation error: {e:?}"),
         })?;
+        self.inner.track_heap_usage(&block, true);
 
         unsafe { logical.bind_image_memory(*handle, *block.memory(), block.offset()) }
             .map_err(OutOfDeviceMemory::on_creation)?;
 
-        tracing::debug!(image = ?*handle, "created image");
+        if let Some(label) = info.label {
+            self.set_object_name(*handle, label);
+        }
+
+        tracing::debug!(image = ?*handle, label = info.label, "created image");
 
         Ok(Image::new(handle.disarm(), info, self.downgrade(), block))
     }
@@ -576,13 +902,7 @@ impl Device {
         handle: vk::Image,
         block: gpu_alloc::MemoryBlock<vk::DeviceMemory>,
     ) {
-        self.inner
-            .allocator
-            .lock()
-            .unwrap()
-            .dealloc(self.logical().as_memory_device(), block);
-
-        self.logical().destroy_image(handle, None)
+        self.destroy_deferred(DeferredDestroy::Image(handle, block));
     }
 
     pub fn create_image_view(&self, info: ImageViewInfo) -> Result<ImageView, OutOfDeviceMemory> {
@@ -602,13 +922,25 @@ impl Device {
                 .map_err(OutOfDeviceMemory::on_creation)?
         };
 
+        // NOTE: Views are named after their image
+        if let Some(label) = info.image.info().label {
+            self.set_object_name(handle, label);
+        }
+
         tracing::debug!(image_view = ?handle, "created image view");
 
         Ok(ImageView::new(handle, info, self.downgrade()))
     }
 
     pub(crate) unsafe fn destroy_image_view(&self, handle: vk::ImageView) {
-        self.logical().destroy_image_view(handle, None);
+        self.destroy_deferred(DeferredDestroy::ImageView(handle));
+    }
+
+    /// Enqueues the resource to be destroyed by [`Device::flush_destroys`].
+    fn destroy_deferred(&self, resource: DeferredDestroy) {
+        self.inner
+            .deferred_destroys
+            .push(&self.inner.epochs, resource);
     }
 
     pub fn create_sampler(&self, info: SamplerInfo) -> Result<Sampler, OutOfDeviceMemory> {
@@ -682,6 +1014,10 @@ impl Device {
                 .map_err(OutOfDeviceMemory::on_creation)?
         };
 
+        if let Some(label) = &info.label {
+            self.set_object_name(handle, label);
+        }
+
         tracing::debug!(shader_module = ?handle, "created shader module");
 
         Ok(ShaderModule::new(handle, info, self.downgrade()))
@@ -699,8 +1035,18 @@ impl Device {
 
         let mut subpasses = SmallVec::<[_; 4]>::with_capacity(info.subpasses.len());
         for (subpass_index, subpass) in info.subpasses.iter().enumerate() {
+            if !subpass.resolves.is_empty() && subpass.resolves.len() != subpass.colors.len() {
+                return Err(CreateRenderPassError::ResolveAttachmentCountMismatch {
+                    resolve_count: subpass.resolves.len(),
+                    color_count: subpass.colors.len(),
+                    subpass_index,
+                });
+            }
+
             let color_offset = subpass_attachments.len();
-            subpass_attachments.reserve(subpass.colors.len() + subpass.depth.is_some() as usize);
+            subpass_attachments.reserve(
+                subpass.colors.len() + subpass.resolves.len() + subpass.depth.is_some() as usize,
+            );
 
             for (color_index, &(i, layout)) in subpass.colors.iter().enumerate() {
                 if i as usize >= info.attachments.len() {
@@ -718,6 +1064,23 @@ impl Device {
                 );
             }
 
+            let resolves_offset = subpass_attachments.len();
+            for (color_index, &(i, layout)) in subpass.resolves.iter().enumerate() {
+                if i as usize >= info.attachments.len() {
+                    return Err(CreateRenderPassError::ResolveAttachmentOutOfBounds {
+                        attachment_index: i,
+                        color_index,
+                        subpass_index,
+                    });
+                }
+
+                subpass_attachments.push(
+                    vk::AttachmentReference::builder()
+                        .attachment(i)
+                        .layout(layout.to_vk()),
+                );
+            }
+
             let depths_offset = subpass_attachments.len();
             if let Some((i, layout)) = subpass.depth {
                 if i as usize >= info.attachments.len() {
@@ -734,21 +1097,28 @@ impl Device {
                 );
             }
 
-            subpasses.push((color_offset, depths_offset));
+            subpasses.push((color_offset, resolves_offset, depths_offset));
         }
         let subpasses = info
             .subpasses
             .iter()
             .zip(subpasses)
-            .map(|(subpass, (color_offset, depths_offset))| {
-                let descr = vk::SubpassDescription::builder()
-                    .color_attachments(&subpass_attachments[color_offset..depths_offset]);
-                if subpass.depth.is_some() {
-                    descr.depth_stencil_attachment(&subpass_attachments[depths_offset])
-                } else {
-                    descr
-                }
-            })
+            .map(
+                |(subpass, (color_offset, resolves_offset, depths_offset))| {
+                    let mut descr = vk::SubpassDescription::builder()
+                        .color_attachments(&subpass_attachments[color_offset..resolves_offset]);
+                    if !subpass.resolves.is_empty() {
+                        descr = descr.resolve_attachments(
+                            &subpass_attachments[resolves_offset..depths_offset],
+                        );
+                    }
+                    if subpass.depth.is_some() {
+                        descr.depth_stencil_attachment(&subpass_attachments[depths_offset])
+                    } else {
+                        descr
+                    }
+                },
+            )
             .collect::<Vec<_>>();
 
         let attachments = info
@@ -761,7 +1131,7 @@ impl Device {
                     .store_op(info.store_op.to_vk())
                     .initial_layout(info.initial_layout.to_vk())
                     .final_layout(info.final_layout.to_vk())
-                    .samples(vk::SampleCountFlags::_1)
+                    .samples(info.samples.to_vk())
             })
             .collect::<Vec<_>>();
 
@@ -844,6 +1214,24 @@ impl Device {
         let graphics = self.graphics();
         let logical = &self.inner.logical;
 
+        if info
+            .flags
+            .contains(DescriptorSetLayoutFlags::PUSH_DESCRIPTOR)
+        {
+            assert!(
+                self.is_feature_enabled(DeviceFeature::PushDescriptor),
+                "`PushDescriptor` feature must be enabled to use `PUSH_DESCRIPTOR` layouts"
+            );
+
+            let max_push_descriptors = self.properties().push_descriptor.max_push_descriptors;
+            let descriptor_count = info.bindings.iter().map(|b| b.count).sum::<u32>();
+            assert!(
+                descriptor_count <= max_push_descriptors,
+                "push descriptor set layout has too many descriptors \
+                ({descriptor_count} > {max_push_descriptors})"
+            );
+        }
+
         let handle = {
             let flags;
             let mut flags_info;
@@ -956,6 +1344,10 @@ impl Device {
             sets.remove(0)
         };
 
+        if let Some(label) = info.label {
+            self.set_object_name(set.handle(), label);
+        }
+
         tracing::debug!(descriptor_set = ?set.handle(), "created descriptor set");
 
         Ok(DescriptorSet::new(set, info, self.downgrade()))
@@ -996,6 +1388,12 @@ impl Device {
             }
         }
 
+        for update in updates {
+            for write in update.writes {
+                check_descriptor_usage(write.data);
+            }
+        }
+
         let alloc = Bump::new();
 
         let writes = {
@@ -1018,119 +1416,7 @@ impl Device {
         for update in updates.iter() {
             for write in update.writes.iter() {
                 let descr = writes_iter.next().unwrap();
-
-                match write.data {
-                    DescriptorSlice::Sampler(data) => {
-                        let images = alloc.alloc_slice_fill_iter(data.iter().map(|sampler| {
-                            vk::DescriptorImageInfo::builder().sampler(sampler.handle())
-                        }));
-                        descr.descriptor_type = vk::DescriptorType::SAMPLER;
-                        descr.descriptor_count = images.len() as _;
-                        descr.image_info = images.as_ptr().cast();
-                    }
-                    DescriptorSlice::CombinedImageSampler(data) => {
-                        let images = alloc.alloc_slice_fill_iter(data.iter().map(|item| {
-                            vk::DescriptorImageInfo::builder()
-                                .sampler(item.sampler.handle())
-                                .image_view(item.view.handle())
-                                .image_layout(item.layout.to_vk())
-                        }));
-                        descr.descriptor_type = vk::DescriptorType::COMBINED_IMAGE_SAMPLER;
-                        descr.descriptor_count = images.len() as _;
-                        descr.image_info = images.as_ptr().cast();
-                    }
-                    DescriptorSlice::SampledImage(data) => {
-                        let images =
-                            alloc.alloc_slice_fill_iter(data.iter().map(|(view, layout)| {
-                                vk::DescriptorImageInfo::builder()
-                                    .image_view(view.handle())
-                                    .image_layout((*layout).to_vk())
-                            }));
-                        descr.descriptor_type = vk::DescriptorType::SAMPLED_IMAGE;
-                        descr.descriptor_count = images.len() as _;
-                        descr.image_info = images.as_ptr().cast();
-                    }
-                    DescriptorSlice::StorageImage(data) => {
-                        let images =
-                            alloc.alloc_slice_fill_iter(data.iter().map(|(view, layout)| {
-                                vk::DescriptorImageInfo::builder()
-                                    .image_view(view.handle())
-                                    .image_layout((*layout).to_vk())
-                            }));
-                        descr.descriptor_type = vk::DescriptorType::STORAGE_IMAGE;
-                        descr.descriptor_count = images.len() as _;
-                        descr.image_info = images.as_ptr().cast();
-                    }
-                    DescriptorSlice::UniformTexelBuffer(data) => {
-                        let views =
-                            alloc.alloc_slice_fill_iter(data.iter().map(BufferView::handle));
-                        descr.descriptor_type = vk::DescriptorType::UNIFORM_TEXEL_BUFFER;
-                        descr.descriptor_count = views.len() as _;
-                        descr.texel_buffer_view = views.as_ptr().cast();
-                    }
-                    DescriptorSlice::StorageTexelBuffer(data) => {
-                        let views =
-                            alloc.alloc_slice_fill_iter(data.iter().map(BufferView::handle));
-                        descr.descriptor_type = vk::DescriptorType::STORAGE_TEXEL_BUFFER;
-                        descr.descriptor_count = views.len() as _;
-                        descr.texel_buffer_view = views.as_ptr().cast();
-                    }
-                    DescriptorSlice::UniformBuffer(data) => {
-                        let buffers = alloc.alloc_slice_fill_iter(data.iter().map(|range| {
-                            vk::DescriptorBufferInfo::builder()
-                                .buffer(range.buffer.handle())
-                                .offset(range.offset as u64)
-                                .range(range.size as u64)
-                        }));
-                        descr.descriptor_type = vk::DescriptorType::UNIFORM_BUFFER;
-                        descr.descriptor_count = buffers.len() as _;
-                        descr.buffer_info = buffers.as_ptr().cast();
-                    }
-                    DescriptorSlice::StorageBuffer(data) => {
-                        let buffers = alloc.alloc_slice_fill_iter(data.iter().map(|range| {
-                            vk::DescriptorBufferInfo::builder()
-                                .buffer(range.buffer.handle())
-                                .offset(range.offset as u64)
-                                .range(range.size as u64)
-                        }));
-                        descr.descriptor_type = vk::DescriptorType::STORAGE_BUFFER;
-                        descr.descriptor_count = buffers.len() as _;
-                        descr.buffer_info = buffers.as_ptr().cast();
-                    }
-                    DescriptorSlice::UniformBufferDynamic(data) => {
-                        let buffers = alloc.alloc_slice_fill_iter(data.iter().map(|range| {
-                            vk::DescriptorBufferInfo::builder()
-                                .buffer(range.buffer.handle())
-                                .offset(range.offset as u64)
-                                .range(range.size as u64)
-                        }));
-                        descr.descriptor_type = vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC;
-                        descr.descriptor_count = buffers.len() as _;
-                        descr.buffer_info = buffers.as_ptr().cast();
-                    }
-                    DescriptorSlice::StorageBufferDynamic(data) => {
-                        let buffers = alloc.alloc_slice_fill_iter(data.iter().map(|range| {
-                            vk::DescriptorBufferInfo::builder()
-                                .buffer(range.buffer.handle())
-                                .offset(range.offset as u64)
-                                .range(range.size as u64)
-                        }));
-                        descr.descriptor_type = vk::DescriptorType::STORAGE_BUFFER_DYNAMIC;
-                        descr.descriptor_count = buffers.len() as _;
-                        descr.buffer_info = buffers.as_ptr().cast();
-                    }
-                    DescriptorSlice::InputAttachment(data) => {
-                        let images =
-                            alloc.alloc_slice_fill_iter(data.iter().map(|(view, layout)| {
-                                vk::DescriptorImageInfo::builder()
-                                    .image_view(view.handle())
-                                    .image_layout((*layout).to_vk())
-                            }));
-                        descr.descriptor_type = vk::DescriptorType::INPUT_ATTACHMENT;
-                        descr.descriptor_count = images.len() as _;
-                        descr.image_info = images.as_ptr().cast();
-                    }
-                }
+                fill_descriptor_write(&alloc, descr, write.data);
             }
         }
         debug_assert!(writes_iter.next().is_none());
@@ -1184,6 +1470,120 @@ impl Device {
         self.logical().destroy_pipeline_layout(handle, None)
     }
 
+    pub fn create_pipeline_cache(
+        &self,
+        initial_data: Option<&[u8]>,
+    ) -> Result<PipelineCache, OutOfDeviceMemory> {
+        let logical = &self.inner.logical;
+
+        let info = vk::PipelineCacheCreateInfo::builder().initial_data(initial_data.unwrap_or(&[]));
+        let handle = unsafe { logical.create_pipeline_cache(&info, None) }
+            .map_err(OutOfDeviceMemory::on_creation)?;
+
+        tracing::debug!(pipeline_cache = ?handle, "created pipeline cache");
+
+        Ok(PipelineCache::new(handle, self.downgrade()))
+    }
+
+    pub(crate) unsafe fn destroy_pipeline_cache(&self, handle: vk::PipelineCache) {
+        self.logical().destroy_pipeline_cache(handle, None)
+    }
+
+    pub fn get_pipeline_cache_data(
+        &self,
+        cache: &PipelineCache,
+    ) -> Result<Vec<u8>, OutOfDeviceMemory> {
+        unsafe { self.logical().get_pipeline_cache_data(cache.handle()) }
+            .map_err(OutOfDeviceMemory::on_creation)
+    }
+
+    /// Merges the contents of `src` caches into the `dst` cache.
+    pub fn merge_pipeline_caches(
+        &self,
+        dst: &PipelineCache,
+        src: &[&PipelineCache],
+    ) -> Result<(), OutOfDeviceMemory> {
+        let src = src.iter().map(|c| c.handle()).collect::<SmallVec<[_; 8]>>();
+        if src.is_empty() {
+            return Ok(());
+        }
+
+        unsafe { self.logical().merge_pipeline_caches(dst.handle(), &src) }
+            .map_err(OutOfDeviceMemory::on_creation)
+    }
+
+    /// Returns the pipeline cache used for all pipelines created by this device.
+    pub fn pipeline_cache(&self) -> Option<PipelineCache> {
+        self.inner.pipeline_cache.lock().unwrap().clone()
+    }
+
+    /// Sets the pipeline cache used for all pipelines created by this device.
+    pub fn set_pipeline_cache(&self, cache: Option<PipelineCache>) {
+        if let Some(cache) = &cache {
+            assert!(
+                cache.owner().is(self),
+                "pipeline cache belongs to another device"
+            );
+        }
+        *self.inner.pipeline_cache.lock().unwrap() = cache;
+    }
+
+    pub fn create_query_pool(&self, info: QueryPoolInfo) -> Result<QueryPool, OutOfDeviceMemory> {
+        let logical = &self.inner.logical;
+
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(info.ty.to_vk())
+            .query_count(info.count);
+        let handle = unsafe { logical.create_query_pool(&create_info, None) }
+            .map_err(OutOfDeviceMemory::on_creation)?;
+
+        tracing::debug!(query_pool = ?handle, "created query pool");
+
+        Ok(QueryPool::new(handle, info, self.downgrade()))
+    }
+
+    pub(crate) unsafe fn destroy_query_pool(&self, handle: vk::QueryPool) {
+        self.logical().destroy_query_pool(handle, None)
+    }
+
+    /// Reads 64-bit results of the queries starting from `first_query` into `results`.
+    ///
+    /// Doesn't wait for the queries to become available. Returns `false` if
+    /// some of the results are not available yet.
+    pub fn get_query_results(
+        &self,
+        pool: &QueryPool,
+        first_query: u32,
+        results: &mut [u64],
+    ) -> Result<bool, DeviceLost> {
+        assert!(
+            first_query as usize + results.len() <= pool.info().count as usize,
+            "query range is out of bounds"
+        );
+
+        let status = unsafe {
+            self.logical().get_query_pool_results(
+                pool.handle(),
+                first_query,
+                results.len() as u32,
+                bytemuck::cast_slice_mut(results),
+                std::mem::size_of::<u64>() as u64,
+                vk::QueryResultFlags::_64,
+            )
+        }
+        .map_err(|e| match e {
+            vk::ErrorCode::DEVICE_LOST => DeviceLost,
+            vk::ErrorCode::OUT_OF_HOST_MEMORY => crate::out_of_host_memory(),
+            _ => crate::unexpected_vulkan_error(e),
+        })?;
+
+        match status {
+            vk::SuccessCode::SUCCESS => Ok(true),
+            vk::SuccessCode::NOT_READY => Ok(false),
+            c => panic!("unexpected status code: {c:?}"),
+        }
+    }
+
     pub fn create_graphics_pipeline(
         &self,
         info: GraphicsPipelineInfo,
@@ -1193,12 +1593,11 @@ impl Device {
 
         let mut create_info = vk::GraphicsPipelineCreateInfo::builder();
 
-        let color_count = {
+        let (color_count, samples) = {
             let r = &info.rendering;
+            let render_pass_info = r.render_pass.info();
 
-            let subpass = r
-                .render_pass
-                .info()
+            let subpass = render_pass_info
                 .subpasses
                 .get(r.subpass as usize)
                 .expect("subpass index is out of bounds");
@@ -1207,7 +1606,16 @@ impl Device {
                 .render_pass(r.render_pass.handle())
                 .subpass(r.subpass);
 
-            subpass.colors.len()
+            // NOTE: All color and depth attachments of the subpass must have the same sample count
+            let samples = subpass
+                .colors
+                .first()
+                .or(subpass.depth.as_ref())
+                .map_or(Samples::_1, |&(i, _)| {
+                    render_pass_info.attachments[i as usize].samples
+                });
+
+            (subpass.colors.len(), samples)
         };
 
         let mut shader_stages = Vec::with_capacity(2);
@@ -1282,8 +1690,7 @@ impl Device {
                 }
 
                 // Multisample state
-                multisample_state =
-                    multisample_state.rasterization_samples(vk::SampleCountFlags::_1);
+                multisample_state = multisample_state.rasterization_samples(samples.to_vk());
 
                 // Depth/stencil state
                 if let Some(depth_test) = rasterizer.depth_test {
@@ -1430,13 +1837,23 @@ impl Device {
                 }
 
                 // Rasterization state
-                vk::PipelineRasterizationStateCreateInfo::builder()
+                let mut rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
                     .rasterizer_discard_enable(false)
                     .depth_clamp_enable(rasterizer.depth_clamp)
                     .polygon_mode(rasterizer.polygin_mode.to_vk())
                     .cull_mode(rasterizer.cull_mode.to_vk())
                     .front_face(rasterizer.front_face.to_vk())
-                    .line_width(1.0)
+                    .line_width(1.0);
+
+                if let Some(depth_bias) = rasterizer.depth_bias {
+                    rasterization_state = rasterization_state
+                        .depth_bias_enable(true)
+                        .depth_bias_constant_factor(depth_bias.constant_factor)
+                        .depth_bias_clamp(depth_bias.clamp)
+                        .depth_bias_slope_factor(depth_bias.slope_factor);
+                }
+
+                rasterization_state
             }
             None => {
                 // Rasterization state (discarded)
@@ -1469,10 +1886,13 @@ impl Device {
                 .color_blend_state(&color_blend_state);
         }
 
+        let cache = self.pipeline_cache();
         let handle = {
             let (mut pipelines, _) = unsafe {
                 logical.create_graphics_pipelines(
-                    vk::PipelineCache::null(),
+                    cache
+                        .as_ref()
+                        .map_or(vk::PipelineCache::null(), |c| c.handle()),
                     std::slice::from_ref(&create_info),
                     None,
                 )
@@ -1482,6 +1902,10 @@ impl Device {
             pipelines.remove(0)
         };
 
+        if let Some(label) = &descr.vertex_shader.module().info().label {
+            self.set_object_name(handle, label);
+        }
+
         tracing::debug!(graphics_pipeline = ?handle, "created graphics pipeline");
 
         Ok(GraphicsPipeline::new(handle, info, self.downgrade()))
@@ -1492,6 +1916,7 @@ impl Device {
         info: ComputePipelineInfo,
     ) -> Result<ComputePipeline, OutOfDeviceMemory> {
         let logical = &self.inner.logical;
+        let cache = self.pipeline_cache();
 
         let handle = {
             let name = vk::StringArray::<64>::from_bytes(info.shader.entry().as_bytes());
@@ -1507,7 +1932,9 @@ impl Device {
 
             let (mut pipelines, _) = unsafe {
                 logical.create_compute_pipelines(
-                    vk::PipelineCache::null(),
+                    cache
+                        .as_ref()
+                        .map_or(vk::PipelineCache::null(), |c| c.handle()),
                     std::slice::from_ref(&info),
                     None,
                 )
@@ -1517,6 +1944,10 @@ impl Device {
             pipelines.remove(0)
         };
 
+        if let Some(label) = &info.shader.module().info().label {
+            self.set_object_name(handle, label);
+        }
+
         tracing::debug!(compute_pipeline = ?handle, "created compute pipeline");
 
         Ok(ComputePipeline::new(handle, info, self.downgrade()))
@@ -1563,13 +1994,63 @@ struct Inner {
     physical: vk::PhysicalDevice,
     properties: Box<DeviceProperties>,
     features: Box<DeviceFeatures>,
+    enabled_features: FastHashSet<DeviceFeature>,
     allocator: Mutex<GpuAllocator<vk::DeviceMemory>>,
+    /// Bytes allocated from each memory heap.
+    heap_usage: Box<[AtomicU64]>,
+    /// Whether all device local memory is host visible.
+    direct_upload: bool,
+    /// Heap of host visible device local memory, e.g. the resizable BAR,
+    /// `None` with unified memory.
+    bar_heap: Option<u32>,
     descriptors: Mutex<DescriptorAlloc>,
     samplers_cache: FastDashMap<SamplerInfo, Sampler>,
+    pipeline_cache: Mutex<Option<PipelineCache>>,
     epochs: Epochs,
+    deferred_destroys: DeferredDestroyQueue,
 }
 
 impl Inner {
+    fn track_heap_usage(&self, block: &gpu_alloc::MemoryBlock<vk::DeviceMemory>, allocated: bool) {
+        let memory = &self.properties.memory;
+        let heap = memory.memory_types[block.memory_type() as usize].heap_index;
+        let usage = &self.heap_usage[heap as usize];
+        if allocated {
+            usage.fetch_add(block.size(), Ordering::Relaxed);
+        } else {
+            usage.fetch_sub(block.size(), Ordering::Relaxed);
+        }
+    }
+
+    unsafe fn destroy_now(&self, resource: DeferredDestroy) {
+        match resource {
+            DeferredDestroy::Buffer(handle, block) => {
+                self.track_heap_usage(&block, false);
+                self.allocator
+                    .lock()
+                    .unwrap()
+                    .dealloc(self.logical.as_memory_device(), block);
+
+                self.logical.destroy_buffer(handle, None);
+            }
+            DeferredDestroy::BufferView(handle) => {
+                self.logical.destroy_buffer_view(handle, None);
+            }
+            DeferredDestroy::Image(handle, block) => {
+                self.track_heap_usage(&block, false);
+                self.allocator
+                    .lock()
+                    .unwrap()
+                    .dealloc(self.logical.as_memory_device(), block);
+
+                self.logical.destroy_image(handle, None);
+            }
+            DeferredDestroy::ImageView(handle) => {
+                self.logical.destroy_image_view(handle, None);
+            }
+        }
+    }
+
     fn wait_idle(&self) -> Result<(), DeviceLost> {
         let old_epochs = self.epochs.next_epoch_all_queues();
 
@@ -1607,6 +2088,18 @@ impl Drop for Inner {
     fn drop(&mut self) {
         let _ = self.wait_idle();
 
+        // NOTE: Even if the device was lost, resources must be destroyed
+        // before the allocator cleanup.
+        for resource in self.deferred_destroys.drain_all() {
+            unsafe { self.destroy_now(resource) };
+        }
+
+        // NOTE: The default pipeline cache cannot be destroyed by its own
+        // drop because the weak device reference is already dead here.
+        if let Some(cache) = self.pipeline_cache.get_mut().unwrap().take() {
+            unsafe { self.logical.destroy_pipeline_cache(cache.handle(), None) };
+        }
+
         unsafe {
             self.allocator
                 .get_mut()
@@ -1618,6 +2111,124 @@ impl Drop for Inner {
     }
 }
 
+/// Fills the descriptor type and data pointers of a descriptor write.
+///
+/// NOTE: Descriptor infos are allocated in `alloc` which must outlive the write.
+pub(crate) fn fill_descriptor_write(
+    alloc: &Bump,
+    descr: &mut vk::WriteDescriptorSet,
+    data: DescriptorSlice<'_>,
+) {
+    match data {
+        DescriptorSlice::Sampler(data) => {
+            let images = alloc.alloc_slice_fill_iter(
+                data.iter()
+                    .map(|sampler| vk::DescriptorImageInfo::builder().sampler(sampler.handle())),
+            );
+            descr.descriptor_type = vk::DescriptorType::SAMPLER;
+            descr.descriptor_count = images.len() as _;
+            descr.image_info = images.as_ptr().cast();
+        }
+        DescriptorSlice::CombinedImageSampler(data) => {
+            let images = alloc.alloc_slice_fill_iter(data.iter().map(|item| {
+                vk::DescriptorImageInfo::builder()
+                    .sampler(item.sampler.handle())
+                    .image_view(item.view.handle())
+                    .image_layout(item.layout.to_vk())
+            }));
+            descr.descriptor_type = vk::DescriptorType::COMBINED_IMAGE_SAMPLER;
+            descr.descriptor_count = images.len() as _;
+            descr.image_info = images.as_ptr().cast();
+        }
+        DescriptorSlice::SampledImage(data) => {
+            let images = alloc.alloc_slice_fill_iter(data.iter().map(|(view, layout)| {
+                vk::DescriptorImageInfo::builder()
+                    .image_view(view.handle())
+                    .image_layout((*layout).to_vk())
+            }));
+            descr.descriptor_type = vk::DescriptorType::SAMPLED_IMAGE;
+            descr.descriptor_count = images.len() as _;
+            descr.image_info = images.as_ptr().cast();
+        }
+        DescriptorSlice::StorageImage(data) => {
+            let images = alloc.alloc_slice_fill_iter(data.iter().map(|(view, layout)| {
+                vk::DescriptorImageInfo::builder()
+                    .image_view(view.handle())
+                    .image_layout((*layout).to_vk())
+            }));
+            descr.descriptor_type = vk::DescriptorType::STORAGE_IMAGE;
+            descr.descriptor_count = images.len() as _;
+            descr.image_info = images.as_ptr().cast();
+        }
+        DescriptorSlice::UniformTexelBuffer(data) => {
+            let views = alloc.alloc_slice_fill_iter(data.iter().map(BufferView::handle));
+            descr.descriptor_type = vk::DescriptorType::UNIFORM_TEXEL_BUFFER;
+            descr.descriptor_count = views.len() as _;
+            descr.texel_buffer_view = views.as_ptr().cast();
+        }
+        DescriptorSlice::StorageTexelBuffer(data) => {
+            let views = alloc.alloc_slice_fill_iter(data.iter().map(BufferView::handle));
+            descr.descriptor_type = vk::DescriptorType::STORAGE_TEXEL_BUFFER;
+            descr.descriptor_count = views.len() as _;
+            descr.texel_buffer_view = views.as_ptr().cast();
+        }
+        DescriptorSlice::UniformBuffer(data) => {
+            let buffers = alloc.alloc_slice_fill_iter(data.iter().map(|range| {
+                vk::DescriptorBufferInfo::builder()
+                    .buffer(range.buffer.handle())
+                    .offset(range.offset as u64)
+                    .range(range.size as u64)
+            }));
+            descr.descriptor_type = vk::DescriptorType::UNIFORM_BUFFER;
+            descr.descriptor_count = buffers.len() as _;
+            descr.buffer_info = buffers.as_ptr().cast();
+        }
+        DescriptorSlice::StorageBuffer(data) => {
+            let buffers = alloc.alloc_slice_fill_iter(data.iter().map(|range| {
+                vk::DescriptorBufferInfo::builder()
+                    .buffer(range.buffer.handle())
+                    .offset(range.offset as u64)
+                    .range(range.size as u64)
+            }));
+            descr.descriptor_type = vk::DescriptorType::STORAGE_BUFFER;
+            descr.descriptor_count = buffers.len() as _;
+            descr.buffer_info = buffers.as_ptr().cast();
+        }
+        DescriptorSlice::UniformBufferDynamic(data) => {
+            let buffers = alloc.alloc_slice_fill_iter(data.iter().map(|range| {
+                vk::DescriptorBufferInfo::builder()
+                    .buffer(range.buffer.handle())
+                    .offset(range.offset as u64)
+                    .range(range.size as u64)
+            }));
+            descr.descriptor_type = vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC;
+            descr.descriptor_count = buffers.len() as _;
+            descr.buffer_info = buffers.as_ptr().cast();
+        }
+        DescriptorSlice::StorageBufferDynamic(data) => {
+            let buffers = alloc.alloc_slice_fill_iter(data.iter().map(|range| {
+                vk::DescriptorBufferInfo::builder()
+                    .buffer(range.buffer.handle())
+                    .offset(range.offset as u64)
+                    .range(range.size as u64)
+            }));
+            descr.descriptor_type = vk::DescriptorType::STORAGE_BUFFER_DYNAMIC;
+            descr.descriptor_count = buffers.len() as _;
+            descr.buffer_info = buffers.as_ptr().cast();
+        }
+        DescriptorSlice::InputAttachment(data) => {
+            let images = alloc.alloc_slice_fill_iter(data.iter().map(|(view, layout)| {
+                vk::DescriptorImageInfo::builder()
+                    .image_view(view.handle())
+                    .image_layout((*layout).to_vk())
+            }));
+            descr.descriptor_type = vk::DescriptorType::INPUT_ATTACHMENT;
+            descr.descriptor_count = images.len() as _;
+            descr.image_info = images.as_ptr().cast();
+        }
+    }
+}
+
 fn map_memory_device_properties(
     propertis: &DeviceProperties,
     features: &DeviceFeatures,
@@ -1649,6 +2260,113 @@ fn map_memory_device_properties(
     }
 }
 
+/// Returns format features required for the image usage.
+fn format_features(format: Format, usage: ImageUsageFlags) -> vk::FormatFeatureFlags {
+    let mut res = vk::FormatFeatureFlags::empty();
+    if usage.contains(ImageUsageFlags::TRANSFER_SRC) {
+        res |= vk::FormatFeatureFlags::TRANSFER_SRC;
+    }
+    if usage.contains(ImageUsageFlags::TRANSFER_DST) {
+        res |= vk::FormatFeatureFlags::TRANSFER_DST;
+    }
+    if usage.contains(ImageUsageFlags::SAMPLED) {
+        res |= vk::FormatFeatureFlags::SAMPLED_IMAGE;
+    }
+    if usage.contains(ImageUsageFlags::STORAGE) {
+        res |= vk::FormatFeatureFlags::STORAGE_IMAGE;
+    }
+    if usage.contains(ImageUsageFlags::COLOR_ATTACHMENT) {
+        res |= vk::FormatFeatureFlags::COLOR_ATTACHMENT;
+    }
+    if usage.contains(ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+        res |= vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT;
+    }
+    if usage.contains(ImageUsageFlags::INPUT_ATTACHMENT) {
+        res |= if format.is_color() {
+            vk::FormatFeatureFlags::COLOR_ATTACHMENT
+        } else {
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+        };
+    }
+    res
+}
+
+/// Returns `true` if there are device local memory types
+/// and all of them are host visible.
+fn is_unified_memory(memory: &vk::PhysicalDeviceMemoryProperties) -> bool {
+    let mut device_local = memory.memory_types[..memory.memory_type_count as usize]
+        .iter()
+        .filter(|ty| {
+            ty.property_flags
+                .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        })
+        .peekable();
+
+    device_local.peek().is_some()
+        && device_local.all(|ty| {
+            ty.property_flags
+                .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        })
+}
+
+/// Returns the heap of memory types which are both device local and host visible.
+fn find_bar_heap(memory: &vk::PhysicalDeviceMemoryProperties) -> Option<u32> {
+    let flags = vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE;
+    memory.memory_types[..memory.memory_type_count as usize]
+        .iter()
+        .find(|ty| ty.property_flags.contains(flags))
+        .map(|ty| ty.heap_index)
+}
+
+/// Returns `true` if `size` bytes can be allocated from the resizable BAR heap
+/// while keeping a part of its budget for buffers which require it.
+fn bar_heap_has_room(heap: &HeapUsage, size: u64) -> bool {
+    let limit = heap.budget - heap.budget / BAR_HEAP_RESERVED_FRACTION;
+    heap.usage.saturating_add(size) <= limit
+}
+
+/// Fraction of the resizable BAR heap budget (1/N) which is not used
+/// by buffers with [`MemoryUsagePreference::Preferred`].
+const BAR_HEAP_RESERVED_FRACTION: u64 = 8;
+
+/// Memory usage of a single memory heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapUsage {
+    /// Total heap size in bytes.
+    pub size: u64,
+    pub device_local: bool,
+    /// Bytes of the heap used by the process.
+    pub usage: u64,
+    /// Bytes of the heap which the process can use without failing allocations
+    /// or degrading performance.
+    pub budget: u64,
+}
+
+impl HeapUsage {
+    /// Bytes which can still be allocated within the budget.
+    pub fn available(&self) -> u64 {
+        self.budget.saturating_sub(self.usage)
+    }
+}
+
+impl std::fmt::Display for HeapUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const MIB: u64 = 1 << 20;
+        write!(
+            f,
+            "{}: {} / {} MiB used (size: {} MiB)",
+            if self.device_local {
+                "device local"
+            } else {
+                "host"
+            },
+            self.usage / MIB,
+            self.budget / MIB,
+            self.size / MIB,
+        )
+    }
+}
+
 /// An error returned when memory mapping fails.
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum MapError {
@@ -1698,4 +2416,88 @@ pub enum CreateRenderPassError {
         attachment_index: u32,
         subpass_index: usize,
     },
+
+    #[error(
+        "attachment index {attachment_index} is out of bounds for the resolve input \
+        {color_index} in the subpass {subpass_index}"
+    )]
+    ResolveAttachmentOutOfBounds {
+        attachment_index: u32,
+        color_index: usize,
+        subpass_index: usize,
+    },
+
+    #[error(
+        "resolve attachment count {resolve_count} doesn't match the color attachment \
+        count {color_count} in the subpass {subpass_index}"
+    )]
+    ResolveAttachmentCountMismatch {
+        resolve_count: usize,
+        color_count: usize,
+        subpass_index: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1 << 20;
+
+    /// Discrete GPU with a 256 MiB host visible part of the device memory.
+    fn small_bar_memory_properties() -> vk::PhysicalDeviceMemoryProperties {
+        let mut memory = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 3,
+            memory_heap_count: 3,
+            ..Default::default()
+        };
+        memory.memory_types[0] = vk::MemoryType {
+            property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            heap_index: 0,
+        };
+        memory.memory_types[1] = vk::MemoryType {
+            property_flags: vk::MemoryPropertyFlags::HOST_VISIBLE
+                | vk::MemoryPropertyFlags::HOST_COHERENT,
+            heap_index: 1,
+        };
+        memory.memory_types[2] = vk::MemoryType {
+            property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL
+                | vk::MemoryPropertyFlags::HOST_VISIBLE
+                | vk::MemoryPropertyFlags::HOST_COHERENT,
+            heap_index: 2,
+        };
+        for (heap, size) in [8192 * MIB, 16384 * MIB, 256 * MIB].into_iter().enumerate() {
+            memory.memory_heaps[heap] = vk::MemoryHeap {
+                size,
+                flags: vk::MemoryHeapFlags::DEVICE_LOCAL,
+            };
+        }
+        memory.memory_heaps[1].flags = vk::MemoryHeapFlags::empty();
+        memory
+    }
+
+    #[test]
+    fn small_bar_heap_falls_back_near_budget() {
+        let memory = small_bar_memory_properties();
+        assert!(!is_unified_memory(&memory));
+        assert_eq!(find_bar_heap(&memory), Some(2));
+
+        let mut heap = HeapUsage {
+            size: 256 * MIB,
+            device_local: true,
+            usage: 0,
+            budget: 256 * MIB,
+        };
+        assert!(bar_heap_has_room(&heap, 64 * MIB));
+
+        // Arena buffers double in size until the reserved part is reached
+        heap.usage = 192 * MIB;
+        assert!(bar_heap_has_room(&heap, 32 * MIB));
+        assert!(!bar_heap_has_room(&heap, 64 * MIB));
+
+        // Budget reported by the driver is respected
+        heap.usage = 0;
+        heap.budget = 100 * MIB;
+        assert!(!bar_heap_has_room(&heap, 128 * MIB));
+    }
 }
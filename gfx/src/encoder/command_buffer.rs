@@ -1,16 +1,21 @@
+use std::ffi::CString;
 use std::ops::Range;
 
 use bumpalo::Bump;
-use glam::{IVec3, UVec3};
+use glam::{IVec3, UVec2, UVec3};
 use shared::util::DeallocOnDrop;
 use shared::FastHashSet;
 use vulkanalia::prelude::v1_0::*;
+use vulkanalia::vk::{DeviceV1_2, ExtDebugUtilsExtension, KhrPushDescriptorExtension};
 
-use crate::device::{Device, WeakDevice};
+use crate::device::{fill_descriptor_write, Device, WeakDevice};
+use crate::physical::DeviceFeature;
 use crate::resources::{
-    Buffer, ClearValue, ComputePipeline, DescriptorSet, Filter, Framebuffer, GraphicsPipeline,
-    Image, ImageLayout, ImageSubresourceLayers, ImageSubresourceRange, IndexType, LoadOp,
-    PipelineBindPoint, PipelineLayout, PipelineStageFlags, Rect, ShaderStageFlags, Viewport,
+    Buffer, BufferView, ClearValue, ComputePipeline, DescriptorSet, DescriptorSetLayoutFlags,
+    DescriptorSetWrite, DescriptorSlice, Filter, Format, Framebuffer, GraphicsPipeline, Image,
+    ImageLayout, ImageSubresourceLayers, ImageSubresourceRange, ImageView, IndexType, LoadOp,
+    PipelineBindPoint, PipelineLayout, PipelineStageFlags, QueryPool, Rect, Sampler,
+    ShaderStageFlags, Viewport,
 };
 use crate::types::OutOfDeviceMemory;
 use crate::util::{compute_supported_access, FromGfx, ToVk};
@@ -293,6 +298,60 @@ impl CommandBuffer {
         }
     }
 
+    pub(crate) fn push_descriptor_set(
+        &mut self,
+        bind_point: PipelineBindPoint,
+        layout: &PipelineLayout,
+        set: u32,
+        writes: &[DescriptorSetWrite<'_>],
+    ) {
+        let inner = self.inner.as_mut();
+        if let Some(device) = inner.state.device_from_full() {
+            assert!(
+                device.is_feature_enabled(DeviceFeature::PushDescriptor),
+                "`PushDescriptor` feature must be enabled to push descriptors"
+            );
+
+            let set_layout = layout
+                .info()
+                .sets
+                .get(set as usize)
+                .expect("descriptor set index is out of bounds");
+            assert!(
+                set_layout
+                    .info()
+                    .flags
+                    .contains(DescriptorSetLayoutFlags::PUSH_DESCRIPTOR),
+                "descriptor set layout must be created with `PUSH_DESCRIPTOR` flag"
+            );
+
+            inner.references.pipeline_layouts.insert(layout.clone());
+            for write in writes {
+                inner.references.add_descriptors(write.data);
+            }
+
+            let alloc = DeallocOnDrop(&mut inner.alloc);
+            let descriptor_writes = alloc.alloc_slice_fill_iter(writes.iter().map(|write| {
+                let mut descr = vk::WriteDescriptorSet::builder()
+                    .dst_binding(write.binding)
+                    .dst_array_element(write.element)
+                    .build();
+                fill_descriptor_write(&alloc, &mut descr, write.data);
+                descr
+            }));
+
+            unsafe {
+                device.logical().cmd_push_descriptor_set_khr(
+                    inner.handle,
+                    bind_point.to_vk(),
+                    layout.handle(),
+                    set,
+                    descriptor_writes,
+                )
+            }
+        }
+    }
+
     pub(crate) fn set_viewport(&mut self, viewport: &Viewport) {
         let inner = self.inner.as_mut();
         if let Some(device) = inner.state.device_from_full() {
@@ -347,6 +406,85 @@ impl CommandBuffer {
         }
     }
 
+    pub(crate) fn draw_indirect(
+        &mut self,
+        buffer: &Buffer,
+        offset: usize,
+        draw_count: u32,
+        stride: u32,
+    ) {
+        let inner = self.inner.as_mut();
+        if let Some(device) = inner.state.device_from_full() {
+            inner.references.buffers.insert(buffer.clone());
+
+            unsafe {
+                device.logical().cmd_draw_indirect(
+                    inner.handle,
+                    buffer.handle(),
+                    offset as u64,
+                    draw_count,
+                    stride,
+                )
+            }
+        }
+    }
+
+    pub(crate) fn draw_indexed_indirect(
+        &mut self,
+        buffer: &Buffer,
+        offset: usize,
+        draw_count: u32,
+        stride: u32,
+    ) {
+        let inner = self.inner.as_mut();
+        if let Some(device) = inner.state.device_from_full() {
+            inner.references.buffers.insert(buffer.clone());
+
+            unsafe {
+                device.logical().cmd_draw_indexed_indirect(
+                    inner.handle,
+                    buffer.handle(),
+                    offset as u64,
+                    draw_count,
+                    stride,
+                )
+            }
+        }
+    }
+
+    pub(crate) fn draw_indexed_indirect_count(
+        &mut self,
+        buffer: &Buffer,
+        offset: usize,
+        count_buffer: &Buffer,
+        count_offset: usize,
+        max_draw_count: u32,
+        stride: u32,
+    ) {
+        let inner = self.inner.as_mut();
+        if let Some(device) = inner.state.device_from_full() {
+            assert!(
+                device.is_feature_enabled(DeviceFeature::DrawIndirectCount),
+                "`DrawIndirectCount` feature must be enabled to read draw counts from a buffer"
+            );
+
+            inner.references.buffers.insert(buffer.clone());
+            inner.references.buffers.insert(count_buffer.clone());
+
+            unsafe {
+                device.logical().cmd_draw_indexed_indirect_count(
+                    inner.handle,
+                    buffer.handle(),
+                    offset as u64,
+                    count_buffer.handle(),
+                    count_offset as u64,
+                    max_draw_count,
+                    stride,
+                )
+            }
+        }
+    }
+
     pub(crate) fn update_buffer(&mut self, buffer: &Buffer, offset: usize, data: &[u8]) {
         let inner = self.inner.as_mut();
         if let Some(device) = inner.state.device_from_full() {
@@ -492,6 +630,35 @@ impl CommandBuffer {
         }
     }
 
+    pub(crate) fn copy_image_to_buffer(
+        &mut self,
+        src_image: &Image,
+        src_layout: ImageLayout,
+        dst_buffer: &Buffer,
+        regions: &[BufferImageCopy],
+    ) {
+        let inner = self.inner.as_mut();
+        if let Some(device) = inner.state.device_from_full() {
+            inner.references.images.push(src_image.clone());
+            inner.references.buffers.insert(dst_buffer.clone());
+
+            let alloc = DeallocOnDrop(&mut inner.alloc);
+
+            let regions = alloc
+                .alloc_slice_fill_iter(regions.iter().map(|r| vk::BufferImageCopy::from_gfx(*r)));
+
+            unsafe {
+                device.logical().cmd_copy_image_to_buffer(
+                    inner.handle,
+                    src_image.handle(),
+                    src_layout.to_vk(),
+                    dst_buffer.handle(),
+                    regions,
+                )
+            }
+        }
+    }
+
     pub(crate) fn blit_image(
         &mut self,
         src_image: &Image,
@@ -630,12 +797,88 @@ impl CommandBuffer {
         }
     }
 
+    pub(crate) fn begin_debug_label(&mut self, name: &str, color: [f32; 4]) {
+        let inner = self.inner.as_mut();
+        if let Some(device) = inner.state.device_from_full() {
+            let graphics = device.graphics();
+            if !graphics.debug_utils_enabled() {
+                return;
+            }
+
+            // NOTE: Labels with null bytes are cut at the first one
+            let name = name.split('\0').next().unwrap_or_default();
+            let name = CString::new(name).unwrap_or_default();
+            let label = vk::DebugUtilsLabelEXT::builder()
+                .label_name(name.as_bytes_with_nul())
+                .color(color);
+
+            unsafe {
+                graphics
+                    .instance()
+                    .cmd_begin_debug_utils_label_ext(inner.handle, &label)
+            }
+        }
+    }
+
+    pub(crate) fn end_debug_label(&mut self) {
+        let inner = self.inner.as_mut();
+        if let Some(device) = inner.state.device_from_full() {
+            let graphics = device.graphics();
+            if !graphics.debug_utils_enabled() {
+                return;
+            }
+
+            unsafe {
+                graphics
+                    .instance()
+                    .cmd_end_debug_utils_label_ext(inner.handle)
+            }
+        }
+    }
+
     pub(crate) fn dispatch(&mut self, x: u32, y: u32, z: u32) {
         let inner = self.inner.as_mut();
         if let Some(device) = inner.state.device_from_full() {
             unsafe { device.logical().cmd_dispatch(inner.handle, x, y, z) }
         }
     }
+
+    pub(crate) fn reset_query_pool(&mut self, pool: &QueryPool, queries: Range<u32>) {
+        let inner = self.inner.as_mut();
+        if let Some(device) = inner.state.device_from_full() {
+            inner.references.query_pools.insert(pool.clone());
+
+            unsafe {
+                device.logical().cmd_reset_query_pool(
+                    inner.handle,
+                    pool.handle(),
+                    queries.start,
+                    queries.end - queries.start,
+                )
+            }
+        }
+    }
+
+    pub(crate) fn write_timestamp(
+        &mut self,
+        stage: PipelineStageFlags,
+        pool: &QueryPool,
+        index: u32,
+    ) {
+        let inner = self.inner.as_mut();
+        if let Some(device) = inner.state.device_from_full() {
+            inner.references.query_pools.insert(pool.clone());
+
+            unsafe {
+                device.logical().cmd_write_timestamp(
+                    inner.handle,
+                    stage.to_vk(),
+                    pool.handle(),
+                    index,
+                )
+            }
+        }
+    }
 }
 
 struct Inner {
@@ -672,6 +915,10 @@ pub(crate) struct References {
     compute_pipelines: Vec<ComputePipeline>,
     pipeline_layouts: FastHashSet<PipelineLayout>,
     descriptor_sets: Vec<DescriptorSet>,
+    query_pools: FastHashSet<QueryPool>,
+    samplers: Vec<Sampler>,
+    image_views: Vec<ImageView>,
+    buffer_views: Vec<BufferView>,
 }
 
 impl References {
@@ -683,6 +930,10 @@ impl References {
             && self.compute_pipelines.is_empty()
             && self.pipeline_layouts.is_empty()
             && self.descriptor_sets.is_empty()
+            && self.query_pools.is_empty()
+            && self.samplers.is_empty()
+            && self.image_views.is_empty()
+            && self.buffer_views.is_empty()
     }
 
     pub fn clear(&mut self) {
@@ -693,9 +944,62 @@ impl References {
         self.compute_pipelines.clear();
         self.pipeline_layouts.clear();
         self.descriptor_sets.clear();
+        self.query_pools.clear();
+        self.samplers.clear();
+        self.image_views.clear();
+        self.buffer_views.clear();
+    }
+
+    /// Keeps resources of pushed descriptors alive.
+    fn add_descriptors(&mut self, data: DescriptorSlice<'_>) {
+        match data {
+            DescriptorSlice::Sampler(data) => self.samplers.extend_from_slice(data),
+            DescriptorSlice::CombinedImageSampler(data) => {
+                for item in data {
+                    self.image_views.push(item.view.clone());
+                    self.samplers.push(item.sampler.clone());
+                }
+            }
+            DescriptorSlice::SampledImage(data)
+            | DescriptorSlice::StorageImage(data)
+            | DescriptorSlice::InputAttachment(data) => self
+                .image_views
+                .extend(data.iter().map(|(view, _)| view.clone())),
+            DescriptorSlice::UniformTexelBuffer(data)
+            | DescriptorSlice::StorageTexelBuffer(data) => {
+                self.buffer_views.extend_from_slice(data)
+            }
+            DescriptorSlice::UniformBuffer(data)
+            | DescriptorSlice::StorageBuffer(data)
+            | DescriptorSlice::UniformBufferDynamic(data)
+            | DescriptorSlice::StorageBufferDynamic(data) => self
+                .buffers
+                .extend(data.iter().map(|range| range.buffer.clone())),
+        }
     }
 }
 
+/// Structure specifying an indirect drawing command.
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct DrawIndirectCommand {
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub first_vertex: u32,
+    pub first_instance: u32,
+}
+
+/// Structure specifying an indexed indirect drawing command.
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct DrawIndexedIndirectCommand {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
 /// Structure specifying a buffer copy operation.
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct BufferCopy {
@@ -748,6 +1052,31 @@ pub struct BufferImageCopy {
     pub image_extent: UVec3,
 }
 
+impl BufferImageCopy {
+    /// Makes a copy of the whole `image_extent` from tightly packed data
+    /// at `buffer_offset`.
+    ///
+    /// Rows of block-compressed formats are padded to whole blocks,
+    /// including mip levels smaller than a single block.
+    pub fn tightly_packed(
+        format: Format,
+        buffer_offset: usize,
+        image_subresource: ImageSubresourceLayers,
+        image_extent: UVec2,
+    ) -> Self {
+        let block = format.description().block;
+        let rows = block.count(image_extent) * UVec2::new(block.width, block.height);
+        Self {
+            buffer_offset,
+            buffer_row_length: rows.x,
+            buffer_image_height: rows.y,
+            image_subresource,
+            image_offset: IVec3::ZERO,
+            image_extent: image_extent.extend(1),
+        }
+    }
+}
+
 impl FromGfx<BufferImageCopy> for vk::BufferImageCopy {
     fn from_gfx(value: BufferImageCopy) -> Self {
         Self {
@@ -1,14 +1,22 @@
 use std::ops::Range;
 
+use glam::IVec3;
+use smallvec::SmallVec;
+
 pub use self::command_buffer::*;
 use crate::device::{Device, MapError};
 use crate::queue::QueueFlags;
+use crate::readback_pool::{DownloadError, ReadbackPool, ReadbackTicket};
 use crate::resources::{
-    Buffer, BufferInfo, BufferUsage, ClearValue, ComputePipeline, DescriptorSet, Filter,
-    Framebuffer, GraphicsPipeline, Image, ImageLayout, IndexType, MemoryUsage, PipelineBindPoint,
-    PipelineLayout, PipelineStageFlags, Rect, RenderPass, ShaderStageFlags, Viewport,
+    Buffer, BufferInfo, BufferRange, BufferUsage, ClearValue, ComputePipeline, DescriptorSet,
+    DescriptorSetWrite, Filter, Framebuffer, GraphicsPipeline, Image, ImageExtent, ImageInfo,
+    ImageLayout, ImageSubresourceLayers, ImageSubresourceRange, ImageUsageFlags, IndexType,
+    MemoryUsage, PipelineBindPoint, PipelineLayout, PipelineStageFlags, QueryPool, Rect,
+    RenderPass, ShaderStageFlags, Viewport,
 };
+use crate::staging_belt::StagingBelt;
 use crate::types::OutOfDeviceMemory;
+use crate::util::{check_buffer_usage, check_descriptor_usage, check_image_usage};
 
 mod command_buffer;
 
@@ -106,6 +114,20 @@ impl Encoder {
         assert!(self.capabilities.supports_graphics());
         self.command_buffer.begin_render_pass(framebuffer, clears);
 
+        let info = framebuffer.info();
+        for (view, attachment) in info
+            .attachments
+            .iter()
+            .zip(&info.render_pass.info().attachments)
+        {
+            let view = view.info();
+            view.image.track_transition(
+                &view.range,
+                attachment.initial_layout,
+                attachment.final_layout,
+            );
+        }
+
         RenderPassEncoder {
             framebuffer,
             render_pass: &framebuffer.info().render_pass,
@@ -134,13 +156,12 @@ impl Encoder {
         buffer: &Buffer,
         offset: usize,
         data: &[T],
-        device: &Device,
+        staging_belt: &StagingBelt,
     ) -> Result<(), MapError>
     where
         T: bytemuck::Pod,
     {
         const SMALL_BUFFER_SIZE: usize = 16384;
-        const MIN_ALIGN: usize = 0b11;
 
         match std::mem::size_of_val(data) {
             0 => Ok(()),
@@ -149,21 +170,15 @@ impl Encoder {
                 Ok(())
             }
             size => {
-                let staging = device.create_mappable_buffer(
-                    BufferInfo {
-                        align_mask: MIN_ALIGN.max(std::mem::align_of::<T>() - 1),
-                        size,
-                        usage: BufferUsage::TRANSFER_SRC,
-                    },
-                    MemoryUsage::UPLOAD | MemoryUsage::TRANSIENT,
-                )?;
-                device.upload_to_memory(&mut staging.as_mappable(), 0, data)?;
+                let mut staging = staging_belt.allocate(size, std::mem::align_of::<T>())?;
+                staging.write(0, data);
+                staging_belt.flush(&staging)?;
 
                 self.copy_buffer(
-                    &staging,
+                    staging.buffer(),
                     buffer,
                     &[BufferCopy {
-                        src_offset: 0,
+                        src_offset: staging.offset(),
                         dst_offset: offset,
                         size,
                     }],
@@ -173,8 +188,147 @@ impl Encoder {
         }
     }
 
+    /// Upload tightly packed pixels to the subresource of an image.
+    ///
+    /// Layers are one after another, rows of block-compressed formats are
+    /// padded to whole blocks. The subresource is transitioned from the undefined
+    /// layout, so its previous contents are discarded, and ends up in the
+    /// `layout_after` available to all following commands.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the image is not a color image with the `TRANSFER_DST` usage.
+    /// - Panics if the subresource is out of the image bounds.
+    /// - Panics if the `data` size doesn't match the subresource.
+    pub fn upload_image(
+        &mut self,
+        image: &Image,
+        subresource: ImageSubresourceLayers,
+        data: &[u8],
+        layout_after: ImageLayout,
+        device: &Device,
+    ) -> Result<(), MapError> {
+        let info = image.info();
+        assert!(
+            info.usage.contains(ImageUsageFlags::TRANSFER_DST),
+            "image must be created with `TRANSFER_DST` usage"
+        );
+        assert!(info.format.is_color(), "only color images can be uploaded");
+        assert!(
+            subresource.mip_level < info.mip_levels
+                && subresource.first_array_layer + subresource.array_layer_count
+                    <= info.array_layers,
+            "subresource is out of the image bounds"
+        );
+        assert_eq!(
+            data.len(),
+            subresource_data_size(info, &subresource),
+            "image data size doesn't match the subresource"
+        );
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        // NOTE: Vulkan has no requirements for the row pitch, only the offset
+        // of the data must be aligned to the block size, so the data is placed
+        // at the start of a dedicated buffer. The buffer is destroyed once
+        // the submission which reads it is completed.
+        let staging_buffer = device.create_mappable_buffer(
+            BufferInfo {
+                align_mask: 0b11,
+                size: data.len(),
+                usage: BufferUsage::TRANSFER_SRC,
+                label: Some("image staging buffer"),
+            },
+            MemoryUsage::UPLOAD | MemoryUsage::TRANSIENT,
+        )?;
+        device.upload_to_memory(&mut staging_buffer.as_mappable(), 0, data)?;
+
+        let range = ImageSubresourceRange::from(subresource);
+        self.image_barriers(
+            PipelineStageFlags::ALL_COMMANDS,
+            PipelineStageFlags::TRANSFER,
+            &[ImageMemoryBarrier {
+                image,
+                src_access: AccessFlags::empty(),
+                dst_access: AccessFlags::TRANSFER_WRITE,
+                old_layout: None,
+                new_layout: ImageLayout::TransferDstOptimal,
+                family_transfer: None,
+                subresource_range: range,
+            }],
+        );
+
+        let extent = info.mip_extent(subresource.mip_level);
+        self.copy_buffer_to_image(
+            &staging_buffer,
+            image,
+            ImageLayout::TransferDstOptimal,
+            &[BufferImageCopy {
+                image_extent: extent,
+                ..BufferImageCopy::tightly_packed(info.format, 0, subresource, extent.truncate())
+            }],
+        );
+
+        self.image_barriers(
+            PipelineStageFlags::TRANSFER,
+            PipelineStageFlags::ALL_COMMANDS,
+            &[ImageMemoryBarrier {
+                image,
+                src_access: AccessFlags::TRANSFER_WRITE,
+                dst_access: AccessFlags::MEMORY_READ | AccessFlags::MEMORY_WRITE,
+                old_layout: Some(ImageLayout::TransferDstOptimal),
+                new_layout: layout_after,
+                family_transfer: None,
+                subresource_range: range,
+            }],
+        );
+        Ok(())
+    }
+
+    /// Copy a buffer range into host memory.
+    ///
+    /// The data is available with [`ReadbackPool::poll`] once the submission
+    /// of this encoder is completed. Waits for all previous shader and transfer
+    /// writes into the source buffer.
+    ///
+    /// Fails if the range is empty or extends past the end of the buffer.
+    pub fn download_buffer(
+        &mut self,
+        src: &BufferRange,
+        readbacks: &ReadbackPool,
+    ) -> Result<ReadbackTicket, DownloadError> {
+        let buffer = readbacks.allocate(src)?;
+
+        self.memory_barrier(
+            PipelineStageFlags::ALL_COMMANDS,
+            AccessFlags::SHADER_WRITE | AccessFlags::TRANSFER_WRITE,
+            PipelineStageFlags::TRANSFER,
+            AccessFlags::TRANSFER_READ,
+        );
+        self.copy_buffer(
+            &src.buffer,
+            &buffer,
+            &[BufferCopy {
+                src_offset: src.offset,
+                dst_offset: 0,
+                size: src.size,
+            }],
+        );
+        self.memory_barrier(
+            PipelineStageFlags::TRANSFER,
+            AccessFlags::TRANSFER_WRITE,
+            PipelineStageFlags::HOST,
+            AccessFlags::HOST_READ,
+        );
+
+        Ok(readbacks.register(buffer, src.size))
+    }
+
     /// Copy data between buffer regions.
     pub fn copy_buffer(&mut self, src: &Buffer, dst: &Buffer, regions: &[BufferCopy]) {
+        check_buffer_usage(src.info(), BufferUsage::TRANSFER_SRC, "a copy source");
+        check_buffer_usage(dst.info(), BufferUsage::TRANSFER_DST, "a copy destination");
         self.command_buffer.copy_buffer(src, dst, regions);
     }
 
@@ -187,6 +341,20 @@ impl Encoder {
         dst_layout: ImageLayout,
         regions: &[ImageCopy],
     ) {
+        check_image_usage(
+            src_image.info(),
+            ImageUsageFlags::TRANSFER_SRC,
+            "a copy source",
+        );
+        check_image_usage(
+            dst_image.info(),
+            ImageUsageFlags::TRANSFER_DST,
+            "a copy destination",
+        );
+        for region in regions {
+            src_image.debug_assert_layout(&region.src_subresource.into(), src_layout);
+            dst_image.debug_assert_layout(&region.dst_subresource.into(), dst_layout);
+        }
         self.command_buffer
             .copy_image(src_image, src_layout, dst_image, dst_layout, regions);
     }
@@ -199,10 +367,48 @@ impl Encoder {
         dst_layout: ImageLayout,
         regions: &[BufferImageCopy],
     ) {
+        check_buffer_usage(
+            src_buffer.info(),
+            BufferUsage::TRANSFER_SRC,
+            "a copy source",
+        );
+        check_image_usage(
+            dst_image.info(),
+            ImageUsageFlags::TRANSFER_DST,
+            "a copy destination",
+        );
+        for region in regions {
+            dst_image.debug_assert_layout(&region.image_subresource.into(), dst_layout);
+        }
         self.command_buffer
             .copy_buffer_to_image(src_buffer, dst_image, dst_layout, regions);
     }
 
+    /// Copy data from an image into a buffer
+    pub fn copy_image_to_buffer(
+        &mut self,
+        src_image: &Image,
+        src_layout: ImageLayout,
+        dst_buffer: &Buffer,
+        regions: &[BufferImageCopy],
+    ) {
+        check_image_usage(
+            src_image.info(),
+            ImageUsageFlags::TRANSFER_SRC,
+            "a copy source",
+        );
+        check_buffer_usage(
+            dst_buffer.info(),
+            BufferUsage::TRANSFER_DST,
+            "a copy destination",
+        );
+        for region in regions {
+            src_image.debug_assert_layout(&region.image_subresource.into(), src_layout);
+        }
+        self.command_buffer
+            .copy_image_to_buffer(src_image, src_layout, dst_buffer, regions);
+    }
+
     /// Copy regions of an image, potentially performing format conversion,
     pub fn blit_image(
         &mut self,
@@ -214,11 +420,160 @@ impl Encoder {
         filter: Filter,
     ) {
         assert!(self.capabilities.supports_graphics());
+        check_image_usage(
+            src_image.info(),
+            ImageUsageFlags::TRANSFER_SRC,
+            "a blit source",
+        );
+        check_image_usage(
+            dst_image.info(),
+            ImageUsageFlags::TRANSFER_DST,
+            "a blit destination",
+        );
+        for region in regions {
+            src_image.debug_assert_layout(&region.src_subresource.into(), src_layout);
+            dst_image.debug_assert_layout(&region.dst_subresource.into(), dst_layout);
+        }
         self.command_buffer.blit_image(
             src_image, src_layout, dst_image, dst_layout, regions, filter,
         );
     }
 
+    /// Generate all mip levels of an image from its first level.
+    ///
+    /// The whole image must be in the `initial_layout` and all its levels
+    /// will be in the `final_layout` after the generation. Contents of all
+    /// levels except the first one are discarded.
+    pub fn generate_mipmaps(
+        &mut self,
+        image: &Image,
+        initial_layout: ImageLayout,
+        final_layout: ImageLayout,
+        filter: Filter,
+    ) {
+        assert!(self.capabilities.supports_graphics());
+
+        let info = image.info();
+        assert!(
+            info.usage
+                .contains(ImageUsageFlags::TRANSFER_SRC | ImageUsageFlags::TRANSFER_DST),
+            "image must be created with `TRANSFER_SRC` and `TRANSFER_DST` usage"
+        );
+        assert!(
+            info.mip_levels > 1,
+            "image must have more than one mip level"
+        );
+
+        let aspect = info.format.aspect_flags();
+        let layers = 0..info.array_layers;
+        let level_range =
+            |level: u32| ImageSubresourceRange::new(aspect, level..level + 1, layers.clone());
+
+        // Prepare the first level as a source and all other levels as destinations
+        self.image_barriers(
+            PipelineStageFlags::ALL_COMMANDS,
+            PipelineStageFlags::TRANSFER,
+            &[
+                ImageMemoryBarrier {
+                    image,
+                    src_access: AccessFlags::MEMORY_WRITE,
+                    dst_access: AccessFlags::TRANSFER_READ,
+                    old_layout: Some(initial_layout),
+                    new_layout: ImageLayout::TransferSrcOptimal,
+                    family_transfer: None,
+                    subresource_range: level_range(0),
+                },
+                ImageMemoryBarrier {
+                    image,
+                    src_access: AccessFlags::empty(),
+                    dst_access: AccessFlags::TRANSFER_WRITE,
+                    old_layout: None,
+                    new_layout: ImageLayout::TransferDstOptimal,
+                    family_transfer: None,
+                    subresource_range: ImageSubresourceRange::new(
+                        aspect,
+                        1..info.mip_levels,
+                        layers.clone(),
+                    ),
+                },
+            ],
+        );
+
+        let mut extent = match info.extent {
+            ImageExtent::D1 { width } => IVec3::new(width as i32, 1, 1),
+            ImageExtent::D2 { width, height } => IVec3::new(width as i32, height as i32, 1),
+            ImageExtent::D3 {
+                width,
+                height,
+                depth,
+            } => IVec3::new(width as i32, height as i32, depth as i32),
+        };
+
+        for level in 1..info.mip_levels {
+            // NOTE: Non-power-of-two extents are rounded down and clamped at 1
+            let next_extent = (extent / 2).max(IVec3::ONE);
+
+            self.blit_image(
+                image,
+                ImageLayout::TransferSrcOptimal,
+                image,
+                ImageLayout::TransferDstOptimal,
+                &[ImageBlit {
+                    src_subresource: ImageSubresourceLayers::new(aspect, level - 1, layers.clone()),
+                    src_offsets: [IVec3::ZERO, extent],
+                    dst_subresource: ImageSubresourceLayers::new(aspect, level, layers.clone()),
+                    dst_offsets: [IVec3::ZERO, next_extent],
+                }],
+                filter,
+            );
+
+            // Use the generated level as a source for the next one
+            self.image_barriers(
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::TRANSFER,
+                &[ImageMemoryBarrier {
+                    image,
+                    src_access: AccessFlags::TRANSFER_WRITE,
+                    dst_access: AccessFlags::TRANSFER_READ,
+                    old_layout: Some(ImageLayout::TransferDstOptimal),
+                    new_layout: ImageLayout::TransferSrcOptimal,
+                    family_transfer: None,
+                    subresource_range: level_range(level),
+                }],
+            );
+
+            extent = next_extent;
+        }
+
+        self.image_barriers(
+            PipelineStageFlags::TRANSFER,
+            PipelineStageFlags::ALL_COMMANDS,
+            &[ImageMemoryBarrier {
+                image,
+                src_access: AccessFlags::TRANSFER_WRITE,
+                dst_access: AccessFlags::MEMORY_READ,
+                old_layout: Some(ImageLayout::TransferSrcOptimal),
+                new_layout: final_layout,
+                family_transfer: None,
+                subresource_range: ImageSubresourceRange::whole(info),
+            }],
+        );
+    }
+
+    /// Open a labeled region of commands, visible in graphics debuggers.
+    ///
+    /// Does nothing if `VK_EXT_debug_utils` is not enabled.
+    pub fn begin_debug_label(&mut self, name: &str, color: [f32; 4]) {
+        self.command_buffer.begin_debug_label(name, color);
+    }
+
+    /// Close the region opened by the last [`begin_debug_label`].
+    ///
+    /// [`begin_debug_label`]: EncoderCommon::begin_debug_label
+    pub fn end_debug_label(&mut self) {
+        self.command_buffer.end_debug_label();
+    }
+
     /// Dispatch compute work items.
     pub fn dispatch(&mut self, x: u32, y: u32, z: u32) {
         assert!(self.capabilities.supports_compute());
@@ -246,16 +601,74 @@ impl Encoder {
     }
 
     /// Insert an image memory dependency.
+    ///
+    /// Updates the tracked layouts of images. In debug builds also checks
+    /// that the declared old layouts match the tracked ones.
     pub fn image_barriers(
         &mut self,
         src: PipelineStageFlags,
         dst: PipelineStageFlags,
         barriers: &[ImageMemoryBarrier],
     ) {
+        for barrier in barriers {
+            barrier.image.track_transition(
+                &barrier.subresource_range,
+                barrier.old_layout,
+                barrier.new_layout,
+            );
+        }
         self.command_buffer
             .pipeline_barrier(src, dst, None, &[], barriers);
     }
 
+    /// Transition the whole image into the `new_layout`.
+    ///
+    /// See [`transition_image_range`] for details.
+    ///
+    /// [`transition_image_range`]: Self::transition_image_range
+    pub fn transition_image(
+        &mut self,
+        image: &Image,
+        new_layout: ImageLayout,
+        stage: Range<PipelineStageFlags>,
+        access: Range<AccessFlags>,
+    ) {
+        let range = ImageSubresourceRange::whole(image.info());
+        self.transition_image_range(image, range, new_layout, stage, access);
+    }
+
+    /// Transition the subresource range of the image into the `new_layout`.
+    ///
+    /// The old layout is taken from the tracked state of the image, so
+    /// subresources in different layouts are transitioned by separate barriers.
+    /// Contents of subresources with unknown layouts are discarded.
+    ///
+    /// Use [`Image::assume_layout`] if the layout was changed externally.
+    pub fn transition_image_range(
+        &mut self,
+        image: &Image,
+        range: ImageSubresourceRange,
+        new_layout: ImageLayout,
+        stage: Range<PipelineStageFlags>,
+        access: Range<AccessFlags>,
+    ) {
+        let barriers = image
+            .tracked_layout_runs(&range)
+            .into_iter()
+            .map(|(subresource_range, old_layout)| ImageMemoryBarrier {
+                image,
+                src_access: access.start,
+                dst_access: access.end,
+                old_layout,
+                new_layout,
+                family_transfer: None,
+                subresource_range,
+            })
+            .collect::<SmallVec<[_; 1]>>();
+
+        self.image_barriers(stage.start, stage.end, &barriers);
+    }
+
     /// Insert a buffer memory dependency.
     pub fn buffer_barriers(
         &mut self,
@@ -266,6 +679,19 @@ impl Encoder {
         self.command_buffer
             .pipeline_barrier(src, dst, None, barriers, &[]);
     }
+
+    /// Reset queries in the pool to the unavailable state.
+    pub fn reset_query_pool(&mut self, pool: &QueryPool, queries: Range<u32>) {
+        assert!(queries.start <= queries.end && queries.end <= pool.info().count);
+        self.command_buffer.reset_query_pool(pool, queries);
+    }
+
+    /// Write a device timestamp into the query once all previous commands
+    /// have completed the specified pipeline stage.
+    pub fn write_timestamp(&mut self, stage: PipelineStageFlags, pool: &QueryPool, index: u32) {
+        assert!(index < pool.info().count);
+        self.command_buffer.write_timestamp(stage, pool, index);
+    }
 }
 
 impl std::fmt::Debug for Encoder {
@@ -327,6 +753,9 @@ impl EncoderCommon {
     /// Bind vertex buffers to a command buffer starting from the `first_binding`.
     pub fn bind_vertex_buffers(&mut self, first_binding: u32, buffers: &[(&Buffer, usize)]) {
         assert!(self.capabilities.supports_graphics());
+        for (buffer, _) in buffers {
+            check_buffer_usage(buffer.info(), BufferUsage::VERTEX, "a vertex buffer");
+        }
         self.command_buffer
             .bind_vertex_buffers(first_binding, buffers);
     }
@@ -334,6 +763,7 @@ impl EncoderCommon {
     /// Bind an index buffer to a command buffer.
     pub fn bind_index_buffer(&mut self, buffer: &Buffer, offset: usize, index_type: IndexType) {
         assert!(self.capabilities.supports_graphics());
+        check_buffer_usage(buffer.info(), BufferUsage::INDEX, "an index buffer");
         self.command_buffer
             .bind_index_buffer(buffer, offset, index_type);
     }
@@ -374,6 +804,35 @@ impl EncoderCommon {
         )
     }
 
+    /// Pushes descriptor updates into a command buffer.
+    ///
+    /// The descriptor set layout at index `set` must be created with
+    /// the [`DescriptorSetLayoutFlags::PUSH_DESCRIPTOR`] flag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`DeviceFeature::PushDescriptor`] was not requested.
+    ///
+    /// [`DescriptorSetLayoutFlags::PUSH_DESCRIPTOR`]: crate::DescriptorSetLayoutFlags::PUSH_DESCRIPTOR
+    /// [`DeviceFeature::PushDescriptor`]: crate::DeviceFeature::PushDescriptor
+    pub fn push_descriptor_set(
+        &mut self,
+        bind_point: PipelineBindPoint,
+        layout: &PipelineLayout,
+        set: u32,
+        writes: &[DescriptorSetWrite<'_>],
+    ) {
+        match bind_point {
+            PipelineBindPoint::Graphics => assert!(self.capabilities.supports_graphics()),
+            PipelineBindPoint::Compute => assert!(self.capabilities.supports_compute()),
+        }
+        for write in writes {
+            check_descriptor_usage(write.data);
+        }
+        self.command_buffer
+            .push_descriptor_set(bind_point, layout, set, writes);
+    }
+
     /// Update the values of push constants.
     pub fn push_constants<T>(
         &mut self,
@@ -423,6 +882,98 @@ impl<'a, 'b> RenderPassEncoder<'a, 'b> {
             .command_buffer
             .draw_indexed(indices, vertex_offset, instances);
     }
+
+    /// Draw primitives with parameters read from a buffer.
+    ///
+    /// The buffer must contain `draw_count` tightly packed or `stride`-separated
+    /// [`DrawIndirectCommand`]s starting at `offset`.
+    pub fn draw_indirect(&mut self, buffer: &Buffer, offset: usize, draw_count: u32, stride: u32) {
+        validate_indirect_buffer::<DrawIndirectCommand>(buffer, offset, draw_count, stride);
+        self.inner
+            .command_buffer
+            .draw_indirect(buffer, offset, draw_count, stride);
+    }
+
+    /// Draw indexed primitives with parameters read from a buffer.
+    ///
+    /// The buffer must contain `draw_count` tightly packed or `stride`-separated
+    /// [`DrawIndexedIndirectCommand`]s starting at `offset`.
+    pub fn draw_indexed_indirect(
+        &mut self,
+        buffer: &Buffer,
+        offset: usize,
+        draw_count: u32,
+        stride: u32,
+    ) {
+        validate_indirect_buffer::<DrawIndexedIndirectCommand>(buffer, offset, draw_count, stride);
+        self.inner
+            .command_buffer
+            .draw_indexed_indirect(buffer, offset, draw_count, stride);
+    }
+
+    /// Draw indexed primitives with parameters and the draw count read from buffers.
+    ///
+    /// The draw count is a `u32` at `count_offset` in `count_buffer`, clamped
+    /// to `max_draw_count`. The buffer must contain `max_draw_count` tightly
+    /// packed or `stride`-separated [`DrawIndexedIndirectCommand`]s starting at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`DeviceFeature::DrawIndirectCount`] was not requested.
+    ///
+    /// [`DeviceFeature::DrawIndirectCount`]: crate::DeviceFeature::DrawIndirectCount
+    pub fn draw_indexed_indirect_count(
+        &mut self,
+        buffer: &Buffer,
+        offset: usize,
+        count_buffer: &Buffer,
+        count_offset: usize,
+        max_draw_count: u32,
+        stride: u32,
+    ) {
+        validate_indirect_buffer::<DrawIndexedIndirectCommand>(
+            buffer,
+            offset,
+            max_draw_count,
+            stride,
+        );
+        validate_indirect_buffer::<u32>(count_buffer, count_offset, 1, 0);
+        self.inner.command_buffer.draw_indexed_indirect_count(
+            buffer,
+            offset,
+            count_buffer,
+            count_offset,
+            max_draw_count,
+            stride,
+        );
+    }
+}
+
+#[track_caller]
+fn validate_indirect_buffer<T>(buffer: &Buffer, offset: usize, draw_count: u32, stride: u32) {
+    let command_size = std::mem::size_of::<T>();
+
+    assert!(
+        buffer.info().usage.contains(BufferUsage::INDIRECT),
+        "buffer must be created with `INDIRECT` usage"
+    );
+    assert!(offset % 4 == 0, "unaligned indirect buffer offset");
+    if draw_count > 1 {
+        assert!(stride % 4 == 0, "unaligned indirect buffer stride");
+        assert!(
+            stride as usize >= command_size,
+            "indirect buffer stride is less than the command size"
+        );
+    }
+
+    let required_size = match draw_count {
+        0 => 0,
+        n => (n as usize - 1) * stride as usize + command_size,
+    };
+    assert!(
+        offset + required_size <= buffer.info().size,
+        "indirect buffer is too small for {draw_count} draws"
+    );
 }
 
 impl std::ops::Deref for RenderPassEncoder<'_, '_> {
@@ -447,6 +998,13 @@ impl Drop for RenderPassEncoder<'_, '_> {
     }
 }
 
+/// Returns the size in bytes of tightly packed data of the `subresource`.
+fn subresource_data_size(info: &ImageInfo, subresource: &ImageSubresourceLayers) -> usize {
+    let extent = info.mip_extent(subresource.mip_level);
+    let block = info.format.description().block;
+    block.data_size(extent.truncate()) * extent.z as usize * subresource.array_layer_count as usize
+}
+
 struct EncoderDropGuard;
 
 impl Drop for EncoderDropGuard {
@@ -454,3 +1012,51 @@ impl Drop for EncoderDropGuard {
         tracing::error!("encoder must be submitted or discarded before dropping");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec2, UVec3};
+
+    use super::*;
+    use crate::resources::Format;
+    use crate::util::fixtures::image_info;
+
+    fn mipped_image_info(format: Format, extent: ImageExtent, mip_levels: u32) -> ImageInfo {
+        ImageInfo {
+            mip_levels,
+            array_layers: 2,
+            ..image_info(format, extent)
+        }
+    }
+
+    #[test]
+    fn odd_sized_rgba8_mips() {
+        let info = mipped_image_info(Format::RGBA8Unorm, UVec2::new(13, 7).into(), 4);
+        let size = |mip_level, layers| {
+            subresource_data_size(&info, &ImageSubresourceLayers::color(mip_level, layers))
+        };
+
+        assert_eq!(size(0, 0..1), 13 * 7 * 4);
+        assert_eq!(size(0, 0..2), 13 * 7 * 4 * 2);
+        assert_eq!(size(1, 1..2), 6 * 3 * 4);
+        assert_eq!(size(2, 0..1), 3 * 4);
+        // Dimensions are never smaller than a single texel
+        assert_eq!(size(3, 0..1), 4);
+    }
+
+    #[test]
+    fn odd_sized_single_channel() {
+        let info = mipped_image_info(Format::R8Unorm, UVec2::new(5, 3).into(), 3);
+        let size = |mip_level| {
+            subresource_data_size(&info, &ImageSubresourceLayers::color(mip_level, 0..1))
+        };
+
+        assert_eq!(size(0), 15);
+        assert_eq!(size(1), 2);
+        assert_eq!(size(2), 1);
+
+        let info = mipped_image_info(Format::R8Unorm, UVec3::new(5, 3, 9).into(), 2);
+        let size = subresource_data_size(&info, &ImageSubresourceLayers::color(1, 0..1));
+        assert_eq!(size, 2 * 4);
+    }
+}
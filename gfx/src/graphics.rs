@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 use std::collections::HashSet;
-use std::ffi::{c_void, CStr, CString};
+use std::ffi::{c_void, CString};
 use std::sync::Mutex;
 
 use once_cell::sync::OnceCell;
@@ -9,8 +9,9 @@ use vulkanalia::prelude::v1_0::*;
 use vulkanalia::vk::ExtDebugUtilsExtension as _;
 use vulkanalia::Instance;
 
-use crate::physical::{PhysicalDevice, PhysicalDeviceSelector};
+use crate::physical::{PhysicalDevice, PhysicalDeviceInfo, PhysicalDeviceSelector};
 use crate::types::OutOfDeviceMemory;
+use crate::validation::{ValidationMessage, ValidationMessages, ValidationSeverity};
 
 /// Graphics instance configuration.
 #[derive(Debug, Clone)]
@@ -18,6 +19,24 @@ pub struct InstanceConfig {
     pub app_name: Cow<'static, str>,
     pub app_version: (u32, u32, u32),
     pub validation_layer_enabled: bool,
+    /// Loads `VK_EXT_debug_utils` for object names and debug labels.
+    ///
+    /// NOTE: The extension is always loaded with the validation layer.
+    pub debug_utils_enabled: bool,
+    /// Validation messages with lower severity are ignored.
+    ///
+    /// Can be changed at runtime with [`Graphics::set_validation_min_severity`].
+    pub validation_min_severity: ValidationSeverity,
+    /// Identifiers of validation messages which are ignored.
+    pub suppressed_validation_messages: Vec<i32>,
+    /// Number of the last validation messages kept in memory.
+    ///
+    /// See [`Graphics::recent_validation_messages`].
+    pub validation_messages_capacity: usize,
+}
+
+impl InstanceConfig {
+    pub const DEFAULT_VALIDATION_MESSAGES_CAPACITY: usize = 64;
 }
 
 /// Graphics instance.
@@ -26,6 +45,10 @@ pub struct Graphics {
     api_version: u32,
     config: InstanceConfig,
     debug_utils_messenger: vk::DebugUtilsMessengerEXT,
+    debug_utils_enabled: bool,
+    validation_enabled: bool,
+    // NOTE: Boxed to be referenced by the debug messenger
+    validation_messages: Box<ValidationMessages>,
     _entry: Entry,
 }
 
@@ -145,6 +168,15 @@ impl Graphics {
             }
         };
 
+        let debug_utils_enabled = validation_enabled
+            || config.debug_utils_enabled && {
+                let available = push_ext(&vk::EXT_DEBUG_UTILS_EXTENSION);
+                if !available {
+                    tracing::warn!("`VK_EXT_debug_utils` is not available");
+                }
+                available
+            };
+
         // Add required extensions for creating windows
         #[cfg(target_os = "macos")]
         let flags = if Self::requires_portability(api_version) {
@@ -193,7 +225,14 @@ impl Graphics {
             .enabled_layer_names(&layers)
             .flags(flags);
 
-        let mut debug_info = make_debug_callback_info();
+        let validation_messages = Box::new(ValidationMessages::new(
+            config.validation_min_severity,
+            &config.suppressed_validation_messages,
+            config.validation_messages_capacity,
+        ));
+        let messages_ptr = &*validation_messages as *const ValidationMessages;
+
+        let mut debug_info = make_debug_callback_info(messages_ptr);
         if validation_enabled {
             instance_info = instance_info.push_next(&mut debug_info);
         }
@@ -216,7 +255,7 @@ impl Graphics {
             })?;
 
         let debug_utils_messenger = if validation_enabled {
-            let debug_info = make_debug_callback_info();
+            let debug_info = make_debug_callback_info(messages_ptr);
             match instance.create_debug_utils_messenger_ext(&debug_info, None) {
                 Ok(handle) => handle,
                 Err(e) => match e {
@@ -233,6 +272,9 @@ impl Graphics {
             api_version,
             config,
             debug_utils_messenger,
+            debug_utils_enabled,
+            validation_enabled,
+            validation_messages,
             _entry: entry,
         })
     }
@@ -242,6 +284,53 @@ impl Graphics {
         &self.config
     }
 
+    /// Returns `true` if `VK_EXT_debug_utils` is loaded.
+    pub fn debug_utils_enabled(&self) -> bool {
+        self.debug_utils_enabled
+    }
+
+    /// Returns `true` if the validation layer is loaded.
+    pub fn validation_enabled(&self) -> bool {
+        self.validation_enabled
+    }
+
+    /// Returns the minimal severity of reported validation messages.
+    pub fn validation_min_severity(&self) -> ValidationSeverity {
+        self.validation_messages.min_severity()
+    }
+
+    /// Changes the minimal severity of reported validation messages.
+    pub fn set_validation_min_severity(&self, severity: ValidationSeverity) {
+        self.validation_messages.set_min_severity(severity);
+    }
+
+    /// Ignores (or reports again) validation messages with the specified identifier.
+    ///
+    /// See [`ValidationMessage::id`].
+    pub fn set_validation_message_suppressed(&self, id: i32, suppressed: bool) {
+        self.validation_messages.set_suppressed(id, suppressed);
+    }
+
+    /// Returns the last reported validation messages, oldest first.
+    ///
+    /// NOTE: Only [`InstanceConfig::validation_messages_capacity`] messages are kept.
+    pub fn recent_validation_messages(&self) -> Vec<ValidationMessage> {
+        self.validation_messages.recent()
+    }
+
+    /// Forgets all stored validation messages.
+    pub fn clear_validation_messages(&self) {
+        self.validation_messages.clear();
+    }
+
+    /// Returns the total number of reported validation errors.
+    ///
+    /// Unlike the stored messages, errors are never evicted, so comparing the
+    /// count before and after a frame tells whether the frame produced any.
+    pub fn validation_error_count(&self) -> u64 {
+        self.validation_messages.error_count()
+    }
+
     /// Returns the [`PhysicalDevice`]s available on the system.
     pub fn get_physical_devices(&self) -> Result<PhysicalDeviceSelector, OutOfDeviceMemory> {
         let devices =
@@ -259,6 +348,20 @@ impl Graphics {
         ))
     }
 
+    /// Returns a summary of all physical devices available on the system.
+    ///
+    /// Devices are listed in the same order as in [`get_physical_devices`].
+    ///
+    /// [`get_physical_devices`]: Self::get_physical_devices
+    pub fn enumerate_devices(&self) -> Result<Vec<PhysicalDeviceInfo>, OutOfDeviceMemory> {
+        Ok(self
+            .get_physical_devices()?
+            .physical_devices()
+            .iter()
+            .map(PhysicalDevice::info)
+            .collect())
+    }
+
     /// Returns the underlying Vulkan instance.
     pub fn instance(&self) -> &Instance {
         &self.instance
@@ -304,36 +407,31 @@ impl Drop for Graphics {
     }
 }
 
-fn make_debug_callback_info() -> vk::DebugUtilsMessengerCreateInfoEXTBuilder<'static> {
-    vk::DebugUtilsMessengerCreateInfoEXT::builder()
-        .message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
-        .message_type(
-            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
-                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
-        )
-        .user_callback(Some(debug_callback))
+/// NOTE: `messages` must outlive the messenger.
+fn make_debug_callback_info(
+    messages: *const ValidationMessages,
+) -> vk::DebugUtilsMessengerCreateInfoEXT {
+    vk::DebugUtilsMessengerCreateInfoEXT {
+        user_data: messages as *mut c_void,
+        ..vk::DebugUtilsMessengerCreateInfoEXT::builder()
+            .message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
+            .message_type(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            )
+            .user_callback(Some(debug_callback))
+            .build()
+    }
 }
 
 unsafe extern "system" fn debug_callback(
     severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     ty: vk::DebugUtilsMessageTypeFlagsEXT,
     data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _: *mut c_void,
+    user_data: *mut c_void,
 ) -> vk::Bool32 {
-    let message = CStr::from_ptr((*data).message).to_string_lossy();
-
-    // TODO: optimize
-    if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
-        tracing::error!(target: "validation", ?ty, "{message}");
-    } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
-        tracing::warn!(target: "validation", ?ty, "{message}");
-    } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::INFO {
-        tracing::debug!(target: "validation", ?ty, "{message}");
-    } else {
-        tracing::trace!(target: "validation", ?ty, "{message}");
-    };
-
+    crate::validation::report_message(severity, ty, &*data, user_data.cast());
     vk::FALSE
 }
 
@@ -342,6 +440,10 @@ static INIT_CONFIG: Mutex<InstanceConfig> = Mutex::new(InstanceConfig {
     app_name: Cow::Borrowed("app"),
     app_version: (0, 0, 1),
     validation_layer_enabled: true,
+    debug_utils_enabled: false,
+    validation_min_severity: ValidationSeverity::Verbose,
+    suppressed_validation_messages: Vec::new(),
+    validation_messages_capacity: InstanceConfig::DEFAULT_VALIDATION_MESSAGES_CAPACITY,
 });
 
 /// An error returned when initializing the graphics instance fails.
@@ -1,4 +1,6 @@
-use glam::{Affine2, Affine3A, Mat2, Mat3, Mat4, Vec2, Vec3, Vec4};
+use glam::{
+    Affine2, Affine3A, DMat2, DMat3, DMat4, DVec2, DVec3, DVec4, Mat2, Mat3, Mat4, Vec2, Vec3, Vec4,
+};
 
 use super::{AsStd140, AsStd430};
 
@@ -26,6 +28,7 @@ impl AsStd140 for Mat3 {
     fn write_as_std140(&self, dst: &mut Self::Output) {
         dst[0].value = self.x_axis;
         dst[1].value = self.y_axis;
+        dst[2].value = self.z_axis;
     }
 }
 
@@ -61,6 +64,66 @@ impl AsStd430 for Mat4 {
     }
 }
 
+impl AsStd140 for DMat2 {
+    type Output = <[DVec2; 2] as AsStd140>::Output;
+
+    fn write_as_std140(&self, dst: &mut Self::Output) {
+        dst[0].value = self.x_axis;
+        dst[1].value = self.y_axis;
+    }
+}
+
+impl AsStd430 for DMat2 {
+    type Output = <[DVec2; 2] as AsStd430>::Output;
+
+    fn write_as_std430(&self, dst: &mut Self::Output) {
+        dst[0].value = self.x_axis;
+        dst[1].value = self.y_axis;
+    }
+}
+
+impl AsStd140 for DMat3 {
+    type Output = <[DVec3; 3] as AsStd140>::Output;
+
+    fn write_as_std140(&self, dst: &mut Self::Output) {
+        dst[0].value = self.x_axis;
+        dst[1].value = self.y_axis;
+        dst[2].value = self.z_axis;
+    }
+}
+
+impl AsStd430 for DMat3 {
+    type Output = <[DVec3; 3] as AsStd430>::Output;
+
+    fn write_as_std430(&self, dst: &mut Self::Output) {
+        dst[0].value = self.x_axis;
+        dst[1].value = self.y_axis;
+        dst[2].value = self.z_axis;
+    }
+}
+
+impl AsStd140 for DMat4 {
+    type Output = <[DVec4; 4] as AsStd140>::Output;
+
+    fn write_as_std140(&self, dst: &mut Self::Output) {
+        dst[0].value = self.x_axis;
+        dst[1].value = self.y_axis;
+        dst[2].value = self.z_axis;
+        dst[3].value = self.w_axis;
+    }
+}
+
+impl AsStd430 for DMat4 {
+    type Output = <[DVec4; 4] as AsStd430>::Output;
+
+    fn write_as_std430(&self, dst: &mut Self::Output) {
+        dst[0].value = self.x_axis;
+        dst[1].value = self.y_axis;
+        dst[2].value = self.z_axis;
+        dst[3].value = self.w_axis;
+    }
+}
+
 impl AsStd140 for Affine2 {
     type Output = <[Vec2; 3] as AsStd140>::Output;
 
@@ -1,5 +1,7 @@
 use bytemuck::{Pod, Zeroable};
 
+pub use self::primitive::F16;
+
 mod array;
 mod matrix;
 mod primitive;
@@ -140,6 +142,78 @@ mod tests {
         field4: glam::Vec2,
     }
 
+    #[derive(gfx::AsStd140, gfx::AsStd430)]
+    struct TestLight {
+        position: glam::Vec3,
+        intensity: f32,
+        color: glam::Vec3,
+    }
+
+    #[derive(gfx::AsStd140, gfx::AsStd430)]
+    enum TestMode {
+        Disabled,
+        Enabled = 4,
+    }
+
+    #[derive(gfx::AsStd140, gfx::AsStd430)]
+    struct TestCompactStruct {
+        flags: u8,
+        id: u16,
+        weight: F16,
+        world_offset: glam::DVec3,
+        ticks: u64,
+    }
+
+    #[derive(gfx::AsStd140, gfx::AsStd430)]
+    struct TestNestedStruct {
+        exposure: f32,
+        weights: [f32; 2],
+        light: TestLight,
+        lights: [TestLight; 2],
+        scale: f32,
+        mode: TestMode,
+    }
+
+    /// Index of an item in a GPU buffer of `T`.
+    #[derive(gfx::AsStd140, gfx::AsStd430)]
+    struct TestTypedIndex<T>
+    where
+        T: Send + 'static,
+    {
+        index: u32,
+        _marker: std::marker::PhantomData<T>,
+    }
+
+    #[derive(gfx::AsStd140, gfx::AsStd430)]
+    struct TestGenericUser {
+        scale: f32,
+        light: TestTypedIndex<TestLight>,
+        mode: TestTypedIndex<TestMode>,
+    }
+
+    impl TestNestedStruct {
+        fn new() -> Self {
+            let light = || TestLight {
+                position: glam::Vec3::ZERO,
+                intensity: 0.0,
+                color: glam::Vec3::ZERO,
+            };
+
+            Self {
+                exposure: 0.0,
+                weights: [0.0; 2],
+                light: light(),
+                lights: [light(), light()],
+                scale: 0.0,
+                mode: TestMode::Enabled,
+            }
+        }
+    }
+
+    fn offset_of<T, F>(base: &T, field: &F) -> usize {
+        field as *const F as usize - base as *const T as usize
+    }
+
     #[test]
     fn correct_std140_repr() {
         type Repr<T> = <T as AsStd140>::Output;
@@ -202,6 +276,37 @@ mod tests {
         assert_eq!(<Repr::<[glam::DVec4; 4]> as Std140>::ALIGN_MASK, 0b11111);
         assert_eq!(std::mem::size_of::<Repr<[glam::DVec4; 4]>>(), 128);
 
+        // small and wide scalars
+        assert_eq!(<Repr<u8> as Std140>::ALIGN_MASK, 0);
+        assert_eq!(std::mem::size_of::<Repr<u8>>(), 1);
+
+        assert_eq!(<Repr<u16> as Std140>::ALIGN_MASK, 0b1);
+        assert_eq!(std::mem::size_of::<Repr<u16>>(), 2);
+
+        assert_eq!(<Repr<F16> as Std140>::ALIGN_MASK, 0b1);
+        assert_eq!(std::mem::size_of::<Repr<F16>>(), 2);
+
+        assert_eq!(<Repr<u64> as Std140>::ALIGN_MASK, 0b111);
+        assert_eq!(std::mem::size_of::<Repr<u64>>(), 8);
+
+        assert_eq!(<Repr<i64> as Std140>::ALIGN_MASK, 0b111);
+        assert_eq!(std::mem::size_of::<Repr<i64>>(), 8);
+
+        assert_eq!(<Repr<f64> as Std140>::ALIGN_MASK, 0b111);
+        assert_eq!(std::mem::size_of::<Repr<f64>>(), 8);
+
+        assert_eq!(Repr::<[u8; 4]>::ALIGN_MASK, 0b1111);
+        assert_eq!(std::mem::size_of::<Repr<[u8; 4]>>(), 64); // u8 -> pad to 16 bytes
+
+        assert_eq!(Repr::<[F16; 4]>::ALIGN_MASK, 0b1111);
+        assert_eq!(std::mem::size_of::<Repr<[F16; 4]>>(), 64); // f16 -> pad to 16 bytes
+
+        assert_eq!(Repr::<[f64; 4]>::ALIGN_MASK, 0b1111);
+        assert_eq!(std::mem::size_of::<Repr<[f64; 4]>>(), 64); // f64 -> pad to 16 bytes
+
+        assert_eq!(<Repr::<[glam::DVec3; 4]> as Std140>::ALIGN_MASK, 0b11111);
+        assert_eq!(std::mem::size_of::<Repr<[glam::DVec3; 4]>>(), 128); // DVec3 -> pad to 32 bytes
+
         // matrix stuff
         assert_eq!(<Repr<glam::Mat2> as Std140>::ALIGN_MASK, 0b1111);
         assert_eq!(std::mem::size_of::<Repr<glam::Mat2>>(), 32); // Vec2 -> pad to 16 bytes
@@ -218,6 +323,15 @@ mod tests {
         assert_eq!(<Repr<glam::Affine3A> as Std140>::ALIGN_MASK, 0b1111);
         assert_eq!(std::mem::size_of::<Repr<glam::Affine3A>>(), 64); // Vec3 -> pad to 16 bytes
 
+        assert_eq!(<Repr<glam::DMat2> as Std140>::ALIGN_MASK, 0b1111);
+        assert_eq!(std::mem::size_of::<Repr<glam::DMat2>>(), 32);
+
+        assert_eq!(<Repr<glam::DMat3> as Std140>::ALIGN_MASK, 0b11111);
+        assert_eq!(std::mem::size_of::<Repr<glam::DMat3>>(), 96); // DVec3 -> pad to 32 bytes
+
+        assert_eq!(<Repr<glam::DMat4> as Std140>::ALIGN_MASK, 0b11111);
+        assert_eq!(std::mem::size_of::<Repr<glam::DMat4>>(), 128);
+
         // derived struct
 
         // \ field  | size | align | offset
@@ -307,6 +421,37 @@ mod tests {
         assert_eq!(<Repr::<[glam::DVec4; 4]> as Std140>::ALIGN_MASK, 0b11111);
         assert_eq!(std::mem::size_of::<Repr<[glam::DVec4; 4]>>(), 128);
 
+        // small and wide scalars
+        assert_eq!(<Repr<u8> as Std430>::ALIGN_MASK, 0);
+        assert_eq!(std::mem::size_of::<Repr<u8>>(), 1);
+
+        assert_eq!(<Repr<u16> as Std430>::ALIGN_MASK, 0b1);
+        assert_eq!(std::mem::size_of::<Repr<u16>>(), 2);
+
+        assert_eq!(<Repr<F16> as Std430>::ALIGN_MASK, 0b1);
+        assert_eq!(std::mem::size_of::<Repr<F16>>(), 2);
+
+        assert_eq!(<Repr<u64> as Std430>::ALIGN_MASK, 0b111);
+        assert_eq!(std::mem::size_of::<Repr<u64>>(), 8);
+
+        assert_eq!(<Repr<i64> as Std430>::ALIGN_MASK, 0b111);
+        assert_eq!(std::mem::size_of::<Repr<i64>>(), 8);
+
+        assert_eq!(<Repr<f64> as Std430>::ALIGN_MASK, 0b111);
+        assert_eq!(std::mem::size_of::<Repr<f64>>(), 8);
+
+        assert_eq!(Repr::<[u8; 4]>::ALIGN_MASK, 0);
+        assert_eq!(std::mem::size_of::<Repr<[u8; 4]>>(), 4);
+
+        assert_eq!(Repr::<[F16; 4]>::ALIGN_MASK, 0b1);
+        assert_eq!(std::mem::size_of::<Repr<[F16; 4]>>(), 8);
+
+        assert_eq!(Repr::<[f64; 4]>::ALIGN_MASK, 0b111);
+        assert_eq!(std::mem::size_of::<Repr<[f64; 4]>>(), 32);
+
+        assert_eq!(<Repr::<[glam::DVec3; 4]> as Std430>::ALIGN_MASK, 0b11111);
+        assert_eq!(std::mem::size_of::<Repr<[glam::DVec3; 4]>>(), 128); // DVec3 -> pad to 32 bytes
+
         // matrix stuff
         assert_eq!(<Repr<glam::Mat2> as Std430>::ALIGN_MASK, 0b111);
         assert_eq!(std::mem::size_of::<Repr<glam::Mat2>>(), 16);
@@ -323,6 +468,15 @@ mod tests {
         assert_eq!(<Repr<glam::Affine3A> as Std430>::ALIGN_MASK, 0b1111);
         assert_eq!(std::mem::size_of::<Repr<glam::Affine3A>>(), 64); // Vec3 -> pad to 16 bytes
 
+        assert_eq!(<Repr<glam::DMat2> as Std430>::ALIGN_MASK, 0b1111);
+        assert_eq!(std::mem::size_of::<Repr<glam::DMat2>>(), 32);
+
+        assert_eq!(<Repr<glam::DMat3> as Std430>::ALIGN_MASK, 0b11111);
+        assert_eq!(std::mem::size_of::<Repr<glam::DMat3>>(), 96); // DVec3 -> pad to 32 bytes
+
+        assert_eq!(<Repr<glam::DMat4> as Std430>::ALIGN_MASK, 0b11111);
+        assert_eq!(std::mem::size_of::<Repr<glam::DMat4>>(), 128);
+
         // derived struct
 
         // \ field  | size | align | offset
@@ -349,4 +503,234 @@ mod tests {
         assert_eq!(std::mem::size_of_val(&test._pad3), 0);
         assert_eq!(std::mem::size_of::<Repr<TestShaderStruct>>(), 24);
     }
+
+    #[test]
+    fn correct_nested_std140_repr() {
+        type Repr<T> = <T as AsStd140>::Output;
+
+        // \ field     | size | align | offset
+        // 0 position  | 12   | 16    | 0
+        // 1 pad0      | 0    | 4     | 12
+        // 2 intensity | 4    | 4     | 12
+        // 3 pad1      | 0    | 16    | 16
+        // 4 color     | 12   | 16    | 16
+        // 5 pad2      | 4    | 16    | 28
+        // total: 32
+        assert_eq!(<Repr<TestLight> as Std140>::ALIGN_MASK, 0b1111);
+        assert_eq!(std::mem::size_of::<Repr<TestLight>>(), 32);
+
+        assert_eq!(<Repr<[TestLight; 2]> as Std140>::ALIGN_MASK, 0b1111);
+        assert_eq!(std::mem::size_of::<Repr<[TestLight; 2]>>(), 64);
+
+        assert_eq!(<Repr<TestMode> as Std140>::ALIGN_MASK, 0b11);
+        assert_eq!(TestMode::Disabled.as_std140(), 0);
+        assert_eq!(TestMode::Enabled.as_std140(), 4);
+
+        // \ field    | size | align | offset
+        // 0  exposure | 4    | 4     | 0
+        // 1  pad0     | 12   | 16    | 4
+        // 2  weights  | 32   | 16    | 16
+        // 3  pad1     | 0    | 16    | 48
+        // 4  light    | 32   | 16    | 48
+        // 5  pad2     | 0    | 16    | 80
+        // 6  lights   | 64   | 16    | 80
+        // 7  pad3     | 0    | 4     | 144
+        // 8  scale    | 4    | 4     | 144
+        // 9  pad4     | 0    | 4     | 148
+        // 10 mode     | 4    | 4     | 148
+        // 11 pad5     | 8    | 16    | 152
+        // total: 160
+        assert_eq!(<Repr<TestNestedStruct> as Std140>::ALIGN_MASK, 0b1111);
+        let test = TestNestedStruct::new().as_std140();
+        assert_eq!(offset_of(&test, &test.exposure), 0);
+        assert_eq!(offset_of(&test, &test.weights), 16);
+        assert_eq!(offset_of(&test, &test.light), 48);
+        assert_eq!(offset_of(&test, &test.lights), 80);
+        assert_eq!(offset_of(&test, &test.lights[1].value), 112);
+        assert_eq!(offset_of(&test, &test.lights[1].value.color), 128);
+        assert_eq!(offset_of(&test, &test.scale), 144);
+        assert_eq!(offset_of(&test, &test.mode), 148);
+        assert_eq!(std::mem::size_of_val(&test._pad0), 12);
+        assert_eq!(std::mem::size_of_val(&test._pad5), 8);
+        assert_eq!(std::mem::size_of::<Repr<TestNestedStruct>>(), 160);
+        assert_eq!(test.mode, 4);
+    }
+
+    #[test]
+    fn correct_nested_std430_repr() {
+        type Repr<T> = <T as AsStd430>::Output;
+
+        // \ field     | size | align | offset
+        // 0 position  | 12   | 16    | 0
+        // 1 pad0      | 0    | 4     | 12
+        // 2 intensity | 4    | 4     | 12
+        // 3 pad1      | 0    | 16    | 16
+        // 4 color     | 12   | 16    | 16
+        // 5 pad2      | 4    | 16    | 28
+        // total: 32
+        assert_eq!(<Repr<TestLight> as Std430>::ALIGN_MASK, 0b1111);
+        assert_eq!(std::mem::size_of::<Repr<TestLight>>(), 32);
+
+        assert_eq!(<Repr<[TestLight; 2]> as Std430>::ALIGN_MASK, 0b1111);
+        assert_eq!(std::mem::size_of::<Repr<[TestLight; 2]>>(), 64);
+
+        assert_eq!(<Repr<TestMode> as Std430>::ALIGN_MASK, 0b11);
+        assert_eq!(TestMode::Disabled.as_std430(), 0);
+        assert_eq!(TestMode::Enabled.as_std430(), 4);
+
+        // \ field    | size | align | offset
+        // 0  exposure | 4    | 4     | 0
+        // 1  pad0     | 0    | 4     | 4
+        // 2  weights  | 8    | 4     | 4
+        // 3  pad1     | 4    | 16    | 12
+        // 4  light    | 32   | 16    | 16
+        // 5  pad2     | 0    | 16    | 48
+        // 6  lights   | 64   | 16    | 48
+        // 7  pad3     | 0    | 4     | 112
+        // 8  scale    | 4    | 4     | 112
+        // 9  pad4     | 0    | 4     | 116
+        // 10 mode     | 4    | 4     | 116
+        // 11 pad5     | 8    | 16    | 120
+        // total: 128
+        assert_eq!(<Repr<TestNestedStruct> as Std430>::ALIGN_MASK, 0b1111);
+        let test = TestNestedStruct::new().as_std430();
+        assert_eq!(offset_of(&test, &test.exposure), 0);
+        assert_eq!(offset_of(&test, &test.weights), 4);
+        assert_eq!(offset_of(&test, &test.light), 16);
+        assert_eq!(offset_of(&test, &test.lights), 48);
+        assert_eq!(offset_of(&test, &test.lights[1].value), 80);
+        assert_eq!(offset_of(&test, &test.lights[1].value.color), 96);
+        assert_eq!(offset_of(&test, &test.scale), 112);
+        assert_eq!(offset_of(&test, &test.mode), 116);
+        assert_eq!(std::mem::size_of_val(&test._pad1), 4);
+        assert_eq!(std::mem::size_of_val(&test._pad5), 8);
+        assert_eq!(std::mem::size_of::<Repr<TestNestedStruct>>(), 128);
+        assert_eq!(test.mode, 4);
+    }
+
+    #[test]
+    fn correct_compact_std140_repr() {
+        type Repr<T> = <T as AsStd140>::Output;
+
+        // \ field        | size | align | offset
+        // 0 flags        | 1    | 1     | 0
+        // 1 pad0         | 1    | 2     | 1
+        // 2 id           | 2    | 2     | 2
+        // 3 pad1         | 0    | 2     | 4
+        // 4 weight       | 2    | 2     | 4
+        // 5 pad2         | 26   | 32    | 6
+        // 6 world_offset | 24   | 32    | 32
+        // 7 pad3         | 0    | 8     | 56
+        // 8 ticks        | 8    | 8     | 56
+        // 9 pad4         | 0    | 32    | 64
+        // total: 64
+        assert_eq!(<Repr<TestCompactStruct> as Std140>::ALIGN_MASK, 0b11111);
+        let test = TestCompactStruct {
+            flags: 1,
+            id: 2,
+            weight: F16::ONE,
+            world_offset: glam::DVec3::ONE,
+            ticks: 3,
+        }
+        .as_std140();
+        assert_eq!(offset_of(&test, &test.id), 2);
+        assert_eq!(offset_of(&test, &test.weight), 4);
+        assert_eq!(offset_of(&test, &test.world_offset), 32);
+        assert_eq!(offset_of(&test, &test.ticks), 56);
+        assert_eq!(std::mem::size_of::<Repr<TestCompactStruct>>(), 64);
+        assert_eq!(test.weight.to_f32(), 1.0);
+    }
+
+    #[test]
+    fn correct_compact_std430_repr() {
+        type Repr<T> = <T as AsStd430>::Output;
+
+        // NOTE: Same as std140, since no field is an array or a struct
+        assert_eq!(<Repr<TestCompactStruct> as Std430>::ALIGN_MASK, 0b11111);
+        let test = TestCompactStruct {
+            flags: 1,
+            id: 2,
+            weight: F16::ONE,
+            world_offset: glam::DVec3::ONE,
+            ticks: 3,
+        }
+        .as_std430();
+        assert_eq!(offset_of(&test, &test.id), 2);
+        assert_eq!(offset_of(&test, &test.weight), 4);
+        assert_eq!(offset_of(&test, &test.world_offset), 32);
+        assert_eq!(offset_of(&test, &test.ticks), 56);
+        assert_eq!(std::mem::size_of::<Repr<TestCompactStruct>>(), 64);
+    }
+
+    #[test]
+    fn generic_struct_repr() {
+        fn index<T: Send + 'static>(index: u32) -> TestTypedIndex<T> {
+            TestTypedIndex {
+                index,
+                _marker: std::marker::PhantomData,
+            }
+        }
+
+        let test = TestGenericUser {
+            scale: 2.0,
+            light: index(3),
+            mode: index(4),
+        };
+
+        // NOTE: `PhantomData` fields are skipped
+        type Repr140<T> = <T as AsStd140>::Output;
+        assert_eq!(
+            <Repr140<TestTypedIndex<TestLight>> as Std140>::ALIGN_MASK,
+            0b1111
+        );
+        assert_eq!(
+            std::mem::size_of::<Repr140<TestTypedIndex<TestLight>>>(),
+            16
+        );
+
+        let std140 = test.as_std140();
+        assert_eq!(std140.scale, 2.0);
+        assert_eq!(offset_of(&std140, &std140.light), 16);
+        assert_eq!(std140.light.index, 3);
+        assert_eq!(offset_of(&std140, &std140.mode), 32);
+        assert_eq!(std140.mode.index, 4);
+        assert_eq!(std::mem::size_of::<Repr140<TestGenericUser>>(), 48);
+
+        // The same struct is generated for all parameters
+        type Repr430<T> = <T as AsStd430>::Output;
+        assert_eq!(
+            <Repr430<TestTypedIndex<TestMode>> as Std430>::ALIGN_MASK,
+            0b11
+        );
+        assert_eq!(std::mem::size_of::<Repr430<TestTypedIndex<TestMode>>>(), 4);
+
+        let std430 = test.as_std430();
+        assert_eq!(offset_of(&std430, &std430.light), 4);
+        assert_eq!(std430.light.index, 3);
+        assert_eq!(offset_of(&std430, &std430.mode), 8);
+        assert_eq!(std430.mode.index, 4);
+        assert_eq!(std::mem::size_of::<Repr430<TestGenericUser>>(), 12);
+    }
+
+    #[test]
+    fn f16_conversion() {
+        assert_eq!(F16::from_f32(0.0), F16::ZERO);
+        assert_eq!(F16::from_f32(1.0), F16::ONE);
+        assert_eq!(F16::from_f32(-2.5).to_f32(), -2.5);
+        assert_eq!(F16::from_f32(65504.0).to_f32(), 65504.0);
+        assert_eq!(F16::from_f32(1e10).to_f32(), f32::INFINITY);
+        assert!(F16::from_f32(f32::NAN).to_f32().is_nan());
+
+        // Subnormals
+        assert_eq!(F16::from_f32(5.960_464_5e-8), F16(1));
+        assert_eq!(F16(1).to_f32(), 5.960_464_5e-8);
+        assert_eq!(F16::from_f32(1e-8), F16::ZERO);
+
+        for bits in 0..=u16::MAX {
+            let value = F16(bits).to_f32();
+            if !value.is_nan() {
+                assert_eq!(F16::from_f32(value), F16(bits));
+            }
+        }
+    }
 }
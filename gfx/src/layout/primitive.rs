@@ -1,4 +1,4 @@
-use bytemuck::Pod;
+use bytemuck::{Pod, Zeroable};
 use glam::{
     DVec2, DVec3, DVec4, IVec2, IVec3, IVec4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec3A, Vec4,
 };
@@ -13,6 +13,103 @@ pub trait PrimitiveShaderType: Pod {
     type ArrayPaddingStd430Type: Padding;
 }
 
+/// A half-precision float (`float16_t` in shaders), stored as its bits.
+///
+/// NOTE: Shaders must enable the `GL_EXT_shader_16bit_storage` extension
+/// to use it in buffers.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct F16(pub u16);
+
+impl F16 {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(0x3c00);
+
+    /// Converts the value rounding to the nearest representable half.
+    ///
+    /// Values out of range become infinities.
+    pub fn from_f32(value: f32) -> Self {
+        let bits = value.to_bits();
+        let sign = (bits >> 16) & 0x8000;
+        let exp = (bits >> 23) & 0xff;
+        let man = bits & 0x007f_ffff;
+
+        // Infinity or NaN (keeping it quiet)
+        if exp == 0xff {
+            let nan_bit = if man == 0 { 0 } else { 0x0200 };
+            return Self((sign | 0x7c00 | nan_bit | (man >> 13)) as u16);
+        }
+
+        let half_exp = exp as i32 - 127 + 15;
+        if half_exp >= 0x1f {
+            return Self((sign | 0x7c00) as u16);
+        }
+
+        // Subnormal or zero
+        if half_exp <= 0 {
+            if 14 - half_exp > 24 {
+                return Self(sign as u16);
+            }
+            let man = man | 0x0080_0000;
+            let shift = (14 - half_exp) as u32;
+            let mut half_man = man >> shift;
+            let round_bit = 1 << (shift - 1);
+            if man & round_bit != 0 && man & (3 * round_bit - 1) != 0 {
+                half_man += 1;
+            }
+            return Self((sign | half_man) as u16);
+        }
+
+        // NOTE: Rounding might carry into the exponent, which is still correct
+        let half = sign | ((half_exp as u32) << 10) | (man >> 13);
+        let round_bit = 0x1000;
+        if man & round_bit != 0 && man & (3 * round_bit - 1) != 0 {
+            Self((half + 1) as u16)
+        } else {
+            Self(half as u16)
+        }
+    }
+
+    pub fn to_f32(self) -> f32 {
+        let sign = ((self.0 & 0x8000) as u32) << 16;
+        let exp = ((self.0 >> 10) & 0x1f) as u32;
+        let man = (self.0 & 0x03ff) as u32;
+
+        let bits = match (exp, man) {
+            (0, 0) => sign,
+            // Subnormal, normalize the mantissa
+            (0, man) => {
+                let shift = man.leading_zeros() - 21;
+                sign | ((127 - 15 + 1 - shift) << 23) | ((man << shift) & 0x03ff) << 13
+            }
+            (0x1f, 0) => sign | 0x7f80_0000,
+            (0x1f, man) => sign | 0x7fc0_0000 | (man << 13),
+            (exp, man) => sign | ((exp + 127 - 15) << 23) | (man << 13),
+        };
+        f32::from_bits(bits)
+    }
+}
+
+impl From<f32> for F16 {
+    #[inline]
+    fn from(value: f32) -> Self {
+        Self::from_f32(value)
+    }
+}
+
+impl From<F16> for f32 {
+    #[inline]
+    fn from(value: F16) -> Self {
+        value.to_f32()
+    }
+}
+
+impl std::fmt::Debug for F16 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.to_f32(), f)
+    }
+}
+
 // === std140 ===
 
 unsafe impl<T: PrimitiveShaderType> Std140 for T {
@@ -232,6 +329,21 @@ macro_rules! impl_shader_type {
 
 impl_shader_type!(
     // scalar
+    u8 {
+        align_mask: 0,
+        array_padding_std140: 15,
+        array_padding_std430: 0,
+    },
+    u16 {
+        align_mask: 0b1,
+        array_padding_std140: 14,
+        array_padding_std430: 0,
+    },
+    F16 {
+        align_mask: 0b1,
+        array_padding_std140: 14,
+        array_padding_std430: 0,
+    },
     u32 {
         align_mask: 0b11,
         array_padding_std140: 12,
@@ -247,6 +359,16 @@ impl_shader_type!(
         array_padding_std140: 12,
         array_padding_std430: 0,
     },
+    u64 {
+        align_mask: 0b111,
+        array_padding_std140: 8,
+        array_padding_std430: 0,
+    },
+    i64 {
+        align_mask: 0b111,
+        array_padding_std140: 8,
+        array_padding_std430: 0,
+    },
     f64 {
         align_mask: 0b111,
         array_padding_std140: 8,
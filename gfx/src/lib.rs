@@ -2,47 +2,57 @@ extern crate self as gfx;
 
 use vulkanalia::vk;
 
-pub use self::device::{CreateRenderPassError, DescriptorAllocError, Device, MapError, WeakDevice};
+pub use self::device::{
+    CreateRenderPassError, DescriptorAllocError, DescriptorAllocStats, Device, HeapUsage, MapError,
+    WeakDevice,
+};
 pub use self::encoder::{
     AccessFlags, BufferCopy, BufferImageCopy, BufferMemoryBarrier, CommandBuffer,
-    CommandBufferLevel, Encoder, EncoderCommon, ImageBlit, ImageCopy, ImageLayoutTransition,
-    ImageMemoryBarrier, MemoryBarrier, PrimaryEncoder, RenderPassEncoder,
+    CommandBufferLevel, DrawIndexedIndirectCommand, DrawIndirectCommand, Encoder, EncoderCommon,
+    ImageBlit, ImageCopy, ImageLayoutTransition, ImageMemoryBarrier, MemoryBarrier, PrimaryEncoder,
+    RenderPassEncoder,
 };
 pub use self::graphics::{Graphics, InitGraphicsError, InstanceConfig};
-pub use self::layout::{AsStd140, AsStd430, Padded, Padding, Std140, Std430};
+pub use self::layout::{AsStd140, AsStd430, Padded, Padding, Std140, Std430, F16};
 pub use self::physical::{
-    CreateDeviceError, DeviceFeature, DeviceFeatures, DeviceProperties, PhysicalDevice,
-    PhysicalDeviceSelector, PhysicalDeviceSelectorError,
+    CreateDeviceError, DeviceFeature, DeviceFeatures, DeviceProperties, DeviceType, MemoryHeapInfo,
+    PhysicalDevice, PhysicalDeviceInfo, PhysicalDeviceSelector, PhysicalDeviceSelectorError,
+    RejectedDevice,
 };
 pub use self::queue::{
-    PresentError, PresentStatus, Queue, QueueError, QueueFamily, QueueFlags, QueueId,
-    QueueNotFound, QueuesQuery, SingleQueueQuery,
+    GraphicsWithTransferQueueQuery, PastPresentationTiming, PresentError, PresentStatus, Queue,
+    QueueError, QueueFamily, QueueFlags, QueueId, QueueNotFound, QueuesQuery, SingleQueueQuery,
 };
+pub use self::readback_pool::{DownloadError, ReadbackPool, ReadbackTicket};
 pub use self::resources::{
     AttachmentInfo, BlendFactor, BlendOp, Blending, BorderColor, Bounds, Buffer, BufferInfo,
     BufferRange, BufferUsage, BufferView, BufferViewInfo, ClearColor, ClearDepth,
     ClearDepthStencil, ClearValue, ColorBlend, CombinedImageSampler, CompareOp, ComponentMapping,
-    ComponentMask, ComputePipeline, ComputePipelineInfo, ComputeShader, CullMode, DepthTest,
-    DescriptorBindingFlags, DescriptorSet, DescriptorSetInfo, DescriptorSetLayout,
+    ComponentMask, ComputePipeline, ComputePipelineInfo, ComputeShader, CullMode, DepthBias,
+    DepthTest, DescriptorBindingFlags, DescriptorSet, DescriptorSetInfo, DescriptorSetLayout,
     DescriptorSetLayoutBinding, DescriptorSetLayoutFlags, DescriptorSetLayoutInfo,
     DescriptorSetSize, DescriptorSetWrite, DescriptorSlice, DescriptorType, Fence, FenceState,
-    Filter, Format, FormatChannels, FormatDescription, FormatType, FragmentShader, Framebuffer,
-    FramebufferInfo, FrontFace, GraphicsPipeline, GraphicsPipelineDescr, GraphicsPipelineInfo,
-    GraphicsPipelineRenderingInfo, Image, ImageAspectFlags, ImageExtent, ImageInfo, ImageLayout,
-    ImageSubresource, ImageSubresourceLayers, ImageSubresourceRange, ImageUsageFlags, ImageView,
-    ImageViewInfo, ImageViewType, IndexType, LoadOp, LogicOp, MakeImageView, MemoryBlockMut,
-    MemoryUsage, MipmapMode, Pipeline, PipelineBindPoint, PipelineLayout, PipelineLayoutInfo,
-    PipelineStageFlags, PolygonMode, PrimitiveTopology, PushConstant, Rasterizer, Rect,
-    ReductionMode, RenderPass, RenderPassInfo, Sampler, SamplerAddressMode, SamplerInfo, Samples,
-    Semaphore, ShaderModule, ShaderModuleInfo, ShaderStageFlags, ShaderType, StencilOp,
-    StencilTest, StencilTests, StoreOp, Subpass, SubpassDependency, Swizzle, UpdateDescriptorSet,
-    VertexFormat, VertexInputAttribute, VertexInputBinding, VertexInputRate, VertexShader,
-    Viewport,
+    FenceWaitStatus, Filter, Format, FormatBlock, FormatChannels, FormatDescription, FormatType,
+    FragmentShader, Framebuffer, FramebufferInfo, FrontFace, GraphicsPipeline,
+    GraphicsPipelineDescr, GraphicsPipelineInfo, GraphicsPipelineRenderingInfo, Image,
+    ImageAspectFlags, ImageCreateFlags, ImageExtent, ImageInfo, ImageLayout, ImageSubresource,
+    ImageSubresourceLayers, ImageSubresourceRange, ImageUsageFlags, ImageView, ImageViewInfo,
+    ImageViewType, IndexType, LoadOp, LogicOp, MakeImageView, MappedBuffer, MemoryBlockMut,
+    MemoryUsage, MemoryUsagePreference, MipmapMode, Pipeline, PipelineBindPoint, PipelineCache,
+    PipelineLayout, PipelineLayoutInfo, PipelineStageFlags, PolygonMode, PrimitiveTopology,
+    PushConstant, QueryPool, QueryPoolInfo, QueryType, Rasterizer, Rect, ReductionMode, RenderPass,
+    RenderPassInfo, Sampler, SamplerAddressMode, SamplerInfo, Samples, Semaphore, ShaderModule,
+    ShaderModuleInfo, ShaderStageFlags, ShaderType, StencilOp, StencilTest, StencilTests, StoreOp,
+    Subpass, SubpassDependency, Swizzle, UpdateDescriptorSet, VertexFormat, VertexInputAttribute,
+    VertexInputBinding, VertexInputRate, VertexShader, Viewport,
 };
+pub use self::staging_belt::{StagingAllocation, StagingBelt};
 pub use self::surface::{
-    CreateSurfaceError, PresentMode, Surface, SurfaceError, SurfaceImage, SwapchainSupport,
+    ColorSpace, CreateSurfaceError, PresentMode, Surface, SurfaceError, SurfaceImage,
+    SwapchainPreferences, SwapchainSupport,
 };
 pub use self::types::{DeviceAddress, DeviceLost, OutOfDeviceMemory, State};
+pub use self::validation::{ValidationMessage, ValidationMessageKind, ValidationSeverity};
 
 pub use gfx_macros::{AsStd140, AsStd430};
 
@@ -52,10 +62,13 @@ mod graphics;
 mod layout;
 mod physical;
 mod queue;
+mod readback_pool;
 mod resources;
+mod staging_belt;
 mod surface;
 mod types;
 mod util;
+mod validation;
 
 #[track_caller]
 pub(crate) fn out_of_host_memory() -> ! {
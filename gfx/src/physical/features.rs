@@ -2,6 +2,8 @@ use shared::hlist::*;
 use shared::FastHashSet;
 use vulkanalia::prelude::v1_0::*;
 
+use super::DeviceFeatures;
+
 /// A feature that can be requested when creating a device.
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum DeviceFeature {
@@ -112,6 +114,36 @@ pub enum DeviceFeature {
     /// Adds ability to query the frame presentation timing.
     DisplayTiming,
 
+    /// Allows reading the draw count of indirect draws from a buffer
+    /// with [`RenderPassEncoder::draw_indexed_indirect_count`].
+    ///
+    /// [`RenderPassEncoder::draw_indexed_indirect_count`]: crate::RenderPassEncoder::draw_indexed_indirect_count
+    DrawIndirectCount,
+
+    /// Allows non-zero `first_instance` in the commands of indirect draws.
+    DrawIndirectFirstInstance,
+
+    /// Allows using [`PolygonMode::Line`] and [`PolygonMode::Point`].
+    ///
+    /// [`PolygonMode::Line`]: crate::PolygonMode::Line
+    /// [`PolygonMode::Point`]: crate::PolygonMode::Point
+    FillModeNonSolid,
+
+    /// Adds ability to query the memory usage and budget of each heap
+    /// with [`Device::memory_usage`].
+    ///
+    /// [`Device::memory_usage`]: crate::Device::memory_usage
+    MemoryBudget,
+
+    /// Allows more than one draw per indirect draw call.
+    MultiDrawIndirect,
+
+    /// Adds ability to push descriptors directly into a command buffer
+    /// for descriptor set layouts with [`DescriptorSetLayoutFlags::PUSH_DESCRIPTOR`].
+    ///
+    /// [`DescriptorSetLayoutFlags::PUSH_DESCRIPTOR`]: crate::DescriptorSetLayoutFlags::PUSH_DESCRIPTOR
+    PushDescriptor,
+
     /// Adds [`Min`] and [`Max`] reduction modes to the [`SamplerInfo`].
     ///
     /// [`Min`]: crate::ReductionMode::Min
@@ -119,6 +151,11 @@ pub enum DeviceFeature {
     /// [`SamplerInfo`]: crate::SamplerInfo
     SamplerFilterMinMax,
 
+    /// Allows using [`SamplerInfo::max_anisotropy`].
+    ///
+    /// [`SamplerInfo::max_anisotropy`]: crate::SamplerInfo::max_anisotropy
+    SamplerAnisotropy,
+
     /// Must be enabled to use the [`Surface`]
     ///
     /// [`Surface`]: crate::Surface
@@ -137,6 +174,76 @@ impl DeviceFeature {
         }
         required
     }
+
+    /// Returns `true` if the feature can be enabled on a physical device
+    /// with the specified features and extensions.
+    pub(crate) fn is_supported(
+        &self,
+        features: &DeviceFeatures,
+        extensions: &FastHashSet<vk::ExtensionName>,
+    ) -> bool {
+        let has_extension = |ext: &vk::Extension| extensions.contains(&ext.name);
+        let (v1_0, v1_2) = (&features.v1_0, &features.v1_2);
+
+        match self {
+            Self::BufferDeviceAddress => v1_2.buffer_device_address != 0,
+            Self::ShaderSampledImageDynamicIndexing => {
+                v1_0.shader_sampled_image_array_dynamic_indexing != 0
+            }
+            Self::ShaderStorageImageDynamicIndexing => {
+                v1_0.shader_storage_image_array_dynamic_indexing != 0
+            }
+            Self::ShaderUniformBufferDynamicIndexing => {
+                v1_0.shader_uniform_buffer_array_dynamic_indexing != 0
+            }
+            Self::ShaderStorageBufferDynamicIndexing => {
+                v1_0.shader_storage_buffer_array_dynamic_indexing != 0
+            }
+            Self::ShaderSampledImageNonUniformIndexing => {
+                v1_2.shader_sampled_image_array_non_uniform_indexing != 0
+            }
+            Self::ShaderStorageImageNonUniformIndexing => {
+                v1_2.shader_storage_image_array_non_uniform_indexing != 0
+            }
+            Self::ShaderUniformBufferNonUniformIndexing => {
+                v1_2.shader_uniform_buffer_array_non_uniform_indexing != 0
+            }
+            Self::ShaderStorageBufferNonUniformIndexing => {
+                v1_2.shader_storage_buffer_array_non_uniform_indexing != 0
+            }
+            Self::DescriptorBindingSampledImageUpdateAfterBind => {
+                v1_2.descriptor_binding_sampled_image_update_after_bind != 0
+            }
+            Self::DescriptorBindingStorageImageUpdateAfterBind => {
+                v1_2.descriptor_binding_storage_image_update_after_bind != 0
+            }
+            Self::DescriptorBindingUniformTexelBufferUpdateAfterBind => {
+                v1_2.descriptor_binding_uniform_texel_buffer_update_after_bind != 0
+            }
+            Self::DescriptorBindingStorageTexelBufferUpdateAfterBind => {
+                v1_2.descriptor_binding_storage_texel_buffer_update_after_bind != 0
+            }
+            Self::DescriptorBindingUniformBufferUpdateAfterBind => {
+                v1_2.descriptor_binding_uniform_buffer_update_after_bind != 0
+            }
+            Self::DescriptorBindingStorageBufferUpdateAfterBind => {
+                v1_2.descriptor_binding_storage_buffer_update_after_bind != 0
+            }
+            Self::DescriptorBindingPartiallyBound => v1_2.descriptor_binding_partially_bound != 0,
+            Self::RuntimeDescriptorArray => v1_2.runtime_descriptor_array != 0,
+            Self::DisplayTiming => has_extension(&vk::GOOGLE_DISPLAY_TIMING_EXTENSION),
+            Self::DrawIndirectCount => v1_2.draw_indirect_count != 0,
+            Self::DrawIndirectFirstInstance => v1_0.draw_indirect_first_instance != 0,
+            Self::FillModeNonSolid => v1_0.fill_mode_non_solid != 0,
+            Self::MemoryBudget => has_extension(&vk::EXT_MEMORY_BUDGET_EXTENSION),
+            Self::MultiDrawIndirect => v1_0.multi_draw_indirect != 0,
+            Self::PushDescriptor => has_extension(&vk::KHR_PUSH_DESCRIPTOR_EXTENSION),
+            Self::SamplerFilterMinMax => v1_2.sampler_filter_minmax != 0,
+            Self::SamplerAnisotropy => v1_0.sampler_anisotropy != 0,
+            Self::SurfacePresentation => has_extension(&vk::KHR_SWAPCHAIN_EXTENSION),
+            Self::ScalarBlockLayout => v1_2.scalar_block_layout != 0,
+        }
+    }
 }
 
 macro_rules! process_features {
@@ -160,6 +267,9 @@ pub type AllExtensions = (
     BufferDeviceAddressExtension,
     DescriptorIndexingExtension,
     DisplayTimingExtension,
+    DrawIndirectCountExtension,
+    MemoryBudgetExtension,
+    PushDescriptorExtension,
     SamplerFilterMinMaxExtension,
     ScalarBlockLayoutExtension,
     SurfacePresentationExtension,
@@ -199,6 +309,11 @@ impl VulkanExtension for BaseExtension {
             extension_features.shader_uniform_buffer_array_dynamic_indexing;
         core_features.shader_storage_buffer_array_dynamic_indexing =
             extension_features.shader_storage_buffer_array_dynamic_indexing;
+        core_features.fill_mode_non_solid = extension_features.fill_mode_non_solid;
+        core_features.sampler_anisotropy = extension_features.sampler_anisotropy;
+        core_features.multi_draw_indirect = extension_features.multi_draw_indirect;
+        core_features.draw_indirect_first_instance =
+            extension_features.draw_indirect_first_instance;
     }
 
     fn process_features(
@@ -212,6 +327,10 @@ impl VulkanExtension for BaseExtension {
             ShaderStorageImageDynamicIndexing => shader_storage_image_array_dynamic_indexing,
             ShaderUniformBufferDynamicIndexing => shader_uniform_buffer_array_dynamic_indexing,
             ShaderStorageBufferDynamicIndexing => shader_storage_buffer_array_dynamic_indexing,
+            FillModeNonSolid => fill_mode_non_solid,
+            SamplerAnisotropy => sampler_anisotropy,
+            MultiDrawIndirect => multi_draw_indirect,
+            DrawIndirectFirstInstance => draw_indirect_first_instance,
         )
     }
 }
@@ -222,6 +341,10 @@ pub struct BaseFeatures {
     shader_storage_image_array_dynamic_indexing: vk::Bool32,
     shader_uniform_buffer_array_dynamic_indexing: vk::Bool32,
     shader_storage_buffer_array_dynamic_indexing: vk::Bool32,
+    fill_mode_non_solid: vk::Bool32,
+    sampler_anisotropy: vk::Bool32,
+    multi_draw_indirect: vk::Bool32,
+    draw_indirect_first_instance: vk::Bool32,
 }
 
 unsafe impl vk::Cast for BaseFeatures {
@@ -418,6 +541,67 @@ impl VulkanExtension for DisplayTimingExtension {
     }
 }
 
+pub struct DrawIndirectCountExtension;
+
+impl VulkanExtension for DrawIndirectCountExtension {
+    const META: &'static vk::Extension = &vk::KHR_DRAW_INDIRECT_COUNT_EXTENSION;
+
+    type Core = VulkanCore<1, 2>;
+    type ExtensionFeatures = NoFeatures;
+    type ExtensionProperties = NoProperties;
+
+    fn copy_features(
+        _extension_features: &Self::ExtensionFeatures,
+        core_features: &mut VulkanCoreFeatures<Self::Core>,
+    ) {
+        core_features.draw_indirect_count = 1;
+    }
+
+    fn process_features(
+        available: &VulkanCoreFeatures<Self::Core>,
+        _enabled: &mut Self::ExtensionFeatures,
+        required: &mut FastHashSet<DeviceFeature>,
+    ) -> bool {
+        DeviceFeature::DrawIndirectCount.check(required, available.draw_indirect_count != 0)
+    }
+}
+
+pub struct MemoryBudgetExtension;
+
+impl VulkanExtension for MemoryBudgetExtension {
+    const META: &'static vk::Extension = &vk::EXT_MEMORY_BUDGET_EXTENSION;
+
+    type Core = VulkanCoreUnknown;
+    type ExtensionFeatures = NoFeatures;
+    type ExtensionProperties = NoProperties;
+
+    fn process_features(
+        _available: &VulkanCoreFeatures<Self::Core>,
+        _enabled: &mut Self::ExtensionFeatures,
+        required: &mut FastHashSet<DeviceFeature>,
+    ) -> bool {
+        required.remove(&DeviceFeature::MemoryBudget)
+    }
+}
+
+pub struct PushDescriptorExtension;
+
+impl VulkanExtension for PushDescriptorExtension {
+    const META: &'static vk::Extension = &vk::KHR_PUSH_DESCRIPTOR_EXTENSION;
+
+    type Core = VulkanCoreUnknown;
+    type ExtensionFeatures = NoFeatures;
+    type ExtensionProperties = NoProperties;
+
+    fn process_features(
+        _available: &VulkanCoreFeatures<Self::Core>,
+        _enabled: &mut Self::ExtensionFeatures,
+        required: &mut FastHashSet<DeviceFeature>,
+    ) -> bool {
+        required.remove(&DeviceFeature::PushDescriptor)
+    }
+}
+
 pub struct SamplerFilterMinMaxExtension;
 
 impl VulkanExtension for SamplerFilterMinMaxExtension {
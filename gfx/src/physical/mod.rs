@@ -6,10 +6,10 @@ use self::features::{AllExtensions, AllExtensionsExt, NoFeatures, NoProperties};
 use crate::graphics::Graphics;
 use crate::queue::{Queue, QueueFamily, QueueId, QueuesQuery};
 use crate::types::{DeviceLost, OutOfDeviceMemory};
-use crate::util::ToGfx;
+use crate::util::{FromVk, ToGfx};
 
 pub use self::features::DeviceFeature;
-pub use self::selector::{PhysicalDeviceSelector, PhysicalDeviceSelectorError};
+pub use self::selector::{PhysicalDeviceSelector, PhysicalDeviceSelectorError, RejectedDevice};
 
 mod features;
 mod selector;
@@ -48,6 +48,40 @@ impl PhysicalDevice {
         &self.features
     }
 
+    /// Returns a summary of the device properties.
+    pub fn info(&self) -> PhysicalDeviceInfo {
+        let properties = &self.properties.v1_0;
+        let memory = &self.properties.memory;
+
+        PhysicalDeviceInfo {
+            name: properties.device_name.to_string(),
+            ty: properties.device_type.to_gfx(),
+            driver_version: properties.driver_version,
+            api_version: properties.api_version,
+            memory_heaps: memory.memory_heaps[..memory.memory_heap_count as usize]
+                .iter()
+                .map(|heap| MemoryHeapInfo {
+                    size: heap.size,
+                    device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns `true` if the feature can be enabled on this device.
+    pub fn supports_feature(&self, feature: DeviceFeature) -> bool {
+        feature.is_supported(&self.features, &self.properties.extensions)
+    }
+
+    /// Returns features from the list which can't be enabled on this device.
+    pub fn unsupported_features(&self, features: &[DeviceFeature]) -> Vec<DeviceFeature> {
+        features
+            .iter()
+            .copied()
+            .filter(|feature| !self.supports_feature(*feature))
+            .collect()
+    }
+
     /// Creates a logical device and a set of queues.
     pub fn create_device<Q>(
         self,
@@ -94,7 +128,8 @@ impl PhysicalDevice {
         device_create_info = device_create_info.queue_create_infos(&queue_create_infos);
 
         // Collect requested features
-        let mut requested_features = features.iter().copied().collect::<FastHashSet<_>>();
+        let enabled_features = features.iter().copied().collect::<FastHashSet<_>>();
+        let mut requested_features = enabled_features.clone();
 
         let mut extensions = Vec::new();
         let mut require_extension = {
@@ -189,6 +224,7 @@ impl PhysicalDevice {
             self.handle,
             self.properties,
             core_features,
+            enabled_features,
             queue_families.iter().flat_map(|&(family, queue_count)| {
                 let family = family as u32;
                 (0..queue_count).map(move |index| {
@@ -262,6 +298,60 @@ macro_rules! impl_as_ref_mut(
     };
 );
 
+/// Kind of a physical device.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum DeviceType {
+    DiscreteGpu,
+    IntegratedGpu,
+    VirtualGpu,
+    Cpu,
+    Other,
+}
+
+impl FromVk<vk::PhysicalDeviceType> for DeviceType {
+    fn from_vk(ty: vk::PhysicalDeviceType) -> Self {
+        match ty {
+            vk::PhysicalDeviceType::DISCRETE_GPU => Self::DiscreteGpu,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => Self::IntegratedGpu,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => Self::VirtualGpu,
+            vk::PhysicalDeviceType::CPU => Self::Cpu,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A summary of the physical device properties, e.g. to list devices in settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhysicalDeviceInfo {
+    pub name: String,
+    pub ty: DeviceType,
+    /// Vendor-specific version of the driver.
+    pub driver_version: u32,
+    /// The highest Vulkan version supported by the device.
+    pub api_version: u32,
+    pub memory_heaps: Vec<MemoryHeapInfo>,
+}
+
+impl PhysicalDeviceInfo {
+    /// Returns the total size of device-local memory heaps in bytes.
+    pub fn device_local_memory(&self) -> u64 {
+        self.memory_heaps
+            .iter()
+            .filter(|heap| heap.device_local)
+            .map(|heap| heap.size)
+            .sum()
+    }
+}
+
+/// A memory heap of the physical device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryHeapInfo {
+    /// Heap size in bytes.
+    pub size: u64,
+    /// Whether the heap is located in the device memory.
+    pub device_local: bool,
+}
+
 /// All physical device properties.
 #[derive(Debug, Default)]
 pub struct DeviceProperties {
@@ -272,6 +362,7 @@ pub struct DeviceProperties {
     pub v1_1: vk::PhysicalDeviceVulkan11Properties,
     pub v1_2: vk::PhysicalDeviceVulkan12Properties,
     pub v1_3: vk::PhysicalDeviceVulkan13Properties,
+    pub push_descriptor: vk::PhysicalDevicePushDescriptorPropertiesKHR,
 }
 
 unsafe impl Sync for DeviceProperties {}
@@ -357,6 +448,11 @@ unsafe fn collect_info(handle: vk::PhysicalDevice) -> (Box<DeviceProperties>, Bo
                 properties2 = properties2.push_next(&mut properties_mt3);
             }
 
+            // Extension properties without a core counterpart
+            if has_extension(&vk::KHR_PUSH_DESCRIPTOR_EXTENSION) {
+                properties2 = properties2.push_next(&mut core_properties.push_descriptor);
+            }
+
             // Other extension properties and features
             features2 = AllExtensions::physical_device_features2_push_all(
                 api_version,
@@ -384,6 +480,7 @@ unsafe fn collect_info(handle: vk::PhysicalDevice) -> (Box<DeviceProperties>, Bo
         core_features.v1_1.next = std::ptr::null_mut();
 
         properties_mt3.next = std::ptr::null_mut();
+        core_properties.push_descriptor.next = std::ptr::null_mut();
         core_properties.v1_3.next = std::ptr::null_mut();
         core_properties.v1_2.next = std::ptr::null_mut();
         core_properties.v1_1.next = std::ptr::null_mut();
@@ -1,8 +1,7 @@
 use shared::{FastHashMap, FastHashSet};
-use vulkanalia::prelude::v1_0::*;
 
 use super::features::DeviceFeature;
-use super::CreateDeviceError;
+use super::{CreateDeviceError, DeviceType, PhysicalDeviceInfo};
 use crate::queue::QueuesQuery;
 
 /// A builder for selecting a physical device.
@@ -13,6 +12,8 @@ pub struct PhysicalDeviceSelector {
     allow_integrated_gpu: bool,
     allow_virtual_gpu: bool,
     allow_cpu: bool,
+    preferred_device_name: Option<String>,
+    preferred_device_type: Option<DeviceType>,
 }
 
 impl PhysicalDeviceSelector {
@@ -24,6 +25,8 @@ impl PhysicalDeviceSelector {
             allow_integrated_gpu: true,
             allow_virtual_gpu: true,
             allow_cpu: false,
+            preferred_device_name: None,
+            preferred_device_type: None,
         }
     }
 
@@ -51,6 +54,24 @@ impl PhysicalDeviceSelector {
         self
     }
 
+    /// Prefers a device with the name containing the specified string (case-insensitive).
+    ///
+    /// Falls back to the best suitable device if there is no such device.
+    pub fn prefer_device_name(mut self, name: impl Into<String>) -> Self {
+        self.preferred_device_name = Some(name.into());
+        self
+    }
+
+    /// Prefers a device of the specified type.
+    ///
+    /// Has lower priority than [`prefer_device_name`].
+    ///
+    /// [`prefer_device_name`]: Self::prefer_device_name
+    pub fn prefer_device_type(mut self, ty: DeviceType) -> Self {
+        self.preferred_device_type = Some(ty);
+        self
+    }
+
     pub fn with_required_feature(mut self, feature: DeviceFeature) -> Self {
         self.requested_features.insert(feature, Necessity::Required);
         self
@@ -64,43 +85,94 @@ impl PhysicalDeviceSelector {
         self
     }
 
-    // pub fn with_optional_feature(mut self, feature: DeviceFeature, score: usize) -> Self {
-    //     self.requested_features
-    //         .insert(feature, Necessity::Optional { score });
-    //     self
-    // }
-
-    // pub fn with_optional_features(mut self, features: &[(DeviceFeature, usize)]) -> Self {
-    //     for (feature, score) in features {
-    //         self.requested_features
-    //             .insert(*feature, Necessity::Optional { score: *score });
-    //     }
-    //     self
-    // }
-
-    // TODO: Add support for optional features
+    /// Enables the feature if it is supported by the selected device.
+    ///
+    /// `score` is added to the score of each device which supports the feature.
+    pub fn with_optional_feature(mut self, feature: DeviceFeature, score: usize) -> Self {
+        self.requested_features
+            .insert(feature, Necessity::Optional { score });
+        self
+    }
+
+    pub fn with_optional_features(mut self, features: &[(DeviceFeature, usize)]) -> Self {
+        for (feature, score) in features {
+            self.requested_features
+                .insert(*feature, Necessity::Optional { score: *score });
+        }
+        self
+    }
+
     pub fn find_best(mut self) -> Result<SelectedPhysicalDevice, PhysicalDeviceSelectorError> {
-        let mut result = None;
+        if self.physical_devices.is_empty() {
+            return Err(PhysicalDeviceSelectorError::NoPhysicalDeviceFound);
+        }
+
+        let required_features = self
+            .requested_features
+            .iter()
+            .filter(|(_, necessity)| matches!(necessity, Necessity::Required))
+            .map(|(feature, _)| *feature)
+            .collect::<Vec<_>>();
+        let optional_features = self
+            .requested_features
+            .iter()
+            .filter_map(|(feature, necessity)| match necessity {
+                Necessity::Optional { score } => Some((*feature, *score)),
+                Necessity::Required => None,
+            })
+            .collect::<Vec<_>>();
+        let preferred_name = self.preferred_device_name.as_deref().map(str::to_lowercase);
 
+        let mut result = None;
+        let mut rejected = Vec::new();
         for (index, physical_device) in self.physical_devices.iter().enumerate() {
-            let properties = physical_device.properties();
+            let info = physical_device.info();
 
             tracing::info!(
-                name = %properties.v1_0.device_name,
-                ty = ?properties.v1_0.device_type,
+                name = %info.name,
+                ty = ?info.ty,
                 "found physical device",
             );
 
-            let mut score = 0usize;
-            match properties.v1_0.device_type {
-                vk::PhysicalDeviceType::DISCRETE_GPU if self.allow_discrete_gpu => score += 1000,
-                vk::PhysicalDeviceType::INTEGRATED_GPU if self.allow_integrated_gpu => score += 100,
-                vk::PhysicalDeviceType::VIRTUAL_GPU if self.allow_virtual_gpu => score += 10,
-                vk::PhysicalDeviceType::CPU if self.allow_cpu => score += 1,
-                _ => continue,
+            let mut score = match info.ty {
+                DeviceType::DiscreteGpu if self.allow_discrete_gpu => 1000,
+                DeviceType::IntegratedGpu if self.allow_integrated_gpu => 100,
+                DeviceType::VirtualGpu if self.allow_virtual_gpu => 10,
+                DeviceType::Cpu if self.allow_cpu => 1,
+                _ => {
+                    rejected.push(RejectedDevice {
+                        info,
+                        type_allowed: false,
+                        missing_features: Vec::new(),
+                    });
+                    continue;
+                }
+            };
+
+            let missing_features = physical_device.unsupported_features(&required_features);
+            if !missing_features.is_empty() {
+                rejected.push(RejectedDevice {
+                    info,
+                    type_allowed: true,
+                    missing_features,
+                });
+                continue;
             }
 
-            // TODO: check for required features
+            score += optional_features
+                .iter()
+                .filter(|(feature, _)| physical_device.supports_feature(*feature))
+                .map(|(_, score)| score)
+                .sum::<usize>();
+
+            if self.preferred_device_type == Some(info.ty) {
+                score += PREFERRED_TYPE_SCORE;
+            }
+            if let Some(name) = &preferred_name {
+                if info.name.to_lowercase().contains(name) {
+                    score += PREFERRED_NAME_SCORE;
+                }
+            }
 
             match &result {
                 Some((_index, best_score)) if *best_score >= score => continue,
@@ -108,11 +180,24 @@ impl PhysicalDeviceSelector {
             }
         }
 
-        let (index, _) = result.ok_or(PhysicalDeviceSelectorError::NoPhysicalDeviceFound)?;
+        let Some((index, score)) = result else {
+            return Err(PhysicalDeviceSelectorError::NoSuitableDevice(rejected));
+        };
+        if let Some(name) = &self.preferred_device_name {
+            if score < PREFERRED_NAME_SCORE {
+                tracing::warn!(%name, "preferred physical device not found");
+            }
+        }
+
         let physical_device = self.physical_devices.swap_remove(index);
 
-        // TODO: filter out unsupported features
-        let supported_features = self.requested_features.keys().copied().collect();
+        let mut supported_features = required_features.into_iter().collect::<FastHashSet<_>>();
+        supported_features.extend(
+            optional_features
+                .into_iter()
+                .map(|(feature, _)| feature)
+                .filter(|feature| physical_device.supports_feature(*feature)),
+        );
 
         Ok(SelectedPhysicalDevice {
             physical_device,
@@ -145,9 +230,34 @@ impl SelectedPhysicalDevice {
     }
 }
 
+// NOTE: Preferences outweigh any combination of the device type scores
+const PREFERRED_TYPE_SCORE: usize = 10_000;
+const PREFERRED_NAME_SCORE: usize = 100_000;
+
 enum Necessity {
     Required,
-    // Optional { score: usize },
+    Optional { score: usize },
+}
+
+/// A physical device which did not pass the selection.
+#[derive(Debug, Clone)]
+pub struct RejectedDevice {
+    pub info: PhysicalDeviceInfo,
+    /// Whether the device type is allowed by the selector.
+    pub type_allowed: bool,
+    /// Required features which are not supported by the device.
+    pub missing_features: Vec<DeviceFeature>,
+}
+
+impl std::fmt::Display for RejectedDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:?})", self.info.name, self.info.ty)?;
+        if !self.type_allowed {
+            f.write_str(": device type is not allowed")
+        } else {
+            write!(f, ": missing features {:?}", self.missing_features)
+        }
+    }
 }
 
 /// Error that can occur when selecting a physical device.
@@ -157,4 +267,13 @@ pub enum PhysicalDeviceSelectorError {
     RequiredFeaturesNotSupported(Vec<DeviceFeature>),
     #[error("no physical device found")]
     NoPhysicalDeviceFound,
+    #[error("no suitable physical device found:{}", display_rejected(.0))]
+    NoSuitableDevice(Vec<RejectedDevice>),
+}
+
+fn display_rejected(devices: &[RejectedDevice]) -> String {
+    devices
+        .iter()
+        .map(|device| format!("\n  - {device}"))
+        .collect()
 }
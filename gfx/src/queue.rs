@@ -1,13 +1,15 @@
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use arrayvec::ArrayVec;
 use bumpalo::Bump;
 use vulkanalia::prelude::v1_0::*;
-use vulkanalia::vk::KhrSwapchainExtension;
+use vulkanalia::vk::{GoogleDisplayTimingExtension, KhrSwapchainExtension};
 
 use crate::encoder::{CommandBuffer, CommandBufferLevel, Encoder, PrimaryEncoder};
+use crate::physical::DeviceFeature;
 use crate::resources::{Fence, PipelineStageFlags, Semaphore};
-use crate::surface::SurfaceImage;
+use crate::surface::{Surface, SurfaceError, SurfaceImage};
 use crate::types::{DeviceLost, OutOfDeviceMemory, SurfaceLost};
 use crate::util::{FromGfx, FromVk, ToGfx, ToVk};
 
@@ -60,6 +62,48 @@ impl QueuesQuery for SingleQueueQuery {
     }
 }
 
+/// Graphics queue query with an optional queue from a dedicated transfer family.
+///
+/// The transfer queue is only returned if there is a family which supports
+/// transfer operations but neither graphics nor compute.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GraphicsWithTransferQueueQuery;
+
+impl QueuesQuery for GraphicsWithTransferQueueQuery {
+    type QueryState = bool;
+    type Query = ArrayVec<(usize, usize), 2>;
+    type Queues = (Queue, Option<Queue>);
+    type Error = QueueNotFound;
+
+    fn query(
+        self,
+        families: &[vk::QueueFamilyProperties],
+    ) -> Result<(Self::Query, Self::QueryState), Self::Error> {
+        let ([graphics], ()) = SingleQueueQuery::GRAPHICS.query(families)?;
+
+        let transfer = families.iter().position(|family| {
+            family.queue_count > 0
+                && family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !family
+                    .queue_flags
+                    .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        });
+
+        let mut query = ArrayVec::new();
+        query.push(graphics);
+        if let Some(index) = transfer {
+            query.push((index, 1));
+        }
+        Ok((query, transfer.is_some()))
+    }
+
+    fn collect(has_transfer: Self::QueryState, mut families: Vec<QueueFamily>) -> Self::Queues {
+        let graphics = families.remove(0).queues.remove(0);
+        let transfer = has_transfer.then(|| families.remove(0).queues.remove(0));
+        (graphics, transfer)
+    }
+}
+
 bitflags::bitflags! {
     /// Queue capabilities.
     #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -163,6 +207,11 @@ impl Queue {
         &self.inner.device
     }
 
+    /// Returns the capabilities of the queue family.
+    pub fn capabilities(&self) -> QueueFlags {
+        self.inner.capabilities
+    }
+
     /// Wait for a queue to become idle.
     pub fn wait_idle(&self) -> Result<(), QueueError> {
         let logical = self.inner.device.logical();
@@ -188,7 +237,17 @@ impl Queue {
             .map(|cb| Encoder::new(cb, capabilities))
     }
 
-    /// Submit a set of command buffers to the queue.
+    /// Submit a batch of command buffers to the queue.
+    ///
+    /// Command buffers are executed in the specified order. All of them share
+    /// the wait and signal semaphores and the fence, so the whole batch (with
+    /// all secondary command buffers executed by it) is recycled at once when
+    /// the epoch of the submission is closed.
+    ///
+    /// NOTE: Resources dropped before the submission are not destroyed
+    /// by it, see [`Device::flush_destroys`].
+    ///
+    /// [`Device::flush_destroys`]: crate::Device::flush_destroys
     pub fn submit<I>(
         &self,
         wait: &mut [(PipelineStageFlags, &mut Semaphore)],
@@ -201,18 +260,19 @@ impl Queue {
         I: IntoIterator<Item = CommandBuffer>,
         I::IntoIter: ExactSizeIterator,
     {
-        let owned_command_buffers = alloc.alloc_with(ArrayVec::<_, 64>::new);
-        let command_buffers =
-            alloc.alloc_slice_fill_iter(command_buffers.into_iter().map(|command_buffer| {
-                debug_assert!(
-                    command_buffer.level() == CommandBufferLevel::Primary,
-                    "only primary command buffers can be submitted directly to a queue"
-                );
-
-                let handle = command_buffer.handle();
-                owned_command_buffers.push(command_buffer);
-                handle
-            }));
+        let command_buffers = command_buffers.into_iter();
+        let mut owned_command_buffers =
+            bumpalo::collections::Vec::with_capacity_in(command_buffers.len(), alloc);
+        let command_buffers = alloc.alloc_slice_fill_iter(command_buffers.map(|command_buffer| {
+            debug_assert!(
+                command_buffer.level() == CommandBufferLevel::Primary,
+                "only primary command buffers can be submitted directly to a queue"
+            );
+
+            let handle = command_buffer.handle();
+            owned_command_buffers.push(command_buffer);
+            handle
+        }));
 
         let this = self.inner.as_ref();
 
@@ -318,21 +378,30 @@ impl Queue {
             this.id.family
         );
 
+        let present_times = [vk::PresentTimeGOOGLE {
+            present_id: image.present_id(),
+            desired_present_time: 0,
+        }];
+        let mut present_times_info = vk::PresentTimesInfoGOOGLE::builder().times(&present_times);
+
         let [_, signal] = image.wait_signal();
 
         let res = {
             let logical = this.device.logical();
 
-            let _guard = this.submission_mutex.lock().unwrap();
-            unsafe {
-                logical.queue_present_khr(
-                    this.handle,
-                    &vk::PresentInfoKHR::builder()
-                        .wait_semaphores(&[signal.handle()])
-                        .swapchains(&[image.swapchain_handle()])
-                        .image_indices(&[image.index()]),
-                )
+            let wait_semaphores = [signal.handle()];
+            let swapchains = [image.swapchain_handle()];
+            let image_indices = [image.index()];
+            let mut present_info = vk::PresentInfoKHR::builder()
+                .wait_semaphores(&wait_semaphores)
+                .swapchains(&swapchains)
+                .image_indices(&image_indices);
+            if this.device.is_feature_enabled(DeviceFeature::DisplayTiming) {
+                present_info = present_info.push_next(&mut present_times_info);
             }
+
+            let _guard = this.submission_mutex.lock().unwrap();
+            unsafe { logical.queue_present_khr(this.handle, &present_info) }
         };
         if let Some(vk::ErrorCode::OUT_OF_HOST_MEMORY) = res.err() {
             crate::out_of_host_memory();
@@ -355,6 +424,56 @@ impl Queue {
         }
     }
 
+    /// Returns the timings of the images presented to the `surface`
+    /// since the last call, in the order of their present ids.
+    ///
+    /// Returns nothing if the swapchain is not configured or
+    /// [`DeviceFeature::DisplayTiming`] is not enabled.
+    ///
+    /// NOTE: Timings of the images presented with a previous swapchain are lost.
+    pub fn get_past_presentation_timing(
+        &self,
+        surface: &Surface,
+    ) -> Result<Vec<PastPresentationTiming>, SurfaceError> {
+        let this = self.inner.as_ref();
+
+        let Some(swapchain) = surface.swapchain_handle() else {
+            return Ok(Vec::new());
+        };
+        if !this.device.is_feature_enabled(DeviceFeature::DisplayTiming) {
+            return Ok(Vec::new());
+        }
+
+        let res = unsafe {
+            this.device
+                .logical()
+                .get_past_presentation_timing_google(swapchain)
+        };
+        let timings = match res {
+            Ok(timings) => timings,
+            // NOTE: The swapchain is recreated on the next present
+            Err(vk::ErrorCode::OUT_OF_DATE_KHR) => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(match e {
+                    vk::ErrorCode::OUT_OF_HOST_MEMORY => crate::out_of_host_memory(),
+                    vk::ErrorCode::DEVICE_LOST => SurfaceError::DeviceLost(DeviceLost),
+                    vk::ErrorCode::SURFACE_LOST_KHR => SurfaceError::SurfaceLost(SurfaceLost),
+                    _ => crate::unexpected_vulkan_error(e),
+                })
+            }
+        };
+
+        Ok(timings
+            .into_iter()
+            .map(|timing| PastPresentationTiming {
+                present_id: timing.present_id,
+                actual_present_time: timing.actual_present_time,
+                earliest_present_time: timing.earliest_present_time,
+                present_margin: Duration::from_nanos(timing.present_margin),
+            })
+            .collect())
+    }
+
     fn begin_command_buffer(
         &self,
         level: CommandBufferLevel,
@@ -413,7 +532,11 @@ impl Queue {
         }
     }
 
-    fn restore_command_buffers(&self) -> Result<(), OutOfDeviceMemory> {
+    /// Returns command buffers of all completed submissions back to the queue cache.
+    ///
+    /// NOTE: This is done automatically on present, so it is only required
+    /// for queues which are never used for presentation.
+    pub fn restore_command_buffers(&self) -> Result<(), OutOfDeviceMemory> {
         let this = self.inner.as_ref();
         let logical = this.device.logical();
 
@@ -501,6 +624,23 @@ pub enum PresentStatus {
     OutOfDate,
 }
 
+/// Timing of a presented image, see [`Queue::get_past_presentation_timing`].
+///
+/// Times are in nanoseconds of the presentation engine clock,
+/// which is `CLOCK_MONOTONIC` on most platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PastPresentationTiming {
+    /// Id of the presented image, see [`SurfaceImage::present_id`].
+    pub present_id: u32,
+    /// Time when the image was shown on the display.
+    pub actual_present_time: u64,
+    /// Earliest time when the image could have been shown.
+    pub earliest_present_time: u64,
+    /// How early the present was processed compared to the latest time
+    /// at which it still could be shown at the earliest present time.
+    pub present_margin: Duration,
+}
+
 /// Queue presentation error.
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum PresentError {
@@ -0,0 +1,303 @@
+use std::sync::{Arc, Mutex, Weak};
+
+use shared::FastHashMap;
+
+use crate::device::{Device, EpochSnapshot, MapError};
+use crate::resources::{Buffer, BufferInfo, BufferRange, BufferUsage, MemoryUsage};
+use crate::types::OutOfDeviceMemory;
+
+/// A set of host-visible buffers for downloading buffer ranges from the GPU.
+///
+/// Each download gets its own buffer, which is recycled by power-of-two size
+/// classes once the data is taken or the ticket is dropped. The data is
+/// available once the submission which copied it is completed (its epoch
+/// is closed when the submission fence is waited).
+///
+/// See [`Encoder::download_buffer`].
+///
+/// [`Encoder::download_buffer`]: crate::Encoder::download_buffer
+pub struct ReadbackPool {
+    device: Device,
+    readbacks: Mutex<Readbacks<Buffer, EpochSnapshot>>,
+}
+
+impl ReadbackPool {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            device: device.clone(),
+            readbacks: Mutex::new(Readbacks::default()),
+        }
+    }
+
+    /// Returns a buffer which fits the downloaded range.
+    pub(crate) fn allocate(&self, range: &BufferRange) -> Result<Buffer, DownloadError> {
+        validate_range(range)?;
+
+        let class = size_class(range.size);
+        if let Some(buffer) = self.readbacks.lock().unwrap().take_free(class) {
+            return Ok(buffer);
+        }
+
+        let buffer = self.device.create_mappable_buffer(
+            BufferInfo {
+                align_mask: 0b11,
+                size: class_size(class),
+                usage: BufferUsage::TRANSFER_DST,
+                label: Some("readback"),
+            },
+            MemoryUsage::DOWNLOAD,
+        )?;
+        Ok(buffer)
+    }
+
+    /// Registers a buffer with the recorded copy of `size` bytes.
+    pub(crate) fn register(&self, buffer: Buffer, size: usize) -> ReadbackTicket {
+        let snapshot = self.device.epochs().snapshot();
+        self.readbacks
+            .lock()
+            .unwrap()
+            .insert(buffer, size, snapshot)
+    }
+
+    /// Returns the downloaded data if the submission which copied it is completed.
+    ///
+    /// NOTE: The data is returned only once, `None` is returned afterwards.
+    pub fn poll(&self, ticket: &ReadbackTicket) -> Result<Option<Vec<u8>>, MapError> {
+        let epochs = self.device.epochs();
+        let mut readbacks = self.readbacks.lock().unwrap();
+        let Some((buffer, size)) = readbacks.take(ticket, |snapshot| epochs.is_passed(snapshot))
+        else {
+            return Ok(None);
+        };
+
+        let mut data = vec![0u8; size];
+        let res = self
+            .device
+            .download_from_memory(&mut buffer.as_mappable(), 0, &mut data);
+
+        readbacks.free(size_class(buffer.info().size), buffer);
+        res.map(|_| Some(data))
+    }
+
+    /// Makes buffers of the dropped tickets available for new downloads.
+    ///
+    /// NOTE: Should be called once per frame after waiting for the frame fence.
+    pub fn recall(&self) {
+        let epochs = self.device.epochs();
+        self.readbacks.lock().unwrap().recall(
+            |buffer| size_class(buffer.info().size),
+            |snapshot| epochs.is_passed(snapshot),
+        );
+    }
+}
+
+/// Handle of a buffer download, see [`ReadbackPool::poll`].
+///
+/// Dropping the ticket discards the data.
+#[derive(Debug)]
+pub struct ReadbackTicket {
+    id: u64,
+    size: usize,
+    _alive: Arc<()>,
+}
+
+impl ReadbackTicket {
+    /// Returns the size of the downloaded range in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// An error returned when a buffer range can't be downloaded.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum DownloadError {
+    #[error("readback range {offset}+{size} is out of the buffer with size {buffer_size}")]
+    OutOfBounds {
+        offset: usize,
+        size: usize,
+        buffer_size: usize,
+    },
+    #[error("readback range must not be empty")]
+    Empty,
+    #[error(transparent)]
+    OutOfDeviceMemory(#[from] OutOfDeviceMemory),
+}
+
+struct Readbacks<B, S> {
+    next_id: u64,
+    pending: FastHashMap<u64, PendingReadback<B, S>>,
+    free: FastHashMap<u32, Vec<B>>,
+}
+
+impl<B, S> Default for Readbacks<B, S> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            pending: FastHashMap::default(),
+            free: FastHashMap::default(),
+        }
+    }
+}
+
+struct PendingReadback<B, S> {
+    buffer: B,
+    size: usize,
+    /// Submissions which must be completed before the data is read.
+    snapshot: S,
+    /// Dead if the ticket was dropped without taking the data.
+    ticket: Weak<()>,
+}
+
+impl<B, S> Readbacks<B, S> {
+    fn take_free(&mut self, class: u32) -> Option<B> {
+        self.free.get_mut(&class).and_then(Vec::pop)
+    }
+
+    fn free(&mut self, class: u32, buffer: B) {
+        self.free.entry(class).or_default().push(buffer);
+    }
+
+    fn insert(&mut self, buffer: B, size: usize, snapshot: S) -> ReadbackTicket {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let alive = Arc::new(());
+        self.pending.insert(
+            id,
+            PendingReadback {
+                buffer,
+                size,
+                snapshot,
+                ticket: Arc::downgrade(&alive),
+            },
+        );
+        ReadbackTicket {
+            id,
+            size,
+            _alive: alive,
+        }
+    }
+
+    /// Removes the readback of the `ticket` if its copy is completed.
+    fn take<F>(&mut self, ticket: &ReadbackTicket, is_passed: F) -> Option<(B, usize)>
+    where
+        F: FnOnce(&S) -> bool,
+    {
+        let readback = self.pending.get(&ticket.id)?;
+        if !is_passed(&readback.snapshot) {
+            return None;
+        }
+        let readback = self.pending.remove(&ticket.id).unwrap();
+        Some((readback.buffer, readback.size))
+    }
+
+    /// Frees buffers of completed readbacks whose tickets were dropped.
+    fn recall<C, F>(&mut self, class_of: C, is_passed: F)
+    where
+        C: Fn(&B) -> u32,
+        F: Fn(&S) -> bool,
+    {
+        let dropped = self
+            .pending
+            .iter()
+            .filter(|(_, readback)| {
+                readback.ticket.strong_count() == 0 && is_passed(&readback.snapshot)
+            })
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+
+        for id in dropped {
+            let readback = self.pending.remove(&id).unwrap();
+            self.free(class_of(&readback.buffer), readback.buffer);
+        }
+    }
+}
+
+fn validate_range(range: &BufferRange) -> Result<(), DownloadError> {
+    if range.size == 0 {
+        return Err(DownloadError::Empty);
+    }
+
+    let buffer_size = range.buffer.info().size;
+    match range.offset.checked_add(range.size) {
+        Some(end) if end <= buffer_size => Ok(()),
+        _ => Err(DownloadError::OutOfBounds {
+            offset: range.offset,
+            size: range.size,
+            buffer_size,
+        }),
+    }
+}
+
+/// Returns the power-of-two size class of a readback buffer.
+fn size_class(size: usize) -> u32 {
+    size.max(MIN_READBACK_SIZE)
+        .next_power_of_two()
+        .trailing_zeros()
+}
+
+fn class_size(class: u32) -> usize {
+    1 << class
+}
+
+const MIN_READBACK_SIZE: usize = 256;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Readbacks of buffer ids copied in frames.
+    type TestReadbacks = Readbacks<u32, u32>;
+
+    #[test]
+    fn sizes_are_rounded_to_classes() {
+        assert_eq!(class_size(size_class(1)), MIN_READBACK_SIZE);
+        assert_eq!(class_size(size_class(MIN_READBACK_SIZE)), MIN_READBACK_SIZE);
+        assert_eq!(
+            class_size(size_class(MIN_READBACK_SIZE + 1)),
+            2 * MIN_READBACK_SIZE
+        );
+        assert_eq!(class_size(size_class(1000)), 1024);
+
+        for size in [1, 255, 256, 257, 4096, 5000] {
+            // Recycled buffers must fit any size of their class
+            let class = size_class(size);
+            assert!(class_size(class) >= size);
+            assert_eq!(size_class(class_size(class)), class);
+        }
+    }
+
+    #[test]
+    fn data_is_taken_once_the_copy_is_completed() {
+        let mut readbacks = TestReadbacks::default();
+        let completed = |frame: u32| move |copied_at: &u32| *copied_at <= frame;
+
+        let ticket = readbacks.insert(0, 16, 1);
+        assert_eq!(ticket.size(), 16);
+        assert_eq!(readbacks.take(&ticket, completed(0)), None);
+        assert_eq!(readbacks.take(&ticket, completed(1)), Some((0, 16)));
+
+        // The data is returned only once
+        assert_eq!(readbacks.take(&ticket, completed(1)), None);
+    }
+
+    #[test]
+    fn buffers_of_dropped_tickets_are_recycled() {
+        let mut readbacks = TestReadbacks::default();
+        let completed = |frame: u32| move |copied_at: &u32| *copied_at <= frame;
+        let class_of = |_: &u32| 8;
+
+        let dropped = readbacks.insert(0, 256, 1);
+        let kept = readbacks.insert(1, 256, 1);
+        drop(dropped);
+
+        // The copy could still write into the buffer
+        readbacks.recall(class_of, completed(0));
+        assert_eq!(readbacks.take_free(8), None);
+
+        readbacks.recall(class_of, completed(1));
+        assert_eq!(readbacks.take_free(8), Some(0));
+        assert_eq!(readbacks.take_free(8), None);
+        assert_eq!(readbacks.take(&kept, completed(1)), Some((1, 256)));
+    }
+}
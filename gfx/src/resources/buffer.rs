@@ -1,5 +1,6 @@
 use std::hash::Hash;
-use std::mem::ManuallyDrop;
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::ops::Range;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 use vulkanalia::prelude::v1_0::*;
@@ -47,6 +48,10 @@ pub struct BufferInfo {
     pub align_mask: usize,
     pub size: usize,
     pub usage: BufferUsage,
+    /// Debug name of the buffer, see [`Device::set_object_name`].
+    ///
+    /// [`Device::set_object_name`]: crate::Device::set_object_name
+    pub label: Option<&'static str>,
 }
 
 bitflags::bitflags! {
@@ -169,6 +174,20 @@ bitflags::bitflags! {
     }
 }
 
+/// How strictly [`MemoryUsage::FAST_DEVICE_ACCESS`] of a mappable buffer is applied.
+#[derive(Default, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum MemoryUsagePreference {
+    /// Memory is selected only by the requested usage.
+    #[default]
+    Required,
+    /// Host visible device local memory is used only while its heap has room.
+    ///
+    /// On devices with a small resizable BAR heap the buffer is allocated
+    /// without fast device access when the heap is close to its budget
+    /// or the allocation fails.
+    Preferred,
+}
+
 /// A wrapper around a Vulkan buffer object.
 ///
 /// Buffers represent linear arrays of data which are used for various purposes
@@ -215,11 +234,24 @@ impl Buffer {
         self.inner.handle
     }
 
+    /// Returns `true` if the buffer memory is device local.
+    pub fn is_device_local(&self) -> bool {
+        let memory_block = self.inner.memory_block.lock().unwrap();
+        memory_block
+            .props()
+            .contains(gpu_alloc::MemoryPropertyFlags::DEVICE_LOCAL)
+    }
+
     pub fn as_mappable(&self) -> MemoryBlockMut<'_> {
         MemoryBlockMut {
             inner: self.inner.memory_block.lock().unwrap(),
         }
     }
+
+    /// Returns `true` if there are no other references to this buffer.
+    pub(crate) fn is_unique(&self) -> bool {
+        Arc::strong_count(&self.inner) == 1
+    }
 }
 
 impl std::fmt::Debug for Buffer {
@@ -272,6 +304,96 @@ impl std::ops::DerefMut for MemoryBlockMut<'_> {
     }
 }
 
+/// A host visible buffer which stays mapped until it is dropped.
+///
+/// NOTE: Writes to memory without the `HOST_COHERENT` property must be
+/// made visible to the device with [`Device::flush_mapped_ranges`].
+///
+/// [`Device::flush_mapped_ranges`]: crate::Device::flush_mapped_ranges
+pub struct MappedBuffer {
+    buffer: Buffer,
+    ptr: *mut MaybeUninit<u8>,
+    coherent: bool,
+}
+
+unsafe impl Send for MappedBuffer {}
+unsafe impl Sync for MappedBuffer {}
+
+impl MappedBuffer {
+    pub(crate) fn new(buffer: Buffer, ptr: *mut MaybeUninit<u8>, coherent: bool) -> Self {
+        Self {
+            buffer,
+            ptr,
+            coherent,
+        }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Size of the mapped memory in bytes.
+    pub fn size(&self) -> usize {
+        self.buffer.info().size
+    }
+
+    /// Returns `true` if writes don't need to be flushed.
+    pub fn is_coherent(&self) -> bool {
+        self.coherent
+    }
+
+    /// Pointer to the start of the mapped memory.
+    ///
+    /// NOTE: Valid for [`MappedBuffer::size`] bytes while the buffer is alive.
+    pub fn as_mut_ptr(&self) -> *mut MaybeUninit<u8> {
+        self.ptr
+    }
+
+    /// Returns the device memory range which contains the buffer `range`.
+    pub(crate) fn memory_range(
+        &self,
+        atom_mask: u64,
+        range: Range<usize>,
+    ) -> vk::MappedMemoryRange {
+        let block = self.buffer.as_mappable();
+        let (offset, size) = aligned_memory_range(atom_mask, block.offset(), block.size(), range);
+        vk::MappedMemoryRange::builder()
+            .memory(*block.memory())
+            .offset(offset)
+            .size(size)
+            .build()
+    }
+}
+
+impl Drop for MappedBuffer {
+    fn drop(&mut self) {
+        if let Some(device) = self.buffer.owner().upgrade() {
+            device.unmap_memory(&mut self.buffer.as_mappable());
+        }
+    }
+}
+
+/// Expands the byte `range` of the memory block to the non-coherent atom boundaries.
+///
+/// Returns the offset and size relative to the start of the device memory.
+fn aligned_memory_range(
+    atom_mask: u64,
+    block_offset: u64,
+    block_size: u64,
+    range: Range<usize>,
+) -> (u64, u64) {
+    let start = (block_offset + range.start as u64) & !atom_mask;
+    let end = (block_offset + range.end as u64 + atom_mask) & !atom_mask;
+
+    // NOTE: The aligned end of the last block could exceed the memory size,
+    // so the range is extended to the end of the memory instead.
+    if end >= block_offset + block_size {
+        (start, vk::WHOLE_SIZE)
+    } else {
+        (start, end - start)
+    }
+}
+
 struct Inner {
     handle: vk::Buffer,
     info: BufferInfo,
@@ -296,3 +418,35 @@ impl Drop for Inner {
 
 unsafe impl Send for Inner {}
 unsafe impl Sync for Inner {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_range_is_aligned_to_atoms() {
+        const ATOM_MASK: u64 = 63;
+
+        // Unaligned range inside the block
+        assert_eq!(
+            aligned_memory_range(ATOM_MASK, 256, 1024, 10..70),
+            (256, 128)
+        );
+        // Offset of the block is applied before the alignment
+        assert_eq!(aligned_memory_range(ATOM_MASK, 320, 1024, 0..64), (320, 64));
+        // Aligned range stays as is
+        assert_eq!(
+            aligned_memory_range(ATOM_MASK, 0, 1024, 128..192),
+            (128, 64)
+        );
+        // Range touching the end of the block is extended to the end of the memory
+        assert_eq!(
+            aligned_memory_range(ATOM_MASK, 0, 1000, 900..1000),
+            (896, vk::WHOLE_SIZE)
+        );
+        assert_eq!(
+            aligned_memory_range(ATOM_MASK, 512, 512, 0..512),
+            (512, vk::WHOLE_SIZE)
+        );
+    }
+}
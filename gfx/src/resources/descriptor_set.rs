@@ -50,6 +50,7 @@ pub struct CombinedImageSampler {
 #[derive(Debug, Clone)]
 pub struct DescriptorSetInfo {
     pub layout: DescriptorSetLayout,
+    pub label: Option<&'static str>,
 }
 
 /// A wrapper around a Vulkan descriptor set object.
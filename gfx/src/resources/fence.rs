@@ -22,6 +22,14 @@ impl FenceState {
     }
 }
 
+/// The result of waiting for fences.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum FenceWaitStatus {
+    Signalled,
+    /// The timeout expired before the fences were signalled.
+    Timeout,
+}
+
 /// A wrapper around a Vulkan fence object.
 ///
 /// Fences are a synchronization primitive that can be used to insert a dependency
@@ -105,7 +113,7 @@ impl Drop for Fence {
     fn drop(&mut self) {
         if let Some(device) = self.owner.upgrade() {
             if let FenceState::Armed { .. } = &self.state {
-                _ = device.wait_fences(&mut [self], true);
+                _ = device.wait_fences(&mut [self], true, None);
             }
 
             unsafe { device.destroy_fence(self.handle) };
@@ -1,12 +1,14 @@
 use std::mem::ManuallyDrop;
 use std::num::NonZeroU64;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use glam::{UVec2, UVec3};
 use gpu_alloc::MemoryBlock;
+use smallvec::SmallVec;
 use vulkanalia::prelude::v1_0::*;
 
 use crate::device::WeakDevice;
+use crate::resources::ImageSubresourceRange;
 use crate::util::{FromGfx, ToVk};
 
 /// Image dimensions.
@@ -135,6 +137,19 @@ pub enum Samples {
     _64,
 }
 
+impl Samples {
+    /// All sample counts in descending order.
+    pub const ALL_DESC: [Self; 7] = [
+        Self::_64,
+        Self::_32,
+        Self::_16,
+        Self::_8,
+        Self::_4,
+        Self::_2,
+        Self::_1,
+    ];
+}
+
 impl FromGfx<Samples> for vk::SampleCountFlags {
     fn from_gfx(value: Samples) -> Self {
         match value {
@@ -224,6 +239,27 @@ pub struct ImageInfo {
     pub samples: Samples,
     pub array_layers: u32,
     pub usage: ImageUsageFlags,
+    pub flags: ImageCreateFlags,
+    /// Debug name of the image, see [`Device::set_object_name`].
+    ///
+    /// [`Device::set_object_name`]: crate::Device::set_object_name
+    pub label: Option<&'static str>,
+}
+
+impl ImageInfo {
+    /// Returns the extent of the mip `level`, unused dimensions are `1`.
+    pub fn mip_extent(&self, level: u32) -> UVec3 {
+        let extent = match self.extent {
+            ImageExtent::D1 { width } => UVec3::new(width, 1, 1),
+            ImageExtent::D2 { width, height } => UVec3::new(width, height, 1),
+            ImageExtent::D3 {
+                width,
+                height,
+                depth,
+            } => UVec3::new(width, height, depth),
+        };
+        (extent >> level).max(UVec3::ONE)
+    }
 }
 
 bitflags::bitflags! {
@@ -268,6 +304,27 @@ impl FromGfx<ImageUsageFlags> for vk::ImageUsageFlags {
     }
 }
 
+bitflags::bitflags! {
+    /// Bitmask specifying additional parameters of an image.
+    #[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+    pub struct ImageCreateFlags: u32 {
+        /// Allows creating cube views of the image.
+        ///
+        /// NOTE: Requires a 2D image with at least 6 array layers of equal width and height.
+        const CUBE_COMPATIBLE = 1 << 4;
+    }
+}
+
+impl FromGfx<ImageCreateFlags> for vk::ImageCreateFlags {
+    fn from_gfx(value: ImageCreateFlags) -> Self {
+        let mut res = Self::empty();
+        if value.contains(ImageCreateFlags::CUBE_COMPATIBLE) {
+            res |= Self::CUBE_COMPATIBLE;
+        }
+        res
+    }
+}
+
 /// A wrapper around a Vulkan image object.
 ///
 /// Images represent multidimensional - up to 3 - arrays of data which can be used
@@ -290,6 +347,7 @@ impl Image {
         Self {
             inner: Arc::new(Inner {
                 handle,
+                layouts: Mutex::new(TrackedLayouts::new(&info)),
                 info,
                 owner,
                 source: ImageSource::Device {
@@ -308,6 +366,7 @@ impl Image {
         Self {
             inner: Arc::new(Inner {
                 handle,
+                layouts: Mutex::new(TrackedLayouts::new(&info)),
                 info,
                 owner,
                 source: ImageSource::Surface { id },
@@ -323,6 +382,56 @@ impl Image {
         self.inner.handle
     }
 
+    /// Returns the tracked layout of the subresource range.
+    ///
+    /// Returns `None` if the layout is undefined or differs between subresources.
+    pub fn tracked_layout(&self, range: &ImageSubresourceRange) -> Option<ImageLayout> {
+        self.inner.layouts.lock().unwrap().uniform(range).flatten()
+    }
+
+    /// Overrides the tracked layout of the subresource range.
+    ///
+    /// Must be used when the layout is changed outside of the recorded commands
+    /// (e.g. by the presentation engine). `None` marks the contents as undefined.
+    pub fn assume_layout(&self, range: &ImageSubresourceRange, layout: Option<ImageLayout>) {
+        self.inner.layouts.lock().unwrap().set(range, layout);
+    }
+
+    /// Returns subresource ranges of the same tracked layout within the range.
+    pub(crate) fn tracked_layout_runs(
+        &self,
+        range: &ImageSubresourceRange,
+    ) -> SmallVec<[(ImageSubresourceRange, Option<ImageLayout>); 1]> {
+        self.inner.layouts.lock().unwrap().runs(range)
+    }
+
+    /// Updates the tracked layout after a recorded layout transition.
+    ///
+    /// In debug builds also checks that the declared old layout matches
+    /// the tracked one.
+    pub(crate) fn track_transition(
+        &self,
+        range: &ImageSubresourceRange,
+        old_layout: Option<ImageLayout>,
+        new_layout: ImageLayout,
+    ) {
+        let mut layouts = self.inner.layouts.lock().unwrap();
+        if let Some(old_layout) = old_layout {
+            layouts.debug_assert_layout(self, range, old_layout);
+        }
+        layouts.set(range, Some(new_layout));
+    }
+
+    /// Checks that the tracked layout of the range matches the declared one.
+    ///
+    /// NOTE: Does nothing in release builds.
+    pub(crate) fn debug_assert_layout(&self, range: &ImageSubresourceRange, layout: ImageLayout) {
+        if cfg!(debug_assertions) {
+            let layouts = self.inner.layouts.lock().unwrap();
+            layouts.debug_assert_layout(self, range, layout);
+        }
+    }
+
     pub fn try_dispose_as_surface(mut self) -> Result<(), Self> {
         if matches!(&self.inner.source, ImageSource::Surface { .. })
             && Arc::get_mut(&mut self.inner).is_some()
@@ -369,6 +478,7 @@ struct Inner {
     info: ImageInfo,
     source: ImageSource,
     owner: WeakDevice,
+    layouts: Mutex<TrackedLayouts>,
 }
 
 impl Drop for Inner {
@@ -416,6 +526,141 @@ impl std::fmt::Debug for ImageSource {
     }
 }
 
+/// Last known layouts of image subresources.
+///
+/// Layouts are updated while commands are recorded, so command buffers which
+/// use the same image must be submitted in the order of their recording.
+///
+/// NOTE: Aspects of the subresource are tracked together.
+struct TrackedLayouts {
+    array_layers: u32,
+    /// Layouts of subresources, indexed by `mip_level * array_layers + array_layer`.
+    layouts: Box<[Option<ImageLayout>]>,
+}
+
+impl TrackedLayouts {
+    fn new(info: &ImageInfo) -> Self {
+        let len = (info.mip_levels * info.array_layers) as usize;
+        Self {
+            array_layers: info.array_layers,
+            layouts: vec![None; len].into_boxed_slice(),
+        }
+    }
+
+    fn mip_levels(&self) -> u32 {
+        self.layouts.len() as u32 / self.array_layers.max(1)
+    }
+
+    /// Returns layer indices of the range for each mip level.
+    fn level_slices(
+        &self,
+        range: &ImageSubresourceRange,
+    ) -> impl Iterator<Item = (u32, std::ops::Range<usize>)> {
+        let array_layers = self.array_layers;
+        let first_layer = range.first_array_layer.min(array_layers);
+        let last_layer = range
+            .first_array_layer
+            .saturating_add(range.array_layer_count)
+            .min(array_layers);
+        let first_level = range.first_mip_level.min(self.mip_levels());
+        let last_level = range
+            .first_mip_level
+            .saturating_add(range.mip_level_count)
+            .min(self.mip_levels());
+
+        (first_level..last_level).map(move |level| {
+            let offset = (level * array_layers) as usize;
+            (
+                level,
+                offset + first_layer as usize..offset + last_layer as usize,
+            )
+        })
+    }
+
+    /// Returns the layout shared by all subresources of the range.
+    fn uniform(&self, range: &ImageSubresourceRange) -> Option<Option<ImageLayout>> {
+        let mut layouts = self
+            .level_slices(range)
+            .flat_map(|(_, slice)| self.layouts[slice].iter().copied());
+        let first = layouts.next()?;
+        layouts.all(|layout| layout == first).then_some(first)
+    }
+
+    /// Splits the range into parts with the same layout.
+    ///
+    /// Returns the whole range if all subresources share the layout,
+    /// otherwise runs of consecutive layers of each mip level.
+    fn runs(
+        &self,
+        range: &ImageSubresourceRange,
+    ) -> SmallVec<[(ImageSubresourceRange, Option<ImageLayout>); 1]> {
+        let mut result = SmallVec::new();
+        if let Some(layout) = self.uniform(range) {
+            result.push((*range, layout));
+            return result;
+        }
+
+        for (level, slice) in self.level_slices(range) {
+            let offset = (level * self.array_layers) as usize;
+            let layouts = &self.layouts[slice.clone()];
+
+            let mut start = 0;
+            while start < layouts.len() {
+                let layout = layouts[start];
+                let len = layouts[start..]
+                    .iter()
+                    .take_while(|item| **item == layout)
+                    .count();
+
+                let first_layer = (slice.start - offset + start) as u32;
+                result.push((
+                    ImageSubresourceRange::new(
+                        range.aspect,
+                        level..level + 1,
+                        first_layer..first_layer + len as u32,
+                    ),
+                    layout,
+                ));
+                start += len;
+            }
+        }
+        result
+    }
+
+    fn set(&mut self, range: &ImageSubresourceRange, layout: Option<ImageLayout>) {
+        for (_, slice) in self.level_slices(range) {
+            self.layouts[slice].fill(layout);
+        }
+    }
+
+    fn debug_assert_layout(
+        &self,
+        image: &Image,
+        range: &ImageSubresourceRange,
+        layout: ImageLayout,
+    ) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        for (level, slice) in self.level_slices(range) {
+            for (i, tracked) in self.layouts[slice.clone()].iter().enumerate() {
+                // NOTE: Subresources with unknown layouts are not checked
+                let Some(tracked) = tracked else {
+                    continue;
+                };
+                debug_assert_eq!(
+                    *tracked,
+                    layout,
+                    "declared layout of image {image:?} (level {level}, layer {}) \
+                    doesn't match the tracked one",
+                    slice.start % self.array_layers.max(1) as usize + i,
+                );
+            }
+        }
+    }
+}
+
 /// Components of a [`Format`].
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 #[allow(clippy::upper_case_acronyms)]
@@ -448,8 +693,63 @@ pub enum FormatType {
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct FormatDescription<Channels, Bits, Type> {
     pub channels: Channels,
+    /// Bits per channel, `0` for block-compressed formats.
     pub bits: Bits,
     pub ty: Type,
+    pub block: FormatBlock,
+}
+
+/// The smallest unit of image data of a [`Format`].
+///
+/// Uncompressed formats have `1x1` blocks of a single texel.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct FormatBlock {
+    pub width: u32,
+    pub height: u32,
+    /// Size of the block in bytes.
+    pub size: u32,
+}
+
+impl FormatBlock {
+    const fn texel(channels: FormatChannels, bits: u32) -> Self {
+        let count = match channels {
+            FormatChannels::R | FormatChannels::D | FormatChannels::S => 1,
+            FormatChannels::RG | FormatChannels::DS => 2,
+            FormatChannels::RGB | FormatChannels::BGR => 3,
+            FormatChannels::RGBA | FormatChannels::BGRA => 4,
+        };
+        Self {
+            width: 1,
+            height: 1,
+            size: count * bits / 8,
+        }
+    }
+
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        self.width > 1 || self.height > 1
+    }
+
+    /// Returns `true` if the `extent` consists of whole blocks.
+    pub fn is_aligned(&self, extent: UVec2) -> bool {
+        extent.x % self.width == 0 && extent.y % self.height == 0
+    }
+
+    /// Returns the number of blocks which cover the `extent`.
+    ///
+    /// NOTE: Partial blocks at the right and bottom edges are counted as whole ones.
+    pub fn count(&self, extent: UVec2) -> UVec2 {
+        UVec2::new(
+            extent.x.div_ceil(self.width),
+            extent.y.div_ceil(self.height),
+        )
+    }
+
+    /// Returns the size in bytes of tightly packed data of the `extent`.
+    pub fn data_size(&self, extent: UVec2) -> usize {
+        let count = self.count(extent);
+        count.x as usize * count.y as usize * self.size as usize
+    }
 }
 
 macro_rules! declare_format {
@@ -457,7 +757,10 @@ macro_rules! declare_format {
         $(#[$format_meta:meta])*
         $enum_name:ident,
         {
-            $($ident:ident => $orig:ident as ($channels:ident, $bits:literal, $ty:ident)),*$(,)?
+            $(
+                $ident:ident => $orig:ident as ($channels:ident, $bits:literal, $ty:ident)
+                $(in ($block_width:literal x $block_height:literal, $block_size:literal))?
+            ),*$(,)?
         }
     ) => {
         $(#[$format_meta])*
@@ -473,6 +776,10 @@ macro_rules! declare_format {
                         channels: FormatChannels::$channels,
                         bits: $bits,
                         ty: FormatType::$ty,
+                        block: declare_format!(
+                            @block $channels, $bits
+                            $(, $block_width, $block_height, $block_size)?
+                        ),
                     }),*,
                 }
             }
@@ -493,6 +800,16 @@ macro_rules! declare_format {
             }
         }
     };
+    (@block $channels:ident, $bits:literal) => {
+        FormatBlock::texel(FormatChannels::$channels, $bits)
+    };
+    (@block $channels:ident, $bits:literal, $width:literal, $height:literal, $size:literal) => {
+        FormatBlock {
+            width: $width,
+            height: $height,
+            size: $size,
+        }
+    };
 }
 
 declare_format! {
@@ -613,9 +930,23 @@ declare_format! {
         D16Unorm => D16_UNORM as (D, 16, Unorm),
         D32Sfloat => D32_SFLOAT as (D, 32, Sfloat),
         S8Uint => S8_UINT as (S, 8, Uint),
-        D16UnormS8Uint => D16_UNORM_S8_UINT as (DS, 16, Unorm),
-        D24UnormS8Uint => D24_UNORM_S8_UINT as (DS, 24, Unorm),
-        D32SfloatS8Uint => D32_SFLOAT_S8_UINT as (DS, 32, Sfloat),
+        D16UnormS8Uint => D16_UNORM_S8_UINT as (DS, 16, Unorm) in (1 x 1, 3),
+        D24UnormS8Uint => D24_UNORM_S8_UINT as (DS, 24, Unorm) in (1 x 1, 4),
+        D32SfloatS8Uint => D32_SFLOAT_S8_UINT as (DS, 32, Sfloat) in (1 x 1, 5),
+
+        BC1RGBUnorm => BC1_RGB_UNORM_BLOCK as (RGB, 0, Unorm) in (4 x 4, 8),
+        BC1RGBSrgb => BC1_RGB_SRGB_BLOCK as (RGB, 0, Srgb) in (4 x 4, 8),
+        BC1RGBAUnorm => BC1_RGBA_UNORM_BLOCK as (RGBA, 0, Unorm) in (4 x 4, 8),
+        BC1RGBASrgb => BC1_RGBA_SRGB_BLOCK as (RGBA, 0, Srgb) in (4 x 4, 8),
+        BC3RGBAUnorm => BC3_UNORM_BLOCK as (RGBA, 0, Unorm) in (4 x 4, 16),
+        BC3RGBASrgb => BC3_SRGB_BLOCK as (RGBA, 0, Srgb) in (4 x 4, 16),
+        BC5RGUnorm => BC5_UNORM_BLOCK as (RG, 0, Unorm) in (4 x 4, 16),
+        BC5RGSnorm => BC5_SNORM_BLOCK as (RG, 0, Snorm) in (4 x 4, 16),
+        BC7RGBAUnorm => BC7_UNORM_BLOCK as (RGBA, 0, Unorm) in (4 x 4, 16),
+        BC7RGBASrgb => BC7_SRGB_BLOCK as (RGBA, 0, Srgb) in (4 x 4, 16),
+
+        ASTC4x4RGBAUnorm => ASTC_4x4_UNORM_BLOCK as (RGBA, 0, Unorm) in (4 x 4, 16),
+        ASTC4x4RGBASrgb => ASTC_4x4_SRGB_BLOCK as (RGBA, 0, Srgb) in (4 x 4, 16),
     }
 }
 
@@ -659,6 +990,11 @@ impl Format {
         )
     }
 
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        self.description().block.is_compressed()
+    }
+
     pub fn is_stencil(&self) -> bool {
         matches!(
             *self,
@@ -702,3 +1038,124 @@ impl FromGfx<ImageAspectFlags> for vk::ImageAspectFlags {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layouts(mip_levels: u32, array_layers: u32) -> TrackedLayouts {
+        TrackedLayouts::new(&ImageInfo {
+            extent: ImageExtent::D2 {
+                width: 64,
+                height: 64,
+            },
+            format: Format::RGBA8Unorm,
+            mip_levels,
+            samples: Samples::_1,
+            array_layers,
+            usage: ImageUsageFlags::SAMPLED,
+            flags: ImageCreateFlags::empty(),
+            label: None,
+        })
+    }
+
+    #[test]
+    fn uniform_layout_is_a_single_run() {
+        let mut layouts = layouts(4, 2);
+        let whole = ImageSubresourceRange::color(0..4, 0..2);
+        assert_eq!(layouts.uniform(&whole), Some(None));
+
+        layouts.set(&whole, Some(ImageLayout::TransferDstOptimal));
+        let runs = layouts.runs(&whole);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0], (whole, Some(ImageLayout::TransferDstOptimal)));
+    }
+
+    #[test]
+    fn mixed_layouts_are_split_into_runs() {
+        let mut layouts = layouts(3, 4);
+        let whole = ImageSubresourceRange::color(0..3, 0..4);
+        layouts.set(&whole, Some(ImageLayout::TransferDstOptimal));
+        layouts.set(
+            &ImageSubresourceRange::color(0..1, 0..4),
+            Some(ImageLayout::TransferSrcOptimal),
+        );
+        layouts.set(&ImageSubresourceRange::color(1..2, 1..3), None);
+        assert_eq!(layouts.uniform(&whole), None);
+
+        let runs = layouts.runs(&whole);
+        assert_eq!(
+            runs.as_slice(),
+            [
+                (
+                    ImageSubresourceRange::color(0..1, 0..4),
+                    Some(ImageLayout::TransferSrcOptimal)
+                ),
+                (
+                    ImageSubresourceRange::color(1..2, 0..1),
+                    Some(ImageLayout::TransferDstOptimal)
+                ),
+                (ImageSubresourceRange::color(1..2, 1..3), None),
+                (
+                    ImageSubresourceRange::color(1..2, 3..4),
+                    Some(ImageLayout::TransferDstOptimal)
+                ),
+                (
+                    ImageSubresourceRange::color(2..3, 0..4),
+                    Some(ImageLayout::TransferDstOptimal)
+                ),
+            ]
+        );
+
+        // Only the requested range is inspected
+        let range = ImageSubresourceRange::color(1..3, 3..4);
+        assert_eq!(
+            layouts.uniform(&range),
+            Some(Some(ImageLayout::TransferDstOptimal))
+        );
+    }
+
+    #[test]
+    fn compressed_mip_tails_are_padded_to_whole_blocks() {
+        let format = Format::BC7RGBAUnorm;
+        let block = format.description().block;
+        assert!(format.is_compressed());
+        assert_eq!((block.width, block.height, block.size), (4, 4, 16));
+
+        // 6x6 image has 3 mip levels: 6x6, 3x3 and 1x1
+        assert_eq!(block.data_size(UVec2::new(6, 6)), 4 * 16);
+        for extent in [UVec2::new(2, 2), UVec2::ONE] {
+            assert_eq!(block.count(extent), UVec2::ONE);
+            assert_eq!(block.data_size(extent), 16);
+            assert!(!block.is_aligned(extent));
+
+            let copy = crate::BufferImageCopy::tightly_packed(
+                format,
+                64,
+                crate::ImageSubresourceLayers::color(2, 0..1),
+                extent,
+            );
+            assert_eq!(copy.buffer_offset, 64);
+            assert_eq!(copy.buffer_row_length, 4);
+            assert_eq!(copy.buffer_image_height, 4);
+            assert_eq!(copy.image_extent, extent.extend(1));
+        }
+    }
+
+    #[test]
+    fn uncompressed_formats_have_single_texel_blocks() {
+        let block = Format::RGBA16Sfloat.description().block;
+        assert!(!block.is_compressed());
+        assert_eq!(block.size, 8);
+        assert_eq!(block.data_size(UVec2::new(3, 5)), 3 * 5 * 8);
+
+        let copy = crate::BufferImageCopy::tightly_packed(
+            Format::RGBA16Sfloat,
+            0,
+            crate::ImageSubresourceLayers::color(0, 0..1),
+            UVec2::new(3, 5),
+        );
+        assert_eq!((copy.buffer_row_length, copy.buffer_image_height), (3, 5));
+        assert_eq!(Format::D24UnormS8Uint.description().block.size, 4);
+    }
+}
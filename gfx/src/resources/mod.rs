@@ -7,7 +7,9 @@ pub use self::framebuffer::*;
 pub use self::image::*;
 pub use self::image_view::*;
 pub use self::pipeline::*;
+pub use self::pipeline_cache::*;
 pub use self::pipeline_layout::*;
+pub use self::query_pool::*;
 pub use self::render_pass::*;
 pub use self::sampler::*;
 pub use self::semaphore::*;
@@ -22,7 +24,9 @@ mod framebuffer;
 mod image;
 mod image_view;
 mod pipeline;
+mod pipeline_cache;
 mod pipeline_layout;
+mod query_pool;
 mod render_pass;
 mod sampler;
 mod semaphore;
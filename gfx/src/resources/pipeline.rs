@@ -134,6 +134,35 @@ impl std::hash::Hash for Bounds {
     }
 }
 
+/// Offset added to the depth of rasterized fragments.
+///
+/// NOTE: A non-zero `clamp` requires the `depthBiasClamp` device feature.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+impl Eq for DepthBias {}
+impl PartialEq for DepthBias {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        f32::to_bits(self.constant_factor) == f32::to_bits(other.constant_factor)
+            && f32::to_bits(self.clamp) == f32::to_bits(other.clamp)
+            && f32::to_bits(self.slope_factor) == f32::to_bits(other.slope_factor)
+    }
+}
+
+impl std::hash::Hash for DepthBias {
+    #[inline]
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_u32(f32::to_bits(self.constant_factor));
+        state.write_u32(f32::to_bits(self.clamp));
+        state.write_u32(f32::to_bits(self.slope_factor));
+    }
+}
+
 /// Specify the bind point of a pipeline object to a command buffer.
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum PipelineBindPoint {
@@ -183,6 +212,7 @@ pub struct Rasterizer {
     pub depth_test: Option<DepthTest>,
     pub stencil_tests: Option<StencilTests>,
     pub depth_bounds: Option<State<Bounds>>,
+    pub depth_bias: Option<DepthBias>,
     pub fragment_shader: Option<FragmentShader>,
     pub color_blend: ColorBlend,
 }
@@ -199,6 +229,7 @@ impl Default for Rasterizer {
             depth_test: None,
             stencil_tests: None,
             depth_bounds: None,
+            depth_bias: None,
             fragment_shader: None,
             color_blend: ColorBlend::default(),
         }
@@ -315,6 +346,9 @@ pub enum VertexFormat {
     Float64x3 = 32,
     /// Four double-precision floats (f64).
     Float64x4 = 33,
+    /// Three signed 10-bit values and a 2-bit value packed into u32. [-511, 511] converted
+    /// to float [-1, 1] `vec4<f32>` in shaders.
+    Snorm10_10_10_2 = 34,
 }
 
 impl VertexFormat {
@@ -333,7 +367,8 @@ impl VertexFormat {
             | Self::Float16x2
             | Self::Float32
             | Self::Uint32
-            | Self::Sint32 => 4,
+            | Self::Sint32
+            | Self::Snorm10_10_10_2 => 4,
             Self::Uint16x4
             | Self::Sint16x4
             | Self::Unorm16x4
@@ -388,6 +423,7 @@ impl FromGfx<VertexFormat> for vk::Format {
             VertexFormat::Float64x2 => Self::R64G64_SFLOAT,
             VertexFormat::Float64x3 => Self::R64G64B64_SFLOAT,
             VertexFormat::Float64x4 => Self::R64G64B64A64_SFLOAT,
+            VertexFormat::Snorm10_10_10_2 => Self::A2B10G10R10_SNORM_PACK32,
         }
     }
 }
@@ -663,14 +699,7 @@ impl std::hash::Hash for ColorBlend {
 impl Default for ColorBlend {
     fn default() -> Self {
         ColorBlend::Blending {
-            blending: Some(Blending {
-                color_src_factor: BlendFactor::SrcAlpha,
-                color_dst_factor: BlendFactor::OneMinusSrcAlpha,
-                color_op: BlendOp::Add,
-                alpha_src_factor: BlendFactor::One,
-                alpha_dst_factor: BlendFactor::OneMinusSrcAlpha,
-                alpha_op: BlendOp::Add,
-            }),
+            blending: Some(Blending::ALPHA),
             write_mask: ComponentMask::RGBA,
             constants: State::Static([0.0; 4]),
         }
@@ -770,6 +799,18 @@ pub struct Blending {
     pub alpha_op: BlendOp,
 }
 
+impl Blending {
+    /// Blends colors by the source alpha, the default blending.
+    pub const ALPHA: Self = Self {
+        color_src_factor: BlendFactor::SrcAlpha,
+        color_dst_factor: BlendFactor::OneMinusSrcAlpha,
+        color_op: BlendOp::Add,
+        alpha_src_factor: BlendFactor::One,
+        alpha_dst_factor: BlendFactor::OneMinusSrcAlpha,
+        alpha_op: BlendOp::Add,
+    };
+}
+
 /// Framebuffer blending factors.
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum BlendFactor {
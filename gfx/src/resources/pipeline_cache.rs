@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::device::WeakDevice;
+use crate::types::OutOfDeviceMemory;
+
+/// A wrapper around a Vulkan pipeline cache object.
+///
+/// Pipeline cache objects allow the result of pipeline construction to be reused
+/// between pipelines and between runs of an application. The contents of the cache
+/// can be retrieved with [`PipelineCache::data`] and passed back as an initial data
+/// when creating a new cache.
+#[derive(Clone)]
+#[repr(transparent)]
+pub struct PipelineCache {
+    inner: Arc<Inner>,
+}
+
+impl PipelineCache {
+    pub(crate) fn new(handle: vk::PipelineCache, owner: WeakDevice) -> Self {
+        Self {
+            inner: Arc::new(Inner { handle, owner }),
+        }
+    }
+
+    pub fn handle(&self) -> vk::PipelineCache {
+        self.inner.handle
+    }
+
+    pub(crate) fn owner(&self) -> &WeakDevice {
+        &self.inner.owner
+    }
+
+    /// Returns serialized cache data.
+    ///
+    /// NOTE: Returns an empty blob if the owner device was already destroyed.
+    pub fn data(&self) -> Result<Vec<u8>, OutOfDeviceMemory> {
+        match self.inner.owner.upgrade() {
+            Some(device) => device.get_pipeline_cache_data(self),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+impl std::fmt::Debug for PipelineCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            f.debug_struct("PipelineCache")
+                .field("handle", &self.inner.handle)
+                .field("owner", &self.inner.owner)
+                .finish()
+        } else {
+            std::fmt::Debug::fmt(&self.inner.handle, f)
+        }
+    }
+}
+
+impl Eq for PipelineCache {}
+impl PartialEq for PipelineCache {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl std::hash::Hash for PipelineCache {
+    #[inline]
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::ptr::hash(&*self.inner, state)
+    }
+}
+
+struct Inner {
+    handle: vk::PipelineCache,
+    owner: WeakDevice,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some(device) = self.owner.upgrade() {
+            unsafe { device.destroy_pipeline_cache(self.handle) }
+        }
+    }
+}
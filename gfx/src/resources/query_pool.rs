@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use vulkanalia::prelude::v1_0::*;
+
+use crate::device::WeakDevice;
+use crate::util::FromGfx;
+
+/// Query pool properties.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct QueryPoolInfo {
+    pub ty: QueryType,
+    pub count: u32,
+}
+
+/// Type of queries managed by the pool.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum QueryType {
+    Timestamp,
+}
+
+impl FromGfx<QueryType> for vk::QueryType {
+    fn from_gfx(value: QueryType) -> Self {
+        match value {
+            QueryType::Timestamp => Self::TIMESTAMP,
+        }
+    }
+}
+
+/// A wrapper around a Vulkan query pool object.
+///
+/// All queries must be reset before being written with
+/// [`Encoder::reset_query_pool`](crate::Encoder::reset_query_pool).
+#[derive(Clone)]
+#[repr(transparent)]
+pub struct QueryPool {
+    inner: Arc<Inner>,
+}
+
+impl QueryPool {
+    pub(crate) fn new(handle: vk::QueryPool, info: QueryPoolInfo, owner: WeakDevice) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                handle,
+                info,
+                owner,
+            }),
+        }
+    }
+
+    pub fn info(&self) -> &QueryPoolInfo {
+        &self.inner.info
+    }
+
+    pub fn handle(&self) -> vk::QueryPool {
+        self.inner.handle
+    }
+}
+
+impl std::fmt::Debug for QueryPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            f.debug_struct("QueryPool")
+                .field("handle", &self.inner.handle)
+                .field("info", &self.inner.info)
+                .field("owner", &self.inner.owner)
+                .finish()
+        } else {
+            std::fmt::Debug::fmt(&self.inner.handle, f)
+        }
+    }
+}
+
+impl Eq for QueryPool {}
+impl PartialEq for QueryPool {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl std::hash::Hash for QueryPool {
+    #[inline]
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::ptr::hash(&*self.inner, state)
+    }
+}
+
+struct Inner {
+    handle: vk::QueryPool,
+    info: QueryPoolInfo,
+    owner: WeakDevice,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some(device) = self.owner.upgrade() {
+            unsafe { device.destroy_query_pool(self.handle) }
+        }
+    }
+}
@@ -232,6 +232,10 @@ pub struct AttachmentInfo {
 pub struct Subpass {
     /// List of color attachment indices and their layouts.
     pub colors: Vec<(u32, ImageLayout)>,
+    /// List of resolve attachment indices and their layouts.
+    ///
+    /// Must be either empty or have the same length as `colors`.
+    pub resolves: Vec<(u32, ImageLayout)>,
     // Depth attachment index and layout.
     pub depth: Option<(u32, ImageLayout)>,
 }
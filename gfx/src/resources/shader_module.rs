@@ -156,6 +156,10 @@ impl FromGfx<ShaderType> for vk::ShaderStageFlags {
 /// Shader module info.
 pub struct ShaderModuleInfo {
     pub data: Box<[u32]>,
+    /// Debug name of the module, e.g. the path of the shader source.
+    ///
+    /// NOTE: Pipelines are named after the modules of their first stage.
+    pub label: Option<String>,
 }
 
 /// A wrapper around a Vulkan shader module.
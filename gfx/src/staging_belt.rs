@@ -0,0 +1,355 @@
+use std::mem::MaybeUninit;
+use std::sync::{Arc, Mutex};
+
+use crate::device::{Device, MapError};
+use crate::resources::{Buffer, BufferInfo, BufferUsage, MappedBuffer, MemoryUsage};
+use crate::types::OutOfDeviceMemory;
+
+/// A set of persistently mapped host-visible buffers for staging uploads.
+///
+/// Allocations are suballocated from shared chunks instead of creating a new
+/// buffer for each upload. A chunk is reused once all command buffers which
+/// reference it are completed (their epochs are closed when the submission
+/// fence is waited) and no allocation from it is alive.
+///
+/// NOTE: Chunks could be allocated from memory without the `HOST_COHERENT`
+/// property, so written allocations must be flushed with [`StagingBelt::flush`]
+/// before the commands which read them are submitted.
+pub struct StagingBelt {
+    device: Device,
+    chunks: Mutex<Chunks<Arc<MappedBuffer>>>,
+}
+
+impl StagingBelt {
+    pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+    pub fn new(device: &Device, chunk_size: usize) -> Self {
+        Self {
+            device: device.clone(),
+            chunks: Mutex::new(Chunks::new(chunk_size)),
+        }
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunks.lock().unwrap().chunk_size
+    }
+
+    /// Returns the number of currently allocated chunks.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.lock().unwrap().len()
+    }
+
+    /// Allocates `size` bytes with the specified alignment.
+    ///
+    /// Allocations larger than the chunk size get a dedicated chunk which
+    /// is freed once it is no longer used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn allocate(&self, size: usize, align: usize) -> Result<StagingAllocation, MapError> {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        let align_mask = (align - 1) | MIN_ALIGN_MASK;
+
+        let mut chunks = self.chunks.lock().unwrap();
+        let (chunk, offset) = chunks.allocate(size, align_mask, |capacity| {
+            create_chunk(&self.device, capacity)
+        })?;
+
+        Ok(StagingAllocation {
+            chunk: chunk.clone(),
+            offset,
+            // SAFETY: `offset + size` is within the mapped chunk.
+            ptr: unsafe { chunk.as_mut_ptr().add(offset) },
+            size,
+        })
+    }
+
+    /// Makes host writes to the allocation visible to the device.
+    ///
+    /// Does nothing if the chunk memory is coherent.
+    pub fn flush(&self, allocation: &StagingAllocation) -> Result<(), OutOfDeviceMemory> {
+        let range = allocation.offset..allocation.offset + allocation.size;
+        self.device
+            .flush_mapped_ranges([(allocation.chunk.as_ref(), range)])
+    }
+
+    /// Makes chunks which are no longer used available for new allocations.
+    ///
+    /// NOTE: Should be called once per frame after waiting for the frame fence.
+    pub fn recall(&self) {
+        // NOTE: Allocations hold their own references to the chunk and recorded
+        // command buffers to the chunk buffer, so the unique one is not in use.
+        self.chunks
+            .lock()
+            .unwrap()
+            .recall(|chunk| Arc::strong_count(chunk) == 1 && chunk.buffer().is_unique());
+    }
+}
+
+/// A mapped region of the staging buffer.
+///
+/// NOTE: The region is not reused while the allocation is alive.
+pub struct StagingAllocation {
+    chunk: Arc<MappedBuffer>,
+    offset: usize,
+    ptr: *mut MaybeUninit<u8>,
+    size: usize,
+}
+
+unsafe impl Send for StagingAllocation {}
+unsafe impl Sync for StagingAllocation {}
+
+impl StagingAllocation {
+    pub fn buffer(&self) -> &Buffer {
+        self.chunk.buffer()
+    }
+
+    /// Offset of the allocation in the buffer in bytes.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the data written into the allocation.
+    pub fn bytes(&self) -> &[MaybeUninit<u8>] {
+        // SAFETY: The region is mapped while the buffer is alive.
+        unsafe { std::slice::from_raw_parts(self.ptr, self.size) }
+    }
+
+    pub fn data(&mut self) -> &mut [MaybeUninit<u8>] {
+        // SAFETY: The region is mapped while the buffer is alive and is
+        // exclusively owned by this allocation.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.size) }
+    }
+
+    /// Copies `data` into the allocation at the specified offset in bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` doesn't fit into the allocation.
+    pub fn write<T>(&mut self, offset: usize, data: &[T])
+    where
+        T: bytemuck::Pod,
+    {
+        let bytes = bytemuck::cast_slice::<T, u8>(data);
+        let dst = &mut self.data()[offset..offset + bytes.len()];
+
+        // SAFETY: `dst` has exactly `bytes.len()` bytes.
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), dst.as_mut_ptr().cast(), bytes.len())
+        }
+    }
+}
+
+fn create_chunk(device: &Device, capacity: usize) -> Result<Arc<MappedBuffer>, MapError> {
+    let buffer = device.create_mappable_buffer(
+        BufferInfo {
+            align_mask: CHUNK_ALIGN_MASK,
+            size: capacity,
+            usage: BufferUsage::TRANSFER_SRC,
+            label: Some("staging belt chunk"),
+        },
+        MemoryUsage::UPLOAD,
+    )?;
+    device.map_persistent(buffer).map(Arc::new)
+}
+
+struct Chunks<C> {
+    chunk_size: usize,
+    /// Chunk which is used for new allocations.
+    active: Option<Chunk<C>>,
+    /// Filled or dedicated chunks which could still be in use.
+    used: Vec<Chunk<C>>,
+    free: Vec<Chunk<C>>,
+}
+
+impl<C> Chunks<C> {
+    fn new(chunk_size: usize) -> Self {
+        Self {
+            chunk_size,
+            active: None,
+            used: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.active.is_some() as usize + self.used.len() + self.free.len()
+    }
+
+    fn allocate<F, E>(
+        &mut self,
+        size: usize,
+        align_mask: usize,
+        make_chunk: F,
+    ) -> Result<(&C, usize), E>
+    where
+        F: FnOnce(usize) -> Result<C, E>,
+    {
+        if size > self.chunk_size {
+            self.used.push(Chunk {
+                inner: make_chunk(size)?,
+                capacity: size,
+                offset: size,
+            });
+            let chunk = self.used.last().unwrap();
+            return Ok((&chunk.inner, 0));
+        }
+
+        let fits = |chunk: &Chunk<C>| {
+            chunk.offset + crate::align_offset(align_mask, chunk.offset) + size <= chunk.capacity
+        };
+
+        if !matches!(&self.active, Some(chunk) if fits(chunk)) {
+            let chunk = match self.free.pop() {
+                Some(chunk) => chunk,
+                None => Chunk {
+                    inner: make_chunk(self.chunk_size)?,
+                    capacity: self.chunk_size,
+                    offset: 0,
+                },
+            };
+            if let Some(filled) = self.active.replace(chunk) {
+                self.used.push(filled);
+            }
+        }
+
+        let chunk = self.active.as_mut().unwrap();
+        let offset = chunk.offset + crate::align_offset(align_mask, chunk.offset);
+        chunk.offset = offset + size;
+        Ok((&chunk.inner, offset))
+    }
+
+    fn recall<F>(&mut self, is_unused: F)
+    where
+        F: Fn(&C) -> bool,
+    {
+        let mut i = 0;
+        while i < self.used.len() {
+            if !is_unused(&self.used[i].inner) {
+                i += 1;
+                continue;
+            }
+
+            let mut chunk = self.used.swap_remove(i);
+            // NOTE: Dedicated chunks are not reused
+            if chunk.capacity <= self.chunk_size {
+                chunk.offset = 0;
+                self.free.push(chunk);
+            }
+        }
+
+        if let Some(chunk) = &mut self.active {
+            if is_unused(&chunk.inner) {
+                chunk.offset = 0;
+            }
+        }
+    }
+}
+
+struct Chunk<C> {
+    inner: C,
+    capacity: usize,
+    offset: usize,
+}
+
+/// NOTE: Offsets are always aligned to words to be usable in most copy commands.
+const MIN_ALIGN_MASK: usize = 0b11;
+const CHUNK_ALIGN_MASK: usize = 0b1111;
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    const CHUNK_SIZE: usize = 64 * 1024;
+    const FRAMES_IN_FLIGHT: u32 = 2;
+
+    /// Chunk which is used until the specified frame is completed.
+    struct TestChunk {
+        used_until: Cell<u32>,
+    }
+
+    #[test]
+    fn allocations_are_aligned() {
+        let mut chunks = Chunks::<()>::new(CHUNK_SIZE);
+
+        let make_chunk = |_| Ok::<_, ()>(());
+        assert_eq!(chunks.allocate(3, 0b11, make_chunk).unwrap().1, 0);
+        assert_eq!(chunks.allocate(8, 0b11, make_chunk).unwrap().1, 4);
+        assert_eq!(chunks.allocate(1, 0b1111, make_chunk).unwrap().1, 16);
+        assert_eq!(chunks.allocate(1, 0b11, make_chunk).unwrap().1, 20);
+        assert_eq!(chunks.len(), 1);
+
+        // Doesn't fit into the remaining space
+        assert_eq!(
+            chunks
+                .allocate(CHUNK_SIZE - 16, 0b11, make_chunk)
+                .unwrap()
+                .1,
+            0
+        );
+        assert_eq!(chunks.len(), 2);
+    }
+
+    #[test]
+    fn dedicated_chunks_are_freed() {
+        let mut chunks = Chunks::<bool>::new(CHUNK_SIZE);
+
+        let (_, offset) = chunks
+            .allocate(CHUNK_SIZE + 1, 0b11, |_| Ok::<_, ()>(false))
+            .unwrap();
+        assert_eq!(offset, 0);
+        assert_eq!(chunks.len(), 1);
+
+        chunks.recall(|in_use| !in_use);
+        assert_eq!(chunks.len(), 0);
+    }
+
+    #[test]
+    fn chunk_count_stays_bounded() {
+        const FRAMES: u32 = 500;
+        const UPLOADS_PER_FRAME: usize = 4000;
+
+        let mut chunks = Chunks::<TestChunk>::new(CHUNK_SIZE);
+        let mut created = 0usize;
+        let mut created_after_warmup = None;
+
+        for frame in 0..FRAMES {
+            // Wait for the oldest frame in flight
+            let completed_frame = frame.checked_sub(FRAMES_IN_FLIGHT);
+            chunks.recall(|chunk| {
+                completed_frame.map_or(false, |completed| chunk.used_until.get() <= completed)
+            });
+
+            for i in 0..UPLOADS_PER_FRAME {
+                let size = 16 + (i * 7919 + frame as usize * 31) % 480;
+                let (chunk, _) = chunks
+                    .allocate(size, 0b11, |_| {
+                        created += 1;
+                        Ok::<_, ()>(TestChunk {
+                            used_until: Cell::new(frame),
+                        })
+                    })
+                    .unwrap();
+                chunk.used_until.set(frame);
+            }
+
+            if frame == 10 {
+                created_after_warmup = Some(created);
+            }
+        }
+
+        // Each frame uses ~1MB, so a few chunks per frame in flight are enough
+        let bytes_per_frame = UPLOADS_PER_FRAME * 496;
+        let max_chunks = (FRAMES_IN_FLIGHT as usize + 1) * (bytes_per_frame / CHUNK_SIZE + 1);
+        assert!(created <= max_chunks, "{created} > {max_chunks}");
+        assert_eq!(chunks.len(), created);
+        assert_eq!(Some(created), created_after_warmup);
+    }
+}
@@ -1,15 +1,20 @@
 use std::collections::VecDeque;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle};
 use shared::util::WithDefer;
 use vulkanalia::prelude::v1_0::*;
-use vulkanalia::vk::{KhrSurfaceExtension, KhrSwapchainExtension};
+use vulkanalia::vk::{GoogleDisplayTimingExtension, KhrSurfaceExtension, KhrSwapchainExtension};
 use vulkanalia::Instance;
 
 use crate::device::WeakDevice;
-use crate::resources::{Format, Image, ImageInfo, ImageUsageFlags, Samples, Semaphore};
+use crate::physical::DeviceFeature;
+use crate::resources::{
+    Format, FormatType, Image, ImageCreateFlags, ImageInfo, ImageSubresourceRange, ImageUsageFlags,
+    Samples, Semaphore,
+};
 use crate::types::{DeviceLost, OutOfDeviceMemory, SurfaceLost};
 use crate::util::{FromGfx, ToVk, TryFromVk};
 
@@ -74,6 +79,67 @@ impl FromGfx<PresentMode> for vk::PresentModeKHR {
     }
 }
 
+/// Color space in which the presentation engine interprets swapchain images.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// sRGB color space with the nonlinear transfer function.
+    ///
+    /// This is the only color space which is supported everywhere.
+    SrgbNonlinear,
+    /// Extended sRGB color space with the linear transfer function.
+    ExtendedSrgbLinear,
+    /// Extended sRGB color space with the nonlinear transfer function.
+    ExtendedSrgbNonlinear,
+    /// Display-P3 color space with the nonlinear transfer function.
+    DisplayP3Nonlinear,
+    /// BT.2020 color space with the SMPTE ST 2084 (PQ) transfer function.
+    Hdr10St2084,
+    /// Image values are passed to the display as is.
+    PassThrough,
+}
+
+impl TryFromVk<vk::ColorSpaceKHR> for ColorSpace {
+    fn try_from_vk(color_space: vk::ColorSpaceKHR) -> Option<Self> {
+        match color_space {
+            vk::ColorSpaceKHR::SRGB_NONLINEAR => Some(Self::SrgbNonlinear),
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => Some(Self::ExtendedSrgbLinear),
+            vk::ColorSpaceKHR::EXTENDED_SRGB_NONLINEAR_EXT => Some(Self::ExtendedSrgbNonlinear),
+            vk::ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT => Some(Self::DisplayP3Nonlinear),
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => Some(Self::Hdr10St2084),
+            vk::ColorSpaceKHR::PASS_THROUGH_EXT => Some(Self::PassThrough),
+            _ => None,
+        }
+    }
+}
+
+impl FromGfx<ColorSpace> for vk::ColorSpaceKHR {
+    fn from_gfx(color_space: ColorSpace) -> Self {
+        match color_space {
+            ColorSpace::SrgbNonlinear => Self::SRGB_NONLINEAR,
+            ColorSpace::ExtendedSrgbLinear => Self::EXTENDED_SRGB_LINEAR_EXT,
+            ColorSpace::ExtendedSrgbNonlinear => Self::EXTENDED_SRGB_NONLINEAR_EXT,
+            ColorSpace::DisplayP3Nonlinear => Self::DISPLAY_P3_NONLINEAR_EXT,
+            ColorSpace::Hdr10St2084 => Self::HDR10_ST2084_EXT,
+            ColorSpace::PassThrough => Self::PASS_THROUGH_EXT,
+        }
+    }
+}
+
+/// Preferred swapchain parameters.
+///
+/// Unsupported values are replaced with the closest supported ones.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapchainPreferences {
+    /// Number of swapchain images, clamped to the surface capabilities.
+    ///
+    /// One more image than the minimum is used by default.
+    pub image_count: Option<u32>,
+    /// Format and color space of swapchain images.
+    ///
+    /// The best supported format is used by default (sRGB formats are preferred).
+    pub format: Option<(Format, ColorSpace)>,
+}
+
 /// Wrapper around a surface object.
 pub struct Surface {
     window: Arc<dyn Window>,
@@ -82,7 +148,12 @@ pub struct Surface {
     swapchain: Option<Swapchain>,
     unused_swapchains: VecDeque<Swapchain>,
     swapchain_support: SwapchainSupport,
+    preferences: SwapchainPreferences,
+    /// Parameters of the swapchain which was not created due to a zero-sized window.
+    suspended_params: Option<SwapchainParams>,
     image_available: Semaphore,
+    /// Replaced surface handles which are destroyed with their swapchains.
+    retired_handles: Vec<vk::SurfaceKHR>,
 }
 
 impl Surface {
@@ -113,10 +184,63 @@ impl Surface {
             swapchain: None,
             unused_swapchains: VecDeque::new(),
             swapchain_support,
+            preferences: SwapchainPreferences::default(),
+            suspended_params: None,
             image_available,
+            retired_handles: Vec::new(),
         })
     }
 
+    /// Recreates the underlying surface from the stored window.
+    ///
+    /// The swapchain is reconfigured with the last parameters if it was
+    /// configured before.
+    pub fn recreate(&mut self) -> Result<(), CreateSurfaceError> {
+        let device = self
+            .owner
+            .upgrade()
+            .ok_or(SurfaceError::SurfaceLost(SurfaceLost))?;
+        let instance = device.graphics().instance();
+
+        let handle = create_raw_surface(instance, &*self.window)?
+            .with_defer(|surface| unsafe { instance.destroy_surface_khr(surface, None) });
+
+        let swapchain_support = SwapchainSupport::new(instance, device.physical(), *handle)?;
+        if swapchain_support
+            .supported_families
+            .iter()
+            .all(|item| !*item)
+        {
+            return Err(CreateSurfaceError::NoPresentQueueFound);
+        }
+
+        // NOTE: The old swapchain can't be used as `old_swapchain` for the new surface
+        let params = self.last_params();
+        if let Some(swapchain) = self.swapchain.take() {
+            self.unused_swapchains.push_back(swapchain);
+        }
+
+        let old_handle = std::mem::replace(&mut self.handle, handle.disarm());
+        self.retired_handles.push(old_handle);
+        self.swapchain_support = swapchain_support;
+
+        tracing::debug!(surface = ?self.handle, ?old_handle, "recreated surface");
+
+        if let Some(params) = params {
+            self.configure_ext(params.usage, params.format, params.mode)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the swapchain is ready to acquire images.
+    ///
+    /// NOTE: The swapchain is not created while the window has a zero size
+    /// (e.g. when it is minimized). Call [`Surface::update`] after the window
+    /// is resized to resume.
+    pub fn is_configured(&self) -> bool {
+        self.swapchain.is_some()
+    }
+
     /// Returns an underlying Vulkan surface handle.
     pub fn handle(&self) -> vk::SurfaceKHR {
         self.handle
@@ -127,34 +251,108 @@ impl Surface {
         &self.swapchain_support
     }
 
+    /// Returns an iterator over all present modes supported by the surface.
+    pub fn supported_present_modes(&self) -> impl Iterator<Item = PresentMode> + '_ {
+        self.swapchain_support.supported_present_modes()
+    }
+
+    /// Returns preferred swapchain parameters.
+    pub fn swapchain_preferences(&self) -> &SwapchainPreferences {
+        &self.preferences
+    }
+
+    /// Sets preferred swapchain parameters.
+    ///
+    /// NOTE: they are applied the next time the swapchain is configured.
+    pub fn set_swapchain_preferences(&mut self, preferences: SwapchainPreferences) {
+        self.preferences = preferences;
+    }
+
+    /// Returns the color space of the configured swapchain.
+    pub fn color_space(&self) -> Option<ColorSpace> {
+        self.swapchain
+            .as_ref()
+            .and_then(|swapchain| ColorSpace::try_from_vk(swapchain.color_space))
+    }
+
+    /// Returns the present mode of the configured (or suspended) swapchain.
+    pub fn present_mode(&self) -> Option<PresentMode> {
+        self.last_params().map(|params| params.mode)
+    }
+
+    pub(crate) fn swapchain_handle(&self) -> Option<vk::SwapchainKHR> {
+        self.swapchain.as_ref().map(|swapchain| swapchain.handle)
+    }
+
+    /// Returns the duration of the display refresh cycle.
+    ///
+    /// Returns `None` if the swapchain is not configured or
+    /// [`DeviceFeature::DisplayTiming`] is not enabled.
+    pub fn refresh_duration(&self) -> Result<Option<Duration>, SurfaceError> {
+        let Some(swapchain) = &self.swapchain else {
+            return Ok(None);
+        };
+        let device = self
+            .owner
+            .upgrade()
+            .ok_or(SurfaceError::SurfaceLost(SurfaceLost))?;
+        if !device.is_feature_enabled(DeviceFeature::DisplayTiming) {
+            return Ok(None);
+        }
+
+        let properties = unsafe {
+            device
+                .logical()
+                .get_refresh_cycle_duration_google(swapchain.handle)
+        }
+        .map_err(|e| match e {
+            vk::ErrorCode::DEVICE_LOST => SurfaceError::DeviceLost(DeviceLost),
+            vk::ErrorCode::SURFACE_LOST_KHR => SurfaceError::SurfaceLost(SurfaceLost),
+            _ => crate::unexpected_vulkan_error(e),
+        })?;
+
+        Ok(Some(Duration::from_nanos(properties.refresh_duration)))
+    }
+
+    /// Recreates the swapchain with the specified present mode.
+    ///
+    /// NOTE: configures the swapchain with the best parameters if it wasn't initialized before.
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> Result<(), SurfaceError> {
+        if let Some(params) = self.last_params() {
+            self.configure_ext(params.usage, params.format, mode)
+        } else {
+            let format = self.select_surface_format()?;
+            self.configure_ext(ImageUsageFlags::COLOR_ATTACHMENT, format, mode)
+        }
+    }
+
     /// Recreates the swapchain with the last parameters.
     ///
+    /// Also resumes the swapchain which was suspended due to a zero-sized window.
+    ///
     /// NOTE: doesn't initialize the swapchain if it wasn't initialized before.
     pub fn update(&mut self) -> Result<(), SurfaceError> {
-        if let Some(swapchain) = &mut self.swapchain {
-            let usage = swapchain.usage;
-            let format = swapchain.format;
-            let mode = swapchain.mode;
-            self.configure_ext(usage, format, mode)
+        if let Some(params) = self.last_params() {
+            self.configure_ext(params.usage, params.format, params.mode)
         } else {
             // TODO: configure with default best values instead?
             Ok(())
         }
     }
 
-    /// Configures the swapchain with the best parameters.
+    /// Configures the swapchain with the preferred or the best parameters.
     pub fn configure(&mut self) -> Result<(), SurfaceError> {
-        let format = self
-            .swapchain_support
-            .find_best_surface_format()
-            .ok_or(SurfaceError::NoSuitableFormat)?;
-
+        let format = self.select_surface_format()?;
         let mode = self.swapchain_support.find_best_present_mode();
 
         self.configure_ext(ImageUsageFlags::COLOR_ATTACHMENT, format, mode)
     }
 
     /// Configures the swapchain with the specified parameters.
+    ///
+    /// NOTE: the color space and the image count are selected from preferences.
+    /// The swapchain is not created while the window has a zero size, see
+    /// [`Surface::is_configured`].
     pub fn configure_ext(
         &mut self,
         usage: ImageUsageFlags,
@@ -180,32 +378,45 @@ impl Surface {
             return Err(SurfaceError::UsageNotSupported { usage });
         }
 
+        let preferred_color_space = self
+            .preferences
+            .format
+            .and_then(|(preferred, color_space)| (preferred == format).then_some(color_space));
         let surface_format = self
             .swapchain_support
-            .surface_formats
-            .iter()
-            .find(|item| Format::from_vk(item.format) == Some(format))
+            .find_surface_format(format, preferred_color_space)
             .ok_or(SurfaceError::FormatNotSupported { format })?;
 
         if self
             .swapchain_support
-            .present_modes
-            .iter()
-            .copied()
-            .filter_map(PresentMode::try_from_vk)
+            .supported_present_modes()
             .all(|item| item != mode)
         {
             return Err(SurfaceError::PresentModeNotSupported { mode });
         }
 
-        let mut image_count = capabilities.min_image_count + 1;
-        if capabilities.max_image_count != 0 && image_count > capabilities.max_image_count {
-            image_count = capabilities.max_image_count;
-        }
+        let image_count = self
+            .swapchain_support
+            .select_image_count(self.preferences.image_count);
 
-        let image_extent = self
+        let Some(image_extent) = self
             .swapchain_support
-            .compute_swapchain_extent(self.window.as_ref());
+            .compute_swapchain_extent(self.window.as_ref())
+        else {
+            // NOTE: Swapchains can't have a zero extent, so the old one is retired
+            // until the window is resized.
+            if let Some(swapchain) = self.swapchain.take() {
+                self.unused_swapchains.push_back(swapchain);
+            }
+            self.suspended_params = Some(SwapchainParams {
+                usage,
+                format,
+                mode,
+            });
+
+            tracing::debug!(surface = ?self.handle, "swapchain suspended due to zero extent");
+            return Ok(());
+        };
 
         let composite_alpha = {
             let bits = capabilities.supported_composite_alpha.bits();
@@ -269,6 +480,8 @@ impl Surface {
                     samples: Samples::_1,
                     array_layers: 1,
                     usage,
+                    flags: ImageCreateFlags::empty(),
+                    label: None,
                 };
                 let id = IMAGE_ID.fetch_add(1, Ordering::Relaxed).try_into().unwrap();
                 let image = Image::new_surface(handle, info, device.downgrade(), id);
@@ -283,14 +496,17 @@ impl Surface {
 
         let handle = handle.disarm();
 
+        self.suspended_params = None;
         self.swapchain = Some(Swapchain {
             handle,
             format,
+            color_space: surface_format.color_space,
             usage,
             mode,
             images,
             optimal: true,
             acquired_count: 0,
+            next_present_id: 1,
         });
 
         tracing::debug!(
@@ -313,6 +529,7 @@ impl Surface {
             .ok_or(SurfaceError::SurfaceLost(SurfaceLost))?;
         self.cleanup_unused_swapchains(&device);
 
+        let mut surface_recreated = false;
         let index = loop {
             let swapchain = self.swapchain.as_mut().ok_or(SurfaceError::NotConfigured)?;
 
@@ -345,6 +562,15 @@ impl Surface {
                     self.configure_ext(usage, format, mode)?;
                     continue;
                 }
+                Err(vk::ErrorCode::SURFACE_LOST_KHR) if !surface_recreated => {
+                    tracing::warn!("surface lost, recreating");
+                    if let Err(e) = self.recreate() {
+                        tracing::error!("failed to recreate surface: {e}");
+                        return Err(SurfaceError::SurfaceLost(SurfaceLost));
+                    }
+                    surface_recreated = true;
+                    continue;
+                }
                 Err(e) => {
                     return Err(match e {
                         vk::ErrorCode::OUT_OF_HOST_MEMORY => crate::out_of_host_memory(),
@@ -366,12 +592,21 @@ impl Surface {
         std::mem::swap(&mut image_state.acquire, &mut self.image_available);
         swapchain.acquired_count += 1;
 
+        let present_id = swapchain.next_present_id;
+        swapchain.next_present_id = swapchain.next_present_id.wrapping_add(1).max(1);
+
+        // NOTE: Contents of the acquired image are not preserved between frames,
+        // so its layout is reset instead of keeping the one set before presenting.
+        let image = &image_state.image;
+        image.assume_layout(&ImageSubresourceRange::whole(image.info()), None);
+
         Ok(SurfaceImage {
             handle: swapchain.handle,
             supported_families: &self.swapchain_support.supported_families,
             total_image_count,
             image: &image_state.image,
             index,
+            present_id,
             acquired_count: &mut swapchain.acquired_count,
             wait: &mut image_state.acquire,
             signal: &mut image_state.release,
@@ -380,6 +615,38 @@ impl Surface {
         })
     }
 
+    fn last_params(&self) -> Option<SwapchainParams> {
+        match &self.swapchain {
+            Some(swapchain) => Some(SwapchainParams {
+                usage: swapchain.usage,
+                format: swapchain.format,
+                mode: swapchain.mode,
+            }),
+            None => self.suspended_params,
+        }
+    }
+
+    fn select_surface_format(&self) -> Result<Format, SurfaceError> {
+        if let Some((format, color_space)) = self.preferences.format {
+            if self
+                .swapchain_support
+                .find_surface_format(format, Some(color_space))
+                .is_some_and(|item| item.color_space == color_space.to_vk())
+            {
+                return Ok(format);
+            }
+            tracing::warn!(
+                ?format,
+                ?color_space,
+                "preferred surface format is not supported, using the best available"
+            );
+        }
+
+        self.swapchain_support
+            .find_best_surface_format()
+            .ok_or(SurfaceError::NoSuitableFormat)
+    }
+
     fn cleanup_unused_swapchains(&mut self, device: &crate::device::Device) {
         let logical = device.logical();
 
@@ -400,6 +667,12 @@ impl Surface {
             unsafe { logical.destroy_swapchain_khr(swapchain.handle, None) };
             self.unused_swapchains.pop_front();
         }
+
+        // Retired surfaces can be destroyed only after all their swapchains
+        let instance = device.graphics().instance();
+        for handle in self.retired_handles.drain(..) {
+            unsafe { instance.destroy_surface_khr(handle, None) };
+        }
     }
 }
 
@@ -410,6 +683,7 @@ pub struct SurfaceImage<'a> {
     total_image_count: usize,
     image: &'a Image,
     index: u32,
+    present_id: u32,
     acquired_count: &'a mut u32,
     wait: &'a mut Semaphore,
     signal: &'a mut Semaphore,
@@ -433,6 +707,16 @@ impl<'a> SurfaceImage<'a> {
         self.index
     }
 
+    /// Returns the id used to match the presentation of the image with
+    /// [`Queue::get_past_presentation_timing`].
+    ///
+    /// NOTE: Ids start from 1 for each new swapchain.
+    ///
+    /// [`Queue::get_past_presentation_timing`]: crate::Queue::get_past_presentation_timing
+    pub fn present_id(&self) -> u32 {
+        self.present_id
+    }
+
     /// Returns the semaphore that should be waited on before using the image,
     /// and the semaphore that should be signaled after using the image.
     pub fn wait_signal(&mut self) -> [&mut Semaphore; 2] {
@@ -481,11 +765,20 @@ impl Window for winit::window::Window {
 struct Swapchain {
     handle: vk::SwapchainKHR,
     format: Format,
+    color_space: vk::ColorSpaceKHR,
     usage: ImageUsageFlags,
     mode: PresentMode,
     images: Vec<SwapchainImageState>,
     acquired_count: u32,
     optimal: bool,
+    next_present_id: u32,
+}
+
+#[derive(Clone, Copy)]
+struct SwapchainParams {
+    usage: ImageUsageFlags,
+    format: Format,
+    mode: PresentMode,
 }
 
 struct SwapchainImageState {
@@ -558,51 +851,120 @@ impl SwapchainSupport {
         })
     }
 
+    /// Finds the best format for presenting with sRGB color space.
+    ///
+    /// Prefers `BGRA8Srgb`, then any other sRGB format, then any format
+    /// with sRGB color space. Shaders must encode colors written into
+    /// non-sRGB formats manually.
     pub fn find_best_surface_format(&self) -> Option<Format> {
         const TARGET: Format = Format::BGRA8Srgb;
         const COLOR_SPACE: vk::ColorSpaceKHR = vk::ColorSpaceKHR::SRGB_NONLINEAR;
 
+        let mut srgb_target = None;
         let mut alternative_target = None;
         for &item in &self.surface_formats {
             let Some(format) = Format::from_vk(item.format) else {
                 continue;
             };
+            if item.color_space != COLOR_SPACE {
+                continue;
+            }
 
-            if format == TARGET && item.color_space == COLOR_SPACE {
+            if format == TARGET {
                 return Some(format);
-            } else if alternative_target.is_none() && item.color_space == COLOR_SPACE {
+            } else if srgb_target.is_none() && format.description().ty == FormatType::Srgb {
+                srgb_target = Some(format);
+            } else if alternative_target.is_none() {
                 alternative_target = Some(format);
             }
         }
 
-        alternative_target.or(self
+        srgb_target.or(alternative_target).or(self
             .surface_formats
             .iter()
             .find_map(|item| Format::from_vk(item.format)))
     }
 
-    pub fn find_best_present_mode(&self) -> PresentMode {
-        const TARGET: PresentMode = PresentMode::Mailbox;
-        const FALLBACK: PresentMode = PresentMode::Fifo;
+    /// Finds a surface format entry for the specified format.
+    ///
+    /// Prefers the specified color space, then sRGB, then any other one.
+    pub fn find_surface_format(
+        &self,
+        format: Format,
+        color_space: Option<ColorSpace>,
+    ) -> Option<vk::SurfaceFormatKHR> {
+        let candidates = self
+            .surface_formats
+            .iter()
+            .filter(|item| Format::from_vk(item.format) == Some(format));
+
+        let preferred = color_space.map(|color_space| color_space.to_vk());
+        candidates
+            .clone()
+            .find(|item| Some(item.color_space) == preferred)
+            .or_else(|| {
+                candidates
+                    .clone()
+                    .find(|item| item.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR)
+            })
+            .or_else(|| candidates.clone().next())
+            .copied()
+    }
+
+    /// Returns the number of swapchain images clamped to the surface capabilities.
+    ///
+    /// One more image than the minimum is used if no count is preferred.
+    pub fn select_image_count(&self, preferred: Option<u32>) -> u32 {
+        let capabilities = &self.capabilities;
+
+        let image_count = preferred
+            .unwrap_or(capabilities.min_image_count + 1)
+            .max(capabilities.min_image_count);
+        if capabilities.max_image_count != 0 {
+            image_count.min(capabilities.max_image_count)
+        } else {
+            image_count
+        }
+    }
 
+    pub fn supported_present_modes(&self) -> impl Iterator<Item = PresentMode> + '_ {
         self.present_modes
             .iter()
             .copied()
             .filter_map(PresentMode::try_from_vk)
+    }
+
+    pub fn find_best_present_mode(&self) -> PresentMode {
+        const TARGET: PresentMode = PresentMode::Mailbox;
+        const FALLBACK: PresentMode = PresentMode::Fifo;
+
+        self.supported_present_modes()
             .find(|p| *p == TARGET)
             .unwrap_or(FALLBACK)
     }
 
-    pub fn compute_swapchain_extent(&self, window: &dyn Window) -> vk::Extent2D {
+    /// Returns the extent of the swapchain images for the specified window.
+    ///
+    /// Returns `None` if the window has a zero size (e.g. when it is minimized).
+    pub fn compute_swapchain_extent(&self, window: &dyn Window) -> Option<vk::Extent2D> {
+        self.compute_extent(window.inner_size())
+    }
+
+    fn compute_extent(&self, (width, height): (u32, u32)) -> Option<vk::Extent2D> {
         let capabilities = &self.capabilities;
 
+        // NOTE: Some platforms report a zero current extent for minimized windows
         if capabilities.current_extent.width != u32::MAX {
-            return capabilities.current_extent;
+            let extent = capabilities.current_extent;
+            return (extent.width != 0 && extent.height != 0).then_some(extent);
+        }
+
+        if width == 0 || height == 0 {
+            return None;
         }
 
-        let (width, height) = window.inner_size();
         let clamp = |min: u32, max: u32, v: u32| min.max(max.min(v));
-        vk::Extent2D::builder()
+        let extent = vk::Extent2D::builder()
             .width(clamp(
                 capabilities.min_image_extent.width,
                 capabilities.max_image_extent.width,
@@ -613,7 +975,8 @@ impl SwapchainSupport {
                 capabilities.max_image_extent.height,
                 height,
             ))
-            .build()
+            .build();
+        Some(extent)
     }
 }
 
@@ -815,3 +1178,152 @@ pub enum SurfaceError {
 }
 
 static IMAGE_ID: AtomicU64 = AtomicU64::new(1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_support(formats: &[(vk::Format, vk::ColorSpaceKHR)]) -> SwapchainSupport {
+        SwapchainSupport {
+            supported_families: Box::new([true]),
+            capabilities: vk::SurfaceCapabilitiesKHR {
+                min_image_count: 2,
+                max_image_count: 4,
+                ..Default::default()
+            },
+            surface_formats: formats
+                .iter()
+                .map(|&(format, color_space)| vk::SurfaceFormatKHR {
+                    format,
+                    color_space,
+                })
+                .collect(),
+            present_modes: vec![vk::PresentModeKHR::FIFO],
+        }
+    }
+
+    #[test]
+    fn image_count_is_clamped() {
+        let mut support = make_support(&[]);
+        assert_eq!(support.select_image_count(None), 3);
+        assert_eq!(support.select_image_count(Some(0)), 2);
+        assert_eq!(support.select_image_count(Some(4)), 4);
+        assert_eq!(support.select_image_count(Some(8)), 4);
+
+        // Zero means that there is no limit
+        support.capabilities.max_image_count = 0;
+        assert_eq!(support.select_image_count(Some(8)), 8);
+    }
+
+    #[test]
+    fn color_space_falls_back_to_srgb() {
+        let support = make_support(&[
+            (
+                vk::Format::B8G8R8A8_UNORM,
+                vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            ),
+            (
+                vk::Format::B8G8R8A8_UNORM,
+                vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            ),
+            (
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+            ),
+        ]);
+
+        let find = |format, color_space| {
+            support
+                .find_surface_format(format, color_space)
+                .map(|item| item.color_space)
+        };
+
+        assert_eq!(
+            find(Format::BGRA8Unorm, Some(ColorSpace::ExtendedSrgbLinear)),
+            Some(vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT)
+        );
+        assert_eq!(
+            find(Format::BGRA8Unorm, Some(ColorSpace::Hdr10St2084)),
+            Some(vk::ColorSpaceKHR::SRGB_NONLINEAR)
+        );
+        assert_eq!(
+            find(Format::BGRA8Unorm, None),
+            Some(vk::ColorSpaceKHR::SRGB_NONLINEAR)
+        );
+        assert_eq!(
+            find(Format::RGBA16Sfloat, None),
+            Some(vk::ColorSpaceKHR::HDR10_ST2084_EXT)
+        );
+        assert_eq!(find(Format::BGRA8Srgb, None), None);
+
+        // The first format with sRGB color space is used as the best one
+        assert_eq!(support.find_best_surface_format(), Some(Format::BGRA8Unorm));
+    }
+
+    #[test]
+    fn srgb_surface_formats_are_preferred() {
+        let mut formats = vec![
+            (
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            ),
+            (
+                vk::Format::B8G8R8A8_UNORM,
+                vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            ),
+            (vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+        ];
+        let support = make_support(&formats);
+        assert_eq!(support.find_best_surface_format(), Some(Format::RGBA8Srgb));
+
+        formats.push((vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR));
+        let support = make_support(&formats);
+        assert_eq!(support.find_best_surface_format(), Some(Format::BGRA8Srgb));
+
+        // Formats with other color spaces are used only as the last resort
+        let support = make_support(&formats[..1]);
+        assert_eq!(
+            support.find_best_surface_format(),
+            Some(Format::RGBA16Sfloat)
+        );
+    }
+
+    #[test]
+    fn zero_sized_window_has_no_extent() {
+        let mut support = make_support(&[]);
+        support.capabilities.current_extent = vk::Extent2D {
+            width: u32::MAX,
+            height: u32::MAX,
+        };
+        support.capabilities.min_image_extent = vk::Extent2D {
+            width: 1,
+            height: 1,
+        };
+        support.capabilities.max_image_extent = vk::Extent2D {
+            width: 4096,
+            height: 4096,
+        };
+
+        // Minimized window
+        assert!(support.compute_extent((0, 0)).is_none());
+        assert!(support.compute_extent((800, 0)).is_none());
+
+        // Restored window
+        let extent = support.compute_extent((800, 600)).unwrap();
+        assert_eq!((extent.width, extent.height), (800, 600));
+
+        // Platforms which report the current extent ignore the window size
+        support.capabilities.current_extent = vk::Extent2D {
+            width: 0,
+            height: 0,
+        };
+        assert!(support.compute_extent((800, 600)).is_none());
+
+        support.capabilities.current_extent = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        let extent = support.compute_extent((0, 0)).unwrap();
+        assert_eq!((extent.width, extent.height), (800, 600));
+    }
+}
@@ -0,0 +1,29 @@
+//! Resource infos shared by the tests which don't need a device.
+
+use crate::resources::{
+    BufferInfo, BufferUsage, Format, ImageCreateFlags, ImageExtent, ImageInfo, ImageUsageFlags,
+    Samples,
+};
+
+pub(crate) fn buffer_info(usage: BufferUsage) -> BufferInfo {
+    BufferInfo {
+        align_mask: 0,
+        size: 64,
+        usage,
+        label: Some("test buffer"),
+    }
+}
+
+/// Returns the info of a sampled single-level image with one layer.
+pub(crate) fn image_info(format: Format, extent: ImageExtent) -> ImageInfo {
+    ImageInfo {
+        extent,
+        format,
+        mip_levels: 1,
+        samples: Samples::_1,
+        array_layers: 1,
+        usage: ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::SAMPLED,
+        flags: ImageCreateFlags::empty(),
+        label: Some("test image"),
+    }
+}
@@ -1,5 +1,9 @@
 pub(crate) use self::access::*;
 pub(crate) use self::traits::*;
+pub(crate) use self::usage::*;
 
 mod access;
+#[cfg(test)]
+pub(crate) mod fixtures;
 mod traits;
+mod usage;
@@ -0,0 +1,307 @@
+#[cfg(feature = "gfx-validation")]
+use crate::resources::{Buffer, ImageView};
+use crate::resources::{BufferInfo, BufferUsage, DescriptorSlice, ImageInfo, ImageUsageFlags};
+
+/// Panics if the buffer was created without the `required` usage.
+#[cfg(feature = "gfx-validation")]
+#[track_caller]
+pub(crate) fn check_buffer_usage(info: &BufferInfo, required: BufferUsage, used_as: &str) {
+    if info.usage.contains(required) {
+        return;
+    }
+    panic!(
+        "buffer `{}` used as {used_as} is missing the {:?} usage",
+        info.label.unwrap_or("<unnamed>"),
+        required.difference(info.usage),
+    );
+}
+
+/// Does nothing without the `gfx-validation` feature.
+#[cfg(not(feature = "gfx-validation"))]
+#[inline(always)]
+pub(crate) fn check_buffer_usage(_: &BufferInfo, _: BufferUsage, _: &str) {}
+
+/// Panics if the image was created without the `required` usage.
+#[cfg(feature = "gfx-validation")]
+#[track_caller]
+pub(crate) fn check_image_usage(info: &ImageInfo, required: ImageUsageFlags, used_as: &str) {
+    if info.usage.contains(required) {
+        return;
+    }
+    panic!(
+        "image `{}` used as {used_as} is missing the {:?} usage",
+        info.label.unwrap_or("<unnamed>"),
+        required.difference(info.usage),
+    );
+}
+
+/// Does nothing without the `gfx-validation` feature.
+#[cfg(not(feature = "gfx-validation"))]
+#[inline(always)]
+pub(crate) fn check_image_usage(_: &ImageInfo, _: ImageUsageFlags, _: &str) {}
+
+/// Panics if any resource of the descriptor write lacks the usage
+/// required by the descriptor type.
+#[cfg(feature = "gfx-validation")]
+#[track_caller]
+pub(crate) fn check_descriptor_usage(data: DescriptorSlice<'_>) {
+    match data {
+        DescriptorSlice::Sampler(_) => {}
+        DescriptorSlice::CombinedImageSampler(data) => check_views(
+            data.iter().map(|item| &item.view),
+            ImageUsageFlags::SAMPLED,
+            "a combined image sampler",
+        ),
+        DescriptorSlice::SampledImage(data) => check_views(
+            data.iter().map(|(view, _)| view),
+            ImageUsageFlags::SAMPLED,
+            "a sampled image",
+        ),
+        DescriptorSlice::StorageImage(data) => check_views(
+            data.iter().map(|(view, _)| view),
+            ImageUsageFlags::STORAGE,
+            "a storage image",
+        ),
+        DescriptorSlice::InputAttachment(data) => check_views(
+            data.iter().map(|(view, _)| view),
+            ImageUsageFlags::INPUT_ATTACHMENT,
+            "an input attachment",
+        ),
+        DescriptorSlice::UniformTexelBuffer(data) => check_buffers(
+            data.iter().map(|view| &view.info().buffer),
+            BufferUsage::UNIFORM_TEXEL,
+            "a uniform texel buffer",
+        ),
+        DescriptorSlice::StorageTexelBuffer(data) => check_buffers(
+            data.iter().map(|view| &view.info().buffer),
+            BufferUsage::STORAGE_TEXEL,
+            "a storage texel buffer",
+        ),
+        DescriptorSlice::UniformBuffer(data) | DescriptorSlice::UniformBufferDynamic(data) => {
+            check_buffers(
+                data.iter().map(|range| &range.buffer),
+                BufferUsage::UNIFORM,
+                "a uniform buffer",
+            )
+        }
+        DescriptorSlice::StorageBuffer(data) | DescriptorSlice::StorageBufferDynamic(data) => {
+            check_buffers(
+                data.iter().map(|range| &range.buffer),
+                BufferUsage::STORAGE,
+                "a storage buffer",
+            )
+        }
+    }
+}
+
+/// Does nothing without the `gfx-validation` feature.
+#[cfg(not(feature = "gfx-validation"))]
+#[inline(always)]
+pub(crate) fn check_descriptor_usage(_: DescriptorSlice<'_>) {}
+
+#[cfg(feature = "gfx-validation")]
+#[track_caller]
+fn check_views<'a>(
+    views: impl Iterator<Item = &'a ImageView>,
+    required: ImageUsageFlags,
+    used_as: &str,
+) {
+    for view in views {
+        check_image_usage(view.info().image.info(), required, used_as);
+    }
+}
+
+#[cfg(feature = "gfx-validation")]
+#[track_caller]
+fn check_buffers<'a>(
+    buffers: impl Iterator<Item = &'a Buffer>,
+    required: BufferUsage,
+    used_as: &str,
+) {
+    for buffer in buffers {
+        check_buffer_usage(buffer.info(), required, used_as);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec2;
+
+    use super::*;
+    #[cfg(feature = "gfx-validation")]
+    use crate::encoder::BufferImageCopy;
+    use crate::resources::Format;
+    #[cfg(feature = "gfx-validation")]
+    use crate::resources::{
+        BufferRange, DescriptorBindingFlags, DescriptorSetInfo, DescriptorSetLayoutBinding,
+        DescriptorSetLayoutFlags, DescriptorSetLayoutInfo, DescriptorSetWrite, DescriptorType,
+        ImageLayout, ImageSubresourceLayers, ImageViewInfo, IndexType, ShaderStageFlags,
+        UpdateDescriptorSet,
+    };
+    use crate::util::fixtures::{self, buffer_info};
+    #[cfg(feature = "gfx-validation")]
+    use crate::{Device, Graphics, Queue, SingleQueueQuery};
+
+    fn image_info(usage: ImageUsageFlags) -> ImageInfo {
+        ImageInfo {
+            usage,
+            ..fixtures::image_info(Format::RGBA8Unorm, UVec2::splat(4).into())
+        }
+    }
+
+    #[cfg(feature = "gfx-validation")]
+    fn create_device() -> (Device, Queue) {
+        Graphics::get_or_init()
+            .unwrap()
+            .get_physical_devices()
+            .unwrap()
+            .find_best()
+            .unwrap()
+            .create_logical_device(SingleQueueQuery::GRAPHICS)
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(not(feature = "gfx-validation"))]
+    fn checks_are_disabled_without_validation() {
+        let info = buffer_info(BufferUsage::INDEX);
+        check_buffer_usage(&info, BufferUsage::VERTEX, "a vertex buffer");
+        let info = image_info(ImageUsageFlags::SAMPLED);
+        check_image_usage(&info, ImageUsageFlags::TRANSFER_DST, "a copy destination");
+    }
+
+    #[test]
+    #[cfg(feature = "gfx-validation")]
+    #[should_panic(expected = "buffer `test buffer` used as a storage buffer is missing")]
+    fn storage_buffer_without_usage() {
+        let info = buffer_info(BufferUsage::UNIFORM | BufferUsage::TRANSFER_DST);
+        check_buffer_usage(&info, BufferUsage::STORAGE, "a storage buffer");
+    }
+
+    #[test]
+    #[cfg(feature = "gfx-validation")]
+    #[should_panic(expected = "used as a copy source is missing the BufferUsage(TRANSFER_SRC)")]
+    fn copy_source_without_usage() {
+        let info = buffer_info(BufferUsage::TRANSFER_DST);
+        check_buffer_usage(&info, BufferUsage::TRANSFER_SRC, "a copy source");
+    }
+
+    #[test]
+    #[cfg(feature = "gfx-validation")]
+    #[should_panic(expected = "used as a vertex buffer is missing the BufferUsage(VERTEX)")]
+    fn vertex_buffer_without_usage() {
+        let info = buffer_info(BufferUsage::INDEX);
+        check_buffer_usage(&info, BufferUsage::VERTEX, "a vertex buffer");
+    }
+
+    #[test]
+    #[cfg(feature = "gfx-validation")]
+    #[should_panic(expected = "image `test image` used as a copy destination is missing")]
+    fn copy_destination_image_without_usage() {
+        let info = image_info(ImageUsageFlags::SAMPLED);
+        check_image_usage(&info, ImageUsageFlags::TRANSFER_DST, "a copy destination");
+    }
+
+    #[test]
+    #[cfg(feature = "gfx-validation")]
+    #[ignore = "requires a Vulkan device"]
+    #[should_panic(expected = "image `test image` used as a sampled image is missing")]
+    fn sampled_image_descriptor_without_usage() {
+        let (device, _queue) = create_device();
+        let image = device
+            .create_image(image_info(ImageUsageFlags::STORAGE))
+            .unwrap();
+        let view = device.create_image_view(ImageViewInfo::new(image)).unwrap();
+
+        // Descriptors of other kinds only check their own usage
+        check_descriptor_usage(DescriptorSlice::StorageImage(&[(
+            view.clone(),
+            ImageLayout::General,
+        )]));
+        check_descriptor_usage(DescriptorSlice::SampledImage(&[(
+            view,
+            ImageLayout::ShaderReadOnlyOptimal,
+        )]));
+    }
+
+    #[test]
+    #[cfg(feature = "gfx-validation")]
+    #[ignore = "requires a Vulkan device"]
+    #[should_panic(expected = "buffer `test buffer` used as a storage buffer is missing \
+        the BufferUsage(STORAGE)")]
+    fn descriptor_sets_are_updated_with_checked_usage() {
+        let (device, _queue) = create_device();
+        let layout = device
+            .create_descriptor_set_layout(DescriptorSetLayoutInfo {
+                bindings: vec![DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: DescriptorType::StorageBuffer,
+                    count: 1,
+                    stages: ShaderStageFlags::COMPUTE,
+                    flags: DescriptorBindingFlags::empty(),
+                }],
+                flags: DescriptorSetLayoutFlags::empty(),
+            })
+            .unwrap();
+        let set = device
+            .create_descriptor_set(DescriptorSetInfo {
+                layout,
+                label: None,
+            })
+            .unwrap();
+        let buffer = device
+            .create_buffer(buffer_info(BufferUsage::UNIFORM))
+            .unwrap();
+
+        device.update_descriptor_sets(&[UpdateDescriptorSet {
+            set: &set,
+            writes: &[DescriptorSetWrite {
+                binding: 0,
+                element: 0,
+                data: DescriptorSlice::StorageBuffer(&[BufferRange::whole(buffer)]),
+            }],
+        }]);
+    }
+
+    #[test]
+    #[cfg(feature = "gfx-validation")]
+    #[ignore = "requires a Vulkan device"]
+    #[should_panic(expected = "image `test image` used as a copy destination is missing")]
+    fn images_are_copied_with_checked_usage() {
+        let (device, queue) = create_device();
+        let buffer = device
+            .create_buffer(buffer_info(BufferUsage::TRANSFER_SRC))
+            .unwrap();
+        let image = device
+            .create_image(image_info(ImageUsageFlags::SAMPLED))
+            .unwrap();
+
+        let mut encoder = queue.create_primary_encoder().unwrap();
+        encoder.copy_buffer_to_image(
+            &buffer,
+            &image,
+            ImageLayout::TransferDstOptimal,
+            &[BufferImageCopy::tightly_packed(
+                Format::RGBA8Unorm,
+                0,
+                ImageSubresourceLayers::color(0, 0..1),
+                UVec2::splat(4),
+            )],
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "gfx-validation")]
+    #[ignore = "requires a Vulkan device"]
+    #[should_panic(expected = "buffer `test buffer` used as an index buffer is missing \
+        the BufferUsage(INDEX)")]
+    fn index_buffers_are_bound_with_checked_usage() {
+        let (device, queue) = create_device();
+        let buffer = device
+            .create_buffer(buffer_info(BufferUsage::VERTEX))
+            .unwrap();
+
+        let mut encoder = queue.create_primary_encoder().unwrap();
+        encoder.bind_index_buffer(&buffer, 0, IndexType::U32);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use vulkanalia::vk;
+
+/// Severity of a validation layer message.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValidationSeverity {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+}
+
+impl ValidationSeverity {
+    fn from_vk(severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> Self {
+        if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+            Self::Error
+        } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+            Self::Warning
+        } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+            Self::Info
+        } else {
+            Self::Verbose
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Verbose,
+            1 => Self::Info,
+            2 => Self::Warning,
+            _ => Self::Error,
+        }
+    }
+}
+
+/// Source of a validation layer message.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum ValidationMessageKind {
+    General,
+    Validation,
+    Performance,
+}
+
+impl ValidationMessageKind {
+    fn from_vk(ty: vk::DebugUtilsMessageTypeFlagsEXT) -> Self {
+        if ty.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION) {
+            Self::Validation
+        } else if ty.contains(vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE) {
+            Self::Performance
+        } else {
+            Self::General
+        }
+    }
+}
+
+/// A message reported by the validation layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationMessage {
+    pub severity: ValidationSeverity,
+    pub kind: ValidationMessageKind,
+    /// Identifier of the validation check, used to suppress messages.
+    pub id: i32,
+    /// Name of the validation check (e.g. `VUID-vkCmdDraw-None-02859`).
+    pub id_name: Option<String>,
+    pub text: String,
+}
+
+/// Filter and storage of the validation layer messages.
+///
+/// NOTE: Accessed from the debug messenger callback on any thread.
+pub(crate) struct ValidationMessages {
+    min_severity: AtomicU8,
+    suppressed_ids: RwLock<HashSet<i32>>,
+    recent: Mutex<VecDeque<ValidationMessage>>,
+    capacity: usize,
+    error_count: AtomicU64,
+}
+
+impl ValidationMessages {
+    pub fn new(min_severity: ValidationSeverity, suppressed_ids: &[i32], capacity: usize) -> Self {
+        Self {
+            min_severity: AtomicU8::new(min_severity as u8),
+            suppressed_ids: RwLock::new(suppressed_ids.iter().copied().collect()),
+            recent: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            error_count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn min_severity(&self) -> ValidationSeverity {
+        ValidationSeverity::from_u8(self.min_severity.load(Ordering::Relaxed))
+    }
+
+    pub fn set_min_severity(&self, severity: ValidationSeverity) {
+        self.min_severity.store(severity as u8, Ordering::Relaxed);
+    }
+
+    pub fn set_suppressed(&self, id: i32, suppressed: bool) {
+        let mut ids = self.suppressed_ids.write().unwrap();
+        if suppressed {
+            ids.insert(id);
+        } else {
+            ids.remove(&id);
+        }
+    }
+
+    /// Returns `true` if the message with the specified severity and id
+    /// must be reported.
+    pub fn is_enabled(&self, severity: ValidationSeverity, id: i32) -> bool {
+        severity >= self.min_severity()
+            && !matches!(self.suppressed_ids.read(), Ok(ids) if ids.contains(&id))
+    }
+
+    /// Stores the message, evicting the oldest one if the buffer is full.
+    pub fn push(&self, message: ValidationMessage) {
+        if message.severity == ValidationSeverity::Error {
+            self.error_count.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+
+        // NOTE: Never panic here since this is called from the FFI callback
+        let Ok(mut recent) = self.recent.lock() else {
+            return;
+        };
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(message);
+    }
+
+    pub fn recent(&self) -> Vec<ValidationMessage> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.recent.lock().unwrap().clear();
+    }
+
+    pub fn error_count(&self) -> u64 {
+        self.error_count.load(Ordering::Relaxed)
+    }
+}
+
+/// Reports the message from the debug messenger callback.
+///
+/// # Safety
+///
+/// The following must be true:
+/// - `data` must be a valid pointer to the callback data.
+/// - `messages` must be either null or a valid pointer to [`ValidationMessages`].
+pub(crate) unsafe fn report_message(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    ty: vk::DebugUtilsMessageTypeFlagsEXT,
+    data: &vk::DebugUtilsMessengerCallbackDataEXT,
+    messages: *const ValidationMessages,
+) {
+    let severity = ValidationSeverity::from_vk(severity);
+    let id = data.message_id_number;
+
+    let messages = messages.as_ref();
+    if let Some(messages) = messages {
+        if !messages.is_enabled(severity, id) {
+            return;
+        }
+    }
+
+    let text = cstr_to_string(data.message).unwrap_or_default();
+    let id_name = cstr_to_string(data.message_id_name);
+
+    let id_name_str = id_name.as_deref().unwrap_or_default();
+    match severity {
+        ValidationSeverity::Error => {
+            tracing::error!(target: "validation", ?ty, id = id_name_str, "{text}")
+        }
+        ValidationSeverity::Warning => {
+            tracing::warn!(target: "validation", ?ty, id = id_name_str, "{text}")
+        }
+        ValidationSeverity::Info => {
+            tracing::debug!(target: "validation", ?ty, id = id_name_str, "{text}")
+        }
+        ValidationSeverity::Verbose => {
+            tracing::trace!(target: "validation", ?ty, id = id_name_str, "{text}")
+        }
+    }
+
+    if let Some(messages) = messages {
+        messages.push(ValidationMessage {
+            severity,
+            kind: ValidationMessageKind::from_vk(ty),
+            id,
+            id_name,
+            text,
+        });
+    }
+}
+
+unsafe fn cstr_to_string(ptr: *const std::ffi::c_char) -> Option<String> {
+    (!ptr.is_null()).then(|| std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(severity: ValidationSeverity, id: i32) -> ValidationMessage {
+        ValidationMessage {
+            severity,
+            kind: ValidationMessageKind::Validation,
+            id,
+            id_name: None,
+            text: format!("message {id}"),
+        }
+    }
+
+    #[test]
+    fn oldest_messages_are_evicted() {
+        let messages = ValidationMessages::new(ValidationSeverity::Verbose, &[], 3);
+        for id in 0..5 {
+            messages.push(message(ValidationSeverity::Error, id));
+        }
+
+        let ids = messages.recent().iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(ids, [2, 3, 4]);
+        assert_eq!(messages.error_count(), 5);
+
+        messages.clear();
+        assert!(messages.recent().is_empty());
+        assert_eq!(messages.error_count(), 5);
+    }
+
+    #[test]
+    fn messages_are_filtered() {
+        let messages = ValidationMessages::new(ValidationSeverity::Warning, &[42], 8);
+        assert!(messages.is_enabled(ValidationSeverity::Error, 1));
+        assert!(messages.is_enabled(ValidationSeverity::Warning, 1));
+        assert!(!messages.is_enabled(ValidationSeverity::Info, 1));
+        assert!(!messages.is_enabled(ValidationSeverity::Error, 42));
+
+        messages.set_suppressed(42, false);
+        messages.set_min_severity(ValidationSeverity::Verbose);
+        assert!(messages.is_enabled(ValidationSeverity::Error, 42));
+        assert!(messages.is_enabled(ValidationSeverity::Verbose, 1));
+    }
+}
@@ -0,0 +1,245 @@
+//! Integration with `bevy_ecs`.
+//!
+//! Entities with a [`Transform`] and a [`StaticMeshInstance`] or a [`DynamicMeshInstance`]
+//! are rendered as objects which follow the entity transform. Objects are removed
+//! along with the entity (or the mesh instance component).
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ::ecs::components::Transform;
+use bevy_ecs::prelude::*;
+use glam::Mat4;
+use shared::FastHashMap;
+
+use crate::{DynamicObjectHandle, MeshHandle, ObjectMaterials, RendererState, StaticObjectHandle};
+
+/// Renderer used by the systems of the [`RendererPlugin`].
+#[derive(Resource, Clone)]
+pub struct RendererResource(pub Arc<RendererState>);
+
+/// Time of the fixed update which is currently running.
+///
+/// NOTE: Must be updated before each run of the fixed update schedule.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct FixedUpdateTime {
+    pub updated_at: Instant,
+    pub step: Duration,
+}
+
+/// Mesh rendered as a static object.
+///
+/// NOTE: Changing the component recreates the object.
+#[derive(Debug, Clone, PartialEq, Component)]
+pub struct StaticMeshInstance {
+    pub mesh: MeshHandle,
+    pub materials: ObjectMaterials,
+}
+
+/// Mesh rendered as a dynamic object, which is interpolated between fixed updates.
+///
+/// NOTE: Changing the component recreates the object.
+#[derive(Debug, Clone, PartialEq, Component)]
+pub struct DynamicMeshInstance {
+    pub mesh: MeshHandle,
+    pub materials: ObjectMaterials,
+}
+
+/// Renderer objects of the entities with mesh instances.
+#[derive(Resource, Default)]
+pub struct RendererObjects {
+    static_objects: FastHashMap<Entity, StaticObjectHandle>,
+    dynamic_objects: FastHashMap<Entity, DynamicObjectHandle>,
+}
+
+impl RendererObjects {
+    /// Returns the object of the entity with [`StaticMeshInstance`].
+    ///
+    /// NOTE: Objects are added during the fixed update after the component is inserted.
+    pub fn static_object(&self, entity: Entity) -> Option<&StaticObjectHandle> {
+        self.static_objects.get(&entity)
+    }
+
+    /// Returns the object of the entity with [`DynamicMeshInstance`].
+    ///
+    /// NOTE: Objects are added during the fixed update after the component is inserted.
+    pub fn dynamic_object(&self, entity: Entity) -> Option<&DynamicObjectHandle> {
+        self.dynamic_objects.get(&entity)
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
+pub enum RendererSet {
+    /// Removes objects of despawned entities.
+    Cleanup,
+    /// Adds new objects and sends the changed transforms.
+    SyncObjects,
+    /// Marks the end of the fixed update for the renderer.
+    FinishFixedUpdate,
+}
+
+/// Keeps renderer objects in sync with the world.
+pub struct RendererPlugin {
+    renderer: Arc<RendererState>,
+}
+
+impl RendererPlugin {
+    pub fn new(renderer: Arc<RendererState>) -> Self {
+        Self { renderer }
+    }
+
+    /// Inserts the plugin resources and adds its systems to the fixed update `schedule`.
+    ///
+    /// The systems run after the `update_set`, which must contain all systems
+    /// that modify transforms of rendered entities. The last system calls
+    /// [`RendererState::finish_fixed_update`] with the [`FixedUpdateTime`].
+    ///
+    /// NOTE: [`World::clear_trackers`] must be called after each run of the
+    /// schedule, otherwise despawned entities are accumulated.
+    pub fn build(self, world: &mut World, schedule: &mut Schedule, update_set: impl SystemSet) {
+        world.insert_resource(FixedUpdateTime {
+            updated_at: Instant::now(),
+            step: self.renderer.fixed_timestep(),
+        });
+        world.insert_resource(RendererResource(self.renderer));
+        world.init_resource::<RendererObjects>();
+
+        schedule.configure_sets(
+            (
+                RendererSet::Cleanup,
+                RendererSet::SyncObjects,
+                RendererSet::FinishFixedUpdate,
+            )
+                .chain()
+                .after(update_set),
+        );
+        schedule.add_systems((
+            (
+                cleanup_static_objects_system,
+                cleanup_dynamic_objects_system,
+            )
+                .in_set(RendererSet::Cleanup),
+            (sync_static_objects_system, sync_dynamic_objects_system)
+                .in_set(RendererSet::SyncObjects),
+            finish_fixed_update_system.in_set(RendererSet::FinishFixedUpdate),
+        ));
+    }
+}
+
+fn cleanup_static_objects_system(
+    mut objects: ResMut<RendererObjects>,
+    mut removed: RemovedComponents<StaticMeshInstance>,
+    query: Query<(), With<StaticMeshInstance>>,
+) {
+    for entity in removed.read() {
+        // NOTE: The component could be inserted again, in which case
+        // the object is replaced during the sync.
+        if !query.contains(entity) {
+            // NOTE: Dropping the last handle sends the remove instruction
+            objects.static_objects.remove(&entity);
+        }
+    }
+}
+
+fn cleanup_dynamic_objects_system(
+    mut objects: ResMut<RendererObjects>,
+    mut removed: RemovedComponents<DynamicMeshInstance>,
+    query: Query<(), With<DynamicMeshInstance>>,
+) {
+    for entity in removed.read() {
+        if !query.contains(entity) {
+            objects.dynamic_objects.remove(&entity);
+        }
+    }
+}
+
+type ChangedMeshInstance<T> = Or<(Changed<Transform>, Changed<T>)>;
+
+fn sync_static_objects_system(
+    renderer: Res<RendererResource>,
+    mut objects: ResMut<RendererObjects>,
+    query: Query<
+        (Entity, Ref<Transform>, Ref<StaticMeshInstance>),
+        ChangedMeshInstance<StaticMeshInstance>,
+    >,
+    mut updated: Local<Vec<(Entity, Mat4)>>,
+) {
+    let renderer = &renderer.0;
+    let objects = &mut objects.static_objects;
+
+    for (entity, transform, instance) in &query {
+        if !instance.is_changed() {
+            updated.push((entity, transform.to_matrix()));
+            continue;
+        }
+
+        let result = renderer.add_static_object(
+            instance.mesh.clone(),
+            instance.materials.clone(),
+            &transform.to_matrix(),
+        );
+        match result {
+            Ok(handle) => {
+                objects.insert(entity, handle);
+            }
+            Err(e) => {
+                tracing::error!(?entity, "failed to add static object: {e:?}");
+                objects.remove(&entity);
+            }
+        }
+    }
+
+    // NOTE: All transforms are sent with a single instruction. It only fails
+    // if the rendering thread is stopped, which is reported separately.
+    let _ = renderer.update_static_objects(
+        updated
+            .drain(..)
+            .filter_map(|(entity, transform)| Some((objects.get(&entity)?, transform))),
+    );
+}
+
+fn sync_dynamic_objects_system(
+    renderer: Res<RendererResource>,
+    mut objects: ResMut<RendererObjects>,
+    query: Query<
+        (Entity, Ref<Transform>, Ref<DynamicMeshInstance>),
+        ChangedMeshInstance<DynamicMeshInstance>,
+    >,
+    mut updated: Local<Vec<(Entity, Mat4)>>,
+) {
+    let renderer = &renderer.0;
+    let objects = &mut objects.dynamic_objects;
+
+    for (entity, transform, instance) in &query {
+        if !instance.is_changed() {
+            updated.push((entity, transform.to_matrix()));
+            continue;
+        }
+
+        let result = renderer.add_dynamic_object(
+            instance.mesh.clone(),
+            instance.materials.clone(),
+            &transform.to_matrix(),
+        );
+        match result {
+            Ok(handle) => {
+                objects.insert(entity, handle);
+            }
+            Err(e) => {
+                tracing::error!(?entity, "failed to add dynamic object: {e:?}");
+                objects.remove(&entity);
+            }
+        }
+    }
+
+    let _ = renderer.update_dynamic_objects(
+        updated
+            .drain(..)
+            .filter_map(|(entity, transform)| Some((objects.get(&entity)?, transform))),
+        false,
+    );
+}
+
+fn finish_fixed_update_system(renderer: Res<RendererResource>, time: Res<FixedUpdateTime>) {
+    let _ = renderer.0.finish_fixed_update(time.updated_at, time.step);
+}
@@ -0,0 +1,185 @@
+//! Integration with `egui`.
+//!
+//! [`EguiOverlay`] converts the output of each egui frame into overlay meshes
+//! and keeps textures of the egui context in sync with the renderer.
+//! Handling of the window input is left to the application.
+
+use std::sync::Arc;
+
+use glam::{UVec2, Vec2};
+use shared::FastHashMap;
+
+use crate::{OverlayMesh, OverlayVertex, RendererError, RendererState, TextureHandle};
+
+/// Draws egui output through the renderer overlay pass.
+#[derive(Default)]
+pub struct EguiOverlay {
+    textures: FastHashMap<egui::TextureId, EguiTexture>,
+    next_user_texture_id: u64,
+}
+
+struct EguiTexture {
+    handle: TextureHandle,
+    /// Copy of the texels, used to apply partial updates.
+    ///
+    /// NOTE: Only textures managed by egui have it.
+    image: Option<egui::ColorImage>,
+}
+
+impl EguiOverlay {
+    /// Uploads changed textures, tessellates the shapes and replaces
+    /// the renderer overlay with them.
+    pub fn draw(
+        &mut self,
+        renderer: &Arc<RendererState>,
+        ctx: &egui::Context,
+        output: egui::FullOutput,
+    ) -> Result<(), RendererError> {
+        for (id, delta) in &output.textures_delta.set {
+            self.set_texture(renderer, *id, delta)?;
+        }
+
+        let primitives = ctx.tessellate(output.shapes, output.pixels_per_point);
+        renderer.set_overlay(self.make_meshes(primitives, output.pixels_per_point));
+
+        // NOTE: Freed textures are still alive while used by the previous overlay
+        for id in &output.textures_delta.free {
+            self.textures.remove(id);
+        }
+
+        Ok(())
+    }
+
+    /// Makes the renderer texture available for egui images.
+    ///
+    /// NOTE: The texture must contain premultiplied colors.
+    pub fn register_texture(&mut self, handle: TextureHandle) -> egui::TextureId {
+        let id = egui::TextureId::User(self.next_user_texture_id);
+        self.next_user_texture_id += 1;
+        self.textures.insert(
+            id,
+            EguiTexture {
+                handle,
+                image: None,
+            },
+        );
+        id
+    }
+
+    pub fn unregister_texture(&mut self, id: egui::TextureId) {
+        self.textures.remove(&id);
+    }
+
+    /// Uploads the whole texture on each change.
+    ///
+    /// NOTE: Partial updates are applied to the CPU copy, which is then uploaded
+    /// as a new texture. This is fine for the font atlas, which rarely changes.
+    fn set_texture(
+        &mut self,
+        renderer: &Arc<RendererState>,
+        id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) -> Result<(), RendererError> {
+        let patch = match &delta.image {
+            egui::ImageData::Color(image) => image.as_ref().clone(),
+            egui::ImageData::Font(image) => egui::ColorImage {
+                size: image.size,
+                pixels: image.srgba_pixels(None).collect(),
+            },
+        };
+
+        let image = match delta.pos {
+            None => patch,
+            Some([x, y]) => {
+                let Some(mut image) = self.textures.get_mut(&id).and_then(|t| t.image.take())
+                else {
+                    tracing::warn!(?id, "tried to partially update an unknown egui texture");
+                    return Ok(());
+                };
+
+                let width = patch.width();
+                for row in 0..patch.height() {
+                    let offset = (y + row) * image.width() + x;
+                    image.pixels[offset..offset + width]
+                        .copy_from_slice(&patch.pixels[row * width..(row + 1) * width]);
+                }
+                image
+            }
+        };
+
+        let data = image
+            .pixels
+            .iter()
+            .flat_map(|color| color.to_array())
+            .collect::<Vec<u8>>();
+        let handle = renderer.add_texture(
+            &data,
+            UVec2::new(image.width() as u32, image.height() as u32),
+            gfx::Format::RGBA8Srgb,
+        )?;
+
+        self.textures.insert(
+            id,
+            EguiTexture {
+                handle,
+                image: Some(image),
+            },
+        );
+        Ok(())
+    }
+
+    fn make_meshes(
+        &self,
+        primitives: Vec<egui::ClippedPrimitive>,
+        pixels_per_point: f32,
+    ) -> Vec<OverlayMesh> {
+        let to_pixels = |pos: egui::Pos2| Vec2::new(pos.x, pos.y) * pixels_per_point;
+
+        primitives
+            .into_iter()
+            .filter_map(|primitive| {
+                let egui::epaint::Primitive::Mesh(mesh) = primitive.primitive else {
+                    tracing::trace!("egui paint callbacks are not supported");
+                    return None;
+                };
+                if mesh.is_empty() {
+                    return None;
+                }
+
+                let Some(texture) = self.textures.get(&mesh.texture_id) else {
+                    tracing::warn!(id = ?mesh.texture_id, "egui mesh uses an unknown texture");
+                    return None;
+                };
+
+                // NOTE: Clip rects can be infinite, the overlay pass clamps them to the target
+                let min = to_pixels(primitive.clip_rect.min)
+                    .round()
+                    .clamp(Vec2::ZERO, Vec2::splat(MAX_CLIP_COORD));
+                let max = to_pixels(primitive.clip_rect.max)
+                    .round()
+                    .clamp(Vec2::ZERO, Vec2::splat(MAX_CLIP_COORD));
+                let clip_rect = gfx::Rect {
+                    offset: min.as_ivec2(),
+                    extent: (max - min).max(Vec2::ZERO).as_uvec2(),
+                };
+
+                Some(OverlayMesh {
+                    vertices: mesh
+                        .vertices
+                        .iter()
+                        .map(|vertex| OverlayVertex {
+                            position: to_pixels(vertex.pos),
+                            uv: Vec2::new(vertex.uv.x, vertex.uv.y),
+                            color: vertex.color.to_array(),
+                        })
+                        .collect(),
+                    indices: mesh.indices,
+                    texture: Some(texture.handle.clone()),
+                    clip_rect,
+                })
+            })
+            .collect()
+    }
+}
+
+const MAX_CLIP_COORD: f32 = (1 << 24) as f32;
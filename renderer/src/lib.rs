@@ -1,31 +1,84 @@
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::any::Any;
+use std::borrow::Cow;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak};
 use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use glam::Mat4;
-use shared::Embed;
+use glam::{Mat4, UVec2, Vec2, Vec3};
+use shared::util::lock_ignore_poison;
+use shared::{Embed, FastHashMap, FastHashSet};
 use winit::window::Window;
 
-pub use self::render_graph::materials;
+pub use gfx::{ColorSpace, Format, PresentMode, PresentStatus, ReadbackTicket, Samples};
+
+pub use self::managers::{MaterialArchetypeStats, MeshManagerStats};
+pub use self::render_graph::{
+    compute_nodes, materials, ComputeNode, GpuCullingStats, RenderGraphContext,
+    MAX_RESOLUTION_SCALE, MIN_RESOLUTION_SCALE,
+};
+pub use self::util::{
+    BindlessResourcesStats, BindlessSlotStats, DrawRequestResult, RenderTargetCacheStats,
+    ShaderCompileError, ShaderDiagnostic, ShaderDiagnosticSeverity, ShaderSnippet,
+};
 pub use crate::types::{
-    CameraProjection, Color, CubeMeshGenerator, DynamicObjectHandle, MaterialInstance,
-    MaterialInstanceHandle, MaterialInstanceTag, Mesh, MeshBuilder, MeshGenerator, MeshHandle,
-    Normal, PlaneMeshGenerator, Position, Sorting, SortingOrder, SortingReason, StaticObjectHandle,
-    Tangent, VertexAttribute, VertexAttributeData, VertexAttributeKind, UV0,
+    CameraProjection, CapsuleMeshGenerator, Color, CubeMeshGenerator, CylinderMeshGenerator,
+    DebugView, DepthMode, DirectionalLight, DynamicObjectHandle, FnLoadMip, FrameStats,
+    InstructionCounts, InstructionKind, MaterialAttributePolicy, MaterialInstance,
+    MaterialInstanceHandle, MaterialInstanceTag, MaterialRenderState, Mesh, MeshBuilder, MeshError,
+    MeshGenerator, MeshHandle, MipData, Normal, ObjectMaterials, ObjectOptions, OverlayMesh,
+    OverlayVertex, PackedNormal, PackedTangent, PackedUV0, PickRequest, PickResult,
+    PlaneMeshGenerator, Position, RenderTargetHandle, SamplerOverride, ShaderDataContext,
+    ShadowBias, Sorting, SortingOrder, SortingReason, SphereMeshGenerator, StaticObjectHandle,
+    StreamedTexture, Tangent, TextureHandle, TextureTag, Tonemap, TorusMeshGenerator,
+    VertexAttribute, VertexAttributeData, VertexAttributeEncoding, VertexAttributeKind,
+    ALL_OBJECT_LAYERS, MAX_RENDER_TARGETS_PER_FRAME, SHADOW_MAP_SIZE, UV0,
 };
 
-use crate::managers::{MaterialManager, MeshManager, ObjectManager, TimeManager};
-use crate::types::{RawMaterialInstanceHandle, RawMeshHandle, RawStaticObjectHandle};
+use crate::managers::{
+    default_vertex_attribute_offset, load_mips, resolve_max_anisotropy, validate_cube_texture_data,
+    validate_streamed_texture, validate_texture_data, validate_texture_extent, GpuMesh, GpuTexture,
+    MaterialManager, MeshManager, ObjectManager, RenderTargetCamera, RenderTargetManager,
+    StagedMesh, TextureManager, TextureStreamer, TimeManager,
+};
+use crate::render_graph::materials::{DebugLines, TextGlyphs};
+use crate::types::{
+    PendingPick, RawMaterialInstanceHandle, RawMeshHandle, RawStaticObjectHandle, RawTextureHandle,
+};
 use crate::util::{
-    BindlessResources, FrameResources, FreelistHandleAllocator, HandleAllocator, HandleData,
-    HandleDeleter, MultiBufferArena, RawResourceHandle, ScatterCopy, ShaderPreprocessor,
-    SimpleHandleAllocator,
+    AtomicSlot, BindlessResources, FontAtlas, FrameResources, FramebufferCache,
+    FreelistHandleAllocator, HandleAllocator, HandleData, HandleDeleter, InstructionQueue,
+    LoopBarrier, MultiBufferArena, RawResourceHandle, RenderPassCache, RenderPassContext,
+    ScatterCopy, ShaderPreprocessor, SimpleHandleAllocator,
 };
-use crate::worker::RendererWorker;
+use crate::worker::{FrameOutput, FrameTimeout, OffscreenTarget, RendererWorker};
 
 use self::types::{DynamicObjectTag, ObjectData, RawDynamicObjectHandle, StaticObjectTag};
 
+/// Opens a profiling scope which lasts until the end of the current block.
+///
+/// NOTE: Scopes are compiled out without the `profiling` feature.
+macro_rules! profile_scope {
+    ($($args:tt)*) => {
+        #[cfg(feature = "profiling")]
+        profiling::scope!($($args)*);
+    };
+}
+
+/// Marks the end of the frame for the profiler.
+macro_rules! profile_finish_frame {
+    () => {
+        #[cfg(feature = "profiling")]
+        profiling::finish_frame!();
+    };
+}
+
+#[cfg(feature = "ecs")]
+pub mod ecs;
+#[cfg(feature = "egui")]
+pub mod egui;
 mod managers;
 mod render_graph;
 mod types;
@@ -33,74 +86,306 @@ mod util;
 mod worker;
 
 pub struct RendererBuilder {
-    window: Arc<Window>,
+    target: RendererTarget,
     app_version: (u32, u32, u32),
     validation_layer: bool,
+    suppressed_validation_messages: Vec<i32>,
+    debug_labels: bool,
     optimize_shaders: bool,
     shaders_debug_info_enabled: bool,
+    pipeline_cache_path: Option<PathBuf>,
+    shaders_override_dir: Option<PathBuf>,
+    msaa_samples: gfx::Samples,
+    depth_mode: DepthMode,
+    present_mode: Option<gfx::PresentMode>,
+    swapchain_preferences: gfx::SwapchainPreferences,
+    preferred_device_name: Option<String>,
+    preferred_device_type: Option<gfx::DeviceType>,
+    default_anisotropy: Option<f32>,
+    buffer_device_address: bool,
+    gpu_culling: bool,
+    #[cfg(feature = "ab_glyph")]
+    font: Option<(Vec<u8>, f32)>,
+}
+
+/// Where the frames are rendered.
+enum RendererTarget {
+    Window(Arc<Window>),
+    /// Offscreen image of the specified size.
+    Headless(UVec2),
 }
 
 impl RendererBuilder {
+    fn new(target: RendererTarget) -> Self {
+        Self {
+            target,
+            app_version: (0, 0, 1),
+            validation_layer: false,
+            suppressed_validation_messages: Vec::new(),
+            debug_labels: false,
+            optimize_shaders: true,
+            shaders_debug_info_enabled: false,
+            pipeline_cache_path: None,
+            shaders_override_dir: None,
+            msaa_samples: gfx::Samples::_1,
+            depth_mode: DepthMode::Standard,
+            present_mode: None,
+            swapchain_preferences: Default::default(),
+            preferred_device_name: None,
+            preferred_device_type: None,
+            default_anisotropy: None,
+            buffer_device_address: false,
+            gpu_culling: false,
+            #[cfg(feature = "ab_glyph")]
+            font: None,
+        }
+    }
+
+    /// Creates a builder of the renderer without a window.
+    ///
+    /// Frames are rendered into an offscreen image of the specified size
+    /// and can be read back with [`RendererState::capture_next_frame`].
+    /// Frame time advances by a fixed step, so that the rendered image
+    /// only depends on the scene.
+    pub fn headless(width: u32, height: u32) -> Self {
+        Self::new(RendererTarget::Headless(UVec2::new(width, height)))
+    }
+
     pub fn build(self) -> Result<Renderer> {
         let app_version = (0, 0, 1);
 
+        let window = match &self.target {
+            RendererTarget::Window(window) => Some(window.clone()),
+            RendererTarget::Headless(_) => None,
+        };
+
         gfx::Graphics::set_init_config(gfx::InstanceConfig {
-            app_name: self.window.title().into(),
+            app_name: match &window {
+                Some(window) => window.title().into(),
+                None => "headless".into(),
+            },
             app_version,
             validation_layer_enabled: self.validation_layer,
+            debug_utils_enabled: self.debug_labels,
+            validation_min_severity: gfx::ValidationSeverity::Verbose,
+            suppressed_validation_messages: self.suppressed_validation_messages,
+            validation_messages_capacity: gfx::InstanceConfig::DEFAULT_VALIDATION_MESSAGES_CAPACITY,
         });
 
+        // NOTE: Culled objects are drawn by multiple indirect draws per call,
+        // with object slots passed as the first instance.
+        const GPU_CULLING_FEATURES: [gfx::DeviceFeature; 3] = [
+            gfx::DeviceFeature::DrawIndirectCount,
+            gfx::DeviceFeature::MultiDrawIndirect,
+            gfx::DeviceFeature::DrawIndirectFirstInstance,
+        ];
+
+        let mut required_features = vec![
+            gfx::DeviceFeature::ShaderSampledImageNonUniformIndexing,
+            gfx::DeviceFeature::ShaderStorageBufferNonUniformIndexing,
+            gfx::DeviceFeature::DescriptorBindingUniformBufferUpdateAfterBind,
+            gfx::DeviceFeature::DescriptorBindingStorageBufferUpdateAfterBind,
+            gfx::DeviceFeature::DescriptorBindingSampledImageUpdateAfterBind,
+            gfx::DeviceFeature::DescriptorBindingPartiallyBound,
+        ];
+        if window.is_some() {
+            required_features.push(gfx::DeviceFeature::SurfacePresentation);
+        }
+
+        let mut optional_features = vec![
+            (gfx::DeviceFeature::MemoryBudget, 1),
+            // NOTE: Only used by the wireframe debug view
+            (gfx::DeviceFeature::FillModeNonSolid, 0),
+            // NOTE: Only used for display pacing and timing stats
+            (gfx::DeviceFeature::DisplayTiming, 0),
+            // NOTE: Only used when the default anisotropy is set
+            (gfx::DeviceFeature::SamplerAnisotropy, 0),
+        ];
+        if self.buffer_device_address {
+            optional_features.push((gfx::DeviceFeature::BufferDeviceAddress, 0));
+        }
+        if self.gpu_culling {
+            optional_features.extend(GPU_CULLING_FEATURES.map(|feature| (feature, 0)));
+        }
+
         let graphics = gfx::Graphics::get_or_init()?;
-        let (device, queue) = graphics
+        let mut selector = graphics
             .get_physical_devices()?
-            .with_required_features(&[
-                gfx::DeviceFeature::SurfacePresentation,
-                gfx::DeviceFeature::ShaderStorageBufferNonUniformIndexing,
-                gfx::DeviceFeature::DescriptorBindingUniformBufferUpdateAfterBind,
-                gfx::DeviceFeature::DescriptorBindingStorageBufferUpdateAfterBind,
-                gfx::DeviceFeature::DescriptorBindingSampledImageUpdateAfterBind,
-                gfx::DeviceFeature::DescriptorBindingPartiallyBound,
-            ])
+            .with_required_features(&required_features)
+            .with_optional_features(&optional_features);
+        if let Some(name) = self.preferred_device_name {
+            selector = selector.prefer_device_name(name);
+        }
+        if let Some(ty) = self.preferred_device_type {
+            selector = selector.prefer_device_type(ty);
+        }
+        let (device, (queue, transfer_queue)) = selector
             .find_best()?
-            .create_logical_device(gfx::SingleQueueQuery::GRAPHICS)?;
+            .create_logical_device(gfx::GraphicsWithTransferQueueQuery)?;
+
+        if let Some(transfer_queue) = &transfer_queue {
+            tracing::debug!(
+                family = transfer_queue.id().family,
+                "using dedicated transfer queue"
+            );
+        }
+
+        let msaa_samples = device.find_supported_framebuffer_samples(self.msaa_samples);
+
+        let buffer_device_address = self.buffer_device_address
+            && device.is_feature_enabled(gfx::DeviceFeature::BufferDeviceAddress);
+        if self.buffer_device_address && !buffer_device_address {
+            tracing::warn!("buffer device address is not supported, using storage buffers");
+        }
+
+        let gpu_culling = self.gpu_culling
+            && GPU_CULLING_FEATURES
+                .iter()
+                .all(|feature| device.is_feature_enabled(*feature));
+        if self.gpu_culling && !gpu_culling {
+            tracing::warn!("indirect count draws are not supported, falling back to CPU culling");
+        }
+
+        let pipeline_cache_data =
+            self.pipeline_cache_path
+                .as_ref()
+                .and_then(|path| match std::fs::read(path) {
+                    Ok(data) => Some(data),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => {
+                        tracing::warn!(?path, "failed to read pipeline cache: {e}");
+                        None
+                    }
+                });
+        let pipeline_cache = device.create_pipeline_cache(pipeline_cache_data.as_deref())?;
+        device.set_pipeline_cache(Some(pipeline_cache));
 
         let mut shader_preprocessor = ShaderPreprocessor::new();
         shader_preprocessor.set_optimizations_enabled(self.optimize_shaders);
         shader_preprocessor.set_debug_info_enabled(self.shaders_debug_info_enabled);
-        for (path, contents) in Shaders::iter() {
-            let contents = std::str::from_utf8(contents)
-                .with_context(|| anyhow::anyhow!("invalid shader {path}"))?;
-            shader_preprocessor.add_file(path, contents)?;
+        load_shaders(
+            &mut shader_preprocessor,
+            self.shaders_override_dir.as_deref(),
+        )?;
+        if buffer_device_address {
+            shader_preprocessor.define_global("VERTEX_FETCH_DEVICE_ADDRESS");
         }
 
-        let frame_resources = FrameResources::new(&device)?;
+        let frame_resources = FrameResources::new(&device, self.depth_mode)?;
         let bindless_resources = BindlessResources::new(&device)?;
-        let scatter_copy = ScatterCopy::new(&device, &shader_preprocessor)?;
+        let scatter_copy = ScatterCopy::new(&device, &shader_preprocessor, queue.capabilities())?;
         let multi_buffer_arena = MultiBufferArena::new(&device);
+        let staging_belt = gfx::StagingBelt::new(&device, gfx::StagingBelt::DEFAULT_CHUNK_SIZE);
 
-        let mesh_manager = MeshManager::new(&device, &bindless_resources)?;
-
-        let mut surface = device.create_surface(self.window.clone())?;
-        surface.configure()?;
+        let mesh_manager = MeshManager::new(
+            &device,
+            &bindless_resources,
+            transfer_queue.clone(),
+            buffer_device_address,
+        )?;
+        let max_anisotropy = resolve_max_anisotropy(
+            self.default_anisotropy,
+            device.is_feature_enabled(gfx::DeviceFeature::SamplerAnisotropy),
+            device.limits().max_sampler_anisotropy,
+        );
+        let texture_manager = TextureManager::new(&queue, &bindless_resources, max_anisotropy)?;
+        let texture_streamer = TextureStreamer::new()?;
+
+        #[cfg(feature = "ab_glyph")]
+        let font = match &self.font {
+            Some((data, height)) => {
+                FontAtlas::from_font(data, *height).context("failed to load font")?
+            }
+            None => FontAtlas::builtin(),
+        };
+        #[cfg(not(feature = "ab_glyph"))]
+        let font = FontAtlas::builtin();
+
+        let output = match self.target {
+            RendererTarget::Window(window) => {
+                let mut surface = device.create_surface(window)?;
+                surface.set_swapchain_preferences(self.swapchain_preferences);
+                match self.present_mode {
+                    Some(mode) => surface.set_present_mode(select_present_mode(&surface, mode))?,
+                    None => surface.configure()?,
+                }
+                FrameOutput::Surface(surface)
+            }
+            RendererTarget::Headless(extent) => {
+                FrameOutput::Offscreen(OffscreenTarget::new(&device, extent)?)
+            }
+        };
+        let (supported_present_modes, present_mode) = match &output {
+            FrameOutput::Surface(surface) => (
+                surface.supported_present_modes().collect(),
+                surface.present_mode().unwrap_or(gfx::PresentMode::Fifo),
+            ),
+            FrameOutput::Offscreen(_) => (Box::default(), gfx::PresentMode::Fifo),
+        };
 
         let state = Arc::new(RendererState {
-            is_running: AtomicBool::new(true),
+            device_lost: AtomicBool::new(false),
+            error: Mutex::new(None),
+            frustum_culling_enabled: AtomicBool::new(true),
+            gpu_culling_enabled: gpu_culling,
+            gpu_culling_stats: Mutex::default(),
+            debug_view: Mutex::default(),
+            tonemap: Mutex::default(),
+            resolution_scale: Mutex::new(1.0),
+            gpu_profiling_enabled: AtomicBool::new(false),
+            shaders_reload_requested: AtomicBool::new(false),
+            present_mode_update_requested: AtomicBool::new(false),
+            surface_recreation_requested: AtomicBool::new(false),
+            surface_resize_requested: AtomicBool::new(false),
+            frame_timer_reset_requested: AtomicBool::new(false),
+            frame_draw_calls: AtomicU32::new(0),
+            frame_drawn_instances: AtomicU32::new(0),
+            frame_object_bytes_uploaded: AtomicU64::new(0),
+            frame_stats: AtomicSlot::default(),
             worker_barrier: LoopBarrier::default(),
             instructions: InstructionQueue::default(),
             mesh_manager,
+            texture_manager,
+            texture_streamer,
+            render_targets: Default::default(),
             synced_managers: Default::default(),
+            synced_managers_dirty: AtomicBool::new(true),
             handles: Default::default(),
+            material_required_attributes: Default::default(),
+            debug_lines: Default::default(),
+            text: Default::default(),
+            font,
+            overlay: Default::default(),
+            pending_compute_nodes: Default::default(),
             frame_resources,
             bindless_resources,
             multi_buffer_arena,
+            readback_pool: gfx::ReadbackPool::new(&device),
+            render_pass_cache: Default::default(),
+            framebuffer_cache: Default::default(),
+            staging_belt,
             scatter_copy,
-            shader_preprocessor,
-            window: self.window,
+            shader_preprocessor: Mutex::new(shader_preprocessor),
+            shaders_override_dir: self.shaders_override_dir,
+            msaa_samples,
+            depth_mode: self.depth_mode,
+            supported_present_modes,
+            present_mode: Mutex::new(present_mode),
+            fixed_timestep: Mutex::new(TimeManager::DEFAULT_FIXED_TIMESTEP),
+            frame_capture_requested: AtomicBool::new(false),
+            pick_requests: Mutex::default(),
+            frame_rate_limit: AtomicU32::new(0),
+            display_paced: AtomicBool::new(false),
+            captured_frame: Mutex::new(None),
+            captured_frame_ready: Condvar::new(),
+            events: Mutex::default(),
+            window,
             queue,
+            transfer_queue,
             device,
         });
 
-        let mut worker = RendererWorker::new(state.clone(), surface)?;
+        let mut worker = RendererWorker::new(state.clone(), output)?;
 
         let worker_thread = std::thread::spawn({
             let state = state.clone();
@@ -109,9 +394,36 @@ impl RendererBuilder {
                 tracing::debug!("rendering thread started");
 
                 let state = state.as_ref();
-                while state.is_running.load(Ordering::Acquire) {
-                    state.worker_barrier.wait();
-                    worker.draw().unwrap();
+                let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    while state.worker_barrier.begin_frame() {
+                        let res = worker.draw();
+                        state.worker_barrier.end_frame();
+                        if let Err(e) = res {
+                            let error = RendererError::from_internal(e);
+                            tracing::error!("rendering thread failed: {error:?}");
+                            state.stop_with_error(error);
+                            break;
+                        }
+                    }
+                }));
+                state.worker_barrier.finish();
+                {
+                    // NOTE: Wake up threads waiting for the frame capture
+                    let _captured_frame = lock_ignore_poison(&state.captured_frame);
+                    state.captured_frame_ready.notify_all();
+                }
+
+                // NOTE: Handles owned by the instructions sent since the last frame
+                // are released here, new ones are released immediately.
+                state.shutdown_instructions();
+
+                if let Err(payload) = res {
+                    let message = panic_message(payload.as_ref());
+                    tracing::error!("rendering thread panicked: {message}");
+                    state.stop_with_error(RendererError::WorkerPanicked { message });
+
+                    // NOTE: The payload is returned by `Renderer::cleanup`
+                    std::panic::resume_unwind(payload);
                 }
 
                 tracing::debug!("rendering thread stopped");
@@ -121,6 +433,7 @@ impl RendererBuilder {
         Ok(Renderer {
             state,
             worker_thread: Some(worker_thread),
+            pipeline_cache_path: self.pipeline_cache_path,
         })
     }
 
@@ -134,6 +447,22 @@ impl RendererBuilder {
         self
     }
 
+    /// Ignores validation messages with the specified identifier.
+    ///
+    /// See [`gfx::ValidationMessage::id`].
+    pub fn suppress_validation_message(mut self, id: i32) -> Self {
+        self.suppressed_validation_messages.push(id);
+        self
+    }
+
+    /// Names GPU resources and labels render passes for graphics debuggers.
+    ///
+    /// NOTE: Always enabled with the validation layer.
+    pub fn debug_labels(mut self, debug_labels: bool) -> Self {
+        self.debug_labels = debug_labels;
+        self
+    }
+
     pub fn optimize_shaders(mut self, optimize_shaders: bool) -> Self {
         self.optimize_shaders = optimize_shaders;
         self
@@ -143,337 +472,2135 @@ impl RendererBuilder {
         self.shaders_debug_info_enabled = shaders_debug_info_enabled;
         self
     }
+
+    pub fn pipeline_cache_path(mut self, pipeline_cache_path: Option<PathBuf>) -> Self {
+        self.pipeline_cache_path = pipeline_cache_path;
+        self
+    }
+
+    /// Loads shaders from the specified directory instead of the embedded ones.
+    ///
+    /// Missing files fall back to the embedded contents.
+    pub fn shaders_override_dir(mut self, path: PathBuf) -> Self {
+        self.shaders_override_dir = Some(path);
+        self
+    }
+
+    /// Sets the sample count used for the main pass.
+    ///
+    /// Unsupported sample counts fall back to the nearest supported one.
+    pub fn msaa_samples(mut self, samples: gfx::Samples) -> Self {
+        self.msaa_samples = samples;
+        self
+    }
+
+    /// Enables reversed depth (the near plane maps to 1.0 and the far plane to 0.0).
+    ///
+    /// Improves depth precision for large scenes. Custom camera projections
+    /// must produce matrices of the matching convention.
+    pub fn reverse_z(mut self, reverse_z: bool) -> Self {
+        self.depth_mode = if reverse_z {
+            DepthMode::Reversed
+        } else {
+            DepthMode::Standard
+        };
+        self
+    }
+
+    /// Sets the swapchain present mode.
+    ///
+    /// Unsupported modes fall back to FIFO.
+    pub fn present_mode(mut self, mode: gfx::PresentMode) -> Self {
+        self.present_mode = Some(mode);
+        self
+    }
+
+    /// Sets the preferred number of swapchain images.
+    ///
+    /// The count is clamped to the range supported by the surface.
+    pub fn preferred_swapchain_images(mut self, count: u32) -> Self {
+        self.swapchain_preferences.image_count = Some(count);
+        self
+    }
+
+    /// Sets the preferred format and color space of swapchain images.
+    ///
+    /// Unsupported formats fall back to the best available one (sRGB is preferred).
+    pub fn preferred_surface_format(
+        mut self,
+        format: gfx::Format,
+        color_space: gfx::ColorSpace,
+    ) -> Self {
+        self.swapchain_preferences.format = Some((format, color_space));
+        self
+    }
+
+    /// Prefers a physical device with the name containing the specified string.
+    ///
+    /// Falls back to the best suitable device if there is no such device.
+    /// See [`gfx::Graphics::enumerate_devices`] for the list of available devices.
+    pub fn preferred_device_name(mut self, name: impl Into<String>) -> Self {
+        self.preferred_device_name = Some(name.into());
+        self
+    }
+
+    /// Prefers a physical device of the specified type (e.g. an integrated GPU
+    /// on hybrid laptops).
+    pub fn preferred_device_type(mut self, ty: gfx::DeviceType) -> Self {
+        self.preferred_device_type = Some(ty);
+        self
+    }
+
+    /// Sets the maximum anisotropy of texture sampling, `None` disables
+    /// anisotropic filtering (default).
+    ///
+    /// The value is clamped to the device limit. Has no effect if
+    /// anisotropic filtering is not supported by the device.
+    /// See [`RendererState::set_default_anisotropy`].
+    pub fn default_anisotropy(mut self, max_anisotropy: Option<f32>) -> Self {
+        self.default_anisotropy = max_anisotropy;
+        self
+    }
+
+    /// Makes shaders read mesh vertices through buffer device addresses
+    /// instead of bindless storage buffers (disabled by default).
+    ///
+    /// Falls back to bindless storage buffers if the device doesn't support
+    /// [`gfx::DeviceFeature::BufferDeviceAddress`].
+    /// See [`RendererState::is_buffer_device_address_enabled`].
+    pub fn buffer_device_address(mut self, enabled: bool) -> Self {
+        self.buffer_device_address = enabled;
+        self
+    }
+
+    /// Culls static objects in a compute shader and draws them
+    /// with indirect count draws (disabled by default).
+    ///
+    /// Dynamic and transparent objects are still culled on the CPU.
+    /// Falls back to CPU culling if the device doesn't support
+    /// [`gfx::DeviceFeature::DrawIndirectCount`].
+    /// See [`RendererState::is_gpu_culling_enabled`].
+    pub fn gpu_culling(mut self, enabled: bool) -> Self {
+        self.gpu_culling = enabled;
+        self
+    }
+
+    /// Draws text with a TrueType or OpenType font rasterized `height` pixels
+    /// tall instead of the built-in bitmap font.
+    ///
+    /// See [`RendererState::draw_text_2d`].
+    #[cfg(feature = "ab_glyph")]
+    pub fn font(mut self, data: Vec<u8>, height: f32) -> Self {
+        self.font = Some((data, height));
+        self
+    }
 }
 
 pub struct Renderer {
     state: Arc<RendererState>,
     worker_thread: Option<std::thread::JoinHandle<()>>,
+    pipeline_cache_path: Option<PathBuf>,
 }
 
 impl Renderer {
     pub fn builder(window: Arc<Window>) -> RendererBuilder {
-        RendererBuilder {
-            window,
-            app_version: (0, 0, 1),
-            validation_layer: false,
-            optimize_shaders: true,
-            shaders_debug_info_enabled: false,
+        RendererBuilder::new(RendererTarget::Window(window))
+    }
+
+    pub fn state(&self) -> &Arc<RendererState> {
+        &self.state
+    }
+
+    /// Stops the rendering thread, waits for the device to become idle
+    /// and saves the pipeline cache.
+    ///
+    /// Fails with [`RendererError::WorkerPanicked`] if the rendering thread
+    /// panicked, the device is still waited for in that case.
+    /// Does nothing when called again.
+    pub fn cleanup(&mut self) -> Result<()> {
+        let Some(worker_thread) = self.worker_thread.take() else {
+            return Ok(());
+        };
+
+        self.state.set_running(false);
+        let worker_panic = worker_thread.join().err();
+
+        // NOTE: Instructions sent after this point are rejected,
+        // the worker does the same unless it was never started.
+        self.state.shutdown_instructions();
+
+        let res = self.wait_idle_and_save_pipeline_cache();
+        self.state.handles.report_leaks();
+
+        match worker_panic {
+            Some(payload) => {
+                if let Err(e) = res {
+                    tracing::error!("failed to cleanup renderer: {e:?}");
+                }
+                let message = panic_message(payload.as_ref());
+                Err(RendererError::WorkerPanicked { message }.into())
+            }
+            None => res,
+        }
+    }
+
+    fn wait_idle_and_save_pipeline_cache(&mut self) -> Result<()> {
+        // NOTE: Nothing can be done with the lost device
+        if self.state.device_lost.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        self.state.device.wait_idle()?;
+
+        if let Some(path) = self.pipeline_cache_path.take() {
+            if let Some(pipeline_cache) = self.state.device.pipeline_cache() {
+                let data = pipeline_cache.data()?;
+                std::fs::write(&path, data)
+                    .with_context(|| format!("failed to save pipeline cache to {path:?}"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the message of a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_owned()
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        if let Err(e) = self.cleanup() {
+            tracing::error!("failed to cleanup renderer: {e:?}");
+        }
+    }
+}
+
+/// Pixels of a frame rendered by a headless renderer,
+/// see [`RendererState::capture_next_frame`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub format: gfx::Format,
+    /// Tightly packed rows of texels, from top to bottom.
+    pub data: Vec<u8>,
+}
+
+impl CapturedFrame {
+    /// Returns the texel at the specified coordinates (RGBA, 8 bits per channel).
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        assert!(x < self.width && y < self.height, "pixel is out of bounds");
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        self.data[offset..offset + 4].try_into().unwrap()
+    }
+}
+
+/// Notification from the rendering thread, see [`RendererState::take_events`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RendererEvent {
+    /// The render resolution has changed.
+    ///
+    /// NOTE: The camera projection is updated with the new aspect ratio automatically.
+    Resized { width: u32, height: u32 },
+}
+
+/// Renderer counters, see [`RendererState::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RendererStats {
+    /// Number of GPU buffer slots written by the scatter copy.
+    pub slots_scattered: u64,
+    /// Number of scatter copy compute dispatches.
+    pub scatter_dispatches: u64,
+    /// Number of scatter copy flushes done with buffer copies.
+    pub scatter_copy_fallbacks: u64,
+    /// Number of GPU buffer slots written into mapped memory by the CPU,
+    /// see [`gfx::Device::supports_direct_upload`].
+    pub slots_written_directly: u64,
+    /// Number of draw calls recorded in the last frame.
+    pub frame_draw_calls: u32,
+    /// Number of instances drawn in the last frame.
+    pub frame_drawn_instances: u32,
+    /// Number of bytes of object data uploaded in the last frame,
+    /// including interpolated transforms of dynamic objects.
+    pub frame_object_bytes_uploaded: u64,
+    /// Slot usage of the bindless descriptor arrays.
+    pub bindless: BindlessResourcesStats,
+    /// Fragmentation and upload counters of the mesh buffers.
+    pub meshes: MeshManagerStats,
+    /// Lookups of the render passes shared by all passes.
+    pub render_passes: RenderTargetCacheStats,
+    /// Lookups of the framebuffers shared by all passes.
+    pub framebuffers: RenderTargetCacheStats,
+    /// Culling counters of the last completed frame with GPU culling.
+    pub gpu_culling: GpuCullingStats,
+}
+
+/// Number of handles still referenced outside the renderer,
+/// see [`RendererState::live_handle_counts`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LiveHandleCounts {
+    pub meshes: usize,
+    pub textures: usize,
+    pub materials: usize,
+    pub static_objects: usize,
+    pub dynamic_objects: usize,
+}
+
+impl LiveHandleCounts {
+    pub fn total(&self) -> usize {
+        self.meshes + self.textures + self.materials + self.static_objects + self.dynamic_objects
+    }
+}
+
+pub struct RendererState {
+    device_lost: AtomicBool,
+    error: Mutex<Option<RendererError>>,
+    frustum_culling_enabled: AtomicBool,
+    gpu_culling_enabled: bool,
+    gpu_culling_stats: Mutex<GpuCullingStats>,
+    debug_view: Mutex<DebugView>,
+    tonemap: Mutex<Tonemap>,
+    resolution_scale: Mutex<f32>,
+    gpu_profiling_enabled: AtomicBool,
+    shaders_reload_requested: AtomicBool,
+    present_mode_update_requested: AtomicBool,
+    surface_recreation_requested: AtomicBool,
+    surface_resize_requested: AtomicBool,
+    /// Set when the renderer is resumed, so the delta time of the next frame
+    /// doesn't include the pause.
+    frame_timer_reset_requested: AtomicBool,
+    frame_draw_calls: AtomicU32,
+    frame_drawn_instances: AtomicU32,
+    frame_object_bytes_uploaded: AtomicU64,
+    frame_stats: AtomicSlot<FrameStats>,
+    worker_barrier: LoopBarrier,
+    instructions: InstructionQueue<Instruction>,
+
+    mesh_manager: MeshManager,
+    texture_manager: TextureManager,
+    texture_streamer: TextureStreamer,
+    render_targets: RenderTargetManager,
+    synced_managers: Mutex<RendererStateSyncedManagers>,
+    /// Whether the synced managers must be flushed even without new instructions.
+    synced_managers_dirty: AtomicBool,
+    handles: RendererStateHandles,
+    material_required_attributes: Mutex<
+        FastHashMap<
+            RawMaterialInstanceHandle,
+            (Box<[VertexAttributeKind]>, MaterialAttributePolicy),
+        >,
+    >,
+    debug_lines: Mutex<DebugLines>,
+    text: Mutex<TextGlyphs>,
+    font: FontAtlas,
+    overlay: Mutex<Vec<OverlayMesh>>,
+    pending_compute_nodes: Mutex<Vec<Box<dyn ComputeNode>>>,
+
+    frame_resources: FrameResources,
+    bindless_resources: BindlessResources,
+    multi_buffer_arena: MultiBufferArena,
+    readback_pool: gfx::ReadbackPool,
+    render_pass_cache: RenderPassCache,
+    framebuffer_cache: FramebufferCache,
+    staging_belt: gfx::StagingBelt,
+    shader_preprocessor: Mutex<ShaderPreprocessor>,
+    shaders_override_dir: Option<PathBuf>,
+    scatter_copy: ScatterCopy,
+    msaa_samples: gfx::Samples,
+    depth_mode: DepthMode,
+    supported_present_modes: Box<[gfx::PresentMode]>,
+    present_mode: Mutex<gfx::PresentMode>,
+    fixed_timestep: Mutex<Duration>,
+    frame_capture_requested: AtomicBool,
+    pick_requests: Mutex<Vec<PendingPick>>,
+    /// Zero if the frame rate is not limited.
+    frame_rate_limit: AtomicU32,
+    display_paced: AtomicBool,
+    captured_frame: Mutex<Option<CapturedFrame>>,
+    captured_frame_ready: Condvar,
+    events: Mutex<Vec<RendererEvent>>,
+
+    /// `None` for headless renderers.
+    window: Option<Arc<Window>>,
+    queue: gfx::Queue,
+    transfer_queue: Option<gfx::Queue>,
+
+    // NOTE: device must be dropped last
+    device: gfx::Device,
+}
+
+impl RendererState {
+    /// Returns the window of the renderer, or `None` if it is headless.
+    pub fn window(&self) -> Option<&Arc<Window>> {
+        self.window.as_ref()
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.window.is_none()
+    }
+
+    /// Stops the rendering thread, or cancels the stop if the thread
+    /// has not exited yet.
+    ///
+    /// NOTE: The frame which is drawn or was requested with [`request_draw`]
+    /// is completed before the thread exits.
+    ///
+    /// [`request_draw`]: Self::request_draw
+    pub fn set_running(&self, is_running: bool) {
+        if is_running {
+            self.worker_barrier.resume();
+        } else {
+            self.worker_barrier.stop();
+        }
+    }
+
+    /// Returns `false` if the rendering thread was stopped.
+    pub fn is_running(&self) -> bool {
+        self.worker_barrier.is_running()
+    }
+
+    /// Pauses or resumes drawing, e.g. while the window is not focused.
+    ///
+    /// Requested frames of the paused renderer only evaluate instructions,
+    /// so removed resources are still reclaimed, but nothing is drawn or
+    /// presented. Such frames are limited to a few per second. The swapchain
+    /// is kept, and the next frame is drawn right after resuming.
+    pub fn set_paused(&self, paused: bool) {
+        // NOTE: Requested before resuming, so the first frame after it sees the request
+        if !paused && self.is_paused() {
+            self.frame_timer_reset_requested
+                .store(true, Ordering::Release);
+        }
+
+        let was_paused = self.worker_barrier.set_paused(paused);
+        if was_paused && !paused {
+            let _ = self.request_draw();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.worker_barrier.is_paused()
+    }
+
+    /// Takes the error which stopped the rendering thread.
+    ///
+    /// The renderer must be recreated after an error.
+    pub fn take_error(&self) -> Option<RendererError> {
+        lock_ignore_poison(&self.error).take()
+    }
+
+    fn stop_with_error(&self, error: RendererError) {
+        if matches!(error, RendererError::DeviceLost(_)) {
+            self.device_lost.store(true, Ordering::Release);
+        }
+        *lock_ignore_poison(&self.error) = Some(error);
+
+        self.set_running(false);
+
+        // NOTE: Handles owned by the pending instructions are released here
+        self.shutdown_instructions();
+    }
+
+    /// Rejects new instructions and releases handles of the pending ones
+    /// without evaluating them.
+    ///
+    /// NOTE: Called once the rendering thread is stopped. Resources of the
+    /// released handles are destroyed along with the renderer.
+    fn shutdown_instructions(&self) {
+        let pending = self.instructions.close();
+        if !pending.is_empty() {
+            tracing::debug!(count = pending.len(), "dropping pending instructions");
+        }
+        for instruction in pending {
+            self.release_instruction(instruction);
         }
     }
 
-    pub fn state(&self) -> &Arc<RendererState> {
-        &self.state
+    /// Releases the handle slot of a removal which will never be evaluated,
+    /// other instructions are just dropped.
+    fn release_instruction(&self, instruction: Instruction) {
+        match instruction {
+            Instruction::RemoveMesh { handle } => {
+                self.handles.mesh_handle_allocator.dealloc(handle);
+            }
+            Instruction::RemoveTexture { handle } => {
+                self.handles.texture_handle_allocator.dealloc(handle);
+            }
+            Instruction::RemoveMaterial { handle } => {
+                self.handles.material_handle_allocator.dealloc(handle);
+                self.material_required_attributes
+                    .lock()
+                    .unwrap()
+                    .remove(&handle);
+            }
+            Instruction::RemoveStaticObject { handle } => {
+                self.handles.static_object_handle_allocator.dealloc(handle);
+            }
+            Instruction::RemoveDynamicObject { handle } => {
+                self.handles.dynamic_object_handle_allocator.dealloc(handle);
+            }
+            // NOTE: Closures and data owned by the instruction are dropped
+            // here, which could release more handles.
+            instruction => drop(instruction),
+        }
+    }
+
+    pub fn set_frustum_culling_enabled(&self, enabled: bool) {
+        self.frustum_culling_enabled
+            .store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_frustum_culling_enabled(&self) -> bool {
+        self.frustum_culling_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if static objects are culled on the GPU,
+    /// see [`RendererBuilder::gpu_culling`].
+    ///
+    /// NOTE: Objects are not culled at all while frustum culling is disabled.
+    pub fn is_gpu_culling_enabled(&self) -> bool {
+        self.gpu_culling_enabled
+    }
+
+    /// Sets the directional light which casts shadows of objects
+    /// added with [`ObjectOptions::cast_shadows`].
+    ///
+    /// The shadow map covers `shadow_extent` world units in each direction
+    /// around the camera.
+    pub fn set_directional_light(&self, direction: Vec3, color: Vec3, shadow_extent: f32) {
+        self.frame_resources
+            .set_directional_light(Some(DirectionalLight {
+                direction,
+                color,
+                shadow_extent,
+            }));
+    }
+
+    /// Removes the directional light, objects are lit by a fixed light without shadows.
+    pub fn clear_directional_light(&self) {
+        self.frame_resources.set_directional_light(None);
+    }
+
+    /// Changes offsets which trade shadow acne for peter-panning.
+    pub fn set_shadow_bias(&self, bias: ShadowBias) {
+        self.frame_resources.set_shadow_bias(bias);
+    }
+
+    pub fn shadow_bias(&self) -> ShadowBias {
+        self.frame_resources.shadow_bias()
+    }
+
+    /// Changes the scale of HDR colors before tonemapping, `1.0` by default.
+    ///
+    /// NOTE: Negative and non-finite values are ignored.
+    pub fn set_exposure(&self, exposure: f32) {
+        if !types::is_valid_exposure(exposure) {
+            tracing::warn!(exposure, "ignored invalid exposure");
+            return;
+        }
+        self.frame_resources.set_exposure(exposure);
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.frame_resources.exposure()
+    }
+
+    /// Changes the operator which maps HDR colors of the main pass into the output.
+    pub fn set_tonemap(&self, tonemap: Tonemap) {
+        *self.tonemap.lock().unwrap() = tonemap;
+    }
+
+    pub fn tonemap(&self) -> Tonemap {
+        *self.tonemap.lock().unwrap()
+    }
+
+    /// Changes the resolution of the main pass relative to the output, `1.0` by default.
+    ///
+    /// The scene is drawn at the scaled resolution and is upscaled with linear
+    /// filtering by the tonemap pass, while the overlay and text are drawn at the
+    /// output resolution. The HDR target is recreated at the start of the next frame.
+    ///
+    /// NOTE: Values outside of [`MIN_RESOLUTION_SCALE`]..=[`MAX_RESOLUTION_SCALE`]
+    /// are ignored.
+    pub fn set_resolution_scale(&self, scale: f32) {
+        if !(MIN_RESOLUTION_SCALE..=MAX_RESOLUTION_SCALE).contains(&scale) {
+            tracing::warn!(scale, "ignored invalid resolution scale");
+            return;
+        }
+        *self.resolution_scale.lock().unwrap() = scale;
+    }
+
+    pub fn resolution_scale(&self) -> f32 {
+        *self.resolution_scale.lock().unwrap()
+    }
+
+    /// Returns `true` if shaders read mesh vertices through buffer device addresses,
+    /// see [`RendererBuilder::buffer_device_address`].
+    pub fn is_buffer_device_address_enabled(&self) -> bool {
+        self.mesh_manager.buffer_addresses().is_some()
+    }
+
+    /// Returns the depth convention used by all passes and pipelines.
+    pub fn depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    /// Changes how all objects of the main pass are visualized.
+    ///
+    /// Returns `false` and keeps the current view if the view
+    /// is not supported by the device.
+    pub fn set_debug_view(&self, view: DebugView) -> bool {
+        if view == DebugView::Wireframe
+            && !self
+                .device
+                .is_feature_enabled(gfx::DeviceFeature::FillModeNonSolid)
+        {
+            tracing::warn!("wireframe debug view is not supported by the device");
+            return false;
+        }
+
+        *self.debug_view.lock().unwrap() = view;
+        true
+    }
+
+    pub fn debug_view(&self) -> DebugView {
+        *self.debug_view.lock().unwrap()
+    }
+
+    /// Enables writing GPU timestamps for the frame passes.
+    ///
+    /// Timings are reported as `debug` events once the frame is completed.
+    /// Does nothing if the device doesn't support timestamp queries.
+    pub fn set_gpu_profiling_enabled(&self, enabled: bool) {
+        self.gpu_profiling_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_gpu_profiling_enabled(&self) -> bool {
+        self.gpu_profiling_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Sets the number of updated slots below which GPU buffers are
+    /// updated with plain buffer copies instead of a compute dispatch.
+    pub fn set_scatter_copy_threshold(&self, slots: u32) {
+        self.scatter_copy.set_copy_threshold(slots);
+    }
+
+    /// Returns counters accumulated since the renderer was created
+    /// and the draw counters of the last frame.
+    pub fn stats(&self) -> RendererStats {
+        RendererStats {
+            slots_scattered: self.scatter_copy.slots_scattered(),
+            scatter_dispatches: self.scatter_copy.dispatches(),
+            scatter_copy_fallbacks: self.scatter_copy.copy_fallbacks(),
+            slots_written_directly: self.scatter_copy.slots_written_directly(),
+            frame_draw_calls: self.frame_draw_calls.load(Ordering::Relaxed),
+            frame_drawn_instances: self.frame_drawn_instances.load(Ordering::Relaxed),
+            frame_object_bytes_uploaded: self.frame_object_bytes_uploaded.load(Ordering::Relaxed),
+            bindless: self.bindless_resources.stats(),
+            meshes: self.mesh_manager.stats(),
+            render_passes: self.render_pass_cache.stats(),
+            framebuffers: self.framebuffer_cache.stats(),
+            gpu_culling: *self.gpu_culling_stats.lock().unwrap(),
+        }
+    }
+
+    /// Returns the number of handles of each kind which are still alive.
+    ///
+    /// Handles are counted while any of their clones exists, including
+    /// the mesh and material handles kept by alive objects.
+    pub fn live_handle_counts(&self) -> LiveHandleCounts {
+        self.handles.live_counts()
+    }
+
+    /// Returns statistics of the last frame finished by the render worker.
+    ///
+    /// Unlike [`RendererState::material_stats`], never waits for the worker.
+    pub fn last_frame_stats(&self) -> FrameStats {
+        self.frame_stats.load()
+    }
+
+    pub(crate) fn publish_frame_stats(&self, stats: FrameStats) {
+        self.frame_stats.publish(stats);
+    }
+
+    /// Returns slot usage of each material archetype.
+    ///
+    /// NOTE: Waits for the render worker to finish processing instructions.
+    pub fn material_stats(&self) -> Vec<MaterialArchetypeStats> {
+        let synced_managers = self.synced_managers.lock().unwrap();
+        synced_managers.material_manager.stats().collect()
+    }
+
+    pub(crate) fn record_frame_draws(&self, draw_calls: u32, drawn_instances: u32) {
+        self.frame_draw_calls.store(draw_calls, Ordering::Relaxed);
+        self.frame_drawn_instances
+            .store(drawn_instances, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_gpu_culling_stats(&self, stats: GpuCullingStats) {
+        *self.gpu_culling_stats.lock().unwrap() = stats;
+    }
+
+    /// Adds bytes of object data written by the render graph to the frame counter.
+    pub(crate) fn record_frame_object_uploads(&self, bytes: usize) {
+        self.frame_object_bytes_uploaded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Returns all present modes supported by the window surface.
+    pub fn supported_present_modes(&self) -> &[gfx::PresentMode] {
+        &self.supported_present_modes
+    }
+
+    /// Returns the last requested present mode.
+    pub fn present_mode(&self) -> gfx::PresentMode {
+        *self.present_mode.lock().unwrap()
+    }
+
+    /// Requests the swapchain to be recreated with the specified present mode.
+    ///
+    /// Unsupported modes fall back to FIFO.
+    pub fn set_present_mode(&self, mode: gfx::PresentMode) {
+        *self.present_mode.lock().unwrap() = mode;
+        self.present_mode_update_requested
+            .store(true, Ordering::Release);
+    }
+
+    pub(crate) fn take_present_mode_request(&self) -> Option<gfx::PresentMode> {
+        self.present_mode_update_requested
+            .swap(false, Ordering::AcqRel)
+            .then(|| self.present_mode())
+    }
+
+    /// Returns the maximum number of frames presented per second.
+    pub fn frame_rate_limit(&self) -> Option<u32> {
+        match self.frame_rate_limit.load(Ordering::Relaxed) {
+            0 => None,
+            fps => Some(fps),
+        }
+    }
+
+    /// Limits the number of frames presented per second, e.g. to not spin
+    /// the rendering thread with the mailbox or immediate present modes.
+    ///
+    /// The worker sleeps before each frame until the limit allows it.
+    /// Has no effect on headless renderers.
+    pub fn set_frame_rate_limit(&self, fps: Option<u32>) {
+        self.frame_rate_limit
+            .store(fps.unwrap_or_default(), Ordering::Relaxed);
+        let _ = self.worker_barrier.request_draw();
+    }
+
+    pub fn is_display_paced(&self) -> bool {
+        self.display_paced.load(Ordering::Relaxed)
+    }
+
+    /// Makes the interval between frames a multiple of the display refresh
+    /// cycle, so they are not presented unevenly with the frame rate limit.
+    ///
+    /// Without the frame rate limit frames are paced to the refresh rate.
+    /// Frames which keep missing refresh cycles are shown for more cycles
+    /// until they are on time again, see [`FrameStats::missed_vblanks`].
+    ///
+    /// Has no effect if [`gfx::DeviceFeature::DisplayTiming`] is not supported.
+    pub fn set_display_paced(&self, display_paced: bool) {
+        self.display_paced.store(display_paced, Ordering::Relaxed);
+    }
+
+    /// Requests the next frame to be copied into host memory.
+    ///
+    /// The frame is available with [`take_captured_frame`] once it is completed.
+    ///
+    /// NOTE: Only headless renderers support frame capture.
+    ///
+    /// [`take_captured_frame`]: Self::take_captured_frame
+    pub fn capture_next_frame(&self) -> Result<(), RendererError> {
+        if !self.is_headless() {
+            return Err(RendererError::Unsupported(
+                "frame capture requires a headless renderer",
+            ));
+        }
+        self.frame_capture_requested.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Takes the last captured frame.
+    pub fn take_captured_frame(&self) -> Option<CapturedFrame> {
+        self.captured_frame.lock().unwrap().take()
+    }
+
+    /// Waits for the requested frame capture to complete and takes it.
+    ///
+    /// Returns `None` on timeout or if the rendering thread exited
+    /// without drawing the frame.
+    ///
+    /// NOTE: Frames requested before the renderer was stopped are still
+    /// drawn, so the capture is awaited until the thread exits.
+    pub fn wait_captured_frame(&self, timeout: Duration) -> Option<CapturedFrame> {
+        let captured_frame = lock_ignore_poison(&self.captured_frame);
+        let (mut captured_frame, _) = self
+            .captured_frame_ready
+            .wait_timeout_while(captured_frame, timeout, |frame| {
+                frame.is_none() && !self.worker_barrier.is_stopped()
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+        captured_frame.take()
+    }
+
+    pub(crate) fn take_frame_capture_request(&self) -> bool {
+        self.frame_capture_requested.swap(false, Ordering::AcqRel)
+    }
+
+    pub(crate) fn set_captured_frame(&self, frame: CapturedFrame) {
+        *self.captured_frame.lock().unwrap() = Some(frame);
+        self.captured_frame_ready.notify_all();
+    }
+
+    /// Requests the object drawn at the position in pixels
+    /// from the top left corner of the window.
+    ///
+    /// The request is resolved once the frame drawn after this call is completed,
+    /// so it requires a couple more frames to be drawn with [`notify_draw`].
+    /// Objects are picked regardless of the material render state,
+    /// e.g. transparent objects can be picked as well.
+    ///
+    /// [`notify_draw`]: Self::notify_draw
+    pub fn pick(&self, x: u32, y: u32) -> PickRequest {
+        let request = PickRequest::new();
+        self.pick_requests.lock().unwrap().push(PendingPick {
+            position: UVec2::new(x, y),
+            request: request.clone(),
+        });
+        request
+    }
+
+    /// Takes at most `limit` pick requests in the order they were made.
+    pub(crate) fn take_pick_requests(&self, limit: usize) -> Vec<PendingPick> {
+        let mut requests = self.pick_requests.lock().unwrap();
+        let count = requests.len().min(limit);
+        requests.drain(..count).collect()
+    }
+
+    pub(crate) fn upgrade_static_object_handle(
+        &self,
+        handle: RawStaticObjectHandle,
+    ) -> Option<StaticObjectHandle> {
+        self.handles.static_object_handle_allocator.upgrade(handle)
+    }
+
+    pub(crate) fn upgrade_dynamic_object_handle(
+        &self,
+        handle: RawDynamicObjectHandle,
+    ) -> Option<DynamicObjectHandle> {
+        self.handles.dynamic_object_handle_allocator.upgrade(handle)
+    }
+
+    /// Returns the pool for buffer readbacks recorded by compute nodes,
+    /// see [`gfx::Encoder::download_buffer`].
+    pub fn readback_pool(&self) -> &gfx::ReadbackPool {
+        &self.readback_pool
+    }
+
+    /// Returns the data of a buffer readback once its frame is completed,
+    /// see [`RendererState::readback_pool`].
+    ///
+    /// NOTE: The data is returned only once, `None` is returned afterwards.
+    pub fn poll_readback(&self, ticket: &ReadbackTicket) -> Result<Option<Vec<u8>>, RendererError> {
+        self.readback_pool
+            .poll(ticket)
+            .map_err(|e| RendererError::from_internal(e.into()))
+    }
+
+    /// Takes events emitted by the rendering thread since the last call.
+    pub fn take_events(&self) -> Vec<RendererEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    pub(crate) fn push_event(&self, event: RendererEvent) {
+        let mut events = self.events.lock().unwrap();
+
+        // NOTE: Only the last size matters, this also bounds the queue
+        // if events are never taken.
+        if let RendererEvent::Resized { .. } = event {
+            events.retain(|event| !matches!(event, RendererEvent::Resized { .. }));
+        }
+        events.push(event);
+    }
+
+    /// Requests the window surface to be recreated before the next frame.
+    ///
+    /// Must be used on platforms where the native window handle changes
+    /// during the window lifetime.
+    pub fn recreate_surface(&self) {
+        self.surface_recreation_requested
+            .store(true, Ordering::Release);
+    }
+
+    pub(crate) fn take_surface_recreation_request(&self) -> bool {
+        self.surface_recreation_requested
+            .swap(false, Ordering::AcqRel)
+    }
+
+    /// Notifies the renderer that the window was resized.
+    ///
+    /// The swapchain is reconfigured before the next frame. Frames are skipped
+    /// while the window has a zero size (e.g. when it is minimized), but the
+    /// submitted instructions are still processed.
+    pub fn notify_resized(&self) {
+        self.surface_resize_requested.store(true, Ordering::Release);
+        let _ = self.worker_barrier.request_draw();
+    }
+
+    pub(crate) fn take_surface_resize_request(&self) -> bool {
+        self.surface_resize_requested.swap(false, Ordering::AcqRel)
+    }
+
+    pub(crate) fn take_frame_timer_reset_request(&self) -> bool {
+        self.frame_timer_reset_requested
+            .swap(false, Ordering::AcqRel)
+    }
+
+    /// Adds a compute node which is executed before the main pass each frame.
+    ///
+    /// The node is added to the render graph before the next frame.
+    pub fn add_compute_node(&self, node: Box<dyn ComputeNode>) -> Result<(), RendererError> {
+        if !self.queue.capabilities().supports_compute() {
+            return Err(RendererError::Unsupported(
+                "graphics queue doesn't support compute",
+            ));
+        }
+        self.pending_compute_nodes.lock().unwrap().push(node);
+        Ok(())
+    }
+
+    pub(crate) fn take_compute_nodes(&self) -> Vec<Box<dyn ComputeNode>> {
+        std::mem::take(&mut *self.pending_compute_nodes.lock().unwrap())
+    }
+
+    /// Requests shaders to be reloaded before the next frame.
+    ///
+    /// Pipelines which failed to recompile keep using the previous shaders.
+    pub fn reload_shaders(&self) {
+        self.shaders_reload_requested.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn take_shaders_reload_request(&self) -> bool {
+        self.shaders_reload_requested.swap(false, Ordering::AcqRel)
+    }
+
+    pub(crate) fn reload_shader_sources(&self) -> Result<()> {
+        let mut shader_preprocessor = self.shader_preprocessor.lock().unwrap();
+        load_shaders(
+            &mut shader_preprocessor,
+            self.shaders_override_dir.as_deref(),
+        )
+    }
+
+    /// Requests the next frame.
+    ///
+    /// Requests sent while the previous frame is drawn are coalesced
+    /// into a single frame.
+    pub fn request_draw(&self) -> DrawRequestResult {
+        self.worker_barrier.request_draw()
+    }
+
+    /// Same as [`request_draw`], but ignores whether the frame will be drawn.
+    ///
+    /// [`request_draw`]: Self::request_draw
+    pub fn notify_draw(&self) {
+        let _ = self.request_draw();
+    }
+
+    /// Sets the camera for the next frames.
+    ///
+    /// The projection matrix is recomputed with the current aspect ratio
+    /// whenever the render resolution changes (except for custom projections).
+    pub fn update_camera(&self, view: &Mat4, projection: &CameraProjection) {
+        self.frame_resources.set_camera(view, projection);
+    }
+
+    /// Draws depth-tested lines during the next frame.
+    ///
+    /// Each line is a `(start, end, color)` tuple in world space.
+    /// Lines over [`MAX_DEBUG_LINES`] until the next frame are dropped.
+    ///
+    /// [`MAX_DEBUG_LINES`]: materials::MAX_DEBUG_LINES
+    pub fn draw_debug_lines(&self, lines: &[(Vec3, Vec3, Color)]) {
+        let mut debug_lines = self.debug_lines.lock().unwrap();
+        debug_lines.add_depth_tested(lines);
+    }
+
+    /// Draws lines on top of all geometry during the next frame.
+    ///
+    /// Each line is a `(start, end, color)` tuple in world space.
+    pub fn draw_debug_lines_on_top(&self, lines: &[(Vec3, Vec3, Color)]) {
+        let mut debug_lines = self.debug_lines.lock().unwrap();
+        debug_lines.add_on_top(lines);
+    }
+
+    pub(crate) fn take_debug_lines(&self) -> DebugLines {
+        std::mem::take(&mut *self.debug_lines.lock().unwrap())
+    }
+
+    /// Draws text on top of the next frame.
+    ///
+    /// `position` is the top left corner of the text in pixels from the top left
+    /// corner of the target, `size` is the height of the glyphs in pixels.
+    /// Lines are separated by `\n`.
+    /// Glyphs over [`MAX_TEXT_GLYPHS`] until the next frame are dropped.
+    ///
+    /// NOTE: Fonts have glyphs only for printable ASCII characters.
+    /// The built-in bitmap font is 9 pixels tall, so multiples of 9 keep
+    /// it sharp. Other fonts require the `ab_glyph` feature, see `RendererBuilder::font`.
+    ///
+    /// [`MAX_TEXT_GLYPHS`]: materials::MAX_TEXT_GLYPHS
+    pub fn draw_text_2d(&self, position: Vec2, text: &str, size: f32, color: Color) {
+        let mut glyphs = self.text.lock().unwrap();
+        glyphs.add_2d(&self.font, position, text, size, color);
+    }
+
+    /// Draws text centered at the projected world `position` on top of the next frame.
+    ///
+    /// The text keeps its `size` in pixels regardless of the distance,
+    /// see [`RendererState::draw_text_2d`].
+    pub fn draw_text_3d(&self, position: Vec3, text: &str, size: f32, color: Color) {
+        let mut glyphs = self.text.lock().unwrap();
+        glyphs.add_3d(&self.font, position, text, size, color);
+    }
+
+    pub(crate) fn take_text(&self) -> TextGlyphs {
+        std::mem::take(&mut *self.text.lock().unwrap())
+    }
+
+    pub(crate) fn has_text(&self) -> bool {
+        !self.text.lock().unwrap().is_empty()
+    }
+
+    /// Replaces meshes which are drawn on top of each frame.
+    ///
+    /// Unlike debug lines, the overlay is kept until it is replaced,
+    /// so it doesn't flicker when frames are rendered more often than it is updated.
+    pub fn set_overlay(&self, meshes: Vec<OverlayMesh>) {
+        *self.overlay.lock().unwrap() = meshes;
+    }
+
+    pub(crate) fn overlay(&self) -> MutexGuard<'_, Vec<OverlayMesh>> {
+        self.overlay.lock().unwrap()
+    }
+
+    pub(crate) fn render_pass_context(&self, frame: u32) -> RenderPassContext<'_> {
+        RenderPassContext {
+            device: &self.device,
+            render_passes: &self.render_pass_cache,
+            framebuffers: &self.framebuffer_cache,
+            frame,
+        }
+    }
+
+    /// Uploads the mesh, or defers its upload until the memory budget allows it.
+    pub fn add_mesh(self: &Arc<Self>, mesh: &Mesh) -> Result<MeshHandle, RendererError> {
+        let mesh = self
+            .mesh_manager
+            .upload_mesh(&self.queue, &self.staging_belt, mesh)
+            .map_err(RendererError::from_internal)?;
+
+        let state = Arc::downgrade(self);
+        let handle = self
+            .handles
+            .mesh_handle_allocator
+            .alloc(Arc::new(InstructedHandleDeleter(state)));
+
+        self.mesh_manager.add(handle.raw(), mesh);
+        Ok(handle)
+    }
+
+    /// Replaces the data of the mesh.
+    ///
+    /// Objects which use this mesh are updated automatically. The new mesh
+    /// must have at least the same vertex attributes as the current one.
+    pub fn update_mesh(
+        self: &Arc<Self>,
+        handle: &MeshHandle,
+        mesh: &Mesh,
+    ) -> Result<(), RendererError> {
+        {
+            let mesh_manager_data = self.mesh_manager.lock_data();
+            let current = mesh_manager_data
+                .get(handle.raw())
+                .ok_or(RendererError::InvalidHandle { kind: "mesh" })?;
+
+            let missing_attributes = current
+                .attributes()
+                .filter(|&kind| !mesh.attribute_data().iter().any(|a| a.kind() == kind))
+                .collect::<Vec<_>>();
+
+            if !missing_attributes.is_empty() {
+                return Err(RendererError::InvalidMesh {
+                    reason: format!(
+                        "updated mesh is missing vertex attributes: {missing_attributes:?}"
+                    ),
+                });
+            }
+        }
+
+        let staged = self
+            .mesh_manager
+            .stage_mesh(&self.staging_belt, mesh)
+            .map_err(RendererError::from_internal)?;
+
+        self.send(Instruction::UpdateMesh {
+            handle: handle.raw(),
+            staged: staged.map(Box::new),
+        })
+    }
+
+    /// Returns `true` if textures of the `format` can be sampled on this device.
+    ///
+    /// Block-compressed formats are usually only supported on a subset of
+    /// platforms, e.g. BC on desktop and ASTC on mobile GPUs, so loaders should
+    /// fall back to uncompressed formats when this returns `false`.
+    pub fn supports_texture_format(&self, format: gfx::Format) -> bool {
+        self.device.format_supported(
+            format,
+            gfx::ImageUsageFlags::TRANSFER_DST | gfx::ImageUsageFlags::SAMPLED,
+        )
     }
 
-    pub fn cleanup(&mut self) -> Result<()> {
-        if let Some(worker_thread) = self.worker_thread.take() {
-            self.state.set_running(false);
-            worker_thread.join().unwrap();
-            self.state.device.wait_idle()?;
+    fn check_texture_format(&self, format: gfx::Format) -> Result<(), RendererError> {
+        if self.supports_texture_format(format) {
+            Ok(())
+        } else {
+            Err(RendererError::InvalidTexture {
+                reason: format!("texture format {format:?} is not supported by the device"),
+            })
         }
-        Ok(())
     }
-}
 
-impl Drop for Renderer {
-    fn drop(&mut self) {
-        if let Err(e) = self.cleanup() {
-            tracing::error!("failed to cleanup renderer: {e:?}");
-        }
+    /// Changes the maximum anisotropy of texture sampling, `None` disables
+    /// anisotropic filtering.
+    ///
+    /// Samplers of all textures are replaced before the next frame without
+    /// reuploading them, except for materials which override the sampler
+    /// (see [`SamplerOverride`]). Returns the applied value which is clamped
+    /// to the device limit, see [`RendererBuilder::default_anisotropy`].
+    pub fn set_default_anisotropy(&self, max_anisotropy: Option<f32>) -> Option<f32> {
+        let max_anisotropy = resolve_max_anisotropy(
+            max_anisotropy,
+            self.device
+                .is_feature_enabled(gfx::DeviceFeature::SamplerAnisotropy),
+            self.device.limits().max_sampler_anisotropy,
+        );
+        self.send(Instruction::SetDefaultAnisotropy { max_anisotropy });
+        max_anisotropy
     }
-}
 
-pub struct RendererState {
-    is_running: AtomicBool,
-    worker_barrier: LoopBarrier,
-    instructions: InstructionQueue,
+    /// Returns the maximum anisotropy of texture sampling.
+    ///
+    /// NOTE: The value set by [`RendererState::set_default_anisotropy`]
+    /// is returned only after it is applied before the next frame.
+    pub fn default_anisotropy(&self) -> Option<f32> {
+        self.texture_manager.max_anisotropy()
+    }
 
-    mesh_manager: MeshManager,
-    synced_managers: Mutex<RendererStateSyncedManagers>,
-    handles: RendererStateHandles,
+    /// Uploads the texture and registers it in the bindless descriptor set.
+    ///
+    /// `data` must contain tightly packed texels of the specified `format`.
+    pub fn add_texture(
+        self: &Arc<Self>,
+        data: &[u8],
+        extent: UVec2,
+        format: gfx::Format,
+    ) -> Result<TextureHandle, RendererError> {
+        validate_texture_data(data, extent, format)
+            .map_err(|reason| RendererError::InvalidTexture { reason })?;
+        self.check_texture_format(format)?;
+
+        let texture = self
+            .texture_manager
+            .upload_texture(&self.queue, &self.bindless_resources, data, extent, format)
+            .map_err(RendererError::from_internal)?;
 
-    frame_resources: FrameResources,
-    bindless_resources: BindlessResources,
-    multi_buffer_arena: MultiBufferArena,
-    shader_preprocessor: ShaderPreprocessor,
-    scatter_copy: ScatterCopy,
+        let state = Arc::downgrade(self);
+        let handle = self
+            .handles
+            .texture_handle_allocator
+            .alloc(Arc::new(InstructedHandleDeleter(state)));
 
-    window: Arc<Window>,
-    queue: gfx::Queue,
+        self.texture_manager.add(handle.raw(), texture);
+        Ok(handle)
+    }
 
-    // NOTE: device must be dropped last
-    device: gfx::Device,
-}
+    /// Uploads the smallest mip levels of the texture and streams
+    /// the larger ones according to its priority.
+    ///
+    /// The texture is used in materials in the same way as the regular ones.
+    /// See [`set_texture_priority`] for details.
+    ///
+    /// [`set_texture_priority`]: Self::set_texture_priority
+    pub fn add_streamed_texture(
+        self: &Arc<Self>,
+        texture: StreamedTexture,
+    ) -> Result<TextureHandle, RendererError> {
+        validate_streamed_texture(&texture)
+            .map_err(|reason| RendererError::InvalidTexture { reason })?;
+        self.check_texture_format(texture.format)?;
+
+        let tail = load_mips(
+            &texture.data,
+            texture.extent,
+            texture.format,
+            texture.tail_level()..texture.mip_levels,
+        )
+        .map_err(|reason| RendererError::InvalidTexture { reason })?
+        .into_iter()
+        .map(Cow::into_owned)
+        .collect::<Vec<_>>();
+
+        let (gpu_texture, streamed) = self
+            .texture_streamer
+            .upload_tail(
+                &self.queue,
+                &self.texture_manager,
+                &self.bindless_resources,
+                texture,
+                &tail,
+            )
+            .map_err(RendererError::from_internal)?;
 
-impl RendererState {
-    pub fn window(&self) -> &Arc<Window> {
-        &self.window
+        let state = Arc::downgrade(self);
+        let handle = self
+            .handles
+            .texture_handle_allocator
+            .alloc(Arc::new(InstructedHandleDeleter(state)));
+
+        self.texture_manager.add(handle.raw(), gpu_texture);
+        self.texture_streamer.add(handle.raw(), streamed);
+        Ok(handle)
     }
 
-    pub fn set_running(&self, is_running: bool) {
-        self.is_running.store(is_running, Ordering::Release);
-        self.worker_barrier.notify();
+    /// Sets the approximate on-screen size in pixels of the streamed texture.
+    ///
+    /// Mip levels larger than the size are not loaded, textures with zero
+    /// priority only keep the levels which were uploaded immediately.
+    /// Under memory pressure, the largest levels of the textures
+    /// with the lowest priorities are evicted first.
+    ///
+    /// See [`CameraProjection::projected_size`] for a simple estimate.
+    pub fn set_texture_priority(&self, handle: &TextureHandle, priority: f32) {
+        if !self.texture_streamer.set_priority(handle.raw(), priority) {
+            tracing::warn!(?handle, "tried to set a priority of a non-streamed texture");
+        }
     }
 
-    pub fn notify_draw(&self) {
-        self.worker_barrier.notify();
+    /// Sets the total size of all streamed textures above which
+    /// their largest mip levels are evicted.
+    pub fn set_texture_streaming_budget(&self, bytes: u64) {
+        self.texture_streamer.set_budget(bytes);
     }
 
-    pub fn update_camera(&self, view: &Mat4, projection: &CameraProjection) {
-        self.frame_resources.set_camera(view, projection);
+    /// Uploads the cube texture and uses it as the background of all frames.
+    ///
+    /// `faces` are in the `+X, -X, +Y, -Y, +Z, -Z` order, each of them must contain
+    /// `size x size` tightly packed texels of the specified `format`.
+    ///
+    /// NOTE: The previous skybox is replaced.
+    pub fn set_skybox(
+        &self,
+        faces: [&[u8]; 6],
+        size: u32,
+        format: gfx::Format,
+    ) -> Result<(), RendererError> {
+        validate_cube_texture_data(faces, size, format)
+            .map_err(|reason| RendererError::InvalidTexture { reason })?;
+        self.check_texture_format(format)?;
+
+        let texture = self
+            .texture_manager
+            .upload_cube_texture(&self.queue, &self.bindless_resources, faces, size, format)
+            .map_err(RendererError::from_internal)?;
+
+        self.send(Instruction::SetSkybox {
+            texture: Some(Box::new(texture)),
+        })
+    }
+
+    /// Removes the skybox set by [`set_skybox`].
+    ///
+    /// [`set_skybox`]: Self::set_skybox
+    pub fn remove_skybox(&self) -> Result<(), RendererError> {
+        self.send(Instruction::SetSkybox { texture: None })
     }
 
-    pub fn add_mesh(self: &Arc<Self>, mesh: &Mesh) -> Result<MeshHandle> {
-        let mesh = self.mesh_manager.upload_mesh(&self.queue, mesh)?;
+    /// Adds an offscreen target which is drawn with its own camera,
+    /// e.g. for mirrors, portals or minimaps.
+    ///
+    /// The target is drawn before the main pass of each frame once its camera
+    /// is set with [`set_render_target_camera`], and is sampled by materials
+    /// through [`RenderTargetHandle::texture`]. At most
+    /// [`MAX_RENDER_TARGETS_PER_FRAME`] targets are drawn each frame
+    /// in the order they were added.
+    ///
+    /// NOTE: Objects which sample the target must not be visible from its
+    /// own camera, since the target can't be sampled while it is drawn.
+    ///
+    /// [`set_render_target_camera`]: Self::set_render_target_camera
+    pub fn add_render_target(
+        self: &Arc<Self>,
+        extent: UVec2,
+        format: gfx::Format,
+    ) -> Result<RenderTargetHandle, RendererError> {
+        self.check_render_target(extent, format)?;
+
+        let view = self
+            .render_targets
+            .create_view(&self.queue, &self.texture_manager, extent, format)
+            .map_err(RendererError::from_internal)?;
+        let texture = self.texture_manager.register_view(
+            &self.device,
+            &self.bindless_resources,
+            view.clone(),
+        );
 
         let state = Arc::downgrade(self);
         let handle = self
             .handles
-            .mesh_handle_allocator
+            .texture_handle_allocator
             .alloc(Arc::new(InstructedHandleDeleter(state)));
 
-        self.mesh_manager.add(handle.raw(), mesh);
-        Ok(handle)
+        self.texture_manager.add(handle.raw(), texture);
+        self.render_targets.add(handle.raw(), view);
+        Ok(RenderTargetHandle::new(handle))
+    }
+
+    /// Sets the camera with which the render target is drawn.
+    ///
+    /// The projection is computed with the aspect ratio of the target.
+    pub fn set_render_target_camera(
+        &self,
+        handle: &RenderTargetHandle,
+        view: &Mat4,
+        projection: &CameraProjection,
+    ) {
+        let camera = RenderTargetCamera {
+            view: *view,
+            projection: *projection,
+        };
+        if !self
+            .render_targets
+            .set_camera(handle.texture().raw(), camera)
+        {
+            tracing::warn!(?handle, "tried to set a camera of a removed render target");
+        }
+    }
+
+    /// Changes the extent of the render target keeping its texture handle.
+    ///
+    /// NOTE: The image is recreated before the target is drawn next time,
+    /// the previous one is destroyed once the frames in flight are completed.
+    pub fn resize_render_target(
+        &self,
+        handle: &RenderTargetHandle,
+        extent: UVec2,
+    ) -> Result<(), RendererError> {
+        let Some(format) = self.render_targets.format(handle.texture().raw()) else {
+            tracing::warn!(?handle, "tried to resize a removed render target");
+            return Ok(());
+        };
+        validate_texture_extent(extent, format)
+            .map_err(|reason| RendererError::InvalidTexture { reason })?;
+
+        self.render_targets.resize(handle.texture().raw(), extent);
+        Ok(())
+    }
+
+    fn check_render_target(&self, extent: UVec2, format: gfx::Format) -> Result<(), RendererError> {
+        validate_texture_extent(extent, format)
+            .map_err(|reason| RendererError::InvalidTexture { reason })?;
+
+        let usage = gfx::ImageUsageFlags::COLOR_ATTACHMENT | gfx::ImageUsageFlags::SAMPLED;
+        if !self.device.format_supported(format, usage) {
+            return Err(RendererError::InvalidTexture {
+                reason: format!("render target format {format:?} is not supported by the device"),
+            });
+        }
+        Ok(())
     }
 
+    /// Adds a material instance which can be used by objects.
+    ///
+    /// NOTE: Objects keep the material alive, so it is removed only after
+    /// the returned handle and all objects created with it are dropped.
     pub fn add_material_instance<M: MaterialInstance>(
         self: &Arc<Self>,
         material: M,
-    ) -> MaterialInstanceHandle {
+    ) -> Result<MaterialInstanceHandle, RendererError> {
         let state = Arc::downgrade(self);
         let handle = self
             .handles
             .material_handle_allocator
             .alloc(Arc::new(InstructedHandleDeleter(state)));
 
-        self.instructions.send(Instruction::AddMaterialInstance {
+        self.material_required_attributes.lock().unwrap().insert(
+            handle.raw(),
+            (
+                M::required_attributes().as_ref().into(),
+                M::attribute_policy(),
+            ),
+        );
+
+        self.send(Instruction::AddMaterialInstance {
             handle: handle.raw(),
             on_add: Box::new(move |manager, handle| {
                 manager.insert_material_instance(handle, material)
             }),
-        });
-        handle
+        })?;
+        Ok(handle)
     }
 
     pub fn update_material<M: MaterialInstance>(
         self: &Arc<Self>,
         handle: &MaterialInstanceHandle,
         material: M,
-    ) {
-        self.instructions.send(Instruction::UpdateMaterial {
+    ) -> Result<(), RendererError> {
+        self.send(Instruction::UpdateMaterial {
             handle: handle.raw(),
             on_update: Box::new(move |manager, handle| manager.update(handle, material)),
-        });
+        })
     }
 
+    /// Adds a static object drawn with either a single material or
+    /// a material per mesh submesh.
     pub fn add_static_object(
         self: &Arc<Self>,
         mesh_handle: MeshHandle,
-        material_handle: MaterialInstanceHandle,
+        materials: impl Into<ObjectMaterials>,
+        global_transform: &Mat4,
+    ) -> Result<StaticObjectHandle, RendererError> {
+        self.add_static_object_with_options(
+            mesh_handle,
+            materials,
+            global_transform,
+            ObjectOptions::default(),
+        )
+    }
+
+    /// Adds a static object which is rendered only by nodes with
+    /// a matching layer mask.
+    pub fn add_static_object_with_layers(
+        self: &Arc<Self>,
+        mesh_handle: MeshHandle,
+        materials: impl Into<ObjectMaterials>,
+        global_transform: &Mat4,
+        layers: u32,
+    ) -> Result<StaticObjectHandle, RendererError> {
+        self.add_static_object_with_options(
+            mesh_handle,
+            materials,
+            global_transform,
+            ObjectOptions {
+                layers,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Adds a static object with the specified layers and shadow casting.
+    pub fn add_static_object_with_options(
+        self: &Arc<Self>,
+        mesh_handle: MeshHandle,
+        materials: impl Into<ObjectMaterials>,
         global_transform: &Mat4,
-    ) -> StaticObjectHandle {
+        options: ObjectOptions,
+    ) -> Result<StaticObjectHandle, RendererError> {
+        let materials = materials.into();
+        self.validate_object(&mesh_handle, &materials)?;
+
         let state = Arc::downgrade(self);
         let handle = self
             .handles
             .static_object_handle_allocator
             .alloc(Arc::new(InstructedHandleDeleter(state)));
 
-        self.instructions.send(Instruction::AddStaticObject {
+        self.send(Instruction::AddStaticObject {
             handle: handle.raw(),
             object: Box::new(ObjectData {
                 mesh: mesh_handle,
-                material: material_handle,
+                materials,
                 global_transform: *global_transform,
+                options,
             }),
-        });
-        handle
+        })?;
+        Ok(handle)
     }
 
+    /// Adds a dynamic object drawn with either a single material or
+    /// a material per mesh submesh.
     pub fn add_dynamic_object(
         self: &Arc<Self>,
         mesh_handle: MeshHandle,
-        material_handle: MaterialInstanceHandle,
+        materials: impl Into<ObjectMaterials>,
+        global_transform: &Mat4,
+    ) -> Result<DynamicObjectHandle, RendererError> {
+        self.add_dynamic_object_with_options(
+            mesh_handle,
+            materials,
+            global_transform,
+            ObjectOptions::default(),
+        )
+    }
+
+    /// Adds a dynamic object which is rendered only by nodes with
+    /// a matching layer mask.
+    pub fn add_dynamic_object_with_layers(
+        self: &Arc<Self>,
+        mesh_handle: MeshHandle,
+        materials: impl Into<ObjectMaterials>,
+        global_transform: &Mat4,
+        layers: u32,
+    ) -> Result<DynamicObjectHandle, RendererError> {
+        self.add_dynamic_object_with_options(
+            mesh_handle,
+            materials,
+            global_transform,
+            ObjectOptions {
+                layers,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Adds a dynamic object with the specified layers and shadow casting.
+    pub fn add_dynamic_object_with_options(
+        self: &Arc<Self>,
+        mesh_handle: MeshHandle,
+        materials: impl Into<ObjectMaterials>,
         global_transform: &Mat4,
-    ) -> DynamicObjectHandle {
+        options: ObjectOptions,
+    ) -> Result<DynamicObjectHandle, RendererError> {
+        let materials = materials.into();
+        self.validate_object(&mesh_handle, &materials)?;
+
         let state = Arc::downgrade(self);
         let handle = self
             .handles
             .dynamic_object_handle_allocator
             .alloc(Arc::new(InstructedHandleDeleter(state)));
 
-        self.instructions.send(Instruction::AddDynamicObject {
+        self.send(Instruction::AddDynamicObject {
             handle: handle.raw(),
             object: Box::new(ObjectData {
                 mesh: mesh_handle,
-                material: material_handle,
+                materials,
                 global_transform: *global_transform,
+                options,
             }),
-        });
-        handle
+        })?;
+        Ok(handle)
+    }
+
+    /// Checks that the mesh has all vertex attributes required by the materials
+    /// and that there is a material for each submesh if they are specified.
+    ///
+    /// Missing attributes are allowed if the material binds default values
+    /// instead of them (see [`MaterialAttributePolicy::Fallback`]).
+    fn validate_object(
+        &self,
+        mesh: &MeshHandle,
+        materials: &ObjectMaterials,
+    ) -> Result<(), RendererError> {
+        let mesh_manager_data = self.mesh_manager.lock_data();
+        let mesh = mesh_manager_data
+            .get(mesh.raw())
+            .ok_or(RendererError::InvalidHandle { kind: "mesh" })?;
+
+        if let ObjectMaterials::PerSubmesh(materials) = materials {
+            if materials.len() != mesh.submesh_count() || materials.is_empty() {
+                return Err(RendererError::IncompatibleMaterial {
+                    reason: format!(
+                        "expected a material for each of {} mesh submeshes, got {}",
+                        mesh.submesh_count(),
+                        materials.len()
+                    ),
+                });
+            }
+        }
+
+        for material in materials.iter() {
+            self.validate_object_material(mesh, material)?;
+        }
+        Ok(())
+    }
+
+    fn validate_object_material(
+        &self,
+        mesh: &GpuMesh,
+        material: &MaterialInstanceHandle,
+    ) -> Result<(), RendererError> {
+        let material_required_attributes = self.material_required_attributes.lock().unwrap();
+        let (required_attributes, policy) = material_required_attributes
+            .get(&material.raw())
+            .ok_or(RendererError::InvalidHandle { kind: "material" })?;
+
+        let missing_attributes = required_attributes
+            .iter()
+            .filter(|&&attribute| mesh.get_attribute_range(attribute).is_none())
+            .collect::<Vec<_>>();
+
+        if missing_attributes.is_empty() {
+            return Ok(());
+        }
+
+        let has_fallbacks = *policy == MaterialAttributePolicy::Fallback
+            && missing_attributes
+                .iter()
+                .all(|&&attribute| default_vertex_attribute_offset(attribute).is_some());
+        if !has_fallbacks {
+            return Err(RendererError::IncompatibleMaterial {
+                reason: format!(
+                    "mesh is missing vertex attributes required by the material: {missing_attributes:?}"
+                ),
+            });
+        }
+
+        tracing::warn!(
+            ?missing_attributes,
+            "mesh is missing vertex attributes required by the material, using default values"
+        );
+        Ok(())
     }
 
-    pub fn update_static_object(self: &Arc<Self>, handle: &StaticObjectHandle, transform: Mat4) {
-        self.instructions.send(Instruction::UpdateStaticObject {
+    pub fn update_static_object(
+        self: &Arc<Self>,
+        handle: &StaticObjectHandle,
+        transform: Mat4,
+    ) -> Result<(), RendererError> {
+        self.send(Instruction::UpdateStaticObject {
             handle: handle.raw(),
             transform: Box::new(transform),
-        });
+        })
     }
 
+    /// Sets the transform of the dynamic object at the end of the current fixed update.
+    ///
+    /// The object is interpolated towards it unless `teleport` is set or the object
+    /// was added during the same fixed update.
     pub fn update_dynamic_object(
         self: &Arc<Self>,
         handle: &DynamicObjectHandle,
         transform: Mat4,
         teleport: bool,
-    ) {
-        self.instructions.send(Instruction::UpdateDynamicObject {
+    ) -> Result<(), RendererError> {
+        self.send(Instruction::UpdateDynamicObject {
             handle: handle.raw(),
             transform: Box::new(transform),
             teleport,
-        });
+        })
+    }
+
+    /// Updates transforms of multiple static objects with a single instruction.
+    pub fn update_static_objects<'a>(
+        self: &Arc<Self>,
+        objects: impl IntoIterator<Item = (&'a StaticObjectHandle, Mat4)>,
+    ) -> Result<(), RendererError> {
+        let objects = objects
+            .into_iter()
+            .map(|(handle, transform)| (handle.raw(), transform))
+            .collect::<Box<[_]>>();
+        if objects.is_empty() {
+            return Ok(());
+        }
+        self.send(Instruction::UpdateStaticObjects { objects })
+    }
+
+    /// Updates transforms of multiple dynamic objects with a single instruction.
+    pub fn update_dynamic_objects<'a>(
+        self: &Arc<Self>,
+        objects: impl IntoIterator<Item = (&'a DynamicObjectHandle, Mat4)>,
+        teleport: bool,
+    ) -> Result<(), RendererError> {
+        let objects = objects
+            .into_iter()
+            .map(|(handle, transform)| (handle.raw(), transform))
+            .collect::<Box<[_]>>();
+        if objects.is_empty() {
+            return Ok(());
+        }
+        self.send(Instruction::UpdateDynamicObjects { objects, teleport })
+    }
+
+    /// Changes layers in which the static object is rendered.
+    pub fn set_static_object_layers(
+        self: &Arc<Self>,
+        handle: &StaticObjectHandle,
+        layers: u32,
+    ) -> Result<(), RendererError> {
+        self.send(Instruction::SetStaticObjectLayers {
+            handle: handle.raw(),
+            layers,
+        })
+    }
+
+    /// Changes layers in which the dynamic object is rendered.
+    pub fn set_dynamic_object_layers(
+        self: &Arc<Self>,
+        handle: &DynamicObjectHandle,
+        layers: u32,
+    ) -> Result<(), RendererError> {
+        self.send(Instruction::SetDynamicObjectLayers {
+            handle: handle.raw(),
+            layers,
+        })
+    }
+
+    /// Returns the last requested interval between fixed updates.
+    pub fn fixed_timestep(&self) -> Duration {
+        *self.fixed_timestep.lock().unwrap()
+    }
+
+    /// Sets the interval between fixed updates which is used to interpolate
+    /// dynamic objects between the last two updates.
+    ///
+    /// NOTE: The interpolation of the current update continues from the same
+    /// point with the new rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `timestep` is zero.
+    pub fn set_fixed_timestep(&self, timestep: Duration) -> Result<(), RendererError> {
+        assert!(!timestep.is_zero(), "fixed timestep must not be zero");
+
+        *self.fixed_timestep.lock().unwrap() = timestep;
+        self.send(Instruction::SetFixedTimestep {
+            timestep,
+            changed_at: Instant::now(),
+        })
     }
 
-    pub fn finish_fixed_update(self: &Arc<Self>, updated_at: Instant, duration: Duration) {
-        self.instructions.send(Instruction::FinishFixedUpdate {
+    pub fn finish_fixed_update(
+        self: &Arc<Self>,
+        updated_at: Instant,
+        duration: Duration,
+    ) -> Result<(), RendererError> {
+        self.send(Instruction::FinishFixedUpdate {
             updated_at,
             duration,
-        });
+        })
+    }
+
+    /// Sends the instruction to the rendering thread.
+    ///
+    /// Fails with [`RendererError::WorkerStopped`] once the thread is stopped.
+    ///
+    /// NOTE: Instructions sent after the shutdown are released immediately.
+    fn send(&self, instruction: Instruction) -> Result<(), RendererError> {
+        self.instructions
+            .try_send(instruction)
+            .map_err(|instruction| {
+                self.release_instruction(instruction);
+                RendererError::WorkerStopped
+            })
+    }
+
+    /// Returns the number of instructions which are not evaluated yet.
+    pub(crate) fn pending_instruction_count(&self) -> usize {
+        self.instructions.pending_len()
     }
 
+    /// Evaluates all pending instructions and counts them by kind.
+    ///
+    /// Returns uploads which must be submitted before the `encoder`.
+    ///
+    /// NOTE: Synced managers are neither locked nor flushed if nothing
+    /// was updated since the previous frame.
     #[tracing::instrument(level = "debug", name = "eval_instructions", skip_all)]
-    pub(crate) fn eval_instructions<'a>(
-        &'a self,
+    pub(crate) fn eval_instructions(
+        &self,
         encoder: &mut gfx::PrimaryEncoder,
-    ) -> Result<MutexGuard<'a, RendererStateSyncedManagers>> {
+        frame: u32,
+        completed_frame: Option<u32>,
+        counts: &mut InstructionCounts,
+    ) -> Result<FrameUploads> {
         self.instructions.swap();
 
-        self.bindless_resources.flush_retired();
+        self.bindless_resources
+            .flush_retired(frame, completed_frame);
+        self.staging_belt.recall();
+        self.readback_pool.recall();
+
+        if let Some(completed_frame) = completed_frame {
+            profile_scope!("complete_frame_resources");
+            self.texture_manager
+                .complete_removals(completed_frame, &self.bindless_resources);
+            self.render_pass_cache.retire(completed_frame);
+            self.framebuffer_cache.retire(completed_frame);
+            self.mesh_manager
+                .complete_uploads(completed_frame, |handle| {
+                    tracing::trace!(?handle, "remove_mesh_deferred");
+                    self.handles.mesh_handle_allocator.dealloc(handle);
+                });
+        }
+
+        let mut instructions = self.instructions.consumer();
 
-        let mut instructions = self.instructions.consumer.lock().unwrap();
+        let uploaded_meshes = self.mesh_manager.flush_deferred_uploads(&self.queue);
+        let moved_meshes = self
+            .mesh_manager
+            .defragment(&self.queue)
+            .unwrap_or_else(|e| {
+                tracing::error!("failed to defragment meshes: {e:?}");
+                Vec::new()
+            });
+
+        let pending = PendingUpdates {
+            instructions: !instructions.is_empty(),
+            updated_meshes: !uploaded_meshes.is_empty() || !moved_meshes.is_empty(),
+            moved_texture_slots: self.texture_manager.has_moved_slots(),
+            synced_managers: self.synced_managers_dirty.load(Ordering::Acquire),
+            used_arena_buffers: self.multi_buffer_arena.has_used_buffers(),
+        };
+        if pending.is_empty() {
+            self.frame_object_bytes_uploaded.store(0, Ordering::Relaxed);
+            return self.drain_uploads(frame);
+        }
 
         let mut synced_managers = self.synced_managers.lock().unwrap();
 
         let mut mesh_manager_data = None;
 
-        for instruction in instructions.drain(..) {
-            let synced_managers = &mut *synced_managers;
-            match instruction {
-                Instruction::RemoveMesh { handle } => {
-                    tracing::trace!(?handle, "remove_mesh");
-                    self.handles.mesh_handle_allocator.dealloc(handle);
-                    self.mesh_manager.remove(handle);
-                }
-                Instruction::AddMaterialInstance { handle, on_add } => {
-                    tracing::trace!(?handle, "add_material");
-                    on_add(&mut synced_managers.material_manager, handle);
-                }
-                Instruction::UpdateMaterial { handle, on_update } => {
-                    tracing::trace!(?handle, "update_material");
-                    on_update(&mut synced_managers.material_manager, handle);
-                }
-                Instruction::RemoveMaterial { handle } => {
-                    tracing::trace!(?handle, "remove_material");
-                    self.handles.material_handle_allocator.dealloc(handle);
-                    synced_managers.material_manager.remove(handle);
-                }
-                Instruction::AddStaticObject { handle, object } => {
-                    tracing::trace!(?handle, "add_static_object");
-                    let inner_meshes =
-                        mesh_manager_data.get_or_insert_with(|| self.mesh_manager.lock_data());
-
-                    synced_managers.object_manager.add_static_object(
-                        handle,
-                        object,
-                        inner_meshes,
-                        &mut synced_managers.material_manager,
-                    );
-                }
-                Instruction::AddDynamicObject { handle, object } => {
-                    tracing::trace!(?handle, "add_dynamic_object");
-                    let inner_meshes =
-                        mesh_manager_data.get_or_insert_with(|| self.mesh_manager.lock_data());
+        if !uploaded_meshes.is_empty() || !moved_meshes.is_empty() {
+            profile_scope!("update_meshes");
+            let inner_meshes =
+                mesh_manager_data.get_or_insert_with(|| self.mesh_manager.lock_data());
+            for handle in uploaded_meshes.into_iter().chain(moved_meshes) {
+                synced_managers
+                    .object_manager
+                    .update_mesh(handle, inner_meshes);
+            }
+        }
 
-                    synced_managers.object_manager.add_dynamic_object(
+        // NOTE: Updates queued after the removal of their material
+        // are dropped instead of reaching the material manager.
+        let mut removed_materials = FastHashSet::<RawMaterialInstanceHandle>::default();
+
+        if !instructions.is_empty() {
+            profile_scope!("instructions");
+            for instruction in instructions.drain(..) {
+                let synced_managers = &mut *synced_managers;
+                counts.record(instruction.kind());
+                match instruction {
+                    Instruction::RemoveMesh { handle } => {
+                        tracing::trace!(?handle, "remove_mesh");
+                        // NOTE: Release the registry lock since removal requires it
+                        mesh_manager_data = None;
+
+                        // NOTE: The handle must not be reused until the mesh is actually removed
+                        if self.mesh_manager.remove(handle) {
+                            self.handles.mesh_handle_allocator.dealloc(handle);
+                        }
+                    }
+                    Instruction::UpdateMesh { handle, staged } => {
+                        tracing::trace!(?handle, "update_mesh");
+                        // NOTE: Release the registry lock since the update requires it
+                        mesh_manager_data = None;
+
+                        match self.mesh_manager.update_mesh(&self.queue, handle, staged) {
+                            Ok(true) => {}
+                            // NOTE: Objects are updated when the deferred upload is flushed
+                            Ok(false) => continue,
+                            Err(e) => {
+                                tracing::error!(?handle, "failed to update mesh: {e:?}");
+                                continue;
+                            }
+                        }
+
+                        let inner_meshes =
+                            mesh_manager_data.get_or_insert_with(|| self.mesh_manager.lock_data());
+                        synced_managers
+                            .object_manager
+                            .update_mesh(handle, inner_meshes);
+                    }
+                    Instruction::RemoveTexture { handle } => {
+                        tracing::trace!(?handle, "remove_texture");
+                        self.texture_manager.remove(handle, frame);
+                        self.texture_streamer.remove(handle);
+                        self.render_targets.remove(handle);
+                        self.handles.texture_handle_allocator.dealloc(handle);
+                    }
+                    Instruction::SetSkybox { texture } => {
+                        tracing::trace!(remove = texture.is_none(), "set_skybox");
+                        self.texture_manager
+                            .set_skybox(texture.map(|texture| *texture), frame);
+                    }
+                    Instruction::SetDefaultAnisotropy { max_anisotropy } => {
+                        tracing::trace!(?max_anisotropy, "set_default_anisotropy");
+                        if let Err(e) = self.texture_manager.set_max_anisotropy(
+                            &self.device,
+                            &self.bindless_resources,
+                            max_anisotropy,
+                            frame,
+                        ) {
+                            tracing::error!("failed to replace texture sampler: {e:?}");
+                        }
+                    }
+                    Instruction::AddMaterialInstance { handle, on_add } => {
+                        tracing::trace!(?handle, "add_material");
+                        on_add(&mut synced_managers.material_manager, handle);
+                    }
+                    Instruction::UpdateMaterial { handle, on_update } => {
+                        tracing::trace!(?handle, "update_material");
+                        if removed_materials.contains(&handle) {
+                            tracing::warn!(?handle, "skipping update of a removed material");
+                            continue;
+                        }
+                        on_update(&mut synced_managers.material_manager, handle);
+                    }
+                    Instruction::RemoveMaterial { handle } => {
+                        tracing::trace!(?handle, "remove_material");
+                        removed_materials.insert(handle);
+                        self.handles.material_handle_allocator.dealloc(handle);
+                        self.material_required_attributes
+                            .lock()
+                            .unwrap()
+                            .remove(&handle);
+                        synced_managers.material_manager.remove(handle);
+                    }
+                    Instruction::AddStaticObject { handle, object } => {
+                        tracing::trace!(?handle, "add_static_object");
+                        let inner_meshes =
+                            mesh_manager_data.get_or_insert_with(|| self.mesh_manager.lock_data());
+
+                        synced_managers.object_manager.add_static_object(
+                            handle,
+                            object,
+                            inner_meshes,
+                            &mut synced_managers.material_manager,
+                        );
+                    }
+                    Instruction::AddDynamicObject { handle, object } => {
+                        tracing::trace!(?handle, "add_dynamic_object");
+                        let inner_meshes =
+                            mesh_manager_data.get_or_insert_with(|| self.mesh_manager.lock_data());
+
+                        synced_managers.object_manager.add_dynamic_object(
+                            handle,
+                            object,
+                            inner_meshes,
+                            &mut synced_managers.material_manager,
+                        );
+                    }
+                    Instruction::UpdateStaticObject { handle, transform } => {
+                        tracing::trace!(?handle, "update_static_object");
+                        synced_managers
+                            .object_manager
+                            .update_static_object(handle, transform.as_ref());
+                    }
+                    Instruction::UpdateDynamicObject {
                         handle,
-                        object,
-                        inner_meshes,
-                        &mut synced_managers.material_manager,
-                    );
-                }
-                Instruction::UpdateStaticObject { handle, transform } => {
-                    tracing::trace!(?handle, "update_static_object");
-                    synced_managers
-                        .object_manager
-                        .update_static_object(handle, transform.as_ref());
-                }
-                Instruction::UpdateDynamicObject {
-                    handle,
-                    transform,
-                    teleport,
-                } => {
-                    tracing::trace!(?handle, "update_dynamic_object");
-                    synced_managers.object_manager.update_dynamic_object(
-                        handle,
-                        transform.as_ref(),
+                        transform,
                         teleport,
-                    );
-                }
-                Instruction::RemoveStaticObject { handle } => {
-                    tracing::trace!(?handle, "remove_static_object");
-                    self.handles.static_object_handle_allocator.dealloc(handle);
-                    synced_managers.object_manager.remove_static_object(handle);
-                }
-                Instruction::RemoveDynamicObject { handle } => {
-                    tracing::trace!(?handle, "remove_dynamic_object");
-                    self.handles.dynamic_object_handle_allocator.dealloc(handle);
-                    synced_managers.object_manager.remove_dynamic_object(handle);
-                }
-                Instruction::FinishFixedUpdate {
-                    updated_at,
-                    duration,
-                } => {
-                    tracing::trace!(?updated_at, ?duration, "finish_fixed_update");
-
-                    synced_managers
-                        .object_manager
-                        .finalize_dynamic_object_transforms();
-
-                    synced_managers
-                        .time_manager
-                        .updated_fixed_time(updated_at, duration);
+                    } => {
+                        tracing::trace!(?handle, "update_dynamic_object");
+                        synced_managers.object_manager.update_dynamic_object(
+                            handle,
+                            transform.as_ref(),
+                            teleport,
+                        );
+                    }
+                    Instruction::UpdateStaticObjects { objects } => {
+                        tracing::trace!(count = objects.len(), "update_static_objects");
+                        for (handle, transform) in objects.iter() {
+                            synced_managers
+                                .object_manager
+                                .update_static_object(*handle, transform);
+                        }
+                    }
+                    Instruction::UpdateDynamicObjects { objects, teleport } => {
+                        tracing::trace!(count = objects.len(), "update_dynamic_objects");
+                        for (handle, transform) in objects.iter() {
+                            synced_managers
+                                .object_manager
+                                .update_dynamic_object(*handle, transform, teleport);
+                        }
+                    }
+                    Instruction::SetStaticObjectLayers { handle, layers } => {
+                        tracing::trace!(?handle, layers, "set_static_object_layers");
+                        synced_managers
+                            .object_manager
+                            .set_static_object_layers(handle, layers);
+                    }
+                    Instruction::SetDynamicObjectLayers { handle, layers } => {
+                        tracing::trace!(?handle, layers, "set_dynamic_object_layers");
+                        synced_managers
+                            .object_manager
+                            .set_dynamic_object_layers(handle, layers);
+                    }
+                    Instruction::RemoveStaticObject { handle } => {
+                        tracing::trace!(?handle, "remove_static_object");
+                        self.handles.static_object_handle_allocator.dealloc(handle);
+                        synced_managers.object_manager.remove_static_object(handle);
+                    }
+                    Instruction::RemoveDynamicObject { handle } => {
+                        tracing::trace!(?handle, "remove_dynamic_object");
+                        self.handles.dynamic_object_handle_allocator.dealloc(handle);
+                        synced_managers.object_manager.remove_dynamic_object(handle);
+                    }
+                    Instruction::FinishFixedUpdate {
+                        updated_at,
+                        duration,
+                    } => {
+                        tracing::trace!(?updated_at, ?duration, "finish_fixed_update");
+
+                        synced_managers
+                            .object_manager
+                            .finalize_dynamic_object_transforms();
+
+                        synced_managers
+                            .time_manager
+                            .updated_fixed_time(updated_at, duration);
+                    }
+                    Instruction::SetFixedTimestep {
+                        timestep,
+                        changed_at,
+                    } => {
+                        tracing::trace!(?timestep, "set_fixed_timestep");
+
+                        synced_managers
+                            .time_manager
+                            .set_fixed_timestep(timestep, changed_at);
+                    }
                 }
             }
         }
 
-        synced_managers.object_manager.flush_static_objects(
-            &self.device,
-            encoder,
-            &self.scatter_copy,
-            &self.bindless_resources,
-            &self.multi_buffer_arena,
-        )?;
+        // NOTE: Release the registry lock before draining mesh uploads
+        drop(mesh_manager_data);
 
-        synced_managers.material_manager.flush(
-            &self.device,
-            encoder,
-            &self.scatter_copy,
+        // NOTE: Materials store bindless indices of their textures
+        if self.texture_manager.take_moved_slots() {
+            synced_managers.material_manager.update_all();
+        }
+
+        // NOTE: Managers are flushed only if anything was updated since the
+        // previous flushes, so idle scenes don't record any writes.
+        if synced_managers.material_manager.is_dirty() {
+            let material_slot_remaps = synced_managers.material_manager.flush(
+                &self.device,
+                encoder,
+                &self.scatter_copy,
+                &self.bindless_resources,
+                &self.texture_manager.lock_data(),
+            )?;
+            synced_managers
+                .object_manager
+                .remap_material_slots(&material_slot_remaps);
+        }
+
+        synced_managers.object_manager.shrink_sparse_archetypes();
+
+        let mut object_bytes = 0;
+        if synced_managers.object_manager.is_dirty() {
+            object_bytes = synced_managers.object_manager.flush(
+                &self.device,
+                encoder,
+                &self.scatter_copy,
+                &self.bindless_resources,
+            )?;
+        }
+        // NOTE: Transforms of dynamic objects are added by the render graph
+        self.frame_object_bytes_uploaded
+            .store(object_bytes as u64, Ordering::Relaxed);
+
+        // NOTE: Writes of all managers are recorded at once
+        self.scatter_copy
+            .flush(&self.device, encoder, &self.multi_buffer_arena)?;
+
+        // NOTE: Both buffers of the managers are written on consecutive flushes
+        let dirty = synced_managers.material_manager.is_dirty()
+            || synced_managers.object_manager.is_dirty()
+            || synced_managers.object_manager.has_sparse_archetypes();
+        self.synced_managers_dirty.store(dirty, Ordering::Release);
+        drop(synced_managers);
+
+        self.multi_buffer_arena
+            .flush(frame, completed_frame, &self.bindless_resources);
+
+        self.drain_uploads(frame)
+    }
+
+    /// Updates streamed textures and finishes uploads recorded since the previous frame.
+    fn drain_uploads(&self, frame: u32) -> Result<FrameUploads> {
+        self.texture_streamer.update(
+            &self.queue,
+            &self.texture_manager,
             &self.bindless_resources,
-            &self.multi_buffer_arena,
+            frame,
         )?;
 
-        if let Some(secondary) = self
+        // NOTE: Uploads are submitted in the same batch as the frame commands
+        let mut uploads = FrameUploads::default();
+        if let Some(textures) = self.texture_manager.drain() {
+            profile_scope!("finish_texture_uploads");
+            uploads.graphics.push(textures.finish()?);
+        }
+
+        let mesh_uploads = self
             .mesh_manager
-            .drain(&self.device, &self.bindless_resources)
-        {
+            .drain(&self.device, &self.bindless_resources, frame);
+        if let Some(meshes) = mesh_uploads.graphics {
             // NOTE: MeshManager registry must not be touched
-            encoder.execute_commands(std::iter::once(secondary.finish()?));
+            uploads.graphics.push(meshes.finish()?);
         }
+        uploads.transfer = mesh_uploads
+            .transfer
+            .map(gfx::PrimaryEncoder::finish)
+            .transpose()?;
+
+        Ok(uploads)
+    }
+}
 
-        self.multi_buffer_arena.flush(&self.bindless_resources);
+/// Work pending for the synced managers, see [`RendererState::eval_instructions`].
+#[derive(Debug, Default, Clone, Copy)]
+struct PendingUpdates {
+    instructions: bool,
+    /// Meshes were uploaded or moved, so objects must be updated.
+    updated_meshes: bool,
+    /// Materials must be updated with new bindless indices of textures.
+    moved_texture_slots: bool,
+    /// Managers were left dirty or sparse by the previous evaluation.
+    synced_managers: bool,
+    /// Arena buffers were used by the previous frame and must be retired.
+    used_arena_buffers: bool,
+}
 
-        Ok(synced_managers)
+impl PendingUpdates {
+    fn is_empty(&self) -> bool {
+        !(self.instructions
+            || self.updated_meshes
+            || self.moved_texture_slots
+            || self.synced_managers
+            || self.used_arena_buffers)
     }
 }
 
+/// Uploads recorded while evaluating instructions.
+#[derive(Default)]
+pub(crate) struct FrameUploads {
+    /// Command buffers which must be submitted to the graphics queue
+    /// before the frame commands.
+    pub graphics: Vec<gfx::CommandBuffer>,
+    /// Command buffer which must be submitted to the transfer queue
+    /// before the graphics ones.
+    pub transfer: Option<gfx::CommandBuffer>,
+}
+
 #[derive(Default)]
 struct RendererStateSyncedManagers {
     material_manager: MaterialManager,
@@ -481,36 +2608,148 @@ struct RendererStateSyncedManagers {
     time_manager: TimeManager,
 }
 
+impl RendererStateSyncedManagers {
+    /// Fills the counts of alive materials and objects.
+    pub(crate) fn fill_frame_stats(&self, stats: &mut FrameStats) {
+        stats.materials = self.material_manager.live_count();
+        stats.static_objects = self.object_manager.static_object_count() as u32;
+        stats.dynamic_objects = self.object_manager.dynamic_object_count() as u32;
+    }
+}
+
 #[derive(Default)]
 struct RendererStateHandles {
     mesh_handle_allocator: FreelistHandleAllocator<Mesh>,
+    texture_handle_allocator: FreelistHandleAllocator<TextureTag>,
     material_handle_allocator: SimpleHandleAllocator<MaterialInstanceTag>,
     static_object_handle_allocator: SimpleHandleAllocator<StaticObjectTag>,
     dynamic_object_handle_allocator: SimpleHandleAllocator<DynamicObjectTag>,
 }
 
-#[derive(Default)]
-struct InstructionQueue {
-    consumer: Mutex<Vec<Instruction>>,
-    producer: Mutex<Vec<Instruction>>,
-}
+impl RendererStateHandles {
+    fn live_counts(&self) -> LiveHandleCounts {
+        LiveHandleCounts {
+            meshes: self.mesh_handle_allocator.live_count(),
+            textures: self.texture_handle_allocator.live_count(),
+            materials: self.material_handle_allocator.live_count(),
+            static_objects: self.static_object_handle_allocator.live_count(),
+            dynamic_objects: self.dynamic_object_handle_allocator.live_count(),
+        }
+    }
 
-impl InstructionQueue {
-    fn swap(&self) {
-        let mut consumer = self.consumer.lock().unwrap();
-        let mut producer = self.producer.lock().unwrap();
-        std::mem::swap(&mut *consumer, &mut *producer);
+    /// Logs handles which outlived the renderer.
+    ///
+    /// NOTE: Objects are reported first since they keep their meshes
+    /// and materials alive.
+    fn report_leaks(&self) {
+        self.static_object_handle_allocator
+            .report_leaks("static object");
+        self.dynamic_object_handle_allocator
+            .report_leaks("dynamic object");
+        self.mesh_handle_allocator.report_leaks("mesh");
+        self.material_handle_allocator
+            .report_leaks("material instance");
+        self.texture_handle_allocator.report_leaks("texture");
     }
+}
+
+/// Error returned by the [`RendererState`] methods.
+///
+/// The error which stopped the rendering thread is available with
+/// [`RendererState::take_error`].
+#[derive(Debug, thiserror::Error)]
+pub enum RendererError {
+    #[error(transparent)]
+    DeviceLost(#[from] gfx::DeviceLost),
+    #[error(transparent)]
+    OutOfDeviceMemory(#[from] gfx::OutOfDeviceMemory),
+    #[error("invalid mesh: {reason}")]
+    InvalidMesh { reason: String },
+    #[error("invalid texture: {reason}")]
+    InvalidTexture { reason: String },
+    /// The handle doesn't refer to a resource of this renderer.
+    #[error("invalid {kind} handle")]
+    InvalidHandle { kind: &'static str },
+    /// The material can't draw the mesh of the object.
+    #[error("incompatible material: {reason}")]
+    IncompatibleMaterial { reason: String },
+    #[error("unsupported operation: {0}")]
+    Unsupported(&'static str),
+    /// The rendering thread is stopped and doesn't accept instructions.
+    #[error("rendering thread is stopped")]
+    WorkerStopped,
+    #[error("rendering thread panicked: {message}")]
+    WorkerPanicked { message: String },
+    /// The GPU didn't complete the frame in time (e.g. due to a lost submission).
+    #[error("frame {frame} was not completed in {elapsed:?}")]
+    FrameTimeout { frame: u32, elapsed: Duration },
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl RendererError {
+    /// Classifies the error by the gfx errors in its chain.
+    fn from_internal(error: anyhow::Error) -> Self {
+        if let Some(e) = error.downcast_ref::<FrameTimeout>() {
+            return Self::FrameTimeout {
+                frame: e.frame,
+                elapsed: e.elapsed,
+            };
+        }
 
-    fn send(&self, instruction: Instruction) {
-        self.producer.lock().unwrap().push(instruction);
+        let device_lost = error.chain().any(|e| {
+            e.is::<gfx::DeviceLost>()
+                || matches!(
+                    e.downcast_ref::<gfx::QueueError>(),
+                    Some(gfx::QueueError::DeviceLost(_))
+                )
+                || matches!(
+                    e.downcast_ref::<gfx::SurfaceError>(),
+                    Some(gfx::SurfaceError::DeviceLost(_))
+                )
+                || matches!(
+                    e.downcast_ref::<gfx::PresentError>(),
+                    Some(gfx::PresentError::DeviceLost(_))
+                )
+        });
+
+        if device_lost {
+            return Self::DeviceLost(gfx::DeviceLost);
+        }
+
+        let out_of_device_memory = error.chain().any(|e| {
+            e.is::<gfx::OutOfDeviceMemory>()
+                || matches!(
+                    e.downcast_ref::<gfx::MapError>(),
+                    Some(gfx::MapError::OutOfDeviceMemory(_))
+                )
+        });
+
+        if out_of_device_memory {
+            Self::OutOfDeviceMemory(gfx::OutOfDeviceMemory)
+        } else {
+            Self::Other(error)
+        }
     }
 }
 
 enum Instruction {
+    UpdateMesh {
+        handle: RawMeshHandle,
+        staged: Option<Box<StagedMesh>>,
+    },
     RemoveMesh {
         handle: RawMeshHandle,
     },
+    RemoveTexture {
+        handle: RawTextureHandle,
+    },
+    SetSkybox {
+        texture: Option<Box<GpuTexture>>,
+    },
+    SetDefaultAnisotropy {
+        max_anisotropy: Option<f32>,
+    },
     AddMaterialInstance {
         handle: RawMaterialInstanceHandle,
         on_add: Box<FnOnAddMaterial>,
@@ -539,6 +2778,21 @@ enum Instruction {
         transform: Box<Mat4>,
         teleport: bool,
     },
+    UpdateStaticObjects {
+        objects: Box<[(RawStaticObjectHandle, Mat4)]>,
+    },
+    UpdateDynamicObjects {
+        objects: Box<[(RawDynamicObjectHandle, Mat4)]>,
+        teleport: bool,
+    },
+    SetStaticObjectLayers {
+        handle: RawStaticObjectHandle,
+        layers: u32,
+    },
+    SetDynamicObjectLayers {
+        handle: RawDynamicObjectHandle,
+        layers: u32,
+    },
     RemoveStaticObject {
         handle: RawStaticObjectHandle,
     },
@@ -549,6 +2803,37 @@ enum Instruction {
         updated_at: Instant,
         duration: Duration,
     },
+    SetFixedTimestep {
+        timestep: Duration,
+        changed_at: Instant,
+    },
+}
+
+impl Instruction {
+    fn kind(&self) -> InstructionKind {
+        match self {
+            Self::UpdateMesh { .. } => InstructionKind::UpdateMesh,
+            Self::RemoveMesh { .. } => InstructionKind::RemoveMesh,
+            Self::RemoveTexture { .. } => InstructionKind::RemoveTexture,
+            Self::SetSkybox { .. } => InstructionKind::SetSkybox,
+            Self::SetDefaultAnisotropy { .. } => InstructionKind::SetDefaultAnisotropy,
+            Self::AddMaterialInstance { .. } => InstructionKind::AddMaterialInstance,
+            Self::UpdateMaterial { .. } => InstructionKind::UpdateMaterial,
+            Self::RemoveMaterial { .. } => InstructionKind::RemoveMaterial,
+            Self::AddStaticObject { .. } => InstructionKind::AddStaticObject,
+            Self::AddDynamicObject { .. } => InstructionKind::AddDynamicObject,
+            Self::UpdateStaticObject { .. } => InstructionKind::UpdateStaticObject,
+            Self::UpdateDynamicObject { .. } => InstructionKind::UpdateDynamicObject,
+            Self::UpdateStaticObjects { .. } => InstructionKind::UpdateStaticObjects,
+            Self::UpdateDynamicObjects { .. } => InstructionKind::UpdateDynamicObjects,
+            Self::SetStaticObjectLayers { .. } => InstructionKind::SetStaticObjectLayers,
+            Self::SetDynamicObjectLayers { .. } => InstructionKind::SetDynamicObjectLayers,
+            Self::RemoveStaticObject { .. } => InstructionKind::RemoveStaticObject,
+            Self::RemoveDynamicObject { .. } => InstructionKind::RemoveDynamicObject,
+            Self::FinishFixedUpdate { .. } => InstructionKind::FinishFixedUpdate,
+            Self::SetFixedTimestep { .. } => InstructionKind::SetFixedTimestep,
+        }
+    }
 }
 
 type FnOnAddMaterial = dyn FnOnce(&mut MaterialManager, RawMaterialInstanceHandle) + Send + Sync;
@@ -565,6 +2850,13 @@ impl IntoRemoveInstruction for RawMeshHandle {
     }
 }
 
+impl IntoRemoveInstruction for RawTextureHandle {
+    #[inline]
+    fn into_remove_instruction(self) -> Instruction {
+        Instruction::RemoveTexture { handle: self }
+    }
+}
+
 impl IntoRemoveInstruction for RawMaterialInstanceHandle {
     #[inline]
     fn into_remove_instruction(self) -> Instruction {
@@ -594,8 +2886,10 @@ where
     RawResourceHandle<T>: IntoRemoveInstruction,
 {
     fn delete(&self, handle: RawResourceHandle<T>) {
+        // NOTE: Resources of the dropped renderer are released along with it,
+        // removals sent after the shutdown are released immediately.
         if let Some(state) = self.0.upgrade() {
-            state.instructions.send(handle.into_remove_instruction());
+            _ = state.send(handle.into_remove_instruction());
         }
     }
 }
@@ -604,6 +2898,10 @@ impl HandleData for Mesh {
     type Deleter = InstructedHandleDeleter;
 }
 
+impl HandleData for TextureTag {
+    type Deleter = InstructedHandleDeleter;
+}
+
 impl HandleData for MaterialInstanceTag {
     type Deleter = InstructedHandleDeleter;
 }
@@ -616,25 +2914,50 @@ impl HandleData for DynamicObjectTag {
     type Deleter = InstructedHandleDeleter;
 }
 
-#[derive(Default)]
-struct LoopBarrier {
-    state: Mutex<bool>,
-    condvar: Condvar,
+/// Returns the `mode` if it is supported by the surface, or FIFO otherwise.
+fn select_present_mode(surface: &gfx::Surface, mode: gfx::PresentMode) -> gfx::PresentMode {
+    if surface.supported_present_modes().any(|item| item == mode) {
+        mode
+    } else {
+        tracing::warn!(?mode, "present mode is not supported, falling back to FIFO");
+        gfx::PresentMode::Fifo
+    }
 }
 
-impl LoopBarrier {
-    fn wait(&self) {
-        let mut state = self.state.lock().unwrap();
-        while !*state {
-            state = self.condvar.wait(state).unwrap();
-        }
-        *state = false;
+/// Adds all known shaders to the preprocessor.
+///
+/// Files from the `override_dir` take precedence over the embedded ones.
+fn load_shaders(
+    shader_preprocessor: &mut ShaderPreprocessor,
+    override_dir: Option<&Path>,
+) -> Result<()> {
+    // NOTE: Read all files first to not leave the preprocessor in a partially updated state
+    let mut files = Vec::new();
+    for (path, contents) in Shaders::iter() {
+        let overridden = match override_dir {
+            Some(dir) => match std::fs::read_to_string(dir.join(path)) {
+                Ok(contents) => Some(contents),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    return Err(e).with_context(|| anyhow::anyhow!("failed to read shader {path}"))
+                }
+            },
+            None => None,
+        };
+
+        let contents: Cow<'static, str> = match overridden {
+            Some(contents) => contents.into(),
+            None => std::str::from_utf8(contents)
+                .with_context(|| anyhow::anyhow!("invalid shader {path}"))?
+                .into(),
+        };
+        files.push((path, contents));
     }
 
-    fn notify(&self) {
-        *self.state.lock().unwrap() = true;
-        self.condvar.notify_one();
+    for (path, contents) in files {
+        shader_preprocessor.add_file(path, contents)?;
     }
+    Ok(())
 }
 
 shared::embed!(
@@ -647,7 +2970,58 @@ shared::embed!(
         "uniforms/globals.glsl",
         "uniforms/object.glsl",
         "scatter_copy.comp",
+        "color_animation.comp",
+        "gpu_culling.comp",
         "opaque_mesh.vert",
-        "opaque_mesh.frag"
+        "opaque_mesh.frag",
+        "debug_line.vert",
+        "debug_line.frag",
+        "skybox.vert",
+        "skybox.frag",
+        "shadow.vert",
+        "pick.vert",
+        "pick.frag",
+        "overlay.vert",
+        "overlay.frag",
+        "text.vert",
+        "text.frag",
+        "fullscreen.vert",
+        "tonemap.frag"
     ]
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synced_managers_are_skipped_only_without_pending_updates() {
+        assert!(PendingUpdates::default().is_empty());
+
+        let pending = [
+            PendingUpdates {
+                instructions: true,
+                ..Default::default()
+            },
+            PendingUpdates {
+                updated_meshes: true,
+                ..Default::default()
+            },
+            PendingUpdates {
+                moved_texture_slots: true,
+                ..Default::default()
+            },
+            PendingUpdates {
+                synced_managers: true,
+                ..Default::default()
+            },
+            PendingUpdates {
+                used_arena_buffers: true,
+                ..Default::default()
+            },
+        ];
+        for pending in pending {
+            assert!(!pending.is_empty(), "{pending:?}");
+        }
+    }
+}
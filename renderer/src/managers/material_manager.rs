@@ -6,10 +6,9 @@ use shared::any::AnyVec;
 use shared::FastHashMap;
 
 use crate::managers::object_manager::{WriteDynamicObject, WriteStaticObject};
-use crate::types::{MaterialInstance, RawMaterialInstanceHandle};
-use crate::util::{
-    BindlessResources, FreelistDoubleBuffer, MultiBufferArena, ScatterCopy, StorageBufferHandle,
-};
+use crate::managers::TextureManagerDataGuard;
+use crate::types::{MaterialInstance, RawMaterialInstanceHandle, ShaderDataContext};
+use crate::util::{BindlessResources, FreelistDoubleBuffer, ScatterCopy, StorageBufferHandle};
 
 #[derive(Default)]
 pub struct MaterialManager {
@@ -18,11 +17,34 @@ pub struct MaterialManager {
 }
 
 impl MaterialManager {
+    /// Archetypes with fewer slots are never compacted.
+    pub const MIN_COMPACTION_SLOTS: u32 = 64;
+    /// Archetypes are compacted when the ratio of live materials to the
+    /// allocated slots drops below this value.
+    pub const COMPACTION_OCCUPANCY: f32 = 0.25;
+
     pub fn materials_data_buffer_handle<M: MaterialInstance>(&self) -> Option<StorageBufferHandle> {
         let archetype = self.archetypes.get(&TypeId::of::<M>())?;
         Some(archetype.buffer.handle())
     }
 
+    /// Returns the upper bound of all material slots of the archetype.
+    pub fn slot_count<M: MaterialInstance>(&self) -> u32 {
+        self.archetypes
+            .get(&TypeId::of::<M>())
+            .map_or(0, |archetype| archetype.next_slot)
+    }
+
+    /// Returns the material instance stored in the specified slot.
+    pub fn get_instance<M: MaterialInstance>(&self, slot: u32) -> Option<&M> {
+        let archetype = self.archetypes.get(&TypeId::of::<M>())?;
+
+        // SAFETY: `typed_data` template parameter is the same as the one used to
+        // construct `archetype`.
+        let data = unsafe { archetype.data.typed_data::<SlotData<M>>() };
+        data.get(slot as usize)?.as_ref()
+    }
+
     #[tracing::instrument(level = "debug", name = "insert_material", skip_all)]
     pub fn insert_material_instance<M: MaterialInstance>(
         &mut self,
@@ -48,6 +70,7 @@ impl MaterialManager {
             data[slot as usize] = Some(material);
         }
 
+        archetype.live_count += 1;
         archetype.buffer.update_slot(slot);
         self.handles.insert(
             handle,
@@ -58,9 +81,16 @@ impl MaterialManager {
         );
     }
 
+    /// Replaces the material instance.
+    ///
+    /// NOTE: Updates of removed instances are ignored, since the last handle
+    /// can be dropped right after the update is sent.
     #[tracing::instrument(level = "debug", name = "update_material", skip_all)]
     pub fn update<M: MaterialInstance>(&mut self, handle: RawMaterialInstanceHandle, material: M) {
-        let HandleData { archetype, slot } = &self.handles[&handle];
+        let Some(HandleData { archetype, slot }) = self.handles.get(&handle) else {
+            tracing::warn!(?handle, "skipping update of a removed material instance");
+            return;
+        };
         assert_eq!(*archetype, TypeId::of::<M>());
 
         let archetype = self
@@ -71,24 +101,91 @@ impl MaterialManager {
         // SAFETY: `typed_data_mut` template parameter is the same as the one used to
         // construct `archetype`.
         let data = unsafe { archetype.data.typed_data_mut::<SlotData<M>>() };
-        let item = data.get_mut(*slot as usize).expect("invalid handle slot");
-        *item.as_mut().expect("value was not initialized") = material;
+        let Some(item) = data.get_mut(*slot as usize).and_then(Option::as_mut) else {
+            tracing::warn!(
+                ?handle,
+                slot = *slot,
+                "skipping update of an empty material slot"
+            );
+            return;
+        };
+        if material.is_unchanged(item) {
+            archetype.skipped_updates += 1;
+            return;
+        }
+        *item = material;
 
+        archetype.applied_updates += 1;
         archetype.buffer.update_slot(*slot);
     }
 
     #[tracing::instrument(level = "debug", name = "remove_material", skip_all)]
     pub fn remove(&mut self, handle: RawMaterialInstanceHandle) {
-        let HandleData { archetype, slot } = &self.handles[&handle];
+        let Some(HandleData { archetype, slot }) = self.handles.remove(&handle) else {
+            tracing::warn!(?handle, "material instance was already removed");
+            return;
+        };
 
         let archetype = self
             .archetypes
-            .get_mut(archetype)
+            .get_mut(&archetype)
             .expect("invalid handle archetype");
 
-        (archetype.remove_slot)(archetype, *slot);
+        (archetype.remove_slot)(archetype, slot);
+    }
+
+    /// Marks all live material instances as updated, so their shader data
+    /// is written again on the next flush.
+    ///
+    /// NOTE: Used when resources referenced by the shader data are moved,
+    /// see [`TextureManager::take_moved_slots`].
+    ///
+    /// [`TextureManager::take_moved_slots`]: crate::managers::TextureManager::take_moved_slots
+    pub fn update_all(&mut self) {
+        for HandleData { archetype, slot } in self.handles.values() {
+            let archetype = self
+                .archetypes
+                .get_mut(archetype)
+                .expect("invalid handle archetype");
+            archetype.buffer.update_slot(*slot);
+        }
+    }
+
+    /// Returns the number of live material instances.
+    pub fn live_count(&self) -> u32 {
+        self.archetypes
+            .values()
+            .map(|archetype| archetype.live_count)
+            .sum()
+    }
+
+    /// Returns slot statistics of each material archetype.
+    pub fn stats(&self) -> impl Iterator<Item = MaterialArchetypeStats> + '_ {
+        self.archetypes
+            .values()
+            .map(|archetype| MaterialArchetypeStats {
+                name: archetype.name,
+                capacity: archetype.data.len() as u32,
+                live: archetype.live_count,
+                free: archetype.free_slots.len() as u32,
+                applied_updates: archetype.applied_updates,
+                skipped_updates: archetype.skipped_updates,
+                uploaded_bytes: archetype.uploaded_bytes,
+            })
     }
 
+    /// Returns `true` if any archetype must be compacted or flushed.
+    pub fn is_dirty(&self) -> bool {
+        self.archetypes.values().any(MaterialArchetype::is_dirty)
+    }
+
+    /// Compacts sparse archetypes and queues updated materials into the
+    /// `scatter_copy` batch.
+    ///
+    /// Archetypes without updated materials are skipped.
+    ///
+    /// Returns slot remappings of the compacted archetypes which must be
+    /// applied to the objects before they are flushed.
     #[tracing::instrument(level = "debug", name = "flush_materials", skip_all)]
     pub fn flush(
         &mut self,
@@ -96,21 +193,60 @@ impl MaterialManager {
         encoder: &mut gfx::Encoder,
         scatter_copy: &ScatterCopy,
         bindless_resources: &BindlessResources,
-        buffers: &MultiBufferArena,
-    ) -> Result<()> {
+        textures: &TextureManagerDataGuard<'_>,
+    ) -> Result<Vec<MaterialSlotRemap>> {
+        profile_scope!("flush_materials");
+
+        let mut remaps = Vec::new();
+        for (id, archetype) in &mut self.archetypes {
+            if !archetype.should_compact() {
+                continue;
+            }
+            profile_scope!("compact_material_archetype", archetype.name);
+
+            let new_slots = (archetype.compact)(archetype);
+            tracing::debug!(
+                archetype = archetype.name,
+                live = archetype.live_count,
+                prev_slots = new_slots.len(),
+                "compacted material slots"
+            );
+            remaps.push(MaterialSlotRemap {
+                archetype: *id,
+                new_slots,
+            });
+        }
+
+        if !remaps.is_empty() {
+            for handle_data in self.handles.values_mut() {
+                let Some(remap) = remaps
+                    .iter()
+                    .find(|remap| remap.archetype == handle_data.archetype)
+                else {
+                    continue;
+                };
+                handle_data.slot = remap.new_slot(handle_data.slot);
+            }
+        }
+
         for archetype in self.archetypes.values_mut() {
-            (archetype.flush)(
+            if !archetype.buffer.is_dirty() {
+                continue;
+            }
+            profile_scope!("flush_material_archetype", archetype.name);
+            let bytes = (archetype.flush)(
                 archetype,
                 FlushMaterial {
                     device,
                     encoder,
                     scatter_copy,
                     bindless_resources,
-                    buffers,
+                    textures,
                 },
             )?;
+            archetype.uploaded_bytes += bytes as u64;
         }
-        Ok(())
+        Ok(remaps)
     }
 
     pub(crate) fn write_static_object(
@@ -118,7 +254,10 @@ impl MaterialManager {
         handle: RawMaterialInstanceHandle,
         args: WriteStaticObject,
     ) {
-        let HandleData { archetype, slot } = &self.handles[&handle];
+        let Some(HandleData { archetype, slot }) = self.handles.get(&handle) else {
+            tracing::error!(?handle, "invalid material instance handle");
+            return;
+        };
 
         let archetype = self
             .archetypes
@@ -133,7 +272,10 @@ impl MaterialManager {
         handle: RawMaterialInstanceHandle,
         args: WriteDynamicObject,
     ) {
-        let HandleData { archetype, slot } = &self.handles[&handle];
+        let Some(HandleData { archetype, slot }) = self.handles.get(&handle) else {
+            tracing::error!(?handle, "invalid material instance handle");
+            return;
+        };
 
         let archetype = self
             .archetypes
@@ -148,11 +290,17 @@ impl MaterialManager {
         match self.archetypes.entry(id) {
             hash_map::Entry::Occupied(entry) => entry.into_mut(),
             hash_map::Entry::Vacant(entry) => entry.insert(MaterialArchetype {
+                name: std::any::type_name::<M>(),
                 data: AnyVec::new::<SlotData<M>>(),
                 buffer: FreelistDoubleBuffer::with_capacity(INITIAL_BUFFER_CAPACITY),
                 next_slot: 0,
+                live_count: 0,
                 free_slots: Vec::new(),
+                applied_updates: 0,
+                skipped_updates: 0,
+                uploaded_bytes: 0,
                 flush: flush::<M>,
+                compact: compact::<M>,
                 write_static_object: write_static_object::<M>,
                 write_dynamic_object: write_dynamic_object::<M>,
                 remove_slot: remove_slot::<M>,
@@ -163,22 +311,77 @@ impl MaterialManager {
 
 const INITIAL_BUFFER_CAPACITY: u32 = 16;
 
+/// Slot usage of a material archetype.
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialArchetypeStats {
+    /// Type name of the material instance.
+    pub name: &'static str,
+    /// Number of allocated slots.
+    pub capacity: u32,
+    /// Number of slots with live materials.
+    pub live: u32,
+    /// Number of released slots available for reuse.
+    pub free: u32,
+    /// Number of updates which were uploaded since the archetype was created.
+    pub applied_updates: u64,
+    /// Number of updates which were skipped, since they didn't change
+    /// the material, see [`MaterialInstance::is_unchanged`].
+    pub skipped_updates: u64,
+    /// Number of bytes of the shader data queued for upload since
+    /// the archetype was created.
+    pub uploaded_bytes: u64,
+}
+
+/// Slots of materials which were moved by the archetype compaction.
+pub struct MaterialSlotRemap {
+    pub archetype: TypeId,
+    /// New slot for each slot used before the compaction.
+    pub new_slots: Vec<u32>,
+}
+
+impl MaterialSlotRemap {
+    pub fn new_slot(&self, slot: u32) -> u32 {
+        match self.new_slots.get(slot as usize) {
+            Some(&new_slot) if new_slot != FREE_SLOT => new_slot,
+            _ => slot,
+        }
+    }
+}
+
 struct HandleData {
     archetype: TypeId,
     slot: u32,
 }
 
 struct MaterialArchetype {
+    name: &'static str,
     data: AnyVec,
     buffer: FreelistDoubleBuffer,
     next_slot: u32,
+    live_count: u32,
     free_slots: Vec<u32>,
-    flush: fn(&mut MaterialArchetype, FlushMaterial) -> Result<()>,
+    applied_updates: u64,
+    skipped_updates: u64,
+    uploaded_bytes: u64,
+    flush: fn(&mut MaterialArchetype, FlushMaterial) -> Result<usize>,
+    compact: fn(&mut MaterialArchetype) -> Vec<u32>,
     write_static_object: fn(&MaterialArchetype, u32, WriteStaticObject),
     write_dynamic_object: fn(&MaterialArchetype, u32, WriteDynamicObject),
     remove_slot: fn(&mut MaterialArchetype, u32),
 }
 
+impl MaterialArchetype {
+    fn is_dirty(&self) -> bool {
+        self.buffer.is_dirty() || self.should_compact()
+    }
+
+    fn should_compact(&self) -> bool {
+        self.next_slot >= MaterialManager::MIN_COMPACTION_SLOTS
+            && (self.live_count as f32)
+                < self.next_slot as f32 * MaterialManager::COMPACTION_OCCUPANCY
+    }
+}
+
 type SlotData<M> = Option<M>;
 
 struct FlushMaterial<'a> {
@@ -186,33 +389,92 @@ struct FlushMaterial<'a> {
     encoder: &'a mut gfx::Encoder,
     scatter_copy: &'a ScatterCopy,
     bindless_resources: &'a BindlessResources,
-    buffers: &'a MultiBufferArena,
+    textures: &'a TextureManagerDataGuard<'a>,
 }
 
 fn flush<M: MaterialInstance>(
     archetype: &mut MaterialArchetype,
     args: FlushMaterial,
-) -> Result<()> {
+) -> Result<usize> {
+    let ctx = ShaderDataContext::new(args.device, args.bindless_resources, args.textures);
+
     // SAFETY: `typed_data` template parameter is the same as the one used to
     // construct `archetype`.
-    unsafe {
+    let bytes = unsafe {
         let data = archetype.data.typed_data::<SlotData<M>>();
         archetype.buffer.flush::<M::ShaderDataType, _>(
             args.device,
             args.encoder,
             args.scatter_copy,
             args.bindless_resources,
-            args.buffers,
             |slot| {
                 let material = data[slot as usize].as_ref().expect("invalid slot");
-                material.shader_data()
+                material.shader_data(&ctx)
             },
-        )?;
+        )?
+    };
+
+    Ok(bytes)
+}
+
+fn compact<M: MaterialInstance>(archetype: &mut MaterialArchetype) -> Vec<u32> {
+    let live_count = archetype.live_count;
+
+    // SAFETY: `downcast_mut` template parameter is the same as the one used to
+    // construct `archetype`.
+    let mut data = unsafe { archetype.data.downcast_mut::<SlotData<M>>() };
+    let new_slots = compact_slots(&mut data[..archetype.next_slot as usize]);
+
+    // NOTE: Keep the same growth policy as in `insert_material_instance`
+    let size = live_count.next_power_of_two().max(INITIAL_BUFFER_CAPACITY);
+    data.truncate(size as usize + 1);
+    data.shrink_to_fit();
+    drop(data);
+
+    for (slot, new_slot) in new_slots.iter().enumerate() {
+        if *new_slot != FREE_SLOT && *new_slot != slot as u32 {
+            archetype.buffer.update_slot(*new_slot);
+        }
     }
+    archetype.buffer.shrink_to(live_count);
 
-    Ok(())
+    archetype.next_slot = live_count;
+    archetype.free_slots = Vec::new();
+    new_slots
 }
 
+/// Moves live items into a dense prefix of the slice.
+///
+/// Returns the new index of each item, [`FREE_SLOT`] for empty items.
+fn compact_slots<T>(data: &mut [Option<T>]) -> Vec<u32> {
+    let mut new_slots = (0..data.len() as u32).collect::<Vec<_>>();
+
+    let mut free = 0;
+    let mut live = data.len();
+    loop {
+        while free < live && data[free].is_some() {
+            free += 1;
+        }
+        while live > free && data[live - 1].is_none() {
+            live -= 1;
+            new_slots[live] = FREE_SLOT;
+        }
+        if live <= free {
+            break;
+        }
+
+        // Move the last live item into the first free slot
+        live -= 1;
+        data.swap(free, live);
+        new_slots[live] = free as u32;
+        free += 1;
+    }
+
+    new_slots
+}
+
+const FREE_SLOT: u32 = u32::MAX;
+
 fn write_static_object<M: MaterialInstance>(
     _archetype: &MaterialArchetype,
     slot: u32,
@@ -239,4 +501,275 @@ fn remove_slot<M: MaterialInstance>(archetype: &mut MaterialArchetype, slot: u32
     std::mem::take(item).expect("value was not initialized");
 
     archetype.free_slots.push(slot);
+    archetype.live_count -= 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Weak};
+    use std::time::{Duration, Instant};
+
+    use glam::Vec3;
+
+    use super::*;
+    use crate::render_graph::materials::DebugMaterialInstance;
+    use crate::types::{MaterialInstanceHandle, MaterialInstanceTag, Sorting};
+    use crate::util::{HandleAllocator, InstructionQueue, SimpleHandleAllocator};
+    use crate::{InstructedHandleDeleter, RendererBuilder, RendererState};
+
+    enum TestInstruction {
+        Update(RawMaterialInstanceHandle, f32),
+        Remove(RawMaterialInstanceHandle),
+    }
+
+    fn material(color: f32) -> DebugMaterialInstance {
+        DebugMaterialInstance {
+            color: Vec3::splat(color),
+            double_sided: false,
+        }
+    }
+
+    fn color_at(manager: &MaterialManager, slot: u32) -> Option<f32> {
+        let instance = manager.get_instance::<DebugMaterialInstance>(slot)?;
+        Some(instance.color.x)
+    }
+
+    /// Debug material which uploads all updates, even the identical ones.
+    struct UncheckedMaterial(DebugMaterialInstance);
+
+    impl MaterialInstance for UncheckedMaterial {
+        type ShaderDataType = <DebugMaterialInstance as MaterialInstance>::ShaderDataType;
+        type RequiredAttributes = <DebugMaterialInstance as MaterialInstance>::RequiredAttributes;
+        type SupportedAttributes = <DebugMaterialInstance as MaterialInstance>::SupportedAttributes;
+
+        fn required_attributes() -> Self::RequiredAttributes {
+            DebugMaterialInstance::required_attributes()
+        }
+        fn supported_attributes() -> Self::SupportedAttributes {
+            DebugMaterialInstance::supported_attributes()
+        }
+
+        fn key(&self) -> u64 {
+            self.0.key()
+        }
+
+        fn sorting(&self) -> Sorting {
+            self.0.sorting()
+        }
+
+        fn shader_data(&self, ctx: &ShaderDataContext<'_>) -> Self::ShaderDataType {
+            self.0.shader_data(ctx)
+        }
+    }
+
+    const BENCH_MATERIALS: u32 = 10_000;
+    const BENCH_FRAMES: u32 = 10;
+
+    /// Updates all materials with the same instances each frame.
+    ///
+    /// Returns the number of uploaded bytes and the time of all frames.
+    fn update_identical_materials<M, F>(state: &RendererState, material: F) -> (u64, Duration)
+    where
+        M: MaterialInstance,
+        F: Fn(f32) -> M,
+    {
+        let allocator = SimpleHandleAllocator::<MaterialInstanceTag>::default();
+        let deleter = Arc::new(InstructedHandleDeleter(Weak::new()));
+        let mut manager = MaterialManager::default();
+
+        // NOTE: The worker doesn't flush the shared scatter copy while
+        // the managers are locked.
+        let _synced_managers = state.synced_managers.lock().unwrap();
+        let flush = |manager: &mut MaterialManager| {
+            let mut encoder = state.queue.create_primary_encoder().unwrap();
+            manager
+                .flush(
+                    &state.device,
+                    &mut encoder,
+                    &state.scatter_copy,
+                    &state.bindless_resources,
+                    &state.texture_manager.lock_data(),
+                )
+                .unwrap();
+            state
+                .scatter_copy
+                .flush(&state.device, &mut encoder, &state.multi_buffer_arena)
+                .unwrap();
+
+            let command_buffer = encoder.finish().unwrap();
+            state.queue.submit_simple(command_buffer, None).unwrap();
+            state.queue.wait_idle().unwrap();
+        };
+        let uploaded_bytes = |manager: &MaterialManager| {
+            manager
+                .stats()
+                .map(|stats| stats.uploaded_bytes)
+                .sum::<u64>()
+        };
+
+        let handles = (0..BENCH_MATERIALS)
+            .map(|_| allocator.alloc(deleter.clone()))
+            .collect::<Vec<_>>();
+        for handle in &handles {
+            manager.insert_material_instance(handle.raw(), material(1.0));
+        }
+        // NOTE: Inserted materials are written into both buffers
+        flush(&mut manager);
+        flush(&mut manager);
+
+        let uploaded_before = uploaded_bytes(&manager);
+        let started_at = Instant::now();
+        for _ in 0..BENCH_FRAMES {
+            for handle in &handles {
+                manager.update(handle.raw(), material(1.0));
+            }
+            flush(&mut manager);
+        }
+        let elapsed = started_at.elapsed();
+        (uploaded_bytes(&manager) - uploaded_before, elapsed)
+    }
+
+    #[test]
+    fn inserted_materials_are_dirty() {
+        let allocator = SimpleHandleAllocator::<MaterialInstanceTag>::default();
+        let deleter = Arc::new(InstructedHandleDeleter(Weak::new()));
+        let mut manager = MaterialManager::default();
+        assert!(!manager.is_dirty());
+
+        let handle = allocator.alloc(deleter);
+        manager.insert_material_instance(handle.raw(), material(1.0));
+        assert!(manager.is_dirty());
+    }
+
+    #[test]
+    fn identical_updates_are_skipped() {
+        let allocator = SimpleHandleAllocator::<MaterialInstanceTag>::default();
+        let deleter = Arc::new(InstructedHandleDeleter(Weak::new()));
+        let mut manager = MaterialManager::default();
+
+        let handle = allocator.alloc(deleter);
+        manager.insert_material_instance(handle.raw(), material(1.0));
+        for _ in 0..10_000 {
+            manager.update(handle.raw(), material(1.0));
+        }
+        manager.update(handle.raw(), material(2.0));
+
+        let stats = manager.stats().next().unwrap();
+        assert_eq!(stats.skipped_updates, 10_000);
+        assert_eq!(stats.applied_updates, 1);
+        assert_eq!(color_at(&manager, 0), Some(2.0));
+    }
+
+    /// Run with `cargo test --release -p renderer -- --ignored --nocapture bench_`.
+    #[test]
+    #[ignore]
+    fn bench_identical_material_updates() {
+        let renderer = RendererBuilder::headless(16, 16).build().unwrap();
+        let state = renderer.state();
+
+        let (checked_bytes, checked) = update_identical_materials(state, material);
+        let (unchecked_bytes, unchecked) =
+            update_identical_materials(state, |color| UncheckedMaterial(material(color)));
+        assert_eq!(checked_bytes, 0);
+        assert!(unchecked_bytes > 0);
+
+        eprintln!(
+            "{BENCH_MATERIALS} identical materials in {BENCH_FRAMES} frames: \
+            checked {checked_bytes} bytes, {:?} per frame, \
+            unchecked {unchecked_bytes} bytes, {:?} per frame",
+            checked / BENCH_FRAMES,
+            unchecked / BENCH_FRAMES,
+        );
+    }
+
+    #[test]
+    fn updates_of_removed_materials_are_ignored() {
+        let allocator = SimpleHandleAllocator::<MaterialInstanceTag>::default();
+        let deleter = Arc::new(InstructedHandleDeleter(Weak::new()));
+        let queue = InstructionQueue::default();
+        let mut manager = MaterialManager::default();
+
+        let eval = |manager: &mut MaterialManager| {
+            queue.swap();
+            for instruction in queue.consumer().drain(..) {
+                match instruction {
+                    TestInstruction::Update(handle, color) => {
+                        manager.update(handle, material(color))
+                    }
+                    TestInstruction::Remove(handle) => {
+                        allocator.dealloc(handle);
+                        manager.remove(handle);
+                    }
+                }
+            }
+        };
+        let add = |manager: &mut MaterialManager, color: f32| -> MaterialInstanceHandle {
+            let handle = allocator.alloc(deleter.clone());
+            manager.insert_material_instance(handle.raw(), material(color));
+            handle
+        };
+
+        // Update followed by the removal in the same drain
+        let first = add(&mut manager, 0.0);
+        queue.send(TestInstruction::Update(first.raw(), 1.0));
+        queue.send(TestInstruction::Remove(first.raw()));
+        eval(&mut manager);
+        assert_eq!(manager.live_count(), 0);
+
+        // Update and removal after the removal was evaluated in an earlier drain,
+        // while the slot is reused by another material
+        let second = add(&mut manager, 2.0);
+        queue.send(TestInstruction::Update(first.raw(), 3.0));
+        queue.send(TestInstruction::Remove(first.raw()));
+        eval(&mut manager);
+        assert_eq!(manager.live_count(), 1);
+        assert_eq!(color_at(&manager, 0), Some(2.0));
+
+        // Update after the removal in the same drain
+        queue.send(TestInstruction::Remove(second.raw()));
+        queue.send(TestInstruction::Update(second.raw(), 4.0));
+        eval(&mut manager);
+        assert_eq!(manager.live_count(), 0);
+        assert_eq!(color_at(&manager, 0), None);
+    }
+
+    #[test]
+    fn live_slots_are_moved_into_prefix() {
+        let mut data = vec![
+            None,
+            Some('a'),
+            None,
+            Some('b'),
+            None,
+            Some('c'),
+            Some('d'),
+            None,
+        ];
+
+        let new_slots = compact_slots(&mut data);
+        assert_eq!(data[..4], [Some('d'), Some('a'), Some('c'), Some('b')]);
+        assert!(data[4..].iter().all(Option::is_none));
+
+        let remap = MaterialSlotRemap {
+            archetype: TypeId::of::<()>(),
+            new_slots,
+        };
+        assert_eq!(remap.new_slot(1), 1);
+        assert_eq!(remap.new_slot(3), 3);
+        assert_eq!(remap.new_slot(5), 2);
+        assert_eq!(remap.new_slot(6), 0);
+    }
+
+    #[test]
+    fn dense_slots_are_not_moved() {
+        let mut data = vec![Some(0), Some(1), Some(2), None, None];
+        let new_slots = compact_slots(&mut data);
+        assert_eq!(new_slots, [0, 1, 2, FREE_SLOT, FREE_SLOT]);
+
+        let mut data = Vec::<Option<u32>>::new();
+        assert!(compact_slots(&mut data).is_empty());
+
+        let mut data = vec![None::<u32>; 4];
+        assert_eq!(compact_slots(&mut data), [FREE_SLOT; 4]);
+    }
 }
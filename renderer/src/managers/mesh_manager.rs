@@ -1,28 +1,57 @@
+use std::collections::VecDeque;
 use std::ops::Range;
 use std::sync::{Mutex, MutexGuard};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use range_alloc::RangeAllocator;
 
-use crate::types::{Mesh, RawMeshHandle, VertexAttributeKind};
+use crate::types::{Mesh, RawMeshHandle, VertexAttributeEncoding, VertexAttributeKind};
 use crate::util::{
-    AtomicStorageBufferHandle, BindlessResources, BoundingSphere, StorageBufferHandle,
+    AtomicStorageBufferHandle, BindlessResources, BoundingSphere, ResourceRegistry,
+    StorageBufferHandle,
 };
 
 pub struct MeshManager {
     state: Mutex<MeshManagerState>,
-    registry: Mutex<Vec<Option<GpuMesh>>>,
+    registry: Mutex<ResourceRegistry<Mesh, GpuMesh>>,
     vertex_buffer_handle: AtomicStorageBufferHandle,
 }
 
 impl MeshManager {
-    pub fn new(device: &gfx::Device, bindless_resources: &BindlessResources) -> Result<Self> {
+    /// Stages which must wait for uploads submitted to the transfer queue.
+    pub const TRANSFER_UPLOADS_WAIT_STAGES: gfx::PipelineStageFlags =
+        gfx::PipelineStageFlags::TRANSFER
+            .union(gfx::PipelineStageFlags::VERTEX_INPUT)
+            .union(gfx::PipelineStageFlags::VERTEX_SHADER);
+
+    /// Creates a new mesh manager.
+    ///
+    /// If `transfer_queue` is specified, staging copies are recorded for it
+    /// instead of the graphics queue. If the device supports direct uploads,
+    /// mesh buffers are mapped and new meshes are written into them directly.
+    /// With `device_address` enabled, mesh buffers can also be accessed
+    /// from shaders by their device addresses.
+    pub fn new(
+        device: &gfx::Device,
+        bindless_resources: &BindlessResources,
+        transfer_queue: Option<gfx::Queue>,
+        device_address: bool,
+    ) -> Result<Self> {
         const INITIAL_VERTICES_CAPACITY: u32 = 1 << 16;
-        const INITIAL_INDEX_COUNT: u32 = 1 << 16;
-
-        let buffers = MeshBuffers::new(device, INITIAL_VERTICES_CAPACITY, INITIAL_INDEX_COUNT)?;
-        let vertex_alloc = RangeAllocator::new(0..INITIAL_VERTICES_CAPACITY);
-        let index_alloc = RangeAllocator::new(0..INITIAL_INDEX_COUNT);
+        const INITIAL_INDEX_WORDS: u32 = 1 << 16;
+
+        let direct_upload = device.supports_direct_upload();
+        let buffers = MeshBuffers::new(
+            device,
+            INITIAL_VERTICES_CAPACITY,
+            INITIAL_INDEX_WORDS,
+            direct_upload,
+            device_address,
+        )?;
+        // NOTE: Default vertex attributes are stored before all allocations
+        let vertex_alloc =
+            RangeAllocator::new(DEFAULT_VERTEX_ATTRIBUTES_SIZE..INITIAL_VERTICES_CAPACITY);
+        let index_alloc = RangeAllocator::new(0..INITIAL_INDEX_WORDS);
 
         let vertex_buffer_handle = bindless_resources
             .alloc_storage_buffer(device, gfx::BufferRange::whole(buffers.vertices.clone()));
@@ -30,10 +59,23 @@ impl MeshManager {
         Ok(Self {
             state: Mutex::new(MeshManagerState {
                 buffers,
+                direct_upload,
+                device_address,
                 new_vertex_buffer: false,
+                default_vertex_attributes_written: false,
                 vertex_alloc,
                 index_alloc,
                 encoder: None,
+                transfer: transfer_queue.map(|queue| TransferUploads {
+                    queue,
+                    encoder: None,
+                }),
+                buffers_reallocated: false,
+                uploads: UploadTracker::default(),
+                retired_allocations: Vec::new(),
+                pending_retired_allocations: Vec::new(),
+                deferred_uploads: VecDeque::new(),
+                stats: MeshManagerStats::default(),
             }),
             registry: Mutex::default(),
             vertex_buffer_handle: AtomicStorageBufferHandle::new(vertex_buffer_handle),
@@ -50,11 +92,30 @@ impl MeshManager {
         self.vertex_buffer_handle.load()
     }
 
+    /// Returns device addresses of the current vertex and index buffers,
+    /// or `None` if mesh buffers were created without them.
+    ///
+    /// NOTE: Buffers are replaced when they grow, so the addresses must be
+    /// taken after the uploads are drained for the frame.
+    pub fn buffer_addresses(&self) -> Option<MeshBufferAddresses> {
+        let state = self.state.lock().unwrap();
+        Some(MeshBufferAddresses {
+            vertices: state.buffers.vertices.address()?,
+            indices: state.buffers.indices.address()?,
+        })
+    }
+
+    /// Takes the encoders with all pending uploads.
+    ///
+    /// NOTE: The returned commands must be submitted as a part of the `frame`.
     pub fn drain(
         &self,
         device: &gfx::Device,
         bindless_resources: &BindlessResources,
-    ) -> Option<gfx::Encoder> {
+        frame: u32,
+    ) -> MeshUploads {
+        profile_scope!("drain_mesh_uploads");
+
         let mut state = self.state.lock().unwrap();
         if std::mem::take(&mut state.new_vertex_buffer) {
             let old_handle =
@@ -65,61 +126,358 @@ impl MeshManager {
                     ));
             bindless_resources.free_storage_buffer(old_handle);
         }
-        state.encoder.take()
+
+        let uploads = MeshUploads {
+            graphics: state.encoder.take(),
+            transfer: state
+                .transfer
+                .as_mut()
+                .and_then(|transfer| transfer.encoder.take()),
+        };
+        state.buffers_reallocated = false;
+
+        if uploads.graphics.is_some() || uploads.transfer.is_some() {
+            state.uploads.submit(frame);
+        }
+
+        // NOTE: Retired ranges are no longer used starting from this frame
+        let retired = std::mem::take(&mut state.pending_retired_allocations);
+        state
+            .retired_allocations
+            .extend(retired.into_iter().map(|allocation| (allocation, frame)));
+
+        uploads
     }
 
-    pub fn bind_index_buffer(&self, encoder: &mut gfx::Encoder) {
+    /// Marks all uploads submitted up to the `frame` (inclusive) as completed
+    /// and removes meshes which were waiting for them.
+    pub fn complete_uploads<F>(&self, frame: u32, mut on_remove: F)
+    where
+        F: FnMut(RawMeshHandle),
+    {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        state.uploads.complete(frame);
+        for handle in state.uploads.drain_removals() {
+            self.remove_now(state, handle);
+            on_remove(handle);
+        }
+
+        let mut retired = std::mem::take(&mut state.retired_allocations);
+        retired.retain(|(allocation, retired_at)| {
+            if *retired_at > frame {
+                return true;
+            }
+            state.free_allocation(allocation);
+            false
+        });
+        state.retired_allocations = retired;
+    }
+
+    /// Binds the shared index buffer to read indices of the specified type.
+    ///
+    /// NOTE: Meshes with different index types share the same buffer, so it
+    /// must be rebound when the index type of the drawn mesh changes.
+    pub fn bind_index_buffer(&self, encoder: &mut gfx::EncoderCommon, index_type: gfx::IndexType) {
         let state = self.state.lock().unwrap();
-        state.buffers.bind_index_buffer(encoder);
+        state.buffers.bind_index_buffer(encoder, index_type);
     }
 
+    /// Uploads the mesh or defers its upload if the mesh buffers can't
+    /// grow without exceeding the memory budget.
     #[tracing::instrument(level = "debug", name = "upload_mesh", skip_all)]
-    pub fn upload_mesh(&self, queue: &gfx::Queue, mesh: &Mesh) -> Result<GpuMesh> {
-        let vertex_count = mesh.vertex_count();
-        let index_count = mesh.indices().len();
-        if vertex_count == 0 || index_count == 0 {
-            return Ok(GpuMesh::new_empty());
+    pub fn upload_mesh(
+        &self,
+        queue: &gfx::Queue,
+        staging_belt: &gfx::StagingBelt,
+        mesh: &Mesh,
+    ) -> Result<MeshUpload> {
+        if mesh.vertex_count() == 0 || mesh.indices().is_empty() {
+            return Ok(MeshUpload::Uploaded(GpuMesh::new_empty()));
         }
 
-        let device = queue.device();
+        {
+            let mut state = self.state.lock().unwrap();
+            // NOTE: Keep the upload order while waiting for the memory budget
+            if state.deferred_uploads.is_empty() && state.can_write_directly() {
+                match state.write_mesh_directly(queue, mesh) {
+                    Ok(Some(mesh)) => return Ok(MeshUpload::Uploaded(mesh)),
+                    // NOTE: Meshes which can't be written directly are staged
+                    Ok(None) => {}
+                    Err(e) if e.is::<OutOfMemoryBudget>() => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        let Some(staged) = self.stage_mesh(staging_belt, mesh)? else {
+            return Ok(MeshUpload::Uploaded(GpuMesh::new_empty()));
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if !state.deferred_uploads.is_empty() {
+            // NOTE: Keep the upload order while waiting for the memory budget
+            return Ok(MeshUpload::Deferred(Box::new(staged)));
+        }
+
+        match state.write_staged_mesh(queue, &staged) {
+            Ok(mesh) => Ok(MeshUpload::Uploaded(mesh)),
+            Err(e) if e.is::<OutOfMemoryBudget>() => {
+                tracing::warn!("{e}, deferring mesh upload");
+                Ok(MeshUpload::Deferred(Box::new(staged)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes deferred uploads while the memory budget allows it.
+    ///
+    /// Returns handles of the uploaded meshes.
+    #[tracing::instrument(level = "debug", name = "flush_deferred_mesh_uploads", skip_all)]
+    pub fn flush_deferred_uploads(&self, queue: &gfx::Queue) -> Vec<RawMeshHandle> {
+        let mut state = self.state.lock().unwrap();
+        if state.deferred_uploads.is_empty() {
+            return Vec::new();
+        }
+
+        let mut registry = self.registry.lock().unwrap();
+
+        let mut uploaded = Vec::new();
+        while let Some((handle, staged)) = state.deferred_uploads.pop_front() {
+            let Some(mesh) = registry.get_mut(handle) else {
+                continue;
+            };
+
+            match state.replace_mesh(queue, mesh, &staged) {
+                Ok(()) => uploaded.push(handle),
+                Err(e) if e.is::<OutOfMemoryBudget>() => {
+                    state.deferred_uploads.push_front((handle, staged));
+                    break;
+                }
+                Err(e) => tracing::error!(?handle, "failed to upload deferred mesh: {e:?}"),
+            }
+        }
+
+        tracing::debug!(
+            uploaded = uploaded.len(),
+            deferred = state.deferred_uploads.len(),
+            "flushed deferred mesh uploads"
+        );
+        uploaded
+    }
+
+    /// Returns the number of alive meshes.
+    pub fn mesh_count(&self) -> usize {
+        self.registry.lock().unwrap().len()
+    }
+
+    /// Returns the fragmentation of the mesh buffers and the amount
+    /// of data moved by the last defragmentation step.
+    pub fn stats(&self) -> MeshManagerStats {
+        self.state.lock().unwrap().stats
+    }
+
+    /// Moves a bounded number of mesh ranges closer to the start of the mesh
+    /// buffers if their free space is too fragmented.
+    ///
+    /// Old ranges are freed once all frames which could use them are completed.
+    /// Returns handles of the moved meshes, objects which use them must be updated.
+    #[tracing::instrument(level = "debug", name = "defragment_meshes", skip_all)]
+    pub fn defragment(&self, queue: &gfx::Queue) -> Result<Vec<RawMeshHandle>> {
+        profile_scope!("defragment_meshes");
+
         let mut state = self.state.lock().unwrap();
         let state = &mut *state;
 
-        let mut vertex_attribute_ranges = Vec::with_capacity(mesh.attribute_data().len());
-        let mut vertex_attribute_copies = Vec::with_capacity(vertex_attribute_ranges.len());
-        let indices_range;
-        let indices_copy;
+        state.stats.vertex_fragmentation = fragmentation(&state.vertex_alloc);
+        state.stats.index_fragmentation = fragmentation(&state.index_alloc);
+        state.stats.frame_moved_bytes = 0;
+
+        let defragment_vertices = state.stats.vertex_fragmentation > DEFRAG_THRESHOLD;
+        let defragment_indices = state.stats.index_fragmentation > DEFRAG_THRESHOLD;
+        if !defragment_vertices && !defragment_indices {
+            return Ok(Vec::new());
+        }
+
+        let mut registry = self.registry.lock().unwrap();
+
+        // NOTE: Meshes with pending uploads could still be written by the transfer queue
+        let mut candidates = registry
+            .iter()
+            .filter(|(_, mesh)| {
+                !matches!(mesh.upload_epoch, Some(epoch) if state.uploads.is_pending(epoch))
+            })
+            .filter_map(|(handle, mesh)| {
+                let allocation = &mesh.allocation;
+                let offset = if defragment_vertices {
+                    let ranges = allocation.vertex_attribute_ranges.iter();
+                    ranges.map(|range| range.start).max()
+                } else {
+                    let range = &allocation.indices_range;
+                    (!range.is_empty()).then_some(range.start)
+                };
+                Some((offset?, handle))
+            })
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // NOTE: The encoder is created before moving any ranges,
+        // so that moved ranges are never left without copies.
+        let had_encoder = state.encoder.is_some();
+        let encoder = make_encoder(queue, &mut state.encoder)?;
+
+        // Move meshes from the end of the buffers first
+        candidates.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+
+        let mut budget = DefragBudget::default();
+        let mut vertex_copies = Vec::new();
+        let mut index_copies = Vec::new();
+        let mut moved = Vec::new();
+        for (_, handle) in candidates {
+            if budget.is_exhausted() {
+                break;
+            }
+
+            let mesh = registry.get_mut(handle).expect("handle must be valid");
+            let mut retired = MeshAllocation::default();
+
+            if defragment_vertices {
+                for (range, (_, _, data_range)) in std::iter::zip(
+                    &mut mesh.allocation.vertex_attribute_ranges,
+                    &mut mesh.vertex_attribute_ranges,
+                ) {
+                    // NOTE: Vertex ranges are allocated in bytes
+                    let Some(old_range) = budget.relocate(&mut state.vertex_alloc, range, 1) else {
+                        continue;
+                    };
+                    tracing::debug!(?old_range, new_range = ?range, "moved vertex attribute range");
+
+                    let size = data_range.end - data_range.start;
+                    if size > 0 {
+                        vertex_copies.push(gfx::BufferCopy {
+                            src_offset: data_range.start as usize,
+                            dst_offset: range.start as usize,
+                            size: size as usize,
+                        });
+                    }
+
+                    *data_range = range.start..range.start + size;
+                    retired.vertex_attribute_ranges.push(old_range);
+                }
+            }
+
+            let range = &mut mesh.allocation.indices_range;
+            if defragment_indices {
+                if let Some(old_range) =
+                    budget.relocate(&mut state.index_alloc, range, INDEX_WORD_SIZE)
+                {
+                    tracing::debug!(?old_range, new_range = ?range, "moved indices range");
+
+                    let index_count = mesh.indices_range.end - mesh.indices_range.start;
+                    let size = index_words(mesh.index_type, index_count) * INDEX_WORD_SIZE;
+                    if size > 0 {
+                        let word_size = INDEX_WORD_SIZE as usize;
+                        index_copies.push(gfx::BufferCopy {
+                            src_offset: old_range.start as usize * word_size,
+                            dst_offset: range.start as usize * word_size,
+                            size: size as usize,
+                        });
+                    }
+
+                    let indices_per_word = INDEX_WORD_SIZE / mesh.index_type.index_size() as u32;
+                    let first_index = range.start * indices_per_word;
+                    mesh.indices_range = first_index..first_index + index_count;
+                    retired.indices_range = old_range;
+                }
+            }
+
+            if !retired.vertex_attribute_ranges.is_empty() || !retired.indices_range.is_empty() {
+                // NOTE: The mesh must not be removed until the copy is completed
+                mesh.upload_epoch = Some(state.uploads.recording_epoch());
+                state.pending_retired_allocations.push(retired);
+                moved.push(handle);
+            }
+        }
+
+        if moved.is_empty() {
+            if !had_encoder {
+                state.encoder = None;
+            }
+            return Ok(moved);
+        }
+
+        // NOTE: Moved data could be written by the preceding copies of this frame
+        encoder.memory_barrier(
+            gfx::PipelineStageFlags::TRANSFER,
+            gfx::AccessFlags::TRANSFER_WRITE,
+            gfx::PipelineStageFlags::TRANSFER,
+            gfx::AccessFlags::TRANSFER_READ | gfx::AccessFlags::TRANSFER_WRITE,
+        );
+        if !vertex_copies.is_empty() {
+            encoder.copy_buffer(
+                &state.buffers.vertices,
+                &state.buffers.vertices,
+                &vertex_copies,
+            );
+        }
+        if !index_copies.is_empty() {
+            encoder.copy_buffer(
+                &state.buffers.indices,
+                &state.buffers.indices,
+                &index_copies,
+            );
+        }
+
+        state.stats.frame_moved_bytes = budget.moved_bytes as u64;
+        state.stats.moved_bytes += budget.moved_bytes as u64;
+        tracing::debug!(
+            meshes = moved.len(),
+            bytes = budget.moved_bytes,
+            "defragmented mesh buffers"
+        );
+        Ok(moved)
+    }
 
-        // Create a host-coherent staging buffer
+    /// Copies mesh data into a region of the staging belt.
+    ///
+    /// Returns `None` for empty meshes.
+    pub fn stage_mesh(
+        &self,
+        staging_belt: &gfx::StagingBelt,
+        mesh: &Mesh,
+    ) -> Result<Option<StagedMesh>> {
+        let vertex_count = mesh.vertex_count();
+        let index_count = mesh.indices().len();
+        let index_type = mesh.index_type();
+        if vertex_count == 0 || index_count == 0 {
+            return Ok(None);
+        }
+
+        let mut vertex_attributes = Vec::with_capacity(mesh.attribute_data().len());
+        let indices_offset;
+
+        // Allocate a staging region
         let total_attribute_size = mesh
             .attribute_data()
             .iter()
             .map(|a| a.byte_len())
             .sum::<usize>();
-        let total_index_size = index_count * (INDEX_SIZE as usize);
+        let index_words = index_words(index_type, index_count as _);
+        let total_index_size = index_words as usize * INDEX_WORD_SIZE as usize;
 
-        let staging_buffer = device.create_mappable_buffer(
-            gfx::BufferInfo {
-                align_mask: VERTEX_ALIGN_MASK.max(INDEX_ALIGN_MASK),
-                size: total_attribute_size + total_index_size,
-                usage: gfx::BufferUsage::TRANSFER_SRC,
-            },
-            gfx::MemoryUsage::UPLOAD | gfx::MemoryUsage::TRANSIENT,
+        let mut staging = staging_belt.allocate(
+            total_attribute_size + total_index_size,
+            VERTEX_ALIGN_MASK.max(INDEX_ALIGN_MASK) + 1,
         )?;
 
         {
-            let mut memory_block = staging_buffer.as_mappable();
-
-            // Map staging buffer to host memory
-            let staging_buffer_data = device.map_memory(
-                &mut memory_block,
-                0,
-                (total_attribute_size + total_index_size) as _,
-            )?;
-            let staging_buffer_data = staging_buffer_data.as_mut_ptr();
+            let staging_buffer_data = staging.data().as_mut_ptr();
             let mut staging_buffer_offset = 0;
 
-            // Allocate ranges for vertex attributes
+            // Copy vertex attributes
             for attribute in mesh.attribute_data() {
                 let data = attribute.untyped_data();
                 let len = data.len();
@@ -133,128 +491,605 @@ impl MeshManager {
                     );
                 }
 
-                let range = state.alloc_range_for_vertices(queue, len as _)?;
-                tracing::debug!(?range, len, "allocated vertex attribute range");
-
-                vertex_attribute_copies.push(gfx::BufferCopy {
-                    src_offset: staging_buffer_offset,
-                    dst_offset: range.start as usize,
-                    size: (range.end - range.start) as usize,
+                vertex_attributes.push(StagedVertexAttribute {
+                    kind: attribute.kind(),
+                    encoding: attribute.encoding(),
+                    offset: staging.offset() + staging_buffer_offset,
+                    len: len as _,
                 });
-                vertex_attribute_ranges.push((attribute.kind(), range));
 
                 staging_buffer_offset += len;
             }
 
-            // Allocate range for indices
-
-            // SAFETY: `staging_buffer_data` is a valid pointer to a slice with
-            // the exact remaining capacity required for `mesh.indices`.
+            // Copy indices
+            // SAFETY: `staging_buffer_data` is a valid pointer to a slice with the exact
+            // remaining capacity for the index words. Staging buffer is aligned to 4 bytes.
             unsafe {
-                std::ptr::copy_nonoverlapping(
-                    mesh.indices().as_ptr().cast::<u8>(),
+                write_indices(
+                    mesh.indices(),
+                    index_type,
                     staging_buffer_data.add(staging_buffer_offset).cast(),
-                    std::mem::size_of_val::<[u32]>(mesh.indices()),
                 );
             }
-
-            indices_range = state.alloc_range_for_indices(queue, index_count as _)?;
-            tracing::debug!(range = ?indices_range, "allocated indices range");
-
-            indices_copy = gfx::BufferCopy {
-                src_offset: staging_buffer_offset,
-                dst_offset: (indices_range.start as usize).saturating_mul(INDEX_SIZE as _),
-                size: ((indices_range.end - indices_range.start) as usize)
-                    .saturating_mul(INDEX_SIZE as _),
-            };
-
-            // Unmap and freeze staging buffer
-            device.unmap_memory(&mut memory_block);
+            indices_offset = staging.offset() + staging_buffer_offset;
         }
-
-        // Encode copy commands
-        let encoder = make_encoder(queue, &mut state.encoder)?;
-        encoder.copy_buffer(
-            &staging_buffer,
-            &state.buffers.vertices,
-            &vertex_attribute_copies,
-        );
-        encoder.copy_buffer(
-            &staging_buffer,
-            &state.buffers.indices,
-            std::slice::from_ref(&indices_copy),
-        );
-
-        // Done
-        Ok(GpuMesh {
-            vertex_attribute_ranges,
-            indices_range,
+        staging_belt.flush(&staging)?;
+
+        Ok(Some(StagedMesh {
+            staging,
+            vertex_attributes,
+            indices_offset,
+            index_type,
+            index_count: index_count as _,
+            submeshes: mesh.submeshes().into(),
             bounding_sphere: *mesh.bounding_sphere(),
-        })
+        }))
     }
 
-    pub fn add(&self, handle: RawMeshHandle, mesh: GpuMesh) {
+    /// Replaces the data of an existing mesh.
+    ///
+    /// Allocated ranges are reused if the new mesh has the same attributes and
+    /// is not larger. Otherwise the mesh is uploaded into new ranges and the old
+    /// ones are freed once all frames which could use them are completed.
+    ///
+    /// Returns `false` if the update was deferred until there is enough memory budget.
+    #[tracing::instrument(level = "debug", name = "update_mesh", skip_all, fields(index = %handle.index))]
+    pub fn update_mesh(
+        &self,
+        queue: &gfx::Queue,
+        handle: RawMeshHandle,
+        staged: Option<Box<StagedMesh>>,
+    ) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
         let mut registry = self.registry.lock().unwrap();
-        let index = handle.index;
-        if index >= registry.len() {
-            registry.resize_with(index + 1, || None);
+        let mesh = registry.get_mut(handle).context("invalid mesh handle")?;
+
+        // NOTE: The new data supersedes the deferred upload of the mesh
+        state
+            .deferred_uploads
+            .retain(|(deferred, _)| *deferred != handle);
+
+        let Some(staged) = staged else {
+            let old_mesh = std::mem::replace(mesh, GpuMesh::new_empty());
+            state.pending_retired_allocations.push(old_mesh.allocation);
+            return Ok(true);
+        };
+
+        match state.replace_mesh(queue, mesh, &staged) {
+            Ok(()) => Ok(true),
+            Err(e) if e.is::<OutOfMemoryBudget>() => {
+                tracing::warn!("{e}, deferring mesh update");
+                state.deferred_uploads.push_back((handle, staged));
+                Ok(false)
+            }
+            Err(e) => Err(e),
         }
-        registry[index] = Some(mesh);
     }
 
-    #[tracing::instrument(level = "debug", name = "remove_mesh", skip_all, fields(index = %handle.index))]
-    pub fn remove(&self, handle: RawMeshHandle) {
-        let index = handle.index;
-        let mesh = {
-            let mut registry = self.registry.lock().unwrap();
-            registry[index].take().expect("handle must be valid")
+    /// Registers the mesh.
+    ///
+    /// Deferred meshes are drawn as empty until they are uploaded
+    /// by [`flush_deferred_uploads`].
+    ///
+    /// [`flush_deferred_uploads`]: Self::flush_deferred_uploads
+    pub fn add(&self, handle: RawMeshHandle, upload: MeshUpload) {
+        let mut state = self.state.lock().unwrap();
+        let mut registry = self.registry.lock().unwrap();
+
+        let mesh = match upload {
+            MeshUpload::Uploaded(mesh) => mesh,
+            MeshUpload::Deferred(staged) => {
+                let mesh = GpuMesh::new_pending(&staged);
+                state.deferred_uploads.push_back((handle, staged));
+                mesh
+            }
         };
+        registry.insert(handle, mesh);
+    }
 
+    /// Removes the mesh or defers its removal until its upload is completed.
+    ///
+    /// Returns `true` if the mesh was removed immediately. Stale handles
+    /// are ignored and never removed.
+    #[tracing::instrument(level = "debug", name = "remove_mesh", skip_all, fields(index = %handle.index))]
+    pub fn remove(&self, handle: RawMeshHandle) -> bool {
         let mut state = self.state.lock().unwrap();
+        state
+            .deferred_uploads
+            .retain(|(deferred, _)| *deferred != handle);
+
+        let upload_epoch = {
+            let registry = self.registry.lock().unwrap();
+            let Some(mesh) = registry.get(handle) else {
+                tracing::error!(?handle, "tried to remove a mesh using a stale handle");
+                return false;
+            };
+            mesh.upload_epoch
+        };
 
-        for (_, range) in mesh.vertex_attribute_ranges {
-            if !range.is_empty() {
-                state.vertex_alloc.free_range(range.clone());
-                tracing::debug!(?range, "freed vertex attribute range");
+        if let Some(epoch) = upload_epoch {
+            if state.uploads.is_pending(epoch) {
+                tracing::debug!(epoch, "deferred mesh removal until upload is completed");
+                state.uploads.defer_removal(handle, epoch);
+                return false;
             }
         }
 
-        if !mesh.indices_range.is_empty() {
-            state.index_alloc.free_range(mesh.indices_range.clone());
-            tracing::debug!(range = ?mesh.indices_range, "freed indices range");
+        self.remove_now(&mut state, handle);
+        true
+    }
+
+    fn remove_now(&self, state: &mut MeshManagerState, handle: RawMeshHandle) {
+        let mesh = {
+            let mut registry = self.registry.lock().unwrap();
+            registry.remove(handle).expect("handle must be valid")
+        };
+
+        if state.direct_upload {
+            // NOTE: Freed ranges could be written by the CPU right away,
+            // so they are kept until the frames which use them are completed.
+            state.pending_retired_allocations.push(mesh.allocation);
+        } else {
+            state.free_allocation(&mesh.allocation);
         }
     }
 }
 
 pub struct MeshManagerDataGuard<'a> {
-    registry: MutexGuard<'a, Vec<Option<GpuMesh>>>,
+    registry: MutexGuard<'a, ResourceRegistry<Mesh, GpuMesh>>,
 }
 
-impl std::ops::Deref for MeshManagerDataGuard<'_> {
-    type Target = Vec<Option<GpuMesh>>;
-
+impl MeshManagerDataGuard<'_> {
+    /// Returns the mesh if the handle is still valid.
     #[inline]
-    fn deref(&self) -> &Self::Target {
-        self.registry.deref()
-    }
-}
-
-impl std::ops::DerefMut for MeshManagerDataGuard<'_> {
-    #[inline]
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.registry.deref_mut()
+    pub fn get(&self, handle: RawMeshHandle) -> Option<&GpuMesh> {
+        self.registry.get(handle)
     }
 }
 
 struct MeshManagerState {
     buffers: MeshBuffers,
+    /// Whether mesh buffers are mapped and written directly.
+    direct_upload: bool,
+    /// Whether mesh buffers have device addresses.
+    device_address: bool,
     new_vertex_buffer: bool,
+    /// Whether the upload of [`DEFAULT_VERTEX_ATTRIBUTES`] is recorded.
+    default_vertex_attributes_written: bool,
     vertex_alloc: RangeAllocator<u32>,
     index_alloc: RangeAllocator<u32>,
-    encoder: Option<gfx::Encoder>,
+    encoder: Option<gfx::PrimaryEncoder>,
+    transfer: Option<TransferUploads>,
+    /// Whether buffers were reallocated since the last drain.
+    ///
+    /// NOTE: Reallocation copies are recorded on the graphics queue, so all
+    /// subsequent copies must also go there to be ordered after them.
+    buffers_reallocated: bool,
+    uploads: UploadTracker<RawMeshHandle>,
+    /// Ranges of updated meshes and the frame since which they are not used.
+    retired_allocations: Vec<(MeshAllocation, u32)>,
+    /// Ranges of meshes updated since the last drain.
+    pending_retired_allocations: Vec<MeshAllocation>,
+    /// Uploads which exceeded the memory budget, in the order of submission.
+    deferred_uploads: VecDeque<(RawMeshHandle, Box<StagedMesh>)>,
+    stats: MeshManagerStats,
 }
 
 impl MeshManagerState {
+    fn replace_mesh(
+        &mut self,
+        queue: &gfx::Queue,
+        mesh: &mut GpuMesh,
+        staged: &StagedMesh,
+    ) -> Result<()> {
+        if self.write_staged_mesh_in_place(queue, staged, mesh)? {
+            tracing::debug!("reused allocated ranges");
+            return Ok(());
+        }
+
+        let new_mesh = self.write_staged_mesh(queue, staged)?;
+        let old_mesh = std::mem::replace(mesh, new_mesh);
+        self.pending_retired_allocations.push(old_mesh.allocation);
+        Ok(())
+    }
+
+    /// Returns `true` if new meshes can be written into the mapped mesh buffers.
+    ///
+    /// NOTE: Reallocated buffers are filled with copies of the old ones on the GPU,
+    /// which would overwrite the data written directly in the same frame.
+    fn can_write_directly(&self) -> bool {
+        self.direct_upload && !self.buffers_reallocated
+    }
+
+    /// Writes the mesh data into the mapped mesh buffers without staging.
+    ///
+    /// Returns `None` if the mesh buffers were reallocated for the mesh,
+    /// so it must be staged instead, see [`MeshManagerState::can_write_directly`].
+    fn write_mesh_directly(&mut self, queue: &gfx::Queue, mesh: &Mesh) -> Result<Option<GpuMesh>> {
+        let index_type = mesh.index_type();
+        let index_count = mesh.indices().len() as u32;
+
+        let attributes = mesh.attribute_data();
+        let (vertex_ranges, indices_range) = self.alloc_mesh_ranges(
+            queue,
+            attributes
+                .iter()
+                .map(|attribute| attribute.byte_len() as u32),
+            index_words(index_type, index_count),
+        )?;
+        let allocation = MeshAllocation {
+            vertex_attribute_ranges: vertex_ranges,
+            indices_range,
+        };
+
+        if !self.can_write_directly() {
+            // NOTE: The ranges were never used, so they are freed immediately
+            self.free_allocation(&allocation);
+            return Ok(None);
+        }
+        let (Some(vertices), Some(indices)) =
+            (&self.buffers.mapped_vertices, &self.buffers.mapped_indices)
+        else {
+            anyhow::bail!("mesh buffers are not mapped");
+        };
+
+        let mut written = Vec::with_capacity(attributes.len() + 1);
+        for (attribute, range) in attributes.iter().zip(&allocation.vertex_attribute_ranges) {
+            let data = attribute.untyped_data();
+            let dst_range = range.start as usize..range.end as usize;
+            assert!(
+                dst_range.end <= vertices.size(),
+                "mesh range is out of bounds"
+            );
+
+            // SAFETY: `vertices` is mapped and has at least `dst_range.end` bytes,
+            // the range is allocated for this mesh and is not used by the GPU.
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    data.as_ptr(),
+                    vertices.as_mut_ptr().add(dst_range.start).cast(),
+                    data.len(),
+                );
+            }
+            written.push((vertices, dst_range));
+        }
+
+        let word_size = INDEX_WORD_SIZE as usize;
+        let indices_range = &allocation.indices_range;
+        let dst_range =
+            indices_range.start as usize * word_size..indices_range.end as usize * word_size;
+        assert!(
+            dst_range.end <= indices.size(),
+            "mesh range is out of bounds"
+        );
+
+        // SAFETY: `indices` is mapped and has at least `dst_range.end` bytes,
+        // the range is allocated for this mesh and is not used by the GPU.
+        // Word ranges are aligned to 4 bytes.
+        unsafe {
+            write_indices(
+                mesh.indices(),
+                index_type,
+                indices.as_mut_ptr().add(dst_range.start).cast(),
+            );
+        }
+        written.push((indices, dst_range));
+
+        queue.device().flush_mapped_ranges(written)?;
+        self.stats.record_upload(UploadPath::Direct);
+
+        Ok(Some(GpuMesh {
+            vertex_attribute_ranges: attributes
+                .iter()
+                .zip(&allocation.vertex_attribute_ranges)
+                .map(|(attribute, range)| (attribute.kind(), attribute.encoding(), range.clone()))
+                .collect(),
+            indices_range: indices_in_words(
+                index_type,
+                index_count,
+                allocation.indices_range.start,
+            ),
+            index_type,
+            submeshes: mesh.submeshes().into(),
+            bounding_sphere: *mesh.bounding_sphere(),
+            allocation,
+            upload_epoch: None,
+        }))
+    }
+
+    fn write_staged_mesh(&mut self, queue: &gfx::Queue, staged: &StagedMesh) -> Result<GpuMesh> {
+        let (vertex_ranges, indices_range) = self.alloc_mesh_ranges(
+            queue,
+            staged
+                .vertex_attributes
+                .iter()
+                .map(|attribute| attribute.len),
+            staged.index_words(),
+        )?;
+
+        let vertex_attribute_copies = staged
+            .vertex_attributes
+            .iter()
+            .zip(&vertex_ranges)
+            .map(|(attribute, range)| gfx::BufferCopy {
+                src_offset: attribute.offset,
+                dst_offset: range.start as usize,
+                size: (range.end - range.start) as usize,
+            })
+            .collect::<Vec<_>>();
+        let indices_copy = gfx::BufferCopy {
+            src_offset: staged.indices_offset,
+            dst_offset: (indices_range.start as usize).saturating_mul(INDEX_WORD_SIZE as _),
+            size: (staged.index_words() as usize).saturating_mul(INDEX_WORD_SIZE as _),
+        };
+
+        self.encode_copies(
+            queue,
+            staged.staging.buffer(),
+            &vertex_attribute_copies,
+            &indices_copy,
+        )?;
+        self.stats.record_upload(UploadPath::Staged);
+
+        // Done
+        Ok(GpuMesh {
+            vertex_attribute_ranges: staged
+                .vertex_attributes
+                .iter()
+                .zip(&vertex_ranges)
+                .map(|(attribute, range)| (attribute.kind, attribute.encoding, range.clone()))
+                .collect(),
+            indices_range: staged.indices_in_words(indices_range.start),
+            index_type: staged.index_type,
+            submeshes: staged.submeshes.clone(),
+            bounding_sphere: staged.bounding_sphere,
+            allocation: MeshAllocation {
+                vertex_attribute_ranges: vertex_ranges,
+                indices_range,
+            },
+            upload_epoch: Some(self.uploads.recording_epoch()),
+        })
+    }
+
+    /// Allocates ranges for vertex attributes of the specified sizes in bytes
+    /// and for the index words.
+    fn alloc_mesh_ranges<I>(
+        &mut self,
+        queue: &gfx::Queue,
+        attribute_sizes: I,
+        index_words: u32,
+    ) -> Result<(Vec<Range<u32>>, Range<u32>)>
+    where
+        I: ExactSizeIterator<Item = u32>,
+    {
+        let mut vertex_ranges = Vec::with_capacity(attribute_sizes.len());
+
+        // Allocate ranges for vertex attributes
+        for size in attribute_sizes {
+            let range = match self.alloc_range_for_vertices(queue, size) {
+                Ok(range) => range,
+                Err(e) => {
+                    // NOTE: Failed uploads might be retried, so ranges must not leak
+                    self.free_vertex_ranges(&vertex_ranges);
+                    return Err(e);
+                }
+            };
+            tracing::debug!(?range, len = size, "allocated vertex attribute range");
+            vertex_ranges.push(range);
+        }
+
+        // Allocate range for indices
+        let indices_range = match self.alloc_range_for_indices(queue, index_words) {
+            Ok(range) => range,
+            Err(e) => {
+                self.free_vertex_ranges(&vertex_ranges);
+                return Err(e);
+            }
+        };
+        tracing::debug!(range = ?indices_range, "allocated indices range");
+
+        // Write default attributes with the first mesh (after all reallocations,
+        // so that the update is not racing with the copy of the old buffer)
+        if !self.default_vertex_attributes_written {
+            make_encoder(queue, &mut self.encoder)?.update_buffer(
+                &self.buffers.vertices,
+                0,
+                &DEFAULT_VERTEX_ATTRIBUTES,
+            );
+            self.default_vertex_attributes_written = true;
+        }
+
+        Ok((vertex_ranges, indices_range))
+    }
+
+    /// Overwrites the mesh data using its allocated ranges.
+    ///
+    /// Returns `false` if the staged mesh doesn't fit into them.
+    ///
+    /// NOTE: The ranges could still be used by the previous frames, so the data
+    /// is always copied on the GPU, even if direct uploads are supported.
+    fn write_staged_mesh_in_place(
+        &mut self,
+        queue: &gfx::Queue,
+        staged: &StagedMesh,
+        mesh: &mut GpuMesh,
+    ) -> Result<bool> {
+        let allocation = &mesh.allocation;
+        // NOTE: Pending meshes have attributes but no allocated ranges
+        let fits = mesh.vertex_attribute_ranges.len() == staged.vertex_attributes.len()
+            && allocation.vertex_attribute_ranges.len() == staged.vertex_attributes.len()
+            && std::iter::zip(&mesh.vertex_attribute_ranges, &staged.vertex_attributes)
+                .zip(&allocation.vertex_attribute_ranges)
+                .all(|(((kind, encoding, _), attribute), range)| {
+                    // NOTE: Objects have to be updated if the encoding changes
+                    *kind == attribute.kind
+                        && *encoding == attribute.encoding
+                        && attribute.len <= range.end - range.start
+                })
+            && staged.index_words()
+                <= allocation.indices_range.end - allocation.indices_range.start;
+        if !fits {
+            return Ok(false);
+        }
+
+        let vertex_attribute_copies = std::iter::zip(
+            &staged.vertex_attributes,
+            &allocation.vertex_attribute_ranges,
+        )
+        .map(|(attribute, range)| gfx::BufferCopy {
+            src_offset: attribute.offset,
+            dst_offset: range.start as usize,
+            size: attribute.len as usize,
+        })
+        .collect::<Vec<_>>();
+
+        let indices_start = allocation.indices_range.start;
+        let indices_copy = gfx::BufferCopy {
+            src_offset: staged.indices_offset,
+            dst_offset: (indices_start as usize).saturating_mul(INDEX_WORD_SIZE as _),
+            size: (staged.index_words() as usize).saturating_mul(INDEX_WORD_SIZE as _),
+        };
+
+        // NOTE: Ranges could still be used by the previous frames, so in-place
+        // copies are always recorded on the graphics queue after them.
+        let encoder = make_encoder(queue, &mut self.encoder)?;
+        encoder.memory_barrier(
+            gfx::PipelineStageFlags::VERTEX_INPUT
+                | gfx::PipelineStageFlags::VERTEX_SHADER
+                | gfx::PipelineStageFlags::TRANSFER,
+            gfx::AccessFlags::TRANSFER_WRITE,
+            gfx::PipelineStageFlags::TRANSFER,
+            gfx::AccessFlags::TRANSFER_WRITE,
+        );
+        encoder.copy_buffer(
+            staged.staging.buffer(),
+            &self.buffers.vertices,
+            &vertex_attribute_copies,
+        );
+        encoder.copy_buffer(
+            staged.staging.buffer(),
+            &self.buffers.indices,
+            std::slice::from_ref(&indices_copy),
+        );
+
+        for ((_, _, range), copy) in
+            std::iter::zip(&mut mesh.vertex_attribute_ranges, &vertex_attribute_copies)
+        {
+            *range = range.start..range.start + copy.size as u32;
+        }
+        mesh.indices_range = staged.indices_in_words(indices_start);
+        mesh.index_type = staged.index_type;
+        mesh.submeshes = staged.submeshes.clone();
+        mesh.bounding_sphere = staged.bounding_sphere;
+        mesh.upload_epoch = Some(self.uploads.recording_epoch());
+        self.stats.record_upload(UploadPath::Staged);
+
+        Ok(true)
+    }
+
+    fn free_allocation(&mut self, allocation: &MeshAllocation) {
+        self.free_vertex_ranges(&allocation.vertex_attribute_ranges);
+
+        let range = &allocation.indices_range;
+        if !range.is_empty() {
+            self.index_alloc.free_range(range.clone());
+            tracing::debug!(?range, "freed indices range");
+        }
+    }
+
+    fn free_vertex_ranges<'a, I>(&mut self, ranges: I)
+    where
+        I: IntoIterator<Item = &'a Range<u32>>,
+    {
+        for range in ranges {
+            if !range.is_empty() {
+                self.vertex_alloc.free_range(range.clone());
+                tracing::debug!(?range, "freed vertex attribute range");
+            }
+        }
+    }
+
+    fn encode_copies(
+        &mut self,
+        queue: &gfx::Queue,
+        staging_buffer: &gfx::Buffer,
+        vertex_attribute_copies: &[gfx::BufferCopy],
+        indices_copy: &gfx::BufferCopy,
+    ) -> Result<()> {
+        let transfer = match &mut self.transfer {
+            Some(transfer) if !self.buffers_reallocated => transfer,
+            _ => {
+                let encoder = make_encoder(queue, &mut self.encoder)?;
+                encoder.copy_buffer(
+                    staging_buffer,
+                    &self.buffers.vertices,
+                    vertex_attribute_copies,
+                );
+                encoder.copy_buffer(
+                    staging_buffer,
+                    &self.buffers.indices,
+                    std::slice::from_ref(indices_copy),
+                );
+                return Ok(());
+            }
+        };
+
+        let encoder = match &mut transfer.encoder {
+            Some(encoder) => encoder,
+            encoder => encoder.insert(transfer.queue.create_primary_encoder()?),
+        };
+        encoder.copy_buffer(
+            staging_buffer,
+            &self.buffers.vertices,
+            vertex_attribute_copies,
+        );
+        encoder.copy_buffer(
+            staging_buffer,
+            &self.buffers.indices,
+            std::slice::from_ref(indices_copy),
+        );
+
+        // Transfer ownership of the written ranges to the graphics queue family
+        let family_transfer = Some((transfer.queue.id().family, queue.id().family));
+        let release = vertex_attribute_copies
+            .iter()
+            .map(|copy| (&self.buffers.vertices, copy))
+            .chain(std::iter::once((&self.buffers.indices, indices_copy)))
+            .map(|(buffer, copy)| gfx::BufferMemoryBarrier {
+                buffer,
+                src_access: gfx::AccessFlags::TRANSFER_WRITE,
+                dst_access: gfx::AccessFlags::empty(),
+                family_transfer,
+                offset: copy.dst_offset,
+                size: copy.size,
+            })
+            .collect::<Vec<_>>();
+
+        let acquire = release
+            .iter()
+            .map(|barrier| gfx::BufferMemoryBarrier {
+                src_access: gfx::AccessFlags::empty(),
+                dst_access: gfx::AccessFlags::SHADER_READ
+                    | gfx::AccessFlags::INDEX_READ
+                    | gfx::AccessFlags::TRANSFER_READ,
+                ..barrier.clone()
+            })
+            .collect::<Vec<_>>();
+
+        encoder.buffer_barriers(
+            gfx::PipelineStageFlags::TRANSFER,
+            gfx::PipelineStageFlags::BOTTOM_OF_PIPE,
+            &release,
+        );
+        make_encoder(queue, &mut self.encoder)?.buffer_barriers(
+            MeshManager::TRANSFER_UPLOADS_WAIT_STAGES,
+            MeshManager::TRANSFER_UPLOADS_WAIT_STAGES,
+            &acquire,
+        );
+
+        Ok(())
+    }
+
     fn alloc_range_for_vertices(&mut self, queue: &gfx::Queue, size: u32) -> Result<Range<u32>> {
         match self.vertex_alloc.allocate_range(size) {
             Ok(range) => Ok(range),
@@ -268,14 +1103,14 @@ impl MeshManagerState {
         }
     }
 
-    fn alloc_range_for_indices(&mut self, queue: &gfx::Queue, count: u32) -> Result<Range<u32>> {
-        match self.index_alloc.allocate_range(count) {
+    fn alloc_range_for_indices(&mut self, queue: &gfx::Queue, words: u32) -> Result<Range<u32>> {
+        match self.index_alloc.allocate_range(words) {
             Ok(range) => Ok(range),
             Err(_) => {
-                self.realloc(queue, 0, count)?;
+                self.realloc(queue, 0, words)?;
                 Ok(self
                     .index_alloc
-                    .allocate_range(count)
+                    .allocate_range(words)
                     .expect("`index_alloc` must grow after `realloc`"))
             }
         }
@@ -286,10 +1121,10 @@ impl MeshManagerState {
         &mut self,
         queue: &gfx::Queue,
         additional_vertices_capacity: u32,
-        additional_index_count: u32,
+        additional_index_words: u32,
     ) -> Result<()> {
         let update_vertices = additional_vertices_capacity > 0;
-        let update_indices = additional_index_count > 0;
+        let update_indices = additional_index_words > 0;
         if !update_vertices && !update_indices {
             return Ok(());
         }
@@ -297,31 +1132,32 @@ impl MeshManagerState {
         let device = queue.device();
         let max_buffer_size = device.limits().max_storage_buffer_range;
 
-        // Make vertices buffer if needed
+        // Compute the new vertices buffer size if needed
         let current_vertices_size = self.index_alloc.initial_range().end;
-        let new_vertices = if update_vertices {
+        let new_vertices_size = if update_vertices {
             let new_vertices_size = current_vertices_size
                 .checked_add(additional_vertices_capacity)
                 .and_then(|size| size.checked_next_power_of_two())
                 .expect("too many vertices")
-                .min(max_buffer_size);
+                .min(max_buffer_size)
+                .min(PACKED_VERTEX_ATTRIBUTE_BIT);
 
             anyhow::ensure!(
                 new_vertices_size > current_vertices_size,
                 "max vertex buffer size exceeded ({max_buffer_size} bytes)"
             );
 
-            Some((make_vertices(device, new_vertices_size)?, new_vertices_size))
+            Some(new_vertices_size)
         } else {
             None
         };
 
-        // Make indices buffer if needed
-        let current_index_count = self.index_alloc.initial_range().end;
-        let current_indices_size = current_index_count.saturating_mul(INDEX_SIZE);
-        let new_indices = if update_indices {
+        // Compute the new indices buffer size if needed
+        let current_index_words = self.index_alloc.initial_range().end;
+        let current_indices_size = current_index_words.saturating_mul(INDEX_WORD_SIZE);
+        let new_indices_size = if update_indices {
             let new_indices_size = current_indices_size
-                .checked_add(additional_index_count.saturating_mul(INDEX_SIZE))
+                .checked_add(additional_index_words.saturating_mul(INDEX_WORD_SIZE))
                 .and_then(|size| size.checked_next_power_of_two())
                 .expect("too many indices")
                 .min(max_buffer_size);
@@ -331,18 +1167,46 @@ impl MeshManagerState {
                 "max index buffer size exceeded ({max_buffer_size} bytes)"
             );
             anyhow::ensure!(
-                new_indices_size % INDEX_SIZE == 0,
-                "unaligned index buffer size ({new_indices_size} bytes, must be multiple of {INDEX_SIZE})"
+                new_indices_size % INDEX_WORD_SIZE == 0,
+                "unaligned index buffer size ({new_indices_size} bytes, must be multiple of {INDEX_WORD_SIZE})"
             );
 
-            Some((make_indices(device, new_indices_size)?, new_indices_size))
+            Some(new_indices_size)
         } else {
             None
         };
 
+        // NOTE: Old buffers are alive until the copy is completed,
+        // so the new ones are allocated in addition to them.
+        let required_size =
+            new_vertices_size.unwrap_or(0) as u64 + new_indices_size.unwrap_or(0) as u64;
+        if !fits_memory_budget(device, required_size) {
+            return Err(OutOfMemoryBudget {
+                size: required_size,
+            }
+            .into());
+        }
+
+        let new_vertices = match new_vertices_size {
+            Some(size) => Some((
+                make_vertices(device, size, self.direct_upload, self.device_address)?,
+                size,
+            )),
+            None => None,
+        };
+        let new_indices = match new_indices_size {
+            Some(size) => Some((
+                make_indices(device, size, self.direct_upload, self.device_address)?,
+                size,
+            )),
+            None => None,
+        };
+
         // Update vertex buffer
         if let Some((new_vertices, new_vertices_size)) = new_vertices {
             let old_buffer = std::mem::replace(&mut self.buffers.vertices, new_vertices);
+            self.buffers.mapped_vertices =
+                map_buffer(device, &self.buffers.vertices, self.direct_upload)?;
             self.new_vertex_buffer = true;
             self.vertex_alloc.grow_to(new_vertices_size);
 
@@ -360,7 +1224,9 @@ impl MeshManagerState {
         // Update index buffer
         if let Some((new_indices, new_indices_size)) = new_indices {
             let old_buffer = std::mem::replace(&mut self.buffers.indices, new_indices);
-            self.index_alloc.grow_to(new_indices_size / INDEX_SIZE);
+            self.buffers.mapped_indices =
+                map_buffer(device, &self.buffers.indices, self.direct_upload)?;
+            self.index_alloc.grow_to(new_indices_size / INDEX_WORD_SIZE);
 
             make_encoder(queue, &mut self.encoder)?.copy_buffer(
                 &old_buffer,
@@ -373,6 +1239,10 @@ impl MeshManagerState {
             );
         }
 
+        // NOTE: Data written directly after this point would be overwritten
+        // by the copies of the old buffers, so uploads fall back to staging.
+        self.buffers_reallocated = true;
+
         // Sync other copies
         make_encoder(queue, &mut self.encoder)?.memory_barrier(
             gfx::PipelineStageFlags::TRANSFER,
@@ -385,10 +1255,155 @@ impl MeshManagerState {
     }
 }
 
+/// Fragmentation and upload counters of the mesh buffers.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MeshManagerStats {
+    /// Fraction of the free vertex buffer space outside of its largest free range.
+    pub vertex_fragmentation: f32,
+    /// Fraction of the free index buffer space outside of its largest free range.
+    pub index_fragmentation: f32,
+    /// Number of bytes moved by the defragmentation in the last frame.
+    pub frame_moved_bytes: u64,
+    /// Number of bytes moved by the defragmentation since the renderer was created.
+    pub moved_bytes: u64,
+    /// Number of meshes written directly into the mapped mesh buffers.
+    pub direct_uploads: u64,
+    /// Number of meshes copied from the staging belt on the GPU.
+    pub staged_uploads: u64,
+}
+
+impl MeshManagerStats {
+    fn record_upload(&mut self, path: UploadPath) {
+        match path {
+            UploadPath::Direct => self.direct_uploads += 1,
+            UploadPath::Staged => self.staged_uploads += 1,
+        }
+    }
+}
+
+/// The way mesh data gets into the mesh buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UploadPath {
+    /// The data is written into the mapped buffers by the CPU.
+    Direct,
+    /// The data is copied from the staging belt by the GPU.
+    Staged,
+}
+
+/// Limits the amount of data moved by a single defragmentation step.
+struct DefragBudget {
+    ranges_left: usize,
+    moved_bytes: u32,
+}
+
+impl Default for DefragBudget {
+    fn default() -> Self {
+        Self {
+            ranges_left: DEFRAG_MAX_RANGES_PER_FRAME,
+            moved_bytes: 0,
+        }
+    }
+}
+
+impl DefragBudget {
+    fn is_exhausted(&self) -> bool {
+        self.ranges_left == 0 || self.moved_bytes >= DEFRAG_MAX_BYTES_PER_FRAME
+    }
+
+    /// Returns `true` if the range of the specified size can be moved.
+    ///
+    /// NOTE: Ranges larger than the whole budget are moved alone.
+    fn allows(&self, size: u32) -> bool {
+        !self.is_exhausted()
+            && (self.moved_bytes == 0
+                || self.moved_bytes.saturating_add(size) <= DEFRAG_MAX_BYTES_PER_FRAME)
+    }
+
+    fn consume(&mut self, size: u32) {
+        self.ranges_left -= 1;
+        self.moved_bytes = self.moved_bytes.saturating_add(size);
+    }
+
+    /// Moves the range towards the start of the buffer if the budget allows it.
+    ///
+    /// Returns the previous range which must be freed once it is no longer used.
+    fn relocate(
+        &mut self,
+        alloc: &mut RangeAllocator<u32>,
+        range: &mut Range<u32>,
+        unit_size: u32,
+    ) -> Option<Range<u32>> {
+        let size = (range.end - range.start).saturating_mul(unit_size);
+        if range.is_empty() || !self.allows(size) {
+            return None;
+        }
+
+        let new_range = relocate_range(alloc, range)?;
+        self.consume(size);
+        Some(std::mem::replace(range, new_range))
+    }
+}
+
+/// A result of [`MeshManager::upload_mesh`].
+pub enum MeshUpload {
+    Uploaded(GpuMesh),
+    /// The mesh buffers can't grow without exceeding the memory budget,
+    /// so the upload is postponed.
+    Deferred(Box<StagedMesh>),
+}
+
+/// Upload commands taken from the [`MeshManager`].
+pub struct MeshUploads {
+    /// Commands which must be submitted to the graphics queue before the frame.
+    pub graphics: Option<gfx::PrimaryEncoder>,
+    /// Commands which must be submitted to the transfer queue before the graphics ones.
+    pub transfer: Option<gfx::PrimaryEncoder>,
+}
+
+struct TransferUploads {
+    queue: gfx::Queue,
+    encoder: Option<gfx::PrimaryEncoder>,
+}
+
+/// Mesh data copied into a staging buffer, ready to be written into the mesh buffers.
+pub struct StagedMesh {
+    /// NOTE: Offsets of the staged data are relative to the staging buffer.
+    staging: gfx::StagingAllocation,
+    vertex_attributes: Vec<StagedVertexAttribute>,
+    indices_offset: usize,
+    index_type: gfx::IndexType,
+    index_count: u32,
+    submeshes: Box<[Range<u32>]>,
+    bounding_sphere: BoundingSphere,
+}
+
+impl StagedMesh {
+    fn index_words(&self) -> u32 {
+        index_words(self.index_type, self.index_count)
+    }
+
+    /// Converts the start of the allocated words range into the range of indices.
+    fn indices_in_words(&self, first_word: u32) -> Range<u32> {
+        indices_in_words(self.index_type, self.index_count, first_word)
+    }
+}
+
+struct StagedVertexAttribute {
+    kind: VertexAttributeKind,
+    encoding: VertexAttributeEncoding,
+    offset: usize,
+    len: u32,
+}
+
 pub struct GpuMesh {
-    vertex_attribute_ranges: Vec<(VertexAttributeKind, Range<u32>)>,
+    vertex_attribute_ranges: Vec<(VertexAttributeKind, VertexAttributeEncoding, Range<u32>)>,
     indices_range: Range<u32>,
+    index_type: gfx::IndexType,
+    /// Ranges of indices relative to the start of `indices_range`.
+    submeshes: Box<[Range<u32>]>,
+    allocation: MeshAllocation,
     bounding_sphere: BoundingSphere,
+    upload_epoch: Option<u64>,
 }
 
 impl GpuMesh {
@@ -396,81 +1411,747 @@ impl GpuMesh {
         Self {
             vertex_attribute_ranges: Default::default(),
             indices_range: 0..0,
+            index_type: gfx::IndexType::U32,
+            submeshes: Default::default(),
+            allocation: MeshAllocation::default(),
             bounding_sphere: BoundingSphere::compute_from_positions(&[]),
+            upload_epoch: None,
+        }
+    }
+
+    /// Makes a mesh which is drawn as empty until the staged data is uploaded.
+    ///
+    /// NOTE: Attributes and submeshes are known, so objects can use it.
+    fn new_pending(staged: &StagedMesh) -> Self {
+        Self {
+            vertex_attribute_ranges: staged
+                .vertex_attributes
+                .iter()
+                .map(|attribute| (attribute.kind, attribute.encoding, 0..0))
+                .collect(),
+            index_type: staged.index_type,
+            submeshes: staged.submeshes.iter().map(|_| 0..0).collect(),
+            bounding_sphere: staged.bounding_sphere,
+            ..Self::new_empty()
         }
     }
 
     pub fn attributes(&self) -> impl Iterator<Item = VertexAttributeKind> + '_ {
         self.vertex_attribute_ranges
             .iter()
-            .map(|(component, _)| *component)
+            .map(|(component, _, _)| *component)
     }
 
     pub fn get_attribute_range(&self, attribute: VertexAttributeKind) -> Option<Range<u32>> {
         self.vertex_attribute_ranges
             .iter()
-            .find_map(|(c, range)| (*c == attribute).then_some(range.clone()))
+            .find_map(|(c, _, range)| (*c == attribute).then_some(range.clone()))
+    }
+
+    /// Returns the offset of the vertex attribute passed to shaders.
+    ///
+    /// Offsets of the packed attributes are marked with a flag bit.
+    pub fn get_attribute_offset(&self, attribute: VertexAttributeKind) -> Option<u32> {
+        self.vertex_attribute_ranges
+            .iter()
+            .find(|(c, _, _)| *c == attribute)
+            .map(|(_, encoding, range)| match encoding {
+                VertexAttributeEncoding::Float => range.start,
+                VertexAttributeEncoding::Packed => range.start | PACKED_VERTEX_ATTRIBUTE_BIT,
+            })
     }
 
+    /// Range of indices in the index buffer bound with [`index_type`].
+    ///
+    /// [`index_type`]: Self::index_type
     pub fn indices(&self) -> Range<u32> {
         self.indices_range.clone()
     }
 
+    pub fn index_type(&self) -> gfx::IndexType {
+        self.index_type
+    }
+
+    /// Number of submeshes, zero if the mesh is not split into them.
+    pub fn submesh_count(&self) -> usize {
+        self.submeshes.len()
+    }
+
+    /// Range of submesh indices in the index buffer bound with [`index_type`].
+    ///
+    /// [`index_type`]: Self::index_type
+    pub fn submesh_indices(&self, submesh: usize) -> Option<Range<u32>> {
+        let range = self.submeshes.get(submesh)?;
+        let start = self.indices_range.start;
+        Some(start + range.start..start + range.end)
+    }
+
     pub fn bounding_sphere(&self) -> &BoundingSphere {
         &self.bounding_sphere
     }
 }
 
+/// Ranges allocated for the mesh which might be larger than its actual data.
+#[derive(Default)]
+struct MeshAllocation {
+    vertex_attribute_ranges: Vec<Range<u32>>,
+    /// Range of index buffer words.
+    indices_range: Range<u32>,
+}
+
+/// Tracks which upload command buffers are still in flight.
+///
+/// Each drained upload encoder gets its own epoch which is associated with
+/// the frame it was submitted with.
+struct UploadTracker<H> {
+    recording_epoch: u64,
+    completed_epoch: u64,
+    submitted: VecDeque<(u64, u32)>,
+    deferred_removals: Vec<(H, u64)>,
+}
+
+impl<H> Default for UploadTracker<H> {
+    fn default() -> Self {
+        Self {
+            recording_epoch: 0,
+            completed_epoch: 0,
+            submitted: VecDeque::new(),
+            deferred_removals: Vec::new(),
+        }
+    }
+}
+
+impl<H: Copy> UploadTracker<H> {
+    /// Epoch of the encoder which is currently recorded.
+    fn recording_epoch(&self) -> u64 {
+        self.recording_epoch
+    }
+
+    fn is_pending(&self, epoch: u64) -> bool {
+        epoch >= self.completed_epoch
+    }
+
+    fn submit(&mut self, frame: u32) {
+        self.submitted.push_back((self.recording_epoch, frame));
+        self.recording_epoch += 1;
+    }
+
+    fn complete(&mut self, frame: u32) {
+        while let Some(&(epoch, submitted_frame)) = self.submitted.front() {
+            if submitted_frame > frame {
+                break;
+            }
+            self.submitted.pop_front();
+            self.completed_epoch = epoch + 1;
+        }
+    }
+
+    fn defer_removal(&mut self, handle: H, epoch: u64) {
+        self.deferred_removals.push((handle, epoch));
+    }
+
+    fn drain_removals(&mut self) -> Vec<H> {
+        let completed_epoch = self.completed_epoch;
+
+        let mut ready = Vec::new();
+        self.deferred_removals.retain(|&(handle, epoch)| {
+            if epoch < completed_epoch {
+                ready.push(handle);
+                false
+            } else {
+                true
+            }
+        });
+        ready
+    }
+}
+
+/// Device addresses of the mesh buffers, see [`MeshManager::buffer_addresses`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshBufferAddresses {
+    pub vertices: gfx::DeviceAddress,
+    pub indices: gfx::DeviceAddress,
+}
+
 struct MeshBuffers {
     vertices: gfx::Buffer,
     indices: gfx::Buffer,
+    /// Mappings of the buffers if direct uploads are supported.
+    mapped_vertices: Option<gfx::MappedBuffer>,
+    mapped_indices: Option<gfx::MappedBuffer>,
 }
 
 impl MeshBuffers {
-    fn new(device: &gfx::Device, vertices_capacity: u32, index_count: u32) -> Result<Self> {
+    fn new(
+        device: &gfx::Device,
+        vertices_capacity: u32,
+        index_words: u32,
+        direct_upload: bool,
+        device_address: bool,
+    ) -> Result<Self> {
+        let vertices = make_vertices(device, vertices_capacity, direct_upload, device_address)?;
+        let indices = make_indices(
+            device,
+            index_words * INDEX_WORD_SIZE,
+            direct_upload,
+            device_address,
+        )?;
         Ok(Self {
-            vertices: make_vertices(device, vertices_capacity)?,
-            indices: make_indices(device, index_count * INDEX_SIZE)?,
+            mapped_vertices: map_buffer(device, &vertices, direct_upload)?,
+            mapped_indices: map_buffer(device, &indices, direct_upload)?,
+            vertices,
+            indices,
         })
     }
 
-    fn bind_index_buffer(&self, encoder: &mut gfx::Encoder) {
-        encoder.bind_index_buffer(&self.indices, 0, INDEX_TYPE);
+    fn bind_index_buffer(&self, encoder: &mut gfx::EncoderCommon, index_type: gfx::IndexType) {
+        encoder.bind_index_buffer(&self.indices, 0, index_type);
     }
 }
 
+/// Max fraction of the heap budget which can be used by mesh buffers allocations.
+const MEMORY_BUDGET_THRESHOLD: f64 = 0.9;
+
+/// Returns `true` if any device local heap can fit `size` more bytes
+/// without exceeding [`MEMORY_BUDGET_THRESHOLD`] of its budget.
+fn fits_memory_budget(device: &gfx::Device, size: u64) -> bool {
+    let heaps = device.memory_usage();
+    let mut device_local = heaps.iter().filter(|heap| heap.device_local).peekable();
+    if device_local.peek().is_none() {
+        return true;
+    }
+
+    device_local.any(|heap| {
+        let limit = (heap.budget as f64 * MEMORY_BUDGET_THRESHOLD) as u64;
+        heap.usage.saturating_add(size) <= limit
+    })
+}
+
+/// An error returned when mesh buffers can't grow without exceeding the memory budget.
+#[derive(Debug, thiserror::Error)]
+#[error("mesh buffers can't grow by {size} bytes without exceeding the memory budget")]
+struct OutOfMemoryBudget {
+    size: u64,
+}
+
 fn make_encoder<'a>(
     queue: &gfx::Queue,
-    encoder: &'a mut Option<gfx::Encoder>,
-) -> Result<&'a mut gfx::Encoder, gfx::OutOfDeviceMemory> {
+    encoder: &'a mut Option<gfx::PrimaryEncoder>,
+) -> Result<&'a mut gfx::PrimaryEncoder, gfx::OutOfDeviceMemory> {
     match encoder {
         Some(encoder) => Ok(encoder),
-        None => Ok(encoder.get_or_insert(queue.create_secondary_encoder()?)),
+        None => Ok(encoder.get_or_insert(queue.create_primary_encoder()?)),
     }
 }
 
-fn make_vertices(device: &gfx::Device, size: u32) -> Result<gfx::Buffer, gfx::OutOfDeviceMemory> {
-    device.create_buffer(gfx::BufferInfo {
-        align_mask: VERTEX_ALIGN_MASK,
-        size: size as _,
-        usage: gfx::BufferUsage::TRANSFER_DST
-            | gfx::BufferUsage::TRANSFER_SRC
-            | gfx::BufferUsage::STORAGE,
-    })
+fn make_vertices(
+    device: &gfx::Device,
+    size: u32,
+    direct_upload: bool,
+    device_address: bool,
+) -> Result<gfx::Buffer, gfx::OutOfDeviceMemory> {
+    make_buffer(
+        device,
+        gfx::BufferInfo {
+            align_mask: VERTEX_ALIGN_MASK,
+            size: size as _,
+            usage: gfx::BufferUsage::TRANSFER_DST
+                | gfx::BufferUsage::TRANSFER_SRC
+                | gfx::BufferUsage::STORAGE
+                | address_usage(device_address),
+            label: Some("mesh vertices"),
+        },
+        direct_upload,
+    )
 }
 
-fn make_indices(device: &gfx::Device, size: u32) -> Result<gfx::Buffer, gfx::OutOfDeviceMemory> {
-    device.create_buffer(gfx::BufferInfo {
-        align_mask: INDEX_ALIGN_MASK,
-        size: size as _,
-        usage: gfx::BufferUsage::TRANSFER_DST
-            | gfx::BufferUsage::TRANSFER_SRC
-            | gfx::BufferUsage::STORAGE
-            | gfx::BufferUsage::INDEX,
-    })
+fn make_indices(
+    device: &gfx::Device,
+    size: u32,
+    direct_upload: bool,
+    device_address: bool,
+) -> Result<gfx::Buffer, gfx::OutOfDeviceMemory> {
+    make_buffer(
+        device,
+        gfx::BufferInfo {
+            align_mask: INDEX_ALIGN_MASK,
+            size: size as _,
+            usage: gfx::BufferUsage::TRANSFER_DST
+                | gfx::BufferUsage::TRANSFER_SRC
+                | gfx::BufferUsage::STORAGE
+                | gfx::BufferUsage::INDEX
+                | address_usage(device_address),
+            label: Some("mesh indices"),
+        },
+        direct_upload,
+    )
+}
+
+fn address_usage(device_address: bool) -> gfx::BufferUsage {
+    if device_address {
+        gfx::BufferUsage::SHADER_DEVICE_ADDRESS
+    } else {
+        gfx::BufferUsage::empty()
+    }
+}
+
+fn make_buffer(
+    device: &gfx::Device,
+    info: gfx::BufferInfo,
+    direct_upload: bool,
+) -> Result<gfx::Buffer, gfx::OutOfDeviceMemory> {
+    if direct_upload {
+        device.create_mappable_buffer(
+            info,
+            gfx::MemoryUsage::UPLOAD | gfx::MemoryUsage::FAST_DEVICE_ACCESS,
+        )
+    } else {
+        device.create_buffer(info)
+    }
+}
+
+fn map_buffer(
+    device: &gfx::Device,
+    buffer: &gfx::Buffer,
+    direct_upload: bool,
+) -> Result<Option<gfx::MappedBuffer>, gfx::MapError> {
+    if direct_upload {
+        device.map_persistent(buffer.clone()).map(Some)
+    } else {
+        Ok(None)
+    }
 }
 
 const VERTEX_ALIGN_MASK: usize = 0b1111;
 const INDEX_ALIGN_MASK: usize = 0b11;
-const INDEX_TYPE: gfx::IndexType = gfx::IndexType::U32;
-const INDEX_SIZE: u32 = INDEX_TYPE.index_size() as _;
+/// Values bound instead of the vertex attributes missing in the mesh:
+/// up-facing normal, x-axis tangent with a positive bitangent sign and zero UV.
+///
+/// NOTE: Padded to keep mesh ranges aligned.
+const DEFAULT_VERTEX_ATTRIBUTES: [f32; 12] = [
+    0.0, 1.0, 0.0, // normal
+    1.0, 0.0, 0.0, 1.0, // tangent
+    0.0, 0.0, // uv0
+    0.0, 0.0, 0.0,
+];
+const DEFAULT_VERTEX_ATTRIBUTES_SIZE: u32 = std::mem::size_of::<[f32; 12]>() as u32;
+
+/// Marks offsets of the default vertex attributes which are read with
+/// a zero stride (must match `VERTEX_DEFAULT_BIT` in `object.glsl`).
+///
+/// NOTE: The vertex buffer is never larger than [`PACKED_VERTEX_ATTRIBUTE_BIT`],
+/// so the bit is always unset for offsets of the mesh data.
+const DEFAULT_VERTEX_ATTRIBUTE_BIT: u32 = 1 << 31;
+
+/// Marks offsets of the vertex attributes packed into a single `u32` per vertex
+/// (must match `VERTEX_PACKED_BIT` in `object.glsl`).
+const PACKED_VERTEX_ATTRIBUTE_BIT: u32 = 1 << 30;
+
+/// Returns the offset of the shared default value of the vertex attribute,
+/// or `None` if the attribute has no default value.
+pub(crate) fn default_vertex_attribute_offset(kind: VertexAttributeKind) -> Option<u32> {
+    let offset = match kind {
+        VertexAttributeKind::Normal => 0,
+        VertexAttributeKind::Tangent => 12,
+        VertexAttributeKind::UV0 => 28,
+        VertexAttributeKind::Position | VertexAttributeKind::Color => return None,
+    };
+    Some(offset | DEFAULT_VERTEX_ATTRIBUTE_BIT)
+}
+
+/// Fraction of the free space outside of its largest range
+/// above which mesh ranges are moved to compact the buffers.
+const DEFRAG_THRESHOLD: f32 = 0.5;
+/// Max number of ranges moved by a single defragmentation step.
+const DEFRAG_MAX_RANGES_PER_FRAME: usize = 64;
+/// Max number of bytes moved by a single defragmentation step.
+const DEFRAG_MAX_BYTES_PER_FRAME: u32 = 4 << 20;
+
+/// Returns the fraction of free space which is outside of the largest free range:
+/// zero if all free space is contiguous, close to one if it is split into
+/// many small ranges.
+fn fragmentation(alloc: &RangeAllocator<u32>) -> f32 {
+    let total_free = alloc.total_available();
+    if total_free == 0 {
+        return 0.0;
+    }
+
+    let initial_range = alloc.initial_range();
+    let mut largest_free = 0;
+    let mut free_start = initial_range.start;
+    for range in alloc.allocated_ranges() {
+        largest_free = largest_free.max(range.start - free_start);
+        free_start = range.end;
+    }
+    largest_free = largest_free.max(initial_range.end - free_start);
+
+    1.0 - largest_free as f32 / total_free as f32
+}
+
+/// Allocates a range of the same size closer to the start of the buffer.
+///
+/// Returns `None` and leaves the allocator unchanged if there is no such range.
+fn relocate_range(alloc: &mut RangeAllocator<u32>, range: &Range<u32>) -> Option<Range<u32>> {
+    let new_range = alloc.allocate_range(range.end - range.start).ok()?;
+    if new_range.start < range.start {
+        Some(new_range)
+    } else {
+        alloc.free_range(new_range);
+        None
+    }
+}
+
+/// Index ranges are allocated in words of this size, so that `u16` and `u32`
+/// indices could be stored in the same buffer.
+const INDEX_WORD_SIZE: u32 = gfx::IndexType::U32.index_size() as _;
+
+/// Returns the number of words required to store `count` indices.
+fn index_words(index_type: gfx::IndexType, count: u32) -> u32 {
+    (count * index_type.index_size() as u32).div_ceil(INDEX_WORD_SIZE)
+}
+
+/// Converts the start of the allocated words range into the range of indices.
+fn indices_in_words(index_type: gfx::IndexType, count: u32, first_word: u32) -> Range<u32> {
+    let first_index = first_word * (INDEX_WORD_SIZE / index_type.index_size() as u32);
+    first_index..first_index + count
+}
+
+/// Writes indices converted to the `index_type` and padded to whole words.
+///
+/// # Safety
+///
+/// `dst` must be aligned to 4 bytes and valid for writes of
+/// `index_words(index_type, indices.len())` words.
+unsafe fn write_indices(indices: &[u32], index_type: gfx::IndexType, dst: *mut u8) {
+    match index_type {
+        gfx::IndexType::U32 => {
+            std::ptr::copy_nonoverlapping(
+                indices.as_ptr().cast::<u8>(),
+                dst,
+                std::mem::size_of_val(indices),
+            );
+        }
+        gfx::IndexType::U16 => {
+            let words = index_words(index_type, indices.len() as u32) as usize;
+            let dst = std::slice::from_raw_parts_mut(dst.cast::<u16>(), words * 2);
+            // NOTE: Indices were checked to fit into `u16` by the mesh builder
+            for (dst, index) in dst.iter_mut().zip(indices) {
+                *dst = *index as u16;
+            }
+            if let Some(padding) = dst.get_mut(indices.len()) {
+                *padding = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn device_address_fetch_matches_storage_buffers() {
+        use glam::{Mat4, Vec3};
+
+        use crate::render_graph::materials::DebugMaterialInstance;
+        use crate::types::{CameraProjection, CubeMeshGenerator};
+        use crate::worker::capture::render_captured_frame;
+        use crate::{CapturedFrame, RendererBuilder};
+
+        fn render_cube(buffer_device_address: bool) -> CapturedFrame {
+            let builder =
+                RendererBuilder::headless(64, 64).buffer_device_address(buffer_device_address);
+            render_captured_frame(builder, |state| {
+                assert_eq!(
+                    state.is_buffer_device_address_enabled(),
+                    buffer_device_address
+                );
+
+                let mesh = Mesh::builder(CubeMeshGenerator::from_size(1.0))
+                    .build()
+                    .unwrap();
+                let mesh = state.add_mesh(&mesh).unwrap();
+                let material = state
+                    .add_material_instance(DebugMaterialInstance {
+                        color: Vec3::new(1.0, 0.5, 0.25),
+                        double_sided: false,
+                    })
+                    .unwrap();
+                state.update_camera(
+                    &Mat4::from_translation(Vec3::new(0.0, 0.5, 3.0)).inverse(),
+                    &CameraProjection::default(),
+                );
+                state
+                    .add_static_object(mesh, material, &Mat4::from_rotation_y(0.5))
+                    .unwrap()
+            })
+        }
+
+        let storage_buffers = render_cube(false);
+        let device_address = render_cube(true);
+
+        // The cube must be visible for the comparison to make sense
+        let background = storage_buffers.pixel(0, 0);
+        assert_ne!(storage_buffers.pixel(32, 32), background);
+        assert!(storage_buffers == device_address, "frames are different");
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn direct_uploads_bypass_staging_and_scatter_copies() {
+        use std::time::Duration;
+
+        use glam::{Mat4, Vec3};
+
+        use crate::render_graph::materials::DebugMaterialInstance;
+        use crate::types::CubeMeshGenerator;
+        use crate::RendererBuilder;
+
+        let renderer = RendererBuilder::headless(16, 16).build().unwrap();
+        let state = renderer.state();
+        let direct_upload = state.device.supports_direct_upload();
+
+        let cube = Mesh::builder(CubeMeshGenerator::from_size(1.0))
+            .build()
+            .unwrap();
+        let mesh = state.add_mesh(&cube).unwrap();
+        let material = state
+            .add_material_instance(DebugMaterialInstance {
+                color: Vec3::ONE,
+                double_sided: false,
+            })
+            .unwrap();
+        let object = state
+            .add_static_object(mesh, material.clone(), &Mat4::IDENTITY)
+            .unwrap();
+
+        let draw_frames = || {
+            for _ in 0..5 {
+                state.notify_draw();
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        };
+
+        // NOTE: Newly allocated buffers are filled through the scatter copy
+        draw_frames();
+        let before = state.stats();
+
+        let _mesh = state.add_mesh(&cube).unwrap();
+        let material_update = DebugMaterialInstance {
+            color: Vec3::ZERO,
+            double_sided: false,
+        };
+        state.update_material(&material, material_update).unwrap();
+        state
+            .update_static_object(&object, Mat4::from_translation(Vec3::X))
+            .unwrap();
+        draw_frames();
+        let after = state.stats();
+
+        let scatter_copies =
+            |stats: &crate::RendererStats| stats.scatter_dispatches + stats.scatter_copy_fallbacks;
+        if direct_upload {
+            assert_eq!(
+                after.meshes.direct_uploads,
+                before.meshes.direct_uploads + 1
+            );
+            assert_eq!(after.meshes.staged_uploads, before.meshes.staged_uploads);
+            assert_eq!(scatter_copies(&after), scatter_copies(&before));
+            assert_eq!(after.slots_scattered, before.slots_scattered);
+            assert!(after.slots_written_directly >= before.slots_written_directly + 2);
+        } else {
+            assert_eq!(after.meshes.direct_uploads, 0);
+            assert_eq!(
+                after.meshes.staged_uploads,
+                before.meshes.staged_uploads + 1
+            );
+            assert!(scatter_copies(&after) > scatter_copies(&before));
+            assert_eq!(after.slots_written_directly, 0);
+        }
+    }
+
+    #[test]
+    fn u16_indices_are_packed_into_words() {
+        assert_eq!(index_words(gfx::IndexType::U32, 3), 3);
+        assert_eq!(index_words(gfx::IndexType::U16, 3), 2);
+        assert_eq!(index_words(gfx::IndexType::U16, 6), 3);
+        assert_eq!(index_words(gfx::IndexType::U16, 0), 0);
+    }
+
+    #[test]
+    fn default_vertex_attributes_are_in_bounds() {
+        let read = |kind, len| {
+            let offset = default_vertex_attribute_offset(kind).unwrap();
+            assert_ne!(offset & DEFAULT_VERTEX_ATTRIBUTE_BIT, 0);
+            assert_eq!(offset & PACKED_VERTEX_ATTRIBUTE_BIT, 0);
+            let start = ((offset & !DEFAULT_VERTEX_ATTRIBUTE_BIT) / 4) as usize;
+            &DEFAULT_VERTEX_ATTRIBUTES[start..start + len]
+        };
+
+        assert_eq!(read(VertexAttributeKind::Normal, 3), [0.0, 1.0, 0.0]);
+        assert_eq!(read(VertexAttributeKind::Tangent, 4), [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(read(VertexAttributeKind::UV0, 2), [0.0, 0.0]);
+        assert!(default_vertex_attribute_offset(VertexAttributeKind::Position).is_none());
+        assert!(default_vertex_attribute_offset(VertexAttributeKind::Color).is_none());
+        assert_eq!(
+            DEFAULT_VERTEX_ATTRIBUTES_SIZE as usize & VERTEX_ALIGN_MASK,
+            0,
+            "mesh ranges must stay aligned"
+        );
+    }
+
+    #[test]
+    fn fragmentation_of_free_ranges() {
+        let mut alloc = RangeAllocator::new(0..100);
+        assert_eq!(fragmentation(&alloc), 0.0);
+
+        let a = alloc.allocate_range(25).unwrap();
+        let _b = alloc.allocate_range(25).unwrap();
+        let c = alloc.allocate_range(25).unwrap();
+        let _d = alloc.allocate_range(25).unwrap();
+        assert_eq!(fragmentation(&alloc), 0.0);
+
+        alloc.free_range(a);
+        alloc.free_range(c);
+        assert_eq!(fragmentation(&alloc), 0.5);
+    }
+
+    #[test]
+    fn ranges_are_only_moved_towards_the_start() {
+        let mut alloc = RangeAllocator::new(0..100);
+        let a = alloc.allocate_range(10).unwrap();
+        let b = alloc.allocate_range(10).unwrap();
+        let c = alloc.allocate_range(20).unwrap();
+
+        // No free space before the range
+        assert_eq!(relocate_range(&mut alloc, &b), None);
+
+        alloc.free_range(a);
+        assert_eq!(relocate_range(&mut alloc, &c), None);
+        assert_eq!(relocate_range(&mut alloc, &b), Some(0..10));
+        assert_eq!(alloc.total_available(), 60);
+    }
+
+    #[test]
+    fn defragmentation_compacts_churned_ranges() {
+        let mut rng = TestRng(0x9e37_79b9_7f4a_7c15);
+        let mut alloc = RangeAllocator::new(0..1 << 20);
+        let mut ranges = Vec::new();
+
+        // Stream meshes of random sizes in and out until the buffer is full
+        for _ in 0..20000 {
+            if ranges.is_empty() || rng.next() % 8 < 5 {
+                if let Ok(range) = alloc.allocate_range(rng.range(16..4096)) {
+                    ranges.push(range);
+                }
+            } else {
+                let index = rng.range(0..ranges.len() as u32) as usize;
+                alloc.free_range(ranges.swap_remove(index));
+            }
+        }
+        // Unload half of the scene
+        for _ in 0..ranges.len() / 2 {
+            let index = rng.range(0..ranges.len() as u32) as usize;
+            alloc.free_range(ranges.swap_remove(index));
+        }
+
+        let total_free = alloc.total_available();
+        assert!(fragmentation(&alloc) > DEFRAG_THRESHOLD);
+        assert!(alloc.allocate_range(total_free / 2).is_err());
+
+        let mut retired = Vec::new();
+        let mut frames = 0;
+        while fragmentation(&alloc) > DEFRAG_THRESHOLD {
+            assert!(frames < 1000, "defragmentation must converge");
+            frames += 1;
+
+            // NOTE: Ranges moved in the previous frame are no longer used
+            for range in retired.drain(..) {
+                alloc.free_range(range);
+            }
+
+            // NOTE: Ranges are moved from the end of the buffer like meshes
+            ranges.sort_unstable_by_key(|range| std::cmp::Reverse(range.start));
+            let mut budget = DefragBudget::default();
+            for range in &mut ranges {
+                if budget.is_exhausted() {
+                    break;
+                }
+                retired.extend(budget.relocate(&mut alloc, range, 1));
+            }
+            assert!(budget.ranges_left < DEFRAG_MAX_RANGES_PER_FRAME);
+        }
+        for range in retired.drain(..) {
+            alloc.free_range(range);
+        }
+
+        assert_eq!(alloc.total_available(), total_free);
+        ranges.sort_unstable_by_key(|range| range.start);
+        for pair in ranges.windows(2) {
+            assert!(pair[0].end <= pair[1].start, "ranges must not overlap");
+        }
+        assert!(alloc.allocate_range(total_free / 2).is_ok());
+    }
+
+    #[test]
+    fn removal_waits_for_upload() {
+        let mut uploads = UploadTracker::<u32>::default();
+
+        // Mesh was uploaded but not yet submitted
+        let epoch = uploads.recording_epoch();
+        assert!(uploads.is_pending(epoch));
+        uploads.defer_removal(123, epoch);
+        assert!(uploads.drain_removals().is_empty());
+
+        // Submitted as a part of frame 10
+        uploads.submit(10);
+        assert_eq!(uploads.recording_epoch(), epoch + 1);
+        uploads.complete(9);
+        assert!(uploads.is_pending(epoch));
+        assert!(uploads.drain_removals().is_empty());
+
+        // Frame 10 is completed
+        uploads.complete(10);
+        assert!(!uploads.is_pending(epoch));
+        assert_eq!(uploads.drain_removals(), [123]);
+        assert!(uploads.drain_removals().is_empty());
+    }
+
+    #[test]
+    fn removals_are_ordered_by_epochs() {
+        let mut uploads = UploadTracker::<u32>::default();
+
+        uploads.defer_removal(1, uploads.recording_epoch());
+        uploads.submit(0);
+        uploads.defer_removal(2, uploads.recording_epoch());
+        uploads.submit(1);
+        uploads.defer_removal(3, uploads.recording_epoch());
+
+        uploads.complete(0);
+        assert_eq!(uploads.drain_removals(), [1]);
+
+        uploads.complete(5);
+        assert_eq!(uploads.drain_removals(), [2]);
+
+        uploads.submit(6);
+        uploads.complete(6);
+        assert_eq!(uploads.drain_removals(), [3]);
+    }
+
+    /// Xorshift generator for reproducible tests.
+    struct TestRng(u64);
+
+    impl TestRng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn range(&mut self, range: Range<u32>) -> u32 {
+            range.start + (self.next() % (range.end - range.start) as u64) as u32
+        }
+    }
+}
@@ -1,9 +1,28 @@
-pub use self::material_manager::MaterialManager;
-pub use self::mesh_manager::{GpuMesh, MeshManager, MeshManagerDataGuard};
-pub use self::object_manager::{ObjectManager, GpuObject};
+pub use self::material_manager::{MaterialArchetypeStats, MaterialManager};
+pub use self::mesh_manager::{
+    GpuMesh, MeshBufferAddresses, MeshManager, MeshManagerDataGuard, MeshManagerStats,
+    StagedMesh,
+};
+pub(crate) use self::mesh_manager::default_vertex_attribute_offset;
+pub use self::object_manager::{
+    CollectTransparentObjects, GpuObjectData, GpuObjectTransform, IndexTypeCounts, ObjectBuffers,
+    ObjectManager, TransparentObject, TransparentObjectKind,
+};
+pub use self::render_target_manager::{ActiveRenderTarget, RenderTargetCamera, RenderTargetManager};
+pub use self::texture_manager::{
+    resolve_max_anisotropy, validate_cube_texture_data, validate_texture_data, GpuTexture,
+    TextureManager, TextureManagerDataGuard,
+};
+pub(crate) use self::texture_manager::validate_texture_extent;
+pub use self::texture_streamer::{
+    load_mips, validate_streamed_texture, PendingStreamedTexture, TextureStreamer,
+};
 pub use self::time_manager::TimeManager;
 
 mod material_manager;
 mod mesh_manager;
 mod object_manager;
+mod render_target_manager;
+mod texture_manager;
+mod texture_streamer;
 mod time_manager;
@@ -1,20 +1,25 @@
 use std::any::TypeId;
 use std::collections::hash_map;
+use std::hash::Hash;
+use std::ops::Range;
 
 use anyhow::Result;
-use gfx::AsStd430;
+use bumpalo::Bump;
 use glam::{Mat4, Quat, UVec4, Vec3, Vec4};
 use shared::any::AnyVec;
-use shared::packed::U32WithBool;
 use shared::FastHashMap;
 
-use crate::managers::{GpuMesh, MaterialManager, MeshManagerDataGuard};
+use crate::managers::material_manager::MaterialSlotRemap;
+use crate::managers::{
+    default_vertex_attribute_offset, GpuMesh, MaterialManager, MeshManagerDataGuard,
+};
 use crate::types::{
-    MaterialInstance, MaterialInstanceHandle, MeshHandle, ObjectData, RawDynamicObjectHandle,
-    RawStaticObjectHandle, VertexAttributeArray, VertexAttributeKind,
+    MaterialAttributePolicy, MaterialInstance, MaterialInstanceHandle, MeshHandle, ObjectData,
+    ObjectMaterials, RawDynamicObjectHandle, RawMeshHandle, RawStaticObjectHandle, Sorting,
+    VertexAttributeArray, VertexAttributeKind,
 };
 use crate::util::{
-    BindlessResources, BoundingSphere, FreelistDoubleBuffer, MultiBufferArena, ScatterCopy,
+    BindlessResources, BoundingSphere, FreelistDoubleBuffer, Frustum, ScatterCopy,
     StorageBufferHandle,
 };
 
@@ -27,6 +32,14 @@ pub struct ObjectManager {
 }
 
 impl ObjectManager {
+    pub fn static_object_count(&self) -> usize {
+        self.static_handles.len()
+    }
+
+    pub fn dynamic_object_count(&self) -> usize {
+        self.dynamic_handles.len()
+    }
+
     pub fn iter_static_objects<M: MaterialInstance>(
         &self,
     ) -> Option<StaticObjectsIter<'_, M::SupportedAttributes>> {
@@ -42,8 +55,13 @@ impl ObjectManager {
 
         Some(StaticObjectsIter {
             inner: data.iter(),
-            buffer_handle: archetype.buffer.handle(),
+            buffers: ObjectBuffers {
+                transforms: archetype.transform_buffer.handle(),
+                data: archetype.data_buffer.handle(),
+            },
+            index_types: archetype.index_types,
             slot: 0,
+            slot_count: archetype.next_slot,
             len: archetype.active_object_count,
         })
     }
@@ -63,10 +81,97 @@ impl ObjectManager {
 
         Some(DynamicObjectsIter {
             inner: data.iter(),
+            data_buffer_handle: archetype.data_buffer.handle(),
+            slot: 0,
+            slot_count: archetype.next_slot,
             len: archetype.active_object_count,
         })
     }
 
+    /// Returns static objects which have a part with the material `M`, indexed by the slot.
+    pub fn static_slot_handles<M: MaterialInstance>(&self) -> &[Option<RawStaticObjectHandle>] {
+        match self.static_archetypes.get(&TypeId::of::<M>()) {
+            Some(archetype) => &archetype.slot_handles,
+            None => &[],
+        }
+    }
+
+    /// Returns dynamic objects which have a part with the material `M`, indexed by the slot.
+    pub fn dynamic_slot_handles<M: MaterialInstance>(&self) -> &[Option<RawDynamicObjectHandle>] {
+        match self.dynamic_archetypes.get(&TypeId::of::<M>()) {
+            Some(archetype) => &archetype.slot_handles,
+            None => &[],
+        }
+    }
+
+    /// Collects visible objects of the material `M` which require blending,
+    /// sorted back-to-front by their view-space depth.
+    ///
+    /// NOTE: The list is allocated in `alloc` and is only valid for the current frame.
+    pub fn collect_transparent_objects<'a, M: MaterialInstance>(
+        &'a self,
+        material_manager: &MaterialManager,
+        args: CollectTransparentObjects<'_>,
+        alloc: &'a Bump,
+    ) -> &'a mut [TransparentObject<'a, M::SupportedAttributes>] {
+        let is_blending = |material_slot: u32| {
+            material_manager
+                .get_instance::<M>(material_slot)
+                .map_or(false, |material| material.sorting() == Sorting::BLENDING)
+        };
+        let is_visible = |bounding_sphere: &BoundingSphere| {
+            args.frustum
+                .map_or(true, |frustum| frustum.contains_sphere(bounding_sphere))
+        };
+        let is_in_layers = |layers: u32| layers & args.layer_mask != 0;
+        // NOTE: Camera looks towards -Z in the view space
+        let view_depth = |bounding_sphere: &BoundingSphere| {
+            -args.camera_view.transform_point3(bounding_sphere.center).z
+        };
+
+        let mut objects = bumpalo::collections::Vec::new_in(alloc);
+
+        for (slot, object) in self.iter_static_objects::<M>().into_iter().flatten() {
+            if !is_in_layers(object.layers)
+                || !is_blending(object.material_slot)
+                || !is_visible(&object.global_bounding_sphere)
+            {
+                continue;
+            }
+
+            objects.push(TransparentObject {
+                depth: view_depth(&object.global_bounding_sphere),
+                kind: TransparentObjectKind::Static { slot, object },
+            });
+        }
+
+        for (slot, object) in self.iter_dynamic_objects::<M>().into_iter().flatten() {
+            if !is_in_layers(object.layers) || !is_blending(object.material_slot) {
+                continue;
+            }
+
+            let transform = object.interpolated_transform(args.interpolation_factor);
+            let bounding_sphere = object.mesh_bounding_sphere.transformed(&transform);
+            if !is_visible(&bounding_sphere) {
+                continue;
+            }
+
+            objects.push(TransparentObject {
+                depth: view_depth(&bounding_sphere),
+                kind: TransparentObjectKind::Dynamic {
+                    slot,
+                    object,
+                    transform,
+                    bounding_sphere,
+                },
+            });
+        }
+
+        let objects = objects.into_bump_slice_mut();
+        objects.sort_unstable_by(|a, b| b.depth.total_cmp(&a.depth));
+        objects
+    }
+
     #[tracing::instrument(level = "debug", name = "add_static_object", skip_all)]
     pub fn add_static_object(
         &mut self,
@@ -75,19 +180,45 @@ impl ObjectManager {
         mesh_manager_data: &MeshManagerDataGuard,
         material_manager: &mut MaterialManager,
     ) {
-        let mesh = mesh_manager_data[object.mesh.index()]
-            .as_ref()
-            .expect("invalid mesh handle");
-
-        material_manager.write_static_object(
-            object.material.raw(),
-            WriteStaticObject {
-                mesh,
-                handle,
-                object,
-                object_manager: Some(self),
-            },
-        );
+        let Some(mesh) = mesh_manager_data.get(object.mesh.raw()) else {
+            tracing::error!(?handle, mesh = ?object.mesh, "invalid mesh handle");
+            return;
+        };
+
+        let ObjectData {
+            mesh: mesh_handle,
+            materials,
+            global_transform,
+            options,
+        } = *object;
+
+        let mut write_part = |submesh: Option<u32>, material: MaterialInstanceHandle| {
+            material_manager.write_static_object(
+                material.raw(),
+                WriteStaticObject {
+                    mesh,
+                    handle,
+                    part: ObjectPartData {
+                        mesh: mesh_handle.clone(),
+                        material,
+                        submesh,
+                        global_transform,
+                        layers: options.layers,
+                        cast_shadows: options.cast_shadows,
+                    },
+                    object_manager: Some(&mut *self),
+                },
+            );
+        };
+
+        match materials {
+            ObjectMaterials::Single(material) => write_part(None, material),
+            ObjectMaterials::PerSubmesh(materials) => {
+                for (submesh, material) in materials.into_iter().enumerate() {
+                    write_part(Some(submesh as u32), material);
+                }
+            }
+        }
     }
 
     #[tracing::instrument(level = "debug", name = "add_dynamic_object", skip_all)]
@@ -98,31 +229,62 @@ impl ObjectManager {
         mesh_manager_data: &MeshManagerDataGuard,
         material_manager: &mut MaterialManager,
     ) {
-        let mesh = mesh_manager_data[object.mesh.index()]
-            .as_ref()
-            .expect("invalid mesh handle");
-
-        material_manager.write_dynamic_object(
-            object.material.raw(),
-            WriteDynamicObject {
-                mesh,
-                handle,
-                object,
-                object_manager: Some(self),
-            },
-        );
+        let Some(mesh) = mesh_manager_data.get(object.mesh.raw()) else {
+            tracing::error!(?handle, mesh = ?object.mesh, "invalid mesh handle");
+            return;
+        };
+
+        let ObjectData {
+            mesh: mesh_handle,
+            materials,
+            global_transform,
+            options,
+        } = *object;
+
+        let mut write_part = |submesh: Option<u32>, material: MaterialInstanceHandle| {
+            material_manager.write_dynamic_object(
+                material.raw(),
+                WriteDynamicObject {
+                    mesh,
+                    handle,
+                    part: ObjectPartData {
+                        mesh: mesh_handle.clone(),
+                        material,
+                        submesh,
+                        global_transform,
+                        layers: options.layers,
+                        cast_shadows: options.cast_shadows,
+                    },
+                    object_manager: Some(&mut *self),
+                },
+            );
+        };
+
+        match materials {
+            ObjectMaterials::Single(material) => write_part(None, material),
+            ObjectMaterials::PerSubmesh(materials) => {
+                for (submesh, material) in materials.into_iter().enumerate() {
+                    write_part(Some(submesh as u32), material);
+                }
+            }
+        }
     }
 
     #[tracing::instrument(level = "debug", name = "update_static_object", skip_all)]
     pub fn update_static_object(&mut self, handle: RawStaticObjectHandle, transform: &Mat4) {
-        let HandleData { archetype, slot } = &self.static_handles[&handle];
+        let Some(handle_data) = self.static_handles.get(&handle) else {
+            tracing::error!(?handle, "invalid static object handle");
+            return;
+        };
 
-        let archetype = self
-            .static_archetypes
-            .get_mut(archetype)
-            .expect("invalid handle archetype");
+        for part in handle_data.parts() {
+            let archetype = self
+                .static_archetypes
+                .get_mut(&part.archetype)
+                .expect("invalid handle archetype");
 
-        (archetype.update_transform)(archetype, *slot, transform);
+            (archetype.update_transform)(archetype, part.slot, transform);
+        }
     }
 
     #[tracing::instrument(level = "debug", name = "update_dynamic_object", skip_all)]
@@ -132,62 +294,234 @@ impl ObjectManager {
         transform: &Mat4,
         teleport: bool,
     ) {
-        let HandleData { archetype, slot } = &self.dynamic_handles[&handle];
+        let Some(handle_data) = self.dynamic_handles.get(&handle) else {
+            tracing::error!(?handle, "invalid dynamic object handle");
+            return;
+        };
 
-        let archetype = self
-            .dynamic_archetypes
-            .get_mut(archetype)
-            .expect("invalid handle archetype");
+        for part in handle_data.parts() {
+            let archetype = self
+                .dynamic_archetypes
+                .get_mut(&part.archetype)
+                .expect("invalid handle archetype");
 
-        (archetype.update_transform)(archetype, *slot, transform, teleport);
+            (archetype.update_transform)(archetype, part.slot, transform, teleport);
+        }
+    }
+
+    #[tracing::instrument(level = "debug", name = "set_static_object_layers", skip_all)]
+    pub fn set_static_object_layers(&mut self, handle: RawStaticObjectHandle, layers: u32) {
+        let Some(handle_data) = self.static_handles.get(&handle) else {
+            tracing::error!(?handle, "invalid static object handle");
+            return;
+        };
+
+        for part in handle_data.parts() {
+            let archetype = self
+                .static_archetypes
+                .get_mut(&part.archetype)
+                .expect("invalid handle archetype");
+
+            (archetype.update_layers)(archetype, part.slot, layers);
+        }
+    }
+
+    #[tracing::instrument(level = "debug", name = "set_dynamic_object_layers", skip_all)]
+    pub fn set_dynamic_object_layers(&mut self, handle: RawDynamicObjectHandle, layers: u32) {
+        let Some(handle_data) = self.dynamic_handles.get(&handle) else {
+            tracing::error!(?handle, "invalid dynamic object handle");
+            return;
+        };
+
+        for part in handle_data.parts() {
+            let archetype = self
+                .dynamic_archetypes
+                .get_mut(&part.archetype)
+                .expect("invalid handle archetype");
+
+            (archetype.update_layers)(archetype, part.slot, layers);
+        }
+    }
+
+    /// Updates vertex attribute offsets, indices and bounds of all objects
+    /// which use the specified mesh.
+    #[tracing::instrument(level = "debug", name = "update_mesh_objects", skip_all)]
+    pub fn update_mesh(&mut self, handle: RawMeshHandle, mesh_manager_data: &MeshManagerDataGuard) {
+        let Some(mesh) = mesh_manager_data.get(handle) else {
+            tracing::error!(?handle, "invalid mesh handle");
+            return;
+        };
+
+        for archetype in self.static_archetypes.values_mut() {
+            (archetype.update_mesh)(archetype, handle, mesh);
+        }
+        for archetype in self.dynamic_archetypes.values_mut() {
+            (archetype.update_mesh)(archetype, handle, mesh);
+        }
+    }
+
+    /// Updates material slots of objects after the material archetypes were compacted.
+    #[tracing::instrument(level = "debug", name = "remap_material_slots", skip_all)]
+    pub fn remap_material_slots(&mut self, remaps: &[MaterialSlotRemap]) {
+        for remap in remaps {
+            if let Some(archetype) = self.static_archetypes.get_mut(&remap.archetype) {
+                (archetype.remap_material_slots)(archetype, remap);
+            }
+            if let Some(archetype) = self.dynamic_archetypes.get_mut(&remap.archetype) {
+                (archetype.remap_material_slots)(archetype, remap);
+            }
+        }
     }
 
     #[tracing::instrument(level = "debug", name = "remove_static_object", skip_all)]
     pub fn remove_static_object(&mut self, handle: RawStaticObjectHandle) {
-        let HandleData { archetype, slot } = &self.static_handles[&handle];
+        let Some(handle_data) = self.static_handles.remove(&handle) else {
+            tracing::error!(?handle, "invalid static object handle");
+            return;
+        };
 
-        let archetype = self
-            .static_archetypes
-            .get_mut(archetype)
-            .expect("invalid handle archetype");
+        for part in handle_data.parts() {
+            let archetype = self
+                .static_archetypes
+                .get_mut(&part.archetype)
+                .expect("invalid handle archetype");
 
-        (archetype.remove)(archetype, *slot);
+            set_slot_handle(&mut archetype.slot_handles, part.slot, None);
+            (archetype.remove)(archetype, part.slot);
+        }
     }
 
     #[tracing::instrument(level = "debug", name = "remove_dynamic_object", skip_all)]
     pub fn remove_dynamic_object(&mut self, handle: RawDynamicObjectHandle) {
-        let HandleData { archetype, slot } = &self.dynamic_handles[&handle];
+        let Some(handle_data) = self.dynamic_handles.remove(&handle) else {
+            tracing::error!(?handle, "invalid dynamic object handle");
+            return;
+        };
+
+        for part in handle_data.parts() {
+            let archetype = self
+                .dynamic_archetypes
+                .get_mut(&part.archetype)
+                .expect("invalid handle archetype");
 
-        let archetype = self
-            .dynamic_archetypes
-            .get_mut(archetype)
-            .expect("invalid handle archetype");
+            set_slot_handle(&mut archetype.slot_handles, part.slot, None);
+            (archetype.remove)(archetype, part.slot);
+        }
+    }
 
-        (archetype.remove)(archetype, *slot);
+    /// Returns `true` if any object data must be flushed.
+    pub fn is_dirty(&self) -> bool {
+        self.static_archetypes
+            .values()
+            .any(StaticObjectArchetype::is_dirty)
+            || self
+                .dynamic_archetypes
+                .values()
+                .any(|archetype| archetype.data_buffer.is_dirty())
     }
 
-    #[tracing::instrument(level = "debug", name = "flush_static_objects", skip_all)]
-    pub fn flush_static_objects(
+    /// Queues updated object data into the `scatter_copy` batch.
+    ///
+    /// Archetypes without updated objects are skipped.
+    ///
+    /// Returns the number of queued bytes.
+    #[tracing::instrument(level = "debug", name = "flush_objects", skip_all)]
+    pub fn flush(
         &mut self,
         device: &gfx::Device,
         encoder: &mut gfx::Encoder,
         scatter_copy: &ScatterCopy,
         bindless_resources: &BindlessResources,
-        buffers: &MultiBufferArena,
-    ) -> Result<()> {
+    ) -> Result<usize> {
+        profile_scope!("flush_objects");
+
+        let mut bytes = 0;
         for archetype in self.static_archetypes.values_mut() {
-            (archetype.flush)(
+            if !archetype.is_dirty() {
+                continue;
+            }
+            bytes += (archetype.flush)(
+                archetype,
+                FlushObjects {
+                    device,
+                    encoder: &mut *encoder,
+                    scatter_copy,
+                    bindless_resources,
+                },
+            )?;
+        }
+        for archetype in self.dynamic_archetypes.values_mut() {
+            if !archetype.data_buffer.is_dirty() {
+                continue;
+            }
+            bytes += (archetype.flush)(
                 archetype,
-                FlushStaticObject {
+                FlushObjects {
                     device,
-                    encoder,
+                    encoder: &mut *encoder,
                     scatter_copy,
                     bindless_resources,
-                    buffers,
                 },
             )?;
         }
-        Ok(())
+        Ok(bytes)
+    }
+
+    /// Returns `true` if any archetype could be shrunk by
+    /// [`shrink_sparse_archetypes`].
+    ///
+    /// [`shrink_sparse_archetypes`]: Self::shrink_sparse_archetypes
+    pub fn has_sparse_archetypes(&self) -> bool {
+        self.static_archetypes.values().any(|archetype| {
+            archetype
+                .data_buffer
+                .is_sparse(archetype.active_object_count)
+        }) || self.dynamic_archetypes.values().any(|archetype| {
+            archetype
+                .data_buffer
+                .is_sparse(archetype.active_object_count)
+        })
+    }
+
+    /// Shrinks buffers of archetypes which stayed sparse for a while,
+    /// see [`FreelistDoubleBuffer::track_occupancy`].
+    ///
+    /// Must be called once per frame before the flush.
+    ///
+    /// NOTE: Objects keep their slots, so only the slots after the last object
+    /// are released. The lowest free slots are reused first afterwards, so the
+    /// objects are eventually packed at the start of the buffers.
+    pub fn shrink_sparse_archetypes(&mut self) {
+        for archetype in self.static_archetypes.values_mut() {
+            let prev_slots = archetype.next_slot;
+            if archetype
+                .data_buffer
+                .track_occupancy(archetype.active_object_count)
+            {
+                (archetype.shrink)(archetype);
+                tracing::debug!(
+                    live = archetype.active_object_count,
+                    prev_slots,
+                    slots = archetype.next_slot,
+                    "shrunk static object slots"
+                );
+            }
+        }
+        for archetype in self.dynamic_archetypes.values_mut() {
+            let prev_slots = archetype.next_slot;
+            if archetype
+                .data_buffer
+                .track_occupancy(archetype.active_object_count)
+            {
+                (archetype.shrink)(archetype);
+                tracing::debug!(
+                    live = archetype.active_object_count,
+                    prev_slots,
+                    slots = archetype.next_slot,
+                    "shrunk dynamic object slots"
+                );
+            }
+        }
     }
 
     #[tracing::instrument(level = "debug", name = "flush_dynamic_objects", skip_all)]
@@ -205,13 +539,20 @@ impl ObjectManager {
             hash_map::Entry::Occupied(entry) => entry.into_mut(),
             hash_map::Entry::Vacant(entry) => entry.insert(StaticObjectArchetype {
                 data: AnyVec::new::<StaticSlotData<M::SupportedAttributes>>(),
-                buffer: FreelistDoubleBuffer::with_capacity(INITIAL_BUFFER_CAPACITY),
+                slot_handles: Vec::new(),
+                transform_buffer: FreelistDoubleBuffer::with_capacity(INITIAL_BUFFER_CAPACITY),
+                data_buffer: FreelistDoubleBuffer::with_capacity(INITIAL_BUFFER_CAPACITY),
                 active_object_count: 0,
+                index_types: IndexTypeCounts::default(),
                 next_slot: 0,
                 free_slots: Vec::new(),
                 flush: flush_static_object::<M::SupportedAttributes>,
                 update_transform: update_static_object_transform::<M::SupportedAttributes>,
+                update_layers: update_static_object_layers::<M::SupportedAttributes>,
+                update_mesh: update_static_object_mesh::<M>,
+                remap_material_slots: remap_static_object_material_slots::<M::SupportedAttributes>,
                 remove: remove_static_object::<M::SupportedAttributes>,
+                shrink: shrink_static_objects::<M::SupportedAttributes>,
             }),
         }
     }
@@ -224,12 +565,19 @@ impl ObjectManager {
             hash_map::Entry::Occupied(entry) => entry.into_mut(),
             hash_map::Entry::Vacant(entry) => entry.insert(DynamicObjectArchetype {
                 data: AnyVec::new::<DynamicSlotData<M::SupportedAttributes>>(),
+                slot_handles: Vec::new(),
+                data_buffer: FreelistDoubleBuffer::with_capacity(INITIAL_BUFFER_CAPACITY),
                 active_object_count: 0,
                 next_slot: 0,
                 free_slots: Vec::new(),
+                flush: flush_dynamic_object::<M::SupportedAttributes>,
                 finalize_transforms: finalize_dynamic_object_transforms::<M::SupportedAttributes>,
                 update_transform: update_dynamic_object_transform::<M::SupportedAttributes>,
+                update_layers: update_dynamic_object_layers::<M::SupportedAttributes>,
+                update_mesh: update_dynamic_object_mesh::<M>,
+                remap_material_slots: remap_dynamic_object_material_slots::<M::SupportedAttributes>,
                 remove: remove_dynamic_object::<M::SupportedAttributes>,
+                shrink: shrink_dynamic_objects::<M::SupportedAttributes>,
             }),
         }
     }
@@ -237,30 +585,108 @@ impl ObjectManager {
 
 const INITIAL_BUFFER_CAPACITY: u32 = 16;
 
-struct HandleData {
+/// Slots of all object parts in material archetypes.
+enum HandleData {
+    Single(ObjectPart),
+    /// One part for each submesh.
+    Multiple(Vec<ObjectPart>),
+}
+
+impl HandleData {
+    fn parts(&self) -> &[ObjectPart] {
+        match self {
+            Self::Single(part) => std::slice::from_ref(part),
+            Self::Multiple(parts) => parts,
+        }
+    }
+
+    fn push(&mut self, part: ObjectPart) {
+        match self {
+            Self::Single(first) => *self = Self::Multiple(vec![*first, part]),
+            Self::Multiple(parts) => parts.push(part),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ObjectPart {
     archetype: TypeId,
     slot: u32,
 }
 
+fn insert_handle_part<H: Eq + Hash>(
+    handles: &mut FastHashMap<H, HandleData>,
+    handle: H,
+    part: ObjectPart,
+) {
+    match handles.entry(handle) {
+        hash_map::Entry::Occupied(entry) => entry.into_mut().push(part),
+        hash_map::Entry::Vacant(entry) => {
+            entry.insert(HandleData::Single(part));
+        }
+    }
+}
+
+/// Part of the object which is drawn with a single material.
+struct ObjectPartData {
+    mesh: MeshHandle,
+    material: MaterialInstanceHandle,
+    /// Submesh index or `None` if the whole mesh is drawn.
+    submesh: Option<u32>,
+    global_transform: Mat4,
+    layers: u32,
+    cast_shadows: bool,
+}
+
+// NOTE: Transforms are updated much more often than the rest of the object data,
+// so they are stored in a separate buffer to avoid uploading unchanged data.
 struct StaticObjectArchetype {
     data: AnyVec,
-    buffer: FreelistDoubleBuffer,
+    /// Object which owns each slot, e.g. to find picked objects.
+    slot_handles: Vec<Option<RawStaticObjectHandle>>,
+    /// Buffer of [`GpuObjectTransform`] items.
+    transform_buffer: FreelistDoubleBuffer,
+    /// Buffer of [`GpuObjectData`] items.
+    data_buffer: FreelistDoubleBuffer,
     active_object_count: u32,
+    /// Index types of the active objects, e.g. to skip empty indirect draws.
+    index_types: IndexTypeCounts,
     next_slot: u32,
     free_slots: Vec<u32>,
-    flush: fn(&mut StaticObjectArchetype, FlushStaticObject) -> Result<()>,
+    flush: fn(&mut StaticObjectArchetype, FlushObjects) -> Result<usize>,
     update_transform: fn(&mut StaticObjectArchetype, u32, &Mat4),
+    update_layers: fn(&mut StaticObjectArchetype, u32, u32),
+    update_mesh: fn(&mut StaticObjectArchetype, RawMeshHandle, &GpuMesh),
+    remap_material_slots: fn(&mut StaticObjectArchetype, &MaterialSlotRemap),
     remove: fn(&mut StaticObjectArchetype, u32),
+    shrink: fn(&mut StaticObjectArchetype),
 }
 
+impl StaticObjectArchetype {
+    fn is_dirty(&self) -> bool {
+        self.transform_buffer.is_dirty() || self.data_buffer.is_dirty()
+    }
+}
+
+// NOTE: Interpolated transforms of dynamic objects are written each frame
+// by the render graph nodes, only the rest of the object data is persistent.
 struct DynamicObjectArchetype {
     data: AnyVec,
+    /// Object which owns each slot, e.g. to find picked objects.
+    slot_handles: Vec<Option<RawDynamicObjectHandle>>,
+    /// Buffer of [`GpuObjectData`] items.
+    data_buffer: FreelistDoubleBuffer,
     active_object_count: u32,
     next_slot: u32,
     free_slots: Vec<u32>,
+    flush: fn(&mut DynamicObjectArchetype, FlushObjects) -> Result<usize>,
     finalize_transforms: fn(&mut DynamicObjectArchetype),
     update_transform: fn(&mut DynamicObjectArchetype, u32, &Mat4, bool),
+    update_layers: fn(&mut DynamicObjectArchetype, u32, u32),
+    update_mesh: fn(&mut DynamicObjectArchetype, RawMeshHandle, &GpuMesh),
+    remap_material_slots: fn(&mut DynamicObjectArchetype, &MaterialSlotRemap),
     remove: fn(&mut DynamicObjectArchetype, u32),
+    shrink: fn(&mut DynamicObjectArchetype),
 }
 
 type StaticSlotData<A> = Option<InternalStaticObject<<A as VertexAttributeArray>::U32Array>>;
@@ -278,42 +704,42 @@ pub struct InternalStaticObject<A> {
     pub vertex_attribute_offsets: A,
     pub first_index: u32,
     pub index_count: u32,
+    pub index_type: gfx::IndexType,
     pub material_slot: u32,
+    /// Bitmask of layers in which the object is rendered.
+    pub layers: u32,
+    /// Whether the object is drawn into the shadow map.
+    pub cast_shadows: bool,
 }
 
 impl<A> InternalStaticObject<A> {
-    fn make_data(&self) -> UVec4 {
-        glam::uvec4(
-            self.first_index,
-            self.index_count,
-            self.material_slot,
-            self.enabled_object_data.is_some() as _,
-        )
+    pub fn make_gpu_transform(&self) -> GpuObjectTransform {
+        GpuObjectTransform::new(self.global_transform, self.global_bounding_sphere)
     }
-}
 
-impl<A> gfx::AsStd430 for InternalStaticObject<A>
-where
-    A: gfx::Std430,
-{
-    type Output = GpuObject<A>;
-
-    fn as_std430(&self) -> Self::Output {
-        GpuObject {
-            transform: self.global_transform,
-            transform_inverse_transpose: self.global_transform.inverse().transpose(),
-            bounding_sphere: self.global_bounding_sphere.into(),
+    pub fn make_gpu_data(&self) -> GpuObjectData<A>
+    where
+        A: gfx::Std430,
+    {
+        GpuObjectData {
             data: self.make_data(),
+            index_type: gpu_index_type(self.index_type),
             vertex_attribute_offsets: self.vertex_attribute_offsets,
         }
     }
 
-    fn write_as_std430(&self, dst: &mut Self::Output) {
-        dst.transform = self.global_transform;
-        dst.transform_inverse_transpose = self.global_transform.inverse().transpose();
-        dst.bounding_sphere = self.global_bounding_sphere.into();
-        dst.data = self.make_data();
-        dst.vertex_attribute_offsets = self.vertex_attribute_offsets;
+    fn make_data(&self) -> UVec4 {
+        glam::uvec4(
+            self.first_index,
+            self.index_count,
+            self.material_slot,
+            // NOTE: Disabled objects are not rendered in any layer
+            if self.enabled_object_data.is_some() {
+                self.layers
+            } else {
+                0
+            },
+        )
     }
 }
 
@@ -321,41 +747,31 @@ pub struct InternalDynamicObject<A> {
     pub enabled_object_data: EnabledObjectData,
     pub mesh_bounding_sphere: BoundingSphere,
 
-    pub prev_global_transform: GlobalTransform,
-    pub next_global_transform: GlobalTransform,
+    pub transform: InterpolatedTransform,
 
     pub vertex_attribute_offsets: A,
     pub first_index: u32,
-    // NOTE: `updated` flag is stored here to reduce the object size.
-    // Index is unlikely to be greater than 2^31.
-    pub index_count_and_updated: U32WithBool,
+    pub index_count: u32,
+    pub index_type: gfx::IndexType,
     pub material_slot: u32,
+    /// Bitmask of layers in which the object is rendered.
+    pub layers: u32,
+    /// Whether the object is drawn into the shadow map.
+    pub cast_shadows: bool,
 }
 
 impl<A> InternalDynamicObject<A> {
-    #[inline]
-    pub fn is_updated(&self) -> bool {
-        self.index_count_and_updated.get_bool()
-    }
-
-    #[inline]
-    pub fn index_count(&self) -> u32 {
-        self.index_count_and_updated.get_u32()
+    pub fn interpolated_transform(&self, t: f32) -> Mat4 {
+        self.transform.interpolate(t)
     }
 
-    pub fn as_interpolated_std430(&self, t: f32) -> GpuObject<A>
+    pub fn make_gpu_data(&self) -> GpuObjectData<A>
     where
         A: gfx::Std430,
     {
-        let transform = self
-            .prev_global_transform
-            .as_interpolated_matrix(&self.next_global_transform, t);
-
-        GpuObject {
-            transform_inverse_transpose: transform.inverse().transpose(),
-            bounding_sphere: self.mesh_bounding_sphere.transformed(&transform).into(),
-            transform,
+        GpuObjectData {
             data: self.make_data(),
+            index_type: gpu_index_type(self.index_type),
             vertex_attribute_offsets: self.vertex_attribute_offsets,
         }
     }
@@ -363,26 +779,106 @@ impl<A> InternalDynamicObject<A> {
     fn make_data(&self) -> UVec4 {
         glam::uvec4(
             self.first_index,
-            self.index_count(),
+            self.index_count,
             self.material_slot,
-            true as _, // NOTE: dynamic objects are always enabled if they exist
+            // NOTE: dynamic objects are always enabled if they exist
+            self.layers,
         )
     }
 }
 
+/// Object buffers used by the mesh shaders, both are indexed by the object slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectBuffers {
+    /// Buffer of [`GpuObjectTransform`] items.
+    pub transforms: StorageBufferHandle,
+    /// Buffer of [`GpuObjectData`] items.
+    pub data: StorageBufferHandle,
+}
+
+/// Frequently updated part of the object data.
+///
+/// NOTE: Must match `ObjectTransform` in `uniforms/object.glsl`.
 #[derive(Clone, Copy)]
-pub struct GpuObject<A> {
+pub struct GpuObjectTransform {
     transform: Mat4,
     transform_inverse_transpose: Mat4,
     bounding_sphere: Vec4,
+}
+
+impl GpuObjectTransform {
+    pub fn new(transform: Mat4, global_bounding_sphere: BoundingSphere) -> Self {
+        Self {
+            transform_inverse_transpose: transform.inverse().transpose(),
+            bounding_sphere: global_bounding_sphere.into(),
+            transform,
+        }
+    }
+}
+
+unsafe impl bytemuck::Pod for GpuObjectTransform {}
+unsafe impl bytemuck::Zeroable for GpuObjectTransform {}
+
+unsafe impl gfx::Std430 for GpuObjectTransform {
+    const ALIGN_MASK: usize = 0b1111;
+
+    type ArrayPadding = [u8; 0];
+}
+
+/// Part of the object data which only changes with the mesh, material or layers.
+///
+/// NOTE: Must match `ObjectData` in `uniforms/object.glsl`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct GpuObjectData<A> {
+    /// `(first_index, index_count, material_slot, layers)`
     data: UVec4,
+    /// `0` for `u16` indices, `1` for `u32` indices.
+    index_type: u32,
     vertex_attribute_offsets: A,
 }
 
-unsafe impl<A: bytemuck::Pod> bytemuck::Pod for GpuObject<A> {}
-unsafe impl<A: bytemuck::Zeroable> bytemuck::Zeroable for GpuObject<A> {}
+impl<A: gfx::Std430> GpuObjectData<A> {
+    /// Size of an item in the object data buffer.
+    pub const STRIDE: usize = gfx::align_size(
+        <Self as gfx::Std430>::ALIGN_MASK,
+        std::mem::size_of::<Self>(),
+    );
+}
+
+/// Number of objects with each index type.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IndexTypeCounts([u32; 2]);
+
+impl IndexTypeCounts {
+    /// Returns index types of at least one object.
+    pub fn iter(&self) -> impl Iterator<Item = gfx::IndexType> {
+        let counts = self.0;
+        [gfx::IndexType::U16, gfx::IndexType::U32]
+            .into_iter()
+            .filter(move |index_type| counts[gpu_index_type(*index_type) as usize] > 0)
+    }
+
+    fn add(&mut self, index_type: gfx::IndexType) {
+        self.0[gpu_index_type(index_type) as usize] += 1;
+    }
+
+    fn remove(&mut self, index_type: gfx::IndexType) {
+        self.0[gpu_index_type(index_type) as usize] -= 1;
+    }
+}
+
+fn gpu_index_type(index_type: gfx::IndexType) -> u32 {
+    match index_type {
+        gfx::IndexType::U16 => 0,
+        gfx::IndexType::U32 => 1,
+    }
+}
+
+unsafe impl<A: bytemuck::Pod> bytemuck::Pod for GpuObjectData<A> {}
+unsafe impl<A: bytemuck::Zeroable> bytemuck::Zeroable for GpuObjectData<A> {}
 
-unsafe impl<A: gfx::Std430> gfx::Std430 for GpuObject<A> {
+unsafe impl<A: gfx::Std430> gfx::Std430 for GpuObjectData<A> {
     const ALIGN_MASK: usize = 0b1111;
 
     // NOTE: may be incorrect, but `FreelistDoubleBuffer` aligns all sizes
@@ -390,8 +886,74 @@ unsafe impl<A: gfx::Std430> gfx::Std430 for GpuObject<A> {
 }
 
 pub struct EnabledObjectData {
-    pub _mesh_handle: MeshHandle,
+    pub mesh_handle: MeshHandle,
     pub _material_handle: MaterialInstanceHandle,
+    /// Submesh index or `None` if the whole mesh is drawn.
+    pub submesh: Option<u32>,
+}
+
+/// Transforms of a dynamic object at the bounds of the current fixed update interval.
+#[derive(Clone, Copy)]
+pub struct InterpolatedTransform {
+    prev: GlobalTransform,
+    next: GlobalTransform,
+    state: InterpolationState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InterpolationState {
+    /// Object was added during the current interval.
+    Added,
+    /// Object was not updated during the current interval.
+    Idle,
+    /// Object was updated during the current interval.
+    Updated,
+}
+
+impl InterpolatedTransform {
+    pub fn new(transform: &Mat4) -> Self {
+        let transform = GlobalTransform::from(*transform);
+        Self {
+            prev: transform,
+            next: transform,
+            state: InterpolationState::Added,
+        }
+    }
+
+    /// Sets the transform at the end of the current interval.
+    ///
+    /// Objects are not interpolated during the interval if they are teleported
+    /// or were added during it, so that they don't move from their initial position.
+    pub fn update(&mut self, transform: &Mat4, teleport: bool) {
+        if !teleport && self.state == InterpolationState::Idle {
+            // Update the previous transform on the first update.
+            self.prev = self.next;
+        }
+
+        self.next = GlobalTransform::from(*transform);
+        if teleport || self.state == InterpolationState::Added {
+            self.prev = self.next;
+        }
+
+        if self.state == InterpolationState::Idle {
+            self.state = InterpolationState::Updated;
+        }
+    }
+
+    /// Starts the next fixed update interval.
+    pub fn finalize(&mut self) {
+        if self.state != InterpolationState::Updated {
+            // Objects which were not updated during the interval should have
+            // their previous transform same as the next one so that they are
+            // not interpolated.
+            self.prev = self.next;
+        }
+        self.state = InterpolationState::Idle;
+    }
+
+    pub fn interpolate(&self, t: f32) -> Mat4 {
+        self.prev.as_interpolated_matrix(&self.next, t)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -423,10 +985,41 @@ impl From<Mat4> for GlobalTransform {
     }
 }
 
+pub struct CollectTransparentObjects<'a> {
+    pub camera_view: &'a Mat4,
+    /// Objects outside of the frustum are skipped if specified.
+    pub frustum: Option<&'a Frustum>,
+    pub interpolation_factor: f32,
+    /// Objects without any of these layers are skipped.
+    pub layer_mask: u32,
+}
+
+pub struct TransparentObject<'a, A: VertexAttributeArray> {
+    /// Distance from the camera plane to the bounding sphere center.
+    pub depth: f32,
+    pub kind: TransparentObjectKind<'a, A>,
+}
+
+pub enum TransparentObjectKind<'a, A: VertexAttributeArray> {
+    Static {
+        slot: u32,
+        object: &'a InternalStaticObject<A::U32Array>,
+    },
+    Dynamic {
+        slot: u32,
+        object: &'a InternalDynamicObject<A::U32Array>,
+        /// Interpolated transform for the current frame.
+        transform: Mat4,
+        bounding_sphere: BoundingSphere,
+    },
+}
+
 pub struct StaticObjectsIter<'a, A: VertexAttributeArray> {
     inner: std::slice::Iter<'a, StaticSlotData<A>>,
-    buffer_handle: StorageBufferHandle,
+    buffers: ObjectBuffers,
+    index_types: IndexTypeCounts,
     slot: u32,
+    slot_count: u32,
     len: u32,
 }
 
@@ -434,8 +1027,25 @@ impl<'a, A> StaticObjectsIter<'a, A>
 where
     A: VertexAttributeArray,
 {
-    pub fn buffer_handle(&self) -> StorageBufferHandle {
-        self.buffer_handle
+    pub fn buffers(&self) -> ObjectBuffers {
+        self.buffers
+    }
+
+    /// Returns the upper bound of all object slots.
+    ///
+    /// NOTE: Data of the removed objects is flushed without layers.
+    pub fn slot_count(&self) -> u32 {
+        self.slot_count
+    }
+
+    /// Returns the size of an item in the object data buffer.
+    pub fn data_stride(&self) -> usize {
+        GpuObjectData::<A::U32Array>::STRIDE
+    }
+
+    /// Returns index types of the meshes of all objects.
+    pub fn index_types(&self) -> IndexTypeCounts {
+        self.index_types
     }
 }
 
@@ -471,13 +1081,36 @@ impl<'a, A> ExactSizeIterator for StaticObjectsIter<'a, A> where A: VertexAttrib
 
 pub struct DynamicObjectsIter<'a, A: VertexAttributeArray> {
     inner: std::slice::Iter<'a, DynamicSlotData<A>>,
+    data_buffer_handle: StorageBufferHandle,
+    slot: u32,
+    slot_count: u32,
     len: u32,
 }
 
+impl<'a, A> DynamicObjectsIter<'a, A>
+where
+    A: VertexAttributeArray,
+{
+    /// Returns the buffer of [`GpuObjectData`] items.
+    ///
+    /// NOTE: Transforms must be written into a per-frame buffer at the same slots.
+    pub fn data_buffer_handle(&self) -> StorageBufferHandle {
+        self.data_buffer_handle
+    }
+
+    /// Returns the upper bound of all object slots.
+    pub fn slot_count(&self) -> u32 {
+        self.slot_count
+    }
+}
+
 impl<A: VertexAttributeArray> Clone for DynamicObjectsIter<'_, A> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            data_buffer_handle: self.data_buffer_handle,
+            slot: self.slot,
+            slot_count: self.slot_count,
             len: self.len,
         }
     }
@@ -487,13 +1120,17 @@ impl<'a, A> Iterator for DynamicObjectsIter<'a, A>
 where
     A: VertexAttributeArray,
 {
-    type Item = &'a InternalDynamicObject<<A as VertexAttributeArray>::U32Array>;
+    type Item = (
+        u32,
+        &'a InternalDynamicObject<<A as VertexAttributeArray>::U32Array>,
+    );
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let res = self.inner.next()?;
-            if res.is_some() {
-                break res.as_ref();
+            let slot = self.slot;
+            self.slot += 1;
+            if let Some(item) = self.inner.next()? {
+                break Some((slot, item));
             }
         }
     }
@@ -509,7 +1146,7 @@ impl<'a, A> ExactSizeIterator for DynamicObjectsIter<'a, A> where A: VertexAttri
 pub(crate) struct WriteStaticObject<'a> {
     mesh: &'a GpuMesh,
     handle: RawStaticObjectHandle,
-    object: Box<ObjectData>,
+    part: ObjectPartData,
     object_manager: Option<&'a mut ObjectManager>,
 }
 
@@ -523,12 +1160,15 @@ impl WriteStaticObject<'_> {
             material_slot,
             M::required_attributes().as_ref(),
             &M::supported_attributes(),
+            M::attribute_policy(),
             archetype,
         );
+        set_slot_handle(&mut archetype.slot_handles, slot, Some(handle));
 
-        object_manager.static_handles.insert(
+        insert_handle_part(
+            &mut object_manager.static_handles,
             handle,
-            HandleData {
+            ObjectPart {
                 archetype: TypeId::of::<M>(),
                 slot,
             },
@@ -540,60 +1180,55 @@ impl WriteStaticObject<'_> {
         material_slot: u32,
         required_attributes: &[VertexAttributeKind],
         supported_attributes: &A,
+        attribute_policy: MaterialAttributePolicy,
         archetype: &mut StaticObjectArchetype,
     ) -> u32
     where
         A: VertexAttributeArray,
     {
-        let vertex_attribute_offsets =
-            make_vertex_attribute_offsets(self.mesh, required_attributes, supported_attributes);
+        let vertex_attribute_offsets = make_vertex_attribute_offsets(
+            self.mesh,
+            required_attributes,
+            supported_attributes,
+            attribute_policy,
+        );
 
-        let indices = self.mesh.indices();
+        let indices = part_indices(self.mesh, self.part.submesh);
         let first_index = indices.start;
         let index_count = indices.end - indices.start;
 
         // Compute bounding sphere in global space
         let mesh_bounding_sphere = *self.mesh.bounding_sphere();
-        let global_bounding_sphere =
-            mesh_bounding_sphere.transformed(&self.object.global_transform);
+        let global_bounding_sphere = mesh_bounding_sphere.transformed(&self.part.global_transform);
 
         let gpu_object = InternalStaticObject::<A::U32Array> {
             enabled_object_data: Some(EnabledObjectData {
-                _mesh_handle: self.object.mesh,
-                _material_handle: self.object.material,
+                mesh_handle: self.part.mesh,
+                _material_handle: self.part.material,
+                submesh: self.part.submesh,
             }),
             mesh_bounding_sphere,
-            global_transform: self.object.global_transform,
+            global_transform: self.part.global_transform,
             global_bounding_sphere,
             vertex_attribute_offsets,
             first_index,
             index_count,
+            index_type: self.mesh.index_type(),
             material_slot,
+            layers: self.part.layers,
+            cast_shadows: self.part.cast_shadows,
         };
 
-        let slot = alloc_slot(&mut archetype.next_slot, &mut archetype.free_slots);
-
-        {
-            // SAFETY: `downcast_mut` template parameter is the same as the one used to
-            // construct `archetype`. (material -> explicit attributes)
-            let mut data = unsafe { archetype.data.downcast_mut::<StaticSlotData<A>>() };
-            if slot as usize >= data.len() {
-                let size = slot.checked_next_power_of_two().expect("too many slots");
-                data.resize_with(size as usize + 1, || None);
-            }
-            data[slot as usize] = Some(gpu_object);
-        }
-
-        archetype.buffer.update_slot(slot);
-        archetype.active_object_count += 1;
-        slot
+        // SAFETY: The archetype was constructed with the same attributes.
+        // (material -> explicit attributes)
+        unsafe { insert_static_object::<A>(archetype, gpu_object) }
     }
 }
 
 pub(crate) struct WriteDynamicObject<'a> {
     mesh: &'a GpuMesh,
     handle: RawDynamicObjectHandle,
-    object: Box<ObjectData>,
+    part: ObjectPartData,
     object_manager: Option<&'a mut ObjectManager>,
 }
 
@@ -607,12 +1242,15 @@ impl WriteDynamicObject<'_> {
             material_slot,
             M::required_attributes().as_ref(),
             &M::supported_attributes(),
+            M::attribute_policy(),
             archetype,
         );
+        set_slot_handle(&mut archetype.slot_handles, slot, Some(handle));
 
-        object_manager.dynamic_handles.insert(
+        insert_handle_part(
+            &mut object_manager.dynamic_handles,
             handle,
-            HandleData {
+            ObjectPart {
                 archetype: TypeId::of::<M>(),
                 slot,
             },
@@ -624,52 +1262,46 @@ impl WriteDynamicObject<'_> {
         material_slot: u32,
         required_attributes: &[VertexAttributeKind],
         supported_attributes: &A,
+        attribute_policy: MaterialAttributePolicy,
         archetype: &mut DynamicObjectArchetype,
     ) -> u32
     where
         A: VertexAttributeArray,
     {
-        let vertex_attribute_offsets =
-            make_vertex_attribute_offsets(self.mesh, required_attributes, supported_attributes);
+        let vertex_attribute_offsets = make_vertex_attribute_offsets(
+            self.mesh,
+            required_attributes,
+            supported_attributes,
+            attribute_policy,
+        );
 
-        let indices = self.mesh.indices();
+        let indices = part_indices(self.mesh, self.part.submesh);
         let first_index = indices.start;
         let index_count = indices.end - indices.start;
 
         // Compute bounding sphere in global space
         let mesh_bounding_sphere = *self.mesh.bounding_sphere();
 
-        let global_transform = GlobalTransform::from(self.object.global_transform);
-
         let gpu_object = InternalDynamicObject::<A::U32Array> {
             enabled_object_data: EnabledObjectData {
-                _mesh_handle: self.object.mesh,
-                _material_handle: self.object.material,
+                mesh_handle: self.part.mesh,
+                _material_handle: self.part.material,
+                submesh: self.part.submesh,
             },
             mesh_bounding_sphere,
-            prev_global_transform: global_transform,
-            next_global_transform: global_transform,
+            transform: InterpolatedTransform::new(&self.part.global_transform),
             vertex_attribute_offsets,
             first_index,
-            index_count_and_updated: U32WithBool::new(index_count, false),
+            index_count,
+            index_type: self.mesh.index_type(),
             material_slot,
+            layers: self.part.layers,
+            cast_shadows: self.part.cast_shadows,
         };
 
-        let slot = alloc_slot(&mut archetype.next_slot, &mut archetype.free_slots);
-
-        {
-            // SAFETY: `downcast_mut` template parameter is the same as the one used to
-            // construct `archetype`. (material -> explicit attributes)
-            let mut data = unsafe { archetype.data.downcast_mut::<DynamicSlotData<A>>() };
-            if slot as usize >= data.len() {
-                let size = slot.checked_next_power_of_two().expect("too many slots");
-                data.resize_with(size as usize + 1, || None);
-            }
-            data[slot as usize] = Some(gpu_object);
-        }
-
-        archetype.active_object_count += 1;
-        slot
+        // SAFETY: The archetype was constructed with the same attributes.
+        // (material -> explicit attributes)
+        unsafe { insert_dynamic_object::<A>(archetype, gpu_object) }
     }
 }
 
@@ -677,28 +1309,93 @@ fn make_vertex_attribute_offsets<A>(
     mesh: &GpuMesh,
     required_attributes: &[VertexAttributeKind],
     supported_attributes: &A,
+    attribute_policy: MaterialAttributePolicy,
 ) -> A::U32Array
 where
     A: VertexAttributeArray,
 {
-    let required_attributes_mask = required_attributes
-        .iter()
-        .fold(0u8, |mask, attribute| mask | *attribute as u8);
-    let mesh_attributes_mask = mesh
-        .attributes()
-        .fold(0u8, |mask, attribute| mask | attribute as u8);
-
-    assert_eq!(
-        mesh_attributes_mask & required_attributes_mask,
-        required_attributes_mask
-    );
-
-    supported_attributes
-        .clone()
-        .map_to_u32(|attribute| match mesh.get_attribute_range(attribute) {
-            Some(range) => range.start,
+    let use_fallbacks = attribute_policy == MaterialAttributePolicy::Fallback;
+
+    // NOTE: Mesh attributes are validated in `RendererState` before the object is added
+    debug_assert!(required_attributes.iter().all(|&attribute| {
+        mesh.get_attribute_range(attribute).is_some()
+            || (use_fallbacks && default_vertex_attribute_offset(attribute).is_some())
+    }));
+
+    supported_attributes.clone().map_to_u32(|attribute| {
+        match mesh.get_attribute_offset(attribute) {
+            Some(offset) => offset,
+            None if use_fallbacks => default_vertex_attribute_offset(attribute).unwrap_or(u32::MAX),
             None => u32::MAX,
-        })
+        }
+    })
+}
+
+/// Returns the range of indices drawn by the object part.
+fn part_indices(mesh: &GpuMesh, submesh: Option<u32>) -> Range<u32> {
+    let Some(submesh) = submesh else {
+        return mesh.indices();
+    };
+    mesh.submesh_indices(submesh as usize).unwrap_or_else(|| {
+        tracing::warn!(submesh, "mesh has no such submesh, it will not be drawn");
+        let start = mesh.indices().start;
+        start..start
+    })
+}
+
+fn set_slot_handle<H>(slot_handles: &mut Vec<Option<H>>, slot: u32, handle: Option<H>) {
+    if slot as usize >= slot_handles.len() {
+        slot_handles.resize_with(slot as usize + 1, || None);
+    }
+    slot_handles[slot as usize] = handle;
+}
+
+/// Stores the object in a free slot and marks it as updated, returns the slot.
+///
+/// # Safety
+/// - `A` must be the same type as used to construct the `archetype`.
+unsafe fn insert_static_object<A: VertexAttributeArray>(
+    archetype: &mut StaticObjectArchetype,
+    object: InternalStaticObject<A::U32Array>,
+) -> u32 {
+    let slot = alloc_slot(&mut archetype.next_slot, &mut archetype.free_slots);
+    archetype.index_types.add(object.index_type);
+
+    let mut data = archetype.data.downcast_mut::<StaticSlotData<A>>();
+    if slot as usize >= data.len() {
+        let size = slot.checked_next_power_of_two().expect("too many slots");
+        data.resize_with(size as usize + 1, || None);
+    }
+    data[slot as usize] = Some(object);
+    drop(data);
+
+    archetype.transform_buffer.update_slot(slot);
+    archetype.data_buffer.update_slot(slot);
+    archetype.active_object_count += 1;
+    slot
+}
+
+/// Stores the object in a free slot and marks it as updated, returns the slot.
+///
+/// # Safety
+/// - `A` must be the same type as used to construct the `archetype`.
+unsafe fn insert_dynamic_object<A: VertexAttributeArray>(
+    archetype: &mut DynamicObjectArchetype,
+    object: InternalDynamicObject<A::U32Array>,
+) -> u32 {
+    let slot = alloc_slot(&mut archetype.next_slot, &mut archetype.free_slots);
+
+    let mut data = archetype.data.downcast_mut::<DynamicSlotData<A>>();
+    if slot as usize >= data.len() {
+        let size = slot.checked_next_power_of_two().expect("too many slots");
+        data.resize_with(size as usize + 1, || None);
+    }
+    data[slot as usize] = Some(object);
+    drop(data);
+
+    archetype.data_buffer.update_slot(slot);
+    archetype.active_object_count += 1;
+    slot
 }
 
 fn alloc_slot(next_slot: &mut u32, free_slots: &mut Vec<u32>) -> u32 {
@@ -709,40 +1406,73 @@ fn alloc_slot(next_slot: &mut u32, free_slots: &mut Vec<u32>) -> u32 {
     })
 }
 
-struct FlushStaticObject<'a> {
+struct FlushObjects<'a> {
     device: &'a gfx::Device,
     encoder: &'a mut gfx::Encoder,
     scatter_copy: &'a ScatterCopy,
     bindless_resources: &'a BindlessResources,
-    buffers: &'a MultiBufferArena,
 }
 
 fn flush_static_object<A: VertexAttributeArray>(
     archetype: &mut StaticObjectArchetype,
-    args: FlushStaticObject,
-) -> Result<()> {
+    args: FlushObjects,
+) -> Result<usize> {
     // SAFETY: `typed_data` template parameter is the same as the one used to
     // construct `archetype`.
     let data = unsafe { archetype.data.typed_data::<StaticSlotData<A>>() };
+    let get_object = |slot: u32| data[slot as usize].as_ref().expect("invalid slot");
+
+    // SAFETY: `flush` is called with the same template parameter all the time.
+    let bytes = unsafe {
+        archetype.transform_buffer.flush::<GpuObjectTransform, _>(
+            args.device,
+            args.encoder,
+            args.scatter_copy,
+            args.bindless_resources,
+            |slot| get_object(slot).make_gpu_transform(),
+        )? + archetype
+            .data_buffer
+            .flush::<GpuObjectData<A::U32Array>, _>(
+                args.device,
+                args.encoder,
+                args.scatter_copy,
+                args.bindless_resources,
+                |slot| get_object(slot).make_gpu_data(),
+            )?
+    };
+
+    Ok(bytes)
+}
+
+fn flush_dynamic_object<A: VertexAttributeArray>(
+    archetype: &mut DynamicObjectArchetype,
+    args: FlushObjects,
+) -> Result<usize> {
+    // SAFETY: `typed_data` template parameter is the same as the one used to
+    // construct `archetype`.
+    let data = unsafe { archetype.data.typed_data::<DynamicSlotData<A>>() };
 
     // SAFETY: `flush` is called with the same template parameter all the time.
-    unsafe {
+    let bytes = unsafe {
         archetype
-            .buffer
-            .flush::<<InternalStaticObject<A::U32Array> as gfx::AsStd430>::Output, _>(
+            .data_buffer
+            .flush::<GpuObjectData<A::U32Array>, _>(
                 args.device,
                 args.encoder,
                 args.scatter_copy,
                 args.bindless_resources,
-                args.buffers,
                 |slot| {
-                    let material = data[slot as usize].as_ref().expect("invalid slot");
-                    material.as_std430()
+                    // NOTE: Removed objects are flushed if they were updated
+                    // in the same frame, their data is never read.
+                    data[slot as usize]
+                        .as_ref()
+                        .map(InternalDynamicObject::make_gpu_data)
+                        .unwrap_or_else(bytemuck::Zeroable::zeroed)
                 },
-            )?;
-    }
+            )?
+    };
 
-    Ok(())
+    Ok(bytes)
 }
 
 fn finalize_dynamic_object_transforms<A: VertexAttributeArray>(
@@ -751,17 +1481,8 @@ fn finalize_dynamic_object_transforms<A: VertexAttributeArray>(
     // SAFETY: `typed_data_mut` template parameter is the same as the one used to construct `data`.
     let data = unsafe { archetype.data.typed_data_mut::<DynamicSlotData<A>>() };
 
-    // Reset `updated` flag on each existing object.
     for item in data.iter_mut().flatten() {
-        if item.index_count_and_updated.get_bool() {
-            // Reset the flag for the next fixed update interval.
-            item.index_count_and_updated.set_bool(false);
-        } else {
-            // Objects which were not updated during the fixed update
-            // interval should have their previous transform same as the
-            // next one so that they are not interpolated.
-            item.prev_global_transform = item.next_global_transform;
-        }
+        item.transform.finalize();
     }
 }
 
@@ -776,7 +1497,7 @@ fn update_static_object_transform<A: VertexAttributeArray>(
     item.global_transform = *transform;
     item.global_bounding_sphere = item.mesh_bounding_sphere.transformed(transform);
 
-    archetype.buffer.update_slot(slot);
+    archetype.transform_buffer.update_slot(slot);
 }
 
 fn update_dynamic_object_transform<A: VertexAttributeArray>(
@@ -788,21 +1509,160 @@ fn update_dynamic_object_transform<A: VertexAttributeArray>(
     // SAFETY: `typed_data_mut` template parameter is the same as the one used to construct `data`.
     let item = unsafe { expect_data_slot_mut::<DynamicSlotData<A>>(&mut archetype.data, slot) };
 
-    if !teleport && !item.is_updated() {
-        // Update the previous transform on the first update.
-        item.prev_global_transform = item.next_global_transform;
+    item.transform.update(transform, teleport);
+}
+
+fn update_static_object_layers<A: VertexAttributeArray>(
+    archetype: &mut StaticObjectArchetype,
+    slot: u32,
+    layers: u32,
+) {
+    // SAFETY: `typed_data_mut` template parameter is the same as the one used to construct `data`.
+    let item = unsafe { expect_data_slot_mut::<StaticSlotData<A>>(&mut archetype.data, slot) };
+
+    if item.layers != layers {
+        item.layers = layers;
+        archetype.data_buffer.update_slot(slot);
+    }
+}
+
+fn update_dynamic_object_layers<A: VertexAttributeArray>(
+    archetype: &mut DynamicObjectArchetype,
+    slot: u32,
+    layers: u32,
+) {
+    // SAFETY: `typed_data_mut` template parameter is the same as the one used to construct `data`.
+    let item = unsafe { expect_data_slot_mut::<DynamicSlotData<A>>(&mut archetype.data, slot) };
+
+    if item.layers != layers {
+        item.layers = layers;
+        archetype.data_buffer.update_slot(slot);
+    }
+}
+
+fn update_static_object_mesh<M: MaterialInstance>(
+    archetype: &mut StaticObjectArchetype,
+    handle: RawMeshHandle,
+    mesh: &GpuMesh,
+) {
+    let required_attributes = M::required_attributes();
+    let supported_attributes = M::supported_attributes();
+
+    // SAFETY: `typed_data_mut` template parameter is the same as the one used to construct `data`.
+    let data = unsafe {
+        archetype
+            .data
+            .typed_data_mut::<StaticSlotData<M::SupportedAttributes>>()
+    };
+
+    for (slot, item) in data.iter_mut().enumerate() {
+        let Some(item) = item else {
+            continue;
+        };
+        let submesh = match &item.enabled_object_data {
+            Some(enabled) if enabled.mesh_handle.raw() == handle => enabled.submesh,
+            _ => continue,
+        };
+
+        let indices = part_indices(mesh, submesh);
+        item.vertex_attribute_offsets = make_vertex_attribute_offsets(
+            mesh,
+            required_attributes.as_ref(),
+            &supported_attributes,
+            M::attribute_policy(),
+        );
+        item.first_index = indices.start;
+        item.index_count = indices.end - indices.start;
+        archetype.index_types.remove(item.index_type);
+        archetype.index_types.add(mesh.index_type());
+        item.index_type = mesh.index_type();
+        item.mesh_bounding_sphere = *mesh.bounding_sphere();
+        item.global_bounding_sphere = item
+            .mesh_bounding_sphere
+            .transformed(&item.global_transform);
+
+        archetype.transform_buffer.update_slot(slot as u32);
+        archetype.data_buffer.update_slot(slot as u32);
+    }
+}
+
+fn update_dynamic_object_mesh<M: MaterialInstance>(
+    archetype: &mut DynamicObjectArchetype,
+    handle: RawMeshHandle,
+    mesh: &GpuMesh,
+) {
+    let required_attributes = M::required_attributes();
+    let supported_attributes = M::supported_attributes();
+
+    // SAFETY: `typed_data_mut` template parameter is the same as the one used to construct `data`.
+    let data = unsafe {
+        archetype
+            .data
+            .typed_data_mut::<DynamicSlotData<M::SupportedAttributes>>()
+    };
+
+    for (slot, item) in data.iter_mut().enumerate() {
+        let Some(item) = item else {
+            continue;
+        };
+        if item.enabled_object_data.mesh_handle.raw() != handle {
+            continue;
+        }
+
+        let indices = part_indices(mesh, item.enabled_object_data.submesh);
+        item.vertex_attribute_offsets = make_vertex_attribute_offsets(
+            mesh,
+            required_attributes.as_ref(),
+            &supported_attributes,
+            M::attribute_policy(),
+        );
+        item.first_index = indices.start;
+        item.index_count = indices.end - indices.start;
+        item.index_type = mesh.index_type();
+        item.mesh_bounding_sphere = *mesh.bounding_sphere();
+
+        archetype.data_buffer.update_slot(slot as u32);
     }
+}
+
+fn remap_static_object_material_slots<A: VertexAttributeArray>(
+    archetype: &mut StaticObjectArchetype,
+    remap: &MaterialSlotRemap,
+) {
+    // SAFETY: `typed_data_mut` template parameter is the same as the one used to construct `data`.
+    let data = unsafe { archetype.data.typed_data_mut::<StaticSlotData<A>>() };
+
+    for (slot, item) in data.iter_mut().enumerate() {
+        let Some(item) = item else {
+            continue;
+        };
 
-    // Update the next transform.
-    item.next_global_transform = GlobalTransform::from(*transform);
-    if teleport {
-        // Make the previous transform equal to the next one to avoid interpolation
-        // for teleported objects.
-        item.prev_global_transform = item.next_global_transform;
+        let material_slot = remap.new_slot(item.material_slot);
+        if item.material_slot != material_slot {
+            item.material_slot = material_slot;
+            archetype.data_buffer.update_slot(slot as u32);
+        }
     }
+}
+
+fn remap_dynamic_object_material_slots<A: VertexAttributeArray>(
+    archetype: &mut DynamicObjectArchetype,
+    remap: &MaterialSlotRemap,
+) {
+    // SAFETY: `typed_data_mut` template parameter is the same as the one used to construct `data`.
+    let data = unsafe { archetype.data.typed_data_mut::<DynamicSlotData<A>>() };
 
-    // Mark object as updated.
-    item.index_count_and_updated.set_bool(true);
+    for (slot, item) in data.iter_mut().enumerate() {
+        let Some(item) = item else {
+            continue;
+        };
+
+        let material_slot = remap.new_slot(item.material_slot);
+        if item.material_slot != material_slot {
+            item.material_slot = material_slot;
+            archetype.data_buffer.update_slot(slot as u32);
+        }
+    }
 }
 
 fn remove_static_object<A: VertexAttributeArray>(archetype: &mut StaticObjectArchetype, slot: u32) {
@@ -810,8 +1670,10 @@ fn remove_static_object<A: VertexAttributeArray>(archetype: &mut StaticObjectArc
     let item = unsafe { expect_data_slot_mut::<StaticSlotData<A>>(&mut archetype.data, slot) };
 
     // Set item as disabled and mark it as updated to flush the data to the GPU.
+    // NOTE: Only the layers mask is changed, so the transform is not flushed.
     item.enabled_object_data = None;
-    archetype.buffer.update_slot(slot);
+    archetype.data_buffer.update_slot(slot);
+    archetype.index_types.remove(item.index_type);
 
     // It is ok to add this slot to available, since an inserted object will
     // overwrite the stored value (including the `enabled`) during this frame,
@@ -830,6 +1692,50 @@ fn remove_dynamic_object<A: VertexAttributeArray>(
     std::mem::take(item).expect("value was not initialized");
 
     archetype.free_slots.push(slot);
+    archetype.active_object_count -= 1;
+}
+
+fn shrink_static_objects<A: VertexAttributeArray>(archetype: &mut StaticObjectArchetype) {
+    // SAFETY: `downcast_mut` template parameter is the same as the one used to
+    // construct `archetype`.
+    let mut data = unsafe { archetype.data.downcast_mut::<StaticSlotData<A>>() };
+    let len = data[..archetype.next_slot as usize]
+        .iter()
+        .rposition(|item| matches!(item, Some(item) if item.enabled_object_data.is_some()))
+        .map_or(0, |slot| slot as u32 + 1);
+    data.truncate(len as usize);
+    data.shrink_to_fit();
+    drop(data);
+
+    archetype.transform_buffer.shrink_to(len);
+    archetype.data_buffer.shrink_to(len);
+    archetype.slot_handles.truncate(len as usize);
+    shrink_free_slots(&mut archetype.next_slot, &mut archetype.free_slots, len);
+}
+
+fn shrink_dynamic_objects<A: VertexAttributeArray>(archetype: &mut DynamicObjectArchetype) {
+    // SAFETY: `downcast_mut` template parameter is the same as the one used to
+    // construct `archetype`.
+    let mut data = unsafe { archetype.data.downcast_mut::<DynamicSlotData<A>>() };
+    let len = data[..archetype.next_slot as usize]
+        .iter()
+        .rposition(Option::is_some)
+        .map_or(0, |slot| slot as u32 + 1);
+    data.truncate(len as usize);
+    data.shrink_to_fit();
+    drop(data);
+
+    archetype.data_buffer.shrink_to(len);
+    archetype.slot_handles.truncate(len as usize);
+    shrink_free_slots(&mut archetype.next_slot, &mut archetype.free_slots, len);
+}
+
+/// Forgets free slots starting from `len`.
+fn shrink_free_slots(next_slot: &mut u32, free_slots: &mut Vec<u32>, len: u32) {
+    *next_slot = len;
+    free_slots.retain(|slot| *slot < len);
+    // NOTE: Slots are popped from the end, so the lowest ones are reused first
+    free_slots.sort_unstable_by(|a, b| b.cmp(a));
 }
 
 // SAFETY: `T` must be the same type as used to construct `data`.
@@ -856,3 +1762,419 @@ impl<T> SlotDataExt for Option<T> {
         Option::as_mut(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Weak};
+
+    use super::*;
+    use crate::types::ALL_OBJECT_LAYERS;
+    use crate::util::{HandleAllocator, SimpleHandleAllocator};
+    use crate::InstructedHandleDeleter;
+
+    #[test]
+    fn gpu_object_strides_match_shader() {
+        fn stride<T: gfx::Std430>() -> usize {
+            gfx::align_size(T::ALIGN_MASK, std::mem::size_of::<T>())
+        }
+
+        // NOTE: `mat4 + mat4 + vec4` and `uvec4 + uint + uint[N]` aligned to 16 bytes
+        assert_eq!(stride::<GpuObjectTransform>(), 144);
+        assert_eq!(stride::<GpuObjectData<[u32; 1]>>(), 32);
+        assert_eq!(stride::<GpuObjectData<[u32; 5]>>(), 48);
+        assert_eq!(GpuObjectData::<[u32; 5]>::STRIDE, 48);
+    }
+
+    fn translation(x: f32) -> Mat4 {
+        Mat4::from_translation(glam::vec3(x, 0.0, 0.0))
+    }
+
+    #[track_caller]
+    fn assert_interpolated(transform: &InterpolatedTransform, from: f32, to: f32) {
+        for (t, expected) in [(0.0, from), (0.5, (from + to) * 0.5), (1.0, to)] {
+            let actual = transform.interpolate(t);
+            assert!(
+                actual.abs_diff_eq(translation(expected), 1e-5),
+                "at {t}: expected x={expected}, got {actual}"
+            );
+        }
+    }
+
+    #[test]
+    fn added_object_is_not_interpolated() {
+        let mut transform = InterpolatedTransform::new(&translation(1.0));
+        assert_interpolated(&transform, 1.0, 1.0);
+        transform.finalize();
+        assert_interpolated(&transform, 1.0, 1.0);
+    }
+
+    #[test]
+    fn added_object_updated_before_finalize_is_not_interpolated() {
+        for teleport in [false, true] {
+            let mut transform = InterpolatedTransform::new(&translation(1.0));
+            transform.update(&translation(2.0), teleport);
+            assert_interpolated(&transform, 2.0, 2.0);
+            transform.update(&translation(3.0), false);
+            assert_interpolated(&transform, 3.0, 3.0);
+
+            transform.finalize();
+            assert_interpolated(&transform, 3.0, 3.0);
+
+            // Updates after the first finalize are interpolated as usual
+            transform.update(&translation(4.0), false);
+            assert_interpolated(&transform, 3.0, 4.0);
+        }
+    }
+
+    #[test]
+    fn updated_object_is_interpolated_until_next_finalize() {
+        let mut transform = InterpolatedTransform::new(&translation(1.0));
+        transform.finalize();
+
+        transform.update(&translation(2.0), false);
+        assert_interpolated(&transform, 1.0, 2.0);
+        // Multiple updates during the interval keep the previous transform
+        transform.update(&translation(3.0), false);
+        assert_interpolated(&transform, 1.0, 3.0);
+
+        transform.finalize();
+        assert_interpolated(&transform, 1.0, 3.0);
+
+        // Object which is not updated during the next interval stops
+        transform.finalize();
+        assert_interpolated(&transform, 3.0, 3.0);
+    }
+
+    #[test]
+    fn teleport_resets_interpolation() {
+        let mut transform = InterpolatedTransform::new(&translation(1.0));
+        transform.finalize();
+
+        // Teleport after a regular update
+        transform.update(&translation(2.0), false);
+        transform.update(&translation(5.0), true);
+        assert_interpolated(&transform, 5.0, 5.0);
+        transform.finalize();
+        assert_interpolated(&transform, 5.0, 5.0);
+
+        // Regular update after a teleport is interpolated from the new position
+        transform.update(&translation(6.0), true);
+        transform.update(&translation(7.0), false);
+        assert_interpolated(&transform, 6.0, 7.0);
+        transform.finalize();
+        assert_interpolated(&transform, 6.0, 7.0);
+    }
+
+    #[test]
+    fn index_types_are_counted() {
+        let mut counts = IndexTypeCounts::default();
+        assert_eq!(counts.iter().count(), 0);
+
+        counts.add(gfx::IndexType::U32);
+        counts.add(gfx::IndexType::U32);
+        assert!(counts.iter().eq([gfx::IndexType::U32]));
+
+        counts.add(gfx::IndexType::U16);
+        assert!(counts.iter().eq([gfx::IndexType::U16, gfx::IndexType::U32]));
+
+        counts.remove(gfx::IndexType::U32);
+        counts.remove(gfx::IndexType::U16);
+        assert!(counts.iter().eq([gfx::IndexType::U32]));
+    }
+
+    type TestMaterial = crate::render_graph::materials::DebugMaterialInstance;
+    type TestAttributes = <TestMaterial as MaterialInstance>::SupportedAttributes;
+
+    /// Handles of the test objects, they don't send any instructions.
+    #[derive(Default)]
+    struct TestHandles {
+        meshes: SimpleHandleAllocator<crate::types::Mesh>,
+        materials: SimpleHandleAllocator<crate::types::MaterialInstanceTag>,
+        static_objects: SimpleHandleAllocator<crate::types::StaticObjectTag>,
+        dynamic_objects: SimpleHandleAllocator<crate::types::DynamicObjectTag>,
+    }
+
+    impl TestHandles {
+        fn deleter() -> Arc<InstructedHandleDeleter> {
+            Arc::new(InstructedHandleDeleter(Weak::new()))
+        }
+
+        fn enabled_object_data(&self) -> EnabledObjectData {
+            EnabledObjectData {
+                mesh_handle: self.meshes.alloc(Self::deleter()),
+                _material_handle: self.materials.alloc(Self::deleter()),
+                submesh: None,
+            }
+        }
+
+        fn add_static(&self, archetype: &mut StaticObjectArchetype) -> u32 {
+            let bounding_sphere = BoundingSphere {
+                center: Vec3::ZERO,
+                radius: 1.0,
+            };
+            let object = InternalStaticObject {
+                enabled_object_data: Some(self.enabled_object_data()),
+                mesh_bounding_sphere: bounding_sphere,
+                global_transform: Mat4::IDENTITY,
+                global_bounding_sphere: bounding_sphere,
+                vertex_attribute_offsets: Default::default(),
+                first_index: 0,
+                index_count: 3,
+                index_type: gfx::IndexType::U16,
+                material_slot: 0,
+                layers: ALL_OBJECT_LAYERS,
+                cast_shadows: true,
+            };
+            let handle = self.static_objects.alloc(Self::deleter()).raw();
+
+            // SAFETY: The archetype is created for `TestMaterial`.
+            let slot = unsafe { insert_static_object::<TestAttributes>(archetype, object) };
+            set_slot_handle(&mut archetype.slot_handles, slot, Some(handle));
+            slot
+        }
+
+        fn add_dynamic(&self, archetype: &mut DynamicObjectArchetype) -> u32 {
+            let object = InternalDynamicObject {
+                enabled_object_data: self.enabled_object_data(),
+                mesh_bounding_sphere: BoundingSphere {
+                    center: Vec3::ZERO,
+                    radius: 1.0,
+                },
+                transform: InterpolatedTransform::new(&Mat4::IDENTITY),
+                vertex_attribute_offsets: Default::default(),
+                first_index: 0,
+                index_count: 3,
+                index_type: gfx::IndexType::U16,
+                material_slot: 0,
+                layers: ALL_OBJECT_LAYERS,
+                cast_shadows: true,
+            };
+            let handle = self.dynamic_objects.alloc(Self::deleter()).raw();
+
+            // SAFETY: The archetype is created for `TestMaterial`.
+            let slot = unsafe { insert_dynamic_object::<TestAttributes>(archetype, object) };
+            set_slot_handle(&mut archetype.slot_handles, slot, Some(handle));
+            slot
+        }
+    }
+
+    /// Number of added objects, the ones after the first [`LIVE_OBJECTS`] are removed.
+    const ADDED_OBJECTS: u32 = 40;
+    const LIVE_OBJECTS: u32 = 10;
+    /// Slots removed before the rest of the objects, in this order.
+    const REMOVED_SLOTS: [u32; 2] = [3, 1];
+
+    #[test]
+    fn shrunk_static_objects_keep_their_slots() {
+        let handles = TestHandles::default();
+        let mut manager = ObjectManager::default();
+        let archetype = manager.get_or_create_static_object_archetype::<TestMaterial>();
+
+        for slot in 0..ADDED_OBJECTS {
+            assert_eq!(handles.add_static(archetype), slot);
+        }
+        for slot in REMOVED_SLOTS.into_iter().chain(LIVE_OBJECTS..ADDED_OBJECTS) {
+            set_slot_handle(&mut archetype.slot_handles, slot, None);
+            (archetype.remove)(archetype, slot);
+        }
+        assert_eq!(archetype.data_buffer.capacity(), 64);
+        assert!(manager.has_sparse_archetypes());
+
+        let archetype = manager.get_or_create_static_object_archetype::<TestMaterial>();
+        (archetype.shrink)(archetype);
+
+        // Slots after the last live object are released
+        // SAFETY: The archetype is created for `TestMaterial`.
+        let data = unsafe {
+            archetype
+                .data
+                .typed_data::<StaticSlotData<TestAttributes>>()
+        };
+        assert_eq!(data.len(), LIVE_OBJECTS as usize);
+        assert!(data[9].as_ref().unwrap().enabled_object_data.is_some());
+        assert!(data[3].as_ref().unwrap().enabled_object_data.is_none());
+        assert_eq!(archetype.slot_handles.len(), LIVE_OBJECTS as usize);
+        assert!(archetype.slot_handles[1].is_none());
+        assert!(archetype.slot_handles[9].is_some());
+        assert_eq!(archetype.next_slot, LIVE_OBJECTS);
+        assert_eq!(
+            archetype.transform_buffer.capacity(),
+            INITIAL_BUFFER_CAPACITY
+        );
+        assert_eq!(archetype.data_buffer.capacity(), INITIAL_BUFFER_CAPACITY);
+        assert_eq!(archetype.active_object_count, LIVE_OBJECTS - 2);
+
+        // The lowest free slots are reused first
+        assert_eq!(archetype.free_slots, [3, 1]);
+        assert_eq!(handles.add_static(archetype), 1);
+        assert_eq!(handles.add_static(archetype), 3);
+        assert_eq!(handles.add_static(archetype), LIVE_OBJECTS);
+
+        // SAFETY: The archetype is created for `TestMaterial`.
+        let data = unsafe {
+            archetype
+                .data
+                .typed_data::<StaticSlotData<TestAttributes>>()
+        };
+        assert!(data[LIVE_OBJECTS as usize].is_some());
+        assert!(archetype.slot_handles[LIVE_OBJECTS as usize].is_some());
+        assert!(!manager.has_sparse_archetypes());
+    }
+
+    #[test]
+    fn shrunk_dynamic_objects_keep_their_slots() {
+        let handles = TestHandles::default();
+        let mut manager = ObjectManager::default();
+        let archetype = manager.get_or_create_dynamic_object_archetype::<TestMaterial>();
+
+        for slot in 0..ADDED_OBJECTS {
+            assert_eq!(handles.add_dynamic(archetype), slot);
+        }
+        for slot in REMOVED_SLOTS.into_iter().chain(LIVE_OBJECTS..ADDED_OBJECTS) {
+            set_slot_handle(&mut archetype.slot_handles, slot, None);
+            (archetype.remove)(archetype, slot);
+        }
+        assert!(manager.has_sparse_archetypes());
+
+        let archetype = manager.get_or_create_dynamic_object_archetype::<TestMaterial>();
+        (archetype.shrink)(archetype);
+
+        // Slots after the last live object are released
+        // SAFETY: The archetype is created for `TestMaterial`.
+        let data = unsafe {
+            archetype
+                .data
+                .typed_data::<DynamicSlotData<TestAttributes>>()
+        };
+        assert_eq!(data.len(), LIVE_OBJECTS as usize);
+        assert!(data[9].is_some());
+        assert!(data[3].is_none());
+        assert_eq!(archetype.slot_handles.len(), LIVE_OBJECTS as usize);
+        assert_eq!(archetype.next_slot, LIVE_OBJECTS);
+        assert_eq!(archetype.data_buffer.capacity(), INITIAL_BUFFER_CAPACITY);
+        assert_eq!(archetype.active_object_count, LIVE_OBJECTS - 2);
+
+        // The lowest free slots are reused first
+        assert_eq!(archetype.free_slots, [3, 1]);
+        assert_eq!(handles.add_dynamic(archetype), 1);
+        assert_eq!(handles.add_dynamic(archetype), 3);
+        assert_eq!(handles.add_dynamic(archetype), LIVE_OBJECTS);
+
+        // SAFETY: The archetype is created for `TestMaterial`.
+        let data = unsafe {
+            archetype
+                .data
+                .typed_data::<DynamicSlotData<TestAttributes>>()
+        };
+        assert!(data[LIVE_OBJECTS as usize].is_some());
+        assert!(archetype.slot_handles[LIVE_OBJECTS as usize].is_some());
+        assert!(!manager.has_sparse_archetypes());
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn idle_scene_uploads_nothing() {
+        use std::time::Duration;
+
+        use crate::render_graph::materials::DebugMaterialInstance;
+        use crate::types::{CubeMeshGenerator, Mesh};
+        use crate::RendererBuilder;
+
+        let renderer = RendererBuilder::headless(16, 16).build().unwrap();
+        let state = renderer.state();
+
+        let mesh = Mesh::builder(CubeMeshGenerator::from_size(1.0))
+            .build()
+            .unwrap();
+        let mesh = state.add_mesh(&mesh).unwrap();
+        let material = state
+            .add_material_instance(DebugMaterialInstance {
+                color: Vec3::ONE,
+                double_sided: false,
+            })
+            .unwrap();
+        let _object = state
+            .add_static_object(mesh, material, &Mat4::IDENTITY)
+            .unwrap();
+
+        let draw_frames = || {
+            for _ in 0..5 {
+                state.notify_draw();
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        };
+
+        // NOTE: Both targets of the double buffers are written during the first frames
+        draw_frames();
+        let before = state.stats();
+        draw_frames();
+        let after = state.stats();
+
+        assert_eq!(after.scatter_dispatches, before.scatter_dispatches);
+        assert_eq!(after.scatter_copy_fallbacks, before.scatter_copy_fallbacks);
+        assert_eq!(after.slots_scattered, before.slots_scattered);
+        assert_eq!(after.slots_written_directly, before.slots_written_directly);
+        assert_eq!(after.frame_object_bytes_uploaded, 0);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn materials_are_removed_after_objects_which_use_them() {
+        use std::time::Duration;
+
+        use crate::render_graph::materials::DebugMaterialInstance;
+        use crate::types::{CubeMeshGenerator, Mesh};
+        use crate::RendererBuilder;
+
+        for drop_material_first in [true, false] {
+            let renderer = RendererBuilder::headless(16, 16).build().unwrap();
+            let state = renderer.state();
+
+            let mesh = Mesh::builder(CubeMeshGenerator::from_size(1.0))
+                .build()
+                .unwrap();
+            let mesh = state.add_mesh(&mesh).unwrap();
+            let material = state
+                .add_material_instance(DebugMaterialInstance {
+                    color: Vec3::ONE,
+                    double_sided: false,
+                })
+                .unwrap();
+            let object = state
+                .add_static_object(mesh, material.clone(), &Mat4::IDENTITY)
+                .unwrap();
+
+            let draw_frames = || {
+                for _ in 0..3 {
+                    state.notify_draw();
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+            };
+            let live_counts = || {
+                let managers = state.synced_managers.lock().unwrap();
+                (
+                    managers.object_manager.static_object_count(),
+                    managers.material_manager.live_count(),
+                )
+            };
+
+            draw_frames();
+            assert_eq!(live_counts(), (1, 1));
+
+            if drop_material_first {
+                // The object keeps using the material
+                drop(material);
+                draw_frames();
+                assert_eq!(live_counts(), (1, 1));
+                drop(object);
+            } else {
+                drop(object);
+                draw_frames();
+                assert_eq!(live_counts(), (0, 1));
+                drop(material);
+            }
+            draw_frames();
+            assert_eq!(live_counts(), (0, 0));
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::sync::Mutex;
+
+use anyhow::Result;
+use gfx::MakeImageView;
+use glam::{Mat4, UVec2};
+
+use crate::managers::TextureManager;
+use crate::types::{CameraProjection, RawTextureHandle, MAX_RENDER_TARGETS_PER_FRAME};
+use crate::util::BindlessResources;
+
+/// Offscreen targets drawn with their own cameras.
+///
+/// Targets are registered in the [`TextureManager`] with the handle of their
+/// texture, so they are sampled by materials like the regular textures.
+#[derive(Default)]
+pub struct RenderTargetManager {
+    targets: Mutex<Vec<RenderTarget>>,
+}
+
+struct RenderTarget {
+    handle: RawTextureHandle,
+    format: gfx::Format,
+    /// Extent requested by [`RenderTargetManager::resize`].
+    extent: UVec2,
+    /// Current view of the target, it is recreated with the requested extent
+    /// before the target is drawn.
+    view: gfx::ImageView,
+    /// `None` until the camera is set, such targets are not drawn.
+    camera: Option<RenderTargetCamera>,
+}
+
+/// Camera of a render target.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderTargetCamera {
+    pub view: Mat4,
+    pub projection: CameraProjection,
+}
+
+/// A render target which must be drawn in the current frame.
+pub struct ActiveRenderTarget {
+    pub handle: RawTextureHandle,
+    pub image: gfx::Image,
+    pub camera: RenderTargetCamera,
+}
+
+impl RenderTargetManager {
+    /// Creates an image which can be both drawn into and sampled.
+    ///
+    /// NOTE: The image is transitioned to the sampled layout with the texture
+    /// uploads, so it can be sampled before it is drawn for the first time.
+    pub fn create_view(
+        &self,
+        queue: &gfx::Queue,
+        texture_manager: &TextureManager,
+        extent: UVec2,
+        format: gfx::Format,
+    ) -> Result<gfx::ImageView> {
+        let view = make_target_view(queue.device(), extent, format)?;
+
+        texture_manager.record_uploads(queue, |encoder| {
+            encoder.transition_image(
+                &view.info().image,
+                gfx::ImageLayout::ShaderReadOnlyOptimal,
+                gfx::PipelineStageFlags::TOP_OF_PIPE
+                    ..gfx::PipelineStageFlags::VERTEX_SHADER
+                        | gfx::PipelineStageFlags::FRAGMENT_SHADER,
+                gfx::AccessFlags::empty()..gfx::AccessFlags::SHADER_READ,
+            );
+        })?;
+
+        Ok(view)
+    }
+
+    pub fn add(&self, handle: RawTextureHandle, view: gfx::ImageView) {
+        let info = view.info().image.info();
+        let target = RenderTarget {
+            handle,
+            format: info.format,
+            extent: info.extent.into(),
+            view,
+            camera: None,
+        };
+        self.targets.lock().unwrap().push(target);
+    }
+
+    /// Returns `true` if there is at least one render target.
+    pub fn has_targets(&self) -> bool {
+        !self.targets.lock().unwrap().is_empty()
+    }
+
+    /// Sets the camera of the target, returns `false` for unknown handles.
+    pub fn set_camera(&self, handle: RawTextureHandle, camera: RenderTargetCamera) -> bool {
+        self.with_target(handle, |target| target.camera = Some(camera))
+    }
+
+    /// Returns the format of the target, or `None` for unknown handles.
+    pub fn format(&self, handle: RawTextureHandle) -> Option<gfx::Format> {
+        let targets = self.targets.lock().unwrap();
+        let target = targets.iter().find(|target| target.handle == handle)?;
+        Some(target.format)
+    }
+
+    /// Requests a new extent of the target, returns `false` for unknown handles.
+    ///
+    /// NOTE: The image is recreated before the target is drawn.
+    pub fn resize(&self, handle: RawTextureHandle, extent: UVec2) -> bool {
+        self.with_target(handle, |target| target.extent = extent)
+    }
+
+    /// Forgets the target, returns `false` if the texture was not a render target.
+    ///
+    /// NOTE: The image is retired along with the texture.
+    pub fn remove(&self, handle: RawTextureHandle) -> bool {
+        let mut targets = self.targets.lock().unwrap();
+        let len = targets.len();
+        targets.retain(|target| target.handle != handle);
+        targets.len() != len
+    }
+
+    /// Returns up to [`MAX_RENDER_TARGETS_PER_FRAME`] targets with cameras
+    /// in the order they were added.
+    ///
+    /// Images of the resized targets are recreated, the previous images
+    /// are retired after the `frame` is completed.
+    pub fn prepare_frame(
+        &self,
+        device: &gfx::Device,
+        texture_manager: &TextureManager,
+        bindless_resources: &BindlessResources,
+        frame: u32,
+    ) -> Result<Vec<ActiveRenderTarget>> {
+        let mut targets = self.targets.lock().unwrap();
+
+        let mut active = Vec::new();
+        for target in targets.iter_mut() {
+            let Some(camera) = target.camera else {
+                continue;
+            };
+            if active.len() == MAX_RENDER_TARGETS_PER_FRAME {
+                break;
+            }
+
+            // NOTE: The new image is drawn before it is sampled in this frame
+            if UVec2::from(target.view.info().image.info().extent) != target.extent {
+                tracing::debug!(handle = ?target.handle, extent = ?target.extent, "resizing render target");
+                let view = make_target_view(device, target.extent, target.format)?;
+                texture_manager.replace_view(
+                    device,
+                    bindless_resources,
+                    target.handle,
+                    view.clone(),
+                    frame,
+                );
+                target.view = view;
+            }
+
+            active.push(ActiveRenderTarget {
+                handle: target.handle,
+                image: target.view.info().image.clone(),
+                camera,
+            });
+        }
+        Ok(active)
+    }
+
+    fn with_target(&self, handle: RawTextureHandle, f: impl FnOnce(&mut RenderTarget)) -> bool {
+        let mut targets = self.targets.lock().unwrap();
+        match targets.iter_mut().find(|target| target.handle == handle) {
+            Some(target) => {
+                f(target);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn make_target_view(
+    device: &gfx::Device,
+    extent: UVec2,
+    format: gfx::Format,
+) -> Result<gfx::ImageView> {
+    let view = device
+        .create_image(gfx::ImageInfo {
+            extent: extent.into(),
+            format,
+            mip_levels: 1,
+            samples: gfx::Samples::_1,
+            array_layers: 1,
+            usage: gfx::ImageUsageFlags::COLOR_ATTACHMENT | gfx::ImageUsageFlags::SAMPLED,
+            flags: gfx::ImageCreateFlags::empty(),
+            label: Some("render target"),
+        })?
+        .make_image_view(device)?;
+    Ok(view)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RendererBuilder;
+
+    const CAMERA: RenderTargetCamera = RenderTargetCamera {
+        view: Mat4::IDENTITY,
+        projection: CameraProjection::Custom(Mat4::IDENTITY),
+    };
+    const FORMAT: gfx::Format = gfx::Format::RGBA8Unorm;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn targets_with_cameras_are_drawn_in_order_up_to_the_cap() {
+        let renderer = RendererBuilder::headless(4, 4).build().unwrap();
+        let state = renderer.state();
+        let manager = &state.render_targets;
+        let prepare_frame = |frame| {
+            let active = manager
+                .prepare_frame(
+                    &state.device,
+                    &state.texture_manager,
+                    &state.bindless_resources,
+                    frame,
+                )
+                .unwrap();
+            active
+                .iter()
+                .map(|target| target.handle)
+                .collect::<Vec<_>>()
+        };
+
+        let targets = (0..MAX_RENDER_TARGETS_PER_FRAME + 2)
+            .map(|_| state.add_render_target(UVec2::splat(4), FORMAT).unwrap())
+            .collect::<Vec<_>>();
+        let raw = |index: usize| targets[index].texture().raw();
+        assert!(manager.has_targets());
+
+        // Targets without cameras are not drawn
+        assert!(prepare_frame(0).is_empty());
+
+        // The order of cameras doesn't matter
+        for index in (1..targets.len()).rev() {
+            assert!(manager.set_camera(raw(index), CAMERA));
+        }
+        let expected = (1..=MAX_RENDER_TARGETS_PER_FRAME)
+            .map(raw)
+            .collect::<Vec<_>>();
+        assert_eq!(prepare_frame(1), expected);
+
+        // Removed targets make room for the next ones
+        assert!(manager.remove(raw(1)));
+        assert!(!manager.remove(raw(1)));
+        assert!(!manager.set_camera(raw(1), CAMERA));
+        assert!(!manager.resize(raw(1), UVec2::splat(8)));
+        assert_eq!(manager.format(raw(1)), None);
+
+        let expected = (2..=MAX_RENDER_TARGETS_PER_FRAME + 1)
+            .map(raw)
+            .collect::<Vec<_>>();
+        assert_eq!(prepare_frame(2), expected);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn resized_targets_move_to_new_slots() {
+        let renderer = RendererBuilder::headless(4, 4).build().unwrap();
+        let state = renderer.state();
+        let manager = &state.render_targets;
+        let texture_manager = &state.texture_manager;
+        let bindless_resources = &state.bindless_resources;
+        let prepare_frame = |frame| {
+            manager
+                .prepare_frame(&state.device, texture_manager, bindless_resources, frame)
+                .unwrap()
+        };
+
+        let target = state.add_render_target(UVec2::splat(4), FORMAT).unwrap();
+        let handle = target.texture().raw();
+        let bindless_index = || texture_manager.lock_data().bindless_index(handle).unwrap();
+        assert!(manager.set_camera(handle, CAMERA));
+        assert_eq!(manager.format(handle), Some(FORMAT));
+
+        let index = bindless_index();
+        texture_manager.take_moved_slots();
+        let images = bindless_resources.stats().images;
+
+        // The image is recreated only when the target is drawn
+        assert!(manager.resize(handle, UVec2::new(8, 2)));
+        assert_eq!(bindless_index(), index);
+
+        let active = prepare_frame(10);
+        assert_eq!(UVec2::from(active[0].image.info().extent), UVec2::new(8, 2));
+        assert_ne!(bindless_index(), index);
+        assert!(texture_manager.take_moved_slots());
+
+        // The previous slot is kept until the frame of the resize is completed
+        assert_eq!(bindless_resources.stats().images.live, images.live + 1);
+        texture_manager.complete_removals(10, bindless_resources);
+        assert_eq!(bindless_resources.stats().images.live, images.live);
+
+        // Targets with the requested extent keep their images
+        let index = bindless_index();
+        prepare_frame(11);
+        assert_eq!(bindless_index(), index);
+        assert!(!texture_manager.take_moved_slots());
+    }
+}
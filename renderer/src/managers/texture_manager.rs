@@ -0,0 +1,779 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::Result;
+use glam::UVec2;
+use shared::FastHashMap;
+
+use crate::types::{RawTextureHandle, SamplerOverride, TextureTag};
+use crate::util::{BindlessResources, ResourceRegistry, SampledImageHandle};
+
+pub struct TextureManager {
+    /// Sampler of all textures, see [`TextureManager::set_max_anisotropy`].
+    sampler: Mutex<gfx::Sampler>,
+    registry: Mutex<ResourceRegistry<TextureTag, GpuTexture>>,
+    /// Slots of the textures sampled with samplers overridden by materials,
+    /// see [`TextureManagerDataGuard::bindless_index_with_sampler`].
+    sampler_overrides: Mutex<FastHashMap<SamplerOverrideKey, GpuTexture>>,
+    skybox: Mutex<Option<GpuTexture>>,
+    encoder: Mutex<Option<gfx::PrimaryEncoder>>,
+    retired: Mutex<Vec<(GpuTexture, u32)>>,
+    /// Whether any texture was moved to another bindless slot since
+    /// the last [`TextureManager::take_moved_slots`].
+    moved_slots: AtomicBool,
+    /// Opaque white texture sampled instead of unknown textures.
+    ///
+    /// NOTE: Always `Some` after [`TextureManager::new`].
+    default_texture: Option<GpuTexture>,
+}
+
+impl TextureManager {
+    /// Creates a manager which samples textures with the specified anisotropy,
+    /// see [`resolve_max_anisotropy`].
+    ///
+    /// NOTE: The upload of the default texture is recorded into the pending uploads.
+    pub fn new(
+        queue: &gfx::Queue,
+        bindless_resources: &BindlessResources,
+        max_anisotropy: Option<f32>,
+    ) -> Result<Self> {
+        let sampler = make_sampler(queue.device(), max_anisotropy)?;
+
+        let mut manager = Self {
+            sampler: Mutex::new(sampler),
+            registry: Mutex::default(),
+            sampler_overrides: Mutex::default(),
+            skybox: Mutex::default(),
+            encoder: Mutex::default(),
+            retired: Mutex::default(),
+            moved_slots: AtomicBool::new(false),
+            default_texture: None,
+        };
+        let default_texture = manager.upload_texture(
+            queue,
+            bindless_resources,
+            &DEFAULT_TEXTURE_DATA,
+            UVec2::ONE,
+            gfx::Format::RGBA8Unorm,
+        )?;
+        manager.default_texture = Some(default_texture);
+        Ok(manager)
+    }
+
+    /// Returns an index of the texture sampled instead of unknown textures
+    /// in the bindless sampled images array.
+    pub fn default_bindless_index(&self) -> u32 {
+        self.default_texture
+            .as_ref()
+            .expect("default texture is uploaded on creation")
+            .bindless_index()
+    }
+
+    /// Returns the maximum anisotropy of the texture sampler.
+    pub fn max_anisotropy(&self) -> Option<f32> {
+        self.sampler.lock().unwrap().info().max_anisotropy
+    }
+
+    /// Replaces the sampler of all textures keeping their views.
+    ///
+    /// NOTE: The slots of the previous sampler could still be used by the frames
+    /// in flight, so the views are registered in new slots and the previous ones
+    /// are retired in the same way as removed textures. Textures sampled with
+    /// overridden samplers keep their slots.
+    /// Materials must be flushed again, see [`TextureManager::take_moved_slots`].
+    pub fn set_max_anisotropy(
+        &self,
+        device: &gfx::Device,
+        bindless_resources: &BindlessResources,
+        max_anisotropy: Option<f32>,
+        frame: u32,
+    ) -> Result<()> {
+        let sampler = make_sampler(device, max_anisotropy)?;
+
+        // NOTE: The registry is locked first to keep the order of `replace_view`
+        let mut registry = self.registry.lock().unwrap();
+        let mut skybox = self.skybox.lock().unwrap();
+        {
+            let mut current = self.sampler.lock().unwrap();
+            if current.info() == sampler.info() {
+                return Ok(());
+            }
+            *current = sampler;
+        }
+
+        // NOTE: The default texture is a single texel, so its sampler doesn't matter
+        let mut retired = self.retired.lock().unwrap();
+        for texture in registry.values_mut().chain(skybox.as_mut()) {
+            let new = self.register_view(device, bindless_resources, texture.view.clone());
+            retired.push((std::mem::replace(texture, new), frame));
+        }
+        self.moved_slots.store(true, Ordering::Release);
+
+        tracing::debug!(?max_anisotropy, "replaced texture sampler");
+        Ok(())
+    }
+
+    pub fn lock_data(&self) -> TextureManagerDataGuard<'_> {
+        TextureManagerDataGuard {
+            registry: self.registry.lock().unwrap(),
+            sampler_overrides: &self.sampler_overrides,
+            default_index: self.default_bindless_index(),
+        }
+    }
+
+    /// Takes the encoder with all pending uploads.
+    ///
+    /// NOTE: The returned commands must be executed before any draw which
+    /// samples the uploaded textures.
+    pub fn drain(&self) -> Option<gfx::PrimaryEncoder> {
+        self.encoder.lock().unwrap().take()
+    }
+
+    /// Uploads the image data through a staging buffer and registers the view
+    /// in the bindless descriptor set.
+    ///
+    /// NOTE: `data` must be checked with [`validate_texture_data`].
+    #[tracing::instrument(level = "debug", name = "upload_texture", skip_all)]
+    pub fn upload_texture(
+        &self,
+        queue: &gfx::Queue,
+        bindless_resources: &BindlessResources,
+        data: &[u8],
+        extent: UVec2,
+        format: gfx::Format,
+    ) -> Result<GpuTexture> {
+        debug_assert!(validate_texture_data(data, extent, format).is_ok());
+        self.upload_layers(queue, bindless_resources, &[data], extent, format, false)
+    }
+
+    /// Uploads six faces of a cube map and registers its cube view
+    /// in the bindless descriptor set.
+    ///
+    /// Faces are in the `+X, -X, +Y, -Y, +Z, -Z` order.
+    ///
+    /// NOTE: `faces` must be checked with [`validate_cube_texture_data`].
+    #[tracing::instrument(level = "debug", name = "upload_cube_texture", skip_all)]
+    pub fn upload_cube_texture(
+        &self,
+        queue: &gfx::Queue,
+        bindless_resources: &BindlessResources,
+        faces: [&[u8]; 6],
+        size: u32,
+        format: gfx::Format,
+    ) -> Result<GpuTexture> {
+        debug_assert!(validate_cube_texture_data(faces, size, format).is_ok());
+        self.upload_layers(
+            queue,
+            bindless_resources,
+            &faces,
+            UVec2::splat(size),
+            format,
+            true,
+        )
+    }
+
+    fn upload_layers(
+        &self,
+        queue: &gfx::Queue,
+        bindless_resources: &BindlessResources,
+        layers: &[&[u8]],
+        extent: UVec2,
+        format: gfx::Format,
+        cube: bool,
+    ) -> Result<GpuTexture> {
+        let device = queue.device();
+
+        let (flags, view_type) = if cube {
+            (
+                gfx::ImageCreateFlags::CUBE_COMPATIBLE,
+                gfx::ImageViewType::Cube,
+            )
+        } else {
+            (gfx::ImageCreateFlags::empty(), gfx::ImageViewType::D2)
+        };
+
+        let image = device.create_image(gfx::ImageInfo {
+            extent: extent.into(),
+            format,
+            mip_levels: 1,
+            samples: gfx::Samples::_1,
+            array_layers: layers.len() as u32,
+            usage: gfx::ImageUsageFlags::TRANSFER_DST | gfx::ImageUsageFlags::SAMPLED,
+            flags,
+            label: Some(if cube { "cube texture" } else { "texture" }),
+        })?;
+        let view = device.create_image_view(gfx::ImageViewInfo {
+            ty: view_type,
+            ..gfx::ImageViewInfo::new(image.clone())
+        })?;
+
+        self.record_uploads(queue, |encoder| {
+            (0..).zip(layers).try_for_each(|(layer, data)| {
+                encoder.upload_image(
+                    &image,
+                    gfx::ImageSubresourceLayers::color(0, layer..layer + 1),
+                    data,
+                    gfx::ImageLayout::ShaderReadOnlyOptimal,
+                    device,
+                )
+            })
+        })??;
+
+        Ok(self.register_view(device, bindless_resources, view))
+    }
+
+    /// Records commands into the encoder with all pending uploads.
+    pub fn record_uploads<R>(
+        &self,
+        queue: &gfx::Queue,
+        f: impl FnOnce(&mut gfx::PrimaryEncoder) -> R,
+    ) -> Result<R> {
+        let mut encoder = self.encoder.lock().unwrap();
+        let encoder = match &mut *encoder {
+            Some(encoder) => encoder,
+            encoder => encoder.insert(queue.create_primary_encoder()?),
+        };
+        Ok(f(encoder))
+    }
+
+    /// Registers the view in the bindless descriptor set.
+    pub fn register_view(
+        &self,
+        device: &gfx::Device,
+        bindless_resources: &BindlessResources,
+        view: gfx::ImageView,
+    ) -> GpuTexture {
+        let bindless_handle = bindless_resources.alloc_image(device, view.clone(), self.sampler());
+
+        GpuTexture {
+            view,
+            bindless_handle,
+        }
+    }
+
+    /// Replaces the view of the texture, registering it in a new bindless slot.
+    ///
+    /// NOTE: The previous view and its slot could still be used by the frames
+    /// in flight, so they are retired in the same way as removed textures.
+    /// Materials must be flushed again, see [`TextureManager::take_moved_slots`].
+    pub fn replace_view(
+        &self,
+        device: &gfx::Device,
+        bindless_resources: &BindlessResources,
+        handle: RawTextureHandle,
+        view: gfx::ImageView,
+        frame: u32,
+    ) {
+        let mut registry = self.registry.lock().unwrap();
+        let Some(texture) = registry.get_mut(handle) else {
+            tracing::error!(?handle, "tried to replace a view using a stale handle");
+            return;
+        };
+
+        let new = self.register_view(device, bindless_resources, view);
+        let prev = std::mem::replace(texture, new);
+        self.retired.lock().unwrap().push((prev, frame));
+        self.retire_sampler_overrides(handle, frame);
+        self.moved_slots.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if any texture was moved to another bindless slot
+    /// since the previous [`take_moved_slots`].
+    ///
+    /// [`take_moved_slots`]: Self::take_moved_slots
+    pub fn has_moved_slots(&self) -> bool {
+        self.moved_slots.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if any texture was moved to another bindless slot
+    /// since the previous call.
+    ///
+    /// NOTE: Materials store bindless indices of their textures, so they
+    /// must be flushed again after the slots are moved.
+    pub fn take_moved_slots(&self) -> bool {
+        self.moved_slots.swap(false, Ordering::AcqRel)
+    }
+
+    fn sampler(&self) -> gfx::Sampler {
+        self.sampler.lock().unwrap().clone()
+    }
+
+    pub fn add(&self, handle: RawTextureHandle, texture: GpuTexture) {
+        self.registry.lock().unwrap().insert(handle, texture);
+    }
+
+    /// Replaces the cube texture sampled by the skybox pass.
+    ///
+    /// NOTE: The previous skybox is retired in the same way as removed textures.
+    pub fn set_skybox(&self, texture: Option<GpuTexture>, frame: u32) {
+        let prev = std::mem::replace(&mut *self.skybox.lock().unwrap(), texture);
+        if let Some(prev) = prev {
+            self.retired.lock().unwrap().push((prev, frame));
+        }
+    }
+
+    /// Returns an index of the skybox cube texture in the bindless sampled images array.
+    pub fn skybox_bindless_index(&self) -> Option<u32> {
+        let skybox = self.skybox.lock().unwrap();
+        Some(skybox.as_ref()?.bindless_handle.index())
+    }
+
+    /// Removes the texture from the registry.
+    ///
+    /// NOTE: The texture could still be sampled by the frames in flight,
+    /// so it is destroyed only after the `frame` is completed.
+    pub fn remove(&self, handle: RawTextureHandle, frame: u32) {
+        let Some(texture) = self.registry.lock().unwrap().remove(handle) else {
+            tracing::error!(?handle, "tried to remove a texture using a stale handle");
+            return;
+        };
+
+        self.retired.lock().unwrap().push((texture, frame));
+        self.retire_sampler_overrides(handle, frame);
+    }
+
+    /// Retires slots of the texture with overridden samplers,
+    /// they are registered again when the materials are flushed.
+    fn retire_sampler_overrides(&self, handle: RawTextureHandle, frame: u32) {
+        let mut overrides = self.sampler_overrides.lock().unwrap();
+        let keys = overrides
+            .keys()
+            .filter(|(texture, _)| *texture == handle)
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut retired = self.retired.lock().unwrap();
+        for key in keys {
+            let texture = overrides.remove(&key).unwrap();
+            retired.push((texture, frame));
+        }
+    }
+
+    /// Destroys all textures which were removed or replaced up to the `frame` (inclusive).
+    pub fn complete_removals(&self, frame: u32, bindless_resources: &BindlessResources) {
+        self.retired
+            .lock()
+            .unwrap()
+            .retain(|(texture, removed_at)| {
+                if *removed_at > frame {
+                    return true;
+                }
+                bindless_resources.free_image(texture.bindless_handle);
+                false
+            });
+    }
+}
+
+pub struct TextureManagerDataGuard<'a> {
+    registry: MutexGuard<'a, ResourceRegistry<TextureTag, GpuTexture>>,
+    sampler_overrides: &'a Mutex<FastHashMap<SamplerOverrideKey, GpuTexture>>,
+    default_index: u32,
+}
+
+impl TextureManagerDataGuard<'_> {
+    /// Returns an index of the texture in the bindless sampled images array.
+    pub fn bindless_index(&self, handle: RawTextureHandle) -> Option<u32> {
+        let texture = self.registry.get(handle)?;
+        Some(texture.bindless_handle.index())
+    }
+
+    /// Returns an index of the texture sampled with the overridden `sampler`
+    /// in the bindless sampled images array.
+    ///
+    /// The view is registered in a new slot on the first use of the sampler.
+    /// Falls back to the default sampler if the new one can't be created.
+    pub fn bindless_index_with_sampler(
+        &self,
+        device: &gfx::Device,
+        bindless_resources: &BindlessResources,
+        handle: RawTextureHandle,
+        sampler: SamplerOverride,
+    ) -> Option<u32> {
+        let texture = self.registry.get(handle)?;
+
+        let key = (handle, sampler.max_anisotropy.map(f32::to_bits));
+        let mut overrides = self.sampler_overrides.lock().unwrap();
+        if let Some(texture) = overrides.get(&key) {
+            return Some(texture.bindless_index());
+        }
+
+        let max_anisotropy = resolve_max_anisotropy(
+            sampler.max_anisotropy,
+            device.is_feature_enabled(gfx::DeviceFeature::SamplerAnisotropy),
+            device.limits().max_sampler_anisotropy,
+        );
+        let sampler = match make_sampler(device, max_anisotropy) {
+            Ok(sampler) => sampler,
+            Err(e) => {
+                tracing::error!(?handle, "failed to create texture sampler: {e}");
+                return Some(texture.bindless_index());
+            }
+        };
+
+        let bindless_handle = bindless_resources.alloc_image(device, texture.view.clone(), sampler);
+        overrides.insert(
+            key,
+            GpuTexture {
+                view: texture.view.clone(),
+                bindless_handle,
+            },
+        );
+        Some(bindless_handle.index())
+    }
+
+    /// Returns an index of the default texture, see [`TextureManager::default_bindless_index`].
+    pub fn default_bindless_index(&self) -> u32 {
+        self.default_index
+    }
+}
+
+pub struct GpuTexture {
+    // NOTE: The view keeps the image alive while it is used by the descriptor set
+    view: gfx::ImageView,
+    bindless_handle: SampledImageHandle,
+}
+
+impl GpuTexture {
+    /// Returns an index of the texture in the bindless sampled images array.
+    pub fn bindless_index(&self) -> u32 {
+        self.bindless_handle.index()
+    }
+}
+
+/// Checks that `data` contains tightly packed texels of the specified `format`.
+///
+/// Returns the reason why the texture can't be uploaded.
+pub fn validate_texture_data(
+    data: &[u8],
+    extent: UVec2,
+    format: gfx::Format,
+) -> Result<(), String> {
+    validate_texture_extent(extent, format)?;
+    validate_mip_data(data, extent, format)
+}
+
+/// Checks that the texture of the `format` can have the `extent`.
+///
+/// NOTE: The base level of block-compressed textures must consist of whole blocks,
+/// smaller mip levels are padded instead.
+pub(crate) fn validate_texture_extent(extent: UVec2, format: gfx::Format) -> Result<(), String> {
+    if !format.is_color() {
+        return Err(format!("unsupported texture format: {format:?}"));
+    }
+    if extent.x == 0 || extent.y == 0 {
+        return Err("texture extent must not be empty".to_owned());
+    }
+
+    let block = format.description().block;
+    if !block.is_aligned(extent) {
+        return Err(format!(
+            "texture extent {}x{} is not a multiple of the {}x{} block of {format:?}",
+            extent.x, extent.y, block.width, block.height
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that `data` has the size of a mip level with the `extent`.
+pub(crate) fn validate_mip_data(
+    data: &[u8],
+    extent: UVec2,
+    format: gfx::Format,
+) -> Result<(), String> {
+    let expected_size = format.description().block.data_size(extent);
+    if data.len() != expected_size {
+        return Err(format!(
+            "invalid texture data size: expected {expected_size} bytes, got {}",
+            data.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that all `faces` are valid square textures of the same size.
+///
+/// Returns the reason why the cube texture can't be uploaded.
+pub fn validate_cube_texture_data(
+    faces: [&[u8]; 6],
+    size: u32,
+    format: gfx::Format,
+) -> Result<(), String> {
+    for (i, face) in faces.into_iter().enumerate() {
+        validate_texture_data(face, UVec2::splat(size), format)
+            .map_err(|reason| format!("invalid cube face {i}: {reason}"))?;
+    }
+    Ok(())
+}
+
+/// A texture and the requested anisotropy of its overridden sampler.
+type SamplerOverrideKey = (RawTextureHandle, Option<u32>);
+
+/// Clamps the requested anisotropy to the device limit.
+///
+/// Returns `None` (anisotropic filtering is disabled) if the device
+/// doesn't support it or if the requested value is not greater than 1.
+pub fn resolve_max_anisotropy(requested: Option<f32>, supported: bool, limit: f32) -> Option<f32> {
+    let requested = requested.filter(|value| *value > 1.0)?;
+    if !supported {
+        tracing::warn!("anisotropic filtering is not supported by the device");
+        return None;
+    }
+    Some(requested.min(limit))
+}
+
+fn make_sampler(
+    device: &gfx::Device,
+    max_anisotropy: Option<f32>,
+) -> Result<gfx::Sampler, gfx::OutOfDeviceMemory> {
+    device.create_sampler(gfx::SamplerInfo {
+        address_mode_u: gfx::SamplerAddressMode::Repeat,
+        address_mode_v: gfx::SamplerAddressMode::Repeat,
+        address_mode_w: gfx::SamplerAddressMode::Repeat,
+        max_anisotropy,
+        // NOTE: Streamed textures have multiple mip levels
+        max_lod: MAX_LOD,
+        ..gfx::SamplerInfo::simple_linear()
+    })
+}
+
+/// Allows sampling all mip levels.
+const MAX_LOD: f32 = 1000.0;
+
+/// A single opaque white texel, so that sampling the default texture
+/// doesn't change the material color.
+const DEFAULT_TEXTURE_DATA: [u8; 4] = [255; 4];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RendererBuilder;
+
+    #[test]
+    fn anisotropy_is_clamped_to_device_limit() {
+        assert_eq!(resolve_max_anisotropy(Some(8.0), true, 16.0), Some(8.0));
+        assert_eq!(resolve_max_anisotropy(Some(32.0), true, 16.0), Some(16.0));
+        assert_eq!(resolve_max_anisotropy(Some(8.0), false, 16.0), None);
+
+        // Values which don't increase the sample count disable the filtering
+        assert_eq!(resolve_max_anisotropy(None, true, 16.0), None);
+        assert_eq!(resolve_max_anisotropy(Some(1.0), true, 16.0), None);
+        assert_eq!(resolve_max_anisotropy(Some(f32::NAN), true, 16.0), None);
+    }
+
+    #[test]
+    fn cube_faces_must_have_the_same_size() {
+        let face = [0u8; 4 * 4 * 4];
+        let faces = [&face[..]; 6];
+        assert!(validate_cube_texture_data(faces, 4, gfx::Format::RGBA8Unorm).is_ok());
+
+        let mut faces = faces;
+        faces[3] = &face[..4 * 4 * 2];
+        let err = validate_cube_texture_data(faces, 4, gfx::Format::RGBA8Unorm).unwrap_err();
+        assert!(err.starts_with("invalid cube face 3"), "{err}");
+    }
+
+    #[test]
+    fn compressed_textures_must_consist_of_whole_blocks() {
+        let format = gfx::Format::BC7RGBAUnorm;
+        let data = [0u8; 2 * 2 * 16];
+        assert!(validate_texture_data(&data, UVec2::new(8, 8), format).is_ok());
+        assert!(validate_texture_data(&data, UVec2::new(6, 8), format).is_err());
+        assert!(validate_texture_data(&data[..16], UVec2::new(4, 4), format).is_ok());
+
+        // Mip levels smaller than a block still take the whole block
+        assert!(validate_mip_data(&data[..16], UVec2::new(2, 2), format).is_ok());
+        assert!(validate_mip_data(&data[..16], UVec2::ONE, format).is_ok());
+        assert!(validate_mip_data(&data[..4], UVec2::ONE, format).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn uploaded_mips_are_read_back() {
+        let renderer = RendererBuilder::headless(4, 4).build().unwrap();
+        let state = renderer.state();
+        let device = &state.device;
+
+        // Odd-sized levels of two layers, one after another
+        let format = gfx::Format::RGBA8Unorm;
+        let extents = [UVec2::new(5, 3), UVec2::new(2, 1)];
+        let levels = extents.map(|extent| {
+            let size = (extent.x * extent.y * 4 * 2) as usize;
+            (0..size).map(|i| (i * 7 + size) as u8).collect::<Vec<_>>()
+        });
+        let data_size = levels.iter().map(Vec::len).sum::<usize>();
+
+        let image = device
+            .create_image(gfx::ImageInfo {
+                extent: extents[0].into(),
+                format,
+                mip_levels: 2,
+                samples: gfx::Samples::_1,
+                array_layers: 2,
+                usage: gfx::ImageUsageFlags::TRANSFER_SRC | gfx::ImageUsageFlags::TRANSFER_DST,
+                flags: gfx::ImageCreateFlags::empty(),
+                label: Some("uploaded image"),
+            })
+            .unwrap();
+        let readback = device
+            .create_mappable_buffer(
+                gfx::BufferInfo {
+                    align_mask: 0b11,
+                    size: data_size,
+                    usage: gfx::BufferUsage::TRANSFER_DST,
+                    label: Some("uploaded image readback"),
+                },
+                gfx::MemoryUsage::DOWNLOAD,
+            )
+            .unwrap();
+
+        let mut encoder = state.queue.create_primary_encoder().unwrap();
+        let mut regions = Vec::new();
+        let mut offset = 0;
+        for ((mip_level, data), extent) in (0..).zip(&levels).zip(extents) {
+            let subresource = gfx::ImageSubresourceLayers::color(mip_level, 0..2);
+            let layout_after = gfx::ImageLayout::TransferSrcOptimal;
+            encoder
+                .upload_image(&image, subresource, data, layout_after, device)
+                .unwrap();
+
+            let range = gfx::ImageSubresourceRange::from(subresource);
+            assert_eq!(image.tracked_layout(&range), Some(layout_after));
+
+            regions.push(gfx::BufferImageCopy::tightly_packed(
+                format,
+                offset,
+                subresource,
+                extent,
+            ));
+            offset += data.len();
+        }
+        encoder.copy_image_to_buffer(
+            &image,
+            gfx::ImageLayout::TransferSrcOptimal,
+            &readback,
+            &regions,
+        );
+
+        let command_buffer = encoder.finish().unwrap();
+        state.queue.submit_simple(command_buffer, None).unwrap();
+        state.queue.wait_idle().unwrap();
+
+        let mut read = vec![0u8; data_size];
+        device
+            .download_from_memory(&mut readback.as_mappable(), 0, &mut read)
+            .unwrap();
+        assert_eq!(read, levels.concat());
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn replaced_views_are_registered_in_new_slots() {
+        let renderer = RendererBuilder::headless(4, 4).build().unwrap();
+        let state = renderer.state();
+        let manager = &state.texture_manager;
+        let bindless_resources = &state.bindless_resources;
+
+        let format = gfx::Format::RGBA8Unorm;
+        let texture = state
+            .add_texture(&[255; 16], UVec2::splat(2), format)
+            .unwrap();
+        let replacement = manager
+            .upload_texture(
+                &state.queue,
+                bindless_resources,
+                &[0; 16],
+                UVec2::splat(2),
+                format,
+            )
+            .unwrap();
+        bindless_resources.free_image(replacement.bindless_handle);
+        assert!(!manager.take_moved_slots());
+
+        let prev_index = manager.lock_data().bindless_index(texture.raw()).unwrap();
+        let images = bindless_resources.stats().images;
+        manager.replace_view(
+            &state.device,
+            bindless_resources,
+            texture.raw(),
+            replacement.view.clone(),
+            10,
+        );
+        let index = manager.lock_data().bindless_index(texture.raw()).unwrap();
+        assert_ne!(index, prev_index);
+        assert_eq!(bindless_resources.stats().images.live, images.live + 1);
+        assert!(manager.take_moved_slots());
+        assert!(!manager.take_moved_slots());
+
+        // The previous slot is kept until the frame of the replacement is completed
+        manager.complete_removals(9, bindless_resources);
+        assert_eq!(bindless_resources.stats().images.live, images.live + 1);
+        manager.complete_removals(10, bindless_resources);
+        assert_eq!(bindless_resources.stats().images.live, images.live);
+        assert_eq!(
+            bindless_resources.stats().images.retired,
+            images.retired + 1
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn new_samplers_move_textures_to_new_slots() {
+        let renderer = RendererBuilder::headless(4, 4).build().unwrap();
+        let state = renderer.state();
+        let manager = &state.texture_manager;
+        let bindless_resources = &state.bindless_resources;
+        let device = &state.device;
+
+        let texture = state
+            .add_texture(&[255; 16], UVec2::splat(2), gfx::Format::RGBA8Unorm)
+            .unwrap();
+        let sampler = SamplerOverride {
+            max_anisotropy: None,
+        };
+        let (index, override_index) = {
+            let data = manager.lock_data();
+            let index = data.bindless_index(texture.raw()).unwrap();
+            let override_index = data
+                .bindless_index_with_sampler(device, bindless_resources, texture.raw(), sampler)
+                .unwrap();
+            assert_ne!(override_index, index);
+            (index, override_index)
+        };
+        manager.take_moved_slots();
+
+        let max_anisotropy = resolve_max_anisotropy(
+            Some(4.0),
+            device.is_feature_enabled(gfx::DeviceFeature::SamplerAnisotropy),
+            device.limits().max_sampler_anisotropy,
+        );
+        if max_anisotropy.is_none() {
+            return;
+        }
+        let images = bindless_resources.stats().images;
+        manager
+            .set_max_anisotropy(device, bindless_resources, max_anisotropy, 10)
+            .unwrap();
+        assert_eq!(manager.max_anisotropy(), max_anisotropy);
+        assert!(manager.take_moved_slots());
+
+        // Overridden samplers keep their slots
+        let data = manager.lock_data();
+        assert_ne!(data.bindless_index(texture.raw()), Some(index));
+        assert_eq!(
+            data.bindless_index_with_sampler(device, bindless_resources, texture.raw(), sampler),
+            Some(override_index)
+        );
+        drop(data);
+
+        // The previous slots are kept until the frame of the replacement is completed
+        assert_eq!(bindless_resources.stats().images.live, images.live + 1);
+        manager.complete_removals(10, bindless_resources);
+        assert_eq!(bindless_resources.stats().images.live, images.live);
+
+        // Setting the same anisotropy again changes nothing
+        manager
+            .set_max_anisotropy(device, bindless_resources, max_anisotropy, 11)
+            .unwrap();
+        assert!(!manager.take_moved_slots());
+    }
+}
@@ -0,0 +1,696 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::{mpsc, Arc, Mutex};
+
+use anyhow::{Context, Result};
+use glam::UVec2;
+use shared::FastHashMap;
+
+use crate::managers::texture_manager::{validate_mip_data, validate_texture_extent};
+use crate::managers::{GpuTexture, TextureManager};
+use crate::types::{full_mip_chain_len, mip_extent, MipData, RawTextureHandle, StreamedTexture};
+use crate::util::BindlessResources;
+
+/// Uploads the smallest mip levels of streamed textures immediately
+/// and loads the larger ones on a background thread based on texture priorities.
+///
+/// Images are recreated with the new mip chain when levels are loaded or evicted,
+/// their views replace the previous ones under the same texture handles.
+pub struct TextureStreamer {
+    state: Mutex<TextureStreamerState>,
+    requests: mpsc::Sender<LoadRequest>,
+    loaded: Mutex<mpsc::Receiver<LoadedMips>>,
+}
+
+impl TextureStreamer {
+    pub fn new() -> Result<Self> {
+        let (requests, requests_rx) = mpsc::channel::<LoadRequest>();
+        let (loaded_tx, loaded) = mpsc::channel();
+
+        std::thread::Builder::new()
+            .name("texture-streamer".to_owned())
+            .spawn(move || {
+                // NOTE: The thread is stopped when the streamer is dropped
+                for request in requests_rx {
+                    profile_scope!("load_mips");
+
+                    let levels = load_mips(
+                        &request.data,
+                        request.extent,
+                        request.format,
+                        request.levels.clone(),
+                    )
+                    .map(|levels| levels.into_iter().map(Cow::into_owned).collect());
+
+                    let loaded = LoadedMips {
+                        handle: request.handle,
+                        first_level: request.levels.start,
+                        bytes: request.bytes,
+                        levels,
+                    };
+                    if loaded_tx.send(loaded).is_err() {
+                        break;
+                    }
+                }
+            })
+            .context("failed to spawn the texture streaming thread")?;
+
+        Ok(Self {
+            state: Mutex::new(TextureStreamerState {
+                textures: FastHashMap::default(),
+                budget: DEFAULT_BUDGET,
+                resident_bytes: 0,
+                pending_bytes: 0,
+                pending_requests: 0,
+            }),
+            requests,
+            loaded: Mutex::new(loaded),
+        })
+    }
+
+    /// Sets the total size of streamed textures above which
+    /// the largest mip levels are evicted.
+    ///
+    /// NOTE: The smallest mip levels which were uploaded immediately are never evicted.
+    pub fn set_budget(&self, bytes: u64) {
+        self.state.lock().unwrap().budget = bytes;
+    }
+
+    /// Sets the approximate on-screen size of the texture in pixels.
+    ///
+    /// Returns `false` if the texture is not streamed.
+    pub fn set_priority(&self, handle: RawTextureHandle, priority: f32) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.textures.get_mut(&handle) {
+            Some(texture) => {
+                texture.residency.priority = priority;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Uploads the immediately resident mip levels of the texture.
+    ///
+    /// NOTE: `tail` must contain the levels loaded with [`load_mips`]
+    /// starting at [`StreamedTexture::tail_level`].
+    pub fn upload_tail(
+        &self,
+        queue: &gfx::Queue,
+        texture_manager: &TextureManager,
+        bindless_resources: &BindlessResources,
+        descr: StreamedTexture,
+        tail: &[Vec<u8>],
+    ) -> Result<(GpuTexture, PendingStreamedTexture)> {
+        let tail_level = descr.tail_level();
+        let residency = MipResidency {
+            extent: descr.extent,
+            block: descr.format.description().block,
+            mip_levels: descr.mip_levels,
+            tail_level,
+            resident_level: tail_level,
+            priority: 0.0,
+        };
+
+        let (image, view) = create_mip_chain(
+            queue,
+            texture_manager,
+            &residency,
+            descr.format,
+            tail_level,
+            tail,
+            None,
+        )?;
+        let texture = texture_manager.register_view(queue.device(), bindless_resources, view);
+
+        Ok((
+            texture,
+            PendingStreamedTexture(StreamedTextureState {
+                residency,
+                format: descr.format,
+                data: Arc::new(descr.data),
+                image,
+                image_level: tail_level,
+                pending: false,
+                failed: false,
+            }),
+        ))
+    }
+
+    pub fn add(&self, handle: RawTextureHandle, texture: PendingStreamedTexture) {
+        let texture = texture.0;
+        let bytes = texture.residency.resident_bytes();
+
+        let mut state = self.state.lock().unwrap();
+        state.resident_bytes += bytes;
+        state.textures.insert(handle, texture);
+    }
+
+    /// Stops streaming of the removed texture.
+    ///
+    /// NOTE: Mip levels which are being loaded are discarded.
+    pub fn remove(&self, handle: RawTextureHandle) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(texture) = state.textures.remove(&handle) {
+            state.resident_bytes -= texture.residency.resident_bytes();
+        }
+    }
+
+    /// Applies loaded mip levels, evicts mip levels of the textures with
+    /// the lowest priorities under memory pressure and requests mip levels
+    /// wanted by the texture priorities.
+    #[tracing::instrument(level = "debug", name = "stream_textures", skip_all)]
+    pub fn update(
+        &self,
+        queue: &gfx::Queue,
+        texture_manager: &TextureManager,
+        bindless_resources: &BindlessResources,
+        frame: u32,
+    ) -> Result<()> {
+        profile_scope!("stream_textures");
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let mut uploaded_bytes = 0;
+        {
+            let loaded_rx = self.loaded.lock().unwrap();
+            while uploaded_bytes < MAX_UPLOAD_BYTES_PER_FRAME {
+                let Ok(loaded) = loaded_rx.try_recv() else {
+                    break;
+                };
+                state.pending_requests -= 1;
+                state.pending_bytes -= loaded.bytes;
+
+                let Some(texture) = state.textures.get_mut(&loaded.handle) else {
+                    continue;
+                };
+                texture.pending = false;
+
+                let levels = match loaded.levels {
+                    Ok(levels) => levels,
+                    Err(reason) => {
+                        tracing::error!(handle = ?loaded.handle, %reason, "failed to stream mips");
+                        texture.failed = true;
+                        continue;
+                    }
+                };
+
+                // NOTE: Levels could be evicted while they were loading
+                let resident = &texture.residency;
+                if loaded.first_level + levels.len() as u32 != resident.resident_level {
+                    continue;
+                }
+
+                let (image, view) = create_mip_chain(
+                    queue,
+                    texture_manager,
+                    resident,
+                    texture.format,
+                    loaded.first_level,
+                    &levels,
+                    Some((&texture.image, texture.image_level)),
+                )?;
+                texture_manager.replace_view(
+                    queue.device(),
+                    bindless_resources,
+                    loaded.handle,
+                    view,
+                    frame,
+                );
+
+                texture.image = image;
+                texture.image_level = loaded.first_level;
+                texture.residency.resident_level = loaded.first_level;
+                state.resident_bytes += loaded.bytes;
+                uploaded_bytes += loaded.bytes;
+            }
+        }
+
+        if state.resident_bytes > state.budget {
+            let bytes = state.resident_bytes - state.budget;
+            let freed = evict(
+                state.textures.values_mut().map(|t| &mut t.residency),
+                bytes,
+                f32::INFINITY,
+            );
+            state.resident_bytes -= freed;
+        }
+
+        let mut wanted = state
+            .textures
+            .iter()
+            .filter(|(_, texture)| !texture.pending && !texture.failed)
+            .filter_map(|(handle, texture)| {
+                let resident = &texture.residency;
+                let desired = resident.desired_level();
+                (desired < resident.resident_level).then_some((*handle, resident.priority))
+            })
+            .collect::<Vec<_>>();
+        wanted.sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        for (handle, priority) in wanted {
+            if state.pending_requests >= MAX_PENDING_REQUESTS {
+                break;
+            }
+
+            let resident = &state.textures[&handle].residency;
+            let levels = resident.desired_level()..resident.resident_level;
+            let bytes = resident.chain_size(levels.clone());
+
+            // NOTE: Textures with lower priorities give up their largest levels
+            let used = state.resident_bytes + state.pending_bytes;
+            let overflow = (used + bytes).saturating_sub(state.budget);
+            if overflow > 0 {
+                let textures = state
+                    .textures
+                    .iter_mut()
+                    .filter(|(other, _)| **other != handle)
+                    .map(|(_, t)| &mut t.residency);
+                let freed = evict(textures, overflow, priority);
+                state.resident_bytes -= freed;
+                if freed < overflow {
+                    continue;
+                }
+            }
+
+            let texture = state.textures.get_mut(&handle).unwrap();
+            let request = LoadRequest {
+                handle,
+                data: texture.data.clone(),
+                extent: texture.residency.extent,
+                format: texture.format,
+                levels,
+                bytes,
+            };
+            if self.requests.send(request).is_err() {
+                anyhow::bail!("texture streaming thread has stopped");
+            }
+
+            texture.pending = true;
+            state.pending_requests += 1;
+            state.pending_bytes += bytes;
+        }
+
+        for (handle, texture) in &mut state.textures {
+            let resident_level = texture.residency.resident_level;
+            if resident_level <= texture.image_level {
+                continue;
+            }
+
+            let (image, view) = create_mip_chain(
+                queue,
+                texture_manager,
+                &texture.residency,
+                texture.format,
+                resident_level,
+                &[] as &[Vec<u8>],
+                Some((&texture.image, texture.image_level)),
+            )?;
+            texture_manager.replace_view(queue.device(), bindless_resources, *handle, view, frame);
+
+            texture.image = image;
+            texture.image_level = resident_level;
+        }
+
+        Ok(())
+    }
+}
+
+/// Streamed texture which is not yet added to the streamer.
+pub struct PendingStreamedTexture(StreamedTextureState);
+
+impl StreamedTexture {
+    /// Returns the first mip level which is uploaded immediately.
+    pub(crate) fn tail_level(&self) -> u32 {
+        self.mip_levels - self.resident_mips
+    }
+}
+
+/// Checks that the texture can be streamed.
+///
+/// Returns the reason why the texture can't be streamed.
+pub fn validate_streamed_texture(descr: &StreamedTexture) -> Result<(), String> {
+    validate_texture_extent(descr.extent, descr.format)?;
+
+    let max_mip_levels = full_mip_chain_len(descr.extent);
+    if descr.mip_levels == 0 || descr.mip_levels > max_mip_levels {
+        return Err(format!(
+            "invalid mip level count: expected at most {max_mip_levels}, got {}",
+            descr.mip_levels
+        ));
+    }
+    if descr.resident_mips == 0 || descr.resident_mips > descr.mip_levels {
+        return Err(format!(
+            "invalid resident mip count: expected at most {}, got {}",
+            descr.mip_levels, descr.resident_mips
+        ));
+    }
+
+    if let MipData::Ranges { ranges, .. } = &descr.data {
+        if ranges.len() != descr.mip_levels as usize {
+            return Err(format!(
+                "invalid mip range count: expected {}, got {}",
+                descr.mip_levels,
+                ranges.len()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Loads the mip `levels` and checks their sizes.
+pub fn load_mips(
+    data: &MipData,
+    extent: UVec2,
+    format: gfx::Format,
+    levels: Range<u32>,
+) -> Result<Vec<Cow<'_, [u8]>>, String> {
+    levels
+        .map(|level| {
+            let data = data
+                .load(level)
+                .map_err(|e| format!("failed to load mip level {level}: {e:?}"))?;
+            validate_mip_data(&data, mip_extent(extent, level), format)
+                .map_err(|reason| format!("invalid mip level {level}: {reason}"))?;
+            Ok(data)
+        })
+        .collect()
+}
+
+/// Creates an image with the mip levels `first_level..` of the texture.
+///
+/// The `uploaded` levels start at `first_level`, the rest of them are copied
+/// from the previous image, which starts at the specified mip level.
+fn create_mip_chain<T: AsRef<[u8]>>(
+    queue: &gfx::Queue,
+    texture_manager: &TextureManager,
+    residency: &MipResidency,
+    format: gfx::Format,
+    first_level: u32,
+    uploaded: &[T],
+    prev: Option<(&gfx::Image, u32)>,
+) -> Result<(gfx::Image, gfx::ImageView)> {
+    let device = queue.device();
+    let data_size = uploaded
+        .iter()
+        .map(|data| data.as_ref().len())
+        .sum::<usize>();
+
+    let image = device.create_image(gfx::ImageInfo {
+        extent: mip_extent(residency.extent, first_level).into(),
+        format,
+        mip_levels: residency.mip_levels - first_level,
+        samples: gfx::Samples::_1,
+        array_layers: 1,
+        usage: gfx::ImageUsageFlags::TRANSFER_SRC
+            | gfx::ImageUsageFlags::TRANSFER_DST
+            | gfx::ImageUsageFlags::SAMPLED,
+        flags: gfx::ImageCreateFlags::empty(),
+        label: Some("streamed texture"),
+    })?;
+    let view = device.create_image_view(gfx::ImageViewInfo::new(image.clone()))?;
+
+    let mut upload_regions = Vec::with_capacity(uploaded.len());
+    let staging_buffer = if data_size > 0 {
+        let staging_buffer = device.create_mappable_buffer(
+            gfx::BufferInfo {
+                align_mask: 0b11,
+                size: data_size,
+                usage: gfx::BufferUsage::TRANSFER_SRC,
+                label: Some("texture staging buffer"),
+            },
+            gfx::MemoryUsage::UPLOAD | gfx::MemoryUsage::TRANSIENT,
+        )?;
+
+        let mut memory_block = staging_buffer.as_mappable();
+        let staging_buffer_data = device.map_memory(&mut memory_block, 0, data_size)?;
+
+        // NOTE: Levels are tightly packed one after another
+        let mut offset = 0;
+        for (i, data) in uploaded.iter().enumerate() {
+            let data = data.as_ref();
+            let dst = &mut staging_buffer_data[offset..offset + data.len()];
+
+            // SAFETY: `dst` is a valid pointer to a slice of exactly `data.len()` bytes.
+            unsafe {
+                std::ptr::copy_nonoverlapping(data.as_ptr(), dst.as_mut_ptr().cast(), data.len());
+            }
+
+            let level = first_level + i as u32;
+            upload_regions.push(gfx::BufferImageCopy::tightly_packed(
+                format,
+                offset,
+                gfx::ImageSubresourceLayers::color(i as u32, 0..1),
+                mip_extent(residency.extent, level),
+            ));
+            offset += data.len();
+        }
+
+        device.unmap_memory(&mut memory_block);
+        Some(staging_buffer)
+    } else {
+        None
+    };
+
+    let copied_levels = first_level + uploaded.len() as u32..residency.mip_levels;
+
+    texture_manager.record_uploads(queue, |encoder| {
+        encoder.transition_image(
+            &image,
+            gfx::ImageLayout::TransferDstOptimal,
+            gfx::PipelineStageFlags::TOP_OF_PIPE..gfx::PipelineStageFlags::TRANSFER,
+            gfx::AccessFlags::empty()..gfx::AccessFlags::TRANSFER_WRITE,
+        );
+
+        if let Some(staging_buffer) = &staging_buffer {
+            encoder.copy_buffer_to_image(
+                staging_buffer,
+                &image,
+                gfx::ImageLayout::TransferDstOptimal,
+                &upload_regions,
+            );
+        }
+
+        if let Some((prev, prev_level)) = prev {
+            if !copied_levels.is_empty() {
+                let range = gfx::ImageSubresourceRange::color(
+                    copied_levels.start - prev_level..copied_levels.end - prev_level,
+                    0..1,
+                );
+                encoder.transition_image_range(
+                    prev,
+                    range,
+                    gfx::ImageLayout::TransferSrcOptimal,
+                    gfx::PipelineStageFlags::VERTEX_SHADER
+                        | gfx::PipelineStageFlags::FRAGMENT_SHADER
+                        ..gfx::PipelineStageFlags::TRANSFER,
+                    gfx::AccessFlags::SHADER_READ..gfx::AccessFlags::TRANSFER_READ,
+                );
+
+                let regions = copied_levels
+                    .clone()
+                    .map(|level| gfx::ImageCopy {
+                        src_subresource: gfx::ImageSubresourceLayers::color(
+                            level - prev_level,
+                            0..1,
+                        ),
+                        src_offset: glam::IVec3::ZERO,
+                        dst_subresource: gfx::ImageSubresourceLayers::color(
+                            level - first_level,
+                            0..1,
+                        ),
+                        dst_offset: glam::IVec3::ZERO,
+                        extent: mip_extent(residency.extent, level).extend(1),
+                    })
+                    .collect::<Vec<_>>();
+                encoder.copy_image(
+                    prev,
+                    gfx::ImageLayout::TransferSrcOptimal,
+                    &image,
+                    gfx::ImageLayout::TransferDstOptimal,
+                    &regions,
+                );
+            }
+        }
+
+        encoder.transition_image(
+            &image,
+            gfx::ImageLayout::ShaderReadOnlyOptimal,
+            gfx::PipelineStageFlags::TRANSFER
+                ..gfx::PipelineStageFlags::VERTEX_SHADER | gfx::PipelineStageFlags::FRAGMENT_SHADER,
+            gfx::AccessFlags::TRANSFER_WRITE..gfx::AccessFlags::SHADER_READ,
+        );
+    })?;
+
+    Ok((image, view))
+}
+
+/// Drops the largest mip levels of the textures with the lowest priorities
+/// until `bytes` are freed or there is nothing left to evict.
+///
+/// Only textures with priorities lower than `below_priority` are evicted.
+/// Returns the number of freed bytes.
+fn evict<'a>(
+    textures: impl Iterator<Item = &'a mut MipResidency>,
+    bytes: u64,
+    below_priority: f32,
+) -> u64 {
+    let mut candidates = textures
+        .filter(|t| t.priority < below_priority && t.resident_level < t.tail_level)
+        .collect::<Vec<_>>();
+    candidates.sort_unstable_by(|a, b| a.priority.total_cmp(&b.priority));
+
+    let mut freed = 0;
+    for texture in candidates {
+        while freed < bytes && texture.resident_level < texture.tail_level {
+            freed += texture.level_size(texture.resident_level);
+            texture.resident_level += 1;
+        }
+        if freed >= bytes {
+            break;
+        }
+    }
+    freed
+}
+
+struct TextureStreamerState {
+    textures: FastHashMap<RawTextureHandle, StreamedTextureState>,
+    budget: u64,
+    /// Size of the resident mip levels of all textures.
+    resident_bytes: u64,
+    /// Size of the mip levels which are being loaded.
+    pending_bytes: u64,
+    pending_requests: usize,
+}
+
+struct StreamedTextureState {
+    residency: MipResidency,
+    format: gfx::Format,
+    data: Arc<MipData>,
+    image: gfx::Image,
+    /// The first mip level of the image.
+    ///
+    /// NOTE: Differs from the resident level until evicted levels are dropped.
+    image_level: u32,
+    /// Whether mip levels are being loaded.
+    pending: bool,
+    /// Whether loading failed, the texture is no longer streamed.
+    failed: bool,
+}
+
+struct MipResidency {
+    extent: UVec2,
+    block: gfx::FormatBlock,
+    mip_levels: u32,
+    /// The first mip level which is never evicted.
+    tail_level: u32,
+    /// The first resident mip level.
+    resident_level: u32,
+    /// Approximate on-screen size of the texture in pixels.
+    priority: f32,
+}
+
+impl MipResidency {
+    fn level_size(&self, level: u32) -> u64 {
+        let extent = mip_extent(self.extent, level);
+        self.block.data_size(extent) as u64
+    }
+
+    fn chain_size(&self, levels: Range<u32>) -> u64 {
+        levels.map(|level| self.level_size(level)).sum()
+    }
+
+    fn resident_bytes(&self) -> u64 {
+        self.chain_size(self.resident_level..self.mip_levels)
+    }
+
+    /// Returns the first mip level which is not larger than
+    /// the on-screen size of the texture.
+    fn desired_level(&self) -> u32 {
+        if self.priority.is_nan() || self.priority <= 0.0 {
+            return self.tail_level;
+        }
+
+        let size = self.extent.max_element() as f32;
+        let level = (size / self.priority).log2().floor().max(0.0) as u32;
+        level.min(self.tail_level)
+    }
+}
+
+struct LoadRequest {
+    handle: RawTextureHandle,
+    data: Arc<MipData>,
+    extent: UVec2,
+    format: gfx::Format,
+    levels: Range<u32>,
+    bytes: u64,
+}
+
+struct LoadedMips {
+    handle: RawTextureHandle,
+    first_level: u32,
+    bytes: u64,
+    levels: Result<Vec<Vec<u8>>, String>,
+}
+
+const DEFAULT_BUDGET: u64 = 512 << 20;
+const MAX_UPLOAD_BYTES_PER_FRAME: u64 = 32 << 20;
+const MAX_PENDING_REQUESTS: usize = 4;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn residency(size: u32, resident_level: u32, priority: f32) -> MipResidency {
+        let mip_levels = full_mip_chain_len(UVec2::splat(size));
+        MipResidency {
+            extent: UVec2::splat(size),
+            block: gfx::Format::RGBA8Unorm.description().block,
+            mip_levels,
+            tail_level: mip_levels - 2,
+            resident_level,
+            priority,
+        }
+    }
+
+    #[test]
+    fn desired_level_matches_on_screen_size() {
+        assert_eq!(full_mip_chain_len(UVec2::new(1024, 300)), 11);
+        assert_eq!(mip_extent(UVec2::new(1024, 300), 9), UVec2::new(2, 1));
+
+        let mut texture = residency(1024, 9, 0.0);
+        assert_eq!(texture.desired_level(), 9);
+        texture.priority = 1024.0;
+        assert_eq!(texture.desired_level(), 0);
+        texture.priority = 300.0;
+        assert_eq!(texture.desired_level(), 1);
+        texture.priority = 4000.0;
+        assert_eq!(texture.desired_level(), 0);
+        texture.priority = 0.5;
+        assert_eq!(texture.desired_level(), 9);
+    }
+
+    #[test]
+    fn largest_levels_of_lowest_priorities_are_evicted_first() {
+        let mut textures = [
+            residency(256, 0, 10.0),
+            residency(256, 0, 1.0),
+            residency(256, 7, 0.0),
+        ];
+
+        // Levels 0 and 1 of the second texture
+        let bytes = textures[1].chain_size(0..2);
+        let freed = evict(textures.iter_mut(), bytes, f32::INFINITY);
+        assert_eq!(freed, bytes);
+        assert_eq!(textures[0].resident_level, 0);
+        assert_eq!(textures[1].resident_level, 2);
+        assert_eq!(textures[2].resident_level, 7);
+
+        // Tail levels are never evicted
+        let freed = evict(textures.iter_mut(), u64::MAX, 5.0);
+        assert_eq!(freed, textures[1].chain_size(2..7));
+        assert_eq!(textures[0].resident_level, 0);
+        assert_eq!(textures[1].resident_level, 7);
+    }
+}
@@ -1,33 +1,156 @@
 use std::time::{Duration, Instant};
 
-#[derive(Default)]
 pub struct TimeManager {
+    fixed_timestep: Duration,
     fixed_update: Option<FixedUpdateInfo>,
 }
 
+impl Default for TimeManager {
+    fn default() -> Self {
+        Self {
+            fixed_timestep: Self::DEFAULT_FIXED_TIMESTEP,
+            fixed_update: None,
+        }
+    }
+}
+
 impl TimeManager {
+    pub const DEFAULT_FIXED_TIMESTEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    pub fn fixed_timestep(&self) -> Duration {
+        self.fixed_timestep
+    }
+
+    /// Changes the interval between fixed updates.
+    ///
+    /// The interpolation of the current update is rescaled so that the factor
+    /// at `changed_at` is preserved and then advances with the new rate.
+    pub fn set_fixed_timestep(&mut self, timestep: Duration, changed_at: Instant) {
+        self.fixed_timestep = timestep;
+
+        let interval_sec = timestep.as_secs_f64();
+        let Some(state) = &mut self.fixed_update else {
+            return;
+        };
+        if interval_sec <= MIN_FRAME_DURATION {
+            return;
+        }
+
+        let factor = state.factor_at(changed_at) as f64;
+        if let Some(updated_at) =
+            changed_at.checked_sub(Duration::from_secs_f64(factor * interval_sec))
+        {
+            state.updated_at = updated_at;
+            state.interval_sec = interval_sec;
+        }
+    }
+
     pub fn updated_fixed_time(&mut self, updated_at: Instant, duration: Duration) {
         let duration_sec = duration.as_secs_f64();
         self.fixed_update = (duration_sec > MIN_FRAME_DURATION).then_some(FixedUpdateInfo {
             updated_at,
-            prev_interval_sec: duration_sec,
+            interval_sec: duration_sec,
+            last_factor: 0.0,
         });
     }
 
-    pub fn compute_interpolation_factor(&self, rendered_at: Instant) -> f32 {
-        let Some(state) = &self.fixed_update else {
+    /// Returns the interpolation factor in range `[0, 1]` between the previous
+    /// and the last fixed update.
+    ///
+    /// NOTE: The factor never decreases until the next fixed update.
+    pub fn compute_interpolation_factor(&mut self, rendered_at: Instant) -> f32 {
+        let Some(state) = &mut self.fixed_update else {
             return 1.0;
         };
 
         // TODO: add noise filter?
-        let since_fixed_update = rendered_at.duration_since(state.updated_at).as_secs_f64();
-        (since_fixed_update / state.prev_interval_sec) as f32
+        let factor = state.factor_at(rendered_at).max(state.last_factor);
+        state.last_factor = factor;
+        factor
     }
 }
 
 struct FixedUpdateInfo {
     updated_at: Instant,
-    prev_interval_sec: f64,
+    interval_sec: f64,
+    last_factor: f32,
+}
+
+impl FixedUpdateInfo {
+    fn factor_at(&self, rendered_at: Instant) -> f32 {
+        let since_fixed_update = rendered_at
+            .saturating_duration_since(self.updated_at)
+            .as_secs_f64();
+        ((since_fixed_update / self.interval_sec) as f32).clamp(0.0, 1.0)
+    }
 }
 
 const MIN_FRAME_DURATION: f64 = 0.000001;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn factor_is_monotonic_between_irregular_updates() {
+        // NOTE: Offset the base time to be able to render before the first update
+        let base = Instant::now() + ms(1000);
+
+        let mut time = TimeManager::default();
+        assert_eq!(time.compute_interpolation_factor(base), 1.0);
+
+        // (updated_at, duration)
+        let updates = [
+            (0, 100),
+            (90, 100),
+            (250, 30),
+            (260, 500),
+            (200, 50),
+            (900, 1),
+            (901, 0),
+            (1000, 16),
+        ];
+
+        for (updated_at, duration) in updates {
+            time.updated_fixed_time(base + ms(updated_at), ms(duration));
+
+            let mut prev_factor = 0.0;
+            for frame in 0..64 {
+                // NOTE: Frames can be rendered before the update timestamp
+                let rendered_at = base + ms(updated_at + frame * 7) - ms(50);
+                let factor = time.compute_interpolation_factor(rendered_at);
+
+                assert!((0.0..=1.0).contains(&factor), "{factor} is out of range");
+                assert!(factor >= prev_factor, "{factor} < {prev_factor}");
+                prev_factor = factor;
+            }
+
+            // Rendering an older frame must not move objects back
+            let factor = time.compute_interpolation_factor(base + ms(updated_at));
+            assert!(factor >= prev_factor);
+        }
+    }
+
+    #[test]
+    fn timestep_change_rescales_interpolation() {
+        let base = Instant::now();
+
+        let mut time = TimeManager::default();
+        time.updated_fixed_time(base, ms(100));
+        assert!((time.compute_interpolation_factor(base + ms(50)) - 0.5).abs() < 1e-4);
+
+        time.set_fixed_timestep(ms(200), base + ms(50));
+        assert_eq!(time.fixed_timestep(), ms(200));
+
+        let factor = time.compute_interpolation_factor(base + ms(50));
+        assert!((factor - 0.5).abs() < 1e-4, "{factor}");
+        let factor = time.compute_interpolation_factor(base + ms(100));
+        assert!((factor - 0.75).abs() < 1e-4, "{factor}");
+        let factor = time.compute_interpolation_factor(base + ms(200));
+        assert_eq!(factor, 1.0);
+    }
+}
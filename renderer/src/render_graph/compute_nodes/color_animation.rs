@@ -0,0 +1,230 @@
+use std::sync::{Arc, Weak};
+
+use anyhow::Result;
+
+use crate::render_graph::{create_pipeline_layout, ComputeNode, RenderGraphContext};
+use crate::util::{ShaderPreprocessor, StorageBufferHandle};
+use crate::RendererState;
+
+/// Sample compute node which cycles the hue of a palette of colors.
+///
+/// Colors are written as `vec4` into a storage buffer which can be read
+/// by materials through [`ColorAnimationNode::colors_buffer_index`].
+///
+/// NOTE: The bindless slot of the buffer is freed when the node is dropped.
+pub struct ColorAnimationNode {
+    state: Weak<RendererState>,
+    pipeline_layout: gfx::PipelineLayout,
+    pipeline: gfx::ComputePipeline,
+    colors_buffer: gfx::Buffer,
+    colors_buffer_handle: StorageBufferHandle,
+    color_count: u32,
+    speed: f32,
+}
+
+impl ColorAnimationNode {
+    /// Creates a node which animates `color_count` colors
+    /// with `speed` hue cycles per second.
+    #[tracing::instrument(level = "debug", name = "create_color_animation_node", skip_all)]
+    pub fn new(state: &Arc<RendererState>, color_count: u32, speed: f32) -> Result<Self> {
+        anyhow::ensure!(
+            color_count > 0,
+            "color animation requires at least one color"
+        );
+
+        let pipeline_layout = create_pipeline_layout(state)?;
+        let pipeline = Self::make_pipeline(
+            &state.device,
+            &pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+        )?;
+
+        let buffer = state.device.create_buffer(gfx::BufferInfo {
+            align_mask: 0b1111,
+            size: color_count as usize * COLOR_SIZE,
+            usage: gfx::BufferUsage::STORAGE | gfx::BufferUsage::TRANSFER_SRC,
+            label: Some("color_animation"),
+        })?;
+        let colors_buffer_handle = state
+            .bindless_resources
+            .alloc_storage_buffer(&state.device, gfx::BufferRange::whole(buffer.clone()));
+
+        Ok(Self {
+            state: Arc::downgrade(state),
+            pipeline_layout,
+            pipeline,
+            colors_buffer: buffer,
+            colors_buffer_handle,
+            color_count,
+            speed,
+        })
+    }
+
+    /// Returns the bindless index of the storage buffer with animated colors.
+    pub fn colors_buffer_index(&self) -> u32 {
+        self.colors_buffer_handle.index()
+    }
+
+    /// Returns the storage buffer with animated colors.
+    pub fn colors_buffer(&self) -> &gfx::Buffer {
+        &self.colors_buffer
+    }
+
+    fn make_pipeline(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<gfx::ComputePipeline> {
+        let shader = shaders
+            .begin()
+            .make_compute_shader(device, "color_animation.comp", "main")?;
+
+        Ok(device.create_compute_pipeline(gfx::ComputePipelineInfo {
+            shader,
+            layout: pipeline_layout.clone(),
+        })?)
+    }
+}
+
+impl ComputeNode for ColorAnimationNode {
+    fn name(&self) -> &str {
+        "color_animation"
+    }
+
+    fn execute(&mut self, ctx: &mut RenderGraphContext<'_>) -> Result<()> {
+        ctx.encoder.bind_compute_pipeline(&self.pipeline);
+        ctx.encoder.push_constants(
+            &self.pipeline_layout,
+            gfx::ShaderStageFlags::ALL,
+            0,
+            &[
+                self.colors_buffer_handle.index(),
+                self.color_count,
+                self.speed.to_bits(),
+                0,
+            ],
+        );
+        ctx.encoder
+            .dispatch(self.color_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        Ok(())
+    }
+
+    fn reload_shaders(&mut self, state: &RendererState) -> Result<()> {
+        self.pipeline = Self::make_pipeline(
+            &state.device,
+            &self.pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for ColorAnimationNode {
+    fn drop(&mut self) {
+        // NOTE: The slot is reused only after the frames in flight are completed
+        if let Some(state) = self.state.upgrade() {
+            state
+                .bindless_resources
+                .free_storage_buffer(self.colors_buffer_handle);
+        }
+    }
+}
+
+const COLOR_SIZE: usize = 16;
+const WORKGROUP_SIZE: u32 = 64;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::{ReadbackTicket, RendererBuilder};
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn colors_are_written_before_the_main_pass() {
+        struct DownloadColors {
+            buffer: gfx::Buffer,
+            ticket: Arc<Mutex<Option<ReadbackTicket>>>,
+        }
+
+        impl ComputeNode for DownloadColors {
+            fn execute(&mut self, ctx: &mut RenderGraphContext<'_>) -> Result<()> {
+                let mut ticket = self.ticket.lock().unwrap();
+                if ticket.is_none() {
+                    // NOTE: Waits for the shader writes of the previous node
+                    ctx.encoder.memory_barrier(
+                        gfx::PipelineStageFlags::COMPUTE_SHADER,
+                        gfx::AccessFlags::SHADER_WRITE,
+                        gfx::PipelineStageFlags::TRANSFER,
+                        gfx::AccessFlags::TRANSFER_READ,
+                    );
+                    let range = gfx::BufferRange::whole(self.buffer.clone());
+                    *ticket = Some(
+                        ctx.encoder
+                            .download_buffer(&range, ctx.state.readback_pool())?,
+                    );
+                }
+                Ok(())
+            }
+        }
+
+        let renderer = RendererBuilder::headless(16, 16).build().unwrap();
+        let state = renderer.state();
+
+        let node = ColorAnimationNode::new(state, 3, 0.0).unwrap();
+        let buffer = node.colors_buffer().clone();
+
+        let ticket = Arc::new(Mutex::new(None));
+        state.add_compute_node(Box::new(node)).unwrap();
+        state
+            .add_compute_node(Box::new(DownloadColors {
+                buffer,
+                ticket: ticket.clone(),
+            }))
+            .unwrap();
+
+        let started_at = Instant::now();
+        let data = loop {
+            assert!(started_at.elapsed() < Duration::from_secs(10));
+            state.notify_draw();
+            std::thread::sleep(Duration::from_millis(10));
+
+            let ticket = ticket.lock().unwrap();
+            if let Some(data) = ticket
+                .as_ref()
+                .and_then(|t| state.poll_readback(t).unwrap())
+            {
+                break data;
+            }
+        };
+
+        // Hues of the colors are evenly spread and fully opaque
+        let colors = bytemuck::cast_slice::<u8, [f32; 4]>(&data);
+        assert_eq!(colors.len(), 3);
+        for color in colors {
+            assert_eq!(color[3], 1.0);
+            assert!(color[..3].iter().all(|c| (0.0..=1.0).contains(c)));
+        }
+        assert_ne!(colors[0], colors[1]);
+        assert_ne!(colors[1], colors[2]);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn colors_buffer_slot_is_retired_on_drop() {
+        let renderer = RendererBuilder::headless(16, 16).build().unwrap();
+        let state = renderer.state();
+
+        let before = state.bindless_resources.stats().storage_buffers;
+        let node = ColorAnimationNode::new(state, 4, 1.0).unwrap();
+        let allocated = state.bindless_resources.stats().storage_buffers;
+        assert_eq!(allocated.live, before.live + 1);
+
+        drop(node);
+        let dropped = state.bindless_resources.stats().storage_buffers;
+        assert_eq!(dropped.live, before.live);
+        assert_eq!(dropped.retired, before.retired + 1);
+    }
+}
@@ -0,0 +1,178 @@
+use std::ops::Range;
+
+use anyhow::Result;
+use bumpalo::Bump;
+
+use crate::render_graph::RenderGraphNodeContext;
+use crate::util::StorageBufferHandle;
+
+/// Groups draws of objects with the same mesh into instanced draw calls.
+///
+/// Object slots of each batch are written into a per-frame instance buffer
+/// which is indexed by `gl_InstanceIndex` in the vertex shader.
+pub struct DrawBatcher<'a> {
+    alloc: &'a Bump,
+    draws: bumpalo::collections::Vec<'a, ObjectDraw>,
+}
+
+impl<'a> DrawBatcher<'a> {
+    pub fn new(alloc: &'a Bump) -> Self {
+        Self {
+            alloc,
+            draws: bumpalo::collections::Vec::new_in(alloc),
+        }
+    }
+
+    pub fn push(&mut self, indices: Range<u32>, index_type: gfx::IndexType, slot: u32) {
+        self.draws.push(ObjectDraw {
+            first_index: indices.start,
+            index_count: indices.end - indices.start,
+            index_type,
+            slot,
+        });
+    }
+
+    /// Writes object slots into the instance buffer and returns the batches.
+    pub fn finish(self, ctx: &RenderGraphNodeContext<'_, '_>) -> Result<DrawBatches<'a>> {
+        let (batches, slots) = self.build();
+        if slots.is_empty() {
+            return Ok(DrawBatches {
+                instance_buffer: None,
+                batches,
+            });
+        }
+
+        let mut arena = ctx.state.multi_buffer_arena.begin::<u32>(
+            &ctx.state.device,
+            slots.len(),
+            gfx::BufferUsage::STORAGE,
+        )?;
+        for slot in slots {
+            arena.write(slot);
+        }
+        let instance_buffer = ctx.state.multi_buffer_arena.end(
+            &ctx.state.device,
+            &ctx.state.bindless_resources,
+            arena,
+        )?;
+
+        Ok(DrawBatches {
+            instance_buffer: Some(instance_buffer),
+            batches,
+        })
+    }
+
+    /// Sorts draws by mesh and merges them into batches.
+    ///
+    /// Returns batches and object slots in the instance order.
+    fn build(self) -> (&'a [DrawBatch], &'a [u32]) {
+        let draws = self.draws.into_bump_slice_mut();
+        // NOTE: Objects with the same mesh share the index range, vertex
+        // attribute offsets are read from the object data of each instance.
+        draws.sort_unstable_by_key(|draw| {
+            (
+                draw.first_index,
+                draw.index_count,
+                draw.index_type as u8,
+                draw.slot,
+            )
+        });
+
+        let mut batches = bumpalo::collections::Vec::<DrawBatch>::new_in(self.alloc);
+        for (instance, draw) in draws.iter().enumerate() {
+            let instance = instance as u32;
+            match batches.last_mut() {
+                Some(batch)
+                    if batch.indices.start == draw.first_index
+                        && batch.indices.len() as u32 == draw.index_count
+                        && batch.index_type == draw.index_type =>
+                {
+                    batch.instances.end += 1;
+                }
+                _ => batches.push(DrawBatch {
+                    indices: draw.first_index..draw.first_index + draw.index_count,
+                    index_type: draw.index_type,
+                    instances: instance..instance + 1,
+                }),
+            }
+        }
+
+        let slots = self
+            .alloc
+            .alloc_slice_fill_iter(draws.iter().map(|draw| draw.slot));
+        (batches.into_bump_slice(), slots)
+    }
+}
+
+pub struct DrawBatches<'a> {
+    /// Object slots of all batches, `None` if there are no batches.
+    pub instance_buffer: Option<StorageBufferHandle>,
+    pub batches: &'a [DrawBatch],
+}
+
+impl DrawBatches<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+}
+
+/// Instanced draw of the same mesh.
+pub struct DrawBatch {
+    pub indices: Range<u32>,
+    pub index_type: gfx::IndexType,
+    /// Range of object slots in the instance buffer.
+    pub instances: Range<u32>,
+}
+
+struct ObjectDraw {
+    first_index: u32,
+    index_count: u32,
+    index_type: gfx::IndexType,
+    slot: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_meshes_are_batched() {
+        let alloc = Bump::new();
+        let mut batcher = DrawBatcher::new(&alloc);
+
+        let cube = 0..36;
+        let sphere = 36..996;
+        batcher.push(sphere.clone(), gfx::IndexType::U32, 4);
+        batcher.push(cube.clone(), gfx::IndexType::U16, 3);
+        batcher.push(sphere.clone(), gfx::IndexType::U32, 1);
+        batcher.push(cube.clone(), gfx::IndexType::U16, 0);
+        batcher.push(cube.clone(), gfx::IndexType::U16, 2);
+
+        let (batches, slots) = batcher.build();
+        assert_eq!(slots, [0, 2, 3, 1, 4]);
+        assert_eq!(batches.len(), 2);
+
+        assert_eq!(batches[0].indices, cube);
+        assert_eq!(batches[0].index_type, gfx::IndexType::U16);
+        assert_eq!(batches[0].instances, 0..3);
+
+        assert_eq!(batches[1].indices, sphere);
+        assert_eq!(batches[1].index_type, gfx::IndexType::U32);
+        assert_eq!(batches[1].instances, 3..5);
+    }
+
+    #[test]
+    fn index_types_are_not_mixed() {
+        let alloc = Bump::new();
+        let mut batcher = DrawBatcher::new(&alloc);
+
+        batcher.push(0..6, gfx::IndexType::U16, 0);
+        batcher.push(0..6, gfx::IndexType::U32, 1);
+
+        let (batches, slots) = batcher.build();
+        assert_eq!(slots.len(), 2);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].instances, 0..1);
+        assert_eq!(batches[1].instances, 1..2);
+    }
+}
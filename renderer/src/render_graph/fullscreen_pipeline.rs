@@ -0,0 +1,88 @@
+use anyhow::Result;
+
+use crate::render_graph::RenderGraphNodeContext;
+use crate::util::{CachedGraphicsPipeline, RenderPassEncoderExt, ShaderPreprocessor};
+
+/// Pipeline which runs a fragment shader for each texel of the target.
+///
+/// A single triangle covering the whole target is generated in the vertex
+/// shader, so no vertex buffers are needed. Fragment shaders usually read
+/// their inputs with `texelFetch` at `gl_FragCoord.xy`.
+pub struct FullscreenPipeline {
+    pipeline: CachedGraphicsPipeline,
+    fragment_shader: &'static str,
+}
+
+impl FullscreenPipeline {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        fragment_shader: &'static str,
+    ) -> Result<Self> {
+        let descr = make_pipeline_descr(device, pipeline_layout, shaders, fragment_shader)?;
+        Ok(Self {
+            pipeline: CachedGraphicsPipeline::new(descr),
+            fragment_shader,
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipeline.
+    ///
+    /// NOTE: The previous pipeline is kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<()> {
+        let pipeline_layout = self.pipeline.descr().layout.clone();
+        let descr = make_pipeline_descr(device, &pipeline_layout, shaders, self.fragment_shader)?;
+        self.pipeline.update_descr(device, descr)
+    }
+
+    /// Binds the pipeline, pushes constants and draws the fullscreen triangle.
+    pub fn draw(
+        &mut self,
+        ctx: &mut RenderGraphNodeContext<'_, '_>,
+        push_constants: &[u32],
+    ) -> Result<()> {
+        ctx.encoder
+            .bind_cached_graphics_pipeline(&mut self.pipeline, &ctx.state.device)?;
+        ctx.encoder.push_constants(
+            ctx.graphics_pipeline_layout,
+            gfx::ShaderStageFlags::ALL,
+            0,
+            push_constants,
+        );
+        ctx.draw(0..3, 0..1);
+        Ok(())
+    }
+}
+
+fn make_pipeline_descr(
+    device: &gfx::Device,
+    pipeline_layout: &gfx::PipelineLayout,
+    shaders: &ShaderPreprocessor,
+    fragment_shader: &str,
+) -> Result<gfx::GraphicsPipelineDescr> {
+    let shaders = shaders.begin();
+
+    let vertex_shader = shaders.make_vertex_shader(device, "fullscreen.vert", "main")?;
+    let fragment_shader = shaders.make_fragment_shader(device, fragment_shader, "main")?;
+
+    Ok(gfx::GraphicsPipelineDescr {
+        vertex_bindings: Vec::new(),
+        vertex_attributes: Vec::new(),
+        primitive_topology: gfx::PrimitiveTopology::TriangleList,
+        primitive_restart_enable: false,
+        vertex_shader,
+        rasterizer: Some(gfx::Rasterizer {
+            fragment_shader: Some(fragment_shader),
+            front_face: gfx::FrontFace::CCW,
+            cull_mode: None,
+            depth_test: None,
+            ..Default::default()
+        }),
+        layout: pipeline_layout.clone(),
+    })
+}
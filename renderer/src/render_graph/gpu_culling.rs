@@ -0,0 +1,474 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+
+use crate::managers::{IndexTypeCounts, ObjectBuffers};
+use crate::render_graph::{RenderGraphContext, RenderGraphNode};
+use crate::types::{MaterialInstance, ALL_OBJECT_LAYERS};
+use crate::util::{FrameResourcesGuard, ShaderPreprocessor, StorageBufferHandle};
+use crate::RendererState;
+
+/// Frustum culling of static objects in a compute shader.
+///
+/// Visible objects are written as indirect draw commands into a list per
+/// bucket and index type, so that nodes can draw them with one
+/// indirect count draw per list.
+pub struct GpuCulling {
+    pipeline_layout: gfx::PipelineLayout,
+    pipeline: gfx::ComputePipeline,
+    /// Stats readbacks of the frames in flight, in the frame order.
+    pending_stats: VecDeque<gfx::ReadbackTicket>,
+}
+
+impl GpuCulling {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<Self> {
+        let pipeline = Self::make_pipeline(device, pipeline_layout, shaders)?;
+        Ok(Self {
+            pipeline_layout: pipeline_layout.clone(),
+            pipeline,
+            pending_stats: VecDeque::new(),
+        })
+    }
+
+    /// Recompiles the culling shader.
+    ///
+    /// NOTE: The previous pipeline is kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<()> {
+        self.pipeline = Self::make_pipeline(device, &self.pipeline_layout, shaders)?;
+        Ok(())
+    }
+
+    fn make_pipeline(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<gfx::ComputePipeline> {
+        let shader = shaders
+            .begin()
+            .make_compute_shader(device, "gpu_culling.comp", "main")?;
+
+        Ok(device.create_compute_pipeline(gfx::ComputePipelineInfo {
+            shader,
+            layout: pipeline_layout.clone(),
+        })?)
+    }
+
+    /// Records stats of the completed frames, see [`RendererState::stats`].
+    pub fn poll_stats(&mut self, state: &RendererState) -> Result<()> {
+        while let Some(ticket) = self.pending_stats.front() {
+            let Some(data) = state.readback_pool.poll(ticket)? else {
+                break;
+            };
+            self.pending_stats.pop_front();
+
+            let [drawn_objects, culled_objects] = bytemuck::pod_read_unaligned::<[u32; 2]>(&data);
+            state.record_gpu_culling_stats(GpuCullingStats {
+                drawn_objects,
+                culled_objects,
+            });
+        }
+        Ok(())
+    }
+
+    /// Starts culling of the current frame.
+    ///
+    /// NOTE: Binds the culling pipeline and frame descriptor sets for the compute bind point.
+    pub fn begin<'a, 'b>(
+        &'a mut self,
+        ctx: &'a mut RenderGraphContext<'b>,
+        globals: &FrameResourcesGuard<'_>,
+    ) -> Result<GpuCullingContext<'a, 'b>> {
+        let state = ctx.state;
+
+        let mut stats = state.multi_buffer_arena.begin::<u32>(
+            &state.device,
+            2,
+            gfx::BufferUsage::STORAGE | gfx::BufferUsage::TRANSFER_SRC,
+        )?;
+        stats.write(&0);
+        stats.write(&0);
+        let (stats_buffer, stats_range) = state.multi_buffer_arena.end_with_range(
+            &state.device,
+            &state.bindless_resources,
+            stats,
+        )?;
+
+        ctx.encoder.bind_compute_pipeline(&self.pipeline);
+        ctx.encoder.bind_compute_descriptor_sets(
+            &self.pipeline_layout,
+            0,
+            &[
+                state.frame_resources.descriptor_set(),
+                state.bindless_resources.descriptor_set(),
+            ],
+            &[globals.dynamic_offset()],
+        );
+
+        // NOTE: Waits for scatter copies and compute nodes
+        ctx.encoder.memory_barrier(
+            gfx::PipelineStageFlags::COMPUTE_SHADER | gfx::PipelineStageFlags::TRANSFER,
+            gfx::AccessFlags::SHADER_WRITE | gfx::AccessFlags::TRANSFER_WRITE,
+            gfx::PipelineStageFlags::COMPUTE_SHADER,
+            gfx::AccessFlags::SHADER_READ,
+        );
+
+        Ok(GpuCullingContext {
+            ctx,
+            culling: self,
+            stats_buffer,
+            stats_range,
+            layer_mask: ALL_OBJECT_LAYERS,
+        })
+    }
+}
+
+pub struct GpuCullingContext<'a, 'b> {
+    ctx: &'a mut RenderGraphContext<'b>,
+    culling: &'a mut GpuCulling,
+    stats_buffer: StorageBufferHandle,
+    stats_range: gfx::BufferRange,
+    /// Layer mask of the currently culled node.
+    layer_mask: u32,
+}
+
+impl GpuCullingContext<'_, '_> {
+    /// Culls static objects drawn by the node.
+    pub fn cull_node<N: RenderGraphNode>(&mut self, node: &mut N) -> Result<()> {
+        self.layer_mask = node.layer_mask();
+        node.cull_static_objects(self)
+    }
+
+    /// Writes draw commands of the visible static objects with the material.
+    ///
+    /// Objects are split by the bucket returned for their material instance,
+    /// objects without a bucket are skipped. Returns `None` if there are
+    /// no static objects with the material.
+    pub fn cull<M: MaterialInstance>(
+        &mut self,
+        mut bucket_for: impl FnMut(Option<&M>) -> Option<u32>,
+    ) -> Result<Option<CulledDraws>> {
+        let state = self.ctx.state;
+        let synced_managers = self.ctx.synced_managers;
+        let Some(static_objects) = synced_managers.object_manager.iter_static_objects::<M>() else {
+            return Ok(None);
+        };
+        let object_count = static_objects.slot_count();
+        let index_types = static_objects.index_types();
+        if object_count == 0 || index_types.iter().next().is_none() {
+            return Ok(None);
+        }
+
+        let material_manager = &synced_managers.material_manager;
+        let buckets =
+            self.ctx
+                .alloc
+                .alloc_slice_fill_iter((0..material_manager.slot_count::<M>()).map(|slot| {
+                    bucket_for(material_manager.get_instance::<M>(slot)).unwrap_or(SKIPPED_BUCKET)
+                }));
+        let Some(bucket_count) = buckets
+            .iter()
+            .filter(|bucket| **bucket != SKIPPED_BUCKET)
+            .max()
+            .map(|bucket| bucket + 1)
+        else {
+            return Ok(None);
+        };
+        let layout = CommandsLayout {
+            bucket_count,
+            object_count,
+        };
+
+        let objects = static_objects.buffers();
+        let data_stride = static_objects.data_stride() / 4;
+
+        let mut input = state.multi_buffer_arena.begin::<u32>(
+            &state.device,
+            INPUT_BUCKETS + buckets.len(),
+            gfx::BufferUsage::STORAGE,
+        )?;
+        // NOTE: Must be in sync with `INPUT_*` in `gpu_culling.comp`
+        input.write(&objects.transforms.index());
+        input.write(&objects.data.index());
+        input.write(&(data_stride as u32));
+        input.write(&object_count);
+        input.write(&self.layer_mask);
+        input.write(&(buckets.len() as u32));
+        input.write(&layout.header_words());
+        for bucket in &*buckets {
+            input.write(bucket);
+        }
+        let input_buffer =
+            state
+                .multi_buffer_arena
+                .end(&state.device, &state.bindless_resources, input)?;
+
+        // NOTE: Only draw counts are zeroed, commands are written by the shader
+        let total_words = layout.total_words();
+        let mut output = state.multi_buffer_arena.begin::<u32>(
+            &state.device,
+            total_words,
+            gfx::BufferUsage::STORAGE | gfx::BufferUsage::INDIRECT,
+        )?;
+        for _ in 0..layout.header_words() {
+            output.write(&0);
+        }
+        output.write_at(total_words - 1, &0);
+        let (output_buffer, output_range) = state.multi_buffer_arena.end_with_range(
+            &state.device,
+            &state.bindless_resources,
+            output,
+        )?;
+
+        self.ctx.encoder.push_constants(
+            &self.culling.pipeline_layout,
+            gfx::ShaderStageFlags::ALL,
+            0,
+            &[
+                input_buffer.index(),
+                output_buffer.index(),
+                self.stats_buffer.index(),
+            ],
+        );
+        self.ctx
+            .encoder
+            .dispatch(object_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+
+        Ok(Some(CulledDraws {
+            objects,
+            buffer: output_range,
+            layout,
+            index_types,
+        }))
+    }
+
+    /// Makes the draw commands visible to indirect draws
+    /// and records the readback of the frame stats.
+    pub fn finish(self) -> Result<()> {
+        self.ctx.encoder.memory_barrier(
+            gfx::PipelineStageFlags::COMPUTE_SHADER,
+            gfx::AccessFlags::SHADER_WRITE,
+            gfx::PipelineStageFlags::DRAW_INDIRECT,
+            gfx::AccessFlags::INDIRECT_COMMAND_READ,
+        );
+
+        let ticket = self
+            .ctx
+            .encoder
+            .download_buffer(&self.stats_range, &self.ctx.state.readback_pool)?;
+        self.culling.pending_stats.push_back(ticket);
+        Ok(())
+    }
+}
+
+/// Draw commands of the static objects of a material which passed the culling.
+pub struct CulledDraws {
+    objects: ObjectBuffers,
+    /// Draw counts followed by the draw commands of all lists.
+    buffer: gfx::BufferRange,
+    layout: CommandsLayout,
+    index_types: IndexTypeCounts,
+}
+
+impl CulledDraws {
+    pub fn objects(&self) -> ObjectBuffers {
+        self.objects
+    }
+
+    /// Returns index types of the culled objects,
+    /// lists of other index types are always empty.
+    pub fn index_types(&self) -> impl Iterator<Item = gfx::IndexType> {
+        self.index_types.iter()
+    }
+
+    /// Returns `true` if any material instance was assigned to the bucket.
+    pub fn has_bucket(&self, bucket: u32) -> bool {
+        bucket < self.layout.bucket_count
+    }
+
+    /// Records the indirect draw of the objects in the bucket with the index type.
+    ///
+    /// NOTE: The index buffer must be bound with the same index type.
+    pub fn draw_indexed_indirect(
+        &self,
+        encoder: &mut gfx::RenderPassEncoder<'_, '_>,
+        bucket: u32,
+        index_type: gfx::IndexType,
+    ) {
+        let list = CommandsLayout::list(bucket, index_type);
+        encoder.draw_indexed_indirect_count(
+            &self.buffer.buffer,
+            self.buffer.offset + self.layout.commands_offset(list),
+            &self.buffer.buffer,
+            self.buffer.offset + self.layout.count_offset(list),
+            self.layout.object_count,
+            COMMAND_SIZE as u32,
+        );
+    }
+}
+
+/// Counters of the last frame culled on the GPU, see [`RendererStats::gpu_culling`].
+///
+/// [`RendererStats::gpu_culling`]: crate::RendererStats::gpu_culling
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GpuCullingStats {
+    /// Number of static objects which passed the frustum test.
+    pub drawn_objects: u32,
+    /// Number of static objects outside of the view frustum.
+    pub culled_objects: u32,
+}
+
+/// Layout of the culling output in words.
+///
+/// Draw counts of all lists are followed by the commands of each list,
+/// with room for all objects in every list.
+#[derive(Debug, Clone, Copy)]
+struct CommandsLayout {
+    bucket_count: u32,
+    object_count: u32,
+}
+
+impl CommandsLayout {
+    /// Returns the list of objects with the bucket and index type.
+    ///
+    /// NOTE: Must match `list` in `gpu_culling.comp`.
+    fn list(bucket: u32, index_type: gfx::IndexType) -> u32 {
+        let index_type = match index_type {
+            gfx::IndexType::U16 => 0,
+            gfx::IndexType::U32 => 1,
+        };
+        bucket * 2 + index_type
+    }
+
+    fn list_count(&self) -> u32 {
+        self.bucket_count * 2
+    }
+
+    fn header_words(&self) -> u32 {
+        self.list_count()
+    }
+
+    fn total_words(&self) -> usize {
+        self.header_words() as usize
+            + self.list_count() as usize * self.object_count as usize * COMMAND_WORDS
+    }
+
+    /// Returns the byte offset of the draw count of the list.
+    fn count_offset(&self, list: u32) -> usize {
+        list as usize * 4
+    }
+
+    /// Returns the byte offset of the first command of the list.
+    fn commands_offset(&self, list: u32) -> usize {
+        self.header_words() as usize * 4 + list as usize * self.object_count as usize * COMMAND_SIZE
+    }
+}
+
+const SKIPPED_BUCKET: u32 = u32::MAX;
+const INPUT_BUCKETS: usize = 7;
+const COMMAND_SIZE: usize = std::mem::size_of::<gfx::DrawIndexedIndirectCommand>();
+const COMMAND_WORDS: usize = COMMAND_SIZE / 4;
+// NOTE: Must be in sync with `local_size_x` in `gpu_culling.comp`
+const WORKGROUP_SIZE: u32 = 64;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_lists_do_not_overlap() {
+        let layout = CommandsLayout {
+            bucket_count: 3,
+            object_count: 10,
+        };
+        assert_eq!(layout.total_words(), 6 + 6 * 10 * 5);
+
+        // Counts are placed before all commands
+        for list in 0..layout.list_count() {
+            assert!(layout.count_offset(list) + 4 <= layout.commands_offset(0));
+        }
+
+        // Each list fits all objects
+        for list in 1..layout.list_count() {
+            let prev_end = layout.commands_offset(list - 1) + 10 * COMMAND_SIZE;
+            assert_eq!(prev_end, layout.commands_offset(list));
+        }
+        let last = layout.list_count() - 1;
+        assert_eq!(
+            layout.commands_offset(last) + 10 * COMMAND_SIZE,
+            layout.total_words() * 4
+        );
+
+        assert_eq!(CommandsLayout::list(1, gfx::IndexType::U16), 2);
+        assert_eq!(CommandsLayout::list(1, gfx::IndexType::U32), 3);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn objects_outside_of_the_frustum_are_culled() {
+        use std::time::{Duration, Instant};
+
+        use glam::{Mat4, Vec3};
+
+        use crate::render_graph::materials::DebugMaterialInstance;
+        use crate::types::{CameraProjection, CubeMeshGenerator, Mesh};
+        use crate::RendererBuilder;
+
+        let renderer = RendererBuilder::headless(64, 64)
+            .gpu_culling(true)
+            .build()
+            .unwrap();
+        let state = renderer.state();
+        if !state.is_gpu_culling_enabled() {
+            return;
+        }
+
+        let mesh = Mesh::builder(CubeMeshGenerator::from_size(1.0))
+            .build()
+            .unwrap();
+        let mesh = state.add_mesh(&mesh).unwrap();
+        let material = state
+            .add_material_instance(DebugMaterialInstance {
+                color: Vec3::ONE,
+                double_sided: false,
+            })
+            .unwrap();
+        // NOTE: The camera looks at `-Z` from the origin
+        let _visible = state
+            .add_static_object(
+                mesh.clone(),
+                material.clone(),
+                &Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0)),
+            )
+            .unwrap();
+        let _behind = state
+            .add_static_object(
+                mesh,
+                material,
+                &Mat4::from_translation(Vec3::new(0.0, 0.0, 5.0)),
+            )
+            .unwrap();
+        state.update_camera(&Mat4::IDENTITY, &CameraProjection::default());
+
+        // NOTE: Stats are read back once the frames are completed
+        let started_at = Instant::now();
+        loop {
+            let stats = state.stats().gpu_culling;
+            if stats != GpuCullingStats::default() {
+                assert_eq!(stats.drawn_objects, 1);
+                assert_eq!(stats.culled_objects, 1);
+                break;
+            }
+            assert!(started_at.elapsed() < Duration::from_secs(10));
+            state.notify_draw();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
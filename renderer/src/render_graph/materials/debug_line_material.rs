@@ -0,0 +1,217 @@
+use anyhow::Result;
+use glam::{Vec3, Vec4};
+
+use crate::render_graph::render_passes::MainPass;
+use crate::render_graph::{RenderGraphNode, RenderGraphNodeContext};
+use crate::types::{Color, DepthMode};
+use crate::util::{CachedGraphicsPipeline, RenderPassEncoderExt, ShaderPreprocessor};
+
+/// Immediate-mode line renderer.
+///
+/// Draws all lines submitted through [`RendererState::draw_debug_lines`]
+/// since the previous frame, up to [`MAX_DEBUG_LINES`] of each kind.
+///
+/// [`RendererState::draw_debug_lines`]: crate::RendererState::draw_debug_lines
+pub struct DebugLineMaterial {
+    depth_tested_pipeline: CachedGraphicsPipeline,
+    on_top_pipeline: CachedGraphicsPipeline,
+}
+
+impl DebugLineMaterial {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<Self> {
+        let [depth_tested, on_top] =
+            Self::make_pipeline_descrs(device, pipeline_layout, shaders, depth_mode)?;
+        Ok(Self {
+            depth_tested_pipeline: CachedGraphicsPipeline::new(depth_tested),
+            on_top_pipeline: CachedGraphicsPipeline::new(on_top),
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipelines.
+    ///
+    /// NOTE: The previous pipelines are kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<()> {
+        let pipeline_layout = self.depth_tested_pipeline.descr().layout.clone();
+        let [depth_tested, on_top] =
+            Self::make_pipeline_descrs(device, &pipeline_layout, shaders, depth_mode)?;
+        self.depth_tested_pipeline
+            .update_descr(device, depth_tested)?;
+        self.on_top_pipeline.update_descr(device, on_top)
+    }
+
+    fn make_pipeline_descrs(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<[gfx::GraphicsPipelineDescr; 2]> {
+        let shaders = shaders.begin();
+
+        let vertex_shader = shaders.make_vertex_shader(device, "debug_line.vert", "main")?;
+        let fragment_shader = shaders.make_fragment_shader(device, "debug_line.frag", "main")?;
+
+        let make_descr = |depth_test: Option<gfx::DepthTest>| gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: gfx::PrimitiveTopology::LineList,
+            primitive_restart_enable: false,
+            vertex_shader: vertex_shader.clone(),
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: Some(fragment_shader.clone()),
+                front_face: gfx::FrontFace::CCW,
+                cull_mode: None,
+                depth_test,
+                ..Default::default()
+            }),
+            layout: pipeline_layout.clone(),
+        };
+
+        Ok([
+            make_descr(Some(gfx::DepthTest {
+                compare: depth_mode.compare_op(),
+                write: false,
+            })),
+            make_descr(None),
+        ])
+    }
+
+    fn draw_lines(
+        ctx: &mut RenderGraphNodeContext<'_, '_>,
+        pipeline: &mut CachedGraphicsPipeline,
+        lines: &[(Vec3, Vec3, Color)],
+    ) -> Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let vertex_count = lines.len() * 2;
+        let mut arena = ctx.state.multi_buffer_arena.begin::<DebugLineGpuVertex>(
+            &ctx.state.device,
+            vertex_count,
+            gfx::BufferUsage::STORAGE,
+        )?;
+        for (start, end, color) in lines {
+            for position in [*start, *end] {
+                arena.write(&gfx::AsStd430::as_std430(&DebugLineVertex {
+                    position,
+                    color: color.0,
+                }));
+            }
+        }
+        let vertices_buffer_handle = ctx.state.multi_buffer_arena.end(
+            &ctx.state.device,
+            &ctx.state.bindless_resources,
+            arena,
+        )?;
+
+        ctx.encoder
+            .bind_cached_graphics_pipeline(pipeline, &ctx.state.device)?;
+        ctx.encoder.push_constants(
+            ctx.graphics_pipeline_layout,
+            gfx::ShaderStageFlags::ALL,
+            0,
+            &[vertices_buffer_handle.index(), 0, 0],
+        );
+        ctx.draw(0..vertex_count as u32, 0..1);
+
+        Ok(())
+    }
+}
+
+impl RenderGraphNode for DebugLineMaterial {
+    type RenderPass = MainPass;
+
+    fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        let lines = ctx.state.take_debug_lines();
+        if lines.dropped > 0 {
+            tracing::warn!(
+                dropped = lines.dropped,
+                "too many debug lines were added since the previous frame"
+            );
+        }
+
+        Self::draw_lines(ctx, &mut self.depth_tested_pipeline, &lines.depth_tested)?;
+        Self::draw_lines(ctx, &mut self.on_top_pipeline, &lines.on_top)?;
+
+        Ok(())
+    }
+}
+
+/// Maximum number of lines of each kind drawn in a frame.
+///
+/// NOTE: Lines are accumulated until the next frame is drawn,
+/// which may not happen for a long time (e.g. while minimized).
+pub const MAX_DEBUG_LINES: usize = 1 << 16;
+
+/// Lines accumulated for the next frame.
+#[derive(Default)]
+pub struct DebugLines {
+    pub depth_tested: Vec<(Vec3, Vec3, Color)>,
+    pub on_top: Vec<(Vec3, Vec3, Color)>,
+    /// Number of lines which didn't fit into [`MAX_DEBUG_LINES`].
+    pub dropped: usize,
+}
+
+impl DebugLines {
+    pub fn add_depth_tested(&mut self, lines: &[(Vec3, Vec3, Color)]) {
+        self.dropped += extend_capped(&mut self.depth_tested, lines);
+    }
+
+    pub fn add_on_top(&mut self, lines: &[(Vec3, Vec3, Color)]) {
+        self.dropped += extend_capped(&mut self.on_top, lines);
+    }
+}
+
+/// Appends lines which fit into [`MAX_DEBUG_LINES`], returns the number of dropped lines.
+fn extend_capped(dst: &mut Vec<(Vec3, Vec3, Color)>, lines: &[(Vec3, Vec3, Color)]) -> usize {
+    let len = lines.len().min(MAX_DEBUG_LINES.saturating_sub(dst.len()));
+    dst.extend_from_slice(&lines[..len]);
+    lines.len() - len
+}
+
+type DebugLineGpuVertex = <DebugLineVertex as gfx::AsStd430>::Output;
+
+#[derive(gfx::AsStd430)]
+pub struct DebugLineVertex {
+    position: Vec3,
+    color: Vec4,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_capped_until_taken() {
+        let line = (Vec3::ZERO, Vec3::ONE, Color(Vec4::ONE));
+        let lines = vec![line; MAX_DEBUG_LINES - 1];
+
+        let mut debug_lines = DebugLines::default();
+        debug_lines.add_depth_tested(&lines);
+        debug_lines.add_depth_tested(&[line; 3]);
+        assert_eq!(debug_lines.depth_tested.len(), MAX_DEBUG_LINES);
+        assert_eq!(debug_lines.dropped, 2);
+
+        // Lists are capped separately
+        debug_lines.add_on_top(&[line; 3]);
+        assert_eq!(debug_lines.on_top.len(), 3);
+        assert_eq!(debug_lines.dropped, 2);
+
+        // Lines of the next frame are accumulated from scratch
+        let taken = std::mem::take(&mut debug_lines);
+        debug_lines.add_depth_tested(&[line]);
+        assert_eq!(debug_lines.depth_tested.len(), 1);
+        assert_eq!(debug_lines.dropped, 0);
+        assert_eq!(taken.dropped, 2);
+    }
+}
@@ -1,14 +1,26 @@
 use anyhow::Result;
 use glam::Vec3;
 
-use crate::managers::GpuObject;
 use crate::render_graph::render_passes::MainPass;
-use crate::render_graph::{RenderGraphNode, RenderGraphNodeContext};
-use crate::types::{MaterialInstance, Sorting, VertexAttributeArray, VertexAttributeKind};
-use crate::util::{CachedGraphicsPipeline, RenderPassEncoderExt, ShaderPreprocessor};
-
+use crate::render_graph::{
+    cull_by_render_state, draw_by_render_state, CulledDraws, DebugViewPipelines, GpuCullingContext,
+    MaterialPipelines, RenderGraphNode, RenderGraphNodeContext,
+};
+use crate::types::{
+    DebugView, DepthMode, MaterialAttributePolicy, MaterialInstance, MaterialRenderState,
+    ShaderDataContext, Sorting, VertexAttributeKind,
+};
+use crate::util::ShaderPreprocessor;
+
+/// Single color material.
+///
+/// NOTE: Supports [`MaterialRenderState`] overrides of the instances.
 pub struct DebugMaterial {
-    pipeline: CachedGraphicsPipeline,
+    pipelines: DebugViewPipelines<MaterialPipelines>,
+    /// Static objects culled on the GPU for the current frame,
+    /// bucketed by the index of their render state in `culled_states`.
+    culled: Option<CulledDraws>,
+    culled_states: Vec<MaterialRenderState>,
 }
 
 impl DebugMaterial {
@@ -16,31 +28,81 @@ impl DebugMaterial {
         device: &gfx::Device,
         pipeline_layout: &gfx::PipelineLayout,
         shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
     ) -> Result<Self> {
-        let shaders = shaders.begin();
+        let pipelines = DebugViewPipelines::new(|debug_view| {
+            let descr = Self::make_pipeline_descr(
+                device,
+                pipeline_layout,
+                shaders,
+                depth_mode,
+                debug_view,
+            )?;
+            Ok(MaterialPipelines::new(descr, depth_mode))
+        })?;
+        Ok(Self {
+            pipelines,
+            culled: None,
+            culled_states: Vec::new(),
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipelines of all debug views and render states.
+    ///
+    /// NOTE: The previous pipelines are kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<()> {
+        let pipeline_layout = self
+            .pipelines
+            .get(DebugView::None)
+            .base_descr()
+            .layout
+            .clone();
+        self.pipelines.update(
+            |debug_view| {
+                Self::make_pipeline_descr(device, &pipeline_layout, shaders, depth_mode, debug_view)
+            },
+            |pipeline, descr| pipeline.update_descr(device, descr),
+        )
+    }
+
+    fn make_pipeline_descr(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+        debug_view: DebugView,
+    ) -> Result<gfx::GraphicsPipelineDescr> {
+        let mut shaders = shaders.begin();
+        if let Some(define) = debug_view.shader_define() {
+            shaders.define(define);
+        }
 
         let vertex_shader = shaders.make_vertex_shader(device, "opaque_mesh.vert", "main")?;
         let fragment_shader = shaders.make_fragment_shader(device, "opaque_mesh.frag", "main")?;
 
-        Ok(Self {
-            pipeline: CachedGraphicsPipeline::new(gfx::GraphicsPipelineDescr {
-                vertex_bindings: Vec::new(),
-                vertex_attributes: Vec::new(),
-                primitive_topology: Default::default(),
-                primitive_restart_enable: false,
-                vertex_shader,
-                rasterizer: Some(gfx::Rasterizer {
-                    fragment_shader: Some(fragment_shader),
-                    front_face: gfx::FrontFace::CCW,
-                    cull_mode: Some(gfx::CullMode::Back),
-                    depth_test: Some(gfx::DepthTest {
-                        compare: gfx::CompareOp::Less,
-                        write: true,
-                    }),
-                    ..Default::default()
+        Ok(gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: Default::default(),
+            primitive_restart_enable: false,
+            vertex_shader,
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: Some(fragment_shader),
+                polygin_mode: debug_view.polygon_mode(),
+                front_face: gfx::FrontFace::CCW,
+                cull_mode: Some(gfx::CullMode::Back),
+                depth_test: Some(gfx::DepthTest {
+                    compare: depth_mode.compare_op(),
+                    write: true,
                 }),
-                layout: pipeline_layout.clone(),
+                ..Default::default()
             }),
+            layout: pipeline_layout.clone(),
         })
     }
 }
@@ -49,102 +111,38 @@ impl RenderGraphNode for DebugMaterial {
     type RenderPass = MainPass;
 
     fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
-        let Some(material_instances_buffer) =
-            ctx.synced_managers
-                .material_manager
-                .materials_data_buffer_handle::<DebugMaterialInstance>()
-        else {
-            return Ok(());
-        };
-
-        let frustum = &ctx.globals.frustum;
-
-        ctx.encoder
-            .bind_cached_graphics_pipeline(&mut self.pipeline, &ctx.state.device)?;
-
-        if let Some(static_objects) = ctx
-            .synced_managers
-            .object_manager
-            .iter_static_objects::<DebugMaterialInstance>()
-        {
-            ctx.encoder.push_constants(
-                ctx.graphics_pipeline_layout,
-                gfx::ShaderStageFlags::ALL,
-                0,
-                &[
-                    ctx.state.mesh_manager.vertex_buffer_handle().index(),
-                    static_objects.buffer_handle().index(),
-                    material_instances_buffer.index(),
-                ],
-            );
-
-            for (slot, object) in static_objects {
-                if !frustum.contains_sphere(&object.global_bounding_sphere) {
-                    continue;
-                }
-
-                ctx.encoder.draw_indexed(
-                    object.first_index..object.first_index + object.index_count,
-                    0,
-                    slot..slot + 1,
-                );
-            }
-        }
-
-        if let Some(dynamic_objects) = ctx
-            .synced_managers
-            .object_manager
-            .iter_dynamic_objects::<DebugMaterialInstance>()
-            .filter(|iter| iter.len() > 0)
-        {
-            let mut arena = ctx.state.multi_buffer_arena.begin::<DebugGpuObject>(
-                &ctx.state.device,
-                dynamic_objects.len(),
-                gfx::BufferUsage::STORAGE,
-            )?;
-
-            // TODO: make it one iteration
-            for object in dynamic_objects.clone() {
-                arena.write(&object.as_interpolated_std430(ctx.interpolation_factor));
-            }
-
-            let objects_buffer_handle = ctx.state.multi_buffer_arena.end(
-                &ctx.state.device,
-                &ctx.state.bindless_resources,
-                arena,
-            );
-
-            ctx.encoder.push_constants(
-                ctx.graphics_pipeline_layout,
-                gfx::ShaderStageFlags::ALL,
-                0,
-                &[
-                    ctx.state.mesh_manager.vertex_buffer_handle().index(),
-                    objects_buffer_handle.index(),
-                    material_instances_buffer.index(),
-                ],
-            );
-
-            for (slot, object) in dynamic_objects.enumerate() {
-                ctx.encoder.draw_indexed(
-                    object.first_index..object.first_index + object.index_count(),
-                    0,
-                    slot as u32..slot as u32 + 1,
-                );
-            }
-        }
+        let pipelines = self.pipelines.get_mut(ctx.debug_view);
+        draw_by_render_state::<DebugMaterialInstance>(
+            ctx,
+            pipelines,
+            self.culled.take(),
+            &self.culled_states,
+            render_state,
+        )
+    }
 
+    fn cull_static_objects(&mut self, culling: &mut GpuCullingContext<'_, '_>) -> Result<()> {
+        self.culled = cull_by_render_state(culling, &mut self.culled_states, render_state)?;
         Ok(())
     }
 }
 
-type DebugGpuObject = GpuObject<
-    <<DebugMaterialInstance as MaterialInstance>::SupportedAttributes as VertexAttributeArray>::U32Array
->;
+fn render_state(material: Option<&DebugMaterialInstance>) -> Option<MaterialRenderState> {
+    Some(
+        material
+            .map(DebugMaterialInstance::render_state)
+            .unwrap_or_default(),
+    )
+}
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct DebugMaterialInstance {
+    /// Linear RGB color, see [`Color::from_srgb`].
+    ///
+    /// [`Color::from_srgb`]: crate::Color::from_srgb
     pub color: Vec3,
+    /// Disables back-face culling.
+    pub double_sided: bool,
 }
 
 impl MaterialInstance for DebugMaterialInstance {
@@ -164,6 +162,9 @@ impl MaterialInstance for DebugMaterialInstance {
             VertexAttributeKind::Color,
         ]
     }
+    fn attribute_policy() -> MaterialAttributePolicy {
+        MaterialAttributePolicy::Fallback
+    }
 
     fn key(&self) -> u64 {
         0
@@ -173,7 +174,18 @@ impl MaterialInstance for DebugMaterialInstance {
         Sorting::OPAQUE
     }
 
-    fn shader_data(&self) -> Self::ShaderDataType {
+    fn render_state(&self) -> MaterialRenderState {
+        MaterialRenderState {
+            cull_mode: (!self.double_sided).then_some(gfx::CullMode::Back),
+            ..Default::default()
+        }
+    }
+
+    fn shader_data(&self, _: &ShaderDataContext<'_>) -> Self::ShaderDataType {
         gfx::AsStd430::as_std430(&self.color)
     }
+
+    fn is_unchanged(&self, prev: &Self) -> bool {
+        self == prev
+    }
 }
@@ -0,0 +1,220 @@
+use anyhow::Result;
+use glam::{IVec2, UVec2, Vec2};
+
+use crate::render_graph::render_passes::OverlayPass;
+use crate::render_graph::{RenderGraphNode, RenderGraphNodeContext};
+use crate::util::{CachedGraphicsPipeline, RenderPassEncoderExt, ShaderPreprocessor};
+
+/// Draws meshes set by [`RendererState::set_overlay`] on top of the frame.
+///
+/// Each mesh is drawn with its own scissor rect.
+///
+/// [`RendererState::set_overlay`]: crate::RendererState::set_overlay
+pub struct OverlayMaterial {
+    pipeline: CachedGraphicsPipeline,
+}
+
+impl OverlayMaterial {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<Self> {
+        let descr = Self::make_pipeline_descr(device, pipeline_layout, shaders)?;
+        Ok(Self {
+            pipeline: CachedGraphicsPipeline::new(descr),
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipeline.
+    ///
+    /// NOTE: The previous pipeline is kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<()> {
+        let pipeline_layout = self.pipeline.descr().layout.clone();
+        let descr = Self::make_pipeline_descr(device, &pipeline_layout, shaders)?;
+        self.pipeline.update_descr(device, descr)
+    }
+
+    fn make_pipeline_descr(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<gfx::GraphicsPipelineDescr> {
+        let shaders = shaders.begin();
+
+        let vertex_shader = shaders.make_vertex_shader(device, "overlay.vert", "main")?;
+        let fragment_shader = shaders.make_fragment_shader(device, "overlay.frag", "main")?;
+
+        Ok(gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: gfx::PrimitiveTopology::TriangleList,
+            primitive_restart_enable: false,
+            vertex_shader,
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: Some(fragment_shader),
+                front_face: gfx::FrontFace::CCW,
+                cull_mode: None,
+                depth_test: None,
+                // NOTE: Overlay colors are premultiplied
+                color_blend: gfx::ColorBlend::Blending {
+                    blending: Some(gfx::Blending {
+                        color_src_factor: gfx::BlendFactor::One,
+                        color_dst_factor: gfx::BlendFactor::OneMinusSrcAlpha,
+                        color_op: gfx::BlendOp::Add,
+                        alpha_src_factor: gfx::BlendFactor::OneMinusDstAlpha,
+                        alpha_dst_factor: gfx::BlendFactor::One,
+                        alpha_op: gfx::BlendOp::Add,
+                    }),
+                    write_mask: gfx::ComponentMask::RGBA,
+                    constants: gfx::State::Static([0.0; 4]),
+                },
+                ..Default::default()
+            }),
+            layout: pipeline_layout.clone(),
+        })
+    }
+}
+
+impl RenderGraphNode for OverlayMaterial {
+    type RenderPass = OverlayPass;
+
+    fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        let state = ctx.state;
+        let overlay = state.overlay();
+        if overlay.is_empty() {
+            return Ok(());
+        }
+
+        let vertex_count = overlay.iter().map(|mesh| mesh.vertices.len()).sum();
+        let index_count = overlay.iter().map(|mesh| mesh.indices.len()).sum();
+
+        let mut vertices = ctx.state.multi_buffer_arena.begin::<OverlayGpuVertex>(
+            &ctx.state.device,
+            vertex_count,
+            gfx::BufferUsage::STORAGE,
+        )?;
+        let mut indices = ctx.state.multi_buffer_arena.begin::<u32>(
+            &ctx.state.device,
+            index_count,
+            gfx::BufferUsage::STORAGE,
+        )?;
+
+        // NOTE: Meshes are merged into the same buffers, so indices
+        // are offset by the number of preceding vertices.
+        let mut base_vertex = 0;
+        for mesh in overlay.iter() {
+            for vertex in &mesh.vertices {
+                vertices.write(&gfx::AsStd430::as_std430(&OverlayShaderVertex {
+                    position: vertex.position,
+                    uv: vertex.uv,
+                    color: u32::from_le_bytes(vertex.color),
+                }));
+            }
+            for index in &mesh.indices {
+                indices.write(&(base_vertex + index));
+            }
+            base_vertex += mesh.vertices.len() as u32;
+        }
+
+        let vertices_buffer_handle = ctx.state.multi_buffer_arena.end(
+            &ctx.state.device,
+            &ctx.state.bindless_resources,
+            vertices,
+        )?;
+        let indices_buffer_handle = ctx.state.multi_buffer_arena.end(
+            &ctx.state.device,
+            &ctx.state.bindless_resources,
+            indices,
+        )?;
+
+        ctx.encoder
+            .bind_cached_graphics_pipeline(&mut self.pipeline, &ctx.state.device)?;
+
+        let target_extent: UVec2 = ctx.encoder.framebuffer().info().extent;
+        let textures = state.texture_manager.lock_data();
+
+        let mut first_index = 0;
+        for mesh in overlay.iter() {
+            let indices = first_index..first_index + mesh.indices.len() as u32;
+            first_index = indices.end;
+
+            let Some(scissor) = clamp_rect(mesh.clip_rect, target_extent) else {
+                continue;
+            };
+            let texture_index = match &mesh.texture {
+                Some(texture) => match textures.bindless_index(texture.raw()) {
+                    Some(index) => index,
+                    None => continue,
+                },
+                None => u32::MAX,
+            };
+
+            ctx.encoder.set_scissor(&scissor);
+            ctx.encoder.push_constants(
+                ctx.graphics_pipeline_layout,
+                gfx::ShaderStageFlags::ALL,
+                0,
+                &[
+                    vertices_buffer_handle.index(),
+                    indices_buffer_handle.index(),
+                    texture_index,
+                ],
+            );
+            ctx.draw(indices, 0..1);
+        }
+
+        Ok(())
+    }
+}
+
+/// Intersects the rect with the target bounds, returns `None` if nothing is left.
+fn clamp_rect(rect: gfx::Rect, target_extent: UVec2) -> Option<gfx::Rect> {
+    let min = rect.offset.max(IVec2::ZERO);
+    let max = (rect.offset + rect.extent.as_ivec2()).min(target_extent.as_ivec2());
+    if min.x >= max.x || min.y >= max.y {
+        return None;
+    }
+    Some(gfx::Rect {
+        offset: min,
+        extent: (max - min).as_uvec2(),
+    })
+}
+
+type OverlayGpuVertex = <OverlayShaderVertex as gfx::AsStd430>::Output;
+
+#[derive(gfx::AsStd430)]
+struct OverlayShaderVertex {
+    position: Vec2,
+    uv: Vec2,
+    color: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_rect_is_clamped_to_target() {
+        let target = glam::uvec2(100, 50);
+        let rect = |x, y, w, h| gfx::Rect {
+            offset: glam::ivec2(x, y),
+            extent: glam::uvec2(w, h),
+        };
+
+        assert_eq!(
+            clamp_rect(rect(10, 10, 20, 20), target),
+            Some(rect(10, 10, 20, 20))
+        );
+        assert_eq!(
+            clamp_rect(rect(-10, 40, 200, 20), target),
+            Some(rect(0, 40, 100, 10))
+        );
+        assert_eq!(clamp_rect(rect(100, 0, 10, 10), target), None);
+        assert_eq!(clamp_rect(rect(0, 0, 0, 10), target), None);
+    }
+}
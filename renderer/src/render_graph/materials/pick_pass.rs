@@ -0,0 +1,466 @@
+use anyhow::Result;
+
+use crate::managers::{GpuObjectTransform, ObjectBuffers};
+use crate::render_graph::materials::{
+    DebugMaterialInstance, StandardMaterialInstance, TexturedMaterialInstance,
+};
+use crate::render_graph::render_passes::PickTargetPass;
+use crate::render_graph::{
+    DrawBatcher, DrawBatches, RenderGraphContext, RenderGraphNode, RenderGraphNodeContext,
+};
+use crate::types::{
+    DepthMode, MaterialInstance, PendingPick, PickRequest, PickResult, RawDynamicObjectHandle,
+    RawStaticObjectHandle,
+};
+use crate::util::{CachedGraphicsPipeline, RenderPassEncoderExt, ShaderPreprocessor};
+
+/// Draws pick ids of all mesh objects and reads back ids under pick requests.
+///
+/// Pick id contains the object slot in the lower 24 bits and the index of
+/// the drawn object group (plus one) in the upper 8 bits, zero means no object.
+///
+/// NOTE: Slots are reused after objects are removed, so handles of each group
+/// are copied when the ids are drawn and the ids are resolved with this copy.
+pub struct PickPass {
+    pipeline: CachedGraphicsPipeline,
+    /// Objects of the groups drawn in the current frame, indexed by the group.
+    groups: Vec<PickGroup>,
+    /// Copies of picked texels which are not completed yet, in frame order.
+    readbacks: Vec<PickReadback>,
+    free_buffers: Vec<gfx::Buffer>,
+}
+
+struct PickReadback {
+    frame: u32,
+    buffer: gfx::Buffer,
+    requests: Vec<PickRequest>,
+    groups: Vec<PickGroup>,
+}
+
+/// Handles of objects with the same material and kind, indexed by the slot.
+enum PickGroup {
+    Static(Vec<Option<RawStaticObjectHandle>>),
+    Dynamic(Vec<Option<RawDynamicObjectHandle>>),
+}
+
+impl PickPass {
+    /// Maximum number of requests which are resolved from one frame,
+    /// the rest is postponed to the next frames.
+    pub const MAX_PICKS_PER_FRAME: usize = 64;
+
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<Self> {
+        let descr = Self::make_pipeline_descr(device, pipeline_layout, shaders, depth_mode)?;
+        Ok(Self {
+            pipeline: CachedGraphicsPipeline::new(descr),
+            groups: Vec::new(),
+            readbacks: Vec::new(),
+            free_buffers: Vec::new(),
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipeline.
+    ///
+    /// NOTE: The previous pipeline is kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<()> {
+        let pipeline_layout = self.pipeline.descr().layout.clone();
+        let descr = Self::make_pipeline_descr(device, &pipeline_layout, shaders, depth_mode)?;
+        self.pipeline.update_descr(device, descr)
+    }
+
+    fn make_pipeline_descr(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<gfx::GraphicsPipelineDescr> {
+        let shaders = shaders.begin();
+        let vertex_shader = shaders.make_vertex_shader(device, "pick.vert", "main")?;
+        let fragment_shader = shaders.make_fragment_shader(device, "pick.frag", "main")?;
+
+        Ok(gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: Default::default(),
+            primitive_restart_enable: false,
+            vertex_shader,
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: Some(fragment_shader),
+                front_face: gfx::FrontFace::CCW,
+                // NOTE: Both faces are drawn so that objects can be
+                // picked from the inside as well.
+                cull_mode: None,
+                depth_test: Some(gfx::DepthTest {
+                    compare: depth_mode.compare_op(),
+                    write: true,
+                }),
+                ..Default::default()
+            }),
+            layout: pipeline_layout.clone(),
+        })
+    }
+
+    /// Resolves requests of all readbacks which were completed by the `completed_frame`.
+    pub fn resolve_completed(
+        &mut self,
+        ctx: &RenderGraphContext<'_>,
+        completed_frame: u32,
+    ) -> Result<()> {
+        let completed = self
+            .readbacks
+            .iter()
+            .take_while(|readback| readback.frame <= completed_frame)
+            .count();
+
+        for readback in self.readbacks.drain(..completed) {
+            let mut ids = vec![0u8; readback.requests.len() * PICK_ID_SIZE];
+            ctx.state.device.download_from_memory(
+                &mut readback.buffer.as_mappable(),
+                0,
+                &mut ids,
+            )?;
+
+            for (request, id) in std::iter::zip(&readback.requests, ids.chunks_exact(PICK_ID_SIZE))
+            {
+                let id = u32::from_ne_bytes(id.try_into().unwrap());
+                request.resolve(resolve_pick_id(ctx, &readback.groups, id));
+            }
+            self.free_buffers.push(readback.buffer);
+        }
+
+        Ok(())
+    }
+
+    /// Copies pick ids under the requests into a readback buffer.
+    ///
+    /// NOTE: Must be recorded after the pick target pass of the `frame`,
+    /// object groups drawn by the pass are resolved with this readback.
+    pub fn encode_readback(
+        &mut self,
+        device: &gfx::Device,
+        encoder: &mut gfx::Encoder,
+        target: &gfx::Image,
+        frame: u32,
+        picks: Vec<PendingPick>,
+    ) -> Result<()> {
+        let buffer = match self.free_buffers.pop() {
+            Some(buffer) => buffer,
+            None => device.create_mappable_buffer(
+                gfx::BufferInfo {
+                    align_mask: 0b11,
+                    size: Self::MAX_PICKS_PER_FRAME * PICK_ID_SIZE,
+                    usage: gfx::BufferUsage::TRANSFER_DST,
+                    label: Some("pick readback"),
+                },
+                gfx::MemoryUsage::DOWNLOAD,
+            )?,
+        };
+
+        let regions = picks
+            .iter()
+            .enumerate()
+            .map(|(i, pick)| gfx::BufferImageCopy {
+                buffer_offset: i * PICK_ID_SIZE,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: gfx::ImageSubresourceLayers::all_layers(target.info(), 0),
+                image_offset: pick.position.as_ivec2().extend(0),
+                image_extent: glam::UVec3::ONE,
+            })
+            .collect::<Vec<_>>();
+
+        encoder.copy_image_to_buffer(
+            target,
+            gfx::ImageLayout::TransferSrcOptimal,
+            &buffer,
+            &regions,
+        );
+        encoder.memory_barrier(
+            gfx::PipelineStageFlags::TRANSFER,
+            gfx::AccessFlags::TRANSFER_WRITE,
+            gfx::PipelineStageFlags::HOST,
+            gfx::AccessFlags::HOST_READ,
+        );
+
+        self.readbacks.push(PickReadback {
+            frame,
+            buffer,
+            requests: picks.into_iter().map(|pick| pick.request).collect(),
+            groups: std::mem::take(&mut self.groups),
+        });
+        Ok(())
+    }
+}
+
+impl RenderGraphNode for PickPass {
+    type RenderPass = PickTargetPass;
+
+    fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        ctx.encoder
+            .bind_cached_graphics_pipeline(&mut self.pipeline, &ctx.state.device)?;
+
+        self.groups.clear();
+        draw_pickable::<DebugMaterialInstance>(ctx, &mut self.groups)?;
+        draw_pickable::<TexturedMaterialInstance>(ctx, &mut self.groups)?;
+        draw_pickable::<StandardMaterialInstance>(ctx, &mut self.groups)?;
+
+        Ok(())
+    }
+}
+
+/// Draws pick ids of all objects with the material `M`
+/// and appends their handles to the `groups`.
+fn draw_pickable<M: MaterialInstance>(
+    ctx: &mut RenderGraphNodeContext<'_, '_>,
+    groups: &mut Vec<PickGroup>,
+) -> Result<()> {
+    let frustum = &ctx.globals.frustum;
+    let frustum_culling = ctx.state.is_frustum_culling_enabled();
+
+    if let Some(static_objects) = ctx
+        .synced_managers
+        .object_manager
+        .iter_static_objects::<M>()
+    {
+        let buffers = static_objects.buffers();
+
+        let mut draws = DrawBatcher::new(ctx.alloc);
+        for (slot, object) in static_objects {
+            if !ctx.is_in_layers(object.layers) || slot > MAX_PICK_SLOT {
+                continue;
+            }
+            if frustum_culling && !frustum.contains_sphere(&object.global_bounding_sphere) {
+                continue;
+            }
+
+            draws.push(
+                object.first_index..object.first_index + object.index_count,
+                object.index_type,
+                slot,
+            );
+        }
+
+        let batches = draws.finish(ctx)?;
+        if !batches.is_empty() {
+            let Some(pick_id_base) = pick_id_base(groups.len()) else {
+                return Ok(());
+            };
+            let handles = ctx
+                .synced_managers
+                .object_manager
+                .static_slot_handles::<M>();
+            groups.push(PickGroup::Static(handles.to_vec()));
+            draw_pick_batches(ctx, buffers, pick_id_base, &batches);
+        }
+    }
+
+    if let Some(dynamic_objects) = ctx
+        .synced_managers
+        .object_manager
+        .iter_dynamic_objects::<M>()
+        .filter(|iter| iter.len() > 0)
+    {
+        let data_buffer = dynamic_objects.data_buffer_handle();
+        let mut transforms = ctx.begin_dynamic_object_transforms(dynamic_objects.slot_count())?;
+
+        let mut draws = DrawBatcher::new(ctx.alloc);
+        for (slot, object) in dynamic_objects {
+            if !ctx.is_in_layers(object.layers) || slot > MAX_PICK_SLOT {
+                continue;
+            }
+
+            let transform = object.interpolated_transform(ctx.interpolation_factor);
+            let bounding_sphere = object.mesh_bounding_sphere.transformed(&transform);
+            if frustum_culling && !frustum.contains_sphere(&bounding_sphere) {
+                continue;
+            }
+
+            transforms.write_at(
+                slot as usize,
+                &GpuObjectTransform::new(transform, bounding_sphere),
+            );
+            draws.push(
+                object.first_index..object.first_index + object.index_count,
+                object.index_type,
+                slot,
+            );
+        }
+
+        let buffers = ctx.end_dynamic_object_transforms(transforms, data_buffer)?;
+        let batches = draws.finish(ctx)?;
+        if !batches.is_empty() {
+            let Some(pick_id_base) = pick_id_base(groups.len()) else {
+                return Ok(());
+            };
+            let handles = ctx
+                .synced_managers
+                .object_manager
+                .dynamic_slot_handles::<M>();
+            groups.push(PickGroup::Dynamic(handles.to_vec()));
+            draw_pick_batches(ctx, buffers, pick_id_base, &batches);
+        }
+    }
+
+    Ok(())
+}
+
+/// Draws batches with the pick id base in place of the material buffer.
+fn draw_pick_batches(
+    ctx: &mut RenderGraphNodeContext<'_, '_>,
+    objects: ObjectBuffers,
+    pick_id_base: u32,
+    batches: &DrawBatches<'_>,
+) {
+    ctx.encoder.push_constants(
+        ctx.graphics_pipeline_layout,
+        gfx::ShaderStageFlags::ALL,
+        0,
+        &[
+            ctx.state.mesh_manager.vertex_buffer_handle().index(),
+            objects.transforms.index(),
+            objects.data.index(),
+            pick_id_base,
+            batches
+                .instance_buffer
+                .map_or(u32::MAX, |buffer| buffer.index()),
+        ],
+    );
+    for batch in batches.batches {
+        ctx.draw_indexed(
+            batch.index_type,
+            batch.indices.clone(),
+            batch.instances.clone(),
+        );
+    }
+}
+
+/// Returns the object which was drawn with the pick `id` using handles of the drawn `groups`.
+fn resolve_pick_id(ctx: &RenderGraphContext<'_>, groups: &[PickGroup], id: u32) -> PickResult {
+    let Some((group, slot)) = decode_pick_id(id) else {
+        return PickResult::Nothing;
+    };
+
+    // NOTE: Objects removed since the frame are not upgraded
+    let result = match groups.get(group as usize) {
+        Some(PickGroup::Static(handles)) => handles
+            .get(slot as usize)
+            .copied()
+            .flatten()
+            .and_then(|handle| ctx.state.upgrade_static_object_handle(handle))
+            .map(PickResult::Static),
+        Some(PickGroup::Dynamic(handles)) => handles
+            .get(slot as usize)
+            .copied()
+            .flatten()
+            .and_then(|handle| ctx.state.upgrade_dynamic_object_handle(handle))
+            .map(PickResult::Dynamic),
+        None => None,
+    };
+    result.unwrap_or(PickResult::Nothing)
+}
+
+/// Returns the pick id of the slot zero of the `group`,
+/// or `None` if there are too many groups.
+fn pick_id_base(group: usize) -> Option<u32> {
+    let group = u32::try_from(group)
+        .ok()
+        .filter(|&group| group < MAX_PICK_GROUPS)?;
+    Some((group + 1) << PICK_SLOT_BITS)
+}
+
+/// Returns the group of the object and its slot.
+fn decode_pick_id(id: u32) -> Option<(u32, u32)> {
+    let group = (id >> PICK_SLOT_BITS).checked_sub(1)?;
+    Some((group, id & MAX_PICK_SLOT))
+}
+
+const PICK_ID_SIZE: usize = std::mem::size_of::<u32>();
+const PICK_SLOT_BITS: u32 = 24;
+/// Objects in higher slots are not drawn and can't be picked.
+const MAX_PICK_SLOT: u32 = (1 << PICK_SLOT_BITS) - 1;
+/// Groups after this number are not drawn and can't be picked.
+const MAX_PICK_GROUPS: u32 = (1 << (32 - PICK_SLOT_BITS)) - 1;
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3};
+
+    use super::*;
+    use crate::types::{CameraProjection, CubeMeshGenerator, Mesh};
+    use crate::worker::capture::{capture_frame, capture_uploaded_frame};
+    use crate::RendererBuilder;
+
+    #[test]
+    fn pick_ids_roundtrip() {
+        assert_eq!(decode_pick_id(0), None);
+
+        for group in [0, 1, MAX_PICK_GROUPS - 1] {
+            for slot in [0, 1, MAX_PICK_SLOT] {
+                let id = pick_id_base(group as usize).unwrap() | slot;
+                assert_ne!(id, 0);
+                assert_eq!(decode_pick_id(id), Some((group, slot)));
+            }
+        }
+        assert_eq!(pick_id_base(MAX_PICK_GROUPS as usize), None);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn removed_objects_are_not_picked_through_reused_slots() {
+        const SIZE: u32 = 64;
+        const FRAMES_IN_FLIGHT: usize = 2;
+
+        let renderer = RendererBuilder::headless(SIZE, SIZE).build().unwrap();
+        let state = renderer.state();
+
+        let mesh = Mesh::builder(CubeMeshGenerator::from_size(1.0))
+            .build()
+            .unwrap();
+        let mesh = state.add_mesh(&mesh).unwrap();
+        let material = state
+            .add_material_instance(DebugMaterialInstance {
+                color: Vec3::ONE,
+                double_sided: false,
+            })
+            .unwrap();
+        let picked = state
+            .add_static_object(mesh.clone(), material.clone(), &Mat4::IDENTITY)
+            .unwrap();
+        state.update_camera(
+            &Mat4::from_translation(Vec3::new(0.0, 0.0, 3.0)).inverse(),
+            &CameraProjection::default(),
+        );
+
+        capture_uploaded_frame(state);
+
+        // Draw ids of the object without resolving them
+        let request = state.pick(SIZE / 2, SIZE / 2);
+        capture_frame(state);
+        assert!(!request.is_ready());
+
+        // The new object takes the slot of the removed one before the request is resolved
+        drop(picked);
+        let _replacement = state
+            .add_static_object(mesh, material, &Mat4::IDENTITY)
+            .unwrap();
+
+        // NOTE: The request is resolved by the frame which reuses the fence
+        // of the frame with the pick, which could be drawn after the captured one.
+        for _ in 0..=FRAMES_IN_FLIGHT {
+            if request.is_ready() {
+                break;
+            }
+            capture_frame(state);
+        }
+        assert_eq!(request.try_get(), Some(PickResult::Nothing));
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::Result;
+
+use crate::managers::GpuObjectTransform;
+use crate::render_graph::materials::{
+    DebugMaterialInstance, StandardMaterialInstance, TexturedMaterialInstance,
+};
+use crate::render_graph::render_passes::ShadowMapPass;
+use crate::render_graph::{DrawBatcher, RenderGraphNode, RenderGraphNodeContext};
+use crate::types::{DepthMode, MaterialInstance, ShadowBias};
+use crate::util::{CachedGraphicsPipeline, Frustum, RenderPassEncoderExt, ShaderPreprocessor};
+
+/// Draws depth of shadow casters of all mesh materials from the directional light.
+///
+/// NOTE: Caster depth is offset by the [`ShadowBias`] in the rasterizer state,
+/// the pipeline is updated when the bias changes.
+pub struct ShadowPass {
+    pipeline: CachedGraphicsPipeline,
+    bias: ShadowBias,
+}
+
+impl ShadowPass {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        bias: ShadowBias,
+    ) -> Result<Self> {
+        let descr = Self::make_pipeline_descr(device, pipeline_layout, shaders, &bias)?;
+        Ok(Self {
+            pipeline: CachedGraphicsPipeline::new(descr),
+            bias,
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipeline.
+    ///
+    /// NOTE: The previous pipeline is kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<()> {
+        let pipeline_layout = self.pipeline.descr().layout.clone();
+        let descr = Self::make_pipeline_descr(device, &pipeline_layout, shaders, &self.bias)?;
+        self.pipeline.update_descr(device, descr)
+    }
+
+    fn update_bias(&mut self, device: &gfx::Device, bias: ShadowBias) -> Result<()> {
+        if self.bias == bias {
+            return Ok(());
+        }
+
+        let mut descr = self.pipeline.descr().clone();
+        if let Some(rasterizer) = &mut descr.rasterizer {
+            rasterizer.depth_bias = Some(make_depth_bias(&bias));
+        }
+        self.pipeline.update_descr(device, descr)?;
+        self.bias = bias;
+        Ok(())
+    }
+
+    fn make_pipeline_descr(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        bias: &ShadowBias,
+    ) -> Result<gfx::GraphicsPipelineDescr> {
+        let vertex_shader = shaders
+            .begin()
+            .make_vertex_shader(device, "shadow.vert", "main")?;
+
+        Ok(gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: Default::default(),
+            primitive_restart_enable: false,
+            vertex_shader,
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: None,
+                front_face: gfx::FrontFace::CCW,
+                // NOTE: Both faces are drawn so that single-sided
+                // and open meshes still cast shadows.
+                cull_mode: None,
+                depth_test: Some(gfx::DepthTest {
+                    compare: gfx::CompareOp::Less,
+                    write: true,
+                }),
+                depth_bias: Some(make_depth_bias(bias)),
+                ..Default::default()
+            }),
+            layout: pipeline_layout.clone(),
+        })
+    }
+}
+
+impl RenderGraphNode for ShadowPass {
+    type RenderPass = ShadowMapPass;
+
+    fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        self.update_bias(&ctx.state.device, ctx.state.frame_resources.shadow_bias())?;
+
+        // NOTE: The shadow map is rendered with the standard depth
+        // regardless of the depth mode of the main pass.
+        let frustum = Frustum::new(ctx.globals.light_view_projection, DepthMode::Standard);
+
+        ctx.encoder
+            .bind_cached_graphics_pipeline(&mut self.pipeline, &ctx.state.device)?;
+
+        draw_casters::<DebugMaterialInstance>(ctx, &frustum)?;
+        draw_casters::<TexturedMaterialInstance>(ctx, &frustum)?;
+        draw_casters::<StandardMaterialInstance>(ctx, &frustum)?;
+
+        Ok(())
+    }
+}
+
+/// Draws all shadow casters with the material `M`.
+fn draw_casters<M: MaterialInstance>(
+    ctx: &mut RenderGraphNodeContext<'_, '_>,
+    frustum: &Frustum,
+) -> Result<()> {
+    let material_manager = &ctx.synced_managers.material_manager;
+    let Some(material_instances_buffer) = material_manager.materials_data_buffer_handle::<M>()
+    else {
+        return Ok(());
+    };
+
+    let frustum_culling = ctx.state.is_frustum_culling_enabled();
+
+    if let Some(static_objects) = ctx
+        .synced_managers
+        .object_manager
+        .iter_static_objects::<M>()
+    {
+        let buffers = static_objects.buffers();
+
+        let mut draws = DrawBatcher::new(ctx.alloc);
+        for (slot, object) in static_objects {
+            if !object.cast_shadows || !ctx.is_in_layers(object.layers) {
+                continue;
+            }
+            if frustum_culling && !frustum.contains_sphere(&object.global_bounding_sphere) {
+                continue;
+            }
+
+            draws.push(
+                object.first_index..object.first_index + object.index_count,
+                object.index_type,
+                slot,
+            );
+        }
+
+        let batches = draws.finish(ctx)?;
+        ctx.draw_batches(buffers, material_instances_buffer, &batches);
+    }
+
+    if let Some(dynamic_objects) = ctx
+        .synced_managers
+        .object_manager
+        .iter_dynamic_objects::<M>()
+        .filter(|iter| iter.len() > 0)
+    {
+        let data_buffer = dynamic_objects.data_buffer_handle();
+        let mut transforms = ctx.begin_dynamic_object_transforms(dynamic_objects.slot_count())?;
+
+        let mut draws = DrawBatcher::new(ctx.alloc);
+        for (slot, object) in dynamic_objects {
+            if !object.cast_shadows || !ctx.is_in_layers(object.layers) {
+                continue;
+            }
+
+            let transform = object.interpolated_transform(ctx.interpolation_factor);
+            let bounding_sphere = object.mesh_bounding_sphere.transformed(&transform);
+            if frustum_culling && !frustum.contains_sphere(&bounding_sphere) {
+                continue;
+            }
+
+            transforms.write_at(
+                slot as usize,
+                &GpuObjectTransform::new(transform, bounding_sphere),
+            );
+            draws.push(
+                object.first_index..object.first_index + object.index_count,
+                object.index_type,
+                slot,
+            );
+        }
+
+        let buffers = ctx.end_dynamic_object_transforms(transforms, data_buffer)?;
+        let batches = draws.finish(ctx)?;
+        ctx.draw_batches(buffers, material_instances_buffer, &batches);
+    }
+
+    Ok(())
+}
+
+fn make_depth_bias(bias: &ShadowBias) -> gfx::DepthBias {
+    gfx::DepthBias {
+        constant_factor: bias.constant_factor,
+        clamp: 0.0,
+        slope_factor: bias.slope_factor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3};
+
+    use crate::render_graph::materials::DebugMaterialInstance;
+    use crate::types::{
+        CameraProjection, CubeMeshGenerator, Mesh, ObjectOptions, PlaneMeshGenerator,
+    };
+    use crate::worker::capture::render_captured_frame;
+    use crate::{CapturedFrame, RendererBuilder};
+
+    const SIZE: u32 = 64;
+
+    /// Renders a cube above a plane lit from the left, seen from the top.
+    fn render_cube_above_plane(cast_shadows: bool) -> CapturedFrame {
+        render_captured_frame(RendererBuilder::headless(SIZE, SIZE), |state| {
+            let plane = Mesh::builder(PlaneMeshGenerator::from_size(8.0))
+                .build()
+                .unwrap();
+            let plane = state.add_mesh(&plane).unwrap();
+            let cube = Mesh::builder(CubeMeshGenerator::from_size(1.0))
+                .build()
+                .unwrap();
+            let cube = state.add_mesh(&cube).unwrap();
+            let material = state
+                .add_material_instance(DebugMaterialInstance {
+                    color: Vec3::ONE,
+                    double_sided: true,
+                })
+                .unwrap();
+
+            state.set_directional_light(Vec3::new(1.0, -1.0, 0.0), Vec3::ONE, 8.0);
+            state.update_camera(
+                &Mat4::look_at_rh(Vec3::new(0.0, 5.0, 0.0), Vec3::ZERO, Vec3::NEG_Z),
+                &CameraProjection::default(),
+            );
+
+            let plane = state
+                .add_static_object(plane, material.clone(), &Mat4::IDENTITY)
+                .unwrap();
+            let cube = state
+                .add_static_object_with_options(
+                    cube,
+                    material,
+                    &Mat4::from_translation(Vec3::new(0.0, 1.0, 0.0)),
+                    ObjectOptions {
+                        cast_shadows,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            (plane, cube)
+        })
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn only_casters_shadow_the_plane() {
+        // NOTE: The shadow of the cube covers the plane from x=0 to x=2,
+        // the pixels sample the plane at x=1.5 and its mirror at x=-1.5.
+        let brightness = |pixel: [u8; 4]| pixel[..3].iter().map(|&c| c as u32).sum::<u32>();
+        let (shadowed, lit) = ((48, SIZE / 2), (15, SIZE / 2));
+
+        let frame = render_cube_above_plane(true);
+        assert!(
+            brightness(frame.pixel(shadowed.0, shadowed.1)) < brightness(frame.pixel(lit.0, lit.1))
+        );
+
+        let frame = render_cube_above_plane(false);
+        assert_eq!(
+            frame.pixel(shadowed.0, shadowed.1),
+            frame.pixel(lit.0, lit.1)
+        );
+    }
+}
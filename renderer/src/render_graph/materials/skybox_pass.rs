@@ -0,0 +1,98 @@
+use anyhow::Result;
+
+use crate::render_graph::render_passes::MainPass;
+use crate::render_graph::{RenderGraphNode, RenderGraphNodeContext};
+use crate::types::DepthMode;
+use crate::util::{CachedGraphicsPipeline, RenderPassEncoderExt, ShaderPreprocessor};
+
+/// Fills the background with the cube texture set by [`RendererState::set_skybox`].
+///
+/// NOTE: Must be executed after all opaque geometry, since the sky is drawn
+/// at the far plane and only covers pixels which are still cleared.
+///
+/// [`RendererState::set_skybox`]: crate::RendererState::set_skybox
+pub struct SkyboxPass {
+    pipeline: CachedGraphicsPipeline,
+}
+
+impl SkyboxPass {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<Self> {
+        let descr = Self::make_pipeline_descr(device, pipeline_layout, shaders, depth_mode)?;
+        Ok(Self {
+            pipeline: CachedGraphicsPipeline::new(descr),
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipeline.
+    ///
+    /// NOTE: The previous pipeline is kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<()> {
+        let pipeline_layout = self.pipeline.descr().layout.clone();
+        let descr = Self::make_pipeline_descr(device, &pipeline_layout, shaders, depth_mode)?;
+        self.pipeline.update_descr(device, descr)
+    }
+
+    fn make_pipeline_descr(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<gfx::GraphicsPipelineDescr> {
+        let mut shaders = shaders.begin();
+        shaders.define_expr("FAR_DEPTH", format!("{:?}", depth_mode.clear_depth().0));
+
+        let vertex_shader = shaders.make_vertex_shader(device, "skybox.vert", "main")?;
+        let fragment_shader = shaders.make_fragment_shader(device, "skybox.frag", "main")?;
+
+        Ok(gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: gfx::PrimitiveTopology::TriangleList,
+            primitive_restart_enable: false,
+            vertex_shader,
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: Some(fragment_shader),
+                front_face: gfx::FrontFace::CCW,
+                cull_mode: None,
+                depth_test: Some(gfx::DepthTest {
+                    compare: depth_mode.compare_op_or_equal(),
+                    write: false,
+                }),
+                ..Default::default()
+            }),
+            layout: pipeline_layout.clone(),
+        })
+    }
+}
+
+impl RenderGraphNode for SkyboxPass {
+    type RenderPass = MainPass;
+
+    fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        let Some(texture_index) = ctx.state.texture_manager.skybox_bindless_index() else {
+            return Ok(());
+        };
+
+        ctx.encoder
+            .bind_cached_graphics_pipeline(&mut self.pipeline, &ctx.state.device)?;
+        ctx.encoder.push_constants(
+            ctx.graphics_pipeline_layout,
+            gfx::ShaderStageFlags::ALL,
+            0,
+            &[texture_index],
+        );
+        ctx.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
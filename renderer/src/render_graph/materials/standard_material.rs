@@ -0,0 +1,402 @@
+use std::ops::Range;
+
+use anyhow::Result;
+use glam::Vec4;
+
+use crate::managers::{CollectTransparentObjects, GpuObjectTransform, TransparentObjectKind};
+use crate::render_graph::render_passes::MainPass;
+use crate::render_graph::{
+    cull_by_render_state, draw_by_render_state, CulledDraws, DebugViewPipelines, GpuCullingContext,
+    MaterialPipelines, RenderGraphNode, RenderGraphNodeContext,
+};
+use crate::types::{
+    DebugView, DepthMode, MaterialAttributePolicy, MaterialInstance, MaterialRenderState,
+    ShaderDataContext, Sorting, VertexAttributeKind,
+};
+use crate::util::{RenderPassEncoderExt, ShaderPreprocessor};
+
+/// Unlit material with parameters of the glTF metallic-roughness model.
+///
+/// NOTE: Pipelines are selected by the [`MaterialRenderState`] of the instances.
+pub struct StandardMaterial {
+    pipelines: DebugViewPipelines<MaterialPipelines>,
+    /// Static opaque objects culled on the GPU for the current frame,
+    /// bucketed by the index of their render state in `culled_states`.
+    culled: Option<CulledDraws>,
+    culled_states: Vec<MaterialRenderState>,
+}
+
+impl StandardMaterial {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<Self> {
+        let pipelines = DebugViewPipelines::new(|debug_view| {
+            let descr = Self::make_pipeline_descr(
+                device,
+                pipeline_layout,
+                shaders,
+                depth_mode,
+                debug_view,
+            )?;
+            Ok(MaterialPipelines::new(descr, depth_mode))
+        })?;
+        Ok(Self {
+            pipelines,
+            culled: None,
+            culled_states: Vec::new(),
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipelines of all debug views and render states.
+    ///
+    /// NOTE: The previous pipelines are kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<()> {
+        let pipeline_layout = self
+            .pipelines
+            .get(DebugView::None)
+            .base_descr()
+            .layout
+            .clone();
+        self.pipelines.update(
+            |debug_view| {
+                Self::make_pipeline_descr(device, &pipeline_layout, shaders, depth_mode, debug_view)
+            },
+            |pipeline, descr| pipeline.update_descr(device, descr),
+        )
+    }
+
+    fn make_pipeline_descr(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+        debug_view: DebugView,
+    ) -> Result<gfx::GraphicsPipelineDescr> {
+        let mut shaders = shaders.begin();
+        shaders.define("MATERIAL_STANDARD");
+        if let Some(define) = debug_view.shader_define() {
+            shaders.define(define);
+        }
+
+        let vertex_shader = shaders.make_vertex_shader(device, "opaque_mesh.vert", "main")?;
+        let fragment_shader = shaders.make_fragment_shader(device, "opaque_mesh.frag", "main")?;
+
+        Ok(gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: Default::default(),
+            primitive_restart_enable: false,
+            vertex_shader,
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: Some(fragment_shader),
+                polygin_mode: debug_view.polygon_mode(),
+                front_face: gfx::FrontFace::CCW,
+                cull_mode: Some(gfx::CullMode::Back),
+                depth_test: Some(gfx::DepthTest {
+                    compare: depth_mode.compare_op(),
+                    write: true,
+                }),
+                ..Default::default()
+            }),
+            layout: pipeline_layout.clone(),
+        })
+    }
+}
+
+impl RenderGraphNode for StandardMaterial {
+    type RenderPass = MainPass;
+
+    fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        let pipelines = self.pipelines.get_mut(ctx.debug_view);
+        draw_by_render_state::<StandardMaterialInstance>(
+            ctx,
+            pipelines,
+            self.culled.take(),
+            &self.culled_states,
+            opaque_render_state,
+        )
+    }
+
+    fn cull_static_objects(&mut self, culling: &mut GpuCullingContext<'_, '_>) -> Result<()> {
+        // NOTE: Blended objects are sorted on the CPU in `execute_transparent`
+        self.culled = cull_by_render_state(culling, &mut self.culled_states, opaque_render_state)?;
+        Ok(())
+    }
+
+    fn execute_transparent(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        let material_manager = &ctx.synced_managers.material_manager;
+        let Some(material_instances_buffer) =
+            material_manager.materials_data_buffer_handle::<StandardMaterialInstance>()
+        else {
+            return Ok(());
+        };
+
+        let object_manager = &ctx.synced_managers.object_manager;
+        let objects = object_manager.collect_transparent_objects::<StandardMaterialInstance>(
+            material_manager,
+            CollectTransparentObjects {
+                camera_view: &ctx.globals.camera_view,
+                frustum: ctx
+                    .state
+                    .is_frustum_culling_enabled()
+                    .then_some(&ctx.globals.frustum),
+                interpolation_factor: ctx.interpolation_factor,
+                layer_mask: ctx.layer_mask,
+            },
+            ctx.alloc,
+        );
+        if objects.is_empty() {
+            return Ok(());
+        }
+
+        let static_objects_buffer = object_manager
+            .iter_static_objects::<StandardMaterialInstance>()
+            .map(|iter| iter.buffers());
+
+        // NOTE: Only transforms of the blended dynamic objects are written
+        let has_dynamic_objects = objects
+            .iter()
+            .any(|object| matches!(object.kind, TransparentObjectKind::Dynamic { .. }));
+        let mut dynamic_objects_buffer = None;
+        if let Some(dynamic_objects) = object_manager
+            .iter_dynamic_objects::<StandardMaterialInstance>()
+            .filter(|_| has_dynamic_objects)
+        {
+            let data_buffer = dynamic_objects.data_buffer_handle();
+            let mut transforms =
+                ctx.begin_dynamic_object_transforms(dynamic_objects.slot_count())?;
+
+            for object in objects.iter() {
+                if let TransparentObjectKind::Dynamic {
+                    slot,
+                    transform,
+                    bounding_sphere,
+                    ..
+                } = &object.kind
+                {
+                    transforms.write_at(
+                        *slot as usize,
+                        &GpuObjectTransform::new(*transform, *bounding_sphere),
+                    );
+                }
+            }
+
+            dynamic_objects_buffer =
+                Some(ctx.end_dynamic_object_transforms(transforms, data_buffer)?);
+        }
+
+        let pipelines = self.pipelines.get_mut(ctx.debug_view);
+        let mut bound_state = None;
+        let mut bound_objects_buffer = None;
+        for object in objects.iter() {
+            let (objects_buffer, draw, material_slot) = match &object.kind {
+                TransparentObjectKind::Static { slot, object } => (
+                    static_objects_buffer,
+                    StandardDraw {
+                        indices: object.first_index..object.first_index + object.index_count,
+                        index_type: object.index_type,
+                        slot: *slot,
+                    },
+                    object.material_slot,
+                ),
+                TransparentObjectKind::Dynamic { slot, object, .. } => (
+                    dynamic_objects_buffer,
+                    StandardDraw {
+                        indices: object.first_index..object.first_index + object.index_count,
+                        index_type: object.index_type,
+                        slot: *slot,
+                    },
+                    object.material_slot,
+                ),
+            };
+            let Some(objects_buffer) = objects_buffer else {
+                continue;
+            };
+
+            // NOTE: Sorted objects are drawn in order, so the pipeline
+            // is switched only when consecutive objects differ
+            let state = material_manager
+                .get_instance::<StandardMaterialInstance>(material_slot)
+                .map(StandardMaterialInstance::render_state)
+                .unwrap_or_default();
+            if bound_state != Some(state) {
+                ctx.encoder
+                    .bind_cached_graphics_pipeline(pipelines.get_mut(&state), &ctx.state.device)?;
+                bound_state = Some(state);
+            }
+
+            // NOTE: Objects buffer is switched only when static and dynamic objects interleave
+            // NOTE: Sorted objects are not batched, the instance index is the object slot
+            if bound_objects_buffer != Some(objects_buffer) {
+                ctx.push_object_constants(objects_buffer, material_instances_buffer, None);
+                bound_objects_buffer = Some(objects_buffer);
+            }
+
+            ctx.draw_indexed(draw.index_type, draw.indices, draw.slot..draw.slot + 1);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the render state of opaque instances.
+///
+/// NOTE: Blended instances are drawn in `execute_transparent` instead.
+fn opaque_render_state(material: Option<&StandardMaterialInstance>) -> Option<MaterialRenderState> {
+    match material {
+        Some(material) if material.blending => None,
+        material => Some(
+            material
+                .map(StandardMaterialInstance::render_state)
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+struct StandardDraw {
+    indices: Range<u32>,
+    index_type: gfx::IndexType,
+    slot: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StandardMaterialInstance {
+    /// Linear RGBA color, multiplied by the vertex color if present.
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Disables back-face culling.
+    pub double_sided: bool,
+    /// Draws the object after all opaque objects with alpha blending.
+    pub blending: bool,
+}
+
+impl Default for StandardMaterialInstance {
+    fn default() -> Self {
+        Self {
+            base_color: Vec4::ONE,
+            metallic: 1.0,
+            roughness: 1.0,
+            double_sided: false,
+            blending: false,
+        }
+    }
+}
+
+#[derive(gfx::AsStd430)]
+pub struct StandardMaterialData {
+    base_color: Vec4,
+    metallic: f32,
+    roughness: f32,
+}
+
+impl MaterialInstance for StandardMaterialInstance {
+    type ShaderDataType = <StandardMaterialData as gfx::AsStd430>::Output;
+    type RequiredAttributes = [VertexAttributeKind; 1];
+    type SupportedAttributes = [VertexAttributeKind; 5];
+
+    fn required_attributes() -> Self::RequiredAttributes {
+        [VertexAttributeKind::Position]
+    }
+    fn supported_attributes() -> Self::SupportedAttributes {
+        [
+            VertexAttributeKind::Position,
+            VertexAttributeKind::Normal,
+            VertexAttributeKind::Tangent,
+            VertexAttributeKind::UV0,
+            VertexAttributeKind::Color,
+        ]
+    }
+    fn attribute_policy() -> MaterialAttributePolicy {
+        MaterialAttributePolicy::Fallback
+    }
+
+    fn key(&self) -> u64 {
+        self.double_sided as u64 | (self.blending as u64) << 1
+    }
+
+    fn sorting(&self) -> Sorting {
+        if self.blending {
+            Sorting::BLENDING
+        } else {
+            Sorting::OPAQUE
+        }
+    }
+
+    fn render_state(&self) -> MaterialRenderState {
+        if self.blending {
+            // NOTE: Blended objects are tested against the opaque depth but don't
+            // occlude each other. Back faces are not culled since they are visible
+            // through the front faces.
+            MaterialRenderState {
+                cull_mode: None,
+                depth_write: false,
+                blending: Some(gfx::Blending::ALPHA),
+                ..Default::default()
+            }
+        } else {
+            MaterialRenderState {
+                cull_mode: (!self.double_sided).then_some(gfx::CullMode::Back),
+                ..Default::default()
+            }
+        }
+    }
+
+    fn shader_data(&self, _: &ShaderDataContext<'_>) -> Self::ShaderDataType {
+        gfx::AsStd430::as_std430(&StandardMaterialData {
+            base_color: self.base_color,
+            metallic: self.metallic,
+            roughness: self.roughness,
+        })
+    }
+
+    fn is_unchanged(&self, prev: &Self) -> bool {
+        self == prev
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_states_follow_instance_flags() {
+        let opaque = StandardMaterialInstance::default();
+        let double_sided = StandardMaterialInstance {
+            double_sided: true,
+            ..Default::default()
+        };
+        let blended = StandardMaterialInstance {
+            blending: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            opaque_render_state(Some(&opaque)),
+            Some(MaterialRenderState::default())
+        );
+        assert_eq!(
+            opaque_render_state(None),
+            Some(MaterialRenderState::default())
+        );
+        assert_eq!(
+            opaque_render_state(Some(&double_sided)).map(|state| state.cull_mode),
+            Some(None)
+        );
+
+        // Blended instances are drawn only by `execute_transparent`
+        assert_eq!(opaque_render_state(Some(&blended)), None);
+        let state = blended.render_state();
+        assert_eq!(state.blending, Some(gfx::Blending::ALPHA));
+        assert!(!state.depth_write);
+        assert_eq!(state.cull_mode, None);
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::Result;
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::managers::{GpuTexture, TextureManager};
+use crate::render_graph::render_passes::OverlayPass;
+use crate::render_graph::{RenderGraphNode, RenderGraphNodeContext};
+use crate::types::Color;
+use crate::util::{
+    BindlessResources, CachedGraphicsPipeline, FontAtlas, RenderPassEncoderExt, ShaderPreprocessor,
+};
+
+/// Immediate-mode text renderer.
+///
+/// Draws all text submitted through [`RendererState::draw_text_2d`] and
+/// [`RendererState::draw_text_3d`] since the previous frame on top of it,
+/// up to [`MAX_TEXT_GLYPHS`] glyphs.
+///
+/// [`RendererState::draw_text_2d`]: crate::RendererState::draw_text_2d
+/// [`RendererState::draw_text_3d`]: crate::RendererState::draw_text_3d
+pub struct TextMaterial {
+    pipeline: CachedGraphicsPipeline,
+    atlas: GpuTexture,
+}
+
+impl TextMaterial {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        queue: &gfx::Queue,
+        texture_manager: &TextureManager,
+        bindless_resources: &BindlessResources,
+        font: &FontAtlas,
+    ) -> Result<Self> {
+        let descr = Self::make_pipeline_descr(device, pipeline_layout, shaders)?;
+
+        let atlas = texture_manager.upload_texture(
+            queue,
+            bindless_resources,
+            font.data(),
+            font.extent(),
+            gfx::Format::R8Unorm,
+        )?;
+
+        Ok(Self {
+            pipeline: CachedGraphicsPipeline::new(descr),
+            atlas,
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipeline.
+    ///
+    /// NOTE: The previous pipeline is kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<()> {
+        let pipeline_layout = self.pipeline.descr().layout.clone();
+        let descr = Self::make_pipeline_descr(device, &pipeline_layout, shaders)?;
+        self.pipeline.update_descr(device, descr)
+    }
+
+    fn make_pipeline_descr(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<gfx::GraphicsPipelineDescr> {
+        let shaders = shaders.begin();
+
+        let vertex_shader = shaders.make_vertex_shader(device, "text.vert", "main")?;
+        let fragment_shader = shaders.make_fragment_shader(device, "text.frag", "main")?;
+
+        Ok(gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: gfx::PrimitiveTopology::TriangleList,
+            primitive_restart_enable: false,
+            vertex_shader,
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: Some(fragment_shader),
+                front_face: gfx::FrontFace::CCW,
+                cull_mode: None,
+                depth_test: None,
+                // NOTE: Glyph colors are premultiplied by their coverage
+                color_blend: gfx::ColorBlend::Blending {
+                    blending: Some(gfx::Blending {
+                        color_src_factor: gfx::BlendFactor::One,
+                        color_dst_factor: gfx::BlendFactor::OneMinusSrcAlpha,
+                        color_op: gfx::BlendOp::Add,
+                        alpha_src_factor: gfx::BlendFactor::OneMinusDstAlpha,
+                        alpha_dst_factor: gfx::BlendFactor::One,
+                        alpha_op: gfx::BlendOp::Add,
+                    }),
+                    write_mask: gfx::ComponentMask::RGBA,
+                    constants: gfx::State::Static([0.0; 4]),
+                },
+                ..Default::default()
+            }),
+            layout: pipeline_layout.clone(),
+        })
+    }
+}
+
+impl RenderGraphNode for TextMaterial {
+    type RenderPass = OverlayPass;
+
+    fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        let text = ctx.state.take_text();
+        if text.dropped > 0 {
+            tracing::warn!(
+                dropped = text.dropped,
+                "too many text glyphs were added since the previous frame"
+            );
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let mut glyphs = ctx.state.multi_buffer_arena.begin::<TextGpuGlyph>(
+            &ctx.state.device,
+            text.glyphs.len(),
+            gfx::BufferUsage::STORAGE,
+        )?;
+        for glyph in &text.glyphs {
+            glyphs.write(&gfx::AsStd430::as_std430(glyph));
+        }
+        let glyphs_buffer_handle = ctx.state.multi_buffer_arena.end(
+            &ctx.state.device,
+            &ctx.state.bindless_resources,
+            glyphs,
+        )?;
+
+        ctx.encoder
+            .bind_cached_graphics_pipeline(&mut self.pipeline, &ctx.state.device)?;
+        ctx.encoder.push_constants(
+            ctx.graphics_pipeline_layout,
+            gfx::ShaderStageFlags::ALL,
+            0,
+            &[glyphs_buffer_handle.index(), self.atlas.bindless_index()],
+        );
+
+        // NOTE: Each glyph is a quad of two triangles generated in the vertex shader
+        ctx.draw(0..text.glyphs.len() as u32 * 6, 0..1);
+
+        Ok(())
+    }
+}
+
+/// Maximum number of text glyphs drawn in a frame.
+///
+/// NOTE: Glyphs are accumulated until the next frame is drawn,
+/// which may not happen for a long time (e.g. while minimized).
+pub const MAX_TEXT_GLYPHS: usize = 1 << 16;
+
+/// Glyphs of the text accumulated for the next frame.
+#[derive(Default)]
+pub struct TextGlyphs {
+    glyphs: Vec<TextGlyph>,
+    /// Number of glyphs which didn't fit into [`MAX_TEXT_GLYPHS`].
+    pub dropped: usize,
+}
+
+impl TextGlyphs {
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    /// Adds glyphs of the text with the top left corner at `position` in pixels.
+    pub fn add_2d(
+        &mut self,
+        font: &FontAtlas,
+        position: Vec2,
+        text: &str,
+        size: f32,
+        color: Color,
+    ) {
+        let anchor = position.extend(0.0).extend(0.0);
+        self.add(font, anchor, Vec2::ZERO, text, size, color);
+    }
+
+    /// Adds glyphs of the text centered at the projected `position`.
+    pub fn add_3d(
+        &mut self,
+        font: &FontAtlas,
+        position: Vec3,
+        text: &str,
+        size: f32,
+        color: Color,
+    ) {
+        let scale = size / font.glyph_size().y as f32;
+        let origin = -0.5 * scale * font.measure_text(text).as_vec2();
+        self.add(font, position.extend(1.0), origin, text, size, color);
+    }
+
+    fn add(
+        &mut self,
+        font: &FontAtlas,
+        anchor: Vec4,
+        origin: Vec2,
+        text: &str,
+        size: f32,
+        color: Color,
+    ) {
+        if !size.is_finite() || size <= 0.0 || !anchor.is_finite() {
+            return;
+        }
+
+        let scale = size / font.glyph_size().y as f32;
+        let texel_size = font.glyph_size().as_vec2();
+        let mut glyphs = font.layout_text(text);
+
+        let len = MAX_TEXT_GLYPHS.saturating_sub(self.glyphs.len());
+        self.glyphs
+            .extend(glyphs.by_ref().take(len).map(|glyph| TextGlyph {
+                anchor,
+                color: color.0,
+                offset: origin + scale * glyph.offset.as_vec2(),
+                size: scale * texel_size,
+                texel: glyph.texel.as_vec2(),
+                texel_size,
+            }));
+        self.dropped += glyphs.count();
+    }
+}
+
+type TextGpuGlyph = <TextGlyph as gfx::AsStd430>::Output;
+
+#[derive(gfx::AsStd430)]
+struct TextGlyph {
+    /// World position for 3D text (`w = 1`), or pixel position for 2D text (`w = 0`).
+    anchor: Vec4,
+    color: Vec4,
+    /// Top left corner of the quad in pixels from the projected anchor.
+    offset: Vec2,
+    /// Size of the quad in pixels.
+    size: Vec2,
+    /// Top left corner of the glyph in the atlas.
+    texel: Vec2,
+    /// Size of the glyph in the atlas.
+    texel_size: Vec2,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_scaled_and_centered() {
+        let font = FontAtlas::builtin();
+        let color = Color(Vec4::ONE);
+        let size = 2.0 * font.glyph_size().y as f32;
+
+        let mut text = TextGlyphs::default();
+        text.add_2d(&font, Vec2::new(10.0, 20.0), "a b", size, color);
+        assert_eq!(text.glyphs.len(), 2);
+        assert_eq!(text.glyphs[0].anchor, Vec4::new(10.0, 20.0, 0.0, 0.0));
+        assert_eq!(text.glyphs[0].offset, Vec2::ZERO);
+        assert_eq!(text.glyphs[0].size, 2.0 * font.glyph_size().as_vec2());
+        assert_eq!(text.glyphs[0].texel_size, font.glyph_size().as_vec2());
+        assert_eq!(text.glyphs[1].offset.x, 4.0 * font.glyph_advance().x as f32);
+
+        // 3D text is centered on its anchor
+        let mut text = TextGlyphs::default();
+        text.add_3d(&font, Vec3::ONE, "ab", size, color);
+        let first = &text.glyphs[0];
+        let last = &text.glyphs[1];
+        assert_eq!(first.anchor, Vec4::ONE);
+        assert_eq!(first.offset.x, -(last.offset.x + last.size.x));
+        assert_eq!(first.offset.y, -0.5 * first.size.y);
+
+        // Invisible text is ignored
+        let mut text = TextGlyphs::default();
+        text.add_2d(&font, Vec2::ZERO, "a", 0.0, color);
+        text.add_3d(&font, Vec3::NAN, "a", size, color);
+        assert!(text.glyphs.is_empty());
+    }
+
+    #[test]
+    fn glyphs_are_capped_until_taken() {
+        let font = FontAtlas::builtin();
+        let color = Color(Vec4::ONE);
+        let line = "a".repeat(MAX_TEXT_GLYPHS - 1);
+
+        let mut text = TextGlyphs::default();
+        text.add_2d(&font, Vec2::ZERO, &line, 9.0, color);
+        text.add_2d(&font, Vec2::ZERO, "a b c", 9.0, color);
+        assert_eq!(text.glyphs.len(), MAX_TEXT_GLYPHS);
+        // Whitespace doesn't produce glyphs
+        assert_eq!(text.dropped, 2);
+
+        // Glyphs of the next frame are accumulated from scratch
+        let taken = std::mem::take(&mut text);
+        text.add_3d(&font, Vec3::ZERO, "a", 9.0, color);
+        assert_eq!(text.glyphs.len(), 1);
+        assert_eq!(text.dropped, 0);
+        assert_eq!(taken.dropped, 2);
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::Result;
+use glam::Vec3;
+
+use crate::render_graph::render_passes::MainPass;
+use crate::render_graph::{
+    cull_by_render_state, draw_by_render_state, CulledDraws, DebugViewPipelines, GpuCullingContext,
+    MaterialPipelines, RenderGraphNode, RenderGraphNodeContext,
+};
+use crate::types::{
+    DebugView, DepthMode, MaterialAttributePolicy, MaterialInstance, MaterialRenderState,
+    SamplerOverride, ShaderDataContext, Sorting, TextureHandle, VertexAttributeKind,
+};
+use crate::util::ShaderPreprocessor;
+
+/// Material which multiplies the color by a texture.
+///
+/// NOTE: Pipelines are selected by the [`MaterialRenderState`] of the instances.
+pub struct TexturedMaterial {
+    pipelines: DebugViewPipelines<MaterialPipelines>,
+    /// Static objects culled on the GPU for the current frame,
+    /// bucketed by the index of their render state in `culled_states`.
+    culled: Option<CulledDraws>,
+    culled_states: Vec<MaterialRenderState>,
+}
+
+impl TexturedMaterial {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<Self> {
+        let pipelines = DebugViewPipelines::new(|debug_view| {
+            let descr = Self::make_pipeline_descr(
+                device,
+                pipeline_layout,
+                shaders,
+                depth_mode,
+                debug_view,
+            )?;
+            Ok(MaterialPipelines::new(descr, depth_mode))
+        })?;
+        Ok(Self {
+            pipelines,
+            culled: None,
+            culled_states: Vec::new(),
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipelines of all debug views and render states.
+    ///
+    /// NOTE: The previous pipelines are kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+    ) -> Result<()> {
+        let pipeline_layout = self
+            .pipelines
+            .get(DebugView::None)
+            .base_descr()
+            .layout
+            .clone();
+        self.pipelines.update(
+            |debug_view| {
+                Self::make_pipeline_descr(device, &pipeline_layout, shaders, depth_mode, debug_view)
+            },
+            |pipeline, descr| pipeline.update_descr(device, descr),
+        )
+    }
+
+    fn make_pipeline_descr(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+        depth_mode: DepthMode,
+        debug_view: DebugView,
+    ) -> Result<gfx::GraphicsPipelineDescr> {
+        let mut shaders = shaders.begin();
+        shaders.define("MATERIAL_TEXTURED");
+        if let Some(define) = debug_view.shader_define() {
+            shaders.define(define);
+        }
+
+        let vertex_shader = shaders.make_vertex_shader(device, "opaque_mesh.vert", "main")?;
+        let fragment_shader = shaders.make_fragment_shader(device, "opaque_mesh.frag", "main")?;
+
+        Ok(gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: Default::default(),
+            primitive_restart_enable: false,
+            vertex_shader,
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: Some(fragment_shader),
+                polygin_mode: debug_view.polygon_mode(),
+                front_face: gfx::FrontFace::CCW,
+                cull_mode: Some(gfx::CullMode::Back),
+                depth_test: Some(gfx::DepthTest {
+                    compare: depth_mode.compare_op(),
+                    write: true,
+                }),
+                ..Default::default()
+            }),
+            layout: pipeline_layout.clone(),
+        })
+    }
+}
+
+impl RenderGraphNode for TexturedMaterial {
+    type RenderPass = MainPass;
+
+    fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        let pipelines = self.pipelines.get_mut(ctx.debug_view);
+        draw_by_render_state::<TexturedMaterialInstance>(
+            ctx,
+            pipelines,
+            self.culled.take(),
+            &self.culled_states,
+            render_state,
+        )
+    }
+
+    fn cull_static_objects(&mut self, culling: &mut GpuCullingContext<'_, '_>) -> Result<()> {
+        self.culled = cull_by_render_state(culling, &mut self.culled_states, render_state)?;
+        Ok(())
+    }
+}
+
+fn render_state(material: Option<&TexturedMaterialInstance>) -> Option<MaterialRenderState> {
+    Some(
+        material
+            .map(TexturedMaterialInstance::render_state)
+            .unwrap_or_default(),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TexturedMaterialInstance {
+    /// Linear RGB color, multiplied by the texture color.
+    pub color: Vec3,
+    pub texture: TextureHandle,
+    /// Sampler used instead of the renderer defaults,
+    /// see [`RendererState::set_default_anisotropy`].
+    ///
+    /// [`RendererState::set_default_anisotropy`]: crate::RendererState::set_default_anisotropy
+    pub sampler: Option<SamplerOverride>,
+}
+
+#[derive(gfx::AsStd430)]
+pub struct TexturedMaterialData {
+    color: Vec3,
+    texture_index: u32,
+}
+
+impl MaterialInstance for TexturedMaterialInstance {
+    type ShaderDataType = <TexturedMaterialData as gfx::AsStd430>::Output;
+    type RequiredAttributes = [VertexAttributeKind; 2];
+    type SupportedAttributes = [VertexAttributeKind; 5];
+
+    fn required_attributes() -> Self::RequiredAttributes {
+        [VertexAttributeKind::Position, VertexAttributeKind::UV0]
+    }
+    fn supported_attributes() -> Self::SupportedAttributes {
+        [
+            VertexAttributeKind::Position,
+            VertexAttributeKind::Normal,
+            VertexAttributeKind::Tangent,
+            VertexAttributeKind::UV0,
+            VertexAttributeKind::Color,
+        ]
+    }
+    fn attribute_policy() -> MaterialAttributePolicy {
+        MaterialAttributePolicy::Fallback
+    }
+
+    fn key(&self) -> u64 {
+        0
+    }
+
+    fn sorting(&self) -> Sorting {
+        Sorting::OPAQUE
+    }
+
+    fn shader_data(&self, ctx: &ShaderDataContext<'_>) -> Self::ShaderDataType {
+        gfx::AsStd430::as_std430(&TexturedMaterialData {
+            color: self.color,
+            texture_index: match self.sampler {
+                Some(sampler) => ctx.texture_index_with_sampler(&self.texture, sampler),
+                None => ctx.texture_index(&self.texture),
+            },
+        })
+    }
+
+    fn is_unchanged(&self, prev: &Self) -> bool {
+        self == prev
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+
+use crate::render_graph::fullscreen_pipeline::FullscreenPipeline;
+use crate::render_graph::render_passes::OutputPass;
+use crate::render_graph::{RenderGraphNode, RenderGraphNodeContext};
+use crate::util::ShaderPreprocessor;
+
+/// Maps the HDR output of the main pass into the output target
+/// with the operator set by [`RendererState::set_tonemap`].
+///
+/// Colors are scaled by the exposure from the frame globals first,
+/// and are encoded as sRGB if the target format doesn't do it.
+///
+/// [`RendererState::set_tonemap`]: crate::RendererState::set_tonemap
+pub struct TonemapPass {
+    pipeline: FullscreenPipeline,
+    /// Bindless index of the HDR target of the current frame.
+    hdr_index: u32,
+}
+
+impl TonemapPass {
+    pub fn new(
+        device: &gfx::Device,
+        pipeline_layout: &gfx::PipelineLayout,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<Self> {
+        Ok(Self {
+            pipeline: FullscreenPipeline::new(device, pipeline_layout, shaders, "tonemap.frag")?,
+            hdr_index: u32::MAX,
+        })
+    }
+
+    /// Recompiles shaders and recreates the pipeline.
+    ///
+    /// NOTE: The previous pipeline is kept on failure.
+    pub fn reload_shaders(
+        &mut self,
+        device: &gfx::Device,
+        shaders: &ShaderPreprocessor,
+    ) -> Result<()> {
+        self.pipeline.reload_shaders(device, shaders)
+    }
+
+    /// Sets the bindless index of the HDR image sampled by the next execution.
+    pub fn set_hdr_index(&mut self, index: u32) {
+        self.hdr_index = index;
+    }
+}
+
+impl RenderGraphNode for TonemapPass {
+    type RenderPass = OutputPass;
+
+    fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        let target_format = ctx.encoder.framebuffer().info().attachments[0]
+            .info()
+            .image
+            .info()
+            .format;
+        let encode_srgb = target_format.description().ty != gfx::FormatType::Srgb;
+
+        let tonemap = ctx.state.tonemap();
+        self.pipeline.draw(
+            ctx,
+            &[self.hdr_index, tonemap.shader_index(), encode_srgb as u32],
+        )
+    }
+}
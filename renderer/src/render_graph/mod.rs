@@ -1,21 +1,82 @@
+use std::ops::Range;
 use std::time::Instant;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use bumpalo::Bump;
+use glam::UVec2;
+use shared::FastHashMap;
 
-use crate::render_graph::render_passes::MainPassInput;
-use crate::util::{EncoderExt, FlushFrameResources, FrameGlobals, RenderPass};
+use crate::managers::{GpuObjectTransform, ObjectBuffers};
+use crate::render_graph::render_passes::{
+    HdrTarget, MainPassInput, OutputPassInput, OverlayPassInput, PickTargetPassInput,
+};
+use crate::types::{
+    DebugView, DepthMode, MaterialInstance, MaterialRenderState, PendingPick, PickResult,
+    RawTextureHandle, ALL_OBJECT_LAYERS,
+};
+use crate::util::{
+    BufferArena, CachedGraphicsPipeline, EncoderExt, FlushFrameResources, FrameGlobals,
+    FrameResourcesGuard, RenderPass, RenderPassEncoderExt, StorageBufferHandle,
+};
 use crate::{RendererState, RendererStateSyncedManagers};
 
+use self::draw_batcher::{DrawBatcher, DrawBatches};
+use self::gpu_culling::{CulledDraws, GpuCulling, GpuCullingContext};
+
+pub use self::gpu_culling::GpuCullingStats;
+pub use self::render_passes::{MAX_RESOLUTION_SCALE, MIN_RESOLUTION_SCALE};
+
 pub mod materials {
+    pub use self::debug_line_material::{DebugLineMaterial, MAX_DEBUG_LINES};
     pub use self::debug_material::{DebugMaterial, DebugMaterialInstance};
+    pub use self::overlay_material::OverlayMaterial;
+    pub use self::pick_pass::PickPass;
+    pub use self::shadow_pass::ShadowPass;
+    pub use self::skybox_pass::SkyboxPass;
+    pub use self::standard_material::{StandardMaterial, StandardMaterialInstance};
+    pub use self::text_material::{TextMaterial, MAX_TEXT_GLYPHS};
+    pub use self::textured_material::{TexturedMaterial, TexturedMaterialInstance};
+    pub use self::tonemap_pass::TonemapPass;
+
+    pub(crate) use self::debug_line_material::DebugLines;
+    pub(crate) use self::text_material::TextGlyphs;
 
+    mod debug_line_material;
     mod debug_material;
+    mod overlay_material;
+    mod pick_pass;
+    mod shadow_pass;
+    mod skybox_pass;
+    mod standard_material;
+    mod text_material;
+    mod textured_material;
+    mod tonemap_pass;
+}
+
+pub mod compute_nodes {
+    pub use self::color_animation::ColorAnimationNode;
+
+    mod color_animation;
 }
 
+mod draw_batcher;
+mod fullscreen_pipeline;
+mod gpu_culling;
+
 mod render_passes {
+    pub use self::hdr_target::{HdrTarget, MAX_RESOLUTION_SCALE, MIN_RESOLUTION_SCALE};
     pub use self::main_pass::{MainPass, MainPassInput};
+    pub use self::output_pass::{OutputPass, OutputPassInput};
+    pub use self::overlay_pass::{OverlayPass, OverlayPassInput};
+    pub use self::pick_target_pass::{PickTargetPass, PickTargetPassInput};
+    pub use self::shadow_map_pass::ShadowMapPass;
 
+    mod hdr_target;
     mod main_pass;
+    mod output_pass;
+    mod overlay_pass;
+    mod pick_target_pass;
+    mod shadow_map_pass;
 }
 
 // NOTE: This is a "fixed-function" stub for now.
@@ -23,54 +84,215 @@ pub struct RenderGraph {
     graphics_pipeline_layout: gfx::PipelineLayout,
 
     // TEMP
+    shadow_map_pass: render_passes::ShadowMapPass,
     main_pass: render_passes::MainPass,
+    /// Created with the extent of the first target.
+    hdr_target: Option<HdrTarget>,
+    output_pass: render_passes::OutputPass,
+    overlay_pass: render_passes::OverlayPass,
+    pick_target_pass: render_passes::PickTargetPass,
+    /// Main passes of the render targets drawn in the previous frame.
+    render_target_passes: FastHashMap<RawTextureHandle, render_passes::MainPass>,
+    shadow_pass: materials::ShadowPass,
     debug_material: materials::DebugMaterial,
+    textured_material: materials::TexturedMaterial,
+    standard_material: materials::StandardMaterial,
+    debug_line_material: materials::DebugLineMaterial,
+    skybox_pass: materials::SkyboxPass,
+    overlay_material: materials::OverlayMaterial,
+    text_material: materials::TextMaterial,
+    tonemap_pass: materials::TonemapPass,
+    pick_pass: materials::PickPass,
+    /// Only created if GPU culling is enabled, see [`RendererState::is_gpu_culling_enabled`].
+    gpu_culling: Option<GpuCulling>,
+
+    compute_nodes: Vec<Box<dyn ComputeNode>>,
 }
 
 impl RenderGraph {
     pub fn new(state: &RendererState) -> Result<Self> {
-        let graphics_pipeline_layout =
-            state
-                .device
-                .create_pipeline_layout(gfx::PipelineLayoutInfo {
-                    sets: vec![
-                        state.frame_resources.descriptor_set_layout().clone(),
-                        state.bindless_resources.descriptor_set_layout().clone(),
-                    ],
-                    push_constants: vec![gfx::PushConstant {
-                        stages: gfx::ShaderStageFlags::ALL,
-                        offset: 0,
-                        size: 12,
-                    }],
-                })?;
+        let graphics_pipeline_layout = create_pipeline_layout(state)?;
 
-        let main_pass = render_passes::MainPass::default();
+        let shadow_map_pass =
+            render_passes::ShadowMapPass::new(&state.device, &state.bindless_resources)?;
+        let shadow_pass = materials::ShadowPass::new(
+            &state.device,
+            &graphics_pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+            state.frame_resources.shadow_bias(),
+        )?;
+        let main_pass = render_passes::MainPass::new(state.msaa_samples, state.depth_mode);
         let debug_material = materials::DebugMaterial::new(
             &state.device,
             &graphics_pipeline_layout,
-            &state.shader_preprocessor,
+            &state.shader_preprocessor.lock().unwrap(),
+            state.depth_mode,
         )?;
+        let textured_material = materials::TexturedMaterial::new(
+            &state.device,
+            &graphics_pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+            state.depth_mode,
+        )?;
+        let standard_material = materials::StandardMaterial::new(
+            &state.device,
+            &graphics_pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+            state.depth_mode,
+        )?;
+        let debug_line_material = materials::DebugLineMaterial::new(
+            &state.device,
+            &graphics_pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+            state.depth_mode,
+        )?;
+        let skybox_pass = materials::SkyboxPass::new(
+            &state.device,
+            &graphics_pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+            state.depth_mode,
+        )?;
+        let overlay_material = materials::OverlayMaterial::new(
+            &state.device,
+            &graphics_pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+        )?;
+        let text_material = materials::TextMaterial::new(
+            &state.device,
+            &graphics_pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+            &state.queue,
+            &state.texture_manager,
+            &state.bindless_resources,
+            &state.font,
+        )?;
+        let tonemap_pass = materials::TonemapPass::new(
+            &state.device,
+            &graphics_pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+        )?;
+        let pick_pass = materials::PickPass::new(
+            &state.device,
+            &graphics_pipeline_layout,
+            &state.shader_preprocessor.lock().unwrap(),
+            state.depth_mode,
+        )?;
+        let gpu_culling = if state.is_gpu_culling_enabled() {
+            Some(GpuCulling::new(
+                &state.device,
+                &graphics_pipeline_layout,
+                &state.shader_preprocessor.lock().unwrap(),
+            )?)
+        } else {
+            None
+        };
 
         Ok(Self {
             graphics_pipeline_layout,
+            shadow_map_pass,
             main_pass,
+            hdr_target: None,
+            output_pass: Default::default(),
+            overlay_pass: Default::default(),
+            pick_target_pass: render_passes::PickTargetPass::new(state.depth_mode),
+            render_target_passes: FastHashMap::default(),
+            shadow_pass,
             debug_material,
+            textured_material,
+            standard_material,
+            debug_line_material,
+            skybox_pass,
+            overlay_material,
+            text_material,
+            tonemap_pass,
+            pick_pass,
+            gpu_culling,
+            compute_nodes: Vec::new(),
         })
     }
 
+    /// Adds a node which is executed before the main pass each frame.
+    ///
+    /// NOTE: Nodes are executed in the order they were added.
+    pub fn add_compute_node(&mut self, node: Box<dyn ComputeNode>) {
+        tracing::debug!(name = node.name(), "added compute node");
+        self.compute_nodes.push(node);
+    }
+
+    /// Recompiles shaders of all graph nodes.
+    pub fn reload_shaders(&mut self, state: &RendererState) -> Result<()> {
+        let shaders = state.shader_preprocessor.lock().unwrap();
+
+        self.shadow_pass
+            .reload_shaders(&state.device, &shaders)
+            .context("failed to reload shadow pass")?;
+        self.debug_material
+            .reload_shaders(&state.device, &shaders, state.depth_mode)
+            .context("failed to reload debug material")?;
+        self.textured_material
+            .reload_shaders(&state.device, &shaders, state.depth_mode)
+            .context("failed to reload textured material")?;
+        self.standard_material
+            .reload_shaders(&state.device, &shaders, state.depth_mode)
+            .context("failed to reload standard material")?;
+        self.debug_line_material
+            .reload_shaders(&state.device, &shaders, state.depth_mode)
+            .context("failed to reload debug line material")?;
+        self.skybox_pass
+            .reload_shaders(&state.device, &shaders, state.depth_mode)
+            .context("failed to reload skybox pass")?;
+        self.overlay_material
+            .reload_shaders(&state.device, &shaders)
+            .context("failed to reload overlay material")?;
+        self.text_material
+            .reload_shaders(&state.device, &shaders)
+            .context("failed to reload text material")?;
+        self.tonemap_pass
+            .reload_shaders(&state.device, &shaders)
+            .context("failed to reload tonemap pass")?;
+        self.pick_pass
+            .reload_shaders(&state.device, &shaders, state.depth_mode)
+            .context("failed to reload pick pass")?;
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            gpu_culling
+                .reload_shaders(&state.device, &shaders)
+                .context("failed to reload gpu culling")?;
+        }
+        drop(shaders);
+
+        for node in &mut self.compute_nodes {
+            let name = node.name().to_owned();
+            node.reload_shaders(state)
+                .with_context(|| format!("failed to reload compute node {name}"))?;
+        }
+
+        Ok(())
+    }
+
     pub fn execute(&mut self, ctx: &mut RenderGraphContext<'_>) -> Result<()> {
-        profiling::scope!("render_graph");
+        profile_scope!("render_graph");
+
+        if let Some(completed_frame) = ctx.completed_frame {
+            self.pick_pass.resolve_completed(ctx, completed_frame)?;
+        }
 
-        let interpolation_factor = ctx
-            .synced_managers
-            .time_manager
-            .compute_interpolation_factor(ctx.now);
+        let interpolation_factor = ctx.interpolation_factor;
 
-        let globals = ctx.state.frame_resources.flush(FlushFrameResources {
-            render_resolution: ctx.surface_image.image().info().extent.into(),
-            delta_time: ctx.delta_time,
-            frame: ctx.frame,
-        });
+        let output_extent = UVec2::from(ctx.target.info().extent);
+        let render_extent = HdrTarget::scaled_extent(output_extent, ctx.state.resolution_scale());
+
+        let mut globals = ctx.state.frame_resources.flush(
+            &ctx.state.device,
+            FlushFrameResources {
+                render_resolution: render_extent,
+                output_resolution: output_extent,
+                delta_time: ctx.delta_time,
+                frame: ctx.frame,
+                interpolation_factor,
+                shadow_map_index: self.shadow_map_pass.bindless_index(),
+                mesh_buffer_addresses: ctx.state.mesh_manager.buffer_addresses(),
+            },
+        )?;
 
         ctx.encoder.bind_graphics_descriptor_sets(
             &self.graphics_pipeline_layout,
@@ -82,28 +304,127 @@ impl RenderGraph {
             &[globals.dynamic_offset()],
         );
 
-        ctx.state.mesh_manager.bind_index_buffer(ctx.encoder);
+        if !self.compute_nodes.is_empty() {
+            self.execute_compute_nodes(ctx, &globals)?;
+        }
 
+        // NOTE: Makes both scatter copies and compute node writes visible
         ctx.encoder.memory_barrier(
             gfx::PipelineStageFlags::COMPUTE_SHADER | gfx::PipelineStageFlags::TRANSFER,
             gfx::AccessFlags::SHADER_WRITE | gfx::AccessFlags::TRANSFER_WRITE,
-            gfx::PipelineStageFlags::VERTEX_SHADER,
+            gfx::PipelineStageFlags::VERTEX_SHADER | gfx::PipelineStageFlags::FRAGMENT_SHADER,
             gfx::AccessFlags::SHADER_READ,
         );
 
+        let mut draw_calls = 0;
+        let mut drawn_instances = 0;
+
+        if globals.has_directional_light() {
+            profile_scope!("shadow_pass");
+            ctx.encoder
+                .begin_debug_label("shadow_pass", SHADOW_PASS_LABEL_COLOR);
+
+            let encoder = ctx.encoder.with_render_pass(
+                &mut self.shadow_map_pass,
+                &(),
+                &ctx.state.render_pass_context(ctx.frame),
+            )?;
+
+            let mut node_ctx = RenderGraphNodeContext {
+                graphics_pipeline_layout: &self.graphics_pipeline_layout,
+                state: ctx.state,
+                globals: &globals,
+                synced_managers: ctx.synced_managers,
+                encoder,
+                now: ctx.now,
+                delta_time: ctx.delta_time,
+                frame: ctx.frame,
+                interpolation_factor,
+                alloc: ctx.alloc,
+                layer_mask: ALL_OBJECT_LAYERS,
+                debug_view: ctx.state.debug_view(),
+                bound_index_type: None,
+                draw_calls: 0,
+                drawn_instances: 0,
+                object_bytes_uploaded: 0,
+            };
+
+            node_ctx.execute_labeled("shadow_pass", &mut self.shadow_pass)?;
+
+            draw_calls += node_ctx.draw_calls;
+            drawn_instances += node_ctx.drawn_instances;
+            ctx.state
+                .record_frame_object_uploads(node_ctx.object_bytes_uploaded);
+
+            drop(node_ctx);
+            ctx.encoder.end_debug_label();
+        }
+
+        if ctx.state.render_targets.has_targets() {
+            let (calls, instances) = self.execute_render_targets(ctx, &mut globals)?;
+            draw_calls += calls;
+            drawn_instances += instances;
+        }
+
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            gpu_culling.poll_stats(ctx.state)?;
+        }
+        if let Some(gpu_culling) = self
+            .gpu_culling
+            .as_mut()
+            .filter(|_| ctx.state.is_frustum_culling_enabled())
+        {
+            profile_scope!("gpu_culling");
+            ctx.encoder
+                .begin_debug_label("gpu_culling", NODE_LABEL_COLOR);
+
+            // NOTE: Only static objects of the main pass are culled, dynamic
+            // objects are culled on the CPU with their interpolated transforms.
+            let mut culling = gpu_culling.begin(ctx, &globals)?;
+            culling.cull_node(&mut self.debug_material)?;
+            culling.cull_node(&mut self.textured_material)?;
+            culling.cull_node(&mut self.standard_material)?;
+            culling.finish()?;
+
+            ctx.encoder.end_debug_label();
+        }
+
+        // NOTE: The main pass renders into the HDR target at the scaled
+        // resolution, which is then tonemapped into the output target.
+        match &mut self.hdr_target {
+            Some(hdr_target) => {
+                hdr_target.resize(
+                    &ctx.state.device,
+                    &ctx.state.bindless_resources,
+                    render_extent,
+                )?;
+            }
+            None => {
+                self.hdr_target = Some(HdrTarget::new(
+                    &ctx.state.device,
+                    &ctx.state.bindless_resources,
+                    render_extent,
+                )?);
+            }
+        }
+        let hdr_target = self.hdr_target.as_ref().unwrap();
+        let hdr_image = hdr_target.image().clone();
+        self.tonemap_pass.set_hdr_index(hdr_target.bindless_index());
+
         {
-            profiling::scope!("main_pass");
+            profile_scope!("main_pass");
+            ctx.encoder
+                .begin_debug_label("main_pass", MAIN_PASS_LABEL_COLOR);
 
             let encoder = ctx.encoder.with_render_pass(
                 &mut self.main_pass,
                 &MainPassInput {
-                    max_image_count: ctx.surface_image.total_image_count(),
-                    target: ctx.surface_image.image().clone(),
+                    target: hdr_image.clone(),
                 },
-                &ctx.state.device,
+                &ctx.state.render_pass_context(ctx.frame),
             )?;
 
-            self.debug_material.execute(&mut RenderGraphNodeContext {
+            let mut node_ctx = RenderGraphNodeContext {
                 graphics_pipeline_layout: &self.graphics_pipeline_layout,
                 state: ctx.state,
                 globals: &globals,
@@ -113,27 +434,746 @@ impl RenderGraph {
                 delta_time: ctx.delta_time,
                 frame: ctx.frame,
                 interpolation_factor,
-            })?;
+                alloc: ctx.alloc,
+                layer_mask: ALL_OBJECT_LAYERS,
+                debug_view: ctx.state.debug_view(),
+                bound_index_type: None,
+                draw_calls: 0,
+                drawn_instances: 0,
+                object_bytes_uploaded: 0,
+            };
+
+            node_ctx.execute_labeled("debug_material", &mut self.debug_material)?;
+            node_ctx.execute_labeled("textured_material", &mut self.textured_material)?;
+            node_ctx.execute_labeled("standard_material", &mut self.standard_material)?;
+
+            // NOTE: Sky is drawn at the far plane behind all opaque geometry
+            node_ctx.execute_labeled("skybox_pass", &mut self.skybox_pass)?;
+
+            // NOTE: Blended objects are drawn after all opaque geometry
+            // so that they can be composed with it.
+            node_ctx
+                .encoder
+                .begin_debug_label("transparent_pass", TRANSPARENT_PASS_LABEL_COLOR);
+            node_ctx.layer_mask = self.standard_material.layer_mask();
+            let res = self.standard_material.execute_transparent(&mut node_ctx);
+            node_ctx.encoder.end_debug_label();
+            res?;
+
+            // NOTE: Lines are drawn after all opaque geometry
+            node_ctx.execute_labeled("debug_line_material", &mut self.debug_line_material)?;
+
+            draw_calls += node_ctx.draw_calls;
+            drawn_instances += node_ctx.drawn_instances;
+            ctx.state
+                .record_frame_object_uploads(node_ctx.object_bytes_uploaded);
+
+            drop(node_ctx);
+            ctx.encoder.end_debug_label();
         }
 
+        ctx.encoder.transition_image(
+            &hdr_image,
+            gfx::ImageLayout::ShaderReadOnlyOptimal,
+            gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                ..gfx::PipelineStageFlags::FRAGMENT_SHADER,
+            gfx::AccessFlags::COLOR_ATTACHMENT_WRITE..gfx::AccessFlags::SHADER_READ,
+        );
+
+        {
+            profile_scope!("tonemap_pass");
+            ctx.encoder
+                .begin_debug_label("tonemap_pass", TONEMAP_PASS_LABEL_COLOR);
+
+            let encoder = ctx.encoder.with_render_pass(
+                &mut self.output_pass,
+                &OutputPassInput {
+                    target: ctx.target.clone(),
+                },
+                &ctx.state.render_pass_context(ctx.frame),
+            )?;
+
+            let mut node_ctx = RenderGraphNodeContext {
+                graphics_pipeline_layout: &self.graphics_pipeline_layout,
+                state: ctx.state,
+                globals: &globals,
+                synced_managers: ctx.synced_managers,
+                encoder,
+                now: ctx.now,
+                delta_time: ctx.delta_time,
+                frame: ctx.frame,
+                interpolation_factor,
+                alloc: ctx.alloc,
+                layer_mask: ALL_OBJECT_LAYERS,
+                debug_view: ctx.state.debug_view(),
+                bound_index_type: None,
+                draw_calls: 0,
+                drawn_instances: 0,
+                object_bytes_uploaded: 0,
+            };
+
+            node_ctx.execute_labeled("tonemap_pass", &mut self.tonemap_pass)?;
+
+            draw_calls += node_ctx.draw_calls;
+            drawn_instances += node_ctx.drawn_instances;
+
+            drop(node_ctx);
+            ctx.encoder.end_debug_label();
+        }
+
+        // NOTE: Overlay is drawn into the output target, so it is neither
+        // multisampled nor tonemapped.
+        if !ctx.state.overlay().is_empty() || ctx.state.has_text() {
+            profile_scope!("overlay_pass");
+            ctx.encoder
+                .begin_debug_label("overlay_pass", OVERLAY_PASS_LABEL_COLOR);
+
+            let encoder = ctx.encoder.with_render_pass(
+                &mut self.overlay_pass,
+                &OverlayPassInput {
+                    target: ctx.target.clone(),
+                },
+                &ctx.state.render_pass_context(ctx.frame),
+            )?;
+
+            let mut node_ctx = RenderGraphNodeContext {
+                graphics_pipeline_layout: &self.graphics_pipeline_layout,
+                state: ctx.state,
+                globals: &globals,
+                synced_managers: ctx.synced_managers,
+                encoder,
+                now: ctx.now,
+                delta_time: ctx.delta_time,
+                frame: ctx.frame,
+                interpolation_factor,
+                alloc: ctx.alloc,
+                layer_mask: ALL_OBJECT_LAYERS,
+                debug_view: ctx.state.debug_view(),
+                bound_index_type: None,
+                draw_calls: 0,
+                drawn_instances: 0,
+                object_bytes_uploaded: 0,
+            };
+
+            node_ctx.execute_labeled("overlay_material", &mut self.overlay_material)?;
+            node_ctx.execute_labeled("text_material", &mut self.text_material)?;
+
+            draw_calls += node_ctx.draw_calls;
+            drawn_instances += node_ctx.drawn_instances;
+
+            drop(node_ctx);
+            ctx.encoder.end_debug_label();
+        }
+
+        // NOTE: Ids are rendered only on frames with pick requests
+        let picks = take_pick_requests(ctx);
+        if !picks.is_empty() {
+            profile_scope!("pick_pass");
+            ctx.encoder
+                .begin_debug_label("pick_pass", PICK_PASS_LABEL_COLOR);
+
+            let encoder = ctx.encoder.with_render_pass(
+                &mut self.pick_target_pass,
+                &PickTargetPassInput {
+                    extent: ctx.target.info().extent.into(),
+                },
+                &ctx.state.render_pass_context(ctx.frame),
+            )?;
+
+            let mut node_ctx = RenderGraphNodeContext {
+                graphics_pipeline_layout: &self.graphics_pipeline_layout,
+                state: ctx.state,
+                globals: &globals,
+                synced_managers: ctx.synced_managers,
+                encoder,
+                now: ctx.now,
+                delta_time: ctx.delta_time,
+                frame: ctx.frame,
+                interpolation_factor,
+                alloc: ctx.alloc,
+                layer_mask: ALL_OBJECT_LAYERS,
+                debug_view: ctx.state.debug_view(),
+                bound_index_type: None,
+                draw_calls: 0,
+                drawn_instances: 0,
+                object_bytes_uploaded: 0,
+            };
+
+            node_ctx.execute_labeled("pick_pass", &mut self.pick_pass)?;
+
+            ctx.state
+                .record_frame_object_uploads(node_ctx.object_bytes_uploaded);
+
+            drop(node_ctx);
+
+            let target = self.pick_target_pass.target().unwrap();
+            self.pick_pass.encode_readback(
+                &ctx.state.device,
+                ctx.encoder,
+                target,
+                ctx.frame,
+                picks,
+            )?;
+            ctx.encoder.end_debug_label();
+        }
+
+        ctx.state.record_frame_draws(draw_calls, drawn_instances);
+
         Ok(())
     }
+
+    /// Draws the main pass nodes into each active render target with its camera.
+    ///
+    /// Returns the number of draw calls and drawn instances.
+    ///
+    /// NOTE: Targets are drawn before the GPU culling of the main pass,
+    /// so their static objects are culled on the CPU with their own frustums.
+    fn execute_render_targets(
+        &mut self,
+        ctx: &mut RenderGraphContext<'_>,
+        globals: &mut FrameResourcesGuard<'_>,
+    ) -> Result<(u32, u32)> {
+        profile_scope!("render_targets");
+
+        let targets = ctx.state.render_targets.prepare_frame(
+            &ctx.state.device,
+            &ctx.state.texture_manager,
+            &ctx.state.bindless_resources,
+            ctx.frame,
+        )?;
+
+        // NOTE: Attachments of the removed targets are released here
+        self.render_target_passes
+            .retain(|handle, _| targets.iter().any(|target| target.handle == *handle));
+
+        let mut draw_calls = 0;
+        let mut drawn_instances = 0;
+
+        for (index, target) in targets.iter().enumerate() {
+            ctx.encoder
+                .begin_debug_label("render_target", RENDER_TARGET_LABEL_COLOR);
+
+            let view_globals = globals.flush_view(
+                &ctx.state.device,
+                index + 1,
+                &target.camera.view,
+                &target.camera.projection,
+                target.image.info().extent.into(),
+            )?;
+            ctx.encoder.bind_graphics_descriptor_sets(
+                &self.graphics_pipeline_layout,
+                0,
+                &[
+                    ctx.state.frame_resources.descriptor_set(),
+                    ctx.state.bindless_resources.descriptor_set(),
+                ],
+                &[view_globals.dynamic_offset()],
+            );
+
+            let main_pass = self
+                .render_target_passes
+                .entry(target.handle)
+                .or_insert_with(|| {
+                    render_passes::MainPass::new(ctx.state.msaa_samples, ctx.state.depth_mode)
+                });
+            let encoder = ctx.encoder.with_render_pass(
+                main_pass,
+                &MainPassInput {
+                    target: target.image.clone(),
+                },
+                &ctx.state.render_pass_context(ctx.frame),
+            )?;
+
+            let mut node_ctx = RenderGraphNodeContext {
+                graphics_pipeline_layout: &self.graphics_pipeline_layout,
+                state: ctx.state,
+                globals: &view_globals,
+                synced_managers: ctx.synced_managers,
+                encoder,
+                now: ctx.now,
+                delta_time: ctx.delta_time,
+                frame: ctx.frame,
+                interpolation_factor: ctx.interpolation_factor,
+                alloc: ctx.alloc,
+                layer_mask: ALL_OBJECT_LAYERS,
+                debug_view: ctx.state.debug_view(),
+                bound_index_type: None,
+                draw_calls: 0,
+                drawn_instances: 0,
+                object_bytes_uploaded: 0,
+            };
+
+            node_ctx.execute_labeled("debug_material", &mut self.debug_material)?;
+            node_ctx.execute_labeled("textured_material", &mut self.textured_material)?;
+            node_ctx.execute_labeled("standard_material", &mut self.standard_material)?;
+            node_ctx.execute_labeled("skybox_pass", &mut self.skybox_pass)?;
+
+            node_ctx.layer_mask = self.standard_material.layer_mask();
+            self.standard_material.execute_transparent(&mut node_ctx)?;
+
+            draw_calls += node_ctx.draw_calls;
+            drawn_instances += node_ctx.drawn_instances;
+            ctx.state
+                .record_frame_object_uploads(node_ctx.object_bytes_uploaded);
+
+            drop(node_ctx);
+
+            ctx.encoder.transition_image(
+                &target.image,
+                gfx::ImageLayout::ShaderReadOnlyOptimal,
+                gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    ..gfx::PipelineStageFlags::FRAGMENT_SHADER,
+                gfx::AccessFlags::COLOR_ATTACHMENT_WRITE..gfx::AccessFlags::SHADER_READ,
+            );
+            ctx.encoder.end_debug_label();
+        }
+
+        // NOTE: Restores the globals of the main camera
+        ctx.encoder.bind_graphics_descriptor_sets(
+            &self.graphics_pipeline_layout,
+            0,
+            &[
+                ctx.state.frame_resources.descriptor_set(),
+                ctx.state.bindless_resources.descriptor_set(),
+            ],
+            &[globals.dynamic_offset()],
+        );
+
+        Ok((draw_calls, drawn_instances))
+    }
+
+    fn execute_compute_nodes(
+        &mut self,
+        ctx: &mut RenderGraphContext<'_>,
+        globals: &FrameResourcesGuard<'_>,
+    ) -> Result<()> {
+        profile_scope!("compute_nodes");
+
+        ctx.encoder.bind_compute_descriptor_sets(
+            &self.graphics_pipeline_layout,
+            0,
+            &[
+                ctx.state.frame_resources.descriptor_set(),
+                ctx.state.bindless_resources.descriptor_set(),
+            ],
+            &[globals.dynamic_offset()],
+        );
+
+        for node in &mut self.compute_nodes {
+            // NOTE: Waits for scatter copies, previous nodes and shader reads
+            // of the previous frame which could use the same buffers.
+            ctx.encoder.memory_barrier(
+                gfx::PipelineStageFlags::COMPUTE_SHADER
+                    | gfx::PipelineStageFlags::TRANSFER
+                    | gfx::PipelineStageFlags::VERTEX_SHADER
+                    | gfx::PipelineStageFlags::FRAGMENT_SHADER,
+                gfx::AccessFlags::SHADER_WRITE | gfx::AccessFlags::TRANSFER_WRITE,
+                gfx::PipelineStageFlags::COMPUTE_SHADER,
+                gfx::AccessFlags::SHADER_READ | gfx::AccessFlags::SHADER_WRITE,
+            );
+
+            ctx.encoder.begin_debug_label(node.name(), NODE_LABEL_COLOR);
+            let res = node.execute(ctx);
+            ctx.encoder.end_debug_label();
+            res?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Takes pick requests for the current frame,
+/// requests outside of the target are resolved immediately.
+fn take_pick_requests(ctx: &RenderGraphContext<'_>) -> Vec<PendingPick> {
+    let extent = UVec2::from(ctx.target.info().extent);
+    let mut picks = ctx
+        .state
+        .take_pick_requests(materials::PickPass::MAX_PICKS_PER_FRAME);
+    picks.retain(|pick| {
+        let inside = pick.position.cmplt(extent).all();
+        if !inside {
+            pick.request.resolve(PickResult::Nothing);
+        }
+        inside
+    });
+    picks
+}
+
+/// Creates the pipeline layout shared by all graph nodes.
+///
+/// Set 0 contains frame globals, set 1 contains bindless resources,
+/// and 20 bytes of push constants are available for all stages.
+pub(crate) fn create_pipeline_layout(state: &RendererState) -> Result<gfx::PipelineLayout> {
+    let layout = state
+        .device
+        .create_pipeline_layout(gfx::PipelineLayoutInfo {
+            sets: vec![
+                state.frame_resources.descriptor_set_layout().clone(),
+                state.bindless_resources.descriptor_set_layout().clone(),
+            ],
+            push_constants: vec![gfx::PushConstant {
+                stages: gfx::ShaderStageFlags::ALL,
+                offset: 0,
+                size: 20,
+            }],
+        })?;
+    Ok(layout)
+}
+
+/// Pipelines of a node for each [`DebugView`].
+///
+/// NOTE: All variants are kept so that switching the view doesn't
+/// require recompiling shaders.
+struct DebugViewPipelines<P> {
+    pipelines: Box<[P]>,
+}
+
+impl<P> DebugViewPipelines<P> {
+    fn new(mut make_pipelines: impl FnMut(DebugView) -> Result<P>) -> Result<Self> {
+        let pipelines = DebugView::ALL
+            .into_iter()
+            .map(&mut make_pipelines)
+            .collect::<Result<_>>()?;
+        Ok(Self { pipelines })
+    }
+
+    fn get(&self, view: DebugView) -> &P {
+        &self.pipelines[view.index()]
+    }
+
+    fn get_mut(&mut self, view: DebugView) -> &mut P {
+        &mut self.pipelines[view.index()]
+    }
+
+    /// Replaces the pipelines of all views.
+    ///
+    /// NOTE: Descriptions of all views are made before any pipeline is updated,
+    /// so the previous pipelines are kept if any shader fails to compile.
+    fn update<D>(
+        &mut self,
+        mut make_descrs: impl FnMut(DebugView) -> Result<D>,
+        mut update_pipelines: impl FnMut(&mut P, D) -> Result<()>,
+    ) -> Result<()> {
+        let descrs = DebugView::ALL
+            .into_iter()
+            .map(&mut make_descrs)
+            .collect::<Result<Vec<_>>>()?;
+        for (pipelines, descrs) in std::iter::zip(self.pipelines.iter_mut(), descrs) {
+            update_pipelines(pipelines, descrs)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pipelines of a material for each [`MaterialRenderState`] of its instances.
+///
+/// NOTE: Variants are made from the base description on first use.
+struct MaterialPipelines {
+    base: gfx::GraphicsPipelineDescr,
+    depth_mode: DepthMode,
+    variants: FastHashMap<MaterialRenderState, CachedGraphicsPipeline>,
+}
+
+impl MaterialPipelines {
+    fn new(base: gfx::GraphicsPipelineDescr, depth_mode: DepthMode) -> Self {
+        Self {
+            base,
+            depth_mode,
+            variants: FastHashMap::default(),
+        }
+    }
+
+    fn base_descr(&self) -> &gfx::GraphicsPipelineDescr {
+        &self.base
+    }
+
+    /// Replaces the base description and updates all variants.
+    fn update_descr(
+        &mut self,
+        device: &gfx::Device,
+        base: gfx::GraphicsPipelineDescr,
+    ) -> Result<()> {
+        for (state, pipeline) in &mut self.variants {
+            pipeline.update_descr(device, make_variant_descr(&base, state, self.depth_mode))?;
+        }
+        self.base = base;
+        Ok(())
+    }
+
+    fn get_mut(&mut self, state: &MaterialRenderState) -> &mut CachedGraphicsPipeline {
+        self.variants.entry(*state).or_insert_with(|| {
+            CachedGraphicsPipeline::new(make_variant_descr(&self.base, state, self.depth_mode))
+        })
+    }
+}
+
+fn make_variant_descr(
+    base: &gfx::GraphicsPipelineDescr,
+    state: &MaterialRenderState,
+    depth_mode: DepthMode,
+) -> gfx::GraphicsPipelineDescr {
+    let mut descr = base.clone();
+    if let Some(rasterizer) = &mut descr.rasterizer {
+        state.apply(rasterizer, depth_mode);
+    }
+    descr
+}
+
+/// Culls static objects of the material on the GPU, bucketed by the index
+/// of their render state in `states`.
+///
+/// Objects without a render state are skipped.
+fn cull_by_render_state<M: MaterialInstance>(
+    culling: &mut GpuCullingContext<'_, '_>,
+    states: &mut Vec<MaterialRenderState>,
+    render_state: impl Fn(Option<&M>) -> Option<MaterialRenderState>,
+) -> Result<Option<CulledDraws>> {
+    states.clear();
+    culling.cull::<M>(|material| {
+        let state = render_state(material)?;
+        let bucket = match states.iter().position(|known| *known == state) {
+            Some(bucket) => bucket,
+            None => {
+                states.push(state);
+                states.len() - 1
+            }
+        };
+        Some(bucket as u32)
+    })
+}
+
+/// Draws objects of the material with a pipeline for each of their render states.
+///
+/// Static objects are drawn from `culled` if they were culled on the GPU with
+/// [`cull_by_render_state`]. Objects without a render state are skipped.
+fn draw_by_render_state<M: MaterialInstance>(
+    ctx: &mut RenderGraphNodeContext<'_, '_>,
+    pipelines: &mut MaterialPipelines,
+    culled: Option<CulledDraws>,
+    culled_states: &[MaterialRenderState],
+    render_state: impl Fn(Option<&M>) -> Option<MaterialRenderState>,
+) -> Result<()> {
+    let material_manager = &ctx.synced_managers.material_manager;
+    let Some(material_instances_buffer) = material_manager.materials_data_buffer_handle::<M>()
+    else {
+        return Ok(());
+    };
+
+    let frustum = &ctx.globals.frustum;
+    let frustum_culling = ctx.state.is_frustum_culling_enabled();
+
+    let state_of =
+        |material_slot: u32| render_state(material_manager.get_instance::<M>(material_slot));
+
+    // NOTE: Draws are split by the render state
+    let mut draws = Vec::new();
+
+    // NOTE: States of the culled buckets go first, so that
+    // bucket indices match the indices of the draws.
+    if culled.is_some() {
+        for state in culled_states {
+            draws_for(&mut draws, ctx.alloc, *state);
+        }
+    }
+
+    let mut static_objects_buffer = None;
+    if let Some(static_objects) = ctx
+        .synced_managers
+        .object_manager
+        .iter_static_objects::<M>()
+        .filter(|_| culled.is_none())
+    {
+        static_objects_buffer = Some(static_objects.buffers());
+
+        for (slot, object) in static_objects {
+            if !ctx.is_in_layers(object.layers) {
+                continue;
+            }
+            let Some(state) = state_of(object.material_slot) else {
+                continue;
+            };
+            if frustum_culling && !frustum.contains_sphere(&object.global_bounding_sphere) {
+                continue;
+            }
+
+            draws_for(&mut draws, ctx.alloc, state).static_draws.push(
+                object.first_index..object.first_index + object.index_count,
+                object.index_type,
+                slot,
+            );
+        }
+    }
+
+    let mut dynamic_objects_buffer = None;
+    if let Some(dynamic_objects) = ctx
+        .synced_managers
+        .object_manager
+        .iter_dynamic_objects::<M>()
+        .filter(|iter| iter.len() > 0)
+    {
+        let data_buffer = dynamic_objects.data_buffer_handle();
+        let mut transforms = ctx.begin_dynamic_object_transforms(dynamic_objects.slot_count())?;
+
+        for (slot, object) in dynamic_objects {
+            if !ctx.is_in_layers(object.layers) {
+                continue;
+            }
+            let Some(state) = state_of(object.material_slot) else {
+                continue;
+            };
+
+            // NOTE: Use the interpolated transform to avoid popping at the screen edges
+            let transform = object.interpolated_transform(ctx.interpolation_factor);
+            let bounding_sphere = object.mesh_bounding_sphere.transformed(&transform);
+            if frustum_culling && !frustum.contains_sphere(&bounding_sphere) {
+                continue;
+            }
+
+            transforms.write_at(
+                slot as usize,
+                &GpuObjectTransform::new(transform, bounding_sphere),
+            );
+            draws_for(&mut draws, ctx.alloc, state).dynamic_draws.push(
+                object.first_index..object.first_index + object.index_count,
+                object.index_type,
+                slot,
+            );
+        }
+
+        dynamic_objects_buffer = Some(ctx.end_dynamic_object_transforms(transforms, data_buffer)?);
+    }
+
+    let draws = draws
+        .into_iter()
+        .map(|draws| {
+            Ok((
+                draws.state,
+                draws.static_draws.finish(ctx)?,
+                draws.dynamic_draws.finish(ctx)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for (bucket, (state, static_batches, dynamic_batches)) in draws.iter().enumerate() {
+        let bucket = bucket as u32;
+        let culled = culled.as_ref().filter(|culled| culled.has_bucket(bucket));
+        if static_batches.is_empty() && dynamic_batches.is_empty() && culled.is_none() {
+            continue;
+        }
+
+        ctx.encoder
+            .bind_cached_graphics_pipeline(pipelines.get_mut(state), &ctx.state.device)?;
+
+        if let Some(culled) = culled {
+            ctx.draw_culled(culled, bucket, material_instances_buffer);
+        }
+        if let Some(buffers) = static_objects_buffer {
+            ctx.draw_batches(buffers, material_instances_buffer, static_batches);
+        }
+        if let Some(buffers) = dynamic_objects_buffer {
+            ctx.draw_batches(buffers, material_instances_buffer, dynamic_batches);
+        }
+    }
+
+    Ok(())
 }
 
+struct RenderStateDraws<'a> {
+    state: MaterialRenderState,
+    static_draws: DrawBatcher<'a>,
+    dynamic_draws: DrawBatcher<'a>,
+}
+
+fn draws_for<'s, 'a>(
+    draws: &'s mut Vec<RenderStateDraws<'a>>,
+    alloc: &'a Bump,
+    state: MaterialRenderState,
+) -> &'s mut RenderStateDraws<'a> {
+    let index = match draws.iter().position(|draws| draws.state == state) {
+        Some(index) => index,
+        None => {
+            draws.push(RenderStateDraws {
+                state,
+                static_draws: DrawBatcher::new(alloc),
+                dynamic_draws: DrawBatcher::new(alloc),
+            });
+            draws.len() - 1
+        }
+    };
+    &mut draws[index]
+}
+
+const SHADOW_PASS_LABEL_COLOR: [f32; 4] = [0.3, 0.3, 0.3, 1.0];
+const MAIN_PASS_LABEL_COLOR: [f32; 4] = [0.2, 0.4, 0.8, 1.0];
+const RENDER_TARGET_LABEL_COLOR: [f32; 4] = [0.3, 0.6, 0.8, 1.0];
+const TRANSPARENT_PASS_LABEL_COLOR: [f32; 4] = [0.8, 0.5, 0.2, 1.0];
+const TONEMAP_PASS_LABEL_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+const OVERLAY_PASS_LABEL_COLOR: [f32; 4] = [0.7, 0.3, 0.7, 1.0];
+const PICK_PASS_LABEL_COLOR: [f32; 4] = [0.9, 0.9, 0.2, 1.0];
+const NODE_LABEL_COLOR: [f32; 4] = [0.4, 0.7, 0.3, 1.0];
+
 pub struct RenderGraphContext<'a> {
     pub state: &'a RendererState,
-    pub synced_managers: &'a RendererStateSyncedManagers,
-    pub surface_image: &'a gfx::SurfaceImage<'a>,
+    pub(crate) synced_managers: &'a RendererStateSyncedManagers,
+    /// Image which receives the tonemapped main pass output and the overlay.
+    pub target: &'a gfx::Image,
     pub encoder: &'a mut gfx::Encoder,
     pub now: Instant,
     pub delta_time: f32,
     pub frame: u32,
+    /// The last frame which is known to be completed by the GPU.
+    pub completed_frame: Option<u32>,
+    /// Interpolation factor between the last two fixed updates.
+    pub interpolation_factor: f32,
+    /// Per-frame allocator, reset after the frame is submitted.
+    pub alloc: &'a Bump,
+}
+
+/// A node which records compute work before the main pass.
+///
+/// Frame globals (set 0) and bindless resources (set 1) are bound for the
+/// compute bind point, so pipelines with the shared graph pipeline layout
+/// can use them directly. Shader writes of all compute nodes are visible to
+/// vertex and fragment shaders of the main pass.
+pub trait ComputeNode: Send {
+    /// Name of the node used for debug labels.
+    fn name(&self) -> &str {
+        "compute_node"
+    }
+
+    fn execute(&mut self, ctx: &mut RenderGraphContext<'_>) -> Result<()>;
+
+    /// Recompiles shaders of the node.
+    fn reload_shaders(&mut self, state: &RendererState) -> Result<()> {
+        _ = state;
+        Ok(())
+    }
 }
 
 trait RenderGraphNode {
     type RenderPass: RenderPass;
 
     fn execute(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()>;
+
+    /// Objects are drawn by the node only if they share at least one layer with this mask.
+    fn layer_mask(&self) -> u32 {
+        ALL_OBJECT_LAYERS
+    }
+
+    /// Draws objects which require blending, called after all opaque nodes.
+    fn execute_transparent(&mut self, ctx: &mut RenderGraphNodeContext<'_, '_>) -> Result<()> {
+        _ = ctx;
+        Ok(())
+    }
+
+    /// Culls static objects before the render pass, called only if GPU culling is enabled.
+    ///
+    /// Culled draws must be drawn in the next [`RenderGraphNode::execute`]
+    /// instead of the static objects.
+    fn cull_static_objects(&mut self, culling: &mut GpuCullingContext<'_, '_>) -> Result<()> {
+        _ = culling;
+        Ok(())
+    }
 }
 
 struct RenderGraphNodeContext<'a, 'pass> {
@@ -146,4 +1186,247 @@ struct RenderGraphNodeContext<'a, 'pass> {
     pub delta_time: f32,
     pub frame: u32,
     pub interpolation_factor: f32,
+    pub alloc: &'a Bump,
+    /// Layer mask of the currently executed node.
+    pub layer_mask: u32,
+    /// Debug view used by all nodes of the frame.
+    pub debug_view: DebugView,
+    bound_index_type: Option<gfx::IndexType>,
+    draw_calls: u32,
+    drawn_instances: u32,
+    object_bytes_uploaded: usize,
+}
+
+impl RenderGraphNodeContext<'_, '_> {
+    /// Executes the node inside of a debug label region.
+    fn execute_labeled<N: RenderGraphNode>(&mut self, name: &str, node: &mut N) -> Result<()> {
+        self.encoder.begin_debug_label(name, NODE_LABEL_COLOR);
+        self.layer_mask = node.layer_mask();
+        let res = node.execute(self);
+        self.encoder.end_debug_label();
+        res
+    }
+
+    /// Returns `true` if objects with the specified layers are drawn by the current node.
+    #[inline]
+    pub fn is_in_layers(&self, layers: u32) -> bool {
+        layers & self.layer_mask != 0
+    }
+
+    /// Binds the mesh index buffer unless it is already bound with the same index type.
+    pub fn bind_index_buffer(&mut self, index_type: gfx::IndexType) {
+        if self.bound_index_type != Some(index_type) {
+            self.state
+                .mesh_manager
+                .bind_index_buffer(&mut self.encoder, index_type);
+            self.bound_index_type = Some(index_type);
+        }
+    }
+
+    /// Pushes buffer indices used by the mesh vertex shader.
+    ///
+    /// NOTE: Without the instance buffer the instance index is used as the object slot.
+    pub fn push_object_constants(
+        &mut self,
+        objects: ObjectBuffers,
+        material_instances_buffer: StorageBufferHandle,
+        instance_buffer: Option<StorageBufferHandle>,
+    ) {
+        self.encoder.push_constants(
+            self.graphics_pipeline_layout,
+            gfx::ShaderStageFlags::ALL,
+            0,
+            &[
+                self.state.mesh_manager.vertex_buffer_handle().index(),
+                objects.transforms.index(),
+                objects.data.index(),
+                material_instances_buffer.index(),
+                instance_buffer.map_or(u32::MAX, |buffer| buffer.index()),
+            ],
+        );
+    }
+
+    pub fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
+        self.draw_calls += 1;
+        self.drawn_instances += instances.len() as u32;
+        self.encoder.draw(vertices, instances);
+    }
+
+    pub fn draw_indexed(
+        &mut self,
+        index_type: gfx::IndexType,
+        indices: Range<u32>,
+        instances: Range<u32>,
+    ) {
+        self.bind_index_buffer(index_type);
+        self.draw_calls += 1;
+        self.drawn_instances += instances.len() as u32;
+        self.encoder.draw_indexed(indices, 0, instances);
+    }
+
+    /// Draws static objects of the bucket which passed the GPU culling.
+    pub fn draw_culled(
+        &mut self,
+        draws: &CulledDraws,
+        bucket: u32,
+        material_instances_buffer: StorageBufferHandle,
+    ) {
+        if !draws.has_bucket(bucket) {
+            return;
+        }
+
+        // NOTE: Commands use the object slot as the first instance
+        self.push_object_constants(draws.objects(), material_instances_buffer, None);
+        for index_type in draws.index_types() {
+            self.bind_index_buffer(index_type);
+            self.draw_calls += 1;
+            draws.draw_indexed_indirect(&mut self.encoder, bucket, index_type);
+        }
+    }
+
+    /// Begins writing interpolated transforms of dynamic objects for the current frame.
+    ///
+    /// NOTE: Transforms must be written at the object slots with [`BufferArena::write_at`].
+    pub fn begin_dynamic_object_transforms(
+        &self,
+        slot_count: u32,
+    ) -> Result<BufferArena<GpuObjectTransform>> {
+        self.state.multi_buffer_arena.begin(
+            &self.state.device,
+            slot_count as usize,
+            gfx::BufferUsage::STORAGE,
+        )
+    }
+
+    /// Finishes writing transforms of dynamic objects which use the specified data buffer.
+    pub fn end_dynamic_object_transforms(
+        &mut self,
+        transforms: BufferArena<GpuObjectTransform>,
+        data_buffer: StorageBufferHandle,
+    ) -> Result<ObjectBuffers> {
+        self.object_bytes_uploaded += transforms.written_bytes();
+        let transforms = self.state.multi_buffer_arena.end(
+            &self.state.device,
+            &self.state.bindless_resources,
+            transforms,
+        )?;
+        Ok(ObjectBuffers {
+            transforms,
+            data: data_buffer,
+        })
+    }
+
+    /// Draws objects from the specified buffers with one call per batch.
+    pub fn draw_batches(
+        &mut self,
+        objects: ObjectBuffers,
+        material_instances_buffer: StorageBufferHandle,
+        batches: &DrawBatches<'_>,
+    ) {
+        if batches.is_empty() {
+            return;
+        }
+
+        self.push_object_constants(objects, material_instances_buffer, batches.instance_buffer);
+        for batch in batches.batches {
+            self.draw_indexed(
+                batch.index_type,
+                batch.indices.clone(),
+                batch.instances.clone(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::{ReadbackTicket, RendererBuilder};
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn downloaded_buffer_matches_written_data() {
+        const PATTERN: [u32; 16] = [
+            0xdeadbeef, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 0xcafebabe,
+        ];
+
+        struct WritePattern {
+            tickets: Arc<Mutex<Vec<ReadbackTicket>>>,
+        }
+
+        impl ComputeNode for WritePattern {
+            fn execute(&mut self, ctx: &mut RenderGraphContext<'_>) -> Result<()> {
+                let mut tickets = self.tickets.lock().unwrap();
+                if !tickets.is_empty() {
+                    return Ok(());
+                }
+
+                let buffer = ctx.state.device.create_buffer(gfx::BufferInfo {
+                    align_mask: 0b11,
+                    size: std::mem::size_of_val(&PATTERN),
+                    usage: gfx::BufferUsage::TRANSFER_SRC | gfx::BufferUsage::TRANSFER_DST,
+                    label: Some("readback test"),
+                })?;
+                ctx.encoder.update_buffer(&buffer, 0, &PATTERN);
+
+                let readbacks = ctx.state.readback_pool();
+
+                // Ranges past the end of the buffer are rejected
+                let size = buffer.info().size;
+                for (offset, size) in [(0, size + 4), (size, 4), (4, 0), (usize::MAX, 8)] {
+                    let range = gfx::BufferRange {
+                        buffer: buffer.clone(),
+                        offset,
+                        size,
+                    };
+                    assert!(ctx.encoder.download_buffer(&range, readbacks).is_err());
+                }
+
+                let whole = gfx::BufferRange::whole(buffer.clone());
+                tickets.push(ctx.encoder.download_buffer(&whole, readbacks)?);
+                let part = gfx::BufferRange {
+                    buffer,
+                    offset: 4,
+                    size: 8,
+                };
+                tickets.push(ctx.encoder.download_buffer(&part, readbacks)?);
+                Ok(())
+            }
+        }
+
+        let renderer = RendererBuilder::headless(16, 16).build().unwrap();
+        let state = renderer.state();
+
+        let tickets = Arc::new(Mutex::new(Vec::new()));
+        state
+            .add_compute_node(Box::new(WritePattern {
+                tickets: tickets.clone(),
+            }))
+            .unwrap();
+
+        let started_at = Instant::now();
+        let (whole, part) = loop {
+            assert!(started_at.elapsed() < Duration::from_secs(10));
+            state.notify_draw();
+            std::thread::sleep(Duration::from_millis(10));
+
+            let tickets = tickets.lock().unwrap();
+            let [whole, part] = &tickets[..] else {
+                continue;
+            };
+            if let Some(whole) = state.poll_readback(whole).unwrap() {
+                break (whole, state.poll_readback(part).unwrap().unwrap());
+            }
+        };
+
+        assert_eq!(whole, bytemuck::cast_slice::<u32, u8>(&PATTERN));
+        assert_eq!(part, bytemuck::cast_slice::<u32, u8>(&PATTERN[1..3]));
+
+        // The data is returned only once
+        let tickets = tickets.lock().unwrap();
+        assert_eq!(state.poll_readback(&tickets[0]).unwrap(), None);
+    }
 }
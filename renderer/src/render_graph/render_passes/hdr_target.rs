@@ -0,0 +1,162 @@
+use anyhow::Result;
+use gfx::MakeImageView;
+use glam::UVec2;
+
+use crate::util::{BindlessResources, SampledImageHandle};
+
+/// Smallest resolution scale of the main pass,
+/// see [`RendererState::set_resolution_scale`].
+///
+/// [`RendererState::set_resolution_scale`]: crate::RendererState::set_resolution_scale
+pub const MIN_RESOLUTION_SCALE: f32 = 0.25;
+/// Largest resolution scale of the main pass, values above `1.0` supersample the scene.
+pub const MAX_RESOLUTION_SCALE: f32 = 2.0;
+
+/// Intermediate target of the main pass with colors outside of the output range.
+///
+/// The target is shared by the frames in flight and is sampled by the tonemap
+/// pass through the bindless slot returned by [`HdrTarget::bindless_index`].
+/// Its extent is scaled by the resolution scale, so it is sampled with linear
+/// filtering to fill the output target.
+pub struct HdrTarget {
+    extent: UVec2,
+    view: gfx::ImageView,
+    bindless_handle: SampledImageHandle,
+}
+
+impl HdrTarget {
+    pub const FORMAT: gfx::Format = gfx::Format::RGBA16Sfloat;
+
+    pub fn new(
+        device: &gfx::Device,
+        bindless_resources: &BindlessResources,
+        extent: UVec2,
+    ) -> Result<Self> {
+        let view = make_image(device, extent)?;
+        let sampler = make_sampler(device)?;
+        let bindless_handle = bindless_resources.alloc_image(device, view.clone(), sampler);
+
+        Ok(Self {
+            extent,
+            view,
+            bindless_handle,
+        })
+    }
+
+    /// Returns the extent of the main pass for the output `extent`.
+    pub fn scaled_extent(extent: UVec2, scale: f32) -> UVec2 {
+        (extent.as_vec2() * scale)
+            .round()
+            .as_uvec2()
+            .max(UVec2::ONE)
+    }
+
+    /// Recreates the image if the extent has changed.
+    ///
+    /// NOTE: The new image gets a new bindless index, since frames in flight
+    /// could still sample the previous one. Its index is retired until they
+    /// are completed.
+    pub fn resize(
+        &mut self,
+        device: &gfx::Device,
+        bindless_resources: &BindlessResources,
+        extent: UVec2,
+    ) -> Result<()> {
+        if self.extent == extent {
+            return Ok(());
+        }
+
+        tracing::debug!(?extent, "resizing hdr target");
+        let view = make_image(device, extent)?;
+        let sampler = make_sampler(device)?;
+        let bindless_handle = bindless_resources.alloc_image(device, view.clone(), sampler);
+        bindless_resources.free_image(std::mem::replace(
+            &mut self.bindless_handle,
+            bindless_handle,
+        ));
+
+        self.extent = extent;
+        self.view = view;
+        Ok(())
+    }
+
+    pub fn image(&self) -> &gfx::Image {
+        &self.view.info().image
+    }
+
+    /// Returns the index of the target in the bindless images.
+    pub fn bindless_index(&self) -> u32 {
+        self.bindless_handle.index()
+    }
+}
+
+fn make_sampler(device: &gfx::Device) -> Result<gfx::Sampler> {
+    let sampler = device.create_sampler(gfx::SamplerInfo {
+        address_mode_u: gfx::SamplerAddressMode::ClampToEdge,
+        address_mode_v: gfx::SamplerAddressMode::ClampToEdge,
+        address_mode_w: gfx::SamplerAddressMode::ClampToEdge,
+        ..gfx::SamplerInfo::simple_linear()
+    })?;
+    Ok(sampler)
+}
+
+fn make_image(device: &gfx::Device, extent: UVec2) -> Result<gfx::ImageView> {
+    let view = device
+        .create_image(gfx::ImageInfo {
+            extent: gfx::ImageExtent::D2 {
+                width: extent.x,
+                height: extent.y,
+            },
+            format: HdrTarget::FORMAT,
+            mip_levels: 1,
+            samples: gfx::Samples::_1,
+            array_layers: 1,
+            usage: gfx::ImageUsageFlags::COLOR_ATTACHMENT | gfx::ImageUsageFlags::SAMPLED,
+            flags: gfx::ImageCreateFlags::empty(),
+            label: Some("hdr target"),
+        })?
+        .make_image_view(device)?;
+    Ok(view)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_extents_are_rounded() {
+        let extent = UVec2::new(1920, 1080);
+        assert_eq!(HdrTarget::scaled_extent(extent, 1.0), extent);
+        assert_eq!(
+            HdrTarget::scaled_extent(extent, MAX_RESOLUTION_SCALE),
+            UVec2::new(3840, 2160)
+        );
+        assert_eq!(
+            HdrTarget::scaled_extent(extent, MIN_RESOLUTION_SCALE),
+            UVec2::new(480, 270)
+        );
+
+        // Halves are rounded away from zero
+        assert_eq!(
+            HdrTarget::scaled_extent(UVec2::new(5, 3), 0.5),
+            UVec2::new(3, 2)
+        );
+        assert_eq!(
+            HdrTarget::scaled_extent(UVec2::new(5, 7), 0.3),
+            UVec2::new(2, 2)
+        );
+    }
+
+    #[test]
+    fn scaled_extents_are_never_empty() {
+        assert_eq!(
+            HdrTarget::scaled_extent(UVec2::new(1, 1), MIN_RESOLUTION_SCALE),
+            UVec2::ONE
+        );
+        assert_eq!(
+            HdrTarget::scaled_extent(UVec2::new(100, 1), MIN_RESOLUTION_SCALE),
+            UVec2::new(25, 1)
+        );
+        assert_eq!(HdrTarget::scaled_extent(UVec2::ZERO, 1.0), UVec2::ONE);
+    }
+}
@@ -1,98 +1,103 @@
 use anyhow::Result;
 use gfx::MakeImageView;
+use glam::UVec2;
 
-use crate::util::RenderPass;
+use crate::types::DepthMode;
+use crate::util::{RenderPass, RenderPassContext};
 
 pub struct MainPassInput {
-    pub max_image_count: usize,
     pub target: gfx::Image,
 }
 
-#[derive(Default)]
 pub struct MainPass {
-    render_pass: Option<gfx::RenderPass>,
-    framebuffers: Vec<gfx::Framebuffer>,
+    samples: gfx::Samples,
+    depth_mode: DepthMode,
+    /// Depth and multisampled color attachments shared by all targets.
+    transient: Option<TransientAttachments>,
+    /// Framebuffer of the current frame, kept alive while it is recorded.
+    framebuffer: Option<gfx::Framebuffer>,
+}
+
+struct TransientAttachments {
+    extent: UVec2,
+    format: gfx::Format,
+    depth: gfx::ImageView,
+    /// `None` without multisampling.
+    color: Option<gfx::ImageView>,
 }
 
 impl MainPass {
-    #[tracing::instrument(level = "debug", name = "create_main_pass", skip_all)]
-    fn get_or_init_framebuffer(
+    /// Creates a main pass which renders with the specified sample count.
+    ///
+    /// With multisampling enabled the pass renders into an intermediate target
+    /// which is resolved into the input target at the end of the pass.
+    /// The depth attachment is cleared to the far plane of the depth mode.
+    pub fn new(samples: gfx::Samples, depth_mode: DepthMode) -> Self {
+        Self {
+            samples,
+            depth_mode,
+            transient: None,
+            framebuffer: None,
+        }
+    }
+
+    fn is_multisampled(&self) -> bool {
+        self.samples != gfx::Samples::_1
+    }
+
+    fn get_or_init_transient(
         &mut self,
         device: &gfx::Device,
-        input: &MainPassInput,
-    ) -> Result<&gfx::Framebuffer> {
-        'compat: {
-            let Some(render_pass) = &self.render_pass else {
-                break 'compat;
-            };
-
-            let target_attachment = &render_pass.info().attachments[0];
-            if target_attachment.format != input.target.info().format
-                || target_attachment.samples != input.target.info().samples
-            {
-                break 'compat;
+        target: &gfx::Image,
+    ) -> Result<&TransientAttachments> {
+        let extent = UVec2::from(target.info().extent);
+        let format = target.info().format;
+
+        match &self.transient {
+            Some(transient) if transient.extent == extent && transient.format == format => {}
+            _ => {
+                tracing::debug!(?extent, ?format, "creating main pass attachments");
+                let color = if self.is_multisampled() {
+                    Some(make_color_attachment(device, target, self.samples)?)
+                } else {
+                    None
+                };
+                self.transient = Some(TransientAttachments {
+                    extent,
+                    format,
+                    depth: make_depth_attachment(device, target, self.samples)?,
+                    color,
+                });
             }
+        }
 
-            //
-            let target_image_info = input.target.info();
-            match self.framebuffers.iter().position(|fb| {
-                let attachment = fb.info().attachments[0].info();
-                attachment.image == input.target
-                    && attachment.range
-                        == gfx::ImageSubresourceRange::new(
-                            target_image_info.format.aspect_flags(),
-                            0..1,
-                            0..1,
-                        )
-            }) {
-                Some(index) => {
-                    let framebuffer = self.framebuffers.remove(index);
-                    self.framebuffers.push(framebuffer);
-                }
-                None => {
-                    let framebuffer = device.create_framebuffer(gfx::FramebufferInfo {
-                        render_pass: render_pass.clone(),
-                        attachments: vec![
-                            input.target.make_image_view(device)?,
-                            make_depth_attachment(device, &input.target)?,
-                        ],
-                        extent: target_image_info.extent.into(),
-                    })?;
-
-                    let to_remove =
-                        (self.framebuffers.len() + 1).saturating_sub(input.max_image_count);
-                    if to_remove > 0 {
-                        self.framebuffers.drain(0..to_remove);
-                    }
-                    self.framebuffers.push(framebuffer);
-                }
-            };
-
-            return Ok(self.framebuffers.last().unwrap());
-        };
-
-        self.recreate_render_pass(device, input)
+        Ok(self.transient.as_ref().unwrap())
     }
 
-    fn recreate_render_pass(
-        &mut self,
-        device: &gfx::Device,
-        input: &MainPassInput,
-    ) -> Result<&gfx::Framebuffer> {
-        let target_image_info = input.target.info();
+    fn render_pass_info(&self, target_image_info: &gfx::ImageInfo) -> gfx::RenderPassInfo {
+        let samples = self.samples;
+        let multisampled = self.is_multisampled();
 
-        let attachments = vec![
+        let mut attachments = vec![
             gfx::AttachmentInfo {
                 format: target_image_info.format,
-                samples: target_image_info.samples,
+                samples: if multisampled {
+                    samples
+                } else {
+                    target_image_info.samples
+                },
                 load_op: gfx::LoadOp::Clear(()),
-                store_op: gfx::StoreOp::Store,
+                store_op: if multisampled {
+                    gfx::StoreOp::DontCare
+                } else {
+                    gfx::StoreOp::Store
+                },
                 initial_layout: None,
                 final_layout: gfx::ImageLayout::ColorAttachmentOptimal,
             },
             gfx::AttachmentInfo {
                 format: gfx::Format::D32Sfloat,
-                samples: gfx::Samples::_1,
+                samples,
                 load_op: gfx::LoadOp::Clear(()),
                 store_op: gfx::StoreOp::DontCare,
                 initial_layout: None,
@@ -100,58 +105,77 @@ impl MainPass {
             },
         ];
 
+        let mut resolves = Vec::new();
+        if multisampled {
+            attachments.push(gfx::AttachmentInfo {
+                format: target_image_info.format,
+                samples: target_image_info.samples,
+                load_op: gfx::LoadOp::DontCare,
+                store_op: gfx::StoreOp::Store,
+                initial_layout: None,
+                final_layout: gfx::ImageLayout::ColorAttachmentOptimal,
+            });
+            resolves.push((2, gfx::ImageLayout::ColorAttachmentOptimal));
+        }
+
         let subpasses = vec![gfx::Subpass {
             colors: vec![(0, gfx::ImageLayout::ColorAttachmentOptimal)],
+            resolves,
             depth: Some((1, gfx::ImageLayout::DepthStencilAttachmentOptimal)),
         }];
 
+        // NOTE: Depth and color attachments are shared by the frames in flight,
+        // so the clear must wait for the previous frame's late depth tests
+        // and for the tonemap pass which samples the target.
         let dependencies = vec![gfx::SubpassDependency {
             src: None,
             src_stages: gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-                | gfx::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                | gfx::PipelineStageFlags::FRAGMENT_SHADER
+                | gfx::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | gfx::PipelineStageFlags::LATE_FRAGMENT_TESTS,
             dst: Some(0),
             dst_stages: gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
                 | gfx::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
         }];
 
-        let render_pass =
-            self.render_pass
-                .insert(device.create_render_pass(gfx::RenderPassInfo {
-                    attachments,
-                    subpasses,
-                    dependencies,
-                })?);
-
-        //
-        let framebuffer_info = match self.framebuffers.iter().find(|fb| {
-            let attachment = fb.info().attachments[0].info();
-            attachment.image == input.target
-                && attachment.range
-                    == gfx::ImageSubresourceRange::new(
-                        target_image_info.format.aspect_flags(),
-                        0..1,
-                        0..1,
-                    )
-        }) {
-            Some(fb) => gfx::FramebufferInfo {
-                render_pass: render_pass.clone(),
-                attachments: fb.info().attachments.clone(),
-                extent: fb.info().extent,
-            },
-            None => gfx::FramebufferInfo {
-                render_pass: render_pass.clone(),
-                attachments: vec![
-                    input.target.make_image_view(device)?,
-                    make_depth_attachment(device, &input.target)?,
-                ],
-                extent: target_image_info.extent.into(),
-            },
+        gfx::RenderPassInfo {
+            attachments,
+            subpasses,
+            dependencies,
+        }
+    }
+
+    fn get_or_init_framebuffer(
+        &mut self,
+        ctx: &RenderPassContext<'_>,
+        input: &MainPassInput,
+    ) -> Result<&gfx::Framebuffer> {
+        let target_image_info = input.target.info();
+        let render_pass = ctx.render_passes.get_or_create(
+            ctx.device,
+            self.render_pass_info(target_image_info),
+            ctx.frame,
+        )?;
+
+        let target_view = ctx
+            .framebuffers
+            .target_view(ctx.device, &input.target, ctx.frame)?;
+        let transient = self.get_or_init_transient(ctx.device, &input.target)?;
+        let attachments = match &transient.color {
+            None => vec![target_view, transient.depth.clone()],
+            Some(color) => vec![color.clone(), transient.depth.clone(), target_view],
         };
-        self.framebuffers.clear();
-        self.framebuffers
-            .push(device.create_framebuffer(framebuffer_info)?);
 
-        Ok(self.framebuffers.last().unwrap())
+        let framebuffer = ctx.framebuffers.get_or_create(
+            ctx.device,
+            gfx::FramebufferInfo {
+                render_pass,
+                attachments,
+                extent: target_image_info.extent.into(),
+            },
+            ctx.frame,
+        )?;
+        Ok(self.framebuffer.insert(framebuffer))
     }
 }
 
@@ -161,32 +185,55 @@ impl RenderPass for MainPass {
     fn begin_render_pass<'a, 'b>(
         &'b mut self,
         input: &Self::Input,
-        device: &gfx::Device,
+        ctx: &RenderPassContext<'_>,
         encoder: &'a mut gfx::Encoder,
     ) -> Result<gfx::RenderPassEncoder<'a, 'b>> {
-        let framebuffer = self.get_or_init_framebuffer(device, input)?;
+        let clear_depth = self.depth_mode.clear_depth();
+        let framebuffer = self.get_or_init_framebuffer(ctx, input)?;
         Ok(encoder.with_framebuffer(
             framebuffer,
             &[
                 gfx::ClearColor(0.02, 0.02, 0.02, 1.0).into(),
-                gfx::ClearDepth(1.0).into(),
+                clear_depth.into(),
             ],
         ))
     }
 }
 
+fn make_color_attachment(
+    device: &gfx::Device,
+    target: &gfx::Image,
+    samples: gfx::Samples,
+) -> Result<gfx::ImageView, gfx::OutOfDeviceMemory> {
+    device
+        .create_image(gfx::ImageInfo {
+            extent: target.info().extent,
+            format: target.info().format,
+            mip_levels: 1,
+            samples,
+            array_layers: 1,
+            usage: gfx::ImageUsageFlags::COLOR_ATTACHMENT,
+            flags: gfx::ImageCreateFlags::empty(),
+            label: Some("main pass color"),
+        })?
+        .make_image_view(device)
+}
+
 fn make_depth_attachment(
     device: &gfx::Device,
     target: &gfx::Image,
+    samples: gfx::Samples,
 ) -> Result<gfx::ImageView, gfx::OutOfDeviceMemory> {
     device
         .create_image(gfx::ImageInfo {
             extent: target.info().extent,
             format: gfx::Format::D32Sfloat,
             mip_levels: 1,
-            samples: gfx::Samples::_1,
+            samples,
             array_layers: 1,
             usage: gfx::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            flags: gfx::ImageCreateFlags::empty(),
+            label: Some("main pass depth"),
         })?
         .make_image_view(device)
 }
@@ -0,0 +1,84 @@
+use anyhow::Result;
+
+use crate::util::{RenderPass, RenderPassContext};
+
+pub struct OverlayPassInput {
+    pub target: gfx::Image,
+}
+
+/// Draws on top of the main pass output.
+///
+/// The target is loaded as is, so the pass must be executed after the main pass.
+#[derive(Default)]
+pub struct OverlayPass {
+    /// Framebuffer of the current frame, kept alive while it is recorded.
+    framebuffer: Option<gfx::Framebuffer>,
+}
+
+impl OverlayPass {
+    fn get_or_init_framebuffer(
+        &mut self,
+        ctx: &RenderPassContext<'_>,
+        input: &OverlayPassInput,
+    ) -> Result<&gfx::Framebuffer> {
+        let target_image_info = input.target.info();
+        let render_pass = ctx.render_passes.get_or_create(
+            ctx.device,
+            render_pass_info(target_image_info),
+            ctx.frame,
+        )?;
+
+        let target_view = ctx
+            .framebuffers
+            .target_view(ctx.device, &input.target, ctx.frame)?;
+        let framebuffer = ctx.framebuffers.get_or_create(
+            ctx.device,
+            gfx::FramebufferInfo {
+                render_pass,
+                attachments: vec![target_view],
+                extent: target_image_info.extent.into(),
+            },
+            ctx.frame,
+        )?;
+        Ok(self.framebuffer.insert(framebuffer))
+    }
+}
+
+impl RenderPass for OverlayPass {
+    type Input = OverlayPassInput;
+
+    fn begin_render_pass<'a, 'b>(
+        &'b mut self,
+        input: &Self::Input,
+        ctx: &RenderPassContext<'_>,
+        encoder: &'a mut gfx::Encoder,
+    ) -> Result<gfx::RenderPassEncoder<'a, 'b>> {
+        let framebuffer = self.get_or_init_framebuffer(ctx, input)?;
+        Ok(encoder.with_framebuffer(framebuffer, &[]))
+    }
+}
+
+fn render_pass_info(target_image_info: &gfx::ImageInfo) -> gfx::RenderPassInfo {
+    gfx::RenderPassInfo {
+        attachments: vec![gfx::AttachmentInfo {
+            format: target_image_info.format,
+            samples: target_image_info.samples,
+            load_op: gfx::LoadOp::Load,
+            store_op: gfx::StoreOp::Store,
+            // NOTE: Matches the final layout of the main pass
+            initial_layout: Some(gfx::ImageLayout::ColorAttachmentOptimal),
+            final_layout: gfx::ImageLayout::ColorAttachmentOptimal,
+        }],
+        subpasses: vec![gfx::Subpass {
+            colors: vec![(0, gfx::ImageLayout::ColorAttachmentOptimal)],
+            resolves: Vec::new(),
+            depth: None,
+        }],
+        dependencies: vec![gfx::SubpassDependency {
+            src: None,
+            src_stages: gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst: Some(0),
+            dst_stages: gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        }],
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::Result;
+use gfx::MakeImageView;
+use glam::UVec2;
+
+use crate::types::DepthMode;
+use crate::util::{RenderPass, RenderPassContext};
+
+pub struct PickTargetPassInput {
+    /// Extent of the main pass target.
+    pub extent: UVec2,
+}
+
+/// Renders pick ids of objects into an offscreen `R32Uint` target.
+///
+/// The target is left in [`gfx::ImageLayout::TransferSrcOptimal`],
+/// so that texels under pick requests can be copied out right after the pass.
+pub struct PickTargetPass {
+    depth_mode: DepthMode,
+    attachments: Option<PickAttachments>,
+    /// Framebuffer of the current frame, kept alive while it is recorded.
+    framebuffer: Option<gfx::Framebuffer>,
+}
+
+struct PickAttachments {
+    extent: UVec2,
+    ids: gfx::ImageView,
+    depth: gfx::ImageView,
+}
+
+impl PickTargetPass {
+    pub const FORMAT: gfx::Format = gfx::Format::R32Uint;
+
+    pub fn new(depth_mode: DepthMode) -> Self {
+        Self {
+            depth_mode,
+            attachments: None,
+            framebuffer: None,
+        }
+    }
+
+    /// Returns the image with pick ids of the last rendered frame.
+    pub fn target(&self) -> Option<&gfx::Image> {
+        self.attachments
+            .as_ref()
+            .map(|attachments| &attachments.ids.info().image)
+    }
+
+    fn get_or_init_attachments(
+        &mut self,
+        device: &gfx::Device,
+        extent: UVec2,
+    ) -> Result<&PickAttachments> {
+        match &self.attachments {
+            Some(attachments) if attachments.extent == extent => {}
+            _ => {
+                tracing::debug!(?extent, "creating pick pass attachments");
+                self.attachments = Some(PickAttachments {
+                    extent,
+                    ids: make_attachment(
+                        device,
+                        extent,
+                        Self::FORMAT,
+                        gfx::ImageUsageFlags::COLOR_ATTACHMENT | gfx::ImageUsageFlags::TRANSFER_SRC,
+                        "pick ids",
+                    )?,
+                    depth: make_attachment(
+                        device,
+                        extent,
+                        gfx::Format::D32Sfloat,
+                        gfx::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                        "pick depth",
+                    )?,
+                });
+            }
+        }
+
+        Ok(self.attachments.as_ref().unwrap())
+    }
+
+    fn get_or_init_framebuffer(
+        &mut self,
+        ctx: &RenderPassContext<'_>,
+        input: &PickTargetPassInput,
+    ) -> Result<&gfx::Framebuffer> {
+        let render_pass =
+            ctx.render_passes
+                .get_or_create(ctx.device, render_pass_info(), ctx.frame)?;
+
+        let attachments = self.get_or_init_attachments(ctx.device, input.extent)?;
+        let framebuffer = ctx.framebuffers.get_or_create(
+            ctx.device,
+            gfx::FramebufferInfo {
+                render_pass,
+                attachments: vec![attachments.ids.clone(), attachments.depth.clone()],
+                extent: input.extent,
+            },
+            ctx.frame,
+        )?;
+        Ok(self.framebuffer.insert(framebuffer))
+    }
+}
+
+impl RenderPass for PickTargetPass {
+    type Input = PickTargetPassInput;
+
+    fn begin_render_pass<'a, 'b>(
+        &'b mut self,
+        input: &Self::Input,
+        ctx: &RenderPassContext<'_>,
+        encoder: &'a mut gfx::Encoder,
+    ) -> Result<gfx::RenderPassEncoder<'a, 'b>> {
+        let clear_depth = self.depth_mode.clear_depth();
+        let framebuffer = self.get_or_init_framebuffer(ctx, input)?;
+        Ok(encoder.with_framebuffer(
+            framebuffer,
+            &[
+                gfx::ClearColor(0.0, 0.0, 0.0, 0.0).into(),
+                clear_depth.into(),
+            ],
+        ))
+    }
+}
+
+fn render_pass_info() -> gfx::RenderPassInfo {
+    gfx::RenderPassInfo {
+        attachments: vec![
+            gfx::AttachmentInfo {
+                format: PickTargetPass::FORMAT,
+                samples: gfx::Samples::_1,
+                load_op: gfx::LoadOp::Clear(()),
+                store_op: gfx::StoreOp::Store,
+                initial_layout: None,
+                final_layout: gfx::ImageLayout::TransferSrcOptimal,
+            },
+            gfx::AttachmentInfo {
+                format: gfx::Format::D32Sfloat,
+                samples: gfx::Samples::_1,
+                load_op: gfx::LoadOp::Clear(()),
+                store_op: gfx::StoreOp::DontCare,
+                initial_layout: None,
+                final_layout: gfx::ImageLayout::DepthStencilAttachmentOptimal,
+            },
+        ],
+        subpasses: vec![gfx::Subpass {
+            colors: vec![(0, gfx::ImageLayout::ColorAttachmentOptimal)],
+            resolves: Vec::new(),
+            depth: Some((1, gfx::ImageLayout::DepthStencilAttachmentOptimal)),
+        }],
+        // NOTE: Attachments are shared by the frames in flight, so the clear
+        // must wait for the copies of the previous frame, and the copies
+        // must wait for the pick ids.
+        dependencies: vec![
+            gfx::SubpassDependency {
+                src: None,
+                src_stages: gfx::PipelineStageFlags::TRANSFER
+                    | gfx::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                dst: Some(0),
+                dst_stages: gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | gfx::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            },
+            gfx::SubpassDependency {
+                src: Some(0),
+                src_stages: gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst: None,
+                dst_stages: gfx::PipelineStageFlags::TRANSFER,
+            },
+        ],
+    }
+}
+
+fn make_attachment(
+    device: &gfx::Device,
+    extent: UVec2,
+    format: gfx::Format,
+    usage: gfx::ImageUsageFlags,
+    label: &'static str,
+) -> Result<gfx::ImageView, gfx::OutOfDeviceMemory> {
+    device
+        .create_image(gfx::ImageInfo {
+            extent: gfx::ImageExtent::D2 {
+                width: extent.x,
+                height: extent.y,
+            },
+            format,
+            mip_levels: 1,
+            samples: gfx::Samples::_1,
+            array_layers: 1,
+            usage,
+            flags: gfx::ImageCreateFlags::empty(),
+            label: Some(label),
+        })?
+        .make_image_view(device)
+}
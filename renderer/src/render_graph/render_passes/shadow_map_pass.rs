@@ -0,0 +1,135 @@
+use anyhow::Result;
+use gfx::MakeImageView;
+
+use crate::types::SHADOW_MAP_SIZE;
+use crate::util::{BindlessResources, RenderPass, RenderPassContext, SampledImageHandle};
+
+/// Renders depth of shadow casters into the shadow map of the directional light.
+///
+/// The shadow map is sampled by the main pass with a comparison sampler
+/// through the bindless slot returned by [`ShadowMapPass::bindless_index`].
+pub struct ShadowMapPass {
+    shadow_map: gfx::ImageView,
+    bindless_handle: SampledImageHandle,
+    /// Framebuffer of the current frame, kept alive while it is recorded.
+    framebuffer: Option<gfx::Framebuffer>,
+}
+
+impl ShadowMapPass {
+    pub fn new(device: &gfx::Device, bindless_resources: &BindlessResources) -> Result<Self> {
+        let shadow_map = device
+            .create_image(gfx::ImageInfo {
+                extent: gfx::ImageExtent::D2 {
+                    width: SHADOW_MAP_SIZE,
+                    height: SHADOW_MAP_SIZE,
+                },
+                format: SHADOW_MAP_FORMAT,
+                mip_levels: 1,
+                samples: gfx::Samples::_1,
+                array_layers: 1,
+                usage: gfx::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                    | gfx::ImageUsageFlags::SAMPLED,
+                flags: gfx::ImageCreateFlags::empty(),
+                label: Some("shadow map"),
+            })?
+            .make_image_view(device)?;
+
+        // NOTE: Points outside of the shadow map are compared with the
+        // white border, so they are never in shadow.
+        let sampler = device.create_sampler(gfx::SamplerInfo {
+            mag_filter: gfx::Filter::Linear,
+            min_filter: gfx::Filter::Linear,
+            address_mode_u: gfx::SamplerAddressMode::ClampToBorder,
+            address_mode_v: gfx::SamplerAddressMode::ClampToBorder,
+            address_mode_w: gfx::SamplerAddressMode::ClampToBorder,
+            compare_op: Some(gfx::CompareOp::LessOrEqual),
+            border_color: gfx::BorderColor::FloatOpaqueWhite,
+            ..Default::default()
+        })?;
+
+        let bindless_handle = bindless_resources.alloc_image(device, shadow_map.clone(), sampler);
+
+        Ok(Self {
+            shadow_map,
+            bindless_handle,
+            framebuffer: None,
+        })
+    }
+
+    /// Returns the index of the shadow map in the bindless images.
+    pub fn bindless_index(&self) -> u32 {
+        self.bindless_handle.index()
+    }
+
+    fn get_or_init_framebuffer(
+        &mut self,
+        ctx: &RenderPassContext<'_>,
+    ) -> Result<&gfx::Framebuffer> {
+        let render_pass =
+            ctx.render_passes
+                .get_or_create(ctx.device, render_pass_info(), ctx.frame)?;
+
+        let framebuffer = ctx.framebuffers.get_or_create(
+            ctx.device,
+            gfx::FramebufferInfo {
+                render_pass,
+                attachments: vec![self.shadow_map.clone()],
+                extent: glam::UVec2::splat(SHADOW_MAP_SIZE),
+            },
+            ctx.frame,
+        )?;
+        Ok(self.framebuffer.insert(framebuffer))
+    }
+}
+
+impl RenderPass for ShadowMapPass {
+    type Input = ();
+
+    fn begin_render_pass<'a, 'b>(
+        &'b mut self,
+        _: &Self::Input,
+        ctx: &RenderPassContext<'_>,
+        encoder: &'a mut gfx::Encoder,
+    ) -> Result<gfx::RenderPassEncoder<'a, 'b>> {
+        let framebuffer = self.get_or_init_framebuffer(ctx)?;
+        Ok(encoder.with_framebuffer(framebuffer, &[gfx::ClearDepth(1.0).into()]))
+    }
+}
+
+fn render_pass_info() -> gfx::RenderPassInfo {
+    gfx::RenderPassInfo {
+        attachments: vec![gfx::AttachmentInfo {
+            format: SHADOW_MAP_FORMAT,
+            samples: gfx::Samples::_1,
+            load_op: gfx::LoadOp::Clear(()),
+            store_op: gfx::StoreOp::Store,
+            initial_layout: None,
+            final_layout: gfx::ImageLayout::ShaderReadOnlyOptimal,
+        }],
+        subpasses: vec![gfx::Subpass {
+            colors: Vec::new(),
+            resolves: Vec::new(),
+            depth: Some((0, gfx::ImageLayout::DepthStencilAttachmentOptimal)),
+        }],
+        // NOTE: The shadow map is shared by the frames in flight, so the clear
+        // must wait for the main pass of the previous frame to sample it, and
+        // the main pass must wait for the depth writes.
+        dependencies: vec![
+            gfx::SubpassDependency {
+                src: None,
+                src_stages: gfx::PipelineStageFlags::FRAGMENT_SHADER,
+                dst: Some(0),
+                dst_stages: gfx::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | gfx::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            },
+            gfx::SubpassDependency {
+                src: Some(0),
+                src_stages: gfx::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                dst: None,
+                dst_stages: gfx::PipelineStageFlags::FRAGMENT_SHADER,
+            },
+        ],
+    }
+}
+
+const SHADOW_MAP_FORMAT: gfx::Format = gfx::Format::D32Sfloat;
@@ -0,0 +1,49 @@
+/// Visualization mode of all objects in the main pass.
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum DebugView {
+    /// Objects are shaded by their materials.
+    #[default]
+    None,
+    /// Only triangle edges are drawn.
+    ///
+    /// Requires the [`gfx::DeviceFeature::FillModeNonSolid`] device feature.
+    Wireframe,
+    /// World-space normals mapped to colors.
+    Normals,
+    /// Fractional part of the first UV set.
+    UVs,
+    /// Distinct color for each object slot.
+    ObjectIndex,
+}
+
+impl DebugView {
+    pub const ALL: [Self; 5] = [
+        Self::None,
+        Self::Wireframe,
+        Self::Normals,
+        Self::UVs,
+        Self::ObjectIndex,
+    ];
+
+    /// Index of the view in [`DebugView::ALL`].
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Shader define which selects the view output.
+    pub(crate) fn shader_define(self) -> Option<&'static str> {
+        match self {
+            Self::None | Self::Wireframe => None,
+            Self::Normals => Some("DEBUG_VIEW_NORMALS"),
+            Self::UVs => Some("DEBUG_VIEW_UVS"),
+            Self::ObjectIndex => Some("DEBUG_VIEW_OBJECT_INDEX"),
+        }
+    }
+
+    pub(crate) fn polygon_mode(self) -> gfx::PolygonMode {
+        match self {
+            Self::Wireframe => gfx::PolygonMode::Line,
+            _ => gfx::PolygonMode::Fill,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+/// Statistics of a rendered frame, see [`RendererState::last_frame_stats`].
+///
+/// [`RendererState::last_frame_stats`]: crate::RendererState::last_frame_stats
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Index of the frame.
+    pub frame: u32,
+    /// Time since the previous frame used to animate this one.
+    pub delta_time: Duration,
+    /// CPU time spent evaluating instructions.
+    pub eval_instructions_time: Duration,
+    /// Instructions evaluated before the frame.
+    pub instructions: InstructionCounts,
+    /// Number of draw calls recorded.
+    pub draw_calls: u32,
+    /// Number of drawn instances.
+    pub drawn_instances: u32,
+    /// Number of alive meshes.
+    pub meshes: u32,
+    /// Number of alive material instances.
+    pub materials: u32,
+    /// Number of alive static objects.
+    pub static_objects: u32,
+    /// Number of alive dynamic objects.
+    pub dynamic_objects: u32,
+    /// Size of the buffers allocated for per-frame data.
+    pub buffer_arena_bytes: u64,
+    /// Number of swapchain recreations since the renderer was created.
+    pub swapchain_recreations: u32,
+    /// Status of the presentation, `None` if the frame was not presented.
+    pub present_status: Option<gfx::PresentStatus>,
+    /// Time from the processing of the present to the frame being shown,
+    /// of the last frame shown before this one.
+    ///
+    /// `None` if [`gfx::DeviceFeature::DisplayTiming`] is not supported.
+    pub display_latency: Option<Duration>,
+    /// Refresh cycles missed by the frames shown since the previous frame.
+    ///
+    /// A frame misses the cycles it is shown for beyond the frame interval,
+    /// see [`RendererState::set_display_paced`].
+    ///
+    /// [`RendererState::set_display_paced`]: crate::RendererState::set_display_paced
+    pub missed_vblanks: u32,
+}
+
+/// Kind of an instruction sent to the render worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstructionKind {
+    UpdateMesh,
+    RemoveMesh,
+    RemoveTexture,
+    SetSkybox,
+    SetDefaultAnisotropy,
+    AddMaterialInstance,
+    UpdateMaterial,
+    RemoveMaterial,
+    AddStaticObject,
+    AddDynamicObject,
+    UpdateStaticObject,
+    UpdateDynamicObject,
+    UpdateStaticObjects,
+    UpdateDynamicObjects,
+    SetStaticObjectLayers,
+    SetDynamicObjectLayers,
+    RemoveStaticObject,
+    RemoveDynamicObject,
+    FinishFixedUpdate,
+    SetFixedTimestep,
+}
+
+impl InstructionKind {
+    pub const COUNT: usize = Self::ALL.len();
+
+    /// All kinds in the order of their discriminants.
+    pub const ALL: [Self; 20] = [
+        Self::UpdateMesh,
+        Self::RemoveMesh,
+        Self::RemoveTexture,
+        Self::SetSkybox,
+        Self::SetDefaultAnisotropy,
+        Self::AddMaterialInstance,
+        Self::UpdateMaterial,
+        Self::RemoveMaterial,
+        Self::AddStaticObject,
+        Self::AddDynamicObject,
+        Self::UpdateStaticObject,
+        Self::UpdateDynamicObject,
+        Self::UpdateStaticObjects,
+        Self::UpdateDynamicObjects,
+        Self::SetStaticObjectLayers,
+        Self::SetDynamicObjectLayers,
+        Self::RemoveStaticObject,
+        Self::RemoveDynamicObject,
+        Self::FinishFixedUpdate,
+        Self::SetFixedTimestep,
+    ];
+}
+
+/// Number of evaluated instructions of each kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionCounts([u32; InstructionKind::COUNT]);
+
+impl InstructionCounts {
+    #[inline]
+    pub fn get(&self, kind: InstructionKind) -> u32 {
+        self.0[kind as usize]
+    }
+
+    pub fn total(&self) -> u32 {
+        self.0.iter().sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (InstructionKind, u32)> + '_ {
+        InstructionKind::ALL.into_iter().zip(self.0)
+    }
+
+    #[inline]
+    pub(crate) fn record(&mut self, kind: InstructionKind) {
+        self.0[kind as usize] += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instruction_kinds_are_ordered_by_discriminants() {
+        for (index, kind) in InstructionKind::ALL.into_iter().enumerate() {
+            assert_eq!(kind as usize, index);
+        }
+
+        let mut counts = InstructionCounts::default();
+        counts.record(InstructionKind::SetFixedTimestep);
+        counts.record(InstructionKind::UpdateMesh);
+        counts.record(InstructionKind::UpdateMesh);
+        assert_eq!(counts.get(InstructionKind::UpdateMesh), 2);
+        assert_eq!(counts.get(InstructionKind::SetFixedTimestep), 1);
+        assert_eq!(counts.total(), 3);
+        assert_eq!(counts.iter().filter(|(_, count)| *count > 0).count(), 2);
+    }
+}
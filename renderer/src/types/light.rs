@@ -0,0 +1,111 @@
+use glam::{Mat4, Vec3};
+
+/// Side of the square shadow map in texels.
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+
+/// Directional light which casts shadows,
+/// see [`RendererState::set_directional_light`].
+///
+/// [`RendererState::set_directional_light`]: crate::RendererState::set_directional_light
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// Direction in which the light shines.
+    pub direction: Vec3,
+    pub color: Vec3,
+    /// Half size of the area around the camera covered by the shadow map.
+    pub shadow_extent: f32,
+}
+
+impl DirectionalLight {
+    /// Computes an orthographic light-space projection which covers
+    /// the shadow extent around the `center`.
+    ///
+    /// The projection moves in whole shadow map texels, so shadow edges
+    /// don't shimmer when the camera moves.
+    pub fn compute_view_projection(&self, center: Vec3) -> Mat4 {
+        let direction = self.direction.try_normalize().unwrap_or(Vec3::NEG_Y);
+        let up = if direction.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let rotation = Mat4::look_to_rh(Vec3::ZERO, direction, up);
+
+        let extent = self.shadow_extent.max(f32::EPSILON);
+        let texel_size = 2.0 * extent / SHADOW_MAP_SIZE as f32;
+        let mut origin = rotation.transform_point3(center);
+        origin.x = (origin.x / texel_size).floor() * texel_size;
+        origin.y = (origin.y / texel_size).floor() * texel_size;
+
+        // NOTE: Casters outside the extent along the light direction
+        // still shadow the covered area, so the depth range is larger.
+        let depth = extent * SHADOW_DEPTH_RANGE_SCALE;
+        let projection = Mat4::orthographic_rh(-extent, extent, -extent, extent, -depth, depth);
+        projection * Mat4::from_translation(-origin) * rotation
+    }
+
+    /// Returns the size of a shadow map texel in world units.
+    pub fn shadow_texel_size(&self) -> f32 {
+        2.0 * self.shadow_extent / SHADOW_MAP_SIZE as f32
+    }
+}
+
+/// Offsets which trade shadow acne for peter-panning,
+/// see [`RendererState::set_shadow_bias`].
+///
+/// [`RendererState::set_shadow_bias`]: crate::RendererState::set_shadow_bias
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowBias {
+    /// Constant depth offset of shadow casters.
+    pub constant_factor: f32,
+    /// Depth offset of shadow casters scaled by their slope.
+    pub slope_factor: f32,
+    /// Offset of shadow receivers along their normals in shadow map texels.
+    pub normal_offset: f32,
+}
+
+impl Default for ShadowBias {
+    fn default() -> Self {
+        Self {
+            constant_factor: 1.25,
+            slope_factor: 1.75,
+            normal_offset: 1.0,
+        }
+    }
+}
+
+const SHADOW_DEPTH_RANGE_SCALE: f32 = 4.0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_projection_covers_extent_around_center() {
+        let light = DirectionalLight {
+            direction: Vec3::new(-1.0, -2.0, 0.5),
+            color: Vec3::ONE,
+            shadow_extent: 32.0,
+        };
+        let center = Vec3::new(10.0, 2.0, -7.0);
+        let view_projection = light.compute_view_projection(center);
+
+        let projected = view_projection.project_point3(center);
+        let texel = 2.0 / SHADOW_MAP_SIZE as f32;
+        assert!(projected.x.abs() <= texel && projected.y.abs() <= texel);
+        assert!((projected.z - 0.5).abs() < 1e-4);
+
+        // Points further along the light direction are deeper
+        let behind = view_projection.project_point3(center + light.direction.normalize());
+        assert!(behind.z > projected.z);
+
+        // Moving by less than a texel doesn't move the projection
+        let moved =
+            light.compute_view_projection(center + Vec3::X * light.shadow_texel_size() * 0.1);
+        let shift = (moved.project_point3(Vec3::ZERO) - view_projection.project_point3(Vec3::ZERO))
+            .truncate()
+            .abs()
+            .max_element();
+        assert!(shift < 1e-5 || (shift - texel).abs() < 1e-4);
+    }
+}
@@ -1,5 +1,6 @@
-use crate::types::VertexAttributeKind;
-use crate::util::{RawResourceHandle, ResourceHandle};
+use crate::managers::TextureManagerDataGuard;
+use crate::types::{DepthMode, SamplerOverride, TextureHandle, VertexAttributeKind};
+use crate::util::{BindlessResources, RawResourceHandle, ResourceHandle};
 
 pub type MaterialInstanceHandle = ResourceHandle<MaterialInstanceTag>;
 pub(crate) type RawMaterialInstanceHandle = RawResourceHandle<MaterialInstanceTag>;
@@ -14,10 +15,89 @@ pub trait MaterialInstance: Send + Sync + 'static {
     fn required_attributes() -> Self::RequiredAttributes;
     fn supported_attributes() -> Self::SupportedAttributes;
 
+    /// How vertex attributes missing in the mesh are handled.
+    fn attribute_policy() -> MaterialAttributePolicy {
+        MaterialAttributePolicy::Strict
+    }
+
     fn key(&self) -> u64;
     fn sorting(&self) -> Sorting;
 
-    fn shader_data(&self) -> Self::ShaderDataType;
+    /// Pipeline state used to draw objects with this instance.
+    ///
+    /// NOTE: Not all materials support overrides, see the material docs.
+    fn render_state(&self) -> MaterialRenderState {
+        MaterialRenderState::default()
+    }
+
+    /// Returns data of the instance read by the material shaders.
+    ///
+    /// NOTE: Colors must be linear, the output is sRGB encoded by the tonemap pass.
+    /// See [`Color::from_srgb`].
+    ///
+    /// [`Color::from_srgb`]: crate::types::Color::from_srgb
+    fn shader_data(&self, ctx: &ShaderDataContext<'_>) -> Self::ShaderDataType;
+
+    /// Returns `true` if replacing the `prev` instance with this one changes
+    /// nothing, so the update is not uploaded.
+    ///
+    /// Returns `false` by default. Comparing is cheap for small instances, but
+    /// for large ones which are rarely updated with the same values it costs
+    /// more than uploading them, so it must be enabled explicitly.
+    fn is_unchanged(&self, prev: &Self) -> bool {
+        let _ = prev;
+        false
+    }
+}
+
+/// Resolves resource handles used in the material shader data.
+pub struct ShaderDataContext<'a> {
+    device: &'a gfx::Device,
+    bindless_resources: &'a BindlessResources,
+    textures: &'a TextureManagerDataGuard<'a>,
+}
+
+impl<'a> ShaderDataContext<'a> {
+    pub(crate) fn new(
+        device: &'a gfx::Device,
+        bindless_resources: &'a BindlessResources,
+        textures: &'a TextureManagerDataGuard<'a>,
+    ) -> Self {
+        Self {
+            device,
+            bindless_resources,
+            textures,
+        }
+    }
+
+    /// Returns an index of the texture in the bindless sampled images array.
+    ///
+    /// Returns an index of the default white texture for unknown textures,
+    /// so the shaders never sample outside of the array.
+    pub fn texture_index(&self, handle: &TextureHandle) -> u32 {
+        self.textures
+            .bindless_index(handle.raw())
+            .unwrap_or_else(|| self.textures.default_bindless_index())
+    }
+
+    /// Returns an index of the texture sampled with the overridden `sampler`
+    /// instead of the renderer defaults, see [`ShaderDataContext::texture_index`].
+    ///
+    /// NOTE: Each used sampler takes another bindless slot for the texture.
+    pub fn texture_index_with_sampler(
+        &self,
+        handle: &TextureHandle,
+        sampler: SamplerOverride,
+    ) -> u32 {
+        self.textures
+            .bindless_index_with_sampler(
+                self.device,
+                self.bindless_resources,
+                handle.raw(),
+                sampler,
+            )
+            .unwrap_or_else(|| self.textures.default_bindless_index())
+    }
 }
 
 pub trait VertexAttributeArray: AsRef<[VertexAttributeKind]> + Clone {
@@ -44,6 +124,72 @@ impl<const N: usize> VertexAttributeArray for [VertexAttributeKind; N] {
     }
 }
 
+/// Handling of the vertex attributes which the mesh doesn't have.
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum MaterialAttributePolicy {
+    /// Objects with meshes missing any required attribute are rejected,
+    /// missing supported attributes are not bound.
+    #[default]
+    Strict,
+    /// Missing normals, tangents and UVs are bound to shared default values
+    /// (up-facing normal, x-axis tangent and zero UV), even if required.
+    Fallback,
+}
+
+/// Fixed-function pipeline state of a material instance.
+///
+/// Objects are drawn with a separate pipeline for each distinct state,
+/// the pipelines are created on first use.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct MaterialRenderState {
+    pub cull_mode: Option<gfx::CullMode>,
+    pub front_face: gfx::FrontFace,
+    pub depth_write: bool,
+    /// Depth comparison, defaults to the one of the [`DepthMode`].
+    pub depth_compare: Option<gfx::CompareOp>,
+    pub blending: Option<gfx::Blending>,
+    /// Depth offset, positive values move fragments towards the camera
+    /// regardless of the [`DepthMode`].
+    pub depth_bias: Option<gfx::DepthBias>,
+}
+
+impl MaterialRenderState {
+    pub(crate) fn apply(&self, rasterizer: &mut gfx::Rasterizer, depth_mode: DepthMode) {
+        rasterizer.cull_mode = self.cull_mode;
+        rasterizer.front_face = self.front_face;
+        rasterizer.depth_test = Some(gfx::DepthTest {
+            compare: self.depth_compare.unwrap_or(depth_mode.compare_op()),
+            write: self.depth_write,
+        });
+        rasterizer.depth_bias = self.depth_bias.map(|bias| match depth_mode {
+            DepthMode::Standard => gfx::DepthBias {
+                constant_factor: -bias.constant_factor,
+                clamp: -bias.clamp,
+                slope_factor: -bias.slope_factor,
+            },
+            DepthMode::Reversed => bias,
+        });
+        rasterizer.color_blend = gfx::ColorBlend::Blending {
+            blending: self.blending,
+            write_mask: gfx::ComponentMask::RGBA,
+            constants: gfx::State::Static([0.0; 4]),
+        };
+    }
+}
+
+impl Default for MaterialRenderState {
+    fn default() -> Self {
+        Self {
+            cull_mode: Some(gfx::CullMode::Back),
+            front_face: gfx::FrontFace::CCW,
+            depth_write: true,
+            depth_compare: None,
+            blending: None,
+            depth_bias: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct Sorting {
     pub reason: SortingReason,
@@ -73,3 +219,37 @@ pub enum SortingReason {
     Optimization,
     Requirement,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_state_depth_bias_points_to_camera() {
+        let state = MaterialRenderState {
+            depth_bias: Some(gfx::DepthBias {
+                constant_factor: 1.0,
+                clamp: 0.0,
+                slope_factor: 2.0,
+            }),
+            ..Default::default()
+        };
+
+        let mut rasterizer = gfx::Rasterizer::default();
+        state.apply(&mut rasterizer, DepthMode::Reversed);
+        assert_eq!(rasterizer.depth_bias, state.depth_bias);
+        assert_eq!(
+            rasterizer.depth_test.map(|test| test.compare),
+            Some(gfx::CompareOp::Greater)
+        );
+
+        state.apply(&mut rasterizer, DepthMode::Standard);
+        let bias = rasterizer.depth_bias.unwrap();
+        assert_eq!(bias.constant_factor, -1.0);
+        assert_eq!(bias.slope_factor, -2.0);
+        assert_eq!(
+            rasterizer.depth_test.map(|test| test.compare),
+            Some(gfx::CompareOp::Less)
+        );
+    }
+}
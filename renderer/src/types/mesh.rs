@@ -1,7 +1,11 @@
-use anyhow::Result;
+use std::ops::Range;
+
 use glam::{Vec2, Vec3};
 
-use crate::types::{Color, Normal, Position, Tangent, VertexAttributeData, UV0};
+use crate::types::{
+    Color, Normal, PackedNormal, PackedTangent, PackedUV0, Position, Tangent, VertexAttributeData,
+    UV0,
+};
 use crate::util::{BoundingSphere, RawResourceHandle, ResourceHandle};
 
 pub type MeshHandle = ResourceHandle<Mesh>;
@@ -11,7 +15,10 @@ pub struct Mesh {
     vertex_count: u32,
     attribute_data: Vec<VertexAttributeData>,
     indices: Vec<u32>,
+    index_type: gfx::IndexType,
+    submeshes: Vec<Range<u32>>,
     bounding_sphere: BoundingSphere,
+    degenerate_triangle_count: u32,
 }
 
 impl Mesh {
@@ -31,9 +38,33 @@ impl Mesh {
         &self.indices
     }
 
+    /// Type of indices in the GPU index buffer.
+    ///
+    /// NOTE: Indices are always stored as `u32` on the CPU side.
+    pub fn index_type(&self) -> gfx::IndexType {
+        self.index_type
+    }
+
+    /// Ranges of [`indices`] which can be drawn with different materials.
+    ///
+    /// Empty if the mesh is not split into submeshes.
+    ///
+    /// [`indices`]: Self::indices
+    pub fn submeshes(&self) -> &[Range<u32>] {
+        &self.submeshes
+    }
+
     pub fn bounding_sphere(&self) -> &BoundingSphere {
         &self.bounding_sphere
     }
+
+    /// Number of zero-area triangles which were skipped
+    /// while computing normals.
+    ///
+    /// Always zero if normals were not computed.
+    pub fn degenerate_triangle_count(&self) -> u32 {
+        self.degenerate_triangle_count
+    }
 }
 
 pub trait MeshGenerator: Sized {
@@ -212,9 +243,15 @@ pub struct MeshBuilder {
     tangents: Option<ComputableData<Vec<Tangent>>>,
     uv0: Option<Vec<UV0>>,
     colors: Option<Vec<Color>>,
+    packed_normals: bool,
+    packed_uv0: bool,
 
     indices: Option<Vec<u32>>,
+    index_type: Option<gfx::IndexType>,
+    submeshes: Vec<Range<u32>>,
     double_sided: bool,
+
+    error: Option<MeshError>,
 }
 
 impl MeshBuilder {
@@ -226,11 +263,24 @@ impl MeshBuilder {
         }
     }
 
+    /// Returns a builder which fails with the `error` when built,
+    /// e.g. if parameters of a [`MeshGenerator`] are invalid.
+    pub fn from_error(error: MeshError) -> Self {
+        Self {
+            error: Some(error),
+            ..Self::default()
+        }
+    }
+
     pub fn with_normals(mut self, normals: Vec<Normal>) -> Self {
         self.normals = Some(ComputableData::Known(normals));
         self
     }
 
+    /// Computes smooth normals from the triangles around each vertex.
+    ///
+    /// Zero-area triangles are skipped, and vertices without any other
+    /// triangles get an up vector, see [`Mesh::degenerate_triangle_count`].
     pub fn with_computed_normals(mut self) -> Self {
         self.normals = Some(ComputableData::Compute);
         self
@@ -241,6 +291,8 @@ impl MeshBuilder {
         self
     }
 
+    /// Computes MikkTSpace-compatible tangents from normals and UV0,
+    /// which must also be present when the mesh is built.
     pub fn with_computed_tangents(mut self) -> Self {
         self.tangents = Some(ComputableData::Compute);
         self
@@ -256,61 +308,138 @@ impl MeshBuilder {
         self
     }
 
+    /// Stores normals and tangents as 10-10-10-2 SNORM vectors,
+    /// which take 4 bytes per vertex instead of 12.
+    pub fn with_packed_normals(mut self) -> Self {
+        self.packed_normals = true;
+        self
+    }
+
+    /// Stores UV0 as two half-floats, which take 4 bytes per vertex instead of 8.
+    ///
+    /// NOTE: Precision is lower for coordinates far from zero (e.g. of tiled textures).
+    pub fn with_packed_uv0(mut self) -> Self {
+        self.packed_uv0 = true;
+        self
+    }
+
+    /// Sets the mesh indices.
+    ///
+    /// The smallest index type which fits the max index is used unless
+    /// it was specified explicitly with [`with_index_type`].
+    ///
+    /// [`with_index_type`]: Self::with_index_type
     pub fn with_indices(mut self, indices: Vec<u32>) -> Self {
         self.indices = Some(indices);
         self
     }
 
+    /// Forces the index type used in the GPU index buffer.
+    pub fn with_index_type(mut self, index_type: gfx::IndexType) -> Self {
+        self.index_type = Some(index_type);
+        self
+    }
+
+    /// Splits the mesh indices into ranges which can be drawn with different
+    /// materials while sharing the same object.
+    ///
+    /// Each range must contain whole triangles.
+    pub fn with_submeshes(mut self, submeshes: Vec<Range<u32>>) -> Self {
+        self.submeshes = submeshes;
+        self
+    }
+
     pub fn double_sided(mut self) -> Self {
         self.double_sided = true;
         self
     }
 
-    pub fn build(self) -> Result<Mesh> {
+    pub fn build(self) -> Result<Mesh, MeshError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
         let len = self.vertex_count;
+        if len == 0 {
+            return Err(MeshError::NoPositions);
+        }
 
-        if matches!(&self.normals, Some(ComputableData::Known(v)) if v.len() != len)
-            || matches!(&self.tangents, Some(ComputableData::Known(v)) if v.len() != len)
-            || matches!(&self.uv0, Some(v) if v.len() != len)
-            || matches!(&self.colors, Some(v) if v.len() != len)
-        {
-            anyhow::bail!("component length mismatch");
+        let check_len = |attribute, data_len: Option<usize>| match data_len {
+            Some(data_len) if data_len != len => Err(MeshError::AttributeLengthMismatch {
+                attribute,
+                len: data_len,
+                vertex_count: len,
+            }),
+            _ => Ok(()),
+        };
+        check_len("normals", known_len(&self.normals))?;
+        check_len("tangents", known_len(&self.tangents))?;
+        check_len("uv0", self.uv0.as_ref().map(Vec::len))?;
+        check_len("colors", self.colors.as_ref().map(Vec::len))?;
+
+        if let Some(vertex) = self.positions.iter().position(|p| !p.0.is_finite()) {
+            return Err(MeshError::NonFinitePosition { vertex });
         }
 
         let mut indices = self.indices.unwrap_or_else(|| (0..len as u32).collect());
 
-        anyhow::ensure!(len <= indices.len(), "index count mismatch");
-        anyhow::ensure!(
-            indices.len() % 3 == 0,
-            "index count must be a multiple of 3"
-        );
+        if len > indices.len() {
+            return Err(MeshError::IndexCountMismatch {
+                index_count: indices.len(),
+                vertex_count: len,
+            });
+        }
+        if indices.len() % 3 != 0 {
+            return Err(MeshError::PartialTriangle {
+                index_count: indices.len(),
+            });
+        }
 
-        anyhow::ensure!(
-            indices.iter().all(|index| (*index as usize) < len),
-            "indices must not exceed vertex count"
-        );
+        let max_index = validate_indices(&indices, len)?;
+        validate_submeshes(&self.submeshes, indices.len())?;
+        let index_type = match self.index_type {
+            None if max_index <= u16::MAX as u32 => gfx::IndexType::U16,
+            None => gfx::IndexType::U32,
+            Some(gfx::IndexType::U16) if max_index > u16::MAX as u32 => {
+                return Err(MeshError::IndexTypeOverflow { max_index });
+            }
+            Some(index_type) => index_type,
+        };
 
         if matches!(
             &self.tangents,
             Some(ComputableData::Compute) if self.normals.is_none() || self.uv0.is_none()
         ) {
-            anyhow::bail!("tangents can only be computed if normals and uv0 is present");
-        }
-
-        if self.double_sided {
-            // SAFETY: `indices` were checked to be valid above.
-            unsafe { make_double_sided(&mut indices) };
+            return Err(MeshError::MissingTangentInputs);
         }
 
+        // NOTE: Normals are computed before the reversed triangles are added,
+        // otherwise they would cancel out the normals of the front faces.
+        let mut degenerate_triangle_count = 0;
         let normals = match self.normals {
             Some(ComputableData::Known(normals)) => Some(normals),
             Some(ComputableData::Compute) => {
                 // SAFETY: `indices` were checked to be valid above.
-                Some(unsafe { compute_normals(&indices, &self.positions) })
+                let (normals, degenerate) = unsafe { compute_normals(&indices, &self.positions) };
+                degenerate_triangle_count = degenerate;
+                Some(normals)
             }
             None => None,
         };
 
+        if self.double_sided {
+            // SAFETY: `indices` were checked to be valid above.
+            unsafe { make_double_sided(&mut indices) };
+        }
+
+        let mut submeshes = self.submeshes;
+        if self.double_sided {
+            // Each triangle is followed by its reversed copy.
+            for range in &mut submeshes {
+                *range = range.start * 2..range.end * 2;
+            }
+        }
+
         let tangents = match (self.tangents, &normals, &self.uv0) {
             (None, _, _) => None,
             (Some(ComputableData::Known(tangents)), _, _) => Some(tangents),
@@ -332,13 +461,25 @@ impl MeshBuilder {
 
         attribute_data.push(VertexAttributeData::new(self.positions));
         if let Some(normals) = normals {
-            attribute_data.push(VertexAttributeData::new(normals));
+            attribute_data.push(if self.packed_normals {
+                VertexAttributeData::new(pack::<_, PackedNormal>(normals))
+            } else {
+                VertexAttributeData::new(normals)
+            });
         }
         if let Some(tangents) = tangents {
-            attribute_data.push(VertexAttributeData::new(tangents));
+            attribute_data.push(if self.packed_normals {
+                VertexAttributeData::new(pack::<_, PackedTangent>(tangents))
+            } else {
+                VertexAttributeData::new(tangents)
+            });
         }
         if let Some(uv0) = self.uv0 {
-            attribute_data.push(VertexAttributeData::new(uv0));
+            attribute_data.push(if self.packed_uv0 {
+                VertexAttributeData::new(pack::<_, PackedUV0>(uv0))
+            } else {
+                VertexAttributeData::new(uv0)
+            });
         }
         if let Some(colors) = self.colors {
             attribute_data.push(VertexAttributeData::new(colors));
@@ -348,11 +489,109 @@ impl MeshBuilder {
             vertex_count: len as u32,
             attribute_data,
             indices,
+            index_type,
+            submeshes,
             bounding_sphere,
+            degenerate_triangle_count,
         })
     }
 }
 
+/// An error returned when a [`MeshBuilder`] contains invalid data.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MeshError {
+    #[error("mesh has no positions")]
+    NoPositions,
+    #[error("mesh has {len} {attribute} for {vertex_count} vertices")]
+    AttributeLengthMismatch {
+        attribute: &'static str,
+        len: usize,
+        vertex_count: usize,
+    },
+    /// NaN or infinite positions break normals and bounds of the mesh.
+    #[error("position of vertex {vertex} is not finite")]
+    NonFinitePosition { vertex: usize },
+    #[error("{index_count} indices can't reference all {vertex_count} vertices")]
+    IndexCountMismatch {
+        index_count: usize,
+        vertex_count: usize,
+    },
+    #[error("index count {index_count} must be a multiple of 3")]
+    PartialTriangle { index_count: usize },
+    #[error("index {index} at position {position} is out of range of {vertex_count} vertices")]
+    IndexOutOfRange {
+        index: u32,
+        position: usize,
+        vertex_count: usize,
+    },
+    #[error("index {max_index} does not fit into the `u16` index type")]
+    IndexTypeOverflow { max_index: u32 },
+    #[error("submesh {submesh} range {range:?} is out of range of {index_count} indices")]
+    SubmeshOutOfRange {
+        submesh: usize,
+        range: Range<u32>,
+        index_count: usize,
+    },
+    #[error("submesh {submesh} range {range:?} must contain whole triangles")]
+    PartialSubmesh { submesh: usize, range: Range<u32> },
+    #[error("tangents can only be computed if normals and uv0 is present")]
+    MissingTangentInputs,
+    #[error("invalid {generator} parameters: {reason}")]
+    InvalidGeneratorParameters {
+        generator: &'static str,
+        reason: &'static str,
+    },
+}
+
+fn known_len<T>(data: &Option<ComputableData<Vec<T>>>) -> Option<usize> {
+    match data {
+        Some(ComputableData::Known(data)) => Some(data.len()),
+        _ => None,
+    }
+}
+
+fn pack<T, P: From<T>>(data: Vec<T>) -> Vec<P> {
+    data.into_iter().map(P::from).collect()
+}
+
+/// Checks that all indices are in range of the position array.
+///
+/// Returns the max index.
+fn validate_indices(indices: &[u32], vertex_count: usize) -> Result<u32, MeshError> {
+    let mut max_index = 0;
+    for (position, &index) in indices.iter().enumerate() {
+        if index as usize >= vertex_count {
+            return Err(MeshError::IndexOutOfRange {
+                index,
+                position,
+                vertex_count,
+            });
+        }
+        max_index = max_index.max(index);
+    }
+    Ok(max_index)
+}
+
+/// Checks that all submeshes are in range of the indices and contain whole triangles.
+fn validate_submeshes(submeshes: &[Range<u32>], index_count: usize) -> Result<(), MeshError> {
+    for (submesh, range) in submeshes.iter().enumerate() {
+        if range.start > range.end || range.end as usize > index_count {
+            return Err(MeshError::SubmeshOutOfRange {
+                submesh,
+                range: range.clone(),
+                index_count,
+            });
+        }
+        if range.start % 3 != 0 || range.end % 3 != 0 {
+            return Err(MeshError::PartialSubmesh {
+                submesh,
+                range: range.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
 enum ComputableData<T> {
     Known(T),
     Compute,
@@ -392,12 +631,17 @@ unsafe fn make_double_sided(indices: &mut Vec<u32>) {
     }
 }
 
+/// Computes area-weighted vertex normals.
+///
+/// Returns the normals and the number of skipped zero-area triangles.
+///
 /// # Safety
 /// The following must be true:
 /// - `indices` must have a length equal to a multiple of 3.
 /// - `indices` must be in a valid range for `positions`.
-unsafe fn compute_normals(indices: &[u32], positions: &[Position]) -> Vec<Normal> {
+unsafe fn compute_normals(indices: &[u32], positions: &[Position]) -> (Vec<Normal>, u32) {
     let mut normals = vec![Normal::ZERO; positions.len()];
+    let mut degenerate_triangle_count = 0;
 
     for idx in indices.chunks_exact(3) {
         let (idx0, idx1, idx2) = match *idx {
@@ -413,6 +657,11 @@ unsafe fn compute_normals(indices: &[u32], positions: &[Position]) -> Vec<Normal
         let edge1 = pos2 - pos0;
 
         let normal = edge0.cross(edge1);
+        if !normal.is_finite() || normal.length() <= f32::EPSILON * edge0.length() * edge1.length()
+        {
+            degenerate_triangle_count += 1;
+            continue;
+        }
 
         normals.get_unchecked_mut(idx0 as usize).0 += normal;
         normals.get_unchecked_mut(idx1 as usize).0 += normal;
@@ -420,12 +669,24 @@ unsafe fn compute_normals(indices: &[u32], positions: &[Position]) -> Vec<Normal
     }
 
     for normal in &mut normals {
-        normal.0 = normal.0.normalize_or_zero();
+        // NOTE: Vertices of only degenerate triangles, or of triangles
+        // which cancel each other out, don't have a direction.
+        normal.0 = normal.0.try_normalize().unwrap_or(Vec3::Y);
     }
 
-    normals
+    (normals, degenerate_triangle_count)
 }
 
+/// Computes tangents in the same way as MikkTSpace, so normal maps baked
+/// by most tools are applied without seams.
+///
+/// Per-triangle directions of the increasing U and V are projected onto
+/// the plane of each vertex normal and accumulated with the corner angle
+/// as a weight. The bitangent sign is stored in `w`.
+///
+/// NOTE: Unlike MikkTSpace, vertices are never split, so the tangents of
+/// vertices shared by triangles with mirrored UVs are averaged.
+///
 /// # Safety
 /// The following must be true:
 /// - `indices` must have a length equal to a multiple of 3.
@@ -438,46 +699,81 @@ unsafe fn compute_tangents(
     normals: &[Normal],
     uv: &[UV0],
 ) -> Vec<Tangent> {
-    let mut tangents = vec![Tangent::ZERO; positions.len()];
+    let mut tangents = vec![Vec3::ZERO; positions.len()];
+    let mut bitangents = vec![Vec3::ZERO; positions.len()];
 
     for idx in indices.chunks_exact(3) {
-        let (idx0, idx1, idx2) = match *idx {
-            [idx0, idx1, idx2] => (idx0, idx1, idx2),
+        let idx = match *idx {
+            [idx0, idx1, idx2] => [idx0 as usize, idx1 as usize, idx2 as usize],
             _ => std::hint::unreachable_unchecked(),
         };
 
-        let pos0 = positions.get_unchecked(idx0 as usize).0;
-        let pos1 = positions.get_unchecked(idx1 as usize).0;
-        let pos2 = positions.get_unchecked(idx2 as usize).0;
+        let pos = idx.map(|i| positions.get_unchecked(i).0);
+        let uv = idx.map(|i| uv.get_unchecked(i).0);
 
-        let uv0 = uv.get_unchecked(idx0 as usize).0;
-        let uv1 = uv.get_unchecked(idx1 as usize).0;
-        let uv2 = uv.get_unchecked(idx2 as usize).0;
+        let pos_edge0 = pos[1] - pos[0];
+        let pos_edge1 = pos[2] - pos[0];
 
-        let pos_edge0 = pos1 - pos0;
-        let pos_edge1 = pos2 - pos0;
+        let uv_edge0 = uv[1] - uv[0];
+        let uv_edge1 = uv[2] - uv[0];
 
-        let uv_edge0 = uv1 - uv0;
-        let uv_edge1 = uv2 - uv0;
+        // NOTE: Triangles without UV area don't define the tangent space,
+        // their vertices get it from the neighbours or a fallback below.
+        let det = uv_edge0.perp_dot(uv_edge1);
+        if det.abs() <= f32::EPSILON * uv_edge0.length() * uv_edge1.length() {
+            continue;
+        }
 
-        let r = 1.0 / (uv_edge0.x * uv_edge1.y - uv_edge0.y * uv_edge1.x);
+        let tangent = (pos_edge0 * uv_edge1.y - pos_edge1 * uv_edge0.y) / det;
+        let bitangent = (pos_edge1 * uv_edge0.x - pos_edge0 * uv_edge1.x) / det;
+        if !tangent.is_finite() || !bitangent.is_finite() {
+            continue;
+        }
 
-        let tangent = Vec3::new(
-            (pos_edge0.x * uv_edge1.y - pos_edge1.x * uv_edge0.y) * r,
-            (pos_edge0.y * uv_edge1.y - pos_edge1.y * uv_edge0.y) * r,
-            (pos_edge0.z * uv_edge1.y - pos_edge1.z * uv_edge0.y) * r,
-        );
+        for corner in 0..3 {
+            let i = idx[corner];
+            let normal = normals.get_unchecked(i).0;
 
-        tangents.get_unchecked_mut(idx0 as usize).0 += tangent;
-        tangents.get_unchecked_mut(idx1 as usize).0 += tangent;
-        tangents.get_unchecked_mut(idx2 as usize).0 += tangent;
-    }
+            let prev = pos[(corner + 2) % 3] - pos[corner];
+            let next = pos[(corner + 1) % 3] - pos[corner];
+            let angle = prev.angle_between(next);
+            if !angle.is_finite() {
+                continue;
+            }
 
-    for (tangent, normal) in tangents.iter_mut().zip(normals) {
-        tangent.0 = (tangent.0 - (normal.0 * normal.0.dot(tangent.0))).normalize_or_zero();
+            *tangents.get_unchecked_mut(i) += reject(tangent, normal) * angle;
+            *bitangents.get_unchecked_mut(i) += reject(bitangent, normal) * angle;
+        }
     }
 
     tangents
+        .into_iter()
+        .zip(bitangents)
+        .zip(normals)
+        .map(|((tangent, bitangent), normal)| {
+            let normal = normal.0;
+            let mut tangent = reject(tangent, normal);
+            if tangent == Vec3::ZERO {
+                tangent = if normal.is_normalized() {
+                    normal.any_orthonormal_vector()
+                } else {
+                    Vec3::X
+                };
+            }
+
+            let sign = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            Tangent(tangent.extend(sign))
+        })
+        .collect()
+}
+
+/// Returns the normalized component of `v` orthogonal to `normal`, or zero.
+fn reject(v: Vec3, normal: Vec3) -> Vec3 {
+    (v - normal * normal.dot(v)).normalize_or_zero()
 }
 
 #[cfg(test)]
@@ -485,6 +781,11 @@ mod tests {
     use std::collections::HashMap;
     use std::str::FromStr;
 
+    use glam::Vec4;
+
+    use super::*;
+    use crate::types::{VertexAttributeEncoding, VertexAttributeKind};
+
     const OBJ: &'static str = r#"v -1.000000 -1.000000 1.000000
 v -1.000000 1.000000 1.000000
 v -1.000000 -1.000000 -1.000000
@@ -572,6 +873,472 @@ f 4/1/6 2/3/6 6/2/6"#;
         println!("UV0: [{uv0}]");
     }
 
+    #[test]
+    fn picks_smallest_index_type() {
+        let mesh = Mesh::builder(CubeMeshGenerator::default()).build().unwrap();
+        assert_eq!(mesh.index_type(), gfx::IndexType::U16);
+
+        let mesh = Mesh::builder(CubeMeshGenerator::default())
+            .with_index_type(gfx::IndexType::U32)
+            .build()
+            .unwrap();
+        assert_eq!(mesh.index_type(), gfx::IndexType::U32);
+
+        // NOTE: 65538 vertices, which is a multiple of 3
+        let positions = vec![Position(Vec3::ZERO); u16::MAX as usize + 3];
+        let mesh = MeshBuilder::new(positions.clone()).build().unwrap();
+        assert_eq!(mesh.index_type(), gfx::IndexType::U32);
+
+        let res = MeshBuilder::new(positions)
+            .with_index_type(gfx::IndexType::U16)
+            .build();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let positions = vec![Position(Vec3::ZERO); 3];
+        let err = MeshBuilder::new(positions)
+            .with_indices(vec![0, 1, 2, 0, 2, 3])
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "index 3 at position 5 is out of range of 3 vertices"
+        );
+    }
+
+    #[test]
+    fn validates_submeshes() {
+        let mesh = Mesh::builder(CubeMeshGenerator::default())
+            .with_submeshes(vec![0..18, 18..36])
+            .double_sided()
+            .build()
+            .unwrap();
+        assert_eq!(mesh.submeshes(), &[0..36, 36..72]);
+
+        let err = Mesh::builder(CubeMeshGenerator::default())
+            .with_submeshes(vec![0..18, 18..39])
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "submesh 1 range 18..39 is out of range of 36 indices"
+        );
+
+        let err = Mesh::builder(CubeMeshGenerator::default())
+            .with_submeshes(vec![0..4])
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "submesh 0 range 0..4 must contain whole triangles"
+        );
+    }
+
+    #[test]
+    fn packs_attributes() {
+        let mesh = Mesh::builder(CubeMeshGenerator::default())
+            .with_computed_normals()
+            .with_computed_tangents()
+            .with_packed_normals()
+            .with_packed_uv0()
+            .build()
+            .unwrap();
+
+        let vertex_count = mesh.vertex_count() as usize;
+        for attribute in mesh.attribute_data() {
+            let (encoding, size) = match attribute.kind() {
+                VertexAttributeKind::Position => (VertexAttributeEncoding::Float, 12),
+                _ => (VertexAttributeEncoding::Packed, 4),
+            };
+            assert_eq!(attribute.encoding(), encoding, "{:?}", attribute.kind());
+            assert_eq!(attribute.byte_len(), vertex_count * size);
+        }
+
+        let unpacked = Mesh::builder(CubeMeshGenerator::default())
+            .with_computed_normals()
+            .with_computed_tangents()
+            .build()
+            .unwrap();
+        fn find(mesh: &Mesh, kind: VertexAttributeKind) -> &VertexAttributeData {
+            mesh.attribute_data()
+                .iter()
+                .find(|attribute| attribute.kind() == kind)
+                .unwrap()
+        }
+
+        let normals = find(&mesh, VertexAttributeKind::Normal)
+            .typed_data::<PackedNormal>()
+            .unwrap();
+        let expected = find(&unpacked, VertexAttributeKind::Normal)
+            .typed_data::<Normal>()
+            .unwrap();
+        for (packed, expected) in std::iter::zip(normals, expected) {
+            let error = (*Normal::from(*packed) - **expected).abs().max_element();
+            assert!(error <= 1.0 / 511.0);
+        }
+    }
+
+    #[test]
+    fn computed_tangents_of_a_cube() {
+        // Faces as (normal, direction of U, direction of V), some of them
+        // have mirrored UVs
+        let faces = [
+            (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+            (Vec3::Y, Vec3::X, Vec3::Z),
+            (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+            (Vec3::Z, Vec3::X, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::X, Vec3::NEG_Y),
+        ];
+
+        let mut positions = Vec::new();
+        let mut uv0 = Vec::new();
+        let mut indices = Vec::new();
+        let mut expected = Vec::new();
+        for (normal, u, v) in faces {
+            let sign = normal.cross(u).dot(v).signum();
+            let first = positions.len() as u32;
+            for (s, t) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                positions.push(Position(normal + u * (s * 2.0 - 1.0) + v * (t * 2.0 - 1.0)));
+                uv0.push(UV0(Vec2::new(s, t)));
+                expected.push(u.extend(sign));
+            }
+            // NOTE: Triangles are front-facing for the right-handed `u, v, normal` basis
+            let quad = if sign > 0.0 {
+                [0, 1, 2, 0, 2, 3]
+            } else {
+                [0, 2, 1, 0, 3, 2]
+            };
+            indices.extend(quad.map(|i| first + i));
+        }
+
+        let mesh = MeshBuilder::new(positions)
+            .with_uv0(uv0)
+            .with_indices(indices)
+            .with_computed_normals()
+            .with_computed_tangents()
+            .build()
+            .unwrap();
+
+        let tangents = tangents(&mesh);
+        for (i, (tangent, expected)) in std::iter::zip(tangents, expected).enumerate() {
+            assert!(
+                tangent.abs_diff_eq(expected, 1e-5),
+                "vertex {i}: {tangent:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn computed_tangents_of_a_sphere() {
+        const RINGS: u32 = 32;
+        const SEGMENTS: u32 = 64;
+
+        let point = |u: f32, v: f32| {
+            let (theta, phi) = (v * std::f32::consts::PI, u * std::f32::consts::TAU);
+            Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            )
+        };
+
+        // NOTE: The seam and the poles have separate vertices for each UV
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uv0 = Vec::new();
+        for ring in 0..=RINGS {
+            for segment in 0..=SEGMENTS {
+                let uv = Vec2::new(segment as f32 / SEGMENTS as f32, ring as f32 / RINGS as f32);
+                positions.push(Position(point(uv.x, uv.y)));
+                normals.push(Normal(point(uv.x, uv.y)));
+                uv0.push(UV0(uv));
+            }
+        }
+
+        let mut indices = Vec::new();
+        for ring in 0..RINGS {
+            for segment in 0..SEGMENTS {
+                let i = ring * (SEGMENTS + 1) + segment;
+                let j = i + SEGMENTS + 1;
+                indices.extend([i, i + 1, j, i + 1, j + 1, j]);
+            }
+        }
+
+        let mesh = MeshBuilder::new(positions)
+            .with_normals(normals.clone())
+            .with_uv0(uv0.clone())
+            .with_indices(indices)
+            .with_computed_tangents()
+            .build()
+            .unwrap();
+
+        let tangents = tangents(&mesh);
+        let mut max_error = 0.0f32;
+        for ((tangent, normal), uv) in tangents.iter().zip(&normals).zip(&uv0) {
+            // Tangents are undefined at the poles
+            if uv.y == 0.0 || uv.y == 1.0 {
+                continue;
+            }
+
+            // Analytic derivatives of the position by U and V
+            let phi = uv.x * std::f32::consts::TAU;
+            let expected_tangent = Vec3::new(-phi.sin(), 0.0, phi.cos());
+            let bitangent = point(uv.x, uv.y + 1e-3) - point(uv.x, uv.y - 1e-3);
+            let sign = normal.cross(expected_tangent).dot(bitangent).signum();
+
+            assert_eq!(tangent.w, sign);
+            max_error = max_error.max(tangent.truncate().distance(expected_tangent));
+        }
+        // NOTE: Vertices on the seam only see triangles on one side,
+        // so their tangents are rotated by half a segment
+        let tolerance = std::f32::consts::PI / SEGMENTS as f32 + 1e-3;
+        assert!(max_error < tolerance, "max error {max_error}");
+    }
+
+    #[test]
+    fn computed_tangents_of_degenerate_uvs() {
+        let positions = vec![
+            Position(Vec3::ZERO),
+            Position(Vec3::X),
+            Position(Vec3::Z),
+            Position(Vec3::ZERO),
+            Position(Vec3::X),
+            Position(Vec3::X),
+        ];
+        let mesh = MeshBuilder::new(positions)
+            .with_uv0(vec![UV0(Vec2::ONE); 6])
+            .with_computed_normals()
+            .with_computed_tangents()
+            .build()
+            .unwrap();
+
+        for tangent in tangents(&mesh) {
+            assert!(tangent.is_finite(), "{tangent:?}");
+            assert!(tangent.truncate().is_normalized());
+            assert_eq!(tangent.w.abs(), 1.0);
+        }
+
+        let err = Mesh::builder(CubeMeshGenerator::default())
+            .with_computed_tangents()
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "tangents can only be computed if normals and uv0 is present"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_data() {
+        let triangle = || vec![Position(Vec3::ZERO), Position(Vec3::X), Position(Vec3::Z)];
+
+        let err = MeshBuilder::new(Vec::new()).build().err().unwrap();
+        assert_eq!(err, MeshError::NoPositions);
+
+        let err = MeshBuilder::new(triangle())
+            .with_uv0(vec![UV0(Vec2::ZERO); 4])
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "mesh has 4 uv0 for 3 vertices");
+
+        let err = MeshBuilder::new(triangle())
+            .with_normals(vec![Normal(Vec3::Y); 2])
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            MeshError::AttributeLengthMismatch {
+                attribute: "normals",
+                len: 2,
+                vertex_count: 3
+            }
+        ));
+
+        let mut positions = triangle();
+        positions[1].0.y = f32::NAN;
+        let err = MeshBuilder::new(positions).build().err().unwrap();
+        assert_eq!(err, MeshError::NonFinitePosition { vertex: 1 });
+
+        let err = MeshBuilder::new(triangle())
+            .with_indices(vec![0, 1, 2, 0])
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "index count 4 must be a multiple of 3");
+    }
+
+    #[test]
+    fn computed_normals_of_degenerate_triangles() {
+        let positions = vec![
+            Position(Vec3::ZERO),
+            Position(Vec3::X),
+            Position(-Vec3::Z),
+            // Collinear points
+            Position(Vec3::ZERO),
+            Position(Vec3::X),
+            Position(Vec3::X * 2.0),
+            // Coincident points
+            Position(Vec3::ONE),
+            Position(Vec3::ONE),
+            Position(Vec3::ONE),
+        ];
+        let mesh = MeshBuilder::new(positions)
+            .with_computed_normals()
+            .build()
+            .unwrap();
+        assert_eq!(mesh.degenerate_triangle_count(), 2);
+
+        for normal in &normals(&mesh)[..3] {
+            assert!((normal.0 - Vec3::Y).length() < 1e-6, "{normal:?}");
+        }
+        for normal in &normals(&mesh)[3..] {
+            assert_eq!(normal.0, Vec3::Y);
+        }
+
+        // Reversed triangles don't cancel out the computed normals
+        let mesh = Mesh::builder(PlaneMeshGenerator::default())
+            .with_computed_normals()
+            .double_sided()
+            .build()
+            .unwrap();
+        assert_eq!(mesh.degenerate_triangle_count(), 0);
+        for normal in normals(&mesh) {
+            assert!((normal.0 - Vec3::Y).length() < 1e-6, "{normal:?}");
+        }
+    }
+
+    #[test]
+    fn build_of_random_meshes_never_panics() {
+        // NOTE: A fixed seed keeps failures reproducible.
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..2000 {
+            let vertex_count = rng.next(12);
+            let positions = (0..vertex_count)
+                .map(|_| {
+                    let v = Vec3::new(rng.next(21) as f32, rng.next(21) as f32, rng.next(3) as f32);
+                    // Mostly finite positions, so that the builder gets further
+                    Position(match rng.next(20) {
+                        0 => v * rng.next_f32(),
+                        1 => Vec3::ZERO,
+                        _ => v,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut builder = MeshBuilder::new(positions);
+
+            match rng.next(3) {
+                0 => builder = builder.with_computed_normals(),
+                1 => builder = builder.with_normals(vec![Normal(Vec3::Z); rng.len(vertex_count)]),
+                _ => {}
+            }
+            match rng.next(3) {
+                0 => builder = builder.with_computed_tangents(),
+                1 => builder = builder.with_tangents(vec![Tangent(Vec4::X); rng.len(vertex_count)]),
+                _ => {}
+            }
+            if rng.next(2) == 0 {
+                let uv0 = (0..rng.len(vertex_count))
+                    .map(|_| UV0(Vec2::new(rng.next(5) as f32, rng.next(5) as f32)))
+                    .collect();
+                builder = builder.with_uv0(uv0);
+            }
+            if rng.next(4) == 0 {
+                builder = builder.with_colors(vec![Color(Vec4::ONE); rng.len(vertex_count)]);
+            }
+            if rng.next(2) == 0 {
+                let indices = (0..rng.next(48))
+                    .map(|_| rng.next(vertex_count + 1))
+                    .collect();
+                builder = builder.with_indices(indices);
+            }
+            if rng.next(4) == 0 {
+                let submeshes = (0..rng.next(3))
+                    .map(|_| rng.next(40)..rng.next(40))
+                    .collect();
+                builder = builder.with_submeshes(submeshes);
+            }
+            if rng.next(4) == 0 {
+                builder = builder.with_index_type(gfx::IndexType::U16);
+            }
+            if rng.next(4) == 0 {
+                builder = builder.double_sided();
+            }
+            if rng.next(2) == 0 {
+                builder = builder.with_packed_normals().with_packed_uv0();
+            }
+
+            let Ok(mesh) = builder.build() else {
+                continue;
+            };
+
+            let vertex_count = mesh.vertex_count();
+            assert!(mesh.indices().iter().all(|&index| index < vertex_count));
+            for attribute in mesh.attribute_data() {
+                if let Some(normals) = attribute.typed_data::<Normal>() {
+                    assert!(normals.iter().all(|normal| normal.0.is_normalized()));
+                }
+                if let Some(tangents) = attribute.typed_data::<Tangent>() {
+                    assert!(tangents.iter().all(|tangent| tangent.0.is_finite()));
+                }
+            }
+        }
+    }
+
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self, bound: u32) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as u32
+        }
+
+        fn next_f32(&mut self) -> f32 {
+            match self.next(8) {
+                0 => f32::NAN,
+                1 => f32::INFINITY,
+                2 => f32::MAX,
+                3 => 0.0,
+                _ => self.next(2001) as f32 / 100.0 - 10.0,
+            }
+        }
+
+        /// Returns the vertex count or sometimes a random attribute length.
+        fn len(&mut self, vertex_count: u32) -> usize {
+            match self.next(8) {
+                0 => self.next(12) as usize,
+                _ => vertex_count as usize,
+            }
+        }
+    }
+
+    fn tangents(mesh: &Mesh) -> &[Tangent] {
+        mesh.attribute_data()
+            .iter()
+            .find(|attribute| attribute.kind() == VertexAttributeKind::Tangent)
+            .and_then(|attribute| attribute.typed_data::<Tangent>())
+            .unwrap()
+    }
+
+    fn normals(mesh: &Mesh) -> &[Normal] {
+        mesh.attribute_data()
+            .iter()
+            .find(|attribute| attribute.kind() == VertexAttributeKind::Normal)
+            .and_then(|attribute| attribute.typed_data::<Normal>())
+            .unwrap()
+    }
+
     fn parse_floats(s: &str) -> Vec<f32> {
         s.split(' ')
             .map(f32::from_str)
@@ -0,0 +1,609 @@
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::types::{MeshBuilder, MeshError, MeshGenerator, Normal, Position, Tangent, UV0};
+
+/// UV sphere centered at the origin.
+///
+/// Poles are on the Y axis, U goes around it and V goes from the top to the bottom.
+#[derive(Debug, Clone, Copy)]
+pub struct SphereMeshGenerator {
+    pub radius: f32,
+    /// Number of vertical slices, at least 3.
+    pub sectors: u32,
+    /// Number of horizontal slices, at least 2.
+    pub stacks: u32,
+}
+
+impl SphereMeshGenerator {
+    pub fn from_radius(radius: f32) -> Self {
+        Self {
+            radius,
+            sectors: 32,
+            stacks: 16,
+        }
+    }
+}
+
+impl Default for SphereMeshGenerator {
+    #[inline]
+    fn default() -> Self {
+        Self::from_radius(0.5)
+    }
+}
+
+impl MeshGenerator for SphereMeshGenerator {
+    fn generate_mesh(self) -> MeshBuilder {
+        let validate = || {
+            validate_radius(self.radius)?;
+            validate_slices(self.sectors, 3, "at least 3 sectors are required")?;
+            validate_slices(self.stacks, 2, "at least 2 stacks are required")
+        };
+        if let Err(reason) = validate() {
+            return MeshBuilder::from_error(invalid("sphere", reason));
+        }
+
+        let profile = arc(Vec2::ZERO, self.radius, 0.0..PI, self.stacks);
+
+        let mut revolve = Revolve::new(self.sectors);
+        revolve.add_strip(&profile);
+        revolve.finish()
+    }
+}
+
+/// Cylinder with hemispheres on both ends, centered at the origin.
+///
+/// The axis is Y, U goes around it and V goes from the top to the bottom.
+#[derive(Debug, Clone, Copy)]
+pub struct CapsuleMeshGenerator {
+    pub radius: f32,
+    /// Distance between the centers of the hemispheres.
+    pub height: f32,
+    /// Number of vertical slices, at least 3.
+    pub sectors: u32,
+    /// Number of horizontal slices of each hemisphere, at least 1.
+    pub stacks: u32,
+}
+
+impl CapsuleMeshGenerator {
+    pub fn from_size(radius: f32, height: f32) -> Self {
+        Self {
+            radius,
+            height,
+            sectors: 32,
+            stacks: 8,
+        }
+    }
+}
+
+impl Default for CapsuleMeshGenerator {
+    #[inline]
+    fn default() -> Self {
+        Self::from_size(0.5, 1.0)
+    }
+}
+
+impl MeshGenerator for CapsuleMeshGenerator {
+    fn generate_mesh(self) -> MeshBuilder {
+        let validate = || {
+            validate_radius(self.radius)?;
+            if !self.height.is_finite() || self.height < 0.0 {
+                return Err("height must be non-negative and finite");
+            }
+            validate_slices(self.sectors, 3, "at least 3 sectors are required")?;
+            validate_slices(self.stacks, 1, "at least 1 stack is required")
+        };
+        if let Err(reason) = validate() {
+            return MeshBuilder::from_error(invalid("capsule", reason));
+        }
+
+        let half_height = self.height * 0.5;
+        let mut profile = arc(
+            Vec2::new(0.0, half_height),
+            self.radius,
+            0.0..FRAC_PI_2,
+            self.stacks,
+        );
+        let bottom = arc(
+            Vec2::new(0.0, -half_height),
+            self.radius,
+            FRAC_PI_2..PI,
+            self.stacks,
+        );
+        // NOTE: Without the cylinder both hemispheres share the equator
+        let skip = (self.height == 0.0) as usize;
+        profile.extend_from_slice(&bottom[skip..]);
+
+        let mut revolve = Revolve::new(self.sectors);
+        revolve.add_strip(&profile);
+        revolve.finish()
+    }
+}
+
+/// Closed cylinder centered at the origin.
+///
+/// The axis is Y, U of the side goes around it and V goes from the top
+/// to the bottom. Caps are mapped from above.
+#[derive(Debug, Clone, Copy)]
+pub struct CylinderMeshGenerator {
+    pub radius: f32,
+    pub height: f32,
+    /// Number of vertical slices, at least 3.
+    pub sectors: u32,
+}
+
+impl CylinderMeshGenerator {
+    pub fn from_size(radius: f32, height: f32) -> Self {
+        Self {
+            radius,
+            height,
+            sectors: 32,
+        }
+    }
+}
+
+impl Default for CylinderMeshGenerator {
+    #[inline]
+    fn default() -> Self {
+        Self::from_size(0.5, 1.0)
+    }
+}
+
+impl MeshGenerator for CylinderMeshGenerator {
+    fn generate_mesh(self) -> MeshBuilder {
+        let validate = || {
+            validate_radius(self.radius)?;
+            if !self.height.is_finite() || self.height <= 0.0 {
+                return Err("height must be positive and finite");
+            }
+            validate_slices(self.sectors, 3, "at least 3 sectors are required")
+        };
+        if let Err(reason) = validate() {
+            return MeshBuilder::from_error(invalid("cylinder", reason));
+        }
+
+        let half_height = self.height * 0.5;
+        let side = [
+            ProfilePoint {
+                radius: self.radius,
+                y: half_height,
+                normal: Vec2::X,
+            },
+            ProfilePoint {
+                radius: self.radius,
+                y: -half_height,
+                normal: Vec2::X,
+            },
+        ];
+
+        let mut revolve = Revolve::new(self.sectors);
+        revolve.add_cap(self.radius, half_height, true);
+        revolve.add_strip(&side);
+        revolve.add_cap(self.radius, -half_height, false);
+        revolve.finish()
+    }
+}
+
+/// Torus around the Y axis centered at the origin.
+///
+/// U goes around the Y axis and V goes around the tube.
+#[derive(Debug, Clone, Copy)]
+pub struct TorusMeshGenerator {
+    /// Distance from the center to the center of the tube.
+    pub radius: f32,
+    /// Radius of the tube, less than `radius`.
+    pub tube_radius: f32,
+    /// Number of slices around the Y axis, at least 3.
+    pub sectors: u32,
+    /// Number of slices around the tube, at least 3.
+    pub sides: u32,
+}
+
+impl TorusMeshGenerator {
+    pub fn from_radii(radius: f32, tube_radius: f32) -> Self {
+        Self {
+            radius,
+            tube_radius,
+            sectors: 32,
+            sides: 16,
+        }
+    }
+}
+
+impl Default for TorusMeshGenerator {
+    #[inline]
+    fn default() -> Self {
+        Self::from_radii(0.5, 0.2)
+    }
+}
+
+impl MeshGenerator for TorusMeshGenerator {
+    fn generate_mesh(self) -> MeshBuilder {
+        let validate = || {
+            validate_radius(self.radius)?;
+            validate_radius(self.tube_radius)?;
+            if self.tube_radius >= self.radius {
+                return Err("tube radius must be less than the radius");
+            }
+            validate_slices(self.sectors, 3, "at least 3 sectors are required")?;
+            validate_slices(self.sides, 3, "at least 3 sides are required")
+        };
+        if let Err(reason) = validate() {
+            return MeshBuilder::from_error(invalid("torus", reason));
+        }
+
+        // NOTE: The first and the last points are the same to close the V seam
+        let profile = arc(
+            Vec2::new(self.radius, 0.0),
+            self.tube_radius,
+            0.0..TAU,
+            self.sides,
+        );
+
+        let mut revolve = Revolve::new(self.sectors);
+        revolve.add_strip(&profile);
+        revolve.finish()
+    }
+}
+
+fn invalid(generator: &'static str, reason: &'static str) -> MeshError {
+    MeshError::InvalidGeneratorParameters { generator, reason }
+}
+
+fn validate_radius(radius: f32) -> Result<(), &'static str> {
+    if radius.is_finite() && radius > 0.0 {
+        Ok(())
+    } else {
+        Err("radius must be positive and finite")
+    }
+}
+
+fn validate_slices(count: u32, min: u32, too_few: &'static str) -> Result<(), &'static str> {
+    if count < min {
+        Err(too_few)
+    } else if count > MAX_SLICES {
+        // NOTE: Huge counts would overflow the index range
+        Err("too many slices")
+    } else {
+        Ok(())
+    }
+}
+
+const MAX_SLICES: u32 = 4096;
+
+/// Point of a profile which is revolved around the Y axis.
+#[derive(Debug, Clone, Copy)]
+struct ProfilePoint {
+    /// Distance from the Y axis.
+    radius: f32,
+    y: f32,
+    /// Normal in the `(radius, y)` plane.
+    normal: Vec2,
+}
+
+/// Returns `steps + 1` points of a circular arc in the `(radius, y)` plane.
+///
+/// Angles are measured clockwise from the Y axis.
+fn arc(center: Vec2, radius: f32, angles: std::ops::Range<f32>, steps: u32) -> Vec<ProfilePoint> {
+    (0..=steps)
+        .map(|i| {
+            let angle = angles.start + (angles.end - angles.start) * i as f32 / steps as f32;
+            let (mut sin, cos) = angle.sin_cos();
+            // NOTE: Poles must be exactly on the axis to be collapsed
+            if sin.abs() < 1e-6 {
+                sin = 0.0;
+            }
+            ProfilePoint {
+                radius: center.x + radius * sin,
+                y: center.y + radius * cos,
+                normal: Vec2::new(sin, cos),
+            }
+        })
+        .collect()
+}
+
+/// Builds surfaces of revolution around the Y axis.
+struct Revolve {
+    sectors: u32,
+    positions: Vec<Position>,
+    normals: Vec<Normal>,
+    tangents: Vec<Tangent>,
+    uv0: Vec<UV0>,
+    indices: Vec<u32>,
+}
+
+impl Revolve {
+    fn new(sectors: u32) -> Self {
+        Self {
+            sectors,
+            positions: Vec::new(),
+            normals: Vec::new(),
+            tangents: Vec::new(),
+            uv0: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Adds a smooth surface of the revolved `points`.
+    ///
+    /// Points must go clockwise in the `(radius, y)` plane (e.g. from the top
+    /// to the bottom on the outer side) for the triangles to face the normals.
+    /// V is proportional to the distance along the points.
+    fn add_strip(&mut self, points: &[ProfilePoint]) {
+        let first = self.positions.len() as u32;
+        let stride = self.sectors + 1;
+
+        let mut distances = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        for (i, point) in points.iter().enumerate() {
+            if let Some(prev) = i.checked_sub(1).map(|i| &points[i]) {
+                total += Vec2::new(point.radius - prev.radius, point.y - prev.y).length();
+            }
+            distances.push(total);
+        }
+
+        for (point, distance) in std::iter::zip(points, distances) {
+            let v = if total > 0.0 { distance / total } else { 0.0 };
+            for j in 0..=self.sectors {
+                let (sin, cos) = self.sector_angle(j).sin_cos();
+                self.positions.push(Position(Vec3::new(
+                    point.radius * cos,
+                    point.y,
+                    point.radius * sin,
+                )));
+                self.normals.push(Normal(Vec3::new(
+                    point.normal.x * cos,
+                    point.normal.y,
+                    point.normal.x * sin,
+                )));
+                // NOTE: The bitangent is the clockwise direction along the profile,
+                // so the handedness is the same everywhere.
+                self.tangents.push(Tangent(Vec4::new(-sin, 0.0, cos, 1.0)));
+                self.uv0
+                    .push(UV0(Vec2::new(j as f32 / self.sectors as f32, v)));
+            }
+        }
+
+        for (band, pair) in std::iter::zip(0.., points.windows(2)) {
+            let top = first + band * stride;
+            let bottom = top + stride;
+            for j in 0..self.sectors {
+                let (a, a1, b, b1) = (top + j, top + j + 1, bottom + j, bottom + j + 1);
+                // NOTE: Rings on the axis are collapsed into triangle fans
+                if pair[0].radius != 0.0 {
+                    self.indices.extend([a, a1, b]);
+                }
+                if pair[1].radius != 0.0 {
+                    self.indices.extend([a1, b1, b]);
+                }
+            }
+        }
+    }
+
+    /// Adds a flat disk facing up or down.
+    ///
+    /// UVs are mapped from above, V is flipped on the top cap so that
+    /// the textures of both caps are not mirrored.
+    fn add_cap(&mut self, radius: f32, y: f32, up: bool) {
+        let center = self.positions.len() as u32;
+        let normal = if up { Vec3::Y } else { Vec3::NEG_Y };
+        let v_sign = if up { -1.0 } else { 1.0 };
+
+        self.positions.push(Position(Vec3::new(0.0, y, 0.0)));
+        for j in 0..self.sectors {
+            let (sin, cos) = self.sector_angle(j).sin_cos();
+            self.positions
+                .push(Position(Vec3::new(radius * cos, y, radius * sin)));
+        }
+        for position in &self.positions[center as usize..] {
+            let uv = Vec2::new(position.0.x, position.0.z * v_sign) / radius;
+            self.uv0.push(UV0(uv * 0.5 + 0.5));
+            self.normals.push(Normal(normal));
+            self.tangents.push(Tangent(Vec4::new(1.0, 0.0, 0.0, 1.0)));
+        }
+
+        for j in 0..self.sectors {
+            let rim = center + 1 + j;
+            let next_rim = center + 1 + (j + 1) % self.sectors;
+            if up {
+                self.indices.extend([center, next_rim, rim]);
+            } else {
+                self.indices.extend([center, rim, next_rim]);
+            }
+        }
+    }
+
+    /// NOTE: The last sector gets exactly the same positions as the first one.
+    fn sector_angle(&self, j: u32) -> f32 {
+        TAU * (j % self.sectors) as f32 / self.sectors as f32
+    }
+
+    fn finish(self) -> MeshBuilder {
+        MeshBuilder::new(self.positions)
+            .with_normals(self.normals)
+            .with_tangents(self.tangents)
+            .with_uv0(self.uv0)
+            .with_indices(self.indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::types::{Mesh, VertexAttribute};
+
+    fn attribute<T: VertexAttribute>(mesh: &Mesh) -> &[T] {
+        mesh.attribute_data()
+            .iter()
+            .find_map(|attribute| attribute.typed_data::<T>())
+            .unwrap()
+    }
+
+    /// Checks that each edge is shared by exactly two triangles with the
+    /// same orientation, i.e. the edge is used in both directions once.
+    #[track_caller]
+    fn assert_closed(mesh: &Mesh) {
+        // NOTE: Seams and poles have separate vertices with the same positions
+        let key = |index: u32| {
+            (attribute::<Position>(mesh)[index as usize].0 * 1e4)
+                .round()
+                .as_ivec3()
+        };
+
+        let mut edges = HashMap::<_, u32>::new();
+        for triangle in mesh.indices().chunks_exact(3) {
+            for i in 0..3 {
+                let edge = (key(triangle[i]), key(triangle[(i + 1) % 3]));
+                assert_ne!(edge.0, edge.1, "degenerate edge");
+                *edges.entry(edge).or_default() += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "edge {a} -> {b} is used {count} times");
+            assert_eq!(edges.get(&(b, a)), Some(&1), "edge {a} -> {b} is open");
+        }
+    }
+
+    /// Checks that triangles and their vertex normals point away from
+    /// the closest point of the `core` (e.g. the center of a sphere).
+    #[track_caller]
+    fn assert_outward(mesh: &Mesh, core: impl Fn(Vec3) -> Vec3) {
+        let positions = attribute::<Position>(mesh);
+        let normals = attribute::<Normal>(mesh);
+
+        for triangle in mesh.indices().chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| positions[triangle[i] as usize].0);
+            let face_normal = (b - a).cross(c - a);
+            let centroid = (a + b + c) / 3.0;
+            assert!(face_normal.dot(centroid - core(centroid)) > 0.0);
+
+            for &i in triangle {
+                assert!(normals[i as usize].0.dot(face_normal) > 0.0);
+            }
+        }
+
+        for (normal, tangent) in normals.iter().zip(attribute::<Tangent>(mesh)) {
+            assert!(normal.0.is_normalized());
+            assert!(normal.0.dot(tangent.0.truncate()).abs() < 1e-5);
+            assert_eq!(tangent.0.w, 1.0);
+        }
+    }
+
+    #[test]
+    fn sphere_is_closed() {
+        let generator = SphereMeshGenerator {
+            radius: 2.0,
+            sectors: 12,
+            stacks: 7,
+        };
+        let mesh = Mesh::builder(generator).build().unwrap();
+        assert_eq!(mesh.vertex_count(), 13 * 8);
+        assert_eq!(mesh.indices().len(), 6 * 12 * 6);
+        assert_closed(&mesh);
+        assert_outward(&mesh, |_| Vec3::ZERO);
+    }
+
+    #[test]
+    fn capsule_is_closed() {
+        for (height, rings) in [(1.0, 2 * 4 + 2), (0.0, 2 * 4 + 1)] {
+            let generator = CapsuleMeshGenerator {
+                radius: 0.5,
+                height,
+                sectors: 8,
+                stacks: 4,
+            };
+            let mesh = Mesh::builder(generator).build().unwrap();
+            assert_eq!(mesh.vertex_count(), 9 * rings);
+            assert_eq!(mesh.indices().len() as u32, 6 * 8 * (rings - 2));
+            assert_closed(&mesh);
+
+            // Normals point away from the segment between the hemisphere centers
+            let half_height = height * 0.5;
+            assert_outward(&mesh, |p| Vec3::Y * p.y.clamp(-half_height, half_height));
+        }
+    }
+
+    #[test]
+    fn cylinder_is_closed() {
+        let generator = CylinderMeshGenerator {
+            radius: 1.0,
+            height: 3.0,
+            sectors: 5,
+        };
+        let mesh = Mesh::builder(generator).build().unwrap();
+        assert_eq!(mesh.vertex_count(), 2 * 6 + 2 * (1 + 5));
+        assert_eq!(mesh.indices().len(), 4 * 5 * 3);
+        assert_closed(&mesh);
+        assert_outward(&mesh, |_| Vec3::ZERO);
+
+        // Caps are flat, the side is smooth
+        let positions = attribute::<Position>(&mesh);
+        for (position, normal) in positions.iter().zip(attribute::<Normal>(&mesh)) {
+            if normal.0.y != 0.0 {
+                assert_eq!(normal.0, Vec3::Y * position.0.y.signum());
+            } else {
+                let radial = Vec3::new(position.0.x, 0.0, position.0.z);
+                assert!(normal.0.abs_diff_eq(radial, 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn torus_is_closed() {
+        let generator = TorusMeshGenerator {
+            radius: 2.0,
+            tube_radius: 0.5,
+            sectors: 10,
+            sides: 6,
+        };
+        let mesh = Mesh::builder(generator).build().unwrap();
+        assert_eq!(mesh.vertex_count(), 11 * 7);
+        assert_eq!(mesh.indices().len(), 6 * 10 * 6);
+        assert_closed(&mesh);
+
+        // Normals point away from the circle in the center of the tube
+        assert_outward(&mesh, |p| Vec3::new(p.x, 0.0, p.z).normalize() * 2.0);
+    }
+
+    #[test]
+    fn rejects_degenerate_parameters() {
+        let err = |builder: MeshBuilder| builder.build().err().unwrap().to_string();
+
+        assert_eq!(
+            err(Mesh::builder(SphereMeshGenerator::from_radius(0.0))),
+            "invalid sphere parameters: radius must be positive and finite"
+        );
+        assert_eq!(
+            err(Mesh::builder(SphereMeshGenerator {
+                sectors: 2,
+                ..Default::default()
+            })),
+            "invalid sphere parameters: at least 3 sectors are required"
+        );
+        assert_eq!(
+            err(Mesh::builder(CapsuleMeshGenerator::from_size(
+                0.5,
+                f32::NAN
+            ))),
+            "invalid capsule parameters: height must be non-negative and finite"
+        );
+        assert_eq!(
+            err(Mesh::builder(CylinderMeshGenerator::from_size(0.5, 0.0))),
+            "invalid cylinder parameters: height must be positive and finite"
+        );
+        assert_eq!(
+            err(Mesh::builder(TorusMeshGenerator::from_radii(1.0, 1.0))),
+            "invalid torus parameters: tube radius must be less than the radius"
+        );
+        assert_eq!(
+            err(Mesh::builder(TorusMeshGenerator {
+                sides: u32::MAX,
+                ..Default::default()
+            })),
+            "invalid torus parameters: too many slices"
+        );
+    }
+}
@@ -1,11 +1,29 @@
+pub use self::debug_view::*;
+pub use self::frame_stats::*;
+pub use self::light::*;
 pub use self::material::*;
 pub use self::mesh::*;
+pub use self::mesh_generators::*;
 pub use self::object::*;
+pub use self::overlay::*;
+pub use self::picking::*;
 pub use self::projection::*;
+pub use self::render_target::*;
+pub use self::texture::*;
+pub use self::tonemap::*;
 pub use self::vertex::*;
 
+mod debug_view;
+mod frame_stats;
+mod light;
 mod material;
 mod mesh;
+mod mesh_generators;
 mod object;
+mod overlay;
+mod picking;
 mod projection;
+mod render_target;
+mod texture;
+mod tonemap;
 mod vertex;
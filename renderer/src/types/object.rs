@@ -9,11 +9,66 @@ pub(crate) type RawStaticObjectHandle = RawResourceHandle<StaticObjectTag>;
 pub type DynamicObjectHandle = ResourceHandle<DynamicObjectTag>;
 pub(crate) type RawDynamicObjectHandle = RawResourceHandle<DynamicObjectTag>;
 
+/// Layers mask of objects which are rendered by all nodes.
+pub const ALL_OBJECT_LAYERS: u32 = u32::MAX;
+
 pub struct StaticObjectTag;
 pub struct DynamicObjectTag;
 
+/// Materials used to draw the object mesh.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectMaterials {
+    /// The whole mesh is drawn with a single material.
+    Single(MaterialInstanceHandle),
+    /// Each submesh is drawn with its own material, in the order of
+    /// the mesh submeshes.
+    PerSubmesh(Vec<MaterialInstanceHandle>),
+}
+
+impl ObjectMaterials {
+    pub fn iter(&self) -> std::slice::Iter<'_, MaterialInstanceHandle> {
+        match self {
+            Self::Single(material) => std::slice::from_ref(material).iter(),
+            Self::PerSubmesh(materials) => materials.iter(),
+        }
+    }
+}
+
+impl From<MaterialInstanceHandle> for ObjectMaterials {
+    #[inline]
+    fn from(material: MaterialInstanceHandle) -> Self {
+        Self::Single(material)
+    }
+}
+
+impl From<Vec<MaterialInstanceHandle>> for ObjectMaterials {
+    #[inline]
+    fn from(materials: Vec<MaterialInstanceHandle>) -> Self {
+        Self::PerSubmesh(materials)
+    }
+}
+
+/// Options of an added object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectOptions {
+    /// Bitmask of layers in which the object is rendered.
+    pub layers: u32,
+    /// Whether the object is drawn into the shadow map.
+    pub cast_shadows: bool,
+}
+
+impl Default for ObjectOptions {
+    fn default() -> Self {
+        Self {
+            layers: ALL_OBJECT_LAYERS,
+            cast_shadows: true,
+        }
+    }
+}
+
 pub struct ObjectData {
     pub mesh: MeshHandle,
-    pub material: MaterialInstanceHandle,
+    pub materials: ObjectMaterials,
     pub global_transform: Mat4,
+    pub options: ObjectOptions,
 }
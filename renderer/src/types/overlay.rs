@@ -0,0 +1,27 @@
+use glam::Vec2;
+
+use crate::types::TextureHandle;
+
+/// Vertex of an [`OverlayMesh`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayVertex {
+    /// Position in pixels from the top left corner of the render target.
+    pub position: Vec2,
+    pub uv: Vec2,
+    /// Premultiplied sRGB color.
+    pub color: [u8; 4],
+}
+
+/// Indexed triangle list drawn on top of the frame by the overlay pass.
+#[derive(Debug, Clone)]
+pub struct OverlayMesh {
+    pub vertices: Vec<OverlayVertex>,
+    /// Indices of the `vertices`, three per triangle.
+    pub indices: Vec<u32>,
+    /// Texture which is multiplied by the vertex color.
+    ///
+    /// NOTE: The texture must contain premultiplied colors.
+    pub texture: Option<TextureHandle>,
+    /// Pixels outside of this rect are discarded.
+    pub clip_rect: gfx::Rect,
+}
@@ -0,0 +1,93 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use glam::UVec2;
+
+use crate::types::{DynamicObjectHandle, StaticObjectHandle};
+
+/// Object drawn at the picked position, see [`RendererState::pick`].
+///
+/// [`RendererState::pick`]: crate::RendererState::pick
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PickResult {
+    /// No object was drawn at the position, or it was removed
+    /// before the request was resolved.
+    Nothing,
+    Static(StaticObjectHandle),
+    Dynamic(DynamicObjectHandle),
+}
+
+/// Pending result of [`RendererState::pick`].
+///
+/// The request is resolved a couple of frames later, once the frame with
+/// the rendered object IDs is completed. It can be either polled with
+/// [`PickRequest::try_get`] or awaited.
+///
+/// [`RendererState::pick`]: crate::RendererState::pick
+#[derive(Clone)]
+pub struct PickRequest {
+    inner: Arc<Mutex<PickState>>,
+}
+
+#[derive(Default)]
+struct PickState {
+    result: Option<PickResult>,
+    waker: Option<Waker>,
+}
+
+impl PickRequest {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Arc::default(),
+        }
+    }
+
+    /// Returns the result if the request is resolved.
+    pub fn try_get(&self) -> Option<PickResult> {
+        self.inner.lock().unwrap().result.clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.inner.lock().unwrap().result.is_some()
+    }
+
+    pub(crate) fn resolve(&self, result: PickResult) {
+        let mut state = self.inner.lock().unwrap();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Future for PickRequest {
+    type Output = PickResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.inner.lock().unwrap();
+        match &state.result {
+            Some(result) => Poll::Ready(result.clone()),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Pick request which is not rendered yet.
+pub(crate) struct PendingPick {
+    /// Position in pixels from the top left corner of the target.
+    pub position: UVec2,
+    pub request: PickRequest,
+}
+
+impl std::fmt::Debug for PickRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PickRequest")
+            .field("result", &self.try_get())
+            .finish()
+    }
+}
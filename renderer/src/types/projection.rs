@@ -1,5 +1,43 @@
 use glam::{Mat4, Vec3A};
 
+/// Depth buffer convention used by the renderer.
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum DepthMode {
+    /// Near plane maps to 0.0, far plane to 1.0.
+    #[default]
+    Standard,
+    /// Near plane maps to 1.0, far plane to 0.0.
+    ///
+    /// Gives a much better precision distribution for large scenes.
+    Reversed,
+}
+
+impl DepthMode {
+    /// Compare op for pipelines with depth test.
+    pub fn compare_op(self) -> gfx::CompareOp {
+        match self {
+            Self::Standard => gfx::CompareOp::Less,
+            Self::Reversed => gfx::CompareOp::Greater,
+        }
+    }
+
+    /// Compare op for pipelines which draw at the far plane.
+    pub fn compare_op_or_equal(self) -> gfx::CompareOp {
+        match self {
+            Self::Standard => gfx::CompareOp::LessOrEqual,
+            Self::Reversed => gfx::CompareOp::GreaterOrEqual,
+        }
+    }
+
+    /// Value to which depth attachments are cleared (the far plane).
+    pub fn clear_depth(self) -> gfx::ClearDepth {
+        match self {
+            Self::Standard => gfx::ClearDepth(1.0),
+            Self::Reversed => gfx::ClearDepth(0.0),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CameraProjection {
     Orhographic {
@@ -16,18 +54,45 @@ pub enum CameraProjection {
 }
 
 impl CameraProjection {
-    pub fn compute_projection_matrix(&self, aspect_ratio: f32) -> Mat4 {
-        match self {
-            Self::Orhographic { extent } => {
+    /// Computes the projection matrix for the specified depth convention.
+    ///
+    /// NOTE: Custom matrices are used as is and must match the depth mode.
+    pub fn compute_projection_matrix(&self, aspect_ratio: f32, depth_mode: DepthMode) -> Mat4 {
+        match (self, depth_mode) {
+            (Self::Orhographic { extent }, DepthMode::Standard) => {
                 let half = *extent * 0.5;
                 Mat4::orthographic_rh(-half.x, half.x, -half.y, half.y, -half.z, half.z)
             }
-            Self::Perspective { fovy, near } => {
+            (Self::Orhographic { extent }, DepthMode::Reversed) => {
+                let half = *extent * 0.5;
+                Mat4::orthographic_rh(-half.x, half.x, -half.y, half.y, half.z, -half.z)
+            }
+            (Self::Perspective { fovy, near }, DepthMode::Standard) => {
                 Mat4::perspective_infinite_rh(*fovy, aspect_ratio, *near)
             }
-            Self::Custom(mat) => *mat,
+            (Self::Perspective { fovy, near }, DepthMode::Reversed) => {
+                Mat4::perspective_infinite_reverse_rh(*fovy, aspect_ratio, *near)
+            }
+            (Self::Custom(mat), _) => *mat,
         }
     }
+
+    /// Returns the approximate height in pixels of an object of `world_size`
+    /// at `distance` from the camera.
+    ///
+    /// Can be used as a priority of streamed textures.
+    ///
+    /// NOTE: Custom matrices are assumed to be perspective projections.
+    pub fn projected_size(&self, world_size: f32, distance: f32, viewport_height: u32) -> f32 {
+        let ndc_height = match self {
+            Self::Orhographic { extent } => 2.0 * world_size / extent.y,
+            Self::Perspective { fovy, .. } => {
+                world_size / (distance * (fovy * 0.5).tan()).max(f32::EPSILON)
+            }
+            Self::Custom(mat) => world_size * mat.y_axis.y.abs() / distance.max(f32::EPSILON),
+        };
+        ndc_height * 0.5 * viewport_height as f32
+    }
 }
 
 impl Default for CameraProjection {
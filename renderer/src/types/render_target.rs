@@ -0,0 +1,32 @@
+use crate::types::TextureHandle;
+
+/// Maximum number of render targets drawn each frame.
+///
+/// Targets past the limit keep the content of their last drawn frame.
+pub const MAX_RENDER_TARGETS_PER_FRAME: usize = 4;
+
+/// Handle of an offscreen target which is drawn with its own camera,
+/// see [`RendererState::add_render_target`].
+///
+/// The target is removed once all clones of the handle and of its texture
+/// are dropped.
+///
+/// [`RendererState::add_render_target`]: crate::RendererState::add_render_target
+#[derive(Debug, Clone)]
+pub struct RenderTargetHandle {
+    texture: TextureHandle,
+}
+
+impl RenderTargetHandle {
+    pub(crate) fn new(texture: TextureHandle) -> Self {
+        Self { texture }
+    }
+
+    /// Returns the texture which can be sampled by materials.
+    ///
+    /// NOTE: Targets are drawn before the primary main pass, so the texture
+    /// contains linear colors of the current frame once the camera is set.
+    pub fn texture(&self) -> &TextureHandle {
+        &self.texture
+    }
+}
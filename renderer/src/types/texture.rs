@@ -0,0 +1,81 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::Arc;
+
+use glam::UVec2;
+
+use crate::util::{RawResourceHandle, ResourceHandle};
+
+pub type TextureHandle = ResourceHandle<TextureTag>;
+pub(crate) type RawTextureHandle = RawResourceHandle<TextureTag>;
+
+pub struct TextureTag;
+
+/// Sampler state which a material uses instead of the renderer defaults,
+/// see [`ShaderDataContext::texture_index_with_sampler`].
+///
+/// [`ShaderDataContext::texture_index_with_sampler`]: crate::ShaderDataContext::texture_index_with_sampler
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SamplerOverride {
+    /// Maximum anisotropy, `None` disables anisotropic filtering.
+    ///
+    /// The value is clamped to the device limit,
+    /// see [`RendererBuilder::default_anisotropy`].
+    ///
+    /// [`RendererBuilder::default_anisotropy`]: crate::RendererBuilder::default_anisotropy
+    pub max_anisotropy: Option<f32>,
+}
+
+/// Texture which is uploaded lazily, see [`RendererState::add_streamed_texture`].
+///
+/// [`RendererState::add_streamed_texture`]: crate::RendererState::add_streamed_texture
+pub struct StreamedTexture {
+    /// Extent of the first (largest) mip level.
+    pub extent: UVec2,
+    pub format: gfx::Format,
+    /// Number of mip levels, each of them is half the size of the previous one.
+    pub mip_levels: u32,
+    /// Number of the smallest mip levels which are uploaded immediately.
+    pub resident_mips: u32,
+    pub data: MipData,
+}
+
+/// Tightly packed texels of each mip level of a streamed texture.
+pub enum MipData {
+    /// Loads the mip level with the specified index.
+    ///
+    /// NOTE: Called from the streaming thread.
+    Fn(Box<FnLoadMip>),
+    /// Ranges of each mip level in the shared bytes.
+    Ranges {
+        bytes: Arc<[u8]>,
+        ranges: Box<[Range<usize>]>,
+    },
+}
+
+impl MipData {
+    pub(crate) fn load(&self, level: u32) -> anyhow::Result<Cow<'_, [u8]>> {
+        match self {
+            Self::Fn(f) => f(level).map(Cow::Owned),
+            Self::Ranges { bytes, ranges } => {
+                let range = ranges.get(level as usize).cloned().unwrap_or_default();
+                match bytes.get(range) {
+                    Some(data) => Ok(Cow::Borrowed(data)),
+                    None => anyhow::bail!("mip level {level} is out of bounds"),
+                }
+            }
+        }
+    }
+}
+
+pub type FnLoadMip = dyn Fn(u32) -> anyhow::Result<Vec<u8>> + Send + Sync;
+
+/// Returns the extent of the mip `level`.
+pub(crate) fn mip_extent(extent: UVec2, level: u32) -> UVec2 {
+    UVec2::new(extent.x >> level, extent.y >> level).max(UVec2::ONE)
+}
+
+/// Returns the number of mip levels down to `1x1`.
+pub(crate) fn full_mip_chain_len(extent: UVec2) -> u32 {
+    u32::BITS - extent.max_element().leading_zeros()
+}
@@ -0,0 +1,52 @@
+/// Operator which maps HDR colors of the main pass into the output range,
+/// see [`RendererState::set_tonemap`].
+///
+/// [`RendererState::set_tonemap`]: crate::RendererState::set_tonemap
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Tonemap {
+    /// Filmic ACES curve, slightly increases contrast and saturation.
+    #[default]
+    Aces,
+    /// Reinhard operator (`c / (1 + c)`) applied to each channel.
+    Reinhard,
+}
+
+impl Tonemap {
+    pub const ALL: [Self; 2] = [Self::Aces, Self::Reinhard];
+
+    /// Index of the operator in the tonemap shader.
+    pub(crate) fn shader_index(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Returns `true` if the exposure can be set, see [`RendererState::set_exposure`].
+///
+/// [`RendererState::set_exposure`]: crate::RendererState::set_exposure
+pub(crate) fn is_valid_exposure(exposure: f32) -> bool {
+    exposure.is_finite() && exposure >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_indices_match_the_shader() {
+        let source = include_str!("../../../assets/shaders/tonemap.frag");
+        for (tonemap, name) in Tonemap::ALL.into_iter().zip(["ACES", "REINHARD"]) {
+            let define = format!("#define TONEMAP_{name} {}", tonemap.shader_index());
+            assert!(source.contains(&define), "missing `{define}`");
+        }
+    }
+
+    #[test]
+    fn invalid_exposures_are_rejected() {
+        for exposure in [0.0, 1.0, 16.0] {
+            assert!(is_valid_exposure(exposure));
+        }
+        for exposure in [-1.0, f32::NAN, f32::INFINITY] {
+            assert!(!is_valid_exposure(exposure));
+        }
+    }
+}
@@ -4,6 +4,21 @@ use glam::{Vec2, Vec3, Vec4};
 pub trait VertexAttribute: std::fmt::Debug + Default + PartialEq + Pod + Send + Sync {
     const FORMAT: gfx::VertexFormat;
     const KIND: VertexAttributeKind;
+    const ENCODING: VertexAttributeEncoding = VertexAttributeEncoding::Float;
+}
+
+/// Encoding of the vertex attribute data in the vertex buffer.
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VertexAttributeEncoding {
+    /// Vector of 32-bit floats.
+    #[default]
+    Float,
+    /// Vector packed into a single `u32`.
+    ///
+    /// Normals and tangents are stored as 10-10-10-2 SNORM,
+    /// UVs are stored as two half-floats.
+    Packed,
 }
 
 macro_rules! define_vertex_attributes {
@@ -97,9 +112,11 @@ define_vertex_attributes! {
         format: Float32x3,
         tag: 1,
     }
-    /// A tangent vector.
-    Tangent(Vec3) {
-        format: Float32x3,
+    /// A tangent vector with the bitangent sign in `w`.
+    ///
+    /// The bitangent is `cross(normal, tangent.xyz) * tangent.w`.
+    Tangent(Vec4) {
+        format: Float32x4,
         tag: 2,
     }
     /// A local UV coordinate.
@@ -107,15 +124,171 @@ define_vertex_attributes! {
         format: Float32x2,
         tag: 3,
     }
-    /// RGBA color.
+    /// Linear RGBA color.
+    ///
+    /// Colors picked in image editors are usually sRGB encoded
+    /// and must be converted with [`Color::from_srgb`].
     Color(Vec4) {
         format: Float32x4,
         tag: 4,
     }
 }
 
+impl Color {
+    /// Converts an sRGB encoded color into the linear one, alpha is kept linear.
+    pub fn from_srgb(r: u8, g: u8, b: u8, a: u8) -> Self {
+        let srgb = Vec4::new(r as f32, g as f32, b as f32, a as f32) / 255.0;
+        Self(Vec4::new(
+            srgb_to_linear(srgb.x),
+            srgb_to_linear(srgb.y),
+            srgb_to_linear(srgb.z),
+            srgb.w,
+        ))
+    }
+
+    /// Returns linear components of the color, as expected by the material shader data.
+    pub fn to_linear(self) -> Vec4 {
+        self.0
+    }
+
+    /// Converts the color into sRGB encoded bytes, alpha is kept linear.
+    pub fn to_srgb(self) -> [u8; 4] {
+        let srgb = Vec4::new(
+            linear_to_srgb(self.0.x),
+            linear_to_srgb(self.0.y),
+            linear_to_srgb(self.0.z),
+            self.0.w,
+        );
+        (srgb.clamp(Vec4::ZERO, Vec4::ONE) * 255.0)
+            .round()
+            .to_array()
+            .map(|value| value as u8)
+    }
+}
+
+/// NOTE: Must match `linear_to_srgb` in `math/color.glsl`.
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+macro_rules! define_packed_vertex_attributes {
+    ($($(#[$ident_meta:meta])* $ident:ident($unpacked:ident) {
+        format: $format:ident,
+        pack: $pack:ident,
+        unpack: $unpack:ident$(,)?
+    })*) => {
+        $(
+            $(#[$ident_meta])*
+            #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Pod, Zeroable)]
+            #[repr(transparent)]
+            pub struct $ident(pub u32);
+
+            impl VertexAttribute for $ident {
+                const FORMAT: gfx::VertexFormat = gfx::VertexFormat::$format;
+                const KIND: VertexAttributeKind = VertexAttributeKind::$unpacked;
+                const ENCODING: VertexAttributeEncoding = VertexAttributeEncoding::Packed;
+            }
+
+            impl From<$unpacked> for $ident {
+                #[inline]
+                fn from($unpacked(value): $unpacked) -> Self {
+                    Self($pack(value))
+                }
+            }
+
+            impl From<$ident> for $unpacked {
+                #[inline]
+                fn from($ident(value): $ident) -> Self {
+                    Self($unpack(value))
+                }
+            }
+        )*
+    };
+}
+
+define_packed_vertex_attributes! {
+    /// A normal vector packed into 10-10-10-2 SNORM.
+    PackedNormal(Normal) {
+        format: Snorm10_10_10_2,
+        pack: pack_snorm_10_10_10,
+        unpack: unpack_snorm_10_10_10,
+    }
+    /// A tangent vector packed into 10-10-10-2 SNORM,
+    /// the bitangent sign is stored in the 2-bit component.
+    PackedTangent(Tangent) {
+        format: Snorm10_10_10_2,
+        pack: pack_snorm_10_10_10_2,
+        unpack: unpack_snorm_10_10_10_2,
+    }
+    /// A local UV coordinate packed into two half-floats.
+    PackedUV0(UV0) {
+        format: Float16x2,
+        pack: pack_half_2x16,
+        unpack: unpack_half_2x16,
+    }
+}
+
+/// Packs a vector with components in [-1, 1] into the lower 30 bits.
+///
+/// NOTE: Must match `unpack_snorm_10_10_10` in `object.glsl`.
+fn pack_snorm_10_10_10(value: Vec3) -> u32 {
+    const MASK: u32 = (1 << 10) - 1;
+
+    let value = (value.clamp(Vec3::NEG_ONE, Vec3::ONE) * 511.0).round();
+    (value.x as i32 as u32 & MASK)
+        | (value.y as i32 as u32 & MASK) << 10
+        | (value.z as i32 as u32 & MASK) << 20
+}
+
+fn unpack_snorm_10_10_10(packed: u32) -> Vec3 {
+    // NOTE: Shifts the component into the highest bits to extend its sign
+    let component = |offset: u32| ((packed << (22 - offset)) as i32 >> 22) as f32 / 511.0;
+    Vec3::new(component(0), component(10), component(20)).max(Vec3::NEG_ONE)
+}
+
+/// Packs a vector with components in [-1, 1] and the sign of `w`
+/// into 10-10-10-2 bits.
+///
+/// NOTE: Must match `unpack_snorm_10_10_10_2` in `object.glsl`.
+fn pack_snorm_10_10_10_2(value: Vec4) -> u32 {
+    let sign: u32 = if value.w < 0.0 { 0b11 } else { 0b01 };
+    pack_snorm_10_10_10(value.truncate()) | sign << 30
+}
+
+fn unpack_snorm_10_10_10_2(packed: u32) -> Vec4 {
+    let sign = ((packed as i32 >> 30) as f32).max(-1.0);
+    unpack_snorm_10_10_10(packed).extend(sign)
+}
+
+/// Packs a vector into two half-floats, the first one in the lower 16 bits.
+///
+/// NOTE: Must match `unpackHalf2x16` in GLSL.
+fn pack_half_2x16(value: Vec2) -> u32 {
+    gfx::F16::from_f32(value.x).0 as u32 | (gfx::F16::from_f32(value.y).0 as u32) << 16
+}
+
+fn unpack_half_2x16(packed: u32) -> Vec2 {
+    Vec2::new(
+        gfx::F16(packed as u16).to_f32(),
+        gfx::F16((packed >> 16) as u16).to_f32(),
+    )
+}
+
 pub struct VertexAttributeData {
     kind: VertexAttributeKind,
+    encoding: VertexAttributeEncoding,
     ptr: *mut u8,
     byte_len: usize,
     drop_fn: unsafe fn(*mut u8, usize),
@@ -135,6 +308,7 @@ impl VertexAttributeData {
 
         Self {
             kind: T::KIND,
+            encoding: T::ENCODING,
             ptr: ptr.cast(),
             byte_len: bytes,
             drop_fn: drop_vec::<T>,
@@ -145,6 +319,10 @@ impl VertexAttributeData {
         self.kind
     }
 
+    pub fn encoding(&self) -> VertexAttributeEncoding {
+        self.encoding
+    }
+
     pub fn byte_len(&self) -> usize {
         self.byte_len
     }
@@ -155,7 +333,7 @@ impl VertexAttributeData {
     }
 
     pub fn typed_data<T: VertexAttribute>(&self) -> Option<&[T]> {
-        if self.kind == T::KIND {
+        if self.kind == T::KIND && self.encoding == T::ENCODING {
             Some(bytemuck::cast_slice(self.untyped_data()))
         } else {
             None
@@ -163,7 +341,7 @@ impl VertexAttributeData {
     }
 
     pub fn typed_data_mut<T: VertexAttribute>(&mut self) -> Option<&mut [T]> {
-        if self.kind == T::KIND {
+        if self.kind == T::KIND && self.encoding == T::ENCODING {
             // SAFETY: `self.ptr` is a valid pointer to a slice of `self.byte_len` bytes.
             let data = unsafe { std::slice::from_raw_parts_mut(self.ptr, self.byte_len) };
             Some(bytemuck::cast_slice_mut(data))
@@ -208,6 +386,27 @@ unsafe fn drop_vec<T>(ptr: *mut u8, bytes: usize) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn srgb_colors_are_linearized() {
+        // NOTE: Mid-gray of the sRGB curve is much darker in linear space
+        let gray = Color::from_srgb(128, 128, 128, 128).to_linear();
+        assert!((gray.x - 0.2158).abs() < 1e-4, "{gray}");
+        assert!((srgb_to_linear(0.5) - 0.2140).abs() < 1e-4);
+        assert!((linear_to_srgb(0.2140) - 0.5).abs() < 1e-4);
+        // Alpha is not encoded
+        assert_eq!(gray.w, 128.0 / 255.0);
+
+        assert_eq!(Color::from_srgb(0, 0, 0, 0).to_linear(), Vec4::ZERO);
+        assert_eq!(Color::from_srgb(255, 255, 255, 255).to_linear(), Vec4::ONE);
+        // Linear part of the curve near black
+        assert!((srgb_to_linear(0.04) - 0.04 / 12.92).abs() < 1e-6);
+
+        for value in [0, 1, 10, 100, 128, 200, 254, 255] {
+            let color = Color::from_srgb(value, value / 2, 255 - value, value);
+            assert_eq!(color.to_srgb(), [value, value / 2, 255 - value, value]);
+        }
+    }
+
     #[test]
     fn usage_with_different_alignment() {
         const POSITIONS: &[Position] = &[
@@ -259,4 +458,83 @@ mod tests {
         );
         assert_eq!(attribute.typed_data_mut::<UV0>(), None);
     }
+
+    #[test]
+    fn packed_normals_round_trip() {
+        // Max error of the 10-bit SNORM quantization
+        const TOLERANCE: f32 = 0.5 / 511.0 + f32::EPSILON;
+
+        let mut max_error = 0.0f32;
+        for i in 0..32 {
+            for j in 0..64 {
+                let theta = i as f32 / 31.0 * std::f32::consts::PI;
+                let phi = j as f32 / 64.0 * std::f32::consts::TAU;
+                let normal = Normal(Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ));
+
+                let unpacked = Normal::from(PackedNormal::from(normal));
+                max_error = max_error.max((*unpacked - *normal).abs().max_element());
+            }
+        }
+        assert!(max_error <= TOLERANCE, "max error {max_error}");
+
+        // NOTE: Only the sign of `w` is stored
+        for value in [
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(0.0, -1.0, 0.0, -1.0),
+            Vec4::W,
+            Vec4::NEG_ONE,
+        ] {
+            let unpacked = Tangent::from(PackedTangent::from(Tangent(value)));
+            assert_eq!(*unpacked, value);
+        }
+
+        // Values out of range are clamped
+        let unpacked = Normal::from(PackedNormal::from(Normal(Vec3::new(2.0, -3.0, 0.0))));
+        assert_eq!(*unpacked, Vec3::new(1.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn packed_uvs_round_trip() {
+        let mut max_error = 0.0f32;
+        for i in 0..=1024 {
+            let uv = UV0(Vec2::new(i as f32 / 1000.0, 1.0 - i as f32 / 500.0));
+            let unpacked = UV0::from(PackedUV0::from(uv));
+
+            // NOTE: Half-floats have 11 bits of precision
+            let tolerance = uv.abs().max_element().max(1.0) / 2048.0;
+            let error = (*unpacked - *uv).abs().max_element();
+            assert!(error <= tolerance, "{uv:?} unpacked as {unpacked:?}");
+            max_error = max_error.max(error);
+        }
+        assert!(max_error > 0.0);
+
+        for value in [0.0, 0.5, 1.0, -2.0, 1024.0] {
+            let unpacked = UV0::from(PackedUV0::from(UV0(Vec2::splat(value))));
+            assert_eq!(*unpacked, Vec2::splat(value));
+        }
+
+        // Small values are stored as subnormals, large values overflow
+        let round_trip = |value: f32| UV0::from(PackedUV0::from(UV0(Vec2::splat(value)))).x;
+        assert_eq!(round_trip(1e-6), 17.0 / (1 << 24) as f32);
+        assert_eq!(round_trip(1e-9), 0.0);
+        assert_eq!(round_trip(1e6), f32::INFINITY);
+        assert!(round_trip(f32::NAN).is_nan());
+    }
+
+    #[test]
+    fn packed_data_is_not_typed_as_unpacked() {
+        let attribute = VertexAttributeData::new(vec![PackedNormal::from(Normal(Vec3::Y)); 3]);
+        assert_eq!(attribute.kind(), VertexAttributeKind::Normal);
+        assert_eq!(attribute.encoding(), VertexAttributeEncoding::Packed);
+        assert_eq!(attribute.byte_len(), 12);
+        assert!(attribute.typed_data::<Normal>().is_none());
+        assert_eq!(
+            attribute.typed_data::<PackedNormal>().map(<[_]>::len),
+            Some(3)
+        );
+    }
 }
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+/// A value published by a writer and copied by readers.
+///
+/// Publishing atomically swaps in a new value, so neither the writer
+/// nor readers ever wait for each other.
+pub struct AtomicSlot<T> {
+    value: ArcSwap<T>,
+}
+
+impl<T: Default> Default for AtomicSlot<T> {
+    fn default() -> Self {
+        Self {
+            value: ArcSwap::from_pointee(T::default()),
+        }
+    }
+}
+
+impl<T: Copy> AtomicSlot<T> {
+    /// Replaces the value seen by readers.
+    pub fn publish(&self, value: T) {
+        self.value.store(Arc::new(value));
+    }
+
+    /// Returns the last published value.
+    pub fn load(&self) -> T {
+        **self.value.load()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readers_see_the_last_published_value() {
+        let slot = AtomicSlot::<u32>::default();
+        assert_eq!(slot.load(), 0);
+
+        slot.publish(1);
+        assert_eq!(slot.load(), 1);
+        slot.publish(2);
+        slot.publish(3);
+        assert_eq!(slot.load(), 3);
+    }
+
+    #[test]
+    fn readers_never_see_torn_values() {
+        let slot = AtomicSlot::<[u64; 8]>::default();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..10_000 {
+                        let value = slot.load();
+                        assert!(value.iter().all(|item| *item == value[0]));
+                    }
+                });
+            }
+            for i in 0..10_000 {
+                slot.publish([i; 8]);
+            }
+        });
+    }
+}
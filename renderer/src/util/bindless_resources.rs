@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Mutex;
 
@@ -49,6 +50,7 @@ impl BindlessResources {
         // Create descriptor set
         let descriptor_set = device.create_descriptor_set(gfx::DescriptorSetInfo {
             layout: descriptor_set_layout.clone(),
+            label: Some("bindless resources"),
         })?;
 
         Ok(Self {
@@ -68,19 +70,37 @@ impl BindlessResources {
         &self.descriptor_set
     }
 
-    pub fn flush_retired(&self) {
-        self.image_allocator.flush_retired();
-        self.uniform_buffer_allocator.flush_retired();
-        self.storage_buffer_allocator.flush_retired();
+    /// Recycles handles which are no longer used by the GPU.
+    ///
+    /// Handles deallocated since the previous call are retired at `frame`
+    /// and can only be reused once `completed_frame` reaches it.
+    pub fn flush_retired(&self, frame: u32, completed_frame: Option<u32>) {
+        profile_scope!("flush_retired_bindless_handles");
+
+        self.image_allocator.flush_retired(frame, completed_frame);
+        self.uniform_buffer_allocator
+            .flush_retired(frame, completed_frame);
+        self.storage_buffer_allocator
+            .flush_retired(frame, completed_frame);
+    }
+
+    /// Returns slot usage of each descriptor array.
+    pub fn stats(&self) -> BindlessResourcesStats {
+        BindlessResourcesStats {
+            images: self.image_allocator.stats(),
+            uniform_buffers: self.uniform_buffer_allocator.stats(),
+            storage_buffers: self.storage_buffer_allocator.stats(),
+        }
     }
 
-    #[allow(dead_code)]
     pub fn alloc_image(
         &self,
         device: &gfx::Device,
         image: gfx::ImageView,
         sampler: gfx::Sampler,
     ) -> SampledImageHandle {
+        profile_scope!("alloc_bindless_image");
+
         let handle = self.image_allocator.alloc();
 
         device.update_descriptor_sets(&[gfx::UpdateDescriptorSet {
@@ -99,7 +119,6 @@ impl BindlessResources {
         handle
     }
 
-    #[allow(dead_code)]
     pub fn free_image(&self, handle: SampledImageHandle) {
         self.image_allocator.dealloc(handle);
     }
@@ -110,6 +129,8 @@ impl BindlessResources {
         device: &gfx::Device,
         buffer: gfx::BufferRange,
     ) -> UniformBufferHandle {
+        profile_scope!("alloc_bindless_uniform_buffer");
+
         let handle = self.uniform_buffer_allocator.alloc();
 
         device.update_descriptor_sets(&[gfx::UpdateDescriptorSet {
@@ -134,6 +155,8 @@ impl BindlessResources {
         device: &gfx::Device,
         buffer: gfx::BufferRange,
     ) -> StorageBufferHandle {
+        profile_scope!("alloc_bindless_storage_buffer");
+
         let handle = self.storage_buffer_allocator.alloc();
 
         device.update_descriptor_sets(&[gfx::UpdateDescriptorSet {
@@ -157,6 +180,23 @@ impl BindlessResources {
     }
 }
 
+/// Slot usage of the bindless descriptor arrays.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BindlessResourcesStats {
+    pub images: BindlessSlotStats,
+    pub uniform_buffers: BindlessSlotStats,
+    pub storage_buffers: BindlessSlotStats,
+}
+
+/// Slot usage of a bindless descriptor array.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BindlessSlotStats {
+    /// Number of allocated slots.
+    pub live: u32,
+    /// Number of deallocated slots which might still be used by the GPU.
+    pub retired: u32,
+}
+
 #[repr(u8)]
 pub enum GpuResourceKind {
     UniformBuffer = 0,
@@ -173,8 +213,9 @@ type ImageHandleAllocator = GpuResourceHandleAllocator<{ GpuResourceKind::Sample
 /// Allocator for GPU resource handles with two-stage deallocation.
 ///
 /// When a handle is deallocated, it is not immediately returned to the free list,
-/// but instead is added to the retired list. The free list is only updated when
-/// `flush_retired` is called.
+/// but instead is added to the retired list. On `flush_retired` retired handles
+/// are tagged with the current frame and are only moved to the free list when
+/// that frame is completed by the GPU.
 #[derive(Default)]
 struct GpuResourceHandleAllocator<const KIND: u8> {
     next_index: AtomicU32,
@@ -216,21 +257,68 @@ impl<const KIND: u8> GpuResourceHandleAllocator<KIND> {
             .extend_from_slice(bytemuck::cast_slice(handles));
     }
 
-    fn flush_retired(&self) {
-        fn flush_retired_impl(unused_handles: &Mutex<UnusedHandles>) {
-            let mut handles = unused_handles.lock().unwrap();
-            let handles = &mut *handles;
-            handles.free_list.append(&mut handles.retired_list);
-            // TODO: sort free list?
+    fn flush_retired(&self, frame: u32, completed_frame: Option<u32>) {
+        self.unused_handles
+            .lock()
+            .unwrap()
+            .flush_retired(frame, completed_frame);
+    }
+
+    fn stats(&self) -> BindlessSlotStats {
+        let handles = self.unused_handles.lock().unwrap();
+        let total = self.next_index.load(Ordering::Relaxed);
+        let retired = handles.retired_count();
+        BindlessSlotStats {
+            live: total - retired - handles.free_list.len() as u32,
+            retired,
         }
-        flush_retired_impl(&self.unused_handles)
     }
 }
 
 #[derive(Default)]
 struct UnusedHandles {
     free_list: Vec<u32>,
+    /// Handles deallocated since the last flush.
     retired_list: Vec<u32>,
+    /// Flushed handles with the frame at which they were retired,
+    /// ordered by the frame.
+    pending: VecDeque<(u32, Vec<u32>)>,
+}
+
+impl UnusedHandles {
+    fn flush_retired(&mut self, frame: u32, completed_frame: Option<u32>) {
+        if !self.retired_list.is_empty() {
+            // NOTE: Handles might have been used while recording any frame
+            // up to the current one.
+            let retired = std::mem::take(&mut self.retired_list);
+            self.pending.push_back((frame, retired));
+        }
+
+        let Some(completed_frame) = completed_frame else {
+            return;
+        };
+        while let Some((retired_at, _)) = self.pending.front() {
+            if *retired_at > completed_frame {
+                break;
+            }
+            let (_, mut handles) = self.pending.pop_front().unwrap();
+            self.free_list.append(&mut handles);
+
+            // NOTE: Reuse the allocation for the next batch
+            if self.retired_list.capacity() < handles.capacity() {
+                self.retired_list = handles;
+            }
+        }
+    }
+
+    fn retired_count(&self) -> u32 {
+        let pending = self
+            .pending
+            .iter()
+            .map(|(_, handles)| handles.len())
+            .sum::<usize>();
+        (self.retired_list.len() + pending) as u32
+    }
 }
 
 pub type UniformBufferHandle = GpuResourceHandle<{ GpuResourceKind::UniformBuffer as u8 }>;
@@ -327,3 +415,92 @@ const STORAGE_BUFFER_BINDING: u32 = 2;
 const IMAGE_CAPACITY: u32 = 1024;
 const UNIFORM_BUFFER_CAPACITY: u32 = 1024;
 const STORAGE_BUFFER_CAPACITY: u32 = 1024;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAMES_IN_FLIGHT: u32 = 2;
+
+    #[test]
+    fn retired_handles_wait_for_frame_completion() {
+        let allocator = StorageBufferHandleAllocator::default();
+        let handle = allocator.alloc();
+        allocator.dealloc(handle);
+        assert_eq!(
+            allocator.stats(),
+            BindlessSlotStats {
+                live: 0,
+                retired: 1
+            }
+        );
+
+        allocator.flush_retired(10, Some(8));
+        assert_ne!(allocator.alloc().index(), handle.index());
+
+        allocator.flush_retired(11, Some(9));
+        assert_ne!(allocator.alloc().index(), handle.index());
+
+        allocator.flush_retired(12, Some(10));
+        let recycled = allocator.alloc();
+        assert_eq!(recycled.index(), handle.index());
+        assert_ne!(recycled.version(), handle.version());
+        assert_eq!(
+            allocator.stats(),
+            BindlessSlotStats {
+                live: 3,
+                retired: 0
+            }
+        );
+    }
+
+    /// Simulates objects rapidly replacing their buffers while frames are in flight.
+    #[test]
+    fn handles_are_not_reused_while_in_flight() {
+        let allocator = StorageBufferHandleAllocator::default();
+
+        // Handles referenced by each frame which is not completed yet
+        let mut in_flight = VecDeque::<(u32, Vec<u32>)>::new();
+        let mut live = Vec::new();
+        let mut rng = 0x2545f491u32;
+        let mut next_random = move || {
+            rng ^= rng << 13;
+            rng ^= rng >> 17;
+            rng ^= rng << 5;
+            rng
+        };
+
+        for frame in 0..1000u32 {
+            let completed_frame = frame.checked_sub(FRAMES_IN_FLIGHT);
+            if let Some(completed_frame) = completed_frame {
+                in_flight.retain(|(used_at, _)| *used_at > completed_frame);
+            }
+            allocator.flush_retired(frame, completed_frame);
+
+            for _ in 0..next_random() % 16 {
+                let handle = allocator.alloc();
+                for (used_at, handles) in &in_flight {
+                    assert!(
+                        !handles.contains(&handle.index()),
+                        "slot {} is reused while frame {used_at} is in flight",
+                        handle.index()
+                    );
+                }
+                live.push(handle);
+            }
+            // NOTE: Handles can be drawn before they are removed in the same frame
+            in_flight.push_back((frame, live.iter().map(|h| h.index()).collect()));
+
+            for _ in 0..next_random() % 16 {
+                if live.is_empty() {
+                    break;
+                }
+                let handle = live.swap_remove(next_random() as usize % live.len());
+                allocator.dealloc(handle);
+            }
+
+            let stats = allocator.stats();
+            assert_eq!(stats.live as usize, live.len());
+        }
+    }
+}
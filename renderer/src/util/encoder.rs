@@ -1,11 +1,13 @@
 use anyhow::Result;
 
+use crate::util::{FramebufferCache, RenderPassCache};
+
 pub trait EncoderExt {
     fn with_render_pass<'a, 'b, P>(
         &'a mut self,
         pass: &'b mut P,
         input: &P::Input,
-        device: &gfx::Device,
+        ctx: &RenderPassContext<'_>,
     ) -> Result<gfx::RenderPassEncoder<'a, 'b>>
     where
         P: RenderPass;
@@ -16,22 +18,30 @@ impl EncoderExt for gfx::Encoder {
         &'a mut self,
         pass: &'b mut P,
         input: &P::Input,
-        device: &gfx::Device,
+        ctx: &RenderPassContext<'_>,
     ) -> Result<gfx::RenderPassEncoder<'a, 'b>>
     where
         P: RenderPass,
     {
-        pass.begin_render_pass(input, device, self)
+        pass.begin_render_pass(input, ctx, self)
     }
 }
 
+/// Objects shared by all render passes of the frame.
+pub struct RenderPassContext<'a> {
+    pub device: &'a gfx::Device,
+    pub render_passes: &'a RenderPassCache,
+    pub framebuffers: &'a FramebufferCache,
+    pub frame: u32,
+}
+
 pub trait RenderPass {
     type Input;
 
     fn begin_render_pass<'a, 'b>(
         &'b mut self,
         input: &Self::Input,
-        device: &gfx::Device,
+        ctx: &RenderPassContext<'_>,
         encoder: &'a mut gfx::Encoder,
     ) -> Result<gfx::RenderPassEncoder<'a, 'b>>;
 }
@@ -77,13 +87,18 @@ impl RenderPassEncoderExt for gfx::RenderPassEncoder<'_, '_> {
 
 pub struct CachedGraphicsPipeline {
     descr: gfx::GraphicsPipelineDescr,
-    cached: Option<gfx::GraphicsPipeline>,
+    /// Pipelines for each render pass in which the pipeline was used,
+    /// e.g. for the main pass and for render targets of other formats.
+    cached: Vec<gfx::GraphicsPipeline>,
 }
 
 impl CachedGraphicsPipeline {
+    /// Maximum number of render passes for which pipelines are kept.
+    const MAX_CACHED: usize = 4;
+
     pub fn new(descr: gfx::GraphicsPipelineDescr) -> Self {
         Self {
-            cached: None,
+            cached: Vec::new(),
             descr,
         }
     }
@@ -92,34 +107,256 @@ impl CachedGraphicsPipeline {
         &self.descr
     }
 
+    /// Replaces the pipeline description.
+    ///
+    /// Pipelines which were already created are recreated immediately
+    /// with the same rendering info. On failure the previous pipelines are kept.
+    pub fn update_descr(
+        &mut self,
+        device: &gfx::Device,
+        descr: gfx::GraphicsPipelineDescr,
+    ) -> Result<()> {
+        let cached = self
+            .cached
+            .iter()
+            .map(|pipeline| {
+                device.create_graphics_pipeline(gfx::GraphicsPipelineInfo {
+                    descr: descr.clone(),
+                    rendering: pipeline.info().rendering.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.cached = cached;
+        self.descr = descr;
+        Ok(())
+    }
+
     pub fn prepare(
         &mut self,
         device: &gfx::Device,
         render_pass: &gfx::RenderPass,
         subpass: u32,
     ) -> Result<&gfx::GraphicsPipeline> {
-        if let Some(pipeline) = &mut self.cached {
-            let info = pipeline.info();
+        let descr = &self.descr;
+        self.cached
+            .retain(|pipeline| pipeline.info().descr == *descr);
 
-            let compatible =
-                &info.rendering.render_pass == render_pass && info.rendering.subpass == subpass;
+        let compatible = self.cached.iter().position(|pipeline| {
+            let info = &pipeline.info().rendering;
+            &info.render_pass == render_pass && info.subpass == subpass
+        });
 
-            if !compatible || info.descr != self.descr {
-                self.cached = None;
-            }
-        }
-
-        Ok(match &mut self.cached {
-            Some(pipeline) => pipeline,
-            cached => cached.get_or_insert(device.create_graphics_pipeline(
-                gfx::GraphicsPipelineInfo {
+        let index = match compatible {
+            Some(index) => index,
+            None => {
+                let pipeline = device.create_graphics_pipeline(gfx::GraphicsPipelineInfo {
                     descr: self.descr.clone(),
                     rendering: gfx::GraphicsPipelineRenderingInfo {
                         render_pass: render_pass.clone(),
                         subpass,
                     },
+                })?;
+                // NOTE: Pipelines of the least recently added render pass are dropped first
+                if self.cached.len() == Self::MAX_CACHED {
+                    self.cached.remove(0);
+                }
+                self.cached.push(pipeline);
+                self.cached.len() - 1
+            }
+        };
+        Ok(&self.cached[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx::MakeImageView;
+    use glam::UVec2;
+
+    use super::*;
+    use crate::util::ShaderPreprocessor;
+    use crate::RendererBuilder;
+
+    const FULLSCREEN_VERT: &str = r#"
+        #version 450
+        void main() {
+            vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+            gl_Position = vec4(position * 2.0f - 1.0f, 0.0f, 1.0f);
+        }
+    "#;
+
+    const WHITE_FRAG: &str = r#"
+        #version 450
+        layout (location = 0) out vec4 out_frag_color;
+        void main() {
+            out_frag_color = vec4(1.0f);
+        }
+    "#;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn indexed_indirect_draws_read_commands_at_offset() {
+        const SIZE: u32 = 4;
+        const FORMAT: gfx::Format = gfx::Format::RGBA8Unorm;
+
+        let renderer = RendererBuilder::headless(SIZE, SIZE).build().unwrap();
+        let state = renderer.state();
+        let device = &state.device;
+
+        let mut shaders = ShaderPreprocessor::new();
+        shaders
+            .add_file("fullscreen.vert", FULLSCREEN_VERT)
+            .unwrap();
+        shaders.add_file("white.frag", WHITE_FRAG).unwrap();
+        let shaders = shaders.begin();
+        let layout = device
+            .create_pipeline_layout(gfx::PipelineLayoutInfo {
+                sets: Vec::new(),
+                push_constants: Vec::new(),
+            })
+            .unwrap();
+        let mut pipeline = CachedGraphicsPipeline::new(gfx::GraphicsPipelineDescr {
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            primitive_topology: gfx::PrimitiveTopology::TriangleList,
+            primitive_restart_enable: false,
+            vertex_shader: shaders
+                .make_vertex_shader(device, "fullscreen.vert", "main")
+                .unwrap(),
+            rasterizer: Some(gfx::Rasterizer {
+                fragment_shader: Some(
+                    shaders
+                        .make_fragment_shader(device, "white.frag", "main")
+                        .unwrap(),
+                ),
+                ..Default::default()
+            }),
+            layout,
+        });
+
+        let target = device
+            .create_image(gfx::ImageInfo {
+                extent: UVec2::splat(SIZE).into(),
+                format: FORMAT,
+                mip_levels: 1,
+                samples: gfx::Samples::_1,
+                array_layers: 1,
+                usage: gfx::ImageUsageFlags::COLOR_ATTACHMENT | gfx::ImageUsageFlags::TRANSFER_SRC,
+                flags: gfx::ImageCreateFlags::empty(),
+                label: Some("indirect draw target"),
+            })
+            .unwrap();
+        let render_pass = device
+            .create_render_pass(gfx::RenderPassInfo {
+                attachments: vec![gfx::AttachmentInfo {
+                    format: FORMAT,
+                    samples: gfx::Samples::_1,
+                    load_op: gfx::LoadOp::Clear(()),
+                    store_op: gfx::StoreOp::Store,
+                    initial_layout: None,
+                    final_layout: gfx::ImageLayout::TransferSrcOptimal,
+                }],
+                subpasses: vec![gfx::Subpass {
+                    colors: vec![(0, gfx::ImageLayout::ColorAttachmentOptimal)],
+                    resolves: Vec::new(),
+                    depth: None,
+                }],
+                dependencies: vec![gfx::SubpassDependency {
+                    src: Some(0),
+                    src_stages: gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    dst: None,
+                    dst_stages: gfx::PipelineStageFlags::TRANSFER,
+                }],
+            })
+            .unwrap();
+        let framebuffer = device
+            .create_framebuffer(gfx::FramebufferInfo {
+                render_pass,
+                attachments: vec![target.make_image_view(device).unwrap()],
+                extent: UVec2::splat(SIZE),
+            })
+            .unwrap();
+
+        let make_buffer = |usage, data: &[u32], label| {
+            let buffer = device
+                .create_mappable_buffer(
+                    gfx::BufferInfo {
+                        align_mask: 0b11,
+                        size: std::mem::size_of_val(data),
+                        usage,
+                        label: Some(label),
+                    },
+                    gfx::MemoryUsage::UPLOAD,
+                )
+                .unwrap();
+            device
+                .upload_to_memory(&mut buffer.as_mappable(), 0, data)
+                .unwrap();
+            buffer
+        };
+        let indices = make_buffer(gfx::BufferUsage::INDEX, &[0, 1, 2], "indirect draw indices");
+
+        // NOTE: Only the second command draws the triangle
+        let empty = gfx::DrawIndexedIndirectCommand {
+            index_count: 3,
+            ..Default::default()
+        };
+        let fullscreen = gfx::DrawIndexedIndirectCommand {
+            index_count: 3,
+            instance_count: 1,
+            ..Default::default()
+        };
+        let commands = make_buffer(
+            gfx::BufferUsage::INDIRECT,
+            bytemuck::cast_slice(&[empty, fullscreen]),
+            "indirect draws",
+        );
+
+        let readback = device
+            .create_mappable_buffer(
+                gfx::BufferInfo {
+                    align_mask: 0b11,
+                    size: (SIZE * SIZE * 4) as usize,
+                    usage: gfx::BufferUsage::TRANSFER_DST,
+                    label: Some("indirect draw readback"),
                 },
-            )?),
-        })
+                gfx::MemoryUsage::DOWNLOAD,
+            )
+            .unwrap();
+
+        let mut encoder = state.queue.create_primary_encoder().unwrap();
+        {
+            let mut pass = encoder
+                .with_framebuffer(&framebuffer, &[gfx::ClearColor(0.0, 0.0, 0.0, 1.0).into()]);
+            pass.bind_cached_graphics_pipeline(&mut pipeline, device)
+                .unwrap();
+            pass.bind_index_buffer(&indices, 0, gfx::IndexType::U32);
+            let command_size = std::mem::size_of::<gfx::DrawIndexedIndirectCommand>();
+            pass.draw_indexed_indirect(&commands, command_size, 1, command_size as u32);
+        }
+        encoder.copy_image_to_buffer(
+            &target,
+            gfx::ImageLayout::TransferSrcOptimal,
+            &readback,
+            &[gfx::BufferImageCopy::tightly_packed(
+                FORMAT,
+                0,
+                gfx::ImageSubresourceLayers::color(0, 0..1),
+                UVec2::splat(SIZE),
+            )],
+        );
+
+        let command_buffer = encoder.finish().unwrap();
+        state.queue.submit_simple(command_buffer, None).unwrap();
+        state.queue.wait_idle().unwrap();
+
+        let mut pixels = vec![0u8; readback.info().size];
+        device
+            .download_from_memory(&mut readback.as_mappable(), 0, &mut pixels)
+            .unwrap();
+        assert!(
+            pixels.iter().all(|&c| c == u8::MAX),
+            "target is not covered"
+        );
     }
 }
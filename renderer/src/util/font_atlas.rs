@@ -0,0 +1,239 @@
+use glam::UVec2;
+
+/// An `R8Unorm` atlas with glyphs of the printable ASCII characters.
+///
+/// Glyphs are laid out on a monospace grid, so proportional fonts
+/// are drawn with the advance of their widest glyph.
+pub struct FontAtlas {
+    data: Vec<u8>,
+    extent: UVec2,
+    glyph_size: UVec2,
+    glyph_advance: UVec2,
+}
+
+impl FontAtlas {
+    /// Returns the atlas of the built-in 9 pixels tall bitmap font.
+    pub fn builtin() -> Self {
+        super::bitmap_font::make_atlas()
+    }
+
+    /// Rasterizes a TrueType or OpenType font into cells `height` pixels tall.
+    #[cfg(feature = "ab_glyph")]
+    pub fn from_font(data: &[u8], height: f32) -> anyhow::Result<Self> {
+        use ab_glyph::{Font, FontRef, ScaleFont};
+
+        anyhow::ensure!(
+            height.is_finite() && height >= 1.0,
+            "invalid font height: {height}"
+        );
+        let font = FontRef::try_from_slice(data)?;
+        let font = font.as_scaled(height);
+
+        let width = printable_chars()
+            .map(|ch| font.h_advance(font.glyph_id(ch)))
+            .fold(1.0, f32::max);
+        let glyph_size = UVec2::new(width.ceil() as u32, font.height().ceil() as u32);
+        let line_gap = font.line_gap().round().max(0.0) as u32;
+
+        Ok(Self::new(
+            glyph_size,
+            glyph_size + UVec2::Y * line_gap,
+            |ch, cell| {
+                // NOTE: The baseline is at the ascent from the top of the cell
+                let mut glyph = font.scaled_glyph(ch);
+                glyph.position = ab_glyph::point(0.0, font.ascent());
+                let Some(outline) = font.outline_glyph(glyph) else {
+                    return;
+                };
+
+                let bounds = outline.px_bounds();
+                outline.draw(|x, y, coverage| {
+                    let x = bounds.min.x as i32 + x as i32;
+                    let y = bounds.min.y as i32 + y as i32;
+                    if (0..glyph_size.x as i32).contains(&x)
+                        && (0..glyph_size.y as i32).contains(&y)
+                    {
+                        let texel = &mut cell[(y as u32 * glyph_size.x + x as u32) as usize];
+                        *texel = (*texel).max((coverage * u8::MAX as f32).round() as u8);
+                    }
+                });
+            },
+        ))
+    }
+
+    /// Builds the atlas from the glyphs drawn into row-major cells of `glyph_size`.
+    pub(super) fn new<F>(glyph_size: UVec2, glyph_advance: UVec2, mut draw_glyph: F) -> Self
+    where
+        F: FnMut(char, &mut [u8]),
+    {
+        let rows = GLYPH_COUNT.div_ceil(ATLAS_COLUMNS);
+        let extent = glyph_size * UVec2::new(ATLAS_COLUMNS, rows);
+
+        let mut data = vec![0u8; (extent.x * extent.y) as usize];
+        let mut cell = vec![0u8; (glyph_size.x * glyph_size.y) as usize];
+        for (index, ch) in printable_chars().enumerate() {
+            cell.fill(0);
+            draw_glyph(ch, &mut cell);
+
+            let origin = atlas_texel(glyph_size, index as u32);
+            for (y, row) in cell.chunks_exact(glyph_size.x as usize).enumerate() {
+                let start = ((origin.y + y as u32) * extent.x + origin.x) as usize;
+                data[start..start + row.len()].copy_from_slice(row);
+            }
+        }
+
+        Self {
+            data,
+            extent,
+            glyph_size,
+            glyph_advance,
+        }
+    }
+
+    /// Returns the texels of the atlas.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the size of the atlas in pixels.
+    pub fn extent(&self) -> UVec2 {
+        self.extent
+    }
+
+    /// Returns the size of the glyphs in pixels.
+    pub fn glyph_size(&self) -> UVec2 {
+        self.glyph_size
+    }
+
+    /// Returns the distance between the origins of neighbouring glyphs and lines.
+    pub fn glyph_advance(&self) -> UVec2 {
+        self.glyph_advance
+    }
+
+    /// Lays out the text from its top left corner, line by line.
+    ///
+    /// Characters which are not in the font are replaced with `?`,
+    /// whitespace only advances the position.
+    pub fn layout_text<'a>(&self, text: &'a str) -> impl Iterator<Item = PositionedGlyph> + 'a {
+        let glyph_size = self.glyph_size;
+        let glyph_advance = self.glyph_advance;
+        text.split('\n').enumerate().flat_map(move |(line, chars)| {
+            chars.chars().enumerate().filter_map(move |(column, ch)| {
+                if ch.is_whitespace() {
+                    return None;
+                }
+                Some(PositionedGlyph {
+                    offset: glyph_advance * UVec2::new(column as u32, line as u32),
+                    texel: atlas_texel(glyph_size, glyph_index(ch)),
+                })
+            })
+        })
+    }
+
+    /// Returns the size of the laid out text in font pixels.
+    pub fn measure_text(&self, text: &str) -> UVec2 {
+        let (lines, columns) = text.split('\n').fold((0, 0), |(lines, columns), line| {
+            (lines + 1, columns.max(line.chars().count() as u32))
+        });
+        // NOTE: There is no gap after the last column and line
+        (self.glyph_advance * UVec2::new(columns, lines))
+            .saturating_sub(self.glyph_advance - self.glyph_size)
+    }
+}
+
+/// Glyph of the laid out text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PositionedGlyph {
+    /// Top left corner of the glyph in font pixels from the text origin.
+    pub offset: UVec2,
+    /// Top left corner of the glyph in the atlas.
+    pub texel: UVec2,
+}
+
+fn printable_chars() -> impl Iterator<Item = char> {
+    FIRST_CHAR..=LAST_CHAR
+}
+
+fn glyph_index(ch: char) -> u32 {
+    let index = (ch as u32).wrapping_sub(FIRST_CHAR as u32);
+    if index < GLYPH_COUNT {
+        index
+    } else {
+        FALLBACK_CHAR as u32 - FIRST_CHAR as u32
+    }
+}
+
+fn atlas_texel(glyph_size: UVec2, glyph_index: u32) -> UVec2 {
+    glyph_size * UVec2::new(glyph_index % ATLAS_COLUMNS, glyph_index / ATLAS_COLUMNS)
+}
+
+const FIRST_CHAR: char = ' ';
+const LAST_CHAR: char = '~';
+const FALLBACK_CHAR: char = '?';
+const GLYPH_COUNT: u32 = LAST_CHAR as u32 - FIRST_CHAR as u32 + 1;
+const ATLAS_COLUMNS: u32 = 16;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atlas_contains_drawn_glyphs() {
+        let glyph_size = UVec2::new(2, 3);
+        let atlas = FontAtlas::new(glyph_size, glyph_size, |ch, cell| {
+            if ch == 'a' {
+                // Bottom right pixel
+                cell[5] = u8::MAX;
+            }
+        });
+        assert_eq!(atlas.extent(), UVec2::new(2 * ATLAS_COLUMNS, 3 * 6));
+        assert_eq!(
+            atlas.data().len(),
+            (atlas.extent().x * atlas.extent().y) as usize
+        );
+
+        let texel = atlas_texel(glyph_size, glyph_index('a')) + UVec2::new(1, 2);
+        for (index, &value) in atlas.data().iter().enumerate() {
+            let expected = index as u32 == texel.y * atlas.extent().x + texel.x;
+            assert_eq!(value == u8::MAX, expected);
+        }
+    }
+
+    #[test]
+    fn text_is_laid_out_by_lines() {
+        let atlas = FontAtlas::builtin();
+        let size = atlas.glyph_size();
+        let advance = atlas.glyph_advance();
+
+        let glyphs = atlas.layout_text("ab c\nd\u{e9}").collect::<Vec<_>>();
+        let offsets = glyphs.iter().map(|glyph| glyph.offset).collect::<Vec<_>>();
+        assert_eq!(
+            offsets,
+            [
+                UVec2::new(0, 0),
+                UVec2::new(advance.x, 0),
+                UVec2::new(3 * advance.x, 0),
+                UVec2::new(0, advance.y),
+                UVec2::new(advance.x, advance.y),
+            ]
+        );
+        assert_eq!(
+            glyphs[0].texel,
+            atlas_texel(size, 'a' as u32 - FIRST_CHAR as u32)
+        );
+        assert_eq!(glyphs[4].texel, atlas_texel(size, glyph_index('?')));
+
+        assert_eq!(atlas.measure_text(""), UVec2::new(0, size.y));
+        assert_eq!(atlas.measure_text("a"), size);
+        assert_eq!(
+            atlas.measure_text("ab c\nd"),
+            UVec2::new(3 * advance.x + size.x, advance.y + size.y)
+        );
+    }
+
+    #[cfg(feature = "ab_glyph")]
+    #[test]
+    fn invalid_fonts_are_rejected() {
+        assert!(FontAtlas::from_font(b"not a font", 16.0).is_err());
+    }
+}
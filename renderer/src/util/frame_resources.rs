@@ -3,21 +3,27 @@ use std::sync::{Mutex, MutexGuard};
 
 use anyhow::Result;
 use gfx::AsStd140;
-use glam::{Mat4, UVec2};
+use glam::{Mat4, UVec2, Vec4};
 
-use crate::types::CameraProjection;
+use crate::managers::MeshBufferAddresses;
+use crate::types::{
+    CameraProjection, DepthMode, DirectionalLight, ShadowBias, MAX_RENDER_TARGETS_PER_FRAME,
+};
 use crate::util::Frustum;
 
 pub struct FrameResources {
     descriptor_set_layout: gfx::DescriptorSetLayout,
     descriptor_set: gfx::DescriptorSet,
+    depth_mode: DepthMode,
     camera_data: Mutex<CameraData>,
+    light_data: Mutex<LightData>,
+    exposure: Mutex<f32>,
     buffer: Mutex<UniformBuffer>,
 }
 
 impl FrameResources {
     #[tracing::instrument(level = "debug", name = "create_frame_resources", skip_all)]
-    pub fn new(device: &gfx::Device) -> Result<Self> {
+    pub fn new(device: &gfx::Device, depth_mode: DepthMode) -> Result<Self> {
         // Create descriptor set layout and descriptor set
         let descriptor_set_layout =
             device.create_descriptor_set_layout(gfx::DescriptorSetLayoutInfo {
@@ -32,6 +38,7 @@ impl FrameResources {
             })?;
         let descriptor_set = device.create_descriptor_set(gfx::DescriptorSetInfo {
             layout: descriptor_set_layout.clone(),
+            label: Some("frame resources"),
         })?;
 
         // Create uniform buffer
@@ -44,7 +51,7 @@ impl FrameResources {
                 binding: 0,
                 element: 0,
                 data: gfx::DescriptorSlice::UniformBufferDynamic(&[gfx::BufferRange {
-                    buffer: buffer.inner.clone(),
+                    buffer: buffer.inner.buffer().clone(),
                     offset: 0,
                     size: gfx::align_size(
                         <GpuFrameGlobals as gfx::Std140>::ALIGN_MASK,
@@ -57,7 +64,10 @@ impl FrameResources {
         Ok(Self {
             descriptor_set_layout,
             descriptor_set,
+            depth_mode,
             camera_data: Mutex::new(CameraData::default()),
+            light_data: Mutex::default(),
+            exposure: Mutex::new(1.0),
             buffer: Mutex::new(buffer),
         })
     }
@@ -77,8 +87,32 @@ impl FrameResources {
         camera.updated = true;
     }
 
+    pub fn set_directional_light(&self, light: Option<DirectionalLight>) {
+        self.light_data.lock().unwrap().light = light;
+    }
+
+    pub fn set_shadow_bias(&self, bias: ShadowBias) {
+        self.light_data.lock().unwrap().bias = bias;
+    }
+
+    pub fn shadow_bias(&self) -> ShadowBias {
+        self.light_data.lock().unwrap().bias
+    }
+
+    pub fn set_exposure(&self, exposure: f32) {
+        *self.exposure.lock().unwrap() = exposure;
+    }
+
+    pub fn exposure(&self) -> f32 {
+        *self.exposure.lock().unwrap()
+    }
+
     /// Update the uniform buffer and return the byte offset of the updated data
-    pub fn flush(&self, args: FlushFrameResources) -> FrameResourcesGuard<'_> {
+    pub fn flush(
+        &self,
+        device: &gfx::Device,
+        args: FlushFrameResources,
+    ) -> Result<FrameResourcesGuard<'_>> {
         const TIME_ROLLOVER: f32 = 3600.0;
 
         let mut camera_data = self.camera_data.lock().unwrap();
@@ -90,6 +124,8 @@ impl FrameResources {
         globals.time = (globals.time + args.delta_time) % TIME_ROLLOVER;
         globals.delta_time = args.delta_time;
         globals.frame_index = args.frame;
+        globals.interpolation_factor = args.interpolation_factor;
+        globals.output_resolution = args.output_resolution;
 
         if std::mem::take(&mut camera_data.updated)
             || args.render_resolution != globals.render_resolution
@@ -102,10 +138,13 @@ impl FrameResources {
             globals.camera_view = camera_data.view;
             globals.camera_projection = camera_data
                 .projection
-                .compute_projection_matrix(aspect_ratio);
+                .compute_projection_matrix(aspect_ratio, self.depth_mode);
             globals.camera_view_inverse = globals.camera_view.inverse();
             globals.camera_projection_inverse = globals.camera_projection.inverse();
-            globals.frustum = Frustum::new(globals.camera_projection * globals.camera_view);
+            globals.frustum = Frustum::new(
+                globals.camera_projection * globals.camera_view,
+                self.depth_mode,
+            );
 
             if !camera_data.initialized {
                 globals.camera_previous_view = globals.camera_view;
@@ -114,19 +153,87 @@ impl FrameResources {
             }
         }
 
-        buffer.flush();
+        // NOTE: The shadow map follows the camera, so it is updated every frame
+        let light_data = self.light_data.lock().unwrap();
+        match &light_data.light {
+            Some(light) => {
+                let camera_position = globals.camera_view_inverse.w_axis.truncate();
+                globals.light_view_projection = light.compute_view_projection(camera_position);
+                globals.light_direction = light.direction.normalize_or_zero().extend(0.0);
+                globals.light_color = light.color.extend(1.0);
+                globals.shadow_map_index = args.shadow_map_index;
+                globals.shadow_normal_offset =
+                    light_data.bias.normal_offset * light.shadow_texel_size();
+            }
+            None => {
+                globals.light_color = Vec4::ZERO;
+                globals.shadow_map_index = u32::MAX;
+            }
+        }
+        drop(light_data);
 
-        FrameResourcesGuard { buffer }
+        globals.exposure = self.exposure();
+
+        let addresses = args.mesh_buffer_addresses;
+        globals.vertex_buffer_address = split_address(addresses.map(|a| a.vertices));
+        globals.index_buffer_address = split_address(addresses.map(|a| a.indices));
+
+        buffer.flush(device)?;
+
+        Ok(FrameResourcesGuard {
+            buffer,
+            depth_mode: self.depth_mode,
+        })
     }
 }
 
 pub struct FrameResourcesGuard<'a> {
     buffer: MutexGuard<'a, UniformBuffer>,
+    depth_mode: DepthMode,
 }
 
 impl FrameResourcesGuard<'_> {
     pub fn dynamic_offset(&self) -> u32 {
-        self.buffer.current_offset()
+        self.buffer.view_offset(0)
+    }
+
+    /// Writes globals of an additional camera of the frame, e.g. of a render target.
+    ///
+    /// Views share all globals of the frame except for the camera
+    /// and the render resolution. `view` must be in `1..=MAX_RENDER_TARGETS_PER_FRAME`.
+    pub fn flush_view(
+        &mut self,
+        device: &gfx::Device,
+        view: usize,
+        camera_view: &Mat4,
+        projection: &CameraProjection,
+        render_resolution: UVec2,
+    ) -> Result<FrameViewGlobals> {
+        assert!((1..UniformBuffer::VIEWS_PER_FRAME).contains(&view));
+
+        let mut globals = self.buffer.globals.clone();
+
+        let aspect_ratio = render_resolution.x as f32 / render_resolution.y as f32;
+        globals.render_resolution = render_resolution;
+        globals.output_resolution = render_resolution;
+        globals.camera_view = *camera_view;
+        globals.camera_projection =
+            projection.compute_projection_matrix(aspect_ratio, self.depth_mode);
+        globals.camera_view_inverse = globals.camera_view.inverse();
+        globals.camera_projection_inverse = globals.camera_projection.inverse();
+        globals.frustum = Frustum::new(
+            globals.camera_projection * globals.camera_view,
+            self.depth_mode,
+        );
+        // NOTE: Views don't keep the history of their cameras
+        globals.camera_previous_view = globals.camera_view;
+        globals.camera_previous_projection = globals.camera_projection;
+
+        self.buffer.write(device, view, &globals)?;
+        Ok(FrameViewGlobals {
+            globals,
+            dynamic_offset: self.buffer.view_offset(view),
+        })
     }
 }
 
@@ -139,23 +246,50 @@ impl std::ops::Deref for FrameResourcesGuard<'_> {
     }
 }
 
+/// Globals of an additional camera of the frame, see [`FrameResourcesGuard::flush_view`].
+pub struct FrameViewGlobals {
+    globals: FrameGlobals,
+    dynamic_offset: u32,
+}
+
+impl FrameViewGlobals {
+    pub fn dynamic_offset(&self) -> u32 {
+        self.dynamic_offset
+    }
+}
+
+impl std::ops::Deref for FrameViewGlobals {
+    type Target = FrameGlobals;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.globals
+    }
+}
+
 pub struct FlushFrameResources {
     pub render_resolution: UVec2,
+    pub output_resolution: UVec2,
     pub delta_time: f32,
     pub frame: u32,
+    pub interpolation_factor: f32,
+    /// Bindless index of the shadow map of the directional light.
+    pub shadow_map_index: u32,
+    /// `None` unless mesh buffers are accessed by their device addresses.
+    pub mesh_buffer_addresses: Option<MeshBufferAddresses>,
 }
 
 struct UniformBuffer {
     globals: FrameGlobals,
-    ptr: *mut MaybeUninit<GpuFrameGlobals>,
     slot_len: u32,
     next_frame: usize,
-    inner: gfx::Buffer,
+    inner: gfx::MappedBuffer,
 }
 
-unsafe impl Send for UniformBuffer {}
-
 impl UniformBuffer {
+    /// Globals of the main camera followed by the globals of each render target.
+    const VIEWS_PER_FRAME: usize = 1 + MAX_RENDER_TARGETS_PER_FRAME;
+
     fn new(device: &gfx::Device) -> Result<Self> {
         let limits = &device.properties().v1_0.limits;
         let min_offset_align_mask = limits.min_uniform_buffer_offset_alignment as usize - 1;
@@ -169,46 +303,65 @@ impl UniformBuffer {
         let buffer = device.create_mappable_buffer(
             gfx::BufferInfo {
                 align_mask: offset_align_mask,
-                size: slot_len * 2,
+                size: slot_len * Self::VIEWS_PER_FRAME * 2,
                 usage: gfx::BufferUsage::UNIFORM,
+                label: Some("frame globals"),
             },
             gfx::MemoryUsage::UPLOAD | gfx::MemoryUsage::FAST_DEVICE_ACCESS,
         )?;
 
-        let ptr = device
-            .map_memory(&mut buffer.as_mappable(), 0, slot_len * 2)?
-            .as_mut_ptr()
-            .cast();
-
         Ok(Self {
             globals: FrameGlobals::default(),
-            ptr,
             slot_len: slot_len as u32,
             next_frame: 1,
-            inner: buffer,
+            inner: device.map_persistent(buffer)?,
         })
     }
 
-    fn current_offset(&self) -> u32 {
-        self.slot_len * self.next_frame as u32
+    fn view_offset(&self, view: usize) -> u32 {
+        let slot = self.next_frame * Self::VIEWS_PER_FRAME + view;
+        self.slot_len * slot as u32
     }
 
-    fn flush(&mut self) {
+    fn flush(&mut self, device: &gfx::Device) -> Result<()> {
         self.next_frame = 1 - self.next_frame;
-        let byte_offset = self.current_offset();
+        let globals = self.globals.as_std140();
+        self.write_std140(device, 0, &globals)
+    }
+
+    fn write(&mut self, device: &gfx::Device, view: usize, globals: &FrameGlobals) -> Result<()> {
+        self.write_std140(device, view, &globals.as_std140())
+    }
+
+    fn write_std140(
+        &mut self,
+        device: &gfx::Device,
+        view: usize,
+        globals: &GpuFrameGlobals,
+    ) -> Result<()> {
+        debug_assert!(view < Self::VIEWS_PER_FRAME);
+        let byte_offset = self.view_offset(view) as usize;
 
         // SAFETY:
-        // - `byte_offset` is always less than `self.slot_len * 2`
-        // - `self.ptr` is a valid pointer to mapped memory
+        // - `byte_offset` is always less than `self.slot_len * VIEWS_PER_FRAME * 2`
+        // - `self.inner` is mapped while it is alive
         unsafe {
-            let ptr = self.ptr.byte_add(byte_offset as usize);
+            let ptr = self
+                .inner
+                .as_mut_ptr()
+                .byte_add(byte_offset)
+                .cast::<MaybeUninit<GpuFrameGlobals>>();
             // TODO: write directly to mapped memory without creating a temporary data on the stack
-            *ptr = MaybeUninit::new(self.globals.as_std140());
+            *ptr = MaybeUninit::new(*globals);
         }
+
+        let range = byte_offset..byte_offset + std::mem::size_of::<GpuFrameGlobals>();
+        device.flush_mapped_ranges([(&self.inner, range)])?;
+        Ok(())
     }
 }
 
-#[derive(AsStd140)]
+#[derive(Clone, AsStd140)]
 pub struct FrameGlobals {
     pub frustum: Frustum,
     pub camera_view: Mat4,
@@ -217,10 +370,35 @@ pub struct FrameGlobals {
     pub camera_projection_inverse: Mat4,
     pub camera_previous_view: Mat4,
     pub camera_previous_projection: Mat4,
+    /// Resolution of the main pass.
     pub render_resolution: UVec2,
+    /// Resolution of the output target, e.g. for the overlay.
+    pub output_resolution: UVec2,
     pub time: f32,
     pub delta_time: f32,
     pub frame_index: u32,
+    /// Interpolation factor between the last two fixed updates.
+    pub interpolation_factor: f32,
+    pub light_view_projection: Mat4,
+    pub light_direction: Vec4,
+    /// Zero without the directional light.
+    pub light_color: Vec4,
+    /// `u32::MAX` without the directional light.
+    pub shadow_map_index: u32,
+    /// Offset of shadow receivers along their normals in world units.
+    pub shadow_normal_offset: f32,
+    /// Scale of HDR colors before tonemapping.
+    pub exposure: f32,
+    /// Low and high bits of the mesh vertex buffer address, or zero.
+    pub vertex_buffer_address: UVec2,
+    /// Low and high bits of the mesh index buffer address, or zero.
+    pub index_buffer_address: UVec2,
+}
+
+impl FrameGlobals {
+    pub fn has_directional_light(&self) -> bool {
+        self.shadow_map_index != u32::MAX
+    }
 }
 
 impl Default for FrameGlobals {
@@ -234,15 +412,37 @@ impl Default for FrameGlobals {
             camera_previous_view: Mat4::IDENTITY,
             camera_previous_projection: Mat4::IDENTITY,
             render_resolution: UVec2::ONE,
+            output_resolution: UVec2::ONE,
             time: 0.0,
             delta_time: f32::EPSILON,
             frame_index: 0,
+            interpolation_factor: 1.0,
+            light_view_projection: Mat4::IDENTITY,
+            light_direction: Vec4::ZERO,
+            light_color: Vec4::ZERO,
+            shadow_map_index: u32::MAX,
+            shadow_normal_offset: 0.0,
+            exposure: 1.0,
+            vertex_buffer_address: UVec2::ZERO,
+            index_buffer_address: UVec2::ZERO,
         }
     }
 }
 
 type GpuFrameGlobals = <FrameGlobals as AsStd140>::Output;
 
+/// Splits the address into a `uvec2` for `GL_EXT_buffer_reference_uvec2`.
+fn split_address(address: Option<gfx::DeviceAddress>) -> UVec2 {
+    let address = address.map_or(0, |address| address.0.get());
+    UVec2::new(address as u32, (address >> 32) as u32)
+}
+
+#[derive(Default)]
+struct LightData {
+    light: Option<DirectionalLight>,
+    bias: ShadowBias,
+}
+
 struct CameraData {
     view: Mat4,
     projection: CameraProjection,
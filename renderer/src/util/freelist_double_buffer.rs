@@ -1,40 +1,124 @@
 use anyhow::Result;
 
-use crate::util::{
-    BindlessResources, MultiBufferArena, ScatterCopy, ScatterData, StorageBufferHandle,
-};
-
+use crate::util::{BindlessResources, ScatterCopy, ScatterData, StorageBufferHandle};
+
+/// Two storage buffers of slots which are flushed in turns.
+///
+/// Buffers are reallocated on the next flushes when the capacity changes,
+/// the contents of the previous buffers are copied on the GPU. Previous
+/// buffers are destroyed once the frames which use them are completed,
+/// and their bindless handles are retired until then.
 pub struct FreelistDoubleBuffer {
     targets: [Target; 2],
     handle: StorageBufferHandle,
     odd_target: bool,
     reserved_count: u32,
+    min_capacity: u32,
+    /// Consecutive calls of [`track_occupancy`] with a low occupancy.
+    ///
+    /// [`track_occupancy`]: Self::track_occupancy
+    sparse_frames: u32,
 }
 
 impl FreelistDoubleBuffer {
+    /// Buffers are sparse when the ratio of used slots
+    /// to the capacity is below this value.
+    pub const SHRINK_OCCUPANCY: f32 = 0.25;
+    /// Number of consecutive frames after which sparse buffers are shrunk.
+    pub const SHRINK_DELAY_FRAMES: u32 = 120;
+
+    /// Creates an empty buffer, it is never shrunk below `initial_capacity`.
     pub fn with_capacity(initial_capacity: u32) -> Self {
         FreelistDoubleBuffer {
             targets: Default::default(),
             handle: StorageBufferHandle::INVALID,
             odd_target: false,
             reserved_count: initial_capacity,
+            min_capacity: initial_capacity,
+            sparse_frames: 0,
         }
     }
 
-    #[allow(dead_code)]
     pub fn handle(&self) -> StorageBufferHandle {
         self.handle
     }
 
+    /// Returns the number of slots of the buffers after the next flushes.
+    pub fn capacity(&self) -> u32 {
+        self.reserved_count
+    }
+
     pub fn update_slot(&mut self, slot: u32) {
         let target = &mut self.targets[self.odd_target as usize];
 
-        if slot > self.reserved_count {
-            self.reserved_count = slot.checked_next_power_of_two().expect("too many slots");
+        if slot >= self.reserved_count {
+            self.reserved_count = (slot + 1)
+                .checked_next_power_of_two()
+                .expect("too many slots");
         }
         target.updated_slots.insert(slot);
     }
 
+    /// Returns `true` if the next flush must write any slots or resize the buffer.
+    pub fn is_dirty(&self) -> bool {
+        let current_target = &self.targets[self.odd_target as usize];
+        current_target.buffer.is_none()
+            || self.targets.iter().any(|target| {
+                target.needs_resize(self.reserved_count) || !target.updated_slots.is_empty()
+            })
+    }
+
+    /// Shrinks the buffer to fit `len` slots on the next flushes.
+    ///
+    /// Updates of the slots starting from `len` are discarded.
+    pub fn shrink_to(&mut self, len: u32) {
+        let capacity = len.next_power_of_two().max(self.min_capacity);
+        self.reserved_count = self.reserved_count.min(capacity);
+        self.sparse_frames = 0;
+        for target in &mut self.targets {
+            target.updated_slots.truncate(len);
+        }
+    }
+
+    /// Returns `true` if fewer than [`SHRINK_OCCUPANCY`] of the slots are `used`
+    /// and the buffer could be shrunk.
+    ///
+    /// [`SHRINK_OCCUPANCY`]: Self::SHRINK_OCCUPANCY
+    pub fn is_sparse(&self, used: u32) -> bool {
+        self.reserved_count > self.min_capacity
+            && (used as f32) < self.reserved_count as f32 * Self::SHRINK_OCCUPANCY
+    }
+
+    /// Must be called once per frame with the number of `used` slots.
+    ///
+    /// Returns `true` if fewer than [`SHRINK_OCCUPANCY`] of the slots were used
+    /// for [`SHRINK_DELAY_FRAMES`] consecutive frames, so the buffer should be
+    /// compacted and [shrunk]. The count restarts after that.
+    ///
+    /// [`SHRINK_OCCUPANCY`]: Self::SHRINK_OCCUPANCY
+    /// [`SHRINK_DELAY_FRAMES`]: Self::SHRINK_DELAY_FRAMES
+    /// [shrunk]: Self::shrink_to
+    pub fn track_occupancy(&mut self, used: u32) -> bool {
+        if !self.is_sparse(used) {
+            self.sparse_frames = 0;
+            return false;
+        }
+
+        self.sparse_frames += 1;
+        if self.sparse_frames < Self::SHRINK_DELAY_FRAMES {
+            return false;
+        }
+        self.sparse_frames = 0;
+        true
+    }
+
+    /// Queues updated slots into the `scatter_copy` batch.
+    ///
+    /// If the device supports direct uploads, the slots are written into
+    /// the mapped buffer instead (unless it was reallocated by this flush).
+    ///
+    /// Returns the number of queued bytes.
+    ///
     /// # Safety
     /// - `T` must be the same type on each invocation.
     #[inline]
@@ -44,9 +128,8 @@ impl FreelistDoubleBuffer {
         encoder: &mut gfx::Encoder,
         scatter_copy: &ScatterCopy,
         bindless_resources: &BindlessResources,
-        buffers: &MultiBufferArena,
         mut get_data: F,
-    ) -> Result<()>
+    ) -> Result<usize>
     where
         T: gfx::Std430,
         F: FnMut(u32) -> T,
@@ -74,32 +157,52 @@ impl FreelistDoubleBuffer {
         self.handle = prepared.handle;
 
         if prepared.updated_slots.is_empty() && prev_target.updated_slots.is_empty() {
-            return Ok(());
+            // NOTE: Both targets are up to date here. Mapped targets are written
+            // by the CPU, so the next flush must not write the one used by this frame.
+            // Targets are also switched to resize the previous one, otherwise
+            // it would keep its allocation until the next update.
+            if prepared.mapped.is_some() || prev_target.needs_resize(self.reserved_count) {
+                self.odd_target = !self.odd_target;
+            }
+            return Ok(0);
         }
 
         let data = prepared
             .updated_slots
             .merge_iter(&prev_target.updated_slots)
             .map(|slot| ScatterData::new(item_size as u32 * slot, get_data(slot)));
+        let bytes = data.len() * item_size;
 
-        scatter_copy.execute(device, encoder, prepared.buffer, buffers, data)?;
+        match prepared.mapped {
+            Some(mapped) if !prepared.reallocated => {
+                scatter_copy.write_mapped(device, mapped, data)?
+            }
+            _ => scatter_copy.push(prepared.buffer, data),
+        }
 
         // Clear previous target updated slots as they are no longer needed.
         prev_target.updated_slots.clear();
 
         self.odd_target = !self.odd_target;
-        Ok(())
+        Ok(bytes)
     }
 }
 
 #[derive(Default)]
 struct Target {
     buffer: Option<(gfx::Buffer, StorageBufferHandle)>,
+    /// Mapping of the buffer if direct uploads are supported.
+    mapped: Option<gfx::MappedBuffer>,
     current_count: u32,
     updated_slots: UpdatedSlots,
 }
 
 impl Target {
+    /// Returns `true` if the allocated buffer doesn't match the `reserved_count`.
+    fn needs_resize(&self, reserved_count: u32) -> bool {
+        self.buffer.is_some() && self.current_count != reserved_count
+    }
+
     fn prepare<'a>(
         &'a mut self,
         device: &gfx::Device,
@@ -108,7 +211,7 @@ impl Target {
         reserved_count: u32,
         item_size: usize,
         align_mask: usize,
-    ) -> Result<PreparedTarget<'a>, gfx::OutOfDeviceMemory> {
+    ) -> Result<PreparedTarget<'a>, gfx::MapError> {
         if self.buffer.is_some() && self.current_count == reserved_count {
             // SAFETY: `self.buffer` is `Some`
             // NOTE: borrow checker is mad, I am too!
@@ -116,6 +219,8 @@ impl Target {
             return Ok(PreparedTarget {
                 buffer,
                 handle: *handle,
+                mapped: self.mapped.as_ref(),
+                reallocated: false,
                 updated_slots: &self.updated_slots,
             });
         }
@@ -123,11 +228,18 @@ impl Target {
         let old_buffer = self.buffer.take();
         let (buffer, handle) = {
             let buffer = make_buffer(device, align_mask, item_size * reserved_count as usize)?;
+            self.mapped = if device.supports_direct_upload() {
+                Some(device.map_persistent(buffer.clone())?)
+            } else {
+                None
+            };
             let handle = bindless_resources
                 .alloc_storage_buffer(device, gfx::BufferRange::whole(buffer.clone()));
             self.buffer.get_or_insert((buffer, handle))
         };
 
+        // NOTE: The old buffer is only read by the copy and by the frames in flight,
+        // so it is destroyed after they are completed and nothing waits for them.
         if let Some((old_buffer, old_buffer_handle)) = old_buffer {
             bindless_resources.free_storage_buffer(old_buffer_handle);
             encoder.copy_buffer(
@@ -136,7 +248,7 @@ impl Target {
                 &[gfx::BufferCopy {
                     src_offset: 0,
                     dst_offset: 0,
-                    size: item_size * self.current_count as usize,
+                    size: item_size * self.current_count.min(reserved_count) as usize,
                 }],
             );
         }
@@ -145,6 +257,8 @@ impl Target {
         Ok(PreparedTarget {
             buffer,
             handle: *handle,
+            mapped: self.mapped.as_ref(),
+            reallocated: true,
             updated_slots: &self.updated_slots,
         })
     }
@@ -153,6 +267,9 @@ impl Target {
 struct PreparedTarget<'a> {
     buffer: &'a gfx::Buffer,
     handle: StorageBufferHandle,
+    mapped: Option<&'a gfx::MappedBuffer>,
+    /// Whether the buffer was replaced and its old contents are copied on the GPU.
+    reallocated: bool,
     updated_slots: &'a UpdatedSlots,
 }
 
@@ -161,13 +278,22 @@ fn make_buffer(
     align_mask: usize,
     size: usize,
 ) -> Result<gfx::Buffer, gfx::OutOfDeviceMemory> {
-    device.create_buffer(gfx::BufferInfo {
+    let info = gfx::BufferInfo {
         align_mask: align_mask | MIN_ALIGN_MASK,
         size,
         usage: gfx::BufferUsage::STORAGE
             | gfx::BufferUsage::TRANSFER_DST
             | gfx::BufferUsage::TRANSFER_SRC,
-    })
+        label: Some("freelist double buffer"),
+    };
+    if device.supports_direct_upload() {
+        device.create_mappable_buffer(
+            info,
+            gfx::MemoryUsage::UPLOAD | gfx::MemoryUsage::FAST_DEVICE_ACCESS,
+        )
+    } else {
+        device.create_buffer(info)
+    }
 }
 
 const MIN_ALIGN_MASK: usize = 0b1111;
@@ -203,6 +329,20 @@ impl UpdatedSlots {
         self.is_empty = true;
     }
 
+    /// Removes all slots starting from `len`.
+    fn truncate(&mut self, len: u32) {
+        let chunk = (len as usize) / BITS_PER_CHUNK;
+        let bit = (len as usize) % BITS_PER_CHUNK;
+
+        if bit == 0 {
+            self.chunks.truncate(chunk);
+        } else if chunk < self.chunks.len() {
+            self.chunks.truncate(chunk + 1);
+            self.chunks[chunk] &= (1 << bit) - 1;
+        }
+        self.is_empty = self.chunks.iter().all(|chunk| *chunk == 0);
+    }
+
     fn is_empty(&self) -> bool {
         self.is_empty
     }
@@ -299,3 +439,87 @@ impl ExactSizeIterator for ChunkIter {}
 type SlotChunk = u64;
 
 const BITS_PER_CHUNK: usize = std::mem::size_of::<SlotChunk>() * 8;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(slots: &UpdatedSlots) -> Vec<u32> {
+        slots.merge_iter(&UpdatedSlots::default()).collect()
+    }
+
+    #[test]
+    fn updated_slots_are_merged_and_truncated() {
+        let mut cur = UpdatedSlots::default();
+        let mut prev = UpdatedSlots::default();
+        for slot in [1, 64, 130] {
+            cur.insert(slot);
+        }
+        for slot in [1, 2, 200] {
+            prev.insert(slot);
+        }
+
+        let merged = cur.merge_iter(&prev);
+        assert_eq!(merged.len(), 5);
+        assert_eq!(merged.collect::<Vec<_>>(), [1, 2, 64, 130, 200]);
+
+        // Boundaries of the chunks
+        cur.truncate(130);
+        assert_eq!(collect(&cur), [1, 64]);
+        cur.truncate(64);
+        assert_eq!(collect(&cur), [1]);
+        cur.truncate(1);
+        assert!(cur.is_empty());
+    }
+
+    #[test]
+    fn capacity_grows_and_shrinks_in_powers_of_two() {
+        let mut buffer = FreelistDoubleBuffer::with_capacity(16);
+        buffer.update_slot(15);
+        assert_eq!(buffer.capacity(), 16);
+        buffer.update_slot(16);
+        assert_eq!(buffer.capacity(), 32);
+        buffer.update_slot(1000);
+        assert_eq!(buffer.capacity(), 1024);
+
+        buffer.shrink_to(100);
+        assert_eq!(buffer.capacity(), 128);
+        assert_eq!(collect(&buffer.targets[0].updated_slots), [15, 16]);
+
+        // Never below the initial capacity, and never grows
+        buffer.shrink_to(0);
+        assert_eq!(buffer.capacity(), 16);
+        assert!(buffer.targets[0].updated_slots.is_empty());
+        buffer.shrink_to(1000);
+        assert_eq!(buffer.capacity(), 16);
+    }
+
+    #[test]
+    fn sparse_buffers_are_shrunk_after_delay() {
+        let mut buffer = FreelistDoubleBuffer::with_capacity(16);
+
+        // Buffers with the initial capacity are never sparse
+        assert!(!buffer.is_sparse(0));
+        for _ in 0..FreelistDoubleBuffer::SHRINK_DELAY_FRAMES {
+            assert!(!buffer.track_occupancy(0));
+        }
+
+        buffer.update_slot(1023);
+        assert!(buffer.is_sparse(255));
+        assert!(!buffer.is_sparse(256));
+        for _ in 1..FreelistDoubleBuffer::SHRINK_DELAY_FRAMES {
+            assert!(!buffer.track_occupancy(255));
+        }
+        // Dense frames restart the count
+        assert!(!buffer.track_occupancy(256));
+        for _ in 1..FreelistDoubleBuffer::SHRINK_DELAY_FRAMES {
+            assert!(!buffer.track_occupancy(10));
+        }
+        assert!(buffer.track_occupancy(10));
+        assert!(!buffer.track_occupancy(10));
+
+        buffer.shrink_to(10);
+        assert_eq!(buffer.capacity(), 16);
+        assert!(!buffer.track_occupancy(10));
+    }
+}
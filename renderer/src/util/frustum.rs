@@ -1,6 +1,6 @@
 use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
 
-use crate::types::Position;
+use crate::types::{DepthMode, Position};
 
 /// Frustum of a camera with infinite far plane.
 #[derive(Debug, Clone, gfx::AsStd140, gfx::AsStd430)]
@@ -37,8 +37,10 @@ impl Frustum {
     };
 
     /// Computes the frustum of the given view-projection matrix.
+    ///
+    /// The depth mode determines which side of the clip volume is the near plane.
     #[allow(dead_code)]
-    pub fn new(view_proj: Mat4) -> Self {
+    pub fn new(view_proj: Mat4, depth_mode: DepthMode) -> Self {
         // x, y, z, w
         let mat = view_proj.to_cols_array_2d();
 
@@ -79,14 +81,21 @@ impl Frustum {
         );
 
         // z
-        let near = Plane::new(
-            Vec3::new(
-                mat[0][3] + mat[0][2],
-                mat[1][3] + mat[1][2],
-                mat[2][3] + mat[2][2],
+        let near = match depth_mode {
+            // NOTE: Near plane is at `z = 0` with Vulkan clip space
+            DepthMode::Standard => {
+                Plane::new(Vec3::new(mat[0][2], mat[1][2], mat[2][2]), mat[3][2])
+            }
+            // NOTE: Near plane is at `z = w` with reversed depth
+            DepthMode::Reversed => Plane::new(
+                Vec3::new(
+                    mat[0][3] - mat[0][2],
+                    mat[1][3] - mat[1][2],
+                    mat[2][3] - mat[2][2],
+                ),
+                mat[3][3] - mat[3][2],
             ),
-            mat[3][3] + mat[3][2],
-        );
+        };
 
         // Normalize plane normals.
         Self {
@@ -277,3 +286,58 @@ impl From<&BoundingSphere> for Vec4 {
         value.center.extend(value.radius)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3A;
+
+    use super::*;
+    use crate::types::CameraProjection;
+
+    #[test]
+    fn culling_matches_between_depth_modes() {
+        let eye = Vec3::new(0.0, 2.0, 5.0);
+        let view = Mat4::look_at_rh(eye, Vec3::ZERO, Vec3::Y);
+        let sphere = |center: Vec3, radius: f32| BoundingSphere { center, radius };
+
+        let spheres = [
+            sphere(Vec3::ZERO, 1.0),
+            // Behind the camera
+            sphere(eye * 2.0, 1.0),
+            // Around the camera, closer than the perspective near plane
+            sphere(eye, 0.05),
+            sphere(Vec3::new(20.0, 0.0, 0.0), 1.0),
+            // Far away in front of the camera
+            sphere(Vec3::new(0.0, 0.0, -1000.0), 1.0),
+            sphere(eye + eye.normalize() * 100.0, 1.0),
+        ];
+
+        let cases = [
+            (
+                CameraProjection::default(),
+                [true, false, false, false, true, false],
+            ),
+            (
+                CameraProjection::Orhographic {
+                    extent: Vec3A::new(8.0, 6.0, 100.0),
+                },
+                [true, true, true, false, false, false],
+            ),
+        ];
+
+        for (projection, expected) in cases {
+            for depth_mode in [DepthMode::Standard, DepthMode::Reversed] {
+                let proj = projection.compute_projection_matrix(16.0 / 9.0, depth_mode);
+                let frustum = Frustum::new(proj * view, depth_mode);
+
+                for (sphere, expected) in std::iter::zip(&spheres, expected) {
+                    assert_eq!(
+                        frustum.contains_sphere(sphere),
+                        expected,
+                        "{projection:?} {depth_mode:?} {sphere:?}"
+                    );
+                }
+            }
+        }
+    }
+}
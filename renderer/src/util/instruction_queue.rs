@@ -0,0 +1,427 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use shared::util::lock_ignore_poison;
+
+/// Multi-producer queue of instructions drained by the render worker.
+///
+/// Producers push into one of several shards selected per thread, so
+/// threads rarely contend for the same lock. Each item is stamped with a
+/// global sequence number and the shards are merged in this order on swap,
+/// which preserves the order of items sent from the same thread (as well
+/// as of causally related items sent from different threads). All shards
+/// are locked at once while draining, so a swap never takes an item
+/// without taking all items sent before it.
+///
+/// Locks ignore poisoning, so the queue can still be closed and handles
+/// can still be dropped after the render worker panicked.
+pub struct InstructionQueue<T> {
+    consumer: Mutex<Vec<T>>,
+    shards: Box<[Shard<T>]>,
+    /// Items of all shards collected for sorting, reused between swaps.
+    pending: Mutex<Vec<(u64, T)>>,
+    next_seq: AtomicU64,
+    closed: AtomicBool,
+}
+
+impl<T> Default for InstructionQueue<T> {
+    fn default() -> Self {
+        Self {
+            consumer: Mutex::default(),
+            shards: (0..SHARD_COUNT).map(|_| Shard::default()).collect(),
+            pending: Mutex::default(),
+            next_seq: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<T> InstructionQueue<T> {
+    /// Moves all sent items into the consumer queue.
+    pub fn swap(&self) {
+        let mut consumer = lock_ignore_poison(&self.consumer);
+        let mut pending = lock_ignore_poison(&self.pending);
+        self.drain_shards(&mut pending);
+        consumer.extend(pending.drain(..).map(|(_, item)| item));
+    }
+
+    /// Returns the number of items which were sent but not yet consumed.
+    pub fn pending_len(&self) -> usize {
+        let sent = self
+            .shards
+            .iter()
+            .map(|shard| lock_ignore_poison(&shard.items).len())
+            .sum::<usize>();
+        sent + lock_ignore_poison(&self.consumer).len()
+    }
+
+    /// Returns items moved by the last swap.
+    pub fn consumer(&self) -> MutexGuard<'_, Vec<T>> {
+        lock_ignore_poison(&self.consumer)
+    }
+
+    /// Returns `false` if the queue is closed and the item was dropped.
+    pub fn send(&self, item: T) -> bool {
+        self.try_send(item).is_ok()
+    }
+
+    /// Returns the item back if the queue is closed.
+    pub fn try_send(&self, item: T) -> Result<(), T> {
+        let mut items = lock_ignore_poison(&self.shards[current_shard()].items);
+        if self.closed.load(Ordering::Relaxed) {
+            // NOTE: The item is returned after the lock is released
+            // since dropping it could send a new one.
+            return Err(item);
+        }
+
+        // NOTE: The sequence number is assigned under the shard lock
+        // to keep each shard sorted.
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        items.push((seq, item));
+        Ok(())
+    }
+
+    /// Ignores new items and returns all pending ones in the order they were sent.
+    ///
+    /// NOTE: Items must be dropped outside of the queue locks, since
+    /// handle deleters send new instructions.
+    pub fn close(&self) -> Vec<T> {
+        // NOTE: Senders check the flag under the shard lock, so nothing
+        // can be pushed into the shard after it is drained below.
+        self.closed.store(true, Ordering::Relaxed);
+
+        let mut consumer = lock_ignore_poison(&self.consumer);
+        let mut pending = lock_ignore_poison(&self.pending);
+        self.drain_shards(&mut pending);
+
+        let mut items = std::mem::take(&mut *consumer);
+        items.extend(pending.drain(..).map(|(_, item)| item));
+        items
+    }
+
+    /// Moves items of all shards into `pending`, sorted by sequence numbers.
+    fn drain_shards(&self, pending: &mut Vec<(u64, T)>) {
+        // NOTE: Sequence numbers are assigned under the shard lock, so while
+        // all shards are locked every sent item has a smaller number than
+        // any item sent later. Draining shards one by one could take a later
+        // item from one shard while an earlier one is yet to be pushed into
+        // an already drained shard. Senders lock only one shard, and shards
+        // are always locked in the same order here, so this can't deadlock.
+        let mut shards: [_; SHARD_COUNT] =
+            std::array::from_fn(|i| lock_ignore_poison(&self.shards[i].items));
+
+        for items in &mut shards {
+            // NOTE: Only move items under the lock, the shard keeps its capacity
+            pending.append(items);
+        }
+        drop(shards);
+
+        // NOTE: Shards are already sorted, so the stable sort merges them
+        // as runs instead of sorting the whole array.
+        pending.sort_by_key(|(seq, _)| *seq);
+    }
+}
+
+/// NOTE: Aligned to avoid false sharing between producers of adjacent shards.
+#[repr(align(128))]
+struct Shard<T> {
+    items: Mutex<Vec<(u64, T)>>,
+}
+
+impl<T> Default for Shard<T> {
+    fn default() -> Self {
+        Self {
+            items: Mutex::default(),
+        }
+    }
+}
+
+/// Returns the shard index of the current thread.
+///
+/// Threads are assigned to shards in a round-robin order on the first send.
+fn current_shard() -> usize {
+    static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+    thread_local! {
+        static SHARD: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+
+    SHARD.with(|shard| match shard.get() {
+        Some(index) => index,
+        None => {
+            let index = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % SHARD_COUNT;
+            shard.set(Some(index));
+            index
+        }
+    })
+}
+
+const SHARD_COUNT: usize = 16;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::render_graph::materials::DebugMaterialInstance;
+    use crate::{RendererBuilder, RendererError};
+
+    const THREADS: usize = 4;
+    const ITEMS_PER_THREAD: usize = 100_000 / THREADS;
+
+    /// The previous design with a single producer vector.
+    #[derive(Default)]
+    struct DoubleMutexQueue<T> {
+        consumer: Mutex<Vec<T>>,
+        producer: Mutex<Vec<T>>,
+    }
+
+    impl<T> DoubleMutexQueue<T> {
+        fn swap(&self) {
+            let mut consumer = self.consumer.lock().unwrap();
+            let mut producer = self.producer.lock().unwrap();
+            std::mem::swap(&mut *consumer, &mut *producer);
+        }
+
+        fn send(&self, item: T) {
+            self.producer.lock().unwrap().push(item);
+        }
+    }
+
+    /// Sends items from several threads while the consumer drains them.
+    ///
+    /// Returns the elapsed time and all drained items.
+    fn run_producers<Q, S, D>(queue: Arc<Q>, send: S, drain: D) -> (Duration, Vec<(usize, usize)>)
+    where
+        Q: Send + Sync + 'static,
+        S: Fn(&Q, (usize, usize)) + Copy + Send + 'static,
+        D: Fn(&Q, &mut Vec<(usize, usize)>),
+    {
+        let started_at = Instant::now();
+        let producers = (0..THREADS)
+            .map(|thread| {
+                let queue = queue.clone();
+                std::thread::spawn(move || {
+                    for i in 0..ITEMS_PER_THREAD {
+                        send(&queue, (thread, i));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut drained = Vec::with_capacity(THREADS * ITEMS_PER_THREAD);
+        while !producers.iter().all(|producer| producer.is_finished()) {
+            drain(&queue, &mut drained);
+            std::thread::yield_now();
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        drain(&queue, &mut drained);
+
+        (started_at.elapsed(), drained)
+    }
+
+    #[test]
+    fn order_is_preserved_per_thread() {
+        let queue = Arc::new(InstructionQueue::default());
+        let (_, drained) = run_producers(
+            queue,
+            |queue, item| {
+                queue.send(item);
+            },
+            |queue, drained| {
+                queue.swap();
+                drained.append(&mut queue.consumer());
+            },
+        );
+
+        assert_eq!(drained.len(), THREADS * ITEMS_PER_THREAD);
+        let mut next = [0; THREADS];
+        for (thread, i) in drained {
+            assert_eq!(i, next[thread], "items of thread {thread} are reordered");
+            next[thread] += 1;
+        }
+    }
+
+    #[test]
+    fn causally_related_items_are_not_reordered() {
+        const CHAIN: usize = 8;
+        const ITEMS: usize = 100_000;
+
+        // Each thread sends its item only after the previous thread of
+        // the chain sent the item with the same index.
+        let queue = Arc::new(InstructionQueue::default());
+        let sent = Arc::new((0..CHAIN).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>());
+        let producers = (0..CHAIN)
+            .map(|thread| {
+                let queue = queue.clone();
+                let sent = sent.clone();
+                std::thread::spawn(move || {
+                    for i in 0..ITEMS {
+                        if thread > 0 {
+                            while sent[thread - 1].load(Ordering::Acquire) <= i {
+                                std::hint::spin_loop();
+                            }
+                        }
+                        queue.send((thread, i));
+                        sent[thread].store(i + 1, Ordering::Release);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut drained = Vec::with_capacity(CHAIN * ITEMS);
+        while !producers.iter().all(|producer| producer.is_finished()) {
+            queue.swap();
+            drained.append(&mut queue.consumer());
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        queue.swap();
+        drained.append(&mut queue.consumer());
+
+        let mut consumed = [0; CHAIN];
+        for (thread, i) in drained {
+            if thread > 0 {
+                assert!(
+                    consumed[thread - 1] > i,
+                    "item {i} is consumed before its cause"
+                );
+            }
+            consumed[thread] = i + 1;
+        }
+    }
+
+    #[test]
+    fn closed_queue_ignores_items() {
+        let queue = InstructionQueue::default();
+        assert!(queue.send(1));
+        queue.swap();
+        queue.send(2);
+
+        assert_eq!(queue.close(), [1, 2]);
+        assert_eq!(queue.try_send(3), Err(3));
+        queue.swap();
+        assert!(queue.consumer().is_empty());
+        assert_eq!(queue.pending_len(), 0);
+    }
+
+    #[test]
+    fn pending_items_are_counted() {
+        let queue = InstructionQueue::default();
+        queue.send(1);
+        queue.send(2);
+        assert_eq!(queue.pending_len(), 2);
+
+        queue.swap();
+        queue.send(3);
+        assert_eq!(queue.pending_len(), 3);
+
+        queue.consumer().clear();
+        assert_eq!(queue.pending_len(), 1);
+    }
+
+    /// Run with `cargo test --release -p renderer -- --ignored --nocapture bench_`.
+    #[test]
+    #[ignore]
+    fn bench_contended_send() {
+        const RUNS: u32 = 10;
+
+        let mut sharded = Duration::ZERO;
+        let mut double_mutex = Duration::ZERO;
+        for _ in 0..RUNS {
+            sharded += run_producers(
+                Arc::new(InstructionQueue::default()),
+                |queue, item| {
+                    queue.send(item);
+                },
+                |queue, drained| {
+                    queue.swap();
+                    drained.append(&mut queue.consumer());
+                },
+            )
+            .0;
+
+            double_mutex += run_producers(
+                Arc::new(DoubleMutexQueue::default()),
+                |queue, item| queue.send(item),
+                |queue, drained| {
+                    queue.swap();
+                    drained.append(&mut queue.consumer.lock().unwrap());
+                },
+            )
+            .0;
+        }
+
+        println!(
+            "{} items from {THREADS} threads: sharded {:?}, double mutex {:?}",
+            THREADS * ITEMS_PER_THREAD,
+            sharded / RUNS,
+            double_mutex / RUNS,
+        );
+    }
+
+    #[test]
+    fn closes_after_consumer_panicked() {
+        let queue = Arc::new(InstructionQueue::default());
+        queue.send(1);
+        queue.swap();
+
+        let consumer = std::thread::spawn({
+            let queue = queue.clone();
+            move || {
+                let _items = queue.consumer();
+                panic!("consumer failed");
+            }
+        });
+        assert!(consumer.join().is_err());
+
+        assert_eq!(queue.pending_len(), 1);
+        queue.close();
+        assert!(!queue.send(2));
+        assert_eq!(queue.pending_len(), 0);
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn instructions_are_released_after_shutdown() {
+        let material = DebugMaterialInstance {
+            color: glam::Vec3::ONE,
+            double_sided: false,
+        };
+
+        let renderer = RendererBuilder::headless(16, 16).build().unwrap();
+        let state = renderer.state();
+        let handle = state.add_material_instance(material).unwrap();
+
+        // Instructions are accepted until the worker observes the flag
+        state.set_running(false);
+        let started_at = Instant::now();
+        while state.add_material_instance(material).is_ok() {
+            assert!(started_at.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        for _ in 0..10_000 {
+            assert!(matches!(
+                state.add_material_instance(material),
+                Err(RendererError::WorkerStopped)
+            ));
+            assert!(state.update_material(&handle, material).is_err());
+        }
+        assert_eq!(state.pending_instruction_count(), 0);
+        assert_eq!(state.material_required_attributes.lock().unwrap().len(), 1);
+
+        drop(handle);
+        assert!(state
+            .material_required_attributes
+            .lock()
+            .unwrap()
+            .is_empty());
+        assert_eq!(state.handles.live_counts().materials, 0);
+    }
+}
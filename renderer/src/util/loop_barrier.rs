@@ -0,0 +1,404 @@
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+use shared::util::lock_ignore_poison;
+
+/// Result of [`RendererState::request_draw`].
+///
+/// [`RendererState::request_draw`]: crate::RendererState::request_draw
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use]
+pub enum DrawRequestResult {
+    /// A new frame will be drawn after the request.
+    Accepted,
+    /// The request is merged with an earlier one whose frame was not started yet.
+    Coalesced,
+    /// The rendering thread is stopped or stopping, no more frames will be drawn.
+    Stopped,
+}
+
+impl DrawRequestResult {
+    /// Returns `true` if a frame will be drawn after the request.
+    pub fn will_draw(&self) -> bool {
+        !matches!(self, Self::Stopped)
+    }
+}
+
+/// Wakes up the rendering loop for each requested frame.
+///
+/// Requests sent while a frame is drawn result in exactly one more frame.
+/// Frames which were requested before the loop was stopped are still drawn.
+#[derive(Default)]
+pub struct LoopBarrier {
+    inner: Mutex<LoopBarrierInner>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct LoopBarrierInner {
+    state: LoopState,
+    /// Set by [`LoopBarrier::stop`], the loop is stopped after
+    /// the requested frames are drawn.
+    stopping: bool,
+    /// Set by [`LoopBarrier::set_paused`].
+    paused: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum LoopState {
+    #[default]
+    Idle,
+    DrawRequested,
+    Drawing {
+        draw_requested: bool,
+    },
+    Stopped,
+}
+
+impl LoopBarrier {
+    /// Requests a frame, see [`DrawRequestResult`].
+    pub fn request_draw(&self) -> DrawRequestResult {
+        let mut inner = self.lock();
+        if inner.stopping {
+            return DrawRequestResult::Stopped;
+        }
+
+        match inner.state {
+            LoopState::Idle => {
+                inner.state = LoopState::DrawRequested;
+                self.condvar.notify_all();
+                DrawRequestResult::Accepted
+            }
+            LoopState::Drawing {
+                draw_requested: false,
+            } => {
+                inner.state = LoopState::Drawing {
+                    draw_requested: true,
+                };
+                DrawRequestResult::Accepted
+            }
+            LoopState::DrawRequested
+            | LoopState::Drawing {
+                draw_requested: true,
+            } => DrawRequestResult::Coalesced,
+            LoopState::Stopped => DrawRequestResult::Stopped,
+        }
+    }
+
+    /// Stops the loop once the frame which is drawn or requested is completed.
+    ///
+    /// NOTE: Doesn't wait for the frame, so it can be called from the loop itself.
+    pub fn stop(&self) {
+        let mut inner = self.lock();
+        inner.stopping = true;
+        if inner.state == LoopState::Idle {
+            inner.state = LoopState::Stopped;
+        }
+        self.condvar.notify_all();
+    }
+
+    /// Cancels [`stop`] unless the loop has already stopped.
+    ///
+    /// [`stop`]: Self::stop
+    pub fn resume(&self) {
+        let mut inner = self.lock();
+        if inner.state != LoopState::Stopped {
+            inner.stopping = false;
+        }
+    }
+
+    /// Returns `false` once [`stop`] was called.
+    ///
+    /// [`stop`]: Self::stop
+    pub fn is_running(&self) -> bool {
+        !self.lock().stopping
+    }
+
+    /// Returns `true` once no more frames will be drawn.
+    pub fn is_stopped(&self) -> bool {
+        self.lock().state == LoopState::Stopped
+    }
+
+    /// Pauses or resumes the loop, returns the previous state.
+    ///
+    /// NOTE: Only wakes up [`sleep_while_paused`], requested frames
+    /// are still started while the loop is paused.
+    ///
+    /// [`sleep_while_paused`]: Self::sleep_while_paused
+    pub fn set_paused(&self, paused: bool) -> bool {
+        let mut inner = self.lock();
+        let prev = std::mem::replace(&mut inner.paused, paused);
+        self.condvar.notify_all();
+        prev
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    /// Waits for the next requested frame.
+    ///
+    /// Returns `false` if the loop was stopped.
+    pub fn begin_frame(&self) -> bool {
+        let mut inner = self.lock();
+        loop {
+            match inner.state {
+                LoopState::DrawRequested => {
+                    inner.state = LoopState::Drawing {
+                        draw_requested: false,
+                    };
+                    return true;
+                }
+                LoopState::Stopped => return false,
+                LoopState::Idle | LoopState::Drawing { .. } => {
+                    inner = self.wait(inner);
+                }
+            }
+        }
+    }
+
+    /// Completes the frame started by [`begin_frame`].
+    ///
+    /// [`begin_frame`]: Self::begin_frame
+    pub fn end_frame(&self) {
+        let mut inner = self.lock();
+        inner.state = match inner.state {
+            LoopState::Drawing {
+                draw_requested: true,
+            } => LoopState::DrawRequested,
+            LoopState::Drawing { .. } if inner.stopping => LoopState::Stopped,
+            LoopState::Drawing { .. } => LoopState::Idle,
+            state => state,
+        };
+        self.condvar.notify_all();
+    }
+
+    /// Stops the loop immediately, e.g. when it exits after an error.
+    ///
+    /// Frames which were requested but not drawn are discarded.
+    pub fn finish(&self) {
+        let mut inner = self.lock();
+        inner.stopping = true;
+        inner.state = LoopState::Stopped;
+        self.condvar.notify_all();
+    }
+
+    /// Sleeps until the `deadline`, or until the loop is [finished].
+    ///
+    /// Returns `false` if the loop was finished, so the requested
+    /// frame must not be drawn.
+    ///
+    /// NOTE: [`stop`] doesn't interrupt the sleep, since the requested
+    /// frame is still drawn after it.
+    ///
+    /// [finished]: Self::finish
+    /// [`stop`]: Self::stop
+    pub fn sleep_until(&self, deadline: Instant) -> bool {
+        self.sleep_until_or(deadline, |_| false)
+    }
+
+    /// Sleeps until the `deadline`, or until the loop is resumed, stopped
+    /// or finished.
+    ///
+    /// Returns `false` if the loop was finished.
+    pub fn sleep_while_paused(&self, deadline: Instant) -> bool {
+        self.sleep_until_or(deadline, |inner| !inner.paused || inner.stopping)
+    }
+
+    fn sleep_until_or(
+        &self,
+        deadline: Instant,
+        wake_up: impl Fn(&LoopBarrierInner) -> bool,
+    ) -> bool {
+        // NOTE: Flags are checked under the lock, so `finish`
+        // can't notify between the check and the wait.
+        let mut inner = self.lock();
+        loop {
+            if inner.state == LoopState::Stopped {
+                return false;
+            }
+            if wake_up(&inner) {
+                return true;
+            }
+            let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                return true;
+            };
+            inner = self
+                .condvar
+                .wait_timeout(inner, timeout)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LoopBarrierInner> {
+        lock_ignore_poison(&self.inner)
+    }
+
+    fn wait<'a>(
+        &self,
+        inner: MutexGuard<'a, LoopBarrierInner>,
+    ) -> MutexGuard<'a, LoopBarrierInner> {
+        self.condvar
+            .wait(inner)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Draws frames on a separate thread until the barrier is stopped,
+    /// returns the number of drawn frames.
+    fn spawn_loop(barrier: &Arc<LoopBarrier>) -> std::thread::JoinHandle<usize> {
+        let barrier = barrier.clone();
+        std::thread::spawn(move || {
+            let mut frames = 0;
+            while barrier.begin_frame() {
+                frames += 1;
+                std::thread::yield_now();
+                barrier.end_frame();
+            }
+            frames
+        })
+    }
+
+    #[test]
+    fn requests_are_coalesced_while_drawing() {
+        let barrier = LoopBarrier::default();
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Accepted);
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Coalesced);
+
+        assert!(barrier.begin_frame());
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Accepted);
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Coalesced);
+        barrier.end_frame();
+
+        // The frame requested while drawing is not lost
+        assert!(barrier.begin_frame());
+        barrier.end_frame();
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Accepted);
+    }
+
+    #[test]
+    fn stop_completes_the_requested_frames() {
+        // Stopping from the drawing thread itself doesn't deadlock
+        let barrier = LoopBarrier::default();
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Accepted);
+        assert!(barrier.begin_frame());
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Accepted);
+        barrier.stop();
+        assert!(!barrier.is_running());
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Stopped);
+        barrier.end_frame();
+
+        assert!(barrier.begin_frame());
+        barrier.end_frame();
+        assert!(!barrier.begin_frame());
+
+        // Stopped loops can't be resumed
+        barrier.resume();
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Stopped);
+    }
+
+    #[test]
+    fn each_accepted_request_is_drawn() {
+        for _ in 0..20 {
+            let barrier = Arc::new(LoopBarrier::default());
+            let worker = spawn_loop(&barrier);
+
+            let accepted = Arc::new(AtomicUsize::new(0));
+            let requesters = (0..4)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    let accepted = accepted.clone();
+                    std::thread::spawn(move || loop {
+                        match barrier.request_draw() {
+                            DrawRequestResult::Accepted => {
+                                accepted.fetch_add(1, Ordering::Relaxed);
+                            }
+                            DrawRequestResult::Coalesced => {}
+                            DrawRequestResult::Stopped => break,
+                        }
+                        std::thread::yield_now();
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            std::thread::sleep(Duration::from_millis(5));
+            barrier.stop();
+
+            for requester in requesters {
+                requester.join().unwrap();
+            }
+            let frames = worker.join().unwrap();
+            assert_eq!(frames, accepted.load(Ordering::Relaxed));
+        }
+    }
+
+    #[test]
+    fn stop_wakes_up_waiting_threads() {
+        let barrier = Arc::new(LoopBarrier::default());
+        let worker = spawn_loop(&barrier);
+        let sleeper = std::thread::spawn({
+            let barrier = barrier.clone();
+            move || barrier.sleep_until(Instant::now() + Duration::from_secs(60))
+        });
+
+        std::thread::sleep(Duration::from_millis(5));
+        barrier.stop();
+        assert_eq!(worker.join().unwrap(), 0);
+        assert!(!sleeper.join().unwrap());
+    }
+
+    #[test]
+    fn stop_does_not_interrupt_the_requested_frame() {
+        let barrier = Arc::new(LoopBarrier::default());
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Accepted);
+        assert!(barrier.begin_frame());
+
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let sleeper = std::thread::spawn({
+            let barrier = barrier.clone();
+            move || barrier.sleep_until(deadline)
+        });
+        std::thread::sleep(Duration::from_millis(5));
+        barrier.stop();
+        assert!(sleeper.join().unwrap());
+        assert!(Instant::now() >= deadline);
+
+        // Paused sleeps are woken up to draw the frame right away
+        barrier.set_paused(true);
+        assert!(barrier.sleep_while_paused(Instant::now() + Duration::from_secs(60)));
+
+        barrier.end_frame();
+        assert!(barrier.is_stopped());
+        assert!(!barrier.sleep_until(Instant::now() + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn resume_wakes_up_paused_sleep() {
+        let barrier = Arc::new(LoopBarrier::default());
+        assert!(!barrier.set_paused(true));
+        let sleeper = std::thread::spawn({
+            let barrier = barrier.clone();
+            move || barrier.sleep_while_paused(Instant::now() + Duration::from_secs(60))
+        });
+
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(barrier.set_paused(false));
+        assert!(sleeper.join().unwrap());
+
+        // Requests are still accepted while paused
+        barrier.set_paused(true);
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Accepted);
+        assert!(barrier.begin_frame());
+        barrier.end_frame();
+        assert!(barrier.sleep_while_paused(Instant::now()));
+    }
+}
@@ -1,27 +1,47 @@
+pub use self::atomic_slot::AtomicSlot;
 pub use self::bindless_resources::{
-    AtomicStorageBufferHandle, BindlessResources, StorageBufferHandle,
+    AtomicStorageBufferHandle, BindlessResources, BindlessResourcesStats, BindlessSlotStats,
+    SampledImageHandle, StorageBufferHandle,
+};
+pub use self::encoder::{
+    CachedGraphicsPipeline, EncoderExt, RenderPass, RenderPassContext, RenderPassEncoderExt,
+};
+pub use self::font_atlas::FontAtlas;
+pub use self::frame_resources::{
+    FlushFrameResources, FrameGlobals, FrameResources, FrameResourcesGuard, FrameViewGlobals,
 };
-pub use self::encoder::{CachedGraphicsPipeline, EncoderExt, RenderPass, RenderPassEncoderExt};
-pub use self::frame_resources::{FlushFrameResources, FrameGlobals, FrameResources};
 pub use self::freelist_double_buffer::FreelistDoubleBuffer;
 pub use self::frustum::{BoundingSphere, Frustum};
-pub use self::multi_buffer_arena::MultiBufferArena;
+pub use self::instruction_queue::InstructionQueue;
+pub use self::loop_barrier::{DrawRequestResult, LoopBarrier};
+pub use self::multi_buffer_arena::{BufferArena, MultiBufferArena};
+pub use self::render_pass_cache::{FramebufferCache, RenderPassCache, RenderTargetCacheStats};
 pub use self::resource_handle::{
     FreelistHandleAllocator, HandleAllocator, HandleData, HandleDeleter, RawResourceHandle,
-    ResourceHandle, SimpleHandleAllocator,
+    ResourceHandle, ResourceRegistry, SimpleHandleAllocator,
 };
 pub use self::scatter_copy::{ScatterCopy, ScatterData};
+pub use self::shader_compile_error::{
+    ShaderCompileError, ShaderDiagnostic, ShaderDiagnosticSeverity, ShaderSnippet,
+};
 pub use self::shader_preprocessor::ShaderPreprocessor;
 pub use self::virtual_fs::{VirtualFs, VirtualPath};
 
+mod atomic_slot;
 mod bindless_resources;
+mod bitmap_font;
 mod device_seletor;
 mod encoder;
+mod font_atlas;
 mod frame_resources;
 mod freelist_double_buffer;
 mod frustum;
+mod instruction_queue;
+mod loop_barrier;
 mod multi_buffer_arena;
+mod render_pass_cache;
 mod resource_handle;
 mod scatter_copy;
+mod shader_compile_error;
 mod shader_preprocessor;
 mod virtual_fs;
@@ -1,5 +1,6 @@
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
 
 use anyhow::Result;
@@ -10,6 +11,7 @@ use crate::util::{BindlessResources, StorageBufferHandle};
 pub struct MultiBufferArena {
     buffer_align_mask: usize,
     buffers: Mutex<FastHashMap<gfx::BufferUsage, Buffers>>,
+    allocated_bytes: AtomicU64,
 }
 
 impl MultiBufferArena {
@@ -18,9 +20,17 @@ impl MultiBufferArena {
         Self {
             buffer_align_mask,
             buffers: Mutex::new(FastHashMap::default()),
+            allocated_bytes: AtomicU64::new(0),
         }
     }
 
+    /// Returns the total size of all buffers created by the arena.
+    ///
+    /// NOTE: Buffers are never freed, so the size never decreases.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes.load(Ordering::Relaxed)
+    }
+
     pub fn begin<T: gfx::Std430>(
         &self,
         device: &gfx::Device,
@@ -33,7 +43,7 @@ impl MultiBufferArena {
             align_mask: usize,
             size: usize,
             usage: gfx::BufferUsage,
-        ) -> Result<MappedBuffer> {
+        ) -> Result<ArenaBuffer> {
             // Find an existing buffer
             if let Some(buffers) = this.buffers.lock().unwrap().get_mut(&usage) {
                 for (i, buffer) in buffers.used.iter().enumerate() {
@@ -53,23 +63,25 @@ impl MultiBufferArena {
             }
 
             // Create new buffer
+            //
+            // NOTE: Buffers are read by shaders every frame, so device local
+            // memory is preferred while the resizable BAR heap has room.
             let capacity = size.next_power_of_two();
-            let buffer = device.create_mappable_buffer(
+            let buffer = device.create_mappable_buffer_with_preference(
                 gfx::BufferInfo {
                     align_mask,
                     size: capacity,
                     usage,
+                    label: Some("multi buffer arena"),
                 },
-                gfx::MemoryUsage::UPLOAD,
+                gfx::MemoryUsage::UPLOAD | gfx::MemoryUsage::FAST_DEVICE_ACCESS,
+                gfx::MemoryUsagePreference::Preferred,
             )?;
+            this.allocated_bytes
+                .fetch_add(capacity as u64, Ordering::Relaxed);
 
-            let ptr = device
-                .map_memory(&mut buffer.as_mappable(), 0, capacity)?
-                .as_mut_ptr();
-
-            Ok(MappedBuffer {
-                buffer,
-                ptr,
+            Ok(ArenaBuffer {
+                mapped: device.map_persistent(buffer)?,
                 offset: 0,
                 capacity,
                 handles: Vec::new(),
@@ -86,25 +98,31 @@ impl MultiBufferArena {
         })
     }
 
-    pub fn end_raw<T: gfx::Std430>(&self, arena: BufferArena<T>) -> gfx::BufferRange {
+    pub fn end_raw<T: gfx::Std430>(
+        &self,
+        device: &gfx::Device,
+        arena: BufferArena<T>,
+    ) -> Result<gfx::BufferRange> {
         let BufferArena {
             inner: mut mapped,
             initial_offset,
             size,
             ..
         } = arena;
+        device.flush_mapped_ranges([(&mapped.mapped, initial_offset..mapped.offset)])?;
         mapped.offset = gfx::align_offset(T::ALIGN_MASK | self.buffer_align_mask, mapped.offset);
 
-        let usage = mapped.buffer.info().usage;
+        let buffer = mapped.mapped.buffer();
+        let usage = buffer.info().usage;
         let range = gfx::BufferRange {
-            buffer: mapped.buffer.clone(),
+            buffer: buffer.clone(),
             offset: initial_offset,
             size,
         };
 
         let mut buffers = self.buffers.lock().unwrap();
         buffers.entry(usage).or_default().used.push(mapped);
-        range
+        Ok(range)
     }
 
     pub fn end<T: gfx::Std430>(
@@ -112,29 +130,40 @@ impl MultiBufferArena {
         device: &gfx::Device,
         bindless_resources: &BindlessResources,
         arena: BufferArena<T>,
-    ) -> StorageBufferHandle {
+    ) -> Result<StorageBufferHandle> {
+        let (handle, _) = self.end_with_range(device, bindless_resources, arena)?;
+        Ok(handle)
+    }
+
+    /// Same as [`MultiBufferArena::end`], but also returns the written range
+    /// for commands which use the buffer directly.
+    pub fn end_with_range<T: gfx::Std430>(
+        &self,
+        device: &gfx::Device,
+        bindless_resources: &BindlessResources,
+        arena: BufferArena<T>,
+    ) -> Result<(StorageBufferHandle, gfx::BufferRange)> {
         fn end_impl(
             this: &MultiBufferArena,
             device: &gfx::Device,
             bindless_resources: &BindlessResources,
-            mut mapped: MappedBuffer,
+            mut mapped: ArenaBuffer,
             initial_offset: usize,
             size: usize,
-        ) -> StorageBufferHandle {
-            let usage = mapped.buffer.info().usage;
-            let handle = bindless_resources.alloc_storage_buffer(
-                device,
-                gfx::BufferRange {
-                    buffer: mapped.buffer.clone(),
-                    offset: initial_offset,
-                    size,
-                },
-            );
+        ) -> (StorageBufferHandle, gfx::BufferRange) {
+            let buffer = mapped.mapped.buffer();
+            let usage = buffer.info().usage;
+            let range = gfx::BufferRange {
+                buffer: buffer.clone(),
+                offset: initial_offset,
+                size,
+            };
+            let handle = bindless_resources.alloc_storage_buffer(device, range.clone());
             mapped.handles.push(handle);
 
             let mut buffers = this.buffers.lock().unwrap();
             buffers.entry(usage).or_default().used.push(mapped);
-            handle
+            (handle, range)
         }
 
         let BufferArena {
@@ -143,63 +172,79 @@ impl MultiBufferArena {
             size,
             ..
         } = arena;
+        device.flush_mapped_ranges([(&mapped.mapped, initial_offset..mapped.offset)])?;
         mapped.offset = gfx::align_offset(T::ALIGN_MASK | self.buffer_align_mask, mapped.offset);
 
-        end_impl(
+        Ok(end_impl(
             self,
             device,
             bindless_resources,
             mapped,
             initial_offset,
             size,
-        )
+        ))
+    }
+
+    /// Returns `true` if any buffer was used since the previous flush.
+    pub fn has_used_buffers(&self) -> bool {
+        let groups = self.buffers.lock().unwrap();
+        groups.values().any(|buffers| !buffers.used.is_empty())
     }
 
-    pub fn flush(&self, bindless_resources: &BindlessResources) {
+    /// Retires buffers used since the previous flush and recycles the ones
+    /// which are no longer used by the GPU.
+    ///
+    /// Buffers retired at `frame` can only be reused once `completed_frame` reaches it.
+    pub fn flush(
+        &self,
+        frame: u32,
+        completed_frame: Option<u32>,
+        bindless_resources: &BindlessResources,
+    ) {
         let mut groups = self.buffers.lock().unwrap();
         for buffers in groups.values_mut() {
-            for mut buffer in buffers.retired.drain(..) {
+            if let Some(completed_frame) = completed_frame {
+                let completed = buffers
+                    .retired
+                    .iter()
+                    .take_while(|(retired_at, _)| *retired_at <= completed_frame)
+                    .count();
+
+                for (_, mut buffer) in buffers.retired.drain(..completed) {
+                    buffer.offset = 0;
+                    buffers.free.push(buffer);
+                }
+            }
+
+            for mut buffer in buffers.used.drain(..) {
+                // NOTE: Bindless handles are retired separately
                 if !buffer.handles.is_empty() {
                     bindless_resources.free_storage_buffers_batch(&buffer.handles);
+                    buffer.handles.clear();
                 }
-
-                buffer.offset = 0;
-                buffer.handles.clear();
-                buffers.free.push(buffer);
+                buffers.retired.push((frame, buffer));
             }
-
-            buffers.retired.append(&mut buffers.used);
         }
     }
 }
 
 #[derive(Default)]
 struct Buffers {
-    used: Vec<MappedBuffer>,
-    free: Vec<MappedBuffer>,
-    retired: Vec<MappedBuffer>,
+    used: Vec<ArenaBuffer>,
+    free: Vec<ArenaBuffer>,
+    /// Buffers with the frame at which they were retired, ordered by the frame.
+    retired: Vec<(u32, ArenaBuffer)>,
 }
 
-struct MappedBuffer {
-    buffer: gfx::Buffer,
-    ptr: *mut MaybeUninit<u8>,
+struct ArenaBuffer {
+    mapped: gfx::MappedBuffer,
     offset: usize,
     capacity: usize,
     handles: Vec<StorageBufferHandle>,
 }
 
-unsafe impl Send for MappedBuffer {}
-
-impl Drop for MappedBuffer {
-    fn drop(&mut self) {
-        if let Some(device) = self.buffer.owner().upgrade() {
-            device.unmap_memory(&mut self.buffer.as_mappable());
-        }
-    }
-}
-
 pub struct BufferArena<T> {
-    inner: MappedBuffer,
+    inner: ArenaBuffer,
     initial_offset: usize,
     size: usize,
     _makrer: PhantomData<T>,
@@ -217,7 +262,7 @@ where
         unsafe {
             std::ptr::copy_nonoverlapping(
                 (data as *const T).cast(),
-                self.inner.ptr.add(self.inner.offset),
+                self.inner.mapped.as_mut_ptr().add(self.inner.offset),
                 std::mem::size_of::<T>(),
             )
         }
@@ -225,9 +270,32 @@ where
         self.inner.offset += Self::ITEM_SIZE;
     }
 
+    /// Writes the item at the specified index from the start of the arena.
+    ///
+    /// NOTE: Skipped items are left uninitialized.
+    pub fn write_at(&mut self, index: usize, data: &T) {
+        let offset = self.initial_offset + index * Self::ITEM_SIZE;
+        assert!(offset + Self::ITEM_SIZE <= self.initial_offset + self.size);
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                (data as *const T).cast(),
+                self.inner.mapped.as_mut_ptr().add(offset),
+                std::mem::size_of::<T>(),
+            )
+        }
+
+        self.inner.offset = self.inner.offset.max(offset + Self::ITEM_SIZE);
+    }
+
+    /// Returns the size of the written range.
+    pub fn written_bytes(&self) -> usize {
+        self.inner.offset - self.initial_offset
+    }
+
     pub fn as_mut_ptr(&mut self) -> *mut MaybeUninit<u8> {
         assert!(self.inner.offset <= self.inner.capacity);
-        unsafe { self.inner.ptr.add(self.inner.offset) }
+        unsafe { self.inner.mapped.as_mut_ptr().add(self.inner.offset) }
     }
 
     /// # Safety
@@ -238,3 +306,30 @@ where
         self.inner.offset += offset;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RendererBuilder;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn arena_buffers_prefer_device_local_memory() {
+        let renderer = RendererBuilder::headless(16, 16).build().unwrap();
+        let device = &renderer.state().device;
+
+        let arena = MultiBufferArena::new(device);
+        let mut buffer = arena
+            .begin::<u32>(device, 16, gfx::BufferUsage::STORAGE)
+            .unwrap();
+        buffer.write(&1);
+        let range = arena.end_raw(device, buffer).unwrap();
+
+        // NOTE: The buffer is small enough to fit any resizable BAR heap
+        assert_eq!(
+            range.buffer.is_device_local(),
+            device.supports_fast_mappable_buffers()
+        );
+        assert_eq!(arena.allocated_bytes(), range.buffer.info().size as u64);
+    }
+}
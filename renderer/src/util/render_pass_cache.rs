@@ -0,0 +1,303 @@
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use glam::UVec2;
+use shared::FastHashMap;
+
+/// Counters of a render pass or framebuffer cache.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RenderTargetCacheStats {
+    /// Number of cached objects.
+    pub len: usize,
+    /// Number of lookups which found a cached object.
+    pub hits: u64,
+    /// Number of lookups which created a new object.
+    pub misses: u64,
+    /// Number of objects removed from the cache.
+    pub evictions: u64,
+}
+
+/// Render passes shared by all passes with the same attachments and operations.
+///
+/// NOTE: Cached pipelines are compatible only with the render pass they were
+/// created for, so render passes are never evicted for being unused and the
+/// least recently used ones are only removed when the cache is full.
+pub struct RenderPassCache {
+    inner: Mutex<LruCache<gfx::RenderPassInfo, gfx::RenderPass>>,
+}
+
+impl Default for RenderPassCache {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(LruCache::new(RENDER_PASS_CACHE_CAPACITY, None)),
+        }
+    }
+}
+
+impl RenderPassCache {
+    /// Returns a render pass with the specified `info`, creating it if needed.
+    pub fn get_or_create(
+        &self,
+        device: &gfx::Device,
+        info: gfx::RenderPassInfo,
+        frame: u32,
+    ) -> Result<gfx::RenderPass> {
+        let mut inner = self.inner.lock().unwrap();
+        let render_pass = inner.get_or_try_insert_with(info, frame, |info| {
+            tracing::debug!(attachments = info.attachments.len(), "creating render pass");
+            device.create_render_pass(info.clone())
+        })?;
+        Ok(render_pass.clone())
+    }
+
+    /// Removes render passes over the capacity, which are not used
+    /// after the `completed_frame`.
+    pub fn retire(&self, completed_frame: u32) {
+        self.inner.lock().unwrap().retire(completed_frame);
+    }
+
+    pub fn stats(&self) -> RenderTargetCacheStats {
+        self.inner.lock().unwrap().stats()
+    }
+}
+
+/// Framebuffers and views of the target images shared by all passes.
+///
+/// Framebuffers which were not used for several frames are evicted,
+/// so the ones of replaced swapchain images don't keep them alive.
+pub struct FramebufferCache {
+    framebuffers: Mutex<LruCache<FramebufferKey, gfx::Framebuffer>>,
+    views: Mutex<LruCache<gfx::Image, gfx::ImageView>>,
+}
+
+/// Attachments are compared by their handles.
+#[derive(Hash, PartialEq, Eq)]
+struct FramebufferKey {
+    render_pass: gfx::RenderPass,
+    attachments: Vec<gfx::ImageView>,
+    extent: UVec2,
+}
+
+impl Default for FramebufferCache {
+    fn default() -> Self {
+        Self {
+            framebuffers: Mutex::new(LruCache::new(
+                FRAMEBUFFER_CACHE_CAPACITY,
+                Some(MAX_UNUSED_FRAMES),
+            )),
+            views: Mutex::new(LruCache::new(
+                FRAMEBUFFER_CACHE_CAPACITY,
+                Some(MAX_UNUSED_FRAMES),
+            )),
+        }
+    }
+}
+
+impl FramebufferCache {
+    /// Returns a framebuffer with the specified `info`, creating it if needed.
+    pub fn get_or_create(
+        &self,
+        device: &gfx::Device,
+        info: gfx::FramebufferInfo,
+        frame: u32,
+    ) -> Result<gfx::Framebuffer> {
+        let key = FramebufferKey {
+            render_pass: info.render_pass.clone(),
+            attachments: info.attachments.clone(),
+            extent: info.extent,
+        };
+
+        let mut framebuffers = self.framebuffers.lock().unwrap();
+        let framebuffer = framebuffers.get_or_try_insert_with(key, frame, |_| {
+            tracing::debug!(extent = ?info.extent, "creating framebuffer");
+            device.create_framebuffer(info)
+        })?;
+        Ok(framebuffer.clone())
+    }
+
+    /// Returns a view of the whole `image`, e.g. of a swapchain image,
+    /// which stays the same while the image is used.
+    pub fn target_view(
+        &self,
+        device: &gfx::Device,
+        image: &gfx::Image,
+        frame: u32,
+    ) -> Result<gfx::ImageView> {
+        let mut views = self.views.lock().unwrap();
+        let view = views.get_or_try_insert_with(image.clone(), frame, |image| {
+            device.create_image_view(gfx::ImageViewInfo::new(image.clone()))
+        })?;
+        Ok(view.clone())
+    }
+
+    /// Removes framebuffers and views which are not used after
+    /// the `completed_frame`, so the GPU no longer needs them.
+    pub fn retire(&self, completed_frame: u32) {
+        self.framebuffers.lock().unwrap().retire(completed_frame);
+        self.views.lock().unwrap().retire(completed_frame);
+    }
+
+    pub fn stats(&self) -> RenderTargetCacheStats {
+        self.framebuffers.lock().unwrap().stats()
+    }
+}
+
+struct LruCache<K, V> {
+    entries: FastHashMap<K, LruEntry<V>>,
+    capacity: usize,
+    /// Entries not used for this number of completed frames are evicted.
+    max_unused_frames: Option<u32>,
+    stats: RenderTargetCacheStats,
+}
+
+struct LruEntry<V> {
+    value: V,
+    last_used: u32,
+}
+
+impl<K: Hash + Eq, V> LruCache<K, V> {
+    fn new(capacity: usize, max_unused_frames: Option<u32>) -> Self {
+        Self {
+            entries: FastHashMap::default(),
+            capacity,
+            max_unused_frames,
+            stats: RenderTargetCacheStats::default(),
+        }
+    }
+
+    fn get_or_try_insert_with<E>(
+        &mut self,
+        key: K,
+        frame: u32,
+        f: impl FnOnce(&K) -> Result<V, E>,
+    ) -> Result<&V, E> {
+        use std::collections::hash_map::Entry;
+
+        let entry = match self.entries.entry(key) {
+            Entry::Occupied(entry) => {
+                self.stats.hits += 1;
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => {
+                let value = f(entry.key())?;
+                self.stats.misses += 1;
+                entry.insert(LruEntry {
+                    value,
+                    last_used: frame,
+                })
+            }
+        };
+        entry.last_used = entry.last_used.max(frame);
+        Ok(&entry.value)
+    }
+
+    /// Evicts entries which are no longer used by the GPU: the ones unused
+    /// for too long and the least recently used ones over the capacity.
+    fn retire(&mut self, completed_frame: u32) {
+        if let Some(max_unused_frames) = self.max_unused_frames {
+            let len = self.entries.len();
+            self.entries.retain(|_, entry| {
+                entry.last_used > completed_frame
+                    || completed_frame - entry.last_used < max_unused_frames
+            });
+            self.stats.evictions += (len - self.entries.len()) as u64;
+        }
+
+        while self.entries.len() > self.capacity {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.last_used <= completed_frame)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(_, entry)| entry.last_used)
+            else {
+                break;
+            };
+
+            let len = self.entries.len();
+            let mut excess = len - self.capacity;
+            self.entries.retain(|_, entry| {
+                if excess > 0 && entry.last_used == oldest {
+                    excess -= 1;
+                    return false;
+                }
+                true
+            });
+            self.stats.evictions += (len - self.entries.len()) as u64;
+        }
+    }
+
+    fn stats(&self) -> RenderTargetCacheStats {
+        RenderTargetCacheStats {
+            len: self.entries.len(),
+            ..self.stats
+        }
+    }
+}
+
+const RENDER_PASS_CACHE_CAPACITY: usize = 16;
+const FRAMEBUFFER_CACHE_CAPACITY: usize = 32;
+/// Number of completed frames after which unused framebuffers are evicted.
+const MAX_UNUSED_FRAMES: u32 = 8;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(cache: &mut LruCache<u32, u32>, key: u32, frame: u32) -> bool {
+        let mut created = false;
+        cache
+            .get_or_try_insert_with(key, frame, |key| {
+                created = true;
+                Ok::<_, ()>(*key)
+            })
+            .unwrap();
+        created
+    }
+
+    #[test]
+    fn unused_entries_are_evicted_after_completion() {
+        let mut cache = LruCache::new(16, Some(4));
+        assert!(insert(&mut cache, 1, 0));
+        assert!(insert(&mut cache, 2, 0));
+        assert!(!insert(&mut cache, 1, 5));
+
+        // Nothing is unused for 4 completed frames yet
+        cache.retire(3);
+        assert_eq!(cache.stats().len, 2);
+
+        cache.retire(8);
+        assert_eq!(cache.stats().len, 1);
+        assert!(!insert(&mut cache, 1, 10));
+
+        assert_eq!(
+            cache.stats(),
+            RenderTargetCacheStats {
+                len: 1,
+                hits: 2,
+                misses: 2,
+                evictions: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted_over_capacity() {
+        let mut cache = LruCache::new(2, None);
+        insert(&mut cache, 1, 0);
+        insert(&mut cache, 2, 1);
+        insert(&mut cache, 3, 2);
+        insert(&mut cache, 1, 3);
+
+        // Entries used by the frames in flight are kept
+        cache.retire(0);
+        assert_eq!(cache.stats().len, 3);
+
+        cache.retire(3);
+        assert_eq!(cache.stats().len, 2);
+        assert!(insert(&mut cache, 2, 4));
+        assert!(!insert(&mut cache, 3, 4));
+        assert!(!insert(&mut cache, 1, 4));
+    }
+}
@@ -1,10 +1,24 @@
+#[cfg(feature = "handle-debug")]
+use std::backtrace::Backtrace;
 use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
+
+use shared::FastHashMap;
 
 pub trait HandleAllocator<T: HandleData> {
     fn alloc(&self, deleter: Arc<T::Deleter>) -> ResourceHandle<T>;
     fn dealloc(&self, handle: RawResourceHandle<T>);
+
+    /// Returns a new clone of the allocated handle if it is still referenced.
+    fn upgrade(&self, handle: RawResourceHandle<T>) -> Option<ResourceHandle<T>>;
+
+    /// Returns the number of allocated handles which are still referenced.
+    fn live_count(&self) -> usize;
+
+    /// Logs allocated handles which are still referenced, e.g. on shutdown.
+    fn report_leaks(&self, kind: &str);
 }
 
 pub trait HandleData: Send + Sync + 'static {
@@ -15,70 +29,246 @@ pub trait HandleDeleter<T: ?Sized>: Send + Sync + 'static {
     fn delete(&self, handle: RawResourceHandle<T>);
 }
 
-pub struct SimpleHandleAllocator<T> {
+pub struct SimpleHandleAllocator<T: HandleData> {
     next: AtomicUsize,
-    _phantom: PhantomData<T>,
+    live: LiveHandles<T>,
 }
 
-impl<T> Default for SimpleHandleAllocator<T> {
+impl<T: HandleData> Default for SimpleHandleAllocator<T> {
     fn default() -> Self {
         Self {
             next: AtomicUsize::new(0),
-            _phantom: PhantomData,
+            live: LiveHandles::default(),
         }
     }
 }
 
 impl<T: HandleData> HandleAllocator<T> for SimpleHandleAllocator<T> {
     fn alloc(&self, deleter: Arc<T::Deleter>) -> ResourceHandle<T> {
-        ResourceHandle {
+        // NOTE: Indices are never reused, so the generation is always zero
+        let handle = ResourceHandle {
             index: self.next.fetch_add(1, Ordering::Relaxed),
-            refcount: deleter,
-        }
+            generation: 0,
+            refcount: ManuallyDrop::new(deleter),
+        };
+        self.live.insert(&handle);
+        handle
+    }
+
+    fn dealloc(&self, handle: RawResourceHandle<T>) {
+        self.live.remove(handle);
+    }
+
+    fn upgrade(&self, handle: RawResourceHandle<T>) -> Option<ResourceHandle<T>> {
+        self.live.upgrade(handle)
     }
 
-    fn dealloc(&self, _handle: RawResourceHandle<T>) {}
+    fn live_count(&self) -> usize {
+        self.live.count()
+    }
+
+    fn report_leaks(&self, kind: &str) {
+        self.live.report(kind);
+    }
 }
 
-pub struct FreelistHandleAllocator<T> {
-    next: AtomicUsize,
-    free_list: Mutex<Vec<usize>>,
-    _phantom: PhantomData<T>,
+/// Allocator which reuses indices of deallocated handles.
+///
+/// Each reuse bumps the generation of the index, so stale raw handles
+/// can be distinguished from the new ones.
+pub struct FreelistHandleAllocator<T: HandleData> {
+    state: Mutex<FreelistState>,
+    live: LiveHandles<T>,
+}
+
+#[derive(Default)]
+struct FreelistState {
+    /// Current generation for each allocated index.
+    generations: Vec<u32>,
+    free_list: Vec<usize>,
 }
 
-impl<T> Default for FreelistHandleAllocator<T> {
+impl<T: HandleData> Default for FreelistHandleAllocator<T> {
     fn default() -> Self {
         Self {
-            next: AtomicUsize::new(0),
-            free_list: Mutex::new(Vec::new()),
-            _phantom: PhantomData,
+            state: Mutex::default(),
+            live: LiveHandles::default(),
         }
     }
 }
 
 impl<T: HandleData> HandleAllocator<T> for FreelistHandleAllocator<T> {
     fn alloc(&self, deleter: Arc<T::Deleter>) -> ResourceHandle<T> {
-        let index = self
-            .free_list
-            .lock()
-            .unwrap()
-            .pop()
-            .unwrap_or_else(|| self.next.fetch_add(1, Ordering::Relaxed));
-
-        ResourceHandle {
+        let mut state = self.state.lock().unwrap();
+        let index = match state.free_list.pop() {
+            Some(index) => index,
+            None => {
+                state.generations.push(0);
+                state.generations.len() - 1
+            }
+        };
+
+        let handle = ResourceHandle {
             index,
-            refcount: deleter,
-        }
+            generation: state.generations[index],
+            refcount: ManuallyDrop::new(deleter),
+        };
+        self.live.insert(&handle);
+        handle
     }
 
     fn dealloc(&self, handle: RawResourceHandle<T>) {
-        self.free_list.lock().unwrap().push(handle.index);
+        let mut state = self.state.lock().unwrap();
+        match state.generations.get_mut(handle.index) {
+            Some(generation) if *generation == handle.generation => {
+                *generation = generation.wrapping_add(1);
+                state.free_list.push(handle.index);
+                self.live.remove(handle);
+            }
+            _ => tracing::error!(?handle, "tried to deallocate a stale handle"),
+        }
+    }
+
+    fn upgrade(&self, handle: RawResourceHandle<T>) -> Option<ResourceHandle<T>> {
+        self.live.upgrade(handle)
+    }
+
+    fn live_count(&self) -> usize {
+        self.live.count()
+    }
+
+    fn report_leaks(&self, kind: &str) {
+        self.live.report(kind);
+    }
+}
+
+/// Handles which were allocated but not deallocated yet.
+///
+/// A handle is live while any of its clones exists. Handles which were
+/// dropped but whose resources are not removed yet are not counted.
+struct LiveHandles<T: HandleData> {
+    entries: Mutex<FastHashMap<usize, LiveHandle<T>>>,
+}
+
+struct LiveHandle<T: HandleData> {
+    generation: u32,
+    refcount: Weak<T::Deleter>,
+    #[cfg(feature = "handle-debug")]
+    backtrace: Backtrace,
+}
+
+impl<T: HandleData> Default for LiveHandles<T> {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::default(),
+        }
+    }
+}
+
+impl<T: HandleData> LiveHandles<T> {
+    fn insert(&self, handle: &ResourceHandle<T>) {
+        let entry = LiveHandle {
+            generation: handle.generation,
+            refcount: Arc::downgrade(&handle.refcount),
+            #[cfg(feature = "handle-debug")]
+            backtrace: Backtrace::force_capture(),
+        };
+        self.entries.lock().unwrap().insert(handle.index, entry);
+    }
+
+    fn remove(&self, handle: RawResourceHandle<T>) {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(&handle.index);
+        if entry.is_some_and(|entry| entry.generation == handle.generation) {
+            entries.remove(&handle.index);
+        }
+    }
+
+    fn upgrade(&self, handle: RawResourceHandle<T>) -> Option<ResourceHandle<T>> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries
+            .get(&handle.index)
+            .filter(|entry| entry.generation == handle.generation)?;
+        Some(ResourceHandle {
+            index: handle.index,
+            generation: handle.generation,
+            refcount: ManuallyDrop::new(entry.refcount.upgrade()?),
+        })
+    }
+
+    fn count(&self) -> usize {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .values()
+            .filter(|entry| entry.is_referenced())
+            .count()
+    }
+
+    fn report(&self, kind: &str) {
+        let entries = self.entries.lock().unwrap();
+        let mut leaked = entries
+            .iter()
+            .filter(|(_, entry)| entry.is_referenced())
+            .collect::<Vec<_>>();
+        if leaked.is_empty() {
+            return;
+        }
+        leaked.sort_unstable_by_key(|(index, _)| **index);
+
+        tracing::warn!(
+            kind,
+            count = leaked.len(),
+            "renderer handles are still alive"
+        );
+        for (index, entry) in leaked.iter().take(MAX_REPORTED_LEAKS) {
+            #[cfg(feature = "handle-debug")]
+            tracing::warn!(
+                kind,
+                index,
+                generation = entry.generation,
+                refcount = entry.refcount.strong_count(),
+                "leaked handle allocated at:\n{}",
+                entry.backtrace
+            );
+            #[cfg(not(feature = "handle-debug"))]
+            tracing::warn!(
+                kind,
+                index,
+                generation = entry.generation,
+                refcount = entry.refcount.strong_count(),
+                "leaked handle"
+            );
+        }
+        if leaked.len() > MAX_REPORTED_LEAKS {
+            tracing::warn!(
+                kind,
+                "{} more leaked handles",
+                leaked.len() - MAX_REPORTED_LEAKS
+            );
+        }
+        #[cfg(not(feature = "handle-debug"))]
+        tracing::warn!("enable the `handle-debug` feature to capture allocation backtraces");
     }
 }
 
+impl<T: HandleData> LiveHandle<T> {
+    fn is_referenced(&self) -> bool {
+        self.refcount.strong_count() > 0
+    }
+}
+
+/// Number of leaked handles of each kind which are logged individually.
+const MAX_REPORTED_LEAKS: usize = 16;
+
+/// Reference counted handle of a renderer resource.
+///
+/// The resource is deleted once the last clone is dropped. Objects keep
+/// clones of their mesh and material handles, so these resources stay alive
+/// until all objects which use them are removed, regardless of the drop order.
 pub struct ResourceHandle<T: HandleData> {
     index: usize,
-    refcount: Arc<T::Deleter>,
+    generation: u32,
+    refcount: ManuallyDrop<Arc<T::Deleter>>,
 }
 
 impl<T: HandleData> ResourceHandle<T> {
@@ -86,9 +276,14 @@ impl<T: HandleData> ResourceHandle<T> {
         self.index
     }
 
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
     pub(crate) fn raw(&self) -> RawResourceHandle<T> {
         RawResourceHandle {
             index: self.index,
+            generation: self.generation,
             _phantom: Default::default(),
         }
     }
@@ -96,8 +291,13 @@ impl<T: HandleData> ResourceHandle<T> {
 
 impl<T: HandleData> Drop for ResourceHandle<T> {
     fn drop(&mut self) {
-        if Arc::strong_count(&self.refcount) == 1 {
-            self.refcount.delete(self.raw());
+        // SAFETY: `refcount` is not used after this point.
+        let refcount = unsafe { ManuallyDrop::take(&mut self.refcount) };
+
+        // NOTE: Clones could be dropped concurrently on different threads,
+        // so the last reference is determined by the decrement itself.
+        if let Some(deleter) = Arc::into_inner(refcount) {
+            deleter.delete(self.raw());
         }
     }
 }
@@ -106,7 +306,8 @@ impl<T: HandleData> Clone for ResourceHandle<T> {
     fn clone(&self) -> Self {
         Self {
             index: self.index,
-            refcount: self.refcount.clone(),
+            generation: self.generation,
+            refcount: ManuallyDrop::new(Arc::clone(&self.refcount)),
         }
     }
 }
@@ -114,13 +315,14 @@ impl<T: HandleData> Clone for ResourceHandle<T> {
 impl<T: HandleData> Eq for ResourceHandle<T> {}
 impl<T: HandleData> PartialEq for ResourceHandle<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.index == other.index
+        self.index == other.index && self.generation == other.generation
     }
 }
 
 impl<T: HandleData> std::hash::Hash for ResourceHandle<T> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.index.hash(state)
+        self.index.hash(state);
+        self.generation.hash(state);
     }
 }
 
@@ -128,6 +330,7 @@ impl<T: HandleData> std::fmt::Debug for ResourceHandle<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ResourceHandle")
             .field("id", &self.index)
+            .field("generation", &self.generation)
             .field("refcount", &Arc::strong_count(&self.refcount))
             .finish()
     }
@@ -135,6 +338,7 @@ impl<T: HandleData> std::fmt::Debug for ResourceHandle<T> {
 
 pub struct RawResourceHandle<T: ?Sized> {
     pub index: usize,
+    pub generation: u32,
     _phantom: PhantomData<T>,
 }
 
@@ -150,6 +354,7 @@ impl<T: ?Sized> std::fmt::Debug for RawResourceHandle<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RawResourceHandle")
             .field("id", &self.index)
+            .field("generation", &self.generation)
             .finish()
     }
 }
@@ -158,13 +363,226 @@ impl<T: ?Sized> Eq for RawResourceHandle<T> {}
 impl<T: ?Sized> PartialEq for RawResourceHandle<T> {
     #[inline(always)]
     fn eq(&self, other: &Self) -> bool {
-        self.index == other.index
+        self.index == other.index && self.generation == other.generation
     }
 }
 
 impl<T: ?Sized> std::hash::Hash for RawResourceHandle<T> {
     #[inline(always)]
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        std::hash::Hash::hash(&self.index, state)
+        std::hash::Hash::hash(&self.index, state);
+        std::hash::Hash::hash(&self.generation, state);
+    }
+}
+
+/// Storage for resources indexed by raw handles.
+///
+/// Each slot remembers the generation of the handle it was inserted with,
+/// so lookups with stale handles to reused slots return `None`.
+pub struct ResourceRegistry<T: ?Sized, V> {
+    slots: Vec<Option<(u32, V)>>,
+    len: usize,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: ?Sized, V> Default for ResourceRegistry<T, V> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            len: 0,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized, V> ResourceRegistry<T, V> {
+    pub fn insert(&mut self, handle: RawResourceHandle<T>, value: V) {
+        if handle.index >= self.slots.len() {
+            self.slots.resize_with(handle.index + 1, || None);
+        }
+        let prev = self.slots[handle.index].replace((handle.generation, value));
+        self.len += prev.is_none() as usize;
+    }
+
+    /// Returns the number of stored resources.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, handle: RawResourceHandle<T>) -> Option<&V> {
+        match self.slots.get(handle.index)? {
+            Some((generation, value)) if *generation == handle.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: RawResourceHandle<T>) -> Option<&mut V> {
+        match self.slots.get_mut(handle.index)? {
+            Some((generation, value)) if *generation == handle.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn remove(&mut self, handle: RawResourceHandle<T>) -> Option<V> {
+        let slot = self.slots.get_mut(handle.index)?;
+        match slot {
+            Some((generation, _)) if *generation == handle.generation => {
+                self.len -= 1;
+                slot.take().map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Iterates over all resources with the handles they were inserted with.
+    pub fn iter(&self) -> impl Iterator<Item = (RawResourceHandle<T>, &V)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let (generation, value) = slot.as_ref()?;
+            let handle = RawResourceHandle {
+                index,
+                generation: *generation,
+                _phantom: PhantomData,
+            };
+            Some((handle, value))
+        })
+    }
+
+    /// Iterates over all resources mutably.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> + '_ {
+        self.slots
+            .iter_mut()
+            .filter_map(|slot| slot.as_mut().map(|(_, value)| value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestTag;
+
+    impl HandleData for TestTag {
+        type Deleter = TestDeleter;
+    }
+
+    #[derive(Default)]
+    struct TestDeleter {
+        deleted: Arc<AtomicUsize>,
+    }
+
+    impl HandleDeleter<TestTag> for TestDeleter {
+        fn delete(&self, _: RawResourceHandle<TestTag>) {
+            self.deleted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn counted_handle() -> (ResourceHandle<TestTag>, Arc<AtomicUsize>) {
+        let allocator = SimpleHandleAllocator::<TestTag>::default();
+        let deleter = TestDeleter::default();
+        let deleted = deleter.deleted.clone();
+        (allocator.alloc(Arc::new(deleter)), deleted)
+    }
+
+    #[test]
+    fn concurrently_dropped_clones_delete_once() {
+        for _ in 0..100 {
+            let (handle, deleted) = counted_handle();
+            let clones = (0..4).map(|_| handle.clone()).collect::<Vec<_>>();
+            drop(handle);
+
+            let barrier = Arc::new(std::sync::Barrier::new(clones.len()));
+            let threads = clones
+                .into_iter()
+                .map(|clone| {
+                    let barrier = barrier.clone();
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        drop(clone);
+                    })
+                })
+                .collect::<Vec<_>>();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            assert_eq!(deleted.load(Ordering::Relaxed), 1);
+        }
+    }
+
+    #[test]
+    fn freelist_bumps_generation_on_reuse() {
+        let allocator = FreelistHandleAllocator::<TestTag>::default();
+        let deleter = Arc::new(TestDeleter::default());
+
+        let first = allocator.alloc(deleter.clone()).raw();
+        let second = allocator.alloc(deleter.clone()).raw();
+        assert_eq!((first.index, first.generation), (0, 0));
+        assert_eq!((second.index, second.generation), (1, 0));
+
+        allocator.dealloc(first);
+        let reused = allocator.alloc(deleter.clone()).raw();
+        assert_eq!((reused.index, reused.generation), (0, 1));
+        assert_ne!(reused, first);
+
+        // Stale handles must not free the reused slot
+        allocator.dealloc(first);
+        let third = allocator.alloc(deleter).raw();
+        assert_eq!((third.index, third.generation), (2, 0));
+    }
+
+    #[test]
+    fn only_referenced_handles_are_live() {
+        let allocator = FreelistHandleAllocator::<TestTag>::default();
+        let first = allocator.alloc(Arc::new(TestDeleter::default()));
+        let second = allocator.alloc(Arc::new(TestDeleter::default()));
+        let second_clone = second.clone();
+        assert_eq!(allocator.live_count(), 2);
+
+        // Dropped handles are not leaked even before they are deallocated
+        let raw_first = first.raw();
+        drop(first);
+        drop(second);
+        assert_eq!(allocator.live_count(), 1);
+
+        // Only referenced handles can be upgraded
+        assert!(allocator.upgrade(raw_first).is_none());
+        assert_eq!(
+            allocator.upgrade(second_clone.raw()).as_ref(),
+            Some(&second_clone)
+        );
+
+        allocator.dealloc(raw_first);
+        let reused = allocator.alloc(Arc::new(TestDeleter::default()));
+        allocator.dealloc(raw_first);
+        assert_eq!(allocator.live_count(), 2);
+
+        drop((second_clone, reused));
+        assert_eq!(allocator.live_count(), 0);
+    }
+
+    #[test]
+    fn registry_rejects_stale_handles() {
+        let allocator = FreelistHandleAllocator::<TestTag>::default();
+        let deleter = Arc::new(TestDeleter::default());
+        let mut registry = ResourceRegistry::<TestTag, &str>::default();
+
+        let stale = allocator.alloc(deleter.clone()).raw();
+        registry.insert(stale, "old");
+        assert_eq!(registry.remove(stale), Some("old"));
+        allocator.dealloc(stale);
+
+        let handle = allocator.alloc(deleter).raw();
+        assert_eq!(handle.index, stale.index);
+        registry.insert(handle, "new");
+
+        assert_eq!(registry.get(stale), None);
+        assert_eq!(registry.get_mut(stale), None);
+        assert_eq!(registry.remove(stale), None);
+        assert_eq!(registry.get(handle), Some(&"new"));
+        assert_eq!(registry.len(), 1);
     }
 }
@@ -1,4 +1,6 @@
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
 
 use anyhow::Result;
 
@@ -21,11 +23,28 @@ impl<T> ScatterData<T> {
 pub struct ScatterCopy {
     descriptor_set_layout: gfx::DescriptorSetLayout,
     pipeline: gfx::ComputePipeline,
+    compute_supported: bool,
+    copy_threshold: AtomicU32,
+    batch: Mutex<ScatterBatch>,
+    slots_scattered: AtomicU64,
+    dispatches: AtomicU64,
+    copy_fallbacks: AtomicU64,
+    slots_written_directly: AtomicU64,
 }
 
 impl ScatterCopy {
+    /// Batches with fewer slots than this are written with plain buffer copies.
+    pub const DEFAULT_COPY_THRESHOLD: u32 = 16;
+
+    /// NOTE: `queue_capabilities` are the capabilities of the queue which
+    /// executes the flushed commands. Without compute support all writes
+    /// are done with buffer copies.
     #[tracing::instrument(level = "debug", name = "create_scatter_copy", skip_all)]
-    pub fn new(device: &gfx::Device, shader_preprocessor: &ShaderPreprocessor) -> Result<Self> {
+    pub fn new(
+        device: &gfx::Device,
+        shader_preprocessor: &ShaderPreprocessor,
+        queue_capabilities: gfx::QueueFlags,
+    ) -> Result<Self> {
         let shader = shader_preprocessor.begin().make_compute_shader(
             device,
             "/scatter_copy.comp",
@@ -45,7 +64,7 @@ impl ScatterCopy {
                     gfx::DescriptorSetLayoutBinding {
                         binding: 1,
                         ty: gfx::DescriptorType::StorageBuffer,
-                        count: 1,
+                        count: MAX_TARGETS_PER_DISPATCH as u32,
                         stages: gfx::ShaderStageFlags::COMPUTE,
                         flags: Default::default(),
                     },
@@ -61,107 +80,422 @@ impl ScatterCopy {
         let pipeline =
             device.create_compute_pipeline(gfx::ComputePipelineInfo { shader, layout })?;
 
+        let compute_supported = queue_capabilities.supports_compute();
+        if !compute_supported {
+            tracing::warn!("queue doesn't support compute, scatter copy uses buffer copies");
+        }
+
         Ok(Self {
             descriptor_set_layout,
             pipeline,
+            compute_supported,
+            copy_threshold: AtomicU32::new(Self::DEFAULT_COPY_THRESHOLD),
+            batch: Mutex::default(),
+            slots_scattered: AtomicU64::new(0),
+            dispatches: AtomicU64::new(0),
+            copy_fallbacks: AtomicU64::new(0),
+            slots_written_directly: AtomicU64::new(0),
         })
     }
 
-    pub fn execute<T, D>(
+    pub fn set_copy_threshold(&self, slots: u32) {
+        self.copy_threshold.store(slots, Ordering::Relaxed);
+    }
+
+    pub fn copy_threshold(&self) -> u32 {
+        self.copy_threshold.load(Ordering::Relaxed)
+    }
+
+    /// Total number of written slots.
+    pub fn slots_scattered(&self) -> u64 {
+        self.slots_scattered.load(Ordering::Relaxed)
+    }
+
+    /// Total number of compute dispatches.
+    pub fn dispatches(&self) -> u64 {
+        self.dispatches.load(Ordering::Relaxed)
+    }
+
+    /// Total number of flushes which used buffer copies instead of a dispatch.
+    pub fn copy_fallbacks(&self) -> u64 {
+        self.copy_fallbacks.load(Ordering::Relaxed)
+    }
+
+    /// Total number of slots written into mapped buffers by the CPU.
+    pub fn slots_written_directly(&self) -> u64 {
+        self.slots_written_directly.load(Ordering::Relaxed)
+    }
+
+    /// Writes data into the mapped `dst` immediately.
+    ///
+    /// NOTE: `dst` must not be used by the frames in flight.
+    pub fn write_mapped<T, D>(
         &self,
         device: &gfx::Device,
-        encoder: &mut gfx::Encoder,
-        dst: &gfx::Buffer,
-        buffers: &MultiBufferArena,
+        dst: &gfx::MappedBuffer,
         data: D,
-    ) -> Result<()>
+    ) -> Result<(), gfx::OutOfDeviceMemory>
     where
         T: gfx::Std430,
         D: IntoIterator<Item = ScatterData<T>>,
-        D::IntoIter: ExactSizeIterator,
     {
-        let data = data.into_iter();
-
         let item_size = std::mem::size_of::<T>();
-        assert_eq!(item_size % 4, 0);
 
-        let count = data.len();
-        let stride_bytes = item_size + 4;
+        let mut slots = 0;
+        let mut written = usize::MAX..0;
+        for item in data {
+            let offset = item.word_offset as usize * 4;
+            assert!(offset + item_size <= dst.size(), "slot is out of bounds");
 
-        let buffer_size = 8 + count * stride_bytes;
+            // SAFETY: `dst` is mapped and has at least `offset + item_size` bytes,
+            // `T` is a plain std430 struct.
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    (&item.data as *const T).cast::<MaybeUninit<u8>>(),
+                    dst.as_mut_ptr().add(offset),
+                    item_size,
+                );
+            }
 
-        let staging_buffer = {
-            let mut staging_buffer = buffers.begin::<u32>(
-                device,
-                buffer_size / 4,
-                gfx::BufferUsage::STORAGE | gfx::BufferUsage::TRANSFER_SRC,
-            )?;
+            written.start = written.start.min(offset);
+            written.end = written.end.max(offset + item_size);
+            slots += 1;
+        }
 
-            let ptr = staging_buffer.as_mut_ptr();
-            debug_assert_eq!(ptr.align_offset(std::mem::align_of::<u32>()), 0);
+        self.slots_written_directly
+            .fetch_add(slots, Ordering::Relaxed);
+        device.flush_mapped_ranges([(dst, written)])
+    }
 
-            let mut writer = Writer { ptr, offset: 0 };
+    /// Queues writes into `dst` until the next [`ScatterCopy::flush`].
+    pub fn push<T, D>(&self, dst: &gfx::Buffer, data: D)
+    where
+        T: gfx::Std430,
+        D: IntoIterator<Item = ScatterData<T>>,
+    {
+        let item_size = std::mem::size_of::<T>();
+        assert_eq!(item_size % 4, 0);
 
-            unsafe {
-                // words_to_copy
-                writer.write_u32((item_size / 4) as u32);
-                // count
-                writer.write_u32(count as u32);
-            }
+        let mut batch = self.batch.lock().unwrap();
+        batch.push(dst, data);
+    }
 
-            for item in data {
-                unsafe {
-                    writer.write_u32(item.word_offset);
-                    writer.write_data(&item.data);
-                }
-            }
+    /// Records all writes queued since the previous flush.
+    ///
+    /// Writes into all targets are done with a single dispatch
+    /// (or one per `MAX_TARGETS_PER_DISPATCH` targets), small batches
+    /// are written with buffer copies instead.
+    #[tracing::instrument(level = "debug", name = "flush_scatter_copy", skip_all)]
+    pub fn flush(
+        &self,
+        device: &gfx::Device,
+        encoder: &mut gfx::Encoder,
+        buffers: &MultiBufferArena,
+    ) -> Result<()> {
+        profile_scope!("flush_scatter_copy");
 
-            unsafe { staging_buffer.add_offset(buffer_size) };
+        let mut batch = self.batch.lock().unwrap();
+        if batch.items.is_empty() {
+            batch.clear();
+            return Ok(());
+        }
 
-            buffers.end_raw(staging_buffer)
+        // NOTE: Items of the same target must be adjacent
+        batch.items.sort_by_key(|item| item.target);
+
+        let slots = batch.items.len();
+        self.slots_scattered
+            .fetch_add(slots as u64, Ordering::Relaxed);
+
+        let res = if !self.compute_supported || slots < self.copy_threshold() as usize {
+            self.copy_fallbacks.fetch_add(1, Ordering::Relaxed);
+            record_copies(device, encoder, buffers, &batch)
+        } else {
+            self.record_dispatches(device, encoder, buffers, &batch)
         };
 
-        let descriptor_set = device.create_descriptor_set(gfx::DescriptorSetInfo {
-            layout: self.descriptor_set_layout.clone(),
-        })?;
-        device.update_descriptor_sets(&[gfx::UpdateDescriptorSet {
-            set: &descriptor_set,
-            writes: &[
-                gfx::DescriptorSetWrite {
-                    binding: 0,
-                    element: 0,
-                    data: gfx::DescriptorSlice::StorageBuffer(&[staging_buffer]),
-                },
-                gfx::DescriptorSetWrite {
-                    binding: 1,
-                    element: 0,
-                    data: gfx::DescriptorSlice::StorageBuffer(&[gfx::BufferRange::whole(
-                        dst.clone(),
-                    )]),
-                },
-            ],
-        }]);
+        batch.clear();
+        res
+    }
 
+    fn record_dispatches(
+        &self,
+        device: &gfx::Device,
+        encoder: &mut gfx::Encoder,
+        buffers: &MultiBufferArena,
+        batch: &ScatterBatch,
+    ) -> Result<()> {
         encoder.bind_compute_pipeline(&self.pipeline);
-        encoder.bind_compute_descriptor_sets(
-            &self.pipeline.info().layout,
-            0,
-            &[&descriptor_set],
-            &[],
-        );
 
+        // NOTE: Targets could have been reallocated and filled with copies
         encoder.memory_barrier(
             gfx::PipelineStageFlags::TRANSFER,
             gfx::AccessFlags::TRANSFER_WRITE,
             gfx::PipelineStageFlags::COMPUTE_SHADER,
-            gfx::AccessFlags::SHADER_READ,
+            gfx::AccessFlags::SHADER_READ | gfx::AccessFlags::SHADER_WRITE,
         );
-        encoder.dispatch(((count + 63) / 64) as u32, 1, 1);
+
+        for (first_target, chunk_items) in dispatch_chunks(&batch.items) {
+            let targets = &batch.targets[first_target as usize..];
+            let targets = &targets[..targets.len().min(MAX_TARGETS_PER_DISPATCH)];
+
+            let header_words = 1 + chunk_items.len() * 4;
+            let data_words = chunk_items
+                .iter()
+                .map(|item| item.word_count as usize)
+                .sum::<usize>();
+            let word_count = header_words + data_words;
+
+            let staging_buffer = {
+                let mut staging_buffer = buffers.begin::<u32>(
+                    device,
+                    word_count,
+                    gfx::BufferUsage::STORAGE | gfx::BufferUsage::TRANSFER_SRC,
+                )?;
+
+                let ptr = staging_buffer.as_mut_ptr();
+                debug_assert_eq!(ptr.align_offset(std::mem::align_of::<u32>()), 0);
+
+                let mut writer = Writer { ptr, offset: 0 };
+                unsafe {
+                    for word in dispatch_header(chunk_items, first_target) {
+                        writer.write_u32(word);
+                    }
+                    for item in chunk_items {
+                        writer.write_words(batch.item_words(item));
+                    }
+
+                    staging_buffer.add_offset(word_count * 4);
+                }
+
+                buffers.end_raw(device, staging_buffer)?
+            };
+
+            // NOTE: Unused descriptors are filled with the first target
+            let target_ranges = (0..MAX_TARGETS_PER_DISPATCH)
+                .map(|i| gfx::BufferRange::whole(targets.get(i).unwrap_or(&targets[0]).clone()))
+                .collect::<Vec<_>>();
+
+            let descriptor_set = device.create_descriptor_set(gfx::DescriptorSetInfo {
+                layout: self.descriptor_set_layout.clone(),
+                label: Some("scatter copy targets"),
+            })?;
+            device.update_descriptor_sets(&[gfx::UpdateDescriptorSet {
+                set: &descriptor_set,
+                writes: &[
+                    gfx::DescriptorSetWrite {
+                        binding: 0,
+                        element: 0,
+                        data: gfx::DescriptorSlice::StorageBuffer(&[staging_buffer]),
+                    },
+                    gfx::DescriptorSetWrite {
+                        binding: 1,
+                        element: 0,
+                        data: gfx::DescriptorSlice::StorageBuffer(&target_ranges),
+                    },
+                ],
+            }]);
+
+            encoder.bind_compute_descriptor_sets(
+                &self.pipeline.info().layout,
+                0,
+                &[&descriptor_set],
+                &[],
+            );
+            encoder.dispatch(((chunk_items.len() + 63) / 64) as u32, 1, 1);
+            self.dispatches.fetch_add(1, Ordering::Relaxed);
+        }
 
         Ok(())
     }
 }
 
+fn record_copies(
+    device: &gfx::Device,
+    encoder: &mut gfx::Encoder,
+    buffers: &MultiBufferArena,
+    batch: &ScatterBatch,
+) -> Result<()> {
+    let staging_buffer = {
+        let mut staging_buffer =
+            buffers.begin::<u32>(device, batch.words.len(), gfx::BufferUsage::TRANSFER_SRC)?;
+
+        let ptr = staging_buffer.as_mut_ptr();
+        debug_assert_eq!(ptr.align_offset(std::mem::align_of::<u32>()), 0);
+
+        let mut writer = Writer { ptr, offset: 0 };
+        unsafe {
+            writer.write_words(&batch.words);
+            staging_buffer.add_offset(batch.words.len() * 4);
+        }
+
+        buffers.end_raw(device, staging_buffer)?
+    };
+
+    // NOTE: Targets could have been reallocated and filled with copies
+    encoder.memory_barrier(
+        gfx::PipelineStageFlags::TRANSFER,
+        gfx::AccessFlags::TRANSFER_WRITE,
+        gfx::PipelineStageFlags::TRANSFER,
+        gfx::AccessFlags::TRANSFER_WRITE,
+    );
+
+    let mut regions = Vec::new();
+    for target_items in target_runs(&batch.items) {
+        regions.clear();
+        regions.extend(copy_regions(target_items, staging_buffer.offset));
+
+        encoder.copy_buffer(
+            &staging_buffer.buffer,
+            &batch.targets[target_items[0].target as usize],
+            &regions,
+        );
+    }
+
+    Ok(())
+}
+
+/// Splits items sorted by target into runs of the same target.
+fn target_runs(mut items: &[ScatterItem]) -> impl Iterator<Item = &[ScatterItem]> {
+    std::iter::from_fn(move || {
+        let first = items.first()?;
+        let count = items
+            .iter()
+            .position(|item| item.target != first.target)
+            .unwrap_or(items.len());
+        let (target_items, rest) = items.split_at(count);
+        items = rest;
+        Some(target_items)
+    })
+}
+
+/// Returns copy regions of the items from the staging buffer at `src_offset`.
+fn copy_regions(
+    items: &[ScatterItem],
+    src_offset: usize,
+) -> impl Iterator<Item = gfx::BufferCopy> + '_ {
+    items.iter().map(move |item| gfx::BufferCopy {
+        src_offset: src_offset + item.src_word_offset as usize * 4,
+        dst_offset: item.dst_word_offset as usize * 4,
+        size: item.word_count as usize * 4,
+    })
+}
+
+/// Splits items sorted by target into chunks of at most
+/// [`MAX_TARGETS_PER_DISPATCH`] targets, each written by a single dispatch.
+///
+/// Returns the first target of each chunk with its items,
+/// chunks without items are skipped.
+fn dispatch_chunks(mut items: &[ScatterItem]) -> impl Iterator<Item = (u32, &[ScatterItem])> {
+    std::iter::from_fn(move || {
+        let first = items.first()?;
+        let first_target = first.target - first.target % MAX_TARGETS_PER_DISPATCH as u32;
+        let end_target = first_target + MAX_TARGETS_PER_DISPATCH as u32;
+
+        let count = items
+            .iter()
+            .position(|item| item.target >= end_target)
+            .unwrap_or(items.len());
+        let (chunk_items, rest) = items.split_at(count);
+        items = rest;
+        Some((first_target, chunk_items))
+    })
+}
+
+/// Returns the header of the dispatch input: the item count followed by
+/// the target, destination offset, source offset and size of each item.
+///
+/// NOTE: Must be in sync with `scatter_copy.comp`. Data words are addressed
+/// relative to the `data` array, which starts right after the `count` word.
+fn dispatch_header(items: &[ScatterItem], first_target: u32) -> impl Iterator<Item = u32> + '_ {
+    let mut source_word_offset = (items.len() * 4) as u32;
+    std::iter::once(items.len() as u32).chain(items.iter().flat_map(move |item| {
+        let header = [
+            item.target - first_target,
+            item.dst_word_offset,
+            source_word_offset,
+            item.word_count,
+        ];
+        source_word_offset += item.word_count;
+        header
+    }))
+}
+
+/// Max number of destination buffers written by a single dispatch.
+///
+/// NOTE: Must be in sync with `MAX_TARGETS` in `scatter_copy.comp`
+const MAX_TARGETS_PER_DISPATCH: usize = 8;
+
+#[derive(Default)]
+struct ScatterBatch {
+    targets: Vec<gfx::Buffer>,
+    items: Vec<ScatterItem>,
+    words: Vec<u32>,
+}
+
+impl ScatterBatch {
+    fn push<T, D>(&mut self, dst: &gfx::Buffer, data: D)
+    where
+        T: gfx::Std430,
+        D: IntoIterator<Item = ScatterData<T>>,
+    {
+        let word_count = (std::mem::size_of::<T>() / 4) as u32;
+
+        let mut data = data.into_iter().peekable();
+        if data.peek().is_none() {
+            return;
+        }
+
+        let target = match self.targets.iter().position(|target| target == dst) {
+            Some(target) => target,
+            None => {
+                self.targets.push(dst.clone());
+                self.targets.len() - 1
+            }
+        } as u32;
+
+        for item in data {
+            let src_word_offset = self.words.len();
+            self.words.resize(src_word_offset + word_count as usize, 0);
+
+            // SAFETY: `T` is a plain std430 struct with a size of exactly `word_count` words.
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    (&item.data as *const T).cast::<u8>(),
+                    self.words[src_word_offset..].as_mut_ptr().cast::<u8>(),
+                    std::mem::size_of::<T>(),
+                );
+            }
+
+            self.items.push(ScatterItem {
+                target,
+                dst_word_offset: item.word_offset,
+                src_word_offset: src_word_offset as u32,
+                word_count,
+            });
+        }
+    }
+
+    fn item_words(&self, item: &ScatterItem) -> &[u32] {
+        let start = item.src_word_offset as usize;
+        &self.words[start..start + item.word_count as usize]
+    }
+
+    fn clear(&mut self) {
+        self.targets.clear();
+        self.items.clear();
+        self.words.clear();
+    }
+}
+
+struct ScatterItem {
+    target: u32,
+    dst_word_offset: u32,
+    /// Offset in [`ScatterBatch::words`].
+    src_word_offset: u32,
+    word_count: u32,
+}
+
 struct Writer {
     ptr: *mut MaybeUninit<u8>,
     offset: usize,
@@ -174,12 +508,79 @@ impl Writer {
         self.offset += 4;
     }
 
-    unsafe fn write_data<T: gfx::Std430>(&mut self, data: &T) {
-        std::ptr::copy_nonoverlapping(
-            (data as *const T).cast(),
-            self.ptr.add(self.offset),
-            std::mem::size_of::<T>(),
+    unsafe fn write_words(&mut self, words: &[u32]) {
+        let size = words.len() * 4;
+        std::ptr::copy_nonoverlapping(words.as_ptr().cast(), self.ptr.add(self.offset), size);
+        self.offset += size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns items of the targets with 2 words each, in the push order.
+    fn items(targets: &[u32]) -> Vec<ScatterItem> {
+        targets
+            .iter()
+            .enumerate()
+            .map(|(i, target)| ScatterItem {
+                target: *target,
+                dst_word_offset: 10 * i as u32,
+                src_word_offset: 2 * i as u32,
+                word_count: 2,
+            })
+            .collect()
+    }
+
+    fn targets<'a>(items: impl IntoIterator<Item = &'a ScatterItem>) -> Vec<u32> {
+        items.into_iter().map(|item| item.target).collect()
+    }
+
+    #[test]
+    fn copies_are_grouped_by_target() {
+        let items = items(&[0, 0, 1, 3, 3, 3]);
+        let runs = target_runs(&items).map(targets).collect::<Vec<_>>();
+        assert_eq!(runs, [vec![0, 0], vec![1], vec![3, 3, 3]]);
+        assert_eq!(target_runs(&[]).count(), 0);
+
+        let regions = copy_regions(&items[3..5], 64)
+            .map(|region| (region.src_offset, region.dst_offset, region.size))
+            .collect::<Vec<_>>();
+        assert_eq!(regions, [(64 + 24, 120, 8), (64 + 32, 160, 8)]);
+    }
+
+    #[test]
+    fn dispatches_are_chunked_by_targets() {
+        let max = MAX_TARGETS_PER_DISPATCH as u32;
+        let items = items(&[0, 1, max - 1, max, 2 * max + 1, 2 * max + 1]);
+
+        let chunks = dispatch_chunks(&items)
+            .map(|(first_target, items)| (first_target, targets(items)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            [
+                (0, vec![0, 1, max - 1]),
+                (max, vec![max]),
+                // NOTE: Chunks without items are skipped
+                (2 * max, vec![2 * max + 1, 2 * max + 1]),
+            ]
         );
-        self.offset += std::mem::size_of::<T>();
+        assert_eq!(dispatch_chunks(&[]).count(), 0);
+    }
+
+    #[test]
+    fn dispatch_header_addresses_data_after_header() {
+        let max = MAX_TARGETS_PER_DISPATCH as u32;
+        let items = items(&[max + 2, max + 5]);
+
+        let header = dispatch_header(&items, max).collect::<Vec<_>>();
+        #[rustfmt::skip]
+        assert_eq!(header, [
+            2,
+            2, 0, 8, 2,
+            5, 10, 10, 2,
+        ]);
     }
 }
@@ -0,0 +1,291 @@
+use std::fmt;
+use std::ops::Range;
+
+/// Shader compilation failure with messages mapped to the original files.
+#[derive(Debug, Clone, thiserror::Error)]
+pub struct ShaderCompileError {
+    /// Absolute path of the root shader.
+    pub path: String,
+    /// Errors and warnings in the order they were reported.
+    pub diagnostics: Vec<ShaderDiagnostic>,
+}
+
+impl ShaderCompileError {
+    pub fn error_count(&self) -> usize {
+        self.count(ShaderDiagnosticSeverity::Error)
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.count(ShaderDiagnosticSeverity::Warning)
+    }
+
+    fn count(&self, severity: ShaderDiagnosticSeverity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    }
+}
+
+impl fmt::Display for ShaderCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{diagnostic}")?;
+        }
+
+        write!(f, "error: could not compile `{}`", self.path)?;
+        let errors = self.error_count();
+        if errors > 0 {
+            write!(f, " due to {errors} previous error{}", plural(errors))?;
+        }
+        let warnings = self.warning_count();
+        if warnings > 0 {
+            write!(f, "; {warnings} warning{} emitted", plural(warnings))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderDiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for ShaderDiagnosticSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        })
+    }
+}
+
+/// A single message of the shader compiler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderDiagnostic {
+    pub severity: ShaderDiagnosticSeverity,
+    pub message: String,
+    /// Absolute path of the file which contains the offending line.
+    pub path: Option<String>,
+    /// One-based line in the original file.
+    pub line: Option<u32>,
+    pub snippet: Option<ShaderSnippet>,
+    /// Files which included the file, up to the root shader.
+    pub included_from: Vec<String>,
+}
+
+impl ShaderDiagnostic {
+    /// Parses the messages of `shaderc`.
+    ///
+    /// Each message has a `<path>:<line>: <severity>: <message>` format,
+    /// where the path and the line are optional. Included files are marked
+    /// with `#line` directives, so the lines are already relative to them.
+    pub(crate) fn parse_all(messages: &str) -> Vec<Self> {
+        let mut res = Vec::<Self>::new();
+        for line in messages.lines() {
+            if line.trim().is_empty() || line.ends_with(" generated.") {
+                continue;
+            }
+
+            match Self::parse(line) {
+                Some(diagnostic) => res.push(diagnostic),
+                // NOTE: Some messages span multiple lines
+                None => match res.last_mut() {
+                    Some(last) => {
+                        last.message.push('\n');
+                        last.message.push_str(line);
+                    }
+                    None => res.push(Self::new(ShaderDiagnosticSeverity::Error, line)),
+                },
+            }
+        }
+        res
+    }
+
+    fn new(severity: ShaderDiagnosticSeverity, message: &str) -> Self {
+        Self {
+            severity,
+            message: message.trim().to_owned(),
+            path: None,
+            line: None,
+            snippet: None,
+            included_from: Vec::new(),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        const SEVERITIES: [(&str, ShaderDiagnosticSeverity); 2] = [
+            ("error: ", ShaderDiagnosticSeverity::Error),
+            ("warning: ", ShaderDiagnosticSeverity::Warning),
+        ];
+
+        for (prefix, severity) in SEVERITIES {
+            if let Some(message) = line.strip_prefix(prefix) {
+                return Some(Self::new(severity, message));
+            }
+
+            let Some((location, message)) = line.split_once(&format!(": {prefix}")) else {
+                continue;
+            };
+            let mut res = Self::new(severity, message);
+            match location.rsplit_once(':') {
+                Some((path, line)) if line.parse::<u32>().is_ok() => {
+                    res.path = Some(path.to_owned());
+                    res.line = line.parse().ok();
+                }
+                _ => res.path = Some(location.to_owned()),
+            }
+            return Some(res);
+        }
+        None
+    }
+
+    /// Attaches the offending line from the source of the file.
+    pub(crate) fn set_source(&mut self, source: &str) {
+        let Some(line) = self.line else {
+            return;
+        };
+        self.snippet = line
+            .checked_sub(1)
+            .and_then(|index| source.lines().nth(index as usize))
+            .map(|text| ShaderSnippet::new(text, &self.message));
+    }
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let line = self.line.map(|line| line.to_string()).unwrap_or_default();
+        let gutter = " ".repeat(line.len().max(1));
+
+        write!(f, "\n{gutter}--> {path}")?;
+        if !line.is_empty() {
+            write!(f, ":{line}")?;
+        }
+
+        if let Some(snippet) = &self.snippet {
+            let offset = " ".repeat(snippet.highlight.start);
+            let carets = "^".repeat(snippet.highlight.len().max(1));
+            write!(f, "\n{gutter} |")?;
+            write!(f, "\n{line} | {}", snippet.text)?;
+            write!(f, "\n{gutter} | {offset}{carets}")?;
+        }
+
+        for path in &self.included_from {
+            write!(f, "\n{gutter} = note: included from {path}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The offending line of a [`ShaderDiagnostic`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderSnippet {
+    /// Text of the line with tabs expanded.
+    pub text: String,
+    /// Highlighted characters of the line.
+    pub highlight: Range<usize>,
+}
+
+impl ShaderSnippet {
+    fn new(text: &str, message: &str) -> Self {
+        let text = text.trim_end().replace('\t', "    ");
+
+        // NOTE: `glslang` quotes the offending token at the start of the message
+        let token = message
+            .strip_prefix('\'')
+            .and_then(|message| message.split_once('\''))
+            .map(|(token, _)| token)
+            .filter(|token| !token.is_empty());
+
+        let highlight = match token.and_then(|token| Some((text.find(token)?, token))) {
+            Some((start, token)) => {
+                let start = text[..start].chars().count();
+                start..start + token.chars().count()
+            }
+            None => {
+                let start = text.chars().take_while(|c| c.is_whitespace()).count();
+                start..text.chars().count()
+            }
+        };
+
+        Self { text, highlight }
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_parsed_and_formatted() {
+        let messages =
+            "/lib/a.glsl:1: warning: '#extension' : extension not supported: GL_EXT_foo\n\
+            /lib/a.glsl:3: error: 'missing' : undeclared identifier\n\
+            /main.comp: error: Linking compute stage: Missing entry point\n\
+            1 warning and 2 errors generated.\n";
+        let source = "#extension GL_EXT_foo: enable\n\tfloat x = missing;\n";
+
+        let mut diagnostics = ShaderDiagnostic::parse_all(messages);
+        assert_eq!(diagnostics.len(), 3);
+        for diagnostic in &mut diagnostics[..2] {
+            diagnostic.set_source(source);
+            diagnostic.included_from = vec!["/main.comp".to_owned()];
+        }
+        assert_eq!(diagnostics[0].severity, ShaderDiagnosticSeverity::Warning);
+        assert_eq!(diagnostics[1].line, Some(3));
+        // Lines outside of the file have no snippet
+        assert_eq!(diagnostics[1].snippet, None);
+        assert_eq!(diagnostics[2].path.as_deref(), Some("/main.comp"));
+        assert_eq!(diagnostics[2].line, None);
+
+        diagnostics[1].line = Some(2);
+        diagnostics[1].set_source(source);
+
+        let err = ShaderCompileError {
+            path: "/main.comp".to_owned(),
+            diagnostics,
+        };
+        assert_eq!(
+            err.to_string(),
+            "\
+warning: '#extension' : extension not supported: GL_EXT_foo
+ --> /lib/a.glsl:1
+  |
+1 | #extension GL_EXT_foo: enable
+  | ^^^^^^^^^^
+  = note: included from /main.comp
+error: 'missing' : undeclared identifier
+ --> /lib/a.glsl:2
+  |
+2 |     float x = missing;
+  |               ^^^^^^^
+  = note: included from /main.comp
+error: Linking compute stage: Missing entry point
+ --> /main.comp
+error: could not compile `/main.comp` due to 2 previous errors; 1 warning emitted"
+        );
+    }
+
+    #[test]
+    fn unknown_messages_are_kept() {
+        let diagnostics = ShaderDiagnostic::parse_all("something went wrong\n  details\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, ShaderDiagnosticSeverity::Error);
+        assert_eq!(diagnostics[0].message, "something went wrong\n  details");
+        assert_eq!(diagnostics[0].path, None);
+    }
+}
@@ -1,11 +1,14 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Mutex;
 
 use once_cell::sync::OnceCell;
 
 use anyhow::Result;
-use shared::FastHashMap;
+use shared::{FastHashMap, FastHashSet};
 
-use crate::util::{VirtualFs, VirtualPath};
+use crate::util::{ShaderCompileError, ShaderDiagnostic, VirtualFs, VirtualPath};
 
 #[derive(Default)]
 pub struct ShaderPreprocessor {
@@ -13,6 +16,8 @@ pub struct ShaderPreprocessor {
     global_defines: FastHashMap<String, Option<String>>,
     optimizations_enabled: bool,
     debug_info_enabled: bool,
+    /// Resolved includes of each compiled root shader.
+    dependencies: Mutex<FastHashMap<String, FastHashSet<String>>>,
 }
 
 impl ShaderPreprocessor {
@@ -51,25 +56,53 @@ impl ShaderPreprocessor {
         self.debug_info_enabled = enabled;
     }
 
+    /// Forgets dependencies of all root shaders which use the specified file
+    /// and returns their absolute paths.
+    ///
+    /// The file itself is returned if it was compiled as a root shader.
+    pub fn invalidate(&self, path: impl AsRef<str>) -> Vec<String> {
+        let path = self.absolute_path(path.as_ref());
+
+        let mut dependencies = self.dependencies.lock().unwrap();
+        let mut roots = dependencies
+            .iter()
+            .filter(|(root, includes)| **root == path || includes.contains(&path))
+            .map(|(root, _)| root.clone())
+            .collect::<Vec<_>>();
+        roots.sort_unstable();
+
+        for root in &roots {
+            dependencies.remove(root);
+        }
+        roots
+    }
+
     pub fn begin(&self) -> ShaderPreprocessorScope<'_> {
+        let includes = Rc::new(RefCell::new(IncludeTracker::default()));
+
         let mut res = ShaderPreprocessorScope {
             inner: self,
             options: shaderc::CompileOptions::new().expect("failed to create `shaderc` options"),
+            includes: includes.clone(),
         };
 
         res.options
-            .set_include_callback(|include, _ty, source, depth| {
+            .set_include_callback(move |include, _ty, source, depth| {
+                // NOTE: The include chain is attached to the diagnostics
                 if depth > 10 {
-                    return Err("too many nested includes".to_string());
+                    return Err("too many nested includes".to_owned());
                 }
 
                 match self.fs.get_file(source, include) {
-                    Ok(Some(file)) => Ok(shaderc::ResolvedInclude {
-                        resolved_name: file.absolute_path,
-                        content: file.contents.to_owned(),
-                    }),
-                    Ok(None) => Err("file not found".to_owned()),
-                    Err(err) => Err(format!("failed to read file: {}", err)),
+                    Ok(Some(file)) => {
+                        includes.borrow_mut().add(&file.absolute_path, source);
+                        Ok(shaderc::ResolvedInclude {
+                            resolved_name: file.absolute_path,
+                            content: file.contents.to_owned(),
+                        })
+                    }
+                    Ok(None) => Err(format!("file not found: {include}")),
+                    Err(err) => Err(format!("failed to read file {include}: {err}")),
                 }
             });
 
@@ -85,11 +118,31 @@ impl ShaderPreprocessor {
         }
         res
     }
+
+    fn absolute_path(&self, path: &str) -> String {
+        match self
+            .fs
+            .get_file(VirtualPath::root(), VirtualPath::new(path))
+        {
+            Ok(Some(file)) => file.absolute_path,
+            // NOTE: Removed files can still be used as dependencies
+            _ if path.starts_with('/') => path.to_owned(),
+            _ => format!("/{path}"),
+        }
+    }
+
+    fn record_dependencies(&self, root: &str, includes: &[String]) {
+        self.dependencies
+            .lock()
+            .unwrap()
+            .insert(root.to_owned(), includes.iter().cloned().collect());
+    }
 }
 
 pub struct ShaderPreprocessorScope<'a> {
     inner: &'a ShaderPreprocessor,
     options: shaderc::CompileOptions<'a>,
+    includes: Rc<RefCell<IncludeTracker>>,
 }
 
 impl<'a> ShaderPreprocessorScope<'a> {
@@ -172,8 +225,10 @@ impl<'a> ShaderPreprocessorScope<'a> {
         entry: &str,
         shader_type: gfx::ShaderType,
     ) -> Result<gfx::ShaderModule> {
-        let info = self.compile_shader(path.as_ref(), entry.as_ref(), shader_type)?;
-        device.create_shader_module(info).map_err(Into::into)
+        let shader = self.compile_shader(path.as_ref(), entry.as_ref(), shader_type)?;
+        self.inner
+            .record_dependencies(&shader.path, &shader.includes);
+        device.create_shader_module(shader.info).map_err(Into::into)
     }
 
     fn compile_shader(
@@ -181,11 +236,12 @@ impl<'a> ShaderPreprocessorScope<'a> {
         path: &str,
         entry: &str,
         shader_type: gfx::ShaderType,
-    ) -> Result<gfx::ShaderModuleInfo> {
+    ) -> Result<CompiledShader> {
         let fs = &self.inner.fs;
         let Some(file) = fs.get_file(VirtualPath::root(), VirtualPath::new(path))? else {
             anyhow::bail!("file not found: {path}");
         };
+        self.includes.borrow_mut().reset(&file.absolute_path);
 
         let shader_type = match shader_type {
             gfx::ShaderType::Vertex => shaderc::ShaderKind::Vertex,
@@ -193,29 +249,260 @@ impl<'a> ShaderPreprocessorScope<'a> {
             gfx::ShaderType::Compute => shaderc::ShaderKind::Compute,
         };
 
-        let data = shader_compiler().compile_into_spirv(
+        let res = shader_compiler().compile_into_spirv(
             file.contents,
             shader_type,
             &file.absolute_path,
             entry,
             Some(&self.options),
-        )?;
+        );
+        let data = match res {
+            Ok(data) => data,
+            Err(shaderc::Error::CompilationError(_, messages)) => {
+                return Err(ShaderCompileError {
+                    path: file.absolute_path,
+                    diagnostics: self.make_diagnostics(&messages),
+                }
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        };
         if data.get_num_warnings() > 0 {
-            tracing::warn!(
-                ?shader_type,
-                path = file.absolute_path,
-                "{}",
-                data.get_warning_messages()
-            );
+            for warning in self.make_diagnostics(&data.get_warning_messages()) {
+                tracing::warn!(?shader_type, path = file.absolute_path, "{warning}");
+            }
         }
 
-        Ok(gfx::ShaderModuleInfo {
-            data: Box::from(data.as_binary()),
+        let includes = std::mem::take(&mut self.includes.borrow_mut().includes);
+        Ok(CompiledShader {
+            info: gfx::ShaderModuleInfo {
+                data: Box::from(data.as_binary()),
+                label: Some(file.absolute_path.clone()),
+            },
+            path: file.absolute_path,
+            includes,
         })
     }
+
+    /// Maps the compiler messages to the original files.
+    fn make_diagnostics(&self, messages: &str) -> Vec<ShaderDiagnostic> {
+        let fs = &self.inner.fs;
+        let includes = self.includes.borrow();
+
+        let mut diagnostics = ShaderDiagnostic::parse_all(messages);
+        for diagnostic in &mut diagnostics {
+            let Some(path) = &diagnostic.path else {
+                continue;
+            };
+            if let Ok(Some(file)) = fs.get_file(VirtualPath::root(), VirtualPath::new(path)) {
+                diagnostic.set_source(file.contents);
+            }
+            diagnostic.included_from = includes.chain(path);
+        }
+        diagnostics
+    }
+}
+
+struct CompiledShader {
+    info: gfx::ShaderModuleInfo,
+    /// Absolute path of the root shader.
+    path: String,
+    /// Absolute paths of all resolved includes.
+    includes: Vec<String>,
+}
+
+/// Collects includes of the shader which is being compiled.
+#[derive(Default)]
+struct IncludeTracker {
+    root: String,
+    includes: Vec<String>,
+    /// The first file which included each of the resolved includes.
+    included_from: FastHashMap<String, String>,
+}
+
+impl IncludeTracker {
+    fn reset(&mut self, root: &str) {
+        self.root.clear();
+        self.root.push_str(root);
+        self.includes.clear();
+        self.included_from.clear();
+    }
+
+    fn add(&mut self, path: &str, source: &str) {
+        if path == self.root || self.included_from.contains_key(path) {
+            return;
+        }
+        self.includes.push(path.to_owned());
+        self.included_from
+            .insert(path.to_owned(), source.to_owned());
+    }
+
+    /// Returns files which included `path`, up to the root shader.
+    fn chain(&self, path: &str) -> Vec<String> {
+        let mut res = Vec::new();
+        let mut current = self.included_from.get(path);
+        // NOTE: The chain length is limited in case of recursive includes
+        while let Some(path) = current {
+            if res.len() > self.included_from.len() {
+                break;
+            }
+            res.push(path.clone());
+            current = self.included_from.get(path);
+        }
+        res
+    }
 }
 
 fn shader_compiler() -> &'static shaderc::Compiler {
     static COMPILER: OnceCell<shaderc::Compiler> = OnceCell::new();
     COMPILER.get_or_init(|| shaderc::Compiler::new().expect("failed to create `shaderc` compiler"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(shaders: &ShaderPreprocessor, path: &str) -> Result<CompiledShader> {
+        let shader = shaders
+            .begin()
+            .compile_shader(path, "main", gfx::ShaderType::Compute)?;
+        shaders.record_dependencies(&shader.path, &shader.includes);
+        Ok(shader)
+    }
+
+    fn compute_shader(includes: &[&str]) -> String {
+        let mut res = "#version 450\n".to_owned();
+        for include in includes {
+            res += &format!("#include \"{include}\"\n");
+        }
+        res + "layout (local_size_x = 1) in;\nvoid main() {}\n"
+    }
+
+    #[test]
+    fn nested_includes_are_tracked() {
+        let mut shaders = ShaderPreprocessor::new();
+        shaders
+            .add_file("main.comp", compute_shader(&["lib/a.glsl"]))
+            .unwrap();
+        shaders.add_file("other.comp", compute_shader(&[])).unwrap();
+        shaders
+            .add_file("lib/a.glsl", "#include \"b.glsl\"\n")
+            .unwrap();
+        shaders
+            .add_file("lib/b.glsl", "#include \"../c.glsl\"\n")
+            .unwrap();
+        shaders.add_file("c.glsl", "// empty\n").unwrap();
+
+        let shader = compile(&shaders, "main.comp").unwrap();
+        assert_eq!(shader.path, "/main.comp");
+        assert_eq!(shader.includes, ["/lib/a.glsl", "/lib/b.glsl", "/c.glsl"]);
+        compile(&shaders, "other.comp").unwrap();
+
+        assert_eq!(shaders.invalidate("c.glsl"), ["/main.comp"]);
+        // Dependencies are forgotten until the shader is compiled again
+        assert!(shaders.invalidate("/lib/b.glsl").is_empty());
+
+        compile(&shaders, "main.comp").unwrap();
+        assert_eq!(shaders.invalidate("lib/b.glsl"), ["/main.comp"]);
+        assert_eq!(shaders.invalidate("other.comp"), ["/other.comp"]);
+    }
+
+    #[test]
+    fn diamond_includes_are_tracked_once() {
+        let mut shaders = ShaderPreprocessor::new();
+        shaders
+            .add_file("first.comp", compute_shader(&["left.glsl", "right.glsl"]))
+            .unwrap();
+        shaders
+            .add_file("second.comp", compute_shader(&["right.glsl"]))
+            .unwrap();
+        let guarded = |name: &str| {
+            format!("#ifndef {name}\n#define {name}\n#include \"common.glsl\"\n#endif\n")
+        };
+        shaders.add_file("left.glsl", guarded("LEFT")).unwrap();
+        shaders.add_file("right.glsl", guarded("RIGHT")).unwrap();
+        shaders
+            .add_file(
+                "common.glsl",
+                "#ifndef COMMON\n#define COMMON\nconst uint VALUE = 1;\n#endif\n",
+            )
+            .unwrap();
+
+        let shader = compile(&shaders, "first.comp").unwrap();
+        assert_eq!(
+            shader.includes,
+            ["/left.glsl", "/common.glsl", "/right.glsl"]
+        );
+        compile(&shaders, "second.comp").unwrap();
+
+        assert_eq!(shaders.invalidate("left.glsl"), ["/first.comp"]);
+        assert_eq!(shaders.invalidate("common.glsl"), ["/second.comp"]);
+    }
+
+    #[test]
+    fn missing_include_error_contains_chain() {
+        let mut shaders = ShaderPreprocessor::new();
+        shaders
+            .add_file("main.comp", compute_shader(&["a.glsl"]))
+            .unwrap();
+        shaders.add_file("a.glsl", "#include \"b.glsl\"\n").unwrap();
+        shaders
+            .add_file("b.glsl", "#include \"missing.glsl\"\n")
+            .unwrap();
+
+        let err = compile(&shaders, "main.comp").err().unwrap();
+        let err = err.downcast_ref::<ShaderCompileError>().unwrap();
+        assert_eq!(err.path, "/main.comp");
+        assert_eq!(err.error_count(), 1);
+
+        let diagnostic = &err.diagnostics[0];
+        assert!(diagnostic.message.contains("file not found: missing.glsl"));
+        assert_eq!(diagnostic.path.as_deref(), Some("/b.glsl"));
+        assert_eq!(diagnostic.line, Some(1));
+        assert_eq!(diagnostic.included_from, ["/a.glsl", "/main.comp"]);
+
+        let err = err.to_string();
+        let chain = [
+            "--> /b.glsl:1",
+            "included from /a.glsl",
+            "included from /main.comp",
+        ]
+        .map(|line| {
+            err.find(line)
+                .unwrap_or_else(|| panic!("{line} is not in the error: {err}"))
+        });
+        assert!(chain.windows(2).all(|w| w[0] < w[1]), "{err}");
+
+        // Failed shaders don't have dependencies
+        assert!(shaders.invalidate("a.glsl").is_empty());
+    }
+
+    #[test]
+    fn compile_errors_point_to_included_files() {
+        let mut shaders = ShaderPreprocessor::new();
+        shaders
+            .add_file("main.comp", compute_shader(&["lib/a.glsl"]))
+            .unwrap();
+        shaders
+            .add_file(
+                "lib/a.glsl",
+                "float first() {\n\treturn 1.0;\n}\n\n\
+                 float second() {\n\treturn missing_value;\n}\n",
+            )
+            .unwrap();
+
+        let err = compile(&shaders, "main.comp").err().unwrap();
+        let err = err.downcast_ref::<ShaderCompileError>().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "\
+error: 'missing_value' : undeclared identifier
+ --> /lib/a.glsl:6
+  |
+6 |     return missing_value;
+  |            ^^^^^^^^^^^^^
+  = note: included from /main.comp
+error: could not compile `/main.comp` due to 1 previous error"
+        );
+    }
+}
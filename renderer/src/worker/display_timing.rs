@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+/// Display timing of the frames presented since the previous update.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayTimingUpdate {
+    /// Number of frames which were shown on the display.
+    pub displayed_frames: u32,
+    /// Present-to-display latency of the last shown frame.
+    pub latency: Option<Duration>,
+    /// Refresh cycles missed by the shown frames.
+    pub missed_vblanks: u32,
+}
+
+/// Matches past presentation timings of a swapchain with the presented frames.
+#[derive(Default)]
+pub struct DisplayTimingTracker {
+    last_present_id: u32,
+    /// Present id and actual present time of the last shown frame.
+    last_displayed: Option<(u32, u64)>,
+}
+
+impl DisplayTimingTracker {
+    /// Records the id of a presented image.
+    ///
+    /// Ids start over with each new swapchain, so earlier timings are forgotten.
+    pub fn on_present(&mut self, present_id: u32) {
+        if present_id <= self.last_present_id {
+            self.last_displayed = None;
+        }
+        self.last_present_id = present_id;
+    }
+
+    /// Computes the latency and missed refresh cycles of the shown frames.
+    ///
+    /// Each frame is expected to be shown for `cycles_per_frame` refresh cycles,
+    /// the cycles past that are counted as missed.
+    pub fn update(
+        &mut self,
+        timings: &[gfx::PastPresentationTiming],
+        refresh_duration: Duration,
+        cycles_per_frame: u32,
+    ) -> DisplayTimingUpdate {
+        let refresh_nanos = refresh_duration.as_nanos() as u64;
+
+        let mut update = DisplayTimingUpdate::default();
+        for timing in timings {
+            // NOTE: Ignores ids which were not presented to the current swapchain
+            if timing.present_id > self.last_present_id {
+                continue;
+            }
+
+            if let Some((prev_id, prev_time)) = self.last_displayed {
+                if timing.present_id <= prev_id {
+                    continue;
+                }
+                let elapsed = timing.actual_present_time.saturating_sub(prev_time);
+                if let Some(cycles) = (elapsed + refresh_nanos / 2).checked_div(refresh_nanos) {
+                    let expected = (timing.present_id - prev_id) as u64 * cycles_per_frame as u64;
+                    update.missed_vblanks += cycles.saturating_sub(expected) as u32;
+                }
+            }
+
+            // NOTE: The present was processed at the earliest present time minus
+            // the margin, the frame could be shown no earlier than that.
+            let processed_at = timing
+                .earliest_present_time
+                .saturating_sub(timing.present_margin.as_nanos() as u64);
+            update.latency = Some(Duration::from_nanos(
+                timing.actual_present_time.saturating_sub(processed_at),
+            ));
+            update.displayed_frames += 1;
+            self.last_displayed = Some((timing.present_id, timing.actual_present_time));
+        }
+        update
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFRESH_NANOS: u64 = 16_666_667;
+
+    #[test]
+    fn counts_missed_refresh_cycles() {
+        let refresh_duration = Duration::from_nanos(REFRESH_NANOS);
+        let mut tracker = DisplayTimingTracker::default();
+        for present_id in 1..=4 {
+            tracker.on_present(present_id);
+        }
+
+        // Frames 1 and 2 are shown on consecutive cycles, frame 3 is late by a cycle
+        let timings = [
+            timing(1, 100 * REFRESH_NANOS),
+            timing(2, 101 * REFRESH_NANOS + 1000),
+            timing(3, 103 * REFRESH_NANOS),
+        ];
+        let update = tracker.update(&timings, refresh_duration, 1);
+        assert_eq!(update.displayed_frames, 3);
+        assert_eq!(update.missed_vblanks, 1);
+        assert_eq!(update.latency, Some(Duration::from_millis(2)));
+
+        // Frame 4 isn't late when frames are paced to every other cycle
+        let update = tracker.update(&[timing(4, 105 * REFRESH_NANOS)], refresh_duration, 2);
+        assert_eq!(update.missed_vblanks, 0);
+
+        // Stale timings are ignored
+        let update = tracker.update(&timings, refresh_duration, 1);
+        assert_eq!(update, DisplayTimingUpdate::default());
+    }
+
+    #[test]
+    fn recreated_swapchains_start_over() {
+        let refresh_duration = Duration::from_nanos(REFRESH_NANOS);
+        let mut tracker = DisplayTimingTracker::default();
+        for present_id in 1..=10 {
+            tracker.on_present(present_id);
+        }
+        tracker.update(&[timing(10, 100 * REFRESH_NANOS)], refresh_duration, 1);
+
+        // The gap caused by the recreation is not counted as missed cycles
+        tracker.on_present(1);
+        tracker.on_present(2);
+        let timings = [
+            timing(1, 200 * REFRESH_NANOS),
+            timing(2, 201 * REFRESH_NANOS),
+        ];
+        let update = tracker.update(&timings, refresh_duration, 1);
+        assert_eq!(update.displayed_frames, 2);
+        assert_eq!(update.missed_vblanks, 0);
+    }
+
+    fn timing(present_id: u32, actual_present_time: u64) -> gfx::PastPresentationTiming {
+        // Processed 2ms before the display
+        gfx::PastPresentationTiming {
+            present_id,
+            actual_present_time,
+            earliest_present_time: actual_present_time - 500_000,
+            present_margin: Duration::from_micros(1500),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::time::{Duration, Instant};
+
+use crate::util::LoopBarrier;
+
+/// Returns the minimal interval between frames, `None` if they are not limited.
+///
+/// With a known `refresh_duration` the interval is rounded up to whole
+/// refresh cycles, so each frame is shown for the same number of cycles.
+pub fn frame_interval(fps: Option<u32>, refresh_duration: Option<Duration>) -> Option<Duration> {
+    let interval = fps.map(|fps| Duration::from_secs(1) / fps.max(1));
+    match refresh_duration {
+        Some(refresh_duration) if !refresh_duration.is_zero() => {
+            // NOTE: Tolerates rounding of the durations, e.g. 30 FPS at 60 Hz
+            let cycles = interval.map_or(1.0, |interval| {
+                (interval.as_secs_f64() / refresh_duration.as_secs_f64() - 1e-3)
+                    .ceil()
+                    .max(1.0)
+            });
+            Some(refresh_duration * cycles as u32)
+        }
+        _ => interval,
+    }
+}
+
+/// Returns the number of whole refresh cycles in the frame `interval`.
+pub fn refresh_cycles(interval: Duration, refresh_duration: Duration) -> u32 {
+    if refresh_duration.is_zero() {
+        return 1;
+    }
+    (interval.as_secs_f64() / refresh_duration.as_secs_f64())
+        .round()
+        .max(1.0) as u32
+}
+
+/// Shows display paced frames for more refresh cycles while they keep
+/// missing them, so they are presented evenly at a lower frame rate.
+#[derive(Default)]
+pub struct AdaptivePacing {
+    extra_cycles: u32,
+    window_frames: u32,
+    window_missed_vblanks: u32,
+    clean_windows: u32,
+}
+
+impl AdaptivePacing {
+    /// Refresh cycles added to the paced frame interval.
+    pub fn extra_cycles(&self) -> u32 {
+        self.extra_cycles
+    }
+
+    /// Records the shown frames and the refresh cycles they missed.
+    pub fn record(&mut self, displayed_frames: u32, missed_vblanks: u32) {
+        self.window_frames += displayed_frames;
+        self.window_missed_vblanks += missed_vblanks;
+        if self.window_frames < PACING_WINDOW_FRAMES {
+            return;
+        }
+
+        if self.window_missed_vblanks * PACING_MISS_RATIO > self.window_frames {
+            self.extra_cycles = (self.extra_cycles + 1).min(MAX_EXTRA_CYCLES);
+            self.clean_windows = 0;
+        } else if self.window_missed_vblanks == 0 && self.extra_cycles > 0 {
+            // NOTE: Goes back slowly, so the rate doesn't flip every window
+            self.clean_windows += 1;
+            if self.clean_windows >= PACING_CLEAN_WINDOWS {
+                self.extra_cycles -= 1;
+                self.clean_windows = 0;
+            }
+        } else {
+            self.clean_windows = 0;
+        }
+        self.window_frames = 0;
+        self.window_missed_vblanks = 0;
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Number of shown frames after which the pacing is adjusted.
+const PACING_WINDOW_FRAMES: u32 = 60;
+/// Pacing slows down if more than one in this many frames misses a cycle.
+const PACING_MISS_RATIO: u32 = 10;
+/// Number of windows without missed cycles before the pacing speeds up.
+const PACING_CLEAN_WINDOWS: u32 = 4;
+const MAX_EXTRA_CYCLES: u32 = 3;
+
+/// Waits until the `deadline` without oversleeping it.
+///
+/// Returns `false` if the loop was finished while waiting. Stopping the loop
+/// doesn't interrupt the wait, so the requested frame is still drawn.
+pub fn wait_until(barrier: &LoopBarrier, deadline: Instant) -> bool {
+    // NOTE: Sleeps are imprecise, so the last part is spent spinning
+    if let Some(sleep_deadline) = deadline.checked_sub(SPIN_DURATION) {
+        if !barrier.sleep_until(sleep_deadline) {
+            return false;
+        }
+    }
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+    true
+}
+
+const SPIN_DURATION: Duration = Duration::from_millis(1);
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::util::DrawRequestResult;
+
+    #[test]
+    fn limited_frames_are_drawn_after_stop() {
+        let deadline = Instant::now() + Duration::from_millis(20);
+
+        let barrier = Arc::new(LoopBarrier::default());
+        let worker = std::thread::spawn({
+            let barrier = barrier.clone();
+            move || {
+                let mut frames = Vec::new();
+                while barrier.begin_frame() {
+                    if wait_until(&barrier, deadline) {
+                        frames.push(Instant::now());
+                    }
+                    barrier.end_frame();
+                }
+                barrier.finish();
+                frames
+            }
+        });
+
+        assert_eq!(barrier.request_draw(), DrawRequestResult::Accepted);
+        std::thread::sleep(Duration::from_millis(5));
+        barrier.stop();
+
+        // The accepted frame is drawn once the limiter allows it
+        let frames = worker.join().unwrap();
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0] >= deadline);
+        assert!(barrier.is_stopped());
+    }
+
+    #[test]
+    fn finish_interrupts_the_wait() {
+        let barrier = Arc::new(LoopBarrier::default());
+        let waiter = std::thread::spawn({
+            let barrier = barrier.clone();
+            move || wait_until(&barrier, Instant::now() + Duration::from_secs(60))
+        });
+
+        std::thread::sleep(Duration::from_millis(5));
+        barrier.finish();
+        assert!(!waiter.join().unwrap());
+    }
+
+    #[test]
+    fn intervals_are_rounded_up_to_refresh_cycles() {
+        let refresh = Duration::from_nanos(1_000_000_000 / 60);
+        assert_eq!(frame_interval(None, None), None);
+        assert_eq!(
+            frame_interval(Some(50), None),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(frame_interval(None, Some(refresh)), Some(refresh));
+        assert_eq!(frame_interval(Some(144), Some(refresh)), Some(refresh));
+        assert_eq!(frame_interval(Some(30), Some(refresh)), Some(refresh * 2));
+        assert_eq!(frame_interval(Some(45), Some(refresh)), Some(refresh * 2));
+        assert_eq!(frame_interval(Some(0), None), Some(Duration::from_secs(1)));
+
+        assert_eq!(refresh_cycles(refresh * 2, refresh), 2);
+        assert_eq!(refresh_cycles(Duration::from_millis(1), refresh), 1);
+    }
+
+    #[test]
+    fn pacing_adapts_to_missed_cycles() {
+        let mut pacing = AdaptivePacing::default();
+
+        // Occasional misses are tolerated
+        pacing.record(PACING_WINDOW_FRAMES, 2);
+        assert_eq!(pacing.extra_cycles(), 0);
+
+        pacing.record(PACING_WINDOW_FRAMES / 2, PACING_WINDOW_FRAMES / 4);
+        assert_eq!(pacing.extra_cycles(), 0);
+        pacing.record(PACING_WINDOW_FRAMES / 2, 0);
+        assert_eq!(pacing.extra_cycles(), 1);
+
+        for _ in 0..10 {
+            pacing.record(PACING_WINDOW_FRAMES, PACING_WINDOW_FRAMES);
+        }
+        assert_eq!(pacing.extra_cycles(), MAX_EXTRA_CYCLES);
+
+        for _ in 1..PACING_CLEAN_WINDOWS {
+            pacing.record(PACING_WINDOW_FRAMES, 0);
+        }
+        assert_eq!(pacing.extra_cycles(), MAX_EXTRA_CYCLES);
+        pacing.record(PACING_WINDOW_FRAMES, 0);
+        assert_eq!(pacing.extra_cycles(), MAX_EXTRA_CYCLES - 1);
+
+        pacing.reset();
+        assert_eq!(pacing.extra_cycles(), 0);
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::Result;
+
+/// Measures GPU time of the frame passes using timestamp queries.
+///
+/// Each frame in flight owns a separate range of queries which is read back
+/// only after the fence of that frame was waited on.
+///
+/// Profiler does nothing if the device doesn't support timestamps on graphics queues.
+pub struct GpuProfiler {
+    queries: Option<TimestampQueries>,
+    current_slot: Option<usize>,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &gfx::Device, slot_count: usize) -> Result<Self> {
+        let limits = device.limits();
+        if limits.timestamp_compute_and_graphics == 0 {
+            tracing::warn!("timestamp queries are not supported, GPU profiling is disabled");
+            return Ok(Self {
+                queries: None,
+                current_slot: None,
+            });
+        }
+
+        let pool = device.create_query_pool(gfx::QueryPoolInfo {
+            ty: gfx::QueryType::Timestamp,
+            count: (slot_count * GpuTimestamp::COUNT) as u32,
+        })?;
+
+        Ok(Self {
+            queries: Some(TimestampQueries {
+                pool,
+                timestamp_period: limits.timestamp_period as f64,
+                pending: vec![None; slot_count].into_boxed_slice(),
+            }),
+            current_slot: None,
+        })
+    }
+
+    /// Reports timings previously written into the `slot` and starts a new frame.
+    ///
+    /// NOTE: Must be called after the fence associated with the `slot` was waited on.
+    pub fn begin_frame(
+        &mut self,
+        device: &gfx::Device,
+        encoder: &mut gfx::Encoder,
+        slot: usize,
+        frame: u32,
+        enabled: bool,
+    ) -> Result<(), gfx::DeviceLost> {
+        self.current_slot = None;
+
+        let Some(queries) = &mut self.queries else {
+            return Ok(());
+        };
+        let range = slot_queries(slot);
+
+        if let Some(completed_frame) = queries.pending[slot].take() {
+            let mut timestamps = [0u64; GpuTimestamp::COUNT];
+            // NOTE: Results might be unavailable if the frame was never submitted.
+            if device.get_query_results(&queries.pool, range.start, &mut timestamps)? {
+                queries.report(completed_frame, &timestamps);
+            }
+        }
+
+        if enabled {
+            encoder.reset_query_pool(&queries.pool, range);
+            queries.pending[slot] = Some(frame);
+            self.current_slot = Some(slot);
+            self.write_timestamp(encoder, GpuTimestamp::FrameStarted);
+        }
+
+        Ok(())
+    }
+
+    pub fn write_timestamp(&mut self, encoder: &mut gfx::Encoder, timestamp: GpuTimestamp) {
+        let (Some(queries), Some(slot)) = (&self.queries, self.current_slot) else {
+            return;
+        };
+
+        let stage = match timestamp {
+            GpuTimestamp::FrameStarted => gfx::PipelineStageFlags::TOP_OF_PIPE,
+            _ => gfx::PipelineStageFlags::BOTTOM_OF_PIPE,
+        };
+        let index = slot_queries(slot).start + timestamp as u32;
+        encoder.write_timestamp(stage, &queries.pool, index);
+    }
+}
+
+struct TimestampQueries {
+    pool: gfx::QueryPool,
+    /// Number of nanoseconds required for a timestamp query to be incremented by 1.
+    timestamp_period: f64,
+    /// Frames which wrote timestamps into the corresponding slot.
+    pending: Box<[Option<u32>]>,
+}
+
+impl TimestampQueries {
+    fn report(&self, frame: u32, timestamps: &[u64; GpuTimestamp::COUNT]) {
+        let to_ms = |from: GpuTimestamp, to: GpuTimestamp| {
+            let ticks = timestamps[to as usize].wrapping_sub(timestamps[from as usize]);
+            ticks as f64 * self.timestamp_period / 1_000_000.0
+        };
+
+        tracing::debug!(
+            frame,
+            eval_instructions_ms = to_ms(
+                GpuTimestamp::FrameStarted,
+                GpuTimestamp::InstructionsEvaluated
+            ),
+            main_pass_ms = to_ms(
+                GpuTimestamp::InstructionsEvaluated,
+                GpuTimestamp::MainPassFinished
+            ),
+            present_barrier_ms = to_ms(
+                GpuTimestamp::MainPassFinished,
+                GpuTimestamp::PresentBarrierFinished
+            ),
+            total_ms = to_ms(
+                GpuTimestamp::FrameStarted,
+                GpuTimestamp::PresentBarrierFinished
+            ),
+            "gpu frame timings"
+        );
+    }
+}
+
+fn slot_queries(slot: usize) -> std::ops::Range<u32> {
+    let start = (slot * GpuTimestamp::COUNT) as u32;
+    start..start + GpuTimestamp::COUNT as u32
+}
+
+/// Points of the frame at which timestamps are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum GpuTimestamp {
+    FrameStarted,
+    InstructionsEvaluated,
+    MainPassFinished,
+    PresentBarrierFinished,
+}
+
+impl GpuTimestamp {
+    const COUNT: usize = 4;
+}
@@ -1,175 +1,684 @@
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use bumpalo::Bump;
+use glam::UVec2;
 use shared::util::DeallocOnDrop;
 
+#[cfg(test)]
+pub(crate) use self::offscreen::capture;
+pub use self::offscreen::OffscreenTarget;
+
+use self::display_timing::DisplayTimingTracker;
+use self::frame_limiter::{frame_interval, refresh_cycles, wait_until, AdaptivePacing};
+use self::gpu_profiler::{GpuProfiler, GpuTimestamp};
+use crate::managers::MeshManager;
 use crate::render_graph::{RenderGraph, RenderGraphContext};
-use crate::RendererState;
+use crate::util::ShaderCompileError;
+use crate::{FrameStats, RendererEvent, RendererState};
+
+mod display_timing;
+mod frame_limiter;
+mod gpu_profiler;
+mod offscreen;
 
 pub struct RendererWorker {
     state: Arc<RendererState>,
 
     graph: RenderGraph,
     fences: Fences,
-    surface: gfx::Surface,
+    surface: Option<gfx::Surface>,
+    offscreen: Option<OffscreenTarget>,
+    gpu_profiler: GpuProfiler,
+    display_timing: DisplayTimingTracker,
+    adaptive_pacing: AdaptivePacing,
 
     alloc: Bump,
     non_optimal_count: usize,
     prev_frame_at: Instant,
+    render_resolution: Option<UVec2>,
+    swapchain_recreations: u32,
     frame: u32,
 }
 
+/// Destination of the rendered frames.
+pub enum FrameOutput {
+    Surface(gfx::Surface),
+    /// Frames are rendered without presentation.
+    Offscreen(OffscreenTarget),
+}
+
 impl RendererWorker {
-    pub fn new(state: Arc<RendererState>, surface: gfx::Surface) -> Result<Self> {
+    pub fn new(state: Arc<RendererState>, output: FrameOutput) -> Result<Self> {
         const FRAMES_IN_FLIGHT: usize = 2;
 
-        let fences = Fences::new(&state.device, FRAMES_IN_FLIGHT)?;
+        let fences = Fences::new(
+            &state.device,
+            FRAMES_IN_FLIGHT,
+            state.transfer_queue.is_some(),
+        )?;
 
         let graph = RenderGraph::new(&state)?;
 
+        let gpu_profiler = GpuProfiler::new(&state.device, FRAMES_IN_FLIGHT)?;
+
+        let (surface, offscreen) = match output {
+            FrameOutput::Surface(surface) => (Some(surface), None),
+            FrameOutput::Offscreen(offscreen) => (None, Some(offscreen)),
+        };
+
         Ok(Self {
             state,
             graph,
             fences,
             surface,
+            offscreen,
+            gpu_profiler,
+            display_timing: DisplayTimingTracker::default(),
+            adaptive_pacing: AdaptivePacing::default(),
             non_optimal_count: 0,
             alloc: Bump::default(),
             prev_frame_at: Instant::now(),
+            render_resolution: None,
+            swapchain_recreations: 0,
             frame: 0,
         })
     }
 
     pub fn draw(&mut self) -> Result<()> {
+        // NOTE: Headless frames are paced by the caller
+        if self.state.is_paused() && self.surface.is_some() {
+            let deadline = self.prev_frame_at + PAUSED_FRAME_INTERVAL;
+            if !self.state.worker_barrier.sleep_while_paused(deadline) {
+                return Ok(());
+            }
+        }
+
+        let paused = self.state.is_paused();
+        if !paused {
+            if self.state.take_frame_timer_reset_request() {
+                // NOTE: The frame after resuming is drawn right away,
+                // and its delta time doesn't include the pause.
+                if self.surface.is_some() {
+                    self.prev_frame_at = Instant::now();
+                }
+            } else if !self.limit_frame_rate()? {
+                return Ok(());
+            }
+        }
+
+        let res = self.draw_frame(paused);
+
+        // NOTE: Failed frames are finished too to keep profiler frames
+        // in sync with the renderer ones.
+        profile_finish_frame!();
+        res
+    }
+
+    /// Waits until the frame rate limit allows the next frame.
+    ///
+    /// Returns `false` if the rendering loop was finished while waiting.
+    fn limit_frame_rate(&self) -> Result<bool> {
+        // NOTE: Headless frames are paced by the caller
+        let Some(surface) = &self.surface else {
+            return Ok(true);
+        };
+
+        let refresh_duration = if self.state.is_display_paced() {
+            surface.refresh_duration()?
+        } else {
+            None
+        };
+        let Some(mut interval) = frame_interval(self.state.frame_rate_limit(), refresh_duration)
+        else {
+            return Ok(true);
+        };
+        if let Some(refresh_duration) = refresh_duration {
+            interval += refresh_duration * self.adaptive_pacing.extra_cycles();
+        }
+
+        profile_scope!("frame_rate_limit");
+        Ok(wait_until(
+            &self.state.worker_barrier,
+            self.prev_frame_at + interval,
+        ))
+    }
+
+    /// Draws the next frame, paused frames only evaluate instructions.
+    fn draw_frame(&mut self, paused: bool) -> Result<()> {
         let device = &self.state.device;
         let queue = &self.state.queue;
 
-        let fence = {
-            profiling::scope!("idle");
-            self.fences.wait_next(device)?
+        let FrameSync {
+            slot,
+            fence,
+            transfer,
+            completed_frame,
+        } = {
+            profile_scope!("idle");
+            self.fences.wait_next(&self.state, self.frame)?
         };
-        profiling::scope!("frame");
+        profile_scope!("frame", &self.frame.to_string());
+
+        // NOTE: Resources dropped during the previous frames could still be
+        // used by the submitted work until its fences are signalled.
+        device.flush_destroys();
 
-        let mut surface_image = {
-            profiling::scope!("aquire_image");
-            self.surface.aquire_image()?
+        if let Some(transfer_queue) = &self.state.transfer_queue {
+            transfer_queue.restore_command_buffers()?;
+        }
+
+        if self.state.take_shaders_reload_request() {
+            profile_scope!("reload_shaders");
+            reload_shaders(&self.state, &mut self.graph);
+        }
+
+        for node in self.state.take_compute_nodes() {
+            self.graph.add_compute_node(node);
+        }
+
+        // NOTE: Surface requests are kept until the renderer is resumed
+        let (recreate_surface, resize_surface) = if paused {
+            (false, false)
+        } else {
+            (
+                self.state.take_surface_recreation_request(),
+                self.state.take_surface_resize_request(),
+            )
         };
+        let mut surface_image = match &mut self.surface {
+            Some(surface) if !paused => {
+                if recreate_surface {
+                    profile_scope!("recreate_surface");
+
+                    // Wait for the device to be idle before replacing the surface.
+                    device.wait_idle()?;
+                    surface.recreate()?;
+                    self.swapchain_recreations += 1;
+                } else if resize_surface {
+                    profile_scope!("resize_surface");
+
+                    // Wait for the device to be idle before recreating the swapchain.
+                    device.wait_idle()?;
+                    surface.update()?;
+                    self.non_optimal_count = 0;
+                    self.swapchain_recreations += 1;
+                }
 
-        let mut encoder = queue.create_primary_encoder()?;
+                if surface.is_configured() {
+                    profile_scope!("aquire_image");
+                    match surface.aquire_image() {
+                        Ok(image) => Some(image),
+                        // NOTE: The window could be minimized while reconfiguring
+                        // an out of date swapchain.
+                        Err(gfx::SurfaceError::NotConfigured) => None,
+                        Err(e) => return Err(e.into()),
+                    }
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if surface_image.is_none() {
+            // NOTE: There is no presentation to recycle command buffers
+            queue.restore_command_buffers()?;
+        }
 
-        let synced_managers = {
-            profiling::scope!("eval_instructions");
-            self.state.eval_instructions(&mut encoder)?
+        // NOTE: There is no target while the window has a zero size or while
+        // paused, so only instructions are evaluated to keep the queues from growing.
+        let target = match (&surface_image, &self.offscreen) {
+            (Some(image), _) => Some(image.image().clone()),
+            (None, Some(offscreen)) if !paused => Some(offscreen.image().clone()),
+            (None, _) => None,
         };
 
-        let prev_frame_at = std::mem::replace(&mut self.prev_frame_at, Instant::now());
-        let delta_time = self
-            .prev_frame_at
-            .duration_since(prev_frame_at)
-            .as_secs_f32();
-
-        self.graph.execute(&mut RenderGraphContext {
-            state: &self.state,
-            synced_managers: &synced_managers,
-            surface_image: &surface_image,
-            encoder: &mut encoder,
-            now: self.prev_frame_at,
-            delta_time,
+        if let Some(target) = &target {
+            let render_resolution = UVec2::from(target.info().extent);
+            if matches!(
+                self.render_resolution.replace(render_resolution),
+                Some(prev) if prev != render_resolution
+            ) {
+                self.state.push_event(RendererEvent::Resized {
+                    width: render_resolution.x,
+                    height: render_resolution.y,
+                });
+            }
+        }
+
+        let mut encoder = queue.create_primary_encoder()?;
+
+        self.gpu_profiler.begin_frame(
+            device,
+            &mut encoder,
+            slot,
+            self.frame,
+            self.state.is_gpu_profiling_enabled(),
+        )?;
+
+        let mut stats = FrameStats {
             frame: self.frame,
-        })?;
+            ..Default::default()
+        };
+
+        let uploads = {
+            profile_scope!("eval_instructions");
+            let started_at = Instant::now();
+            let res = self.state.eval_instructions(
+                &mut encoder,
+                self.frame,
+                completed_frame,
+                &mut stats.instructions,
+            )?;
+            stats.eval_instructions_time = started_at.elapsed();
+            res
+        };
+        let mut synced_managers = self.state.synced_managers.lock().unwrap();
+        synced_managers.fill_frame_stats(&mut stats);
+        self.gpu_profiler
+            .write_timestamp(&mut encoder, GpuTimestamp::InstructionsEvaluated);
+
+        let mut uploads_semaphore = None;
+        if let Some(command_buffer) = uploads.transfer {
+            profile_scope!("transfer_submit");
+
+            let (transfer_queue, transfer) = self
+                .state
+                .transfer_queue
+                .as_ref()
+                .zip(transfer)
+                .expect("transfer uploads require a transfer queue");
+
+            transfer_queue.submit(
+                &mut [],
+                Some(command_buffer),
+                &mut [&mut transfer.semaphore],
+                Some(&mut transfer.fence),
+                &mut DeallocOnDrop(&mut self.alloc),
+            )?;
+            uploads_semaphore = Some(&mut transfer.semaphore);
+        }
+
+        // NOTE: Headless frames advance by a fixed step to be reproducible
+        let prev_frame_at = self.prev_frame_at;
+        self.prev_frame_at = match &self.surface {
+            Some(_) => Instant::now(),
+            None => prev_frame_at + HEADLESS_FRAME_DURATION,
+        };
+        stats.delta_time = self.prev_frame_at.duration_since(prev_frame_at);
+        let delta_time = stats.delta_time.as_secs_f32();
+
+        let interpolation_factor = synced_managers
+            .time_manager
+            .compute_interpolation_factor(self.prev_frame_at);
+
+        if let Some(target) = &target {
+            self.graph.execute(&mut RenderGraphContext {
+                state: &self.state,
+                synced_managers: &synced_managers,
+                target,
+                encoder: &mut encoder,
+                now: self.prev_frame_at,
+                delta_time,
+                frame: self.frame,
+                completed_frame,
+                interpolation_factor,
+                alloc: &self.alloc,
+            })?;
+            stats.draw_calls = self.state.frame_draw_calls.load(Ordering::Relaxed);
+            stats.drawn_instances = self.state.frame_drawn_instances.load(Ordering::Relaxed);
+        }
         drop(synced_managers);
+        self.gpu_profiler
+            .write_timestamp(&mut encoder, GpuTimestamp::MainPassFinished);
+
+        let mut capture = None;
+        match (&target, &self.offscreen) {
+            (Some(_), Some(offscreen)) if surface_image.is_none() => {
+                if self.state.take_frame_capture_request() {
+                    offscreen.encode_capture(&mut encoder);
+                    capture = Some(offscreen);
+                }
+            }
+            (Some(target), _) => encoder.transition_image(
+                target,
+                gfx::ImageLayout::Present,
+                gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    ..gfx::PipelineStageFlags::BOTTOM_OF_PIPE,
+                gfx::AccessFlags::COLOR_ATTACHMENT_WRITE..gfx::AccessFlags::empty(),
+            ),
+            (None, _) => {}
+        }
+        self.gpu_profiler
+            .write_timestamp(&mut encoder, GpuTimestamp::PresentBarrierFinished);
+
+        let mut wait = Vec::new();
+        let mut signal = Vec::new();
+        if let Some(surface_image) = &mut surface_image {
+            let [image_wait, image_signal] = surface_image.wait_signal();
+            wait.push((gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, image_wait));
+            signal.push(image_signal);
+        }
+        if let Some(semaphore) = uploads_semaphore {
+            wait.push((MeshManager::TRANSFER_UPLOADS_WAIT_STAGES, semaphore));
+        }
 
-        encoder.image_barriers(
-            gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            gfx::PipelineStageFlags::BOTTOM_OF_PIPE,
-            &[gfx::ImageMemoryBarrier {
-                image: surface_image.image(),
-                src_access: gfx::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                dst_access: gfx::AccessFlags::empty(),
-                old_layout: Some(gfx::ImageLayout::ColorAttachmentOptimal),
-                new_layout: gfx::ImageLayout::Present,
-                family_transfer: None,
-                subresource_range: gfx::ImageSubresourceRange::whole(surface_image.image().info()),
-            }],
-        );
-
-        let [wait, signal] = surface_image.wait_signal();
+        // NOTE: Uploads and frame commands are submitted as a single batch
+        let mut command_buffers = uploads.graphics;
+        command_buffers.push(encoder.finish()?);
 
         {
-            profiling::scope!("queue_submit");
+            profile_scope!("queue_submit");
             queue.submit(
-                &mut [(gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, wait)],
-                Some(encoder.finish()?),
-                &mut [signal],
-                Some(fence),
+                &mut wait,
+                command_buffers,
+                &mut signal,
+                Some(&mut *fence),
                 &mut DeallocOnDrop(&mut self.alloc),
             )?;
         }
 
-        let mut is_optimal = surface_image.is_optimal();
-        {
-            profiling::scope!("queue_present");
-
-            self.state.window.pre_present_notify();
-            match queue.present(surface_image)? {
-                gfx::PresentStatus::Ok => {}
-                gfx::PresentStatus::Suboptimal => is_optimal = false,
-                gfx::PresentStatus::OutOfDate => {
-                    is_optimal = false;
-                    self.non_optimal_count += NON_OPTIMAL_LIMIT;
+        if let Some(offscreen) = capture {
+            profile_scope!("frame_capture");
+
+            // NOTE: The fence is reset when the slot is reused
+            wait_fence(&self.state, fence, self.frame)?;
+            let frame = offscreen.read_capture(device)?;
+            self.state.set_captured_frame(frame);
+        }
+
+        if let Some(surface_image) = surface_image {
+            let mut is_optimal = surface_image.is_optimal();
+            {
+                profile_scope!("queue_present");
+
+                if let Some(window) = self.state.window() {
+                    window.pre_present_notify();
+                }
+                self.display_timing.on_present(surface_image.present_id());
+                let present_status = queue.present(surface_image)?;
+                stats.present_status = Some(present_status);
+                match present_status {
+                    gfx::PresentStatus::Ok => {}
+                    gfx::PresentStatus::Suboptimal => is_optimal = false,
+                    gfx::PresentStatus::OutOfDate => {
+                        is_optimal = false;
+                        self.non_optimal_count += NON_OPTIMAL_LIMIT;
+                    }
                 }
             }
-        }
 
-        self.non_optimal_count += !is_optimal as usize;
-        if self.non_optimal_count >= NON_OPTIMAL_LIMIT {
-            profiling::scope!("recreate_swapchain");
+            let present_mode = self.state.take_present_mode_request();
+            if present_mode.is_some() {
+                // Force the swapchain to be recreated with the new present mode.
+                self.non_optimal_count += NON_OPTIMAL_LIMIT;
+            }
+
+            self.non_optimal_count += !is_optimal as usize;
+            if self.non_optimal_count >= NON_OPTIMAL_LIMIT {
+                profile_scope!("recreate_swapchain");
 
-            // Wait for the device to be idle before recreating the swapchain.
-            device.wait_idle()?;
+                let surface = self.surface.as_mut().expect("presented without a surface");
 
-            self.surface.update()?;
-            self.non_optimal_count = 0;
+                // Wait for the device to be idle before recreating the swapchain.
+                device.wait_idle()?;
+
+                match present_mode {
+                    Some(mode) => {
+                        let mode = crate::select_present_mode(surface, mode);
+                        surface.set_present_mode(mode)?;
+                    }
+                    None => surface.update()?,
+                }
+                self.non_optimal_count = 0;
+                self.swapchain_recreations += 1;
+            }
         }
 
-        profiling::finish_frame!();
+        self.update_display_timing(&mut stats)?;
+
+        stats.meshes = self.state.mesh_manager.mesh_count() as u32;
+        stats.buffer_arena_bytes = self.state.multi_buffer_arena.allocated_bytes();
+        stats.swapchain_recreations = self.swapchain_recreations;
+        self.state.publish_frame_stats(stats);
+
         self.frame += 1;
         Ok(())
     }
+
+    /// Collects timings of the shown frames and adapts the display pacing to them.
+    fn update_display_timing(&mut self, stats: &mut FrameStats) -> Result<()> {
+        let Some(surface) = &self.surface else {
+            return Ok(());
+        };
+        // NOTE: The refresh duration is unknown without display timing
+        let Some(refresh_duration) = surface.refresh_duration()? else {
+            return Ok(());
+        };
+        let timings = self.state.queue.get_past_presentation_timing(surface)?;
+
+        let display_paced = self.state.is_display_paced();
+        let extra_cycles = if display_paced {
+            self.adaptive_pacing.extra_cycles()
+        } else {
+            0
+        };
+        let interval = frame_interval(self.state.frame_rate_limit(), Some(refresh_duration))
+            .unwrap_or(refresh_duration);
+        let cycles_per_frame = refresh_cycles(interval, refresh_duration) + extra_cycles;
+
+        let update = self
+            .display_timing
+            .update(&timings, refresh_duration, cycles_per_frame);
+        stats.display_latency = update.latency;
+        stats.missed_vblanks = update.missed_vblanks;
+
+        if display_paced {
+            self.adaptive_pacing
+                .record(update.displayed_frames, update.missed_vblanks);
+        } else {
+            self.adaptive_pacing.reset();
+        }
+        Ok(())
+    }
+}
+
+fn reload_shaders(state: &RendererState, graph: &mut RenderGraph) {
+    let res = state
+        .reload_shader_sources()
+        .and_then(|_| graph.reload_shaders(state));
+
+    // NOTE: Pipelines of the failed shaders are kept
+    match res {
+        Ok(()) => tracing::info!("shaders reloaded"),
+        Err(e) => match e.downcast_ref::<ShaderCompileError>() {
+            Some(compile_error) => {
+                tracing::error!("failed to reload shaders: {e}\n{compile_error}")
+            }
+            None => tracing::error!("failed to reload shaders: {e:?}"),
+        },
+    }
 }
 
 struct Fences {
     fences: Box<[gfx::Fence]>,
+    transfer: Box<[TransferSync]>,
+    frames: Box<[Option<u32>]>,
     fence_index: usize,
 }
 
 impl Fences {
-    fn new(device: &gfx::Device, count: usize) -> Result<Self, gfx::OutOfDeviceMemory> {
+    fn new(
+        device: &gfx::Device,
+        count: usize,
+        with_transfer: bool,
+    ) -> Result<Self, gfx::OutOfDeviceMemory> {
         assert!(count > 0, "frames in flight must be greater than 0");
 
         let fences = (0..count)
             .map(|_| device.create_fence())
             .collect::<Result<Box<[_]>, _>>()?;
 
+        let transfer = (0..if with_transfer { count } else { 0 })
+            .map(|_| {
+                Ok(TransferSync {
+                    fence: device.create_fence()?,
+                    semaphore: device.create_semaphore()?,
+                })
+            })
+            .collect::<Result<Box<[_]>, gfx::OutOfDeviceMemory>>()?;
+
         Ok(Self {
+            frames: vec![None; fences.len()].into_boxed_slice(),
             fences,
+            transfer,
             fence_index: 0,
         })
     }
 
-    fn wait_next(&mut self, device: &gfx::Device) -> Result<&mut gfx::Fence, gfx::DeviceLost> {
+    /// Waits for the next fence and assigns it to the specified `frame`.
+    ///
+    /// Returns the frame sync primitives and the last frame which is known to be completed.
+    fn wait_next(&mut self, state: &RendererState, frame: u32) -> Result<FrameSync<'_>> {
+        let device = &state.device;
         let fence_count = self.fences.len();
-        let fence = &mut self.fences[self.fence_index];
+        let slot = self.fence_index;
+        let fence = &mut self.fences[slot];
+        let mut transfer = self.transfer.get_mut(slot);
+        let completed_frame = self.frames[slot].replace(frame);
         self.fence_index = (self.fence_index + 1) % fence_count;
 
+        // NOTE: Fences of the slot were submitted with the completed frame
+        let submitted_frame = completed_frame.unwrap_or(frame);
+
         if !fence.state().is_unsignalled() {
-            device.wait_fences(&mut [fence], true)?;
+            wait_fence(state, fence, submitted_frame)?;
             device.reset_fences(&mut [fence])?;
         }
 
-        Ok(fence)
+        if let Some(transfer) = &mut transfer {
+            let fence = &mut transfer.fence;
+            if !fence.state().is_unsignalled() {
+                wait_fence(state, fence, submitted_frame)?;
+                device.reset_fences(&mut [fence])?;
+            }
+        }
+
+        Ok(FrameSync {
+            slot,
+            fence,
+            transfer,
+            completed_frame,
+        })
+    }
+}
+
+/// Waits for the `fence` submitted with the specified `frame`.
+///
+/// Logs a warning each time the wait exceeds [`FENCE_WAIT_TIMEOUT`] and fails
+/// with [`FrameTimeout`] after [`MAX_FENCE_TIMEOUTS`] consecutive timeouts.
+fn wait_fence(state: &RendererState, fence: &mut gfx::Fence, frame: u32) -> Result<()> {
+    let device = &state.device;
+    for timeouts in 1..=MAX_FENCE_TIMEOUTS {
+        let status = device.wait_fences(&mut [&mut *fence], true, Some(FENCE_WAIT_TIMEOUT))?;
+        match status {
+            gfx::FenceWaitStatus::Signalled => return Ok(()),
+            gfx::FenceWaitStatus::Timeout => {
+                let queue = match fence.state() {
+                    gfx::FenceState::Armed { queue_id, .. } => Some(queue_id),
+                    _ => None,
+                };
+                tracing::warn!(
+                    frame,
+                    ?queue,
+                    pending_instructions = state.pending_instruction_count(),
+                    "fence is still not signalled after {:?}",
+                    FENCE_WAIT_TIMEOUT * timeouts,
+                );
+            }
+        }
+    }
+
+    Err(FrameTimeout {
+        frame,
+        elapsed: FENCE_WAIT_TIMEOUT * MAX_FENCE_TIMEOUTS,
     }
+    .into())
+}
+
+/// The GPU didn't signal the fence of the frame in time.
+#[derive(Debug, thiserror::Error)]
+#[error("frame {frame} was not completed in {elapsed:?}")]
+pub(crate) struct FrameTimeout {
+    pub frame: u32,
+    pub elapsed: Duration,
+}
+
+struct FrameSync<'a> {
+    /// Index of the frame in flight.
+    slot: usize,
+    fence: &'a mut gfx::Fence,
+    transfer: Option<&'a mut TransferSync>,
+    completed_frame: Option<u32>,
+}
+
+/// Sync primitives for uploads submitted to the dedicated transfer queue.
+struct TransferSync {
+    fence: gfx::Fence,
+    semaphore: gfx::Semaphore,
 }
 
 const NON_OPTIMAL_LIMIT: usize = 100;
+
+const FENCE_WAIT_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_FENCE_TIMEOUTS: u32 = 5;
+
+const HEADLESS_FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// Minimal interval between the frames of the paused renderer.
+const PAUSED_FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RendererBuilder;
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn resumed_frames_reset_the_frame_timer() {
+        let renderer = RendererBuilder::headless(4, 4).build().unwrap();
+        let state = renderer.state();
+
+        let draw_next_frame = || {
+            let prev = state.last_frame_stats();
+            state.notify_draw();
+            let started_at = Instant::now();
+            loop {
+                let stats = state.last_frame_stats();
+                if stats != prev {
+                    return stats;
+                }
+                assert!(
+                    started_at.elapsed() < Duration::from_secs(10),
+                    "frame is still not drawn"
+                );
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        };
+
+        draw_next_frame();
+        state.set_paused(true);
+        for _ in 0..3 {
+            let stats = draw_next_frame();
+            assert_eq!(stats.delta_time, HEADLESS_FRAME_DURATION);
+        }
+
+        // NOTE: Headless frames advance by a fixed step,
+        // so the pause must not leak into the resumed frames either.
+        state.set_paused(false);
+        assert!(!state.is_paused());
+        let stats = draw_next_frame();
+        assert_eq!(stats.delta_time, HEADLESS_FRAME_DURATION);
+    }
+}
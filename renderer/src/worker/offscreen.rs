@@ -0,0 +1,252 @@
+use anyhow::Result;
+use glam::UVec2;
+
+use crate::CapturedFrame;
+
+/// Image which receives frames of a headless renderer.
+pub struct OffscreenTarget {
+    image: gfx::Image,
+    /// Host-visible copy of the image for frame captures.
+    readback: gfx::Buffer,
+    extent: UVec2,
+}
+
+impl OffscreenTarget {
+    pub const FORMAT: gfx::Format = gfx::Format::RGBA8Srgb;
+    const TEXEL_SIZE: usize = 4;
+
+    pub fn new(device: &gfx::Device, extent: UVec2) -> Result<Self> {
+        anyhow::ensure!(
+            extent.x > 0 && extent.y > 0,
+            "offscreen target size must not be zero ({extent})"
+        );
+
+        let image = device.create_image(gfx::ImageInfo {
+            extent: gfx::ImageExtent::D2 {
+                width: extent.x,
+                height: extent.y,
+            },
+            format: Self::FORMAT,
+            mip_levels: 1,
+            samples: gfx::Samples::_1,
+            array_layers: 1,
+            usage: gfx::ImageUsageFlags::COLOR_ATTACHMENT | gfx::ImageUsageFlags::TRANSFER_SRC,
+            flags: gfx::ImageCreateFlags::empty(),
+            label: Some("offscreen target"),
+        })?;
+
+        let readback = device.create_mappable_buffer(
+            gfx::BufferInfo {
+                align_mask: 0b11,
+                size: extent.x as usize * extent.y as usize * Self::TEXEL_SIZE,
+                usage: gfx::BufferUsage::TRANSFER_DST,
+                label: Some("offscreen readback"),
+            },
+            gfx::MemoryUsage::DOWNLOAD,
+        )?;
+
+        Ok(Self {
+            image,
+            readback,
+            extent,
+        })
+    }
+
+    pub fn image(&self) -> &gfx::Image {
+        &self.image
+    }
+
+    /// Copies the rendered frame into the readback buffer.
+    ///
+    /// NOTE: Must be recorded after the main pass.
+    pub fn encode_capture(&self, encoder: &mut gfx::Encoder) {
+        encoder.transition_image(
+            &self.image,
+            gfx::ImageLayout::TransferSrcOptimal,
+            gfx::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT..gfx::PipelineStageFlags::TRANSFER,
+            gfx::AccessFlags::COLOR_ATTACHMENT_WRITE..gfx::AccessFlags::TRANSFER_READ,
+        );
+        encoder.copy_image_to_buffer(
+            &self.image,
+            gfx::ImageLayout::TransferSrcOptimal,
+            &self.readback,
+            &[gfx::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: gfx::ImageSubresourceLayers::all_layers(self.image.info(), 0),
+                image_offset: glam::IVec3::ZERO,
+                image_extent: self.extent.extend(1),
+            }],
+        );
+        encoder.memory_barrier(
+            gfx::PipelineStageFlags::TRANSFER,
+            gfx::AccessFlags::TRANSFER_WRITE,
+            gfx::PipelineStageFlags::HOST,
+            gfx::AccessFlags::HOST_READ,
+        );
+    }
+
+    /// Reads the frame copied by [`encode_capture`].
+    ///
+    /// NOTE: The frame must be completed.
+    ///
+    /// [`encode_capture`]: Self::encode_capture
+    pub fn read_capture(&self, device: &gfx::Device) -> Result<CapturedFrame, gfx::MapError> {
+        let mut data = vec![0u8; self.readback.info().size];
+        device.download_from_memory(&mut self.readback.as_mappable(), 0, &mut data)?;
+
+        Ok(CapturedFrame {
+            width: self.extent.x,
+            height: self.extent.y,
+            format: Self::FORMAT,
+            data,
+        })
+    }
+}
+
+/// Frame captures of headless renderers shared by the tests.
+#[cfg(test)]
+pub(crate) mod capture {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::{CapturedFrame, RendererBuilder, RendererState};
+
+    /// Builds a headless renderer, sets up its scene and captures the frame
+    /// which draws the uploaded scene, see [`capture_uploaded_frame`].
+    ///
+    /// Handles returned by `setup` are kept alive until the frame is captured.
+    pub(crate) fn render_captured_frame<F, T>(builder: RendererBuilder, setup: F) -> CapturedFrame
+    where
+        F: FnOnce(&Arc<RendererState>) -> T,
+    {
+        let renderer = builder.build().unwrap();
+        let state = renderer.state();
+        let _scene = setup(state);
+        capture_uploaded_frame(state)
+    }
+
+    /// Captures frames until all sent instructions are drawn
+    /// and the frame is the same as the previous one.
+    pub(crate) fn capture_uploaded_frame(state: &RendererState) -> CapturedFrame {
+        let started_at = Instant::now();
+        let mut prev = None;
+        loop {
+            assert!(
+                started_at.elapsed() < CAPTURE_TIMEOUT,
+                "captured frames are still changing"
+            );
+
+            // NOTE: Instructions are evaluated before the frame which takes
+            // the capture request is drawn.
+            let evaluated = state.pending_instruction_count() == 0;
+            let frame = capture_frame(state);
+            if evaluated && prev.as_ref() == Some(&frame) {
+                return frame;
+            }
+            prev = Some(frame);
+        }
+    }
+
+    /// Requests the next frame capture and draws frames until it is completed.
+    pub(crate) fn capture_frame(state: &RendererState) -> CapturedFrame {
+        let started_at = Instant::now();
+        state.capture_next_frame().unwrap();
+        loop {
+            assert!(
+                started_at.elapsed() < CAPTURE_TIMEOUT,
+                "frame is still not captured"
+            );
+            state.notify_draw();
+            if let Some(frame) = state.wait_captured_frame(Duration::from_millis(10)) {
+                return frame;
+            }
+        }
+    }
+
+    const CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3};
+
+    use super::capture::render_captured_frame;
+    use super::*;
+    use crate::render_graph::materials::DebugMaterialInstance;
+    use crate::types::{CameraProjection, CubeMeshGenerator, Mesh};
+    use crate::RendererBuilder;
+
+    const SIZE: u32 = 64;
+
+    /// Renders a unit cube at the origin seen from its front face.
+    fn render_cube() -> CapturedFrame {
+        render_captured_frame(RendererBuilder::headless(SIZE, SIZE), |state| {
+            let mesh = Mesh::builder(CubeMeshGenerator::from_size(1.0))
+                .build()
+                .unwrap();
+            let mesh = state.add_mesh(&mesh).unwrap();
+            let material = state
+                .add_material_instance(DebugMaterialInstance {
+                    color: Vec3::new(1.0, 0.5, 0.25),
+                    double_sided: false,
+                })
+                .unwrap();
+            state.update_camera(
+                &Mat4::from_translation(Vec3::new(0.0, 0.0, 3.0)).inverse(),
+                &CameraProjection::default(),
+            );
+            state
+                .add_static_object(mesh, material, &Mat4::IDENTITY)
+                .unwrap()
+        })
+    }
+
+    #[test]
+    #[ignore = "requires a Vulkan device"]
+    fn cube_covers_its_projected_square() {
+        let frame = render_cube();
+        assert_eq!((frame.width, frame.height), (SIZE, SIZE));
+        assert_eq!(frame.format, OffscreenTarget::FORMAT);
+        assert_eq!(frame.data.len(), (SIZE * SIZE) as usize * 4);
+
+        // The front face is 2.5 units away from the camera and is the only
+        // visible one, so it covers a square of 0.5 / (2.5 * tan(fovy / 2))
+        // of the half-extent around the center.
+        let CameraProjection::Perspective { fovy, .. } = CameraProjection::default() else {
+            unreachable!();
+        };
+        let half_extent = SIZE as f32 / 2.0;
+        let face_half_size = 0.5 / (2.5 * (fovy / 2.0).tan()) * half_extent;
+        let face = (half_extent - face_half_size)..(half_extent + face_half_size);
+
+        let background = frame.pixel(0, 0);
+        let face_color = frame.pixel(SIZE / 2, SIZE / 2);
+        assert_ne!(face_color, background);
+        assert_eq!(background[3], u8::MAX);
+
+        // NOTE: Pixels within one texel of the edges are skipped
+        // since they can be partially covered.
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let center = [x as f32 + 0.5, y as f32 + 0.5];
+                let inside = center
+                    .iter()
+                    .all(|c| *c > face.start + 1.0 && *c < face.end - 1.0);
+                let outside = center
+                    .iter()
+                    .any(|c| *c < face.start - 1.0 || *c > face.end + 1.0);
+                if inside {
+                    let pixel = frame.pixel(x, y);
+                    assert_eq!(pixel, face_color, "pixel ({x}, {y}) is not on the face");
+                } else if outside {
+                    assert_eq!(frame.pixel(x, y), background, "pixel ({x}, {y}) is covered");
+                }
+            }
+        }
+
+        // The frame is the same on every run
+        assert!(render_cube() == frame, "frames are different");
+    }
+}
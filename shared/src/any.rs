@@ -10,6 +10,14 @@ impl AnyVec {
         Self::from(Vec::<T>::new())
     }
 
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     /// # Safety
     /// The following must be true:
     /// - `T` must be an original type of `Vec<T>`.
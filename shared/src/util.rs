@@ -1,4 +1,5 @@
 use std::mem::ManuallyDrop;
+use std::sync::{Mutex, MutexGuard, PoisonError};
 
 use bumpalo::Bump;
 
@@ -83,3 +84,11 @@ impl std::ops::DerefMut for DeallocOnDrop<'_> {
         self.0
     }
 }
+
+/// Locks the mutex even if a thread panicked while holding it.
+///
+/// NOTE: Only use for data which stays consistent after an interrupted update.
+#[inline]
+pub fn lock_ignore_poison<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}